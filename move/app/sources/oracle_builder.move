@@ -5,14 +5,22 @@ module app::oracle_builder;
 
 use enclave::enclave::{Self, Enclave};
 use std::string::String;
+use sui::clock::Clock;
 use sui::event;
+use sui::vec_map::VecMap;
 
 /// ====
 /// Core onchain oracle builder logic for price feeds
 /// ====
 
 const PRICE_FEED_INTENT: u8 = 0;
+const PRICE_FEED_WITH_CHANGE_INTENT: u8 = 1;
+const PRICE_FEED_MARKET_CLOSED_INTENT: u8 = 2;
+const PRICE_FEED_REDUCED_PRECISION_INTENT: u8 = 8;
+const PRICE_FEED_EXTENDED_INTENT: u8 = 13;
 const EInvalidSignature: u64 = 1;
+const ELeaseHeld: u64 = 2;
+const ENotEnclaveSender: u64 = 3;
 
 /// Object representing a price update from the oracle
 public struct PriceUpdate has key, store {
@@ -23,6 +31,15 @@ public struct PriceUpdate has key, store {
     price: u64,
 }
 
+/// Lease electing the one enclave instance that pushes prices, so that
+/// replicas sharing a signing key do not all pay for the same updates.
+public struct PushLease has key {
+    id: UID,
+    /// Instance id of the holder
+    holder: String,
+    expires_at_ms: u64,
+}
+
 /// Event emitted when a new price update is created
 public struct PriceUpdateEvent has copy, drop {
     price_feed_id: String,
@@ -39,6 +56,73 @@ public struct PriceFeedResponse has copy, drop {
     timestamp_ms: u64,
 }
 
+/// Change of a price versus the previous signed observation of the feed.
+public struct RateOfChange has copy, drop {
+    previous_price: u64,
+    change_bps: u64,
+    is_negative: bool,
+    elapsed_ms: u64,
+}
+
+/// Version and digest of the PriceFeed object a price was fetched with.
+public struct FeedObject has copy, drop {
+    version: u64,
+    digest: String,
+}
+
+/// Rounding mode of a feed's scaled prices, in the order of the Rust enum.
+public enum RoundingMode has copy, drop {
+    Truncate,
+    Floor,
+    Ceil,
+    HalfEven,
+    HalfUp,
+}
+
+/// Layout of the PriceFeedWithChange intent.
+public struct PriceFeedWithChangeResponse has copy, drop {
+    oracle_id: String,
+    price_feed_id: String,
+    price: u64,
+    timestamp_ms: u64,
+    rate_of_change: Option<RateOfChange>,
+}
+
+/// Layout of the PriceFeedMarketClosed intent, the last close of the market.
+public struct PriceFeedMarketClosedResponse has copy, drop {
+    oracle_id: String,
+    price_feed_id: String,
+    price: u64,
+    timestamp_ms: u64,
+    market_closed: bool,
+}
+
+/// Layout of the PriceFeedReducedPrecision intent.
+public struct PriceFeedReducedPrecisionResponse has copy, drop {
+    oracle_id: String,
+    price_feed_id: String,
+    price: u64,
+    timestamp_ms: u64,
+    decimals: Option<u32>,
+}
+
+/// Layout of the PriceFeedExtended intent, carrying every optional field.
+/// `params` must hold its entries in BCS order (sorted by their encoding),
+/// as the enclave signs them.
+public struct PriceFeedExtendedResponse has copy, drop {
+    oracle_id: String,
+    price_feed_id: String,
+    price: u64,
+    timestamp_ms: u64,
+    rate_of_change: Option<RateOfChange>,
+    market_closed: bool,
+    source_timestamp_ms: Option<u64>,
+    decimals: Option<u32>,
+    rounding: Option<RoundingMode>,
+    feed_object: Option<FeedObject>,
+    params: VecMap<String, String>,
+}
+
 public struct ORACLE_BUILDER has drop {}
 
 fun init(otw: ORACLE_BUILDER, ctx: &mut TxContext) {
@@ -69,24 +153,171 @@ public fun new_price_update<T>(
     );
     assert!(res, EInvalidSignature);
     
-    // Create the PriceUpdate object
-    let price_update = PriceUpdate {
+    mint_price_update(
+        response.price_feed_id,
+        response.oracle_id,
+        response.timestamp_ms,
+        response.price,
+        ctx,
+    )
+}
+
+public fun new_price_update_with_change<T>(
+    response: PriceFeedWithChangeResponse,
+    sig: &vector<u8>,
+    enclave: &Enclave<T>,
+    ctx: &mut TxContext,
+): PriceUpdate {
+    let res = enclave.verify_signature(
+        PRICE_FEED_WITH_CHANGE_INTENT,
+        response.timestamp_ms,
+        response,
+        sig,
+    );
+    assert!(res, EInvalidSignature);
+    mint_price_update(
+        response.price_feed_id,
+        response.oracle_id,
+        response.timestamp_ms,
+        response.price,
+        ctx,
+    )
+}
+
+public fun new_price_update_market_closed<T>(
+    response: PriceFeedMarketClosedResponse,
+    sig: &vector<u8>,
+    enclave: &Enclave<T>,
+    ctx: &mut TxContext,
+): PriceUpdate {
+    let res = enclave.verify_signature(
+        PRICE_FEED_MARKET_CLOSED_INTENT,
+        response.timestamp_ms,
+        response,
+        sig,
+    );
+    assert!(res, EInvalidSignature);
+    mint_price_update(
+        response.price_feed_id,
+        response.oracle_id,
+        response.timestamp_ms,
+        response.price,
+        ctx,
+    )
+}
+
+public fun new_price_update_reduced_precision<T>(
+    response: PriceFeedReducedPrecisionResponse,
+    sig: &vector<u8>,
+    enclave: &Enclave<T>,
+    ctx: &mut TxContext,
+): PriceUpdate {
+    let res = enclave.verify_signature(
+        PRICE_FEED_REDUCED_PRECISION_INTENT,
+        response.timestamp_ms,
+        response,
+        sig,
+    );
+    assert!(res, EInvalidSignature);
+    mint_price_update(
+        response.price_feed_id,
+        response.oracle_id,
+        response.timestamp_ms,
+        response.price,
+        ctx,
+    )
+}
+
+public fun new_price_update_extended<T>(
+    response: PriceFeedExtendedResponse,
+    sig: &vector<u8>,
+    enclave: &Enclave<T>,
+    ctx: &mut TxContext,
+): PriceUpdate {
+    let res = enclave.verify_signature(
+        PRICE_FEED_EXTENDED_INTENT,
+        response.timestamp_ms,
+        response,
+        sig,
+    );
+    assert!(res, EInvalidSignature);
+    mint_price_update(
+        response.price_feed_id,
+        response.oracle_id,
+        response.timestamp_ms,
+        response.price,
+        ctx,
+    )
+}
+
+/// Verify a `PriceFeed` response the enclave signed and share it as a
+/// PriceUpdate. Called by the enclave's push mode, which cannot construct
+/// the response struct in a programmable transaction.
+entry fun push_price_update<T>(
+    oracle_id: String,
+    price_feed_id: String,
+    price: u64,
+    timestamp_ms: u64,
+    sig: vector<u8>,
+    enclave: &Enclave<T>,
+    ctx: &mut TxContext,
+) {
+    let response = PriceFeedResponse { oracle_id, price_feed_id, price, timestamp_ms };
+    let price_update = new_price_update(response, &sig, enclave, ctx);
+    transfer::public_share_object(price_update);
+}
+
+/// Share a PushLease for the instances of one deployment to claim.
+public fun create_push_lease(ctx: &mut TxContext) {
+    transfer::share_object(PushLease {
         id: object::new(ctx),
-        price_feed_id: response.price_feed_id,
-        oracle_id: response.oracle_id,
-        timestamp_ms: response.timestamp_ms,
-        price: response.price,
-    };
-    
-    // Emit PriceUpdateEvent
-    event::emit(PriceUpdateEvent {
-        price_feed_id: response.price_feed_id,
-        oracle_id: response.oracle_id,
-        timestamp_ms: response.timestamp_ms,
-        price: response.price,
+        holder: b"".to_string(),
+        expires_at_ms: 0,
     });
-    
-    price_update
+}
+
+/// Claim `lease` for `instance`, or renew its claim, until `duration_ms`
+/// from now. Aborts while another instance holds it, and unless sent by the
+/// enclave key, so that nobody else can hold it. Push mode calls it in the
+/// transaction of every push.
+entry fun claim_push_lease<T>(
+    lease: &mut PushLease,
+    enclave: &Enclave<T>,
+    instance: String,
+    duration_ms: u64,
+    clock: &Clock,
+    ctx: &TxContext,
+) {
+    assert!(ctx.sender() == enclave_address(enclave), ENotEnclaveSender);
+    let now = clock.timestamp_ms();
+    assert!(lease.holder == instance || now >= lease.expires_at_ms, ELeaseHeld);
+    lease.holder = instance;
+    lease.expires_at_ms = now + duration_ms;
+}
+
+/// Sui address of the enclave's Ed25519 key.
+fun enclave_address<T>(enclave: &Enclave<T>): address {
+    let mut bytes = vector[0u8];
+    bytes.append(*enclave.pk());
+    sui::address::from_bytes(sui::hash::blake2b256(&bytes))
+}
+
+/// Create the PriceUpdate object of a verified response and emit its event.
+fun mint_price_update(
+    price_feed_id: String,
+    oracle_id: String,
+    timestamp_ms: u64,
+    price: u64,
+    ctx: &mut TxContext,
+): PriceUpdate {
+    event::emit(PriceUpdateEvent { price_feed_id, oracle_id, timestamp_ms, price });
+    PriceUpdate {
+        id: object::new(ctx),
+        price_feed_id,
+        oracle_id,
+        timestamp_ms,
+        price,
+    }
 }
 
 #[test]
@@ -146,9 +377,158 @@ fun test_oracle_builder_flow() {
     );
 
     sui::transfer::public_share_object(price_update);
+    push_price_update(
+        b"test_oracle".to_string(),
+        b"test_price_feed_id".to_string(),
+        10050000000,
+        1744683300000,
+        sig,
+        &enclave,
+        scenario.ctx(),
+    );
+
+    // The enclave key claims the lease for one instance, which may renew it;
+    // another instance gets it once it expired
+    scenario.next_tx(@0x53392c15b104b55c3fff40cde746993fe5cceb1f7275ba20d7680262e6146b51);
+    let mut lease = PushLease { id: object::new(scenario.ctx()), holder: b"".to_string(), expires_at_ms: 0 };
+    claim_push_lease(&mut lease, &enclave, b"a".to_string(), 30000, &clock, scenario.ctx());
+    claim_push_lease(&mut lease, &enclave, b"a".to_string(), 30000, &clock, scenario.ctx());
+    clock.increment_for_testing(30000);
+    claim_push_lease(&mut lease, &enclave, b"b".to_string(), 30000, &clock, scenario.ctx());
+    assert!(lease.holder == b"b".to_string());
+    assert!(lease.expires_at_ms == 1744684007462 + 60000);
+    destroy(lease);
+
     test_scenario::return_shared(config);
     clock.destroy_for_testing();
     enclave.destroy();
     destroy(cap);
     scenario.end();
 }
+
+/// The bytes `enclave::verify_signature` checks a signature over.
+#[test_only]
+fun signing_bytes<P: drop>(intent: u8, timestamp_ms: u64, response: P): vector<u8> {
+    let mut bytes = vector[intent];
+    bytes.append(sui::bcs::to_bytes(&timestamp_ms));
+    bytes.append(sui::bcs::to_bytes(&response));
+    bytes
+}
+
+#[test]
+fun test_price_feed_layouts() {
+    // Should be consistent with `fn test_serde` in `src/nautilus-server/src/app.rs`.
+    let timestamp_ms = 1744038900000;
+    let oracle_id = b"test_oracle".to_string();
+    let price_feed_id = b"test_price_feed_id".to_string();
+    let price = 10050000000;
+
+    let bytes = signing_bytes(
+        PRICE_FEED_INTENT,
+        timestamp_ms,
+        PriceFeedResponse { oracle_id, price_feed_id, price, timestamp_ms },
+    );
+    assert!(bytes == x"0020b1d110960100000b746573745f6f7261636c6512746573745f70726963655f666565645f696480d406570200000020b1d11096010000");
+
+    let bytes = signing_bytes(
+        PRICE_FEED_WITH_CHANGE_INTENT,
+        timestamp_ms,
+        PriceFeedWithChangeResponse {
+            oracle_id,
+            price_feed_id,
+            price,
+            timestamp_ms,
+            rate_of_change: option::some(RateOfChange {
+                previous_price: 10000000000,
+                change_bps: 50,
+                is_negative: false,
+                elapsed_ms: 60000,
+            }),
+        },
+    );
+    assert!(bytes == x"0120b1d110960100000b746573745f6f7261636c6512746573745f70726963655f666565645f696480d406570200000020b1d110960100000100e40b540200000032000000000000000060ea000000000000");
+
+    let bytes = signing_bytes(
+        PRICE_FEED_MARKET_CLOSED_INTENT,
+        timestamp_ms,
+        PriceFeedMarketClosedResponse {
+            oracle_id,
+            price_feed_id,
+            price,
+            timestamp_ms,
+            market_closed: true,
+        },
+    );
+    assert!(bytes == x"0220b1d110960100000b746573745f6f7261636c6512746573745f70726963655f666565645f696480d406570200000020b1d1109601000001");
+
+    let bytes = signing_bytes(
+        PRICE_FEED_REDUCED_PRECISION_INTENT,
+        timestamp_ms,
+        PriceFeedReducedPrecisionResponse {
+            oracle_id,
+            price_feed_id,
+            price: 1005,
+            timestamp_ms,
+            decimals: option::some(2),
+        },
+    );
+    assert!(bytes == x"0820b1d110960100000b746573745f6f7261636c6512746573745f70726963655f666565645f6964ed0300000000000020b1d110960100000102000000");
+
+    let mut params = sui::vec_map::empty();
+    params.insert(b"symbol".to_string(), b"BTC".to_string());
+    let bytes = signing_bytes(
+        PRICE_FEED_EXTENDED_INTENT,
+        timestamp_ms,
+        PriceFeedExtendedResponse {
+            oracle_id,
+            price_feed_id,
+            price,
+            timestamp_ms,
+            rate_of_change: option::none(),
+            market_closed: false,
+            source_timestamp_ms: option::some(1744038899000),
+            decimals: option::none(),
+            rounding: option::some(RoundingMode::HalfUp),
+            feed_object: option::some(FeedObject { version: 42, digest: b"abc".to_string() }),
+            params,
+        },
+    );
+    assert!(bytes == x"0d20b1d110960100000b746573745f6f7261636c6512746573745f70726963655f666565645f696480d406570200000020b1d1109601000000000138add11096010000000104012a0000000000000003616263010673796d626f6c03425443");
+}
+
+#[test]
+fun test_integration_vectors() {
+    // Should be consistent with `PRICE_FEED_VECTOR` and
+    // `PRICE_FEED_EXTENDED_VECTOR` in `src/nautilus-server/tests/common/mod.rs`.
+    let timestamp_ms = 1744038900000;
+    let oracle_id = b"0x0e5d8e5d6a1a5f7b3b6b1d7f1b9e7c4b6f6a4b8e6c2d1a0f9e8d7c6b5a4f3e2d".to_string();
+    let price_feed_id = b"0xb2b928c198e2037b5116c4d51ce90a61d534912e49c44d340fab1f8ed3de7e50".to_string();
+    let price = 10050000000;
+
+    let bytes = signing_bytes(
+        PRICE_FEED_INTENT,
+        timestamp_ms,
+        PriceFeedResponse { oracle_id, price_feed_id, price, timestamp_ms },
+    );
+    assert!(bytes == x"0020b1d11096010000423078306535643865356436613161356637623362366231643766316239653763346236663661346238653663326431613066396538643763366235613466336532644230786232623932386331393865323033376235313136633464353163653930613631643533343931326534396334346433343066616231663865643364653765353080d406570200000020b1d11096010000");
+
+    let source_timestamp_ms = timestamp_ms - 1000;
+    let bytes = signing_bytes(
+        PRICE_FEED_EXTENDED_INTENT,
+        source_timestamp_ms,
+        PriceFeedExtendedResponse {
+            oracle_id,
+            price_feed_id,
+            price,
+            timestamp_ms: source_timestamp_ms,
+            rate_of_change: option::none(),
+            market_closed: false,
+            source_timestamp_ms: option::some(source_timestamp_ms),
+            decimals: option::none(),
+            rounding: option::some(RoundingMode::HalfUp),
+            feed_object: option::none(),
+            params: sui::vec_map::empty(),
+        },
+    );
+    assert!(bytes == x"0d38add11096010000423078306535643865356436613161356637623362366231643766316239653763346236663661346238653663326431613066396538643763366235613466336532644230786232623932386331393865323033376235313136633464353163653930613631643533343931326534396334346433343066616231663865643364653765353080d406570200000038add1109601000000000138add110960100000001040000");
+}
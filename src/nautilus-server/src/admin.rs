@@ -0,0 +1,313 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Operator endpoints under `/admin`. When `admin.token_sha256` is set every
+//! request must carry the matching `Authorization: Bearer` token.
+
+use crate::backup;
+use crate::capture;
+use crate::common::{attestation_document, current_timestamp_ms, IntentScope};
+use crate::config::KeyMode;
+use crate::consumers;
+use crate::entropy;
+use crate::mirrors;
+use crate::push;
+use crate::retirement;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap};
+use axum::middleware::{self, Next};
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{KeyPair, ToFromBytes};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// `[admin]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Admin {
+    /// SHA-256 of the admin token in Hex. Without it the admin routes are
+    /// open to anyone who can reach the server.
+    #[serde(default)]
+    pub token_sha256: Option<String>,
+}
+
+impl Admin {
+    /// Check the config, returning the problems found.
+    pub fn validate(&self) -> Vec<String> {
+        match &self.token_sha256 {
+            Some(hash) if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) => {
+                vec!["admin.token_sha256 must be the SHA-256 of the token, 64 hex digits".to_string()]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Check the bearer token of an admin request.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<(), EnclaveError> {
+        let Some(expected) = &self.token_sha256 else {
+            return Ok(());
+        };
+        let token = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| EnclaveError::Unauthorized("Missing admin bearer token".to_string()))?;
+        if Hex::encode(Sha256::digest(token.as_bytes())).eq_ignore_ascii_case(expected) {
+            Ok(())
+        } else {
+            Err(EnclaveError::Unauthorized("Invalid admin token".to_string()))
+        }
+    }
+}
+
+/// The `/admin` routes, behind the admin token. `/admin/handoff` is left
+/// out: it is called by a replacement instance, which proves itself with an
+/// attestation instead.
+pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    if state.config.load().admin.token_sha256.is_none() {
+        warn!("admin.token_sha256 is not set, admin routes are unauthenticated");
+    }
+    Router::new()
+        .route("/admin/reload", post(reload_config))
+        .route("/admin/rotate_key", post(rotate_key))
+        .route("/admin/key", get(key_info))
+        .route("/admin/retire_key", post(retirement::retire_key))
+        .route("/admin/export_key_backup", post(backup::export_key_backup))
+        .route("/admin/override_breaker", post(override_breaker))
+        .route("/admin/pause", get(pause_status).post(pause))
+        .route("/admin/flush_cache", post(flush_cache))
+        .route("/admin/served", get(consumers::served_updates))
+        .route("/admin/mirrors", get(mirrors::mirror_stats))
+        .route("/admin/push", get(push::push_status))
+        .route("/admin/upstream_captures/:feed_id", get(capture::upstream_captures))
+        .route_layer(middleware::from_fn_with_state(state, require_token))
+}
+
+/// Refuse admin requests without the configured token. The config is read
+/// per request, so a reload can set or change the token.
+async fn require_token(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, EnclaveError> {
+    state.config.load().admin.authenticate(request.headers())?;
+    Ok(next.run(request).await)
+}
+
+/// Response for reload config.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReloadConfigResponse {
+    pub status: String,
+}
+
+/// Endpoint that re-reads the config file and swaps it into the running
+/// state without regenerating the ephemeral keypair.
+pub async fn reload_config(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ReloadConfigResponse>, EnclaveError> {
+    info!("reload config called");
+
+    state
+        .reload_config()
+        .await
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to reload config: {:#}", e)))?;
+
+    Ok(Json(ReloadConfigResponse {
+        status: "reloaded".to_string(),
+    }))
+}
+
+/// Response for rotate key.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateKeyResponse {
+    /// New public key in Hex.
+    pub public_key: String,
+    /// Attestation document committed to the new public key, in Hex.
+    pub attestation: String,
+    /// When the new key replaces the current one for signing.
+    pub activate_at_ms: u64,
+}
+
+/// Endpoint that generates a new signing keypair and returns an attestation
+/// for it. The current key keeps signing until the configured grace period
+/// has passed, leaving time to register the new key on chain.
+pub async fn rotate_key(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<RotateKeyResponse>, EnclaveError> {
+    info!("rotate key called");
+
+    let kp = Ed25519KeyPair::generate(&mut entropy::key_rng());
+    let attestation = attestation_document(kp.public().as_bytes(), None)?;
+
+    let key_config = state.config.load().key.clone();
+    let public_key = Hex::encode(kp.public().as_bytes());
+    let activate_at_ms = current_timestamp_ms()? + key_config.rotation_grace_secs * 1000;
+    // Sealed apart from the current key until it activates
+    state
+        .signing_keys
+        .rotate(kp, activate_at_ms)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to persist new key: {:#}", e)))?;
+
+    Ok(Json(RotateKeyResponse {
+        public_key,
+        attestation: Hex::encode(attestation),
+        activate_at_ms,
+    }))
+}
+
+/// Request for override breaker.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OverrideBreakerRequest {
+    pub price_feed_id: String,
+}
+
+/// Response for override breaker.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OverrideBreakerResponse {
+    pub status: String,
+}
+
+/// Endpoint that lets the next price of a feed be signed even if it trips
+/// the circuit breaker, once an operator has confirmed the move is real.
+pub async fn override_breaker(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<OverrideBreakerRequest>,
+) -> Result<Json<OverrideBreakerResponse>, EnclaveError> {
+    info!("override breaker called for {}", request.price_feed_id);
+
+    state.breaker_overrides.allow_next(&request.price_feed_id);
+    Ok(Json(OverrideBreakerResponse {
+        status: "next price will be signed".to_string(),
+    }))
+}
+
+/// Request for pause.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PauseRequest {
+    /// Feed to pause or resume; everything if omitted.
+    #[serde(default)]
+    pub price_feed_id: Option<String>,
+    pub paused: bool,
+    /// Why signing is paused, included in the errors returned meanwhile.
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// Response for pause.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PauseResponse {
+    /// Whether everything is paused, through the admin API or the config.
+    pub global_paused: bool,
+    /// Feeds paused through the admin API.
+    pub paused_feeds: Vec<String>,
+}
+
+/// Endpoint that stops or resumes signing, globally or for one feed, while
+/// upstream data is suspect. Pauses set in the config are not lifted here.
+pub async fn pause(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<PauseRequest>,
+) -> Result<Json<PauseResponse>, EnclaveError> {
+    let target = request.price_feed_id.as_deref().unwrap_or("all feeds");
+    info!("pause called for {}: paused={}", target, request.paused);
+
+    let reason = request.reason.as_deref().unwrap_or("paused by operator");
+    state
+        .pause
+        .set(request.price_feed_id.as_deref(), request.paused, reason);
+    pause_status(State(state)).await
+}
+
+/// Endpoint that reports what is paused.
+pub async fn pause_status(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<PauseResponse>, EnclaveError> {
+    let (global_paused, paused_feeds) = state.pause.status();
+    Ok(Json(PauseResponse {
+        global_paused: global_paused || state.config.load().pause.global,
+        paused_feeds,
+    }))
+}
+
+/// Response for key info.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyInfoResponse {
+    pub mode: KeyMode,
+    /// Public key currently signing, in Hex.
+    pub public_key: String,
+    /// Public key of a rotation in progress, in Hex.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_public_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_activate_at_ms: Option<u64>,
+    /// Time after which the current key signs nothing, if retired.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retire_at_ms: Option<u64>,
+    /// Keys signing a single intent instead of the current key.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scoped_keys: Vec<ScopedKeyInfo>,
+}
+
+/// A key of `key.scoped_intents`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScopedKeyInfo {
+    pub intent_scope: IntentScope,
+    /// Public key, in Hex.
+    pub public_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retire_at_ms: Option<u64>,
+}
+
+/// Endpoint that reports the signing key and any rotation in progress.
+pub async fn key_info(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<KeyInfoResponse>, EnclaveError> {
+    let kp = state.signing_keys.active_at(current_timestamp_ms()?);
+    let pending = state.signing_keys.pending();
+    Ok(Json(KeyInfoResponse {
+        mode: state.config.load().key.mode,
+        public_key: Hex::encode(kp.public().as_bytes()),
+        pending_public_key: pending
+            .as_ref()
+            .map(|pending| Hex::encode(pending.kp.public().as_bytes())),
+        pending_activate_at_ms: pending.map(|pending| pending.activate_at_ms),
+        retire_at_ms: state.signing_keys.retire_at(kp.public().as_bytes()),
+        scoped_keys: state
+            .signing_keys
+            .scoped_keys()
+            .into_iter()
+            .map(|(intent_scope, kp)| ScopedKeyInfo {
+                intent_scope,
+                public_key: Hex::encode(kp.public().as_bytes()),
+                retire_at_ms: state.signing_keys.retire_at(kp.public().as_bytes()),
+            })
+            .collect(),
+    }))
+}
+
+/// Response for flush cache.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FlushCacheResponse {
+    pub status: String,
+}
+
+/// Endpoint that drops the signed prices served to batch items and the
+/// cached attestation document, so both are produced afresh.
+pub async fn flush_cache(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<FlushCacheResponse>, EnclaveError> {
+    info!("flush cache called");
+
+    state.signed_prices.clear();
+    state.attestation_cache.clear();
+    Ok(Json(FlushCacheResponse {
+        status: "flushed".to_string(),
+    }))
+}
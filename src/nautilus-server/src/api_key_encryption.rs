@@ -0,0 +1,257 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! API keys stored on chain encrypted to the enclave. An `api_key` of the
+//! form `enc:<hex>` is an X25519/AES-GCM ciphertext to the enclave's API key
+//! encryption key, which GET /api_key_encryption_key returns together with
+//! an attestation committed to it, and is decrypted before each upstream
+//! request. Each ciphertext is bound to a feed id and upstream host, which
+//! are authenticated with it and mixed into its key, so it is only decrypted
+//! for requests of that feed to that host and cannot be copied into another
+//! feed pointing at a URL of its author's choosing. The key is derived from
+//! the signing key, so ciphertexts survive restarts when the signing key
+//! persists and must be re-encrypted when it is replaced.
+
+use anyhow::Result;
+use axum::{extract::State, Json};
+use fastcrypto::aes::{Aes256Gcm, AesKey, AuthenticatedCipher, InitializationVector};
+use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{Generate, ToFromBytes};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::Arc;
+use tracing::info;
+use typenum::U12;
+use utoipa::ToSchema;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+use crate::common::attestation_document;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Prefix of on-chain `api_key` values encrypted to the enclave.
+pub const ENCRYPTED_PREFIX: &str = "enc:";
+
+/// Domain separator for the derived keys and the AAD.
+const API_KEY_INFO: &[u8] = b"nautilus-api-key-encryption-v1";
+/// Length of the X25519 ephemeral key and AES-GCM nonce prefixed to each
+/// ciphertext.
+const EPHEMERAL_KEY_LENGTH: usize = 32;
+const NONCE_LENGTH: usize = 12;
+
+/// The X25519 key on-chain API keys are encrypted to.
+pub struct ApiKeyDecryption {
+    secret: StaticSecret,
+}
+
+impl ApiKeyDecryption {
+    /// The key derived from the signing keypair `kp`.
+    pub fn derive(kp: &Ed25519KeyPair) -> Self {
+        let mut secret = [0u8; 32];
+        Hkdf::<Sha256>::new(None, kp.as_bytes())
+            .expand(API_KEY_INFO, &mut secret)
+            .expect("32 bytes is a valid HKDF output length");
+        Self {
+            secret: StaticSecret::from(secret),
+        }
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from(&self.secret)
+    }
+
+    /// `api_key` as given, or decrypted if it is an `enc:` ciphertext, which
+    /// must be bound to `feed_id` and the host of `url`.
+    pub fn decrypt_api_key(
+        &self,
+        api_key: &str,
+        feed_id: &str,
+        url: &str,
+    ) -> Result<String, EnclaveError> {
+        let Some(hex) = api_key.strip_prefix(ENCRYPTED_PREFIX) else {
+            return Ok(api_key.to_string());
+        };
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string))
+            .ok_or_else(|| {
+                EnclaveError::GenericError(format!("Upstream URL {} has no host", url))
+            })?;
+        self.decrypt(hex, &binding(feed_id, &host)).map_err(|e| {
+            EnclaveError::GenericError(format!("Failed to decrypt the API key: {}", e))
+        })
+    }
+
+    fn decrypt(&self, hex: &str, binding: &[u8]) -> Result<String> {
+        let bytes = Hex::decode(hex).map_err(|e| anyhow::anyhow!("invalid hex: {}", e))?;
+        if bytes.len() <= EPHEMERAL_KEY_LENGTH + NONCE_LENGTH {
+            return Err(anyhow::anyhow!("ciphertext is too short"));
+        }
+        let (ephemeral_key, rest) = bytes.split_at(EPHEMERAL_KEY_LENGTH);
+        let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+        let ephemeral_key = PublicKey::from(
+            <[u8; EPHEMERAL_KEY_LENGTH]>::try_from(ephemeral_key).expect("split at its length"),
+        );
+        let public_key = self.public_key();
+        let key = content_key(
+            self.secret.diffie_hellman(&ephemeral_key).as_bytes(),
+            &public_key,
+            &ephemeral_key,
+            binding,
+        )?;
+        let nonce = InitializationVector::<U12>::from_bytes(nonce)
+            .map_err(|e| anyhow::anyhow!("invalid nonce: {}", e))?;
+        let plaintext = Aes256Gcm::<U12>::new(key)
+            .decrypt_authenticated(&nonce, binding, ciphertext)
+            .map_err(|_| {
+                anyhow::anyhow!(
+                    "it was encrypted to another key, for another feed or upstream host, \
+                     or has been tampered with"
+                )
+            })?;
+        String::from_utf8(plaintext).map_err(|_| anyhow::anyhow!("it is not UTF-8"))
+    }
+}
+
+/// Encrypt `api_key` to the enclave's `public_key` for the feed `feed_id`
+/// fetching from `host`, returning the `enc:` value to store on chain.
+pub fn encrypt_api_key(public_key: &PublicKey, feed_id: &str, host: &str, api_key: &str) -> String {
+    let binding = binding(feed_id, host);
+    let secret = EphemeralSecret::random_from_rng(rand::thread_rng());
+    let ephemeral_key = PublicKey::from(&secret);
+    let key = content_key(
+        secret.diffie_hellman(public_key).as_bytes(),
+        public_key,
+        &ephemeral_key,
+        &binding,
+    )
+    .expect("HKDF output of 32 bytes is a valid AES key");
+    let nonce = InitializationVector::<U12>::generate(&mut rand::thread_rng());
+    let ciphertext =
+        Aes256Gcm::<U12>::new(key).encrypt_authenticated(&nonce, &binding, api_key.as_bytes());
+    format!(
+        "{}{}",
+        ENCRYPTED_PREFIX,
+        Hex::encode([ephemeral_key.as_bytes(), nonce.as_bytes(), &ciphertext].concat())
+    )
+}
+
+/// Parse an X25519 public key from Hex.
+pub fn parse_public_key(hex: &str) -> Result<PublicKey> {
+    let bytes: [u8; 32] = Hex::decode(hex.trim().trim_start_matches("0x"))
+        .map_err(|e| anyhow::anyhow!("Invalid X25519 public key: {}", e))?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("X25519 public key must be 32 bytes"))?;
+    Ok(PublicKey::from(bytes))
+}
+
+/// Domain separator followed by the BCS encoded feed id and host, which is
+/// the AAD of a ciphertext and part of its HKDF info. Hosts are compared
+/// case-insensitively.
+fn binding(feed_id: &str, host: &str) -> Vec<u8> {
+    let bound = bcs::to_bytes(&(feed_id, host.to_ascii_lowercase())).expect("should not fail");
+    [API_KEY_INFO, &bound].concat()
+}
+
+/// Derive the AES key of a ciphertext bound to `binding` from the X25519
+/// shared secret.
+fn content_key(
+    shared: &[u8],
+    public_key: &PublicKey,
+    ephemeral_key: &PublicKey,
+    binding: &[u8],
+) -> Result<AesKey<typenum::U32>> {
+    let info = [binding, public_key.as_bytes(), ephemeral_key.as_bytes()].concat();
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, shared)
+        .expand(&info, &mut key)
+        .map_err(|_| anyhow::anyhow!("Failed to derive API key encryption key"))?;
+    AesKey::from_bytes(&key).map_err(|_| anyhow::anyhow!("Invalid API key encryption key"))
+}
+
+/// Response of GET /api_key_encryption_key.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ApiKeyEncryptionKeyResponse {
+    /// X25519 public key to encrypt API keys to, in Hex
+    pub public_key: String,
+    /// Attestation document committed to `public_key`, in Hex
+    pub attestation: String,
+}
+
+/// The key feed authors encrypt their API keys to, with an attestation
+/// proving it is held by this enclave.
+#[utoipa::path(
+    get,
+    path = "/api_key_encryption_key",
+    tag = "attestation",
+    responses(
+        (status = 200, body = ApiKeyEncryptionKeyResponse),
+        (status = 400, body = crate::ErrorResponse),
+    )
+)]
+pub async fn api_key_encryption_key(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<ApiKeyEncryptionKeyResponse>, EnclaveError> {
+    info!("api key encryption key called");
+
+    let public_key = state.api_key_decryption.public_key();
+    let attestation = attestation_document(public_key.as_bytes(), None)?;
+    Ok(Json(ApiKeyEncryptionKeyResponse {
+        public_key: Hex::encode(public_key.as_bytes()),
+        attestation: Hex::encode(attestation),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastcrypto::traits::KeyPair;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_encrypted_api_key_round_trip() {
+        let kp = Ed25519KeyPair::generate(&mut StdRng::seed_from_u64(7));
+        let decryption = ApiKeyDecryption::derive(&kp);
+        // Derived deterministically, so ciphertexts outlive a restart
+        assert_eq!(
+            ApiKeyDecryption::derive(&kp).public_key().as_bytes(),
+            decryption.public_key().as_bytes()
+        );
+
+        let url = "https://API.vendor.com/price?symbol=BTC";
+        let encrypted =
+            encrypt_api_key(&decryption.public_key(), "0xfeed", "api.vendor.com", "k3y");
+        assert!(encrypted.starts_with(ENCRYPTED_PREFIX));
+        assert_eq!(
+            decryption
+                .decrypt_api_key(&encrypted, "0xfeed", url)
+                .unwrap(),
+            "k3y"
+        );
+        assert_eq!(
+            decryption.decrypt_api_key("plain", "0xfeed", url).unwrap(),
+            "plain"
+        );
+
+        // Only for the feed and host it was encrypted for
+        assert!(decryption
+            .decrypt_api_key(&encrypted, "0xother", url)
+            .is_err());
+        assert!(decryption
+            .decrypt_api_key(&encrypted, "0xfeed", "https://evil.com/price")
+            .is_err());
+
+        let other =
+            ApiKeyDecryption::derive(&Ed25519KeyPair::generate(&mut StdRng::seed_from_u64(8)));
+        assert!(other.decrypt_api_key(&encrypted, "0xfeed", url).is_err());
+        let mut tampered = encrypted.clone();
+        let flipped = if tampered.ends_with('0') { "1" } else { "0" };
+        tampered.replace_range(tampered.len() - 1.., flipped);
+        assert!(decryption
+            .decrypt_api_key(&tampered, "0xfeed", url)
+            .is_err());
+        assert!(decryption.decrypt_api_key("enc:00", "0xfeed", url).is_err());
+    }
+}
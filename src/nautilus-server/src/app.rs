@@ -1,107 +1,738 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::breaker::BreakerMode;
+use crate::capture::CapturedResponse;
 use crate::common::IntentMessage;
-use crate::common::{to_signed_response, IntentScope, ProcessDataRequest, ProcessedDataResponse};
+use crate::common::{
+    current_timestamp_ms, to_signed_response, IntentScope, ProcessDataRequest, ProcessedDataResponse,
+    RoundingMode, TimestampSource,
+};
+use crate::config::{Config, FeedConfig};
+use crate::conversion;
+use crate::fees::{self, Priority};
+use crate::formats::UpstreamFormat;
+use crate::history::Observation;
+use crate::hmac_auth::{HmacSigning, HMAC_SHA256};
+use crate::limits;
+use crate::market_hours::{MarketHours, WhenClosed};
+use crate::oauth::{self, OAUTH2};
+use crate::oracle::{self, OracleModule, Signed};
+use crate::payments::check_receipt;
+use crate::secrets;
+use crate::telemetry;
+use crate::verify;
+use crate::xml;
+use crate::types::PriceFeed;
 use crate::AppState;
 use crate::EnclaveError;
+use axum::body::Bytes;
 use axum::extract::State;
+use axum::http::HeaderMap;
 use axum::Json;
+use chrono::{DateTime, Utc};
+use opentelemetry::KeyValue;
+use fastcrypto::encoding::{Encoding, Hex};
 use rust_decimal::Decimal;
 use rust_decimal::prelude::ToPrimitive;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info_span, instrument, warn};
+use utoipa::ToSchema;
 /// ====
 /// Core Nautilus server logic, replace it with your own
 /// relavant structs and process_data endpoint.
 /// ====
 
-/// Inner type T for IntentMessage<T>
-#[derive(Debug, Serialize, Deserialize, Clone)]
+/// Inner type T for IntentMessage<T>. Serialized as the layout of its
+/// [`intent`](PriceFeedResponse::intent), each of which is a fixed struct
+/// mirrored in `oracle_builder.move`.
+#[derive(Debug, Deserialize, Clone, ToSchema)]
 pub struct PriceFeedResponse {
     pub oracle_id: String,
     pub price_feed_id: String,
     pub price: u64, // Price as integer (e.g., scaled by 10^8 for 8 decimal places)
     pub timestamp_ms: u64, // Current UTC timestamp in milliseconds
+    /// When set the intent is `PriceFeedWithChange`
+    #[serde(default)]
+    pub rate_of_change: Option<RateOfChange>,
+    /// Set outside market hours, when the price is the last close. When set
+    /// the intent is `PriceFeedMarketClosed`.
+    #[serde(default)]
+    pub market_closed: bool,
+    /// Time the upstream says the price is from, for feeds with a
+    /// `timestamp_field`. Signed under `PriceFeedExtended`.
+    #[serde(default)]
+    pub source_timestamp_ms: Option<u64>,
+    /// Decimals the price was rounded to at the consumer's request. When set
+    /// the intent is `PriceFeedReducedPrecision`.
+    #[serde(default)]
+    pub decimals: Option<u32>,
+    /// Rounding mode of the feed, for feeds that configure one. Signed under
+    /// `PriceFeedExtended`.
+    #[serde(default)]
+    pub rounding: Option<RoundingMode>,
+    /// The PriceFeed object the price was fetched with, when
+    /// `response.include_feed_object` is set. Signed under
+    /// `PriceFeedExtended`.
+    #[serde(default)]
+    pub feed_object: Option<FeedObject>,
+    /// Parameters the feed's URL template was filled with, by name, so the
+    /// price is signed together with the symbol or date it is for. Signed
+    /// under `PriceFeedExtended` when the request had any.
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+}
+
+impl PriceFeedResponse {
+    /// Intent the response is signed under, which fixes its layout. A
+    /// response with any of the optional fields only `PriceFeedExtended`
+    /// carries, or with more than one of rate of change, market closed and
+    /// decimals, is signed under `PriceFeedExtended`.
+    pub fn intent(&self) -> IntentScope {
+        let variants = [
+            self.rate_of_change.is_some(),
+            self.market_closed,
+            self.decimals.is_some(),
+        ];
+        if self.source_timestamp_ms.is_some()
+            || self.rounding.is_some()
+            || self.feed_object.is_some()
+            || !self.params.is_empty()
+            || variants.iter().filter(|set| **set).count() > 1
+        {
+            IntentScope::PriceFeedExtended
+        } else if self.rate_of_change.is_some() {
+            IntentScope::PriceFeedWithChange
+        } else if self.market_closed {
+            IntentScope::PriceFeedMarketClosed
+        } else if self.decimals.is_some() {
+            IntentScope::PriceFeedReducedPrecision
+        } else {
+            IntentScope::PriceFeed
+        }
+    }
+
+    /// Hex SHA-256 of the BCS encoded feed id, price and the timestamp the
+    /// price is from, and of the URL parameters if any. Repeated responses
+    /// for the same market close, or for the same upstream tick, share it.
+    pub fn update_id(&self) -> String {
+        let timestamp_ms = self.source_timestamp_ms.unwrap_or(self.timestamp_ms);
+        let bytes = if self.params.is_empty() {
+            bcs::to_bytes(&(&self.price_feed_id, self.price, timestamp_ms))
+        } else {
+            bcs::to_bytes(&(&self.price_feed_id, self.price, timestamp_ms, &self.params))
+        }
+        .expect("should not fail");
+        Hex::encode(Sha256::digest(bytes))
+    }
+}
+
+impl Serialize for PriceFeedResponse {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let oracle_id = &self.oracle_id;
+        let price_feed_id = &self.price_feed_id;
+        let (price, timestamp_ms) = (self.price, self.timestamp_ms);
+        match self.intent() {
+            IntentScope::PriceFeedWithChange => PriceFeedWithChangeLayout {
+                oracle_id,
+                price_feed_id,
+                price,
+                timestamp_ms,
+                rate_of_change: &self.rate_of_change,
+            }
+            .serialize(serializer),
+            IntentScope::PriceFeedMarketClosed => PriceFeedMarketClosedLayout {
+                oracle_id,
+                price_feed_id,
+                price,
+                timestamp_ms,
+                market_closed: self.market_closed,
+            }
+            .serialize(serializer),
+            IntentScope::PriceFeedReducedPrecision => PriceFeedReducedPrecisionLayout {
+                oracle_id,
+                price_feed_id,
+                price,
+                timestamp_ms,
+                decimals: self.decimals,
+            }
+            .serialize(serializer),
+            IntentScope::PriceFeedExtended => PriceFeedExtendedLayout {
+                oracle_id,
+                price_feed_id,
+                price,
+                timestamp_ms,
+                rate_of_change: &self.rate_of_change,
+                market_closed: self.market_closed,
+                source_timestamp_ms: self.source_timestamp_ms,
+                decimals: self.decimals,
+                rounding: self.rounding,
+                feed_object: &self.feed_object,
+                params: &self.params,
+            }
+            .serialize(serializer),
+            _ => PriceFeedLayout {
+                oracle_id,
+                price_feed_id,
+                price,
+                timestamp_ms,
+            }
+            .serialize(serializer),
+        }
+    }
+}
+
+/// `PriceFeedResponse` in oracle_builder.move.
+#[derive(Serialize)]
+struct PriceFeedLayout<'a> {
+    oracle_id: &'a str,
+    price_feed_id: &'a str,
+    price: u64,
+    timestamp_ms: u64,
+}
+
+/// `PriceFeedWithChangeResponse` in oracle_builder.move.
+#[derive(Serialize)]
+struct PriceFeedWithChangeLayout<'a> {
+    oracle_id: &'a str,
+    price_feed_id: &'a str,
+    price: u64,
+    timestamp_ms: u64,
+    rate_of_change: &'a Option<RateOfChange>,
+}
+
+/// `PriceFeedMarketClosedResponse` in oracle_builder.move.
+#[derive(Serialize)]
+struct PriceFeedMarketClosedLayout<'a> {
+    oracle_id: &'a str,
+    price_feed_id: &'a str,
+    price: u64,
+    timestamp_ms: u64,
+    market_closed: bool,
+}
+
+/// `PriceFeedReducedPrecisionResponse` in oracle_builder.move.
+#[derive(Serialize)]
+struct PriceFeedReducedPrecisionLayout<'a> {
+    oracle_id: &'a str,
+    price_feed_id: &'a str,
+    price: u64,
+    timestamp_ms: u64,
+    decimals: Option<u32>,
+}
+
+/// `PriceFeedExtendedResponse` in oracle_builder.move.
+#[derive(Serialize)]
+struct PriceFeedExtendedLayout<'a> {
+    oracle_id: &'a str,
+    price_feed_id: &'a str,
+    price: u64,
+    timestamp_ms: u64,
+    rate_of_change: &'a Option<RateOfChange>,
+    market_closed: bool,
+    source_timestamp_ms: Option<u64>,
+    decimals: Option<u32>,
+    rounding: Option<RoundingMode>,
+    feed_object: &'a Option<FeedObject>,
+    params: &'a BTreeMap<String, String>,
+}
+
+/// Version and digest of an on-chain object, pinning exactly which
+/// configuration the enclave read.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct FeedObject {
+    pub version: u64,
+    /// Base58, as reported by the RPC
+    pub digest: String,
+}
+
+impl FeedObject {
+    fn of(price_feed: &PriceFeed) -> Self {
+        Self {
+            version: price_feed.version,
+            digest: price_feed.digest.clone(),
+        }
+    }
+}
+
+/// Change of a price versus the previous signed observation of the same feed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct RateOfChange {
+    pub previous_price: u64,
+    /// Absolute change in basis points of the previous price
+    pub change_bps: u64,
+    /// Move has no signed integers, so the direction is carried separately
+    pub is_negative: bool,
+    /// Milliseconds since the previous observation
+    pub elapsed_ms: u64,
+}
+
+impl RateOfChange {
+    /// Change from `previous` to the new price, or None if the previous price
+    /// was zero and no percentage can be given.
+    pub fn between(previous: Observation, price: u64, timestamp_ms: u64) -> Option<Self> {
+        if previous.price == 0 {
+            return None;
+        }
+        let diff = (price as i128 - previous.price as i128).unsigned_abs();
+        let change_bps = u64::try_from(diff * 10_000 / previous.price as u128).unwrap_or(u64::MAX);
+        Some(RateOfChange {
+            previous_price: previous.price,
+            change_bps,
+            is_negative: price < previous.price,
+            elapsed_ms: timestamp_ms.saturating_sub(previous.timestamp_ms),
+        })
+    }
 }
 
 /// Inner type T for ProcessDataRequest<T>
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct PriceFeedRequest {
     pub price_feed_id: String,
+    /// On-chain payment receipt, required when `payments.required` is set
+    #[serde(default)]
+    pub receipt_id: Option<String>,
+    /// Fewer decimals than the feed's to round the price to, half away from
+    /// zero, for authenticated consumers whose fixed-point math needs them
+    #[serde(default)]
+    pub decimals: Option<u32>,
+    /// Values of the `{name}` placeholders of the feed's `underlying_url`,
+    /// e.g. a symbol or date, for providers with one URL per query. Such
+    /// prices are neither cached nor recorded in the feed's history.
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+    /// The PriceFeed object, when fetched ahead together with those of other
+    /// requests, e.g. by a batch
+    #[serde(skip)]
+    pub price_feed: Option<PriceFeed>,
 }
 
-/// Extract a value from JSON using a field path that supports both object fields and array indices
-/// Supports paths like: "response[0].cardmarket.prices.averageSellPrice"
-fn extract_field_from_json<'a>(json: &'a Value, field_path: &str) -> Result<&'a Value, String> {
-    let mut current = json;
+/// A single step of a response field path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// Object field access, e.g. `price`
+    Field(String),
+    /// Array element access, e.g. `[0]`
+    Index(usize),
+    /// Last array element, `[last]`
+    Last,
+    /// Every array element, `[*]`; the rest of the path applies to each
+    Each,
+    /// Array elements whose `field` is the string or number `value`, e.g.
+    /// `[symbol=BTCUSD]`, for arrays in no particular order
+    Filter { field: String, value: String },
+    /// Aggregate of the numbers selected, e.g. `[max]`; only at the end of
+    /// a price's path
+    Aggregate(Aggregate),
+}
+
+/// Aggregates over the numbers a field path selects, for arrays where the
+/// index of the wanted value is not stable, like order books.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Min,
+    Max,
+    Avg,
+}
+
+impl Aggregate {
+    fn apply(self, values: &[Decimal]) -> Result<Decimal, String> {
+        let aggregate = match self {
+            Aggregate::Min => values.iter().min().copied(),
+            Aggregate::Max => values.iter().max().copied(),
+            Aggregate::Avg => values
+                .iter()
+                .try_fold(Decimal::ZERO, |sum, value| sum.checked_add(*value))
+                .and_then(|sum| sum.checked_div(Decimal::from(values.len()))),
+        };
+        aggregate.ok_or_else(|| format!("Cannot aggregate {} values", values.len()))
+    }
+}
+
+/// Parse a field path such as "response[0].cardmarket.prices.averageSellPrice"
+/// into its segments, without needing a JSON document to walk.
+pub fn parse_field_path(field_path: &str) -> Result<Vec<PathSegment>, String> {
+    let mut segments = Vec::new();
     let mut remaining_path = field_path;
-    
+
     while !remaining_path.is_empty() {
-        // Check if we have an array access pattern
-        if let Some(bracket_start) = remaining_path.find('[') {
-            // Extract the field name before the bracket (if any)
-            let field_name = &remaining_path[..bracket_start];
-            if !field_name.is_empty() {
-                current = current.get(field_name).ok_or_else(|| {
-                    format!("Field '{}' not found", field_name)
-                })?;
-            }
-            
-            // Find the closing bracket
-            let bracket_end = remaining_path.find(']').ok_or_else(|| {
+        if let Some(rest) = remaining_path.strip_prefix('[') {
+            // Array access, find the closing bracket and parse the index
+            let bracket_end = rest.find(']').ok_or_else(|| {
                 "Missing closing bracket in field path".to_string()
             })?;
-            
-            // Extract and parse the array index
-            let index_str = &remaining_path[bracket_start + 1..bracket_end];
-            let index: usize = index_str.parse().map_err(|_| {
-                format!("Invalid array index: '{}'", index_str)
-            })?;
-            
-            // Access the array element
-            current = current.get(index).ok_or_else(|| {
-                format!("Array index {} not found or out of bounds", index)
-            })?;
-            
-            // Move past the bracket and optional dot
-            remaining_path = &remaining_path[bracket_end + 1..];
-            if remaining_path.starts_with('.') {
-                remaining_path = &remaining_path[1..];
+            let index_str = &rest[..bracket_end];
+            let segment = match index_str {
+                "last" => PathSegment::Last,
+                "*" => PathSegment::Each,
+                "min" => PathSegment::Aggregate(Aggregate::Min),
+                "max" => PathSegment::Aggregate(Aggregate::Max),
+                "avg" => PathSegment::Aggregate(Aggregate::Avg),
+                _ if index_str.contains('=') => parse_filter(index_str)?,
+                _ => PathSegment::Index(index_str.parse().map_err(|_| {
+                    format!("Invalid array index: '{}'", index_str)
+                })?),
+            };
+            if matches!(segments.last(), Some(PathSegment::Aggregate(_))) {
+                return Err("An aggregate must end the field path".to_string());
             }
+            segments.push(segment);
+            remaining_path = &rest[bracket_end + 1..];
         } else {
-            // Handle regular field access with dot notation
-            if let Some(dot_pos) = remaining_path.find('.') {
-                let field_name = &remaining_path[..dot_pos];
-                current = current.get(field_name).ok_or_else(|| {
-                    format!("Field '{}' not found", field_name)
-                })?;
-                remaining_path = &remaining_path[dot_pos + 1..];
-            } else {
-                // Last component in the path
-                current = current.get(remaining_path).ok_or_else(|| {
-                    format!("Field '{}' not found", remaining_path)
-                })?;
-                break;
+            // Field access, runs until the next dot or bracket
+            let end = remaining_path
+                .find(['.', '['])
+                .unwrap_or(remaining_path.len());
+            let field_name = &remaining_path[..end];
+            if field_name.is_empty() {
+                return Err("Empty field name in field path".to_string());
             }
+            if matches!(segments.last(), Some(PathSegment::Aggregate(_))) {
+                return Err("An aggregate must end the field path".to_string());
+            }
+            segments.push(PathSegment::Field(field_name.to_string()));
+            remaining_path = &remaining_path[end..];
+        }
+
+        // Move past the optional dot separator
+        if let Some(rest) = remaining_path.strip_prefix('.') {
+            remaining_path = rest;
         }
     }
-    
+
+    Ok(segments)
+}
+
+/// Parse the `field=value` of a filter segment, the value optionally quoted.
+fn parse_filter(filter: &str) -> Result<PathSegment, String> {
+    let (field, value) = filter.split_once('=').unwrap_or((filter, ""));
+    let (field, value) = (field.trim(), value.trim());
+    let value = value
+        .strip_prefix('\'')
+        .and_then(|v| v.strip_suffix('\''))
+        .or_else(|| value.strip_prefix('"').and_then(|v| v.strip_suffix('"')))
+        .unwrap_or(value);
+    if field.is_empty() || value.is_empty() {
+        return Err(format!("Invalid array filter: '{}'", filter));
+    }
+    Ok(PathSegment::Filter {
+        field: field.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// Extract a value from JSON using a field path that supports both object fields and array indices
+/// Supports paths like: "response[0].cardmarket.prices.averageSellPrice"
+/// Paths starting with '/' are XPath-like selectors of XML responses, see [`xml`].
+pub fn extract_field_from_json<'a>(json: &'a Value, field_path: &str) -> Result<&'a Value, String> {
+    if field_path.starts_with('/') {
+        return xml::select(json, field_path);
+    }
+    let segments = parse_field_path(field_path)?;
+    if segments
+        .iter()
+        .any(|segment| matches!(segment, PathSegment::Each | PathSegment::Aggregate(_)))
+    {
+        return Err(format!(
+            "'{}' selects several values, which only prices can aggregate",
+            field_path
+        ));
+    }
+    match select_values(json, &segments)?[..] {
+        [value] => Ok(value),
+        ref values => Err(format!(
+            "'{}' matched {} array elements, expected one",
+            field_path,
+            values.len()
+        )),
+    }
+}
+
+/// Whether `segments` can select several values, through `[*]` or a filter.
+fn fans_out(segments: &[PathSegment]) -> bool {
+    segments
+        .iter()
+        .any(|segment| matches!(segment, PathSegment::Each | PathSegment::Filter { .. }))
+}
+
+/// Every value `segments` select, `[*]` and filters fanning out over array
+/// elements. Aggregates are left to the caller.
+fn select_values<'a>(json: &'a Value, segments: &[PathSegment]) -> Result<Vec<&'a Value>, String> {
+    let mut current = vec![json];
+
+    for segment in segments {
+        current = match segment {
+            PathSegment::Field(field_name) => current
+                .into_iter()
+                .map(|value| {
+                    value
+                        .get(field_name)
+                        .ok_or_else(|| format!("Field '{}' not found", field_name))
+                })
+                .collect::<Result<_, _>>()?,
+            PathSegment::Index(index) => current
+                .into_iter()
+                .map(|value| {
+                    value.get(index).ok_or_else(|| {
+                        format!("Array index {} not found or out of bounds", index)
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            PathSegment::Last => current
+                .into_iter()
+                .map(|value| {
+                    value
+                        .as_array()
+                        .and_then(|array| array.last())
+                        .ok_or_else(|| "[last] needs a non-empty array".to_string())
+                })
+                .collect::<Result<_, _>>()?,
+            PathSegment::Each => current
+                .into_iter()
+                .map(|value| {
+                    value
+                        .as_array()
+                        .ok_or_else(|| "[*] needs an array".to_string())
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .flatten()
+                .collect(),
+            PathSegment::Filter { field, value } => {
+                let arrays = current
+                    .into_iter()
+                    .map(|array| {
+                        array
+                            .as_array()
+                            .ok_or_else(|| format!("[{}={}] needs an array", field, value))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let matched: Vec<&Value> = arrays
+                    .into_iter()
+                    .flatten()
+                    .filter(|element| match element.get(field) {
+                        Some(Value::String(s)) => s == value,
+                        Some(Value::Number(n)) => n.to_string() == *value,
+                        _ => false,
+                    })
+                    .collect();
+                if matched.is_empty() {
+                    return Err(format!("No array element has {}={}", field, value));
+                }
+                matched
+            }
+            PathSegment::Aggregate(_) => {
+                return Err("An aggregate must end the field path".to_string())
+            }
+        };
+    }
+
     Ok(current)
 }
 
+/// Fetch the price of a feed registered on chain and sign it. The route is
+/// served by [`PriceFeedModule`]; this function carries its OpenAPI
+/// description and can be called directly.
+#[utoipa::path(
+    post,
+    path = "/process_data",
+    tag = "price feed",
+    request_body = ProcessDataRequest<PriceFeedRequest>,
+    params(
+        ("x-consumer-key" = Option<String>, Header, description = "API key of the consumer, see `[consumers]`"),
+        ("x-sui-signature" = Option<String>, Header, description = "Sui wallet signature of the timestamp and body, instead of an API key"),
+        ("x-sui-timestamp-ms" = Option<u64>, Header, description = "Time the wallet signature was made"),
+    ),
+    responses(
+        (status = 200, description = "Signed price", body = ProcessedDataResponse<IntentMessage<PriceFeedResponse>>),
+        (status = 400, description = "Invalid feed or failed fetch", body = crate::ErrorResponse),
+        (status = 401, description = "Missing or unknown consumer key or wallet", body = crate::ErrorResponse),
+        (status = 403, description = "Outside the feed's signing windows", body = crate::ErrorResponse),
+        (status = 503, description = "Signing is paused, globally or for the feed", body = crate::ErrorResponse),
+    )
+)]
 pub async fn process_data(
-    State(state): State<Arc<AppState>>,
-    Json(request): Json<ProcessDataRequest<PriceFeedRequest>>,
+    state: State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> Result<Json<ProcessedDataResponse<IntentMessage<PriceFeedResponse>>>, EnclaveError> {
+    oracle::handle::<PriceFeedModule>(state, headers, body).await
+}
+
+/// Signed prices of on-chain PriceFeed objects, the first oracle module.
+pub struct PriceFeedModule;
+
+impl OracleModule for PriceFeedModule {
+    const NAME: &'static str = "price_feed";
+    const PATH: &'static str = "/process_data";
+    const INTENT: IntentScope = IntentScope::PriceFeed;
+
+    type Request = PriceFeedRequest;
+    type Response = PriceFeedResponse;
+
+    fn authorize(request: &PriceFeedRequest, consumer: Option<&str>) -> Result<(), EnclaveError> {
+        match (request.decimals, consumer) {
+            (Some(_), None) => Err(EnclaveError::Unauthorized(
+                "Reduced precision is only served to authenticated consumers".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    #[instrument(name = "process_data", skip_all, fields(price_feed_id = %request.price_feed_id))]
+    async fn process(
+        state: &AppState,
+        request: PriceFeedRequest,
+    ) -> Result<Signed<PriceFeedResponse>, EnclaveError> {
+        if let Some(signed) = presigned_price(state, &request)? {
+            return Ok(signed);
+        }
+        sign_and_cache(state, request).await
+    }
+}
+
+/// Sign a price for `request`, keeping it for batch items and hot feed
+/// requests if it is at full precision of the feed's own URL.
+pub async fn sign_and_cache(
+    state: &AppState,
+    request: PriceFeedRequest,
+) -> Result<Signed<PriceFeedResponse>, EnclaveError> {
+    let price_feed_id = request.price_feed_id.clone();
+    let cacheable = request.decimals.is_none() && request.params.is_empty();
+    let signed = sign_price_feed(state, request).await?;
+    if cacheable {
+        state.signed_prices.store(&price_feed_id, &signed);
+    }
+    Ok(signed)
+}
+
+/// Fetch the PriceFeed objects of several feeds in as few RPC calls as
+/// possible, for [`PriceFeedRequest::price_feed`]. Feeds that fail are left
+/// out, to be fetched and reported by their own request.
+pub(crate) async fn prefetch_price_feeds(state: &AppState, price_feed_ids: &[&str]) -> HashMap<String, PriceFeed> {
+    if price_feed_ids.is_empty() {
+        return HashMap::new();
+    }
+    let started = Instant::now();
+    let price_feeds = state.sui_client.load_full().fetch_price_feeds(price_feed_ids).await;
+    telemetry::metrics()
+        .sui_fetch_duration_ms
+        .record(started.elapsed().as_secs_f64() * 1000.0, &[]);
+    price_feed_ids
+        .iter()
+        .zip(price_feeds)
+        .filter_map(|(price_feed_id, price_feed)| match price_feed {
+            Ok(price_feed) => Some((price_feed_id.to_string(), price_feed)),
+            Err(e) => {
+                warn!("Prefetch of price feed {} failed: {}", price_feed_id, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// The price the refresher signed for a hot feed, if recent enough to serve
+/// instead of signing one. Requests needing a payment, reduced precision or
+/// URL parameters always get a price of their own.
+fn presigned_price(
+    state: &AppState,
+    request: &PriceFeedRequest,
+) -> Result<Option<Signed<PriceFeedResponse>>, EnclaveError> {
+    let config = state.config.load_full();
+    let price_feed_id = &request.price_feed_id;
+    if request.decimals.is_some()
+        || !request.params.is_empty()
+        || config.payments.required
+        || !config.refresher.is_hot(price_feed_id)
+    {
+        return Ok(None);
+    }
+    let now_ms = current_timestamp_ms()?;
+    let Some(signed) = state
+        .signed_prices
+        .get(price_feed_id, config.refresher.max_age_ms, now_ms)
+    else {
+        return Ok(None);
+    };
+    // A price signed before a pause or the end of a window is held back too
+    check_may_sign(state, &config, &config.feed(price_feed_id), price_feed_id)?;
+    Ok(Some(signed))
+}
+
+/// Refuse paused feeds, and feeds outside their signing windows or, when
+/// set to refuse, their market hours.
+pub(crate) fn check_may_sign(
+    state: &AppState,
+    config: &Config,
+    feed_config: &FeedConfig,
+    price_feed_id: &str,
+) -> Result<(), EnclaveError> {
+    state.pause.check(config, Some(price_feed_id))?;
+    if let Some(signing_windows) = &feed_config.signing_windows {
+        check_signing_window(signing_windows, "signing windows", current_timestamp_ms()?)?;
+    }
+    if let (Some(market_hours), WhenClosed::Refuse) = (&feed_config.market_hours, feed_config.when_closed) {
+        check_signing_window(market_hours, "market hours", current_timestamp_ms()?)?;
+    }
+    Ok(())
+}
+
+async fn sign_price_feed(
+    state: &AppState,
+    request: PriceFeedRequest,
+) -> Result<ProcessedDataResponse<IntentMessage<PriceFeedResponse>>, EnclaveError> {
+    // Take a consistent snapshot of the config for the whole request
+    let config = state.config.load_full();
+    let price_feed_id = request.price_feed_id;
+    let feed_config = config.feed(&price_feed_id);
+
+    // Feeds restricted to signing windows are refused outright outside them,
+    // as are feeds set to refuse rather than serve the last close
+    check_may_sign(state, &config, &feed_config, &price_feed_id)?;
+
+    // Check the payment before doing any work; it is only consumed once
+    // there is a price to sign.
+    let receipt_id = if config.payments.required {
+        let receipt_id = request.receipt_id.ok_or_else(|| {
+            EnclaveError::GenericError("A payment receipt_id is required".to_string())
+        })?;
+        verify_payment(state, &config, &price_feed_id, &receipt_id).await?;
+        Some(receipt_id)
+    } else {
+        None
+    };
+    let consume_receipt = || match &receipt_id {
+        Some(receipt_id) if !state.receipts.consume(receipt_id) => Err(EnclaveError::GenericError(
+            format!("Receipt {} has already been used", receipt_id),
+        )),
+        _ => Ok(()),
+    };
+
     // Fetch the PriceFeed object from Sui network
-    let price_feed = state
-        .sui_client
-        .fetch_price_feed(&request.payload.price_feed_id)
-        .await
-        .map_err(|e| EnclaveError::GenericError(format!("Failed to fetch price feed: {}", e)))?;
+    let mut price_feed = match request.price_feed {
+        Some(price_feed) => price_feed,
+        None => {
+            let started = Instant::now();
+            let price_feed = state
+                .sui_client
+                .load_full()
+                .fetch_price_feed(&price_feed_id)
+                .await
+                .map_err(|e| {
+                    EnclaveError::GenericError(format!("Failed to fetch price feed: {}", e))
+                });
+            telemetry::metrics()
+                .sui_fetch_duration_ms
+                .record(started.elapsed().as_secs_f64() * 1000.0, &[]);
+            price_feed?
+        }
+    };
 
     // Check if the price feed is valid
     if !price_feed.is_valid {
@@ -110,136 +741,862 @@ pub async fn process_data(
         ));
     }
 
-    // Create HTTP client
-    let client = reqwest::Client::new();
-    let mut request_builder = client.get(&price_feed.underlying_url);
+    // Fill the URL template from the request's parameters. Such prices are
+    // for another symbol or date than the feed's own, so they are neither
+    // compared with nor recorded in its history.
+    price_feed.underlying_url = fill_url_template(&price_feed.underlying_url, &request.params)
+        .map_err(EnclaveError::GenericError)?;
+    let templated = !request.params.is_empty();
 
-    // Add authentication headers if configured
-    if let (Some(api_key), Some(api_key_config)) = (&price_feed.api_key, &price_feed.api_key_config) {
-        match api_key_config.as_str() {
-            "Bearer" => {
-                request_builder = request_builder.header("Authorization", format!("Bearer {}", api_key));
-            }
-            "x-api-key" => {
-                request_builder = request_builder.header("x-api-key", api_key);
+    // Self-check the extraction against the feed's example response, if any
+    if let Some(example) = &feed_config.example {
+        example.check(&feed_config.format, &price_feed.response_field).map_err(|e| {
+            warn!("Feed {} failed its extraction self-check: {}", price_feed_id, e);
+            EnclaveError::GenericError(format!("Feed failed its extraction self-check: {}", e))
+        })?;
+    }
+
+    let feed_object = config
+        .response
+        .include_feed_object
+        .then(|| FeedObject::of(&price_feed));
+
+    let current_timestamp = current_timestamp_ms()?;
+
+    // Outside market hours serve the last close, flagged as such, rather than
+    // signing a stale upstream tick as fresh.
+    if let Some(market_hours) = &feed_config.market_hours {
+        let now = DateTime::<Utc>::from_timestamp_millis(current_timestamp as i64)
+            .ok_or_else(|| EnclaveError::GenericError("Invalid current timestamp".to_string()))?;
+        let is_open = market_hours.is_open(now).map_err(|e| {
+            EnclaveError::GenericError(format!("Invalid market hours for feed: {}", e))
+        })?;
+        if !is_open {
+            if request.decimals.is_some() {
+                return Err(EnclaveError::GenericError(
+                    "Reduced precision is not served while the market is closed".to_string(),
+                ));
             }
-            _ => {
+            if templated {
                 return Err(EnclaveError::GenericError(
-                    format!("Unsupported api_key_config: {}", api_key_config),
+                    "URL parameters are not served while the market is closed".to_string(),
                 ));
             }
+            let last_close = match state.history.latest(&price_feed_id) {
+                Some(observation) => observation,
+                None => {
+                    let observation = Observation {
+                        price: fetch_price(state, &config, &price_feed_id, &price_feed)
+                            .await?
+                            .price_u64()?,
+                        timestamp_ms: current_timestamp,
+                    };
+                    state.history.record(&price_feed_id, observation);
+                    observation
+                }
+            };
+
+            let response = PriceFeedResponse {
+                oracle_id: price_feed.oracle_id,
+                price_feed_id,
+                price: last_close.price,
+                timestamp_ms: last_close.timestamp_ms,
+                rate_of_change: None,
+                market_closed: true,
+                source_timestamp_ms: None,
+                decimals: None,
+                rounding: None,
+                feed_object,
+                params: BTreeMap::new(),
+            };
+            let update_id = response.update_id();
+            let intent = response.intent();
+            let kp = state.signing_keys.signer_for(intent, current_timestamp, current_timestamp)?;
+            consume_receipt()?;
+            let signed = info_span!("sign")
+                .in_scope(|| to_signed_response(&kp, response, current_timestamp, intent));
+            return Ok(signed.with_update_id(update_id));
         }
     }
 
-    // Make the request
-    let response = request_builder.send().await.map_err(|e| {
-        EnclaveError::GenericError(format!("Failed to get price feed response: {}", e))
-    })?;
+    let upstream = fetch_price(state, &config, &price_feed_id, &price_feed).await?;
+    let price = upstream.price_u64()?;
 
-    let json = response.json::<Value>().await.map_err(|e| {
-        EnclaveError::GenericError(format!("Failed to parse price feed response: {}", e))
-    })?;
+    let previous = if templated {
+        None
+    } else {
+        state.history.latest(&price_feed_id)
+    };
+    let max_deviation_bps = feed_config
+        .max_deviation_bps
+        .or(config.circuit_breaker.max_deviation_bps);
+    if let (Some(max_deviation_bps), Some(previous)) = (max_deviation_bps, previous) {
+        let breaker = &config.circuit_breaker;
+        if let Some(deviation_bps) = breaker.tripped(max_deviation_bps, previous, price, current_timestamp) {
+            if state.breaker_overrides.take(&price_feed_id) {
+                warn!(
+                    "Feed {} moved {} bps from {} to {}, signing under operator override",
+                    price_feed_id, deviation_bps, previous.price, price
+                );
+            } else if breaker.mode == BreakerMode::Flag {
+                warn!(
+                    "Feed {} moved {} bps from {} to {}, above the {} bps limit",
+                    price_feed_id, deviation_bps, previous.price, price, max_deviation_bps
+                );
+            } else {
+                // The price is not recorded, so later requests still compare
+                // against the last signed one.
+                return Err(EnclaveError::GenericError(format!(
+                    "Circuit breaker tripped: price moved {} bps from {} to {}, above the {} bps limit. \
+                     POST /admin/override_breaker to sign it anyway",
+                    deviation_bps, previous.price, price, max_deviation_bps
+                )));
+            }
+        }
+    }
+    if !templated {
+        state.history.record(
+            &price_feed_id,
+            Observation {
+                price,
+                timestamp_ms: current_timestamp,
+            },
+        );
+    }
+
+    // Reduced precision prices carry no rate of change, which is relative to
+    // full precision history
+    let (price, rate_of_change) = match request.decimals {
+        Some(decimals) => (reduce_precision(price, upstream.decimals, decimals)?, None),
+        None => {
+            let rate_of_change = if config.response.include_rate_of_change {
+                previous.and_then(|previous| RateOfChange::between(previous, price, current_timestamp))
+            } else {
+                None
+            };
+            (price, rate_of_change)
+        }
+    };
+
+    let timestamp_source = feed_config.timestamp_source;
+    let timestamp_ms =
+        intent_timestamp_ms(state, &config, &feed_config, &upstream, current_timestamp).await?;
+    let response = PriceFeedResponse {
+        oracle_id: price_feed.oracle_id,
+        price_feed_id,
+        price,
+        timestamp_ms,
+        rate_of_change,
+        market_closed: false,
+        source_timestamp_ms: upstream.source_timestamp_ms,
+        decimals: request.decimals,
+        rounding: upstream.rounding,
+        feed_object,
+        params: request.params,
+    };
+    let update_id = response.update_id();
+    let intent = response.intent();
+    let kp = state.signing_keys.signer_for(intent, current_timestamp, timestamp_ms)?;
+    consume_receipt()?;
+    let signed =
+        info_span!("sign").in_scope(|| to_signed_response(&kp, response, timestamp_ms, intent));
+    Ok(signed
+        .with_update_id(update_id)
+        .with_timestamp_source(timestamp_source))
+}
+
+/// Timestamp to sign a fresh price with, from the feed's `timestamp_source`.
+/// Sui clock readings must be as fresh as upstream timestamps, so a lagging
+/// fullnode is not mistaken for the time.
+pub(crate) async fn intent_timestamp_ms(
+    state: &AppState,
+    config: &Config,
+    feed_config: &FeedConfig,
+    upstream: &UpstreamPrice,
+    now_ms: u64,
+) -> Result<u64, EnclaveError> {
+    match feed_config.timestamp_source {
+        TimestampSource::Enclave => Ok(now_ms),
+        TimestampSource::Upstream => upstream.source_timestamp_ms.ok_or_else(|| {
+            EnclaveError::GenericError(
+                "Feed signs with the upstream timestamp but has no timestamp_field".to_string(),
+            )
+        }),
+        TimestampSource::SuiClock => {
+            let clock_ms = state
+                .sui_client
+                .load_full()
+                .fetch_clock_ms()
+                .await
+                .map_err(|e| EnclaveError::GenericError(format!("Failed to fetch Sui clock: {}", e)))?;
+            let max_staleness_secs = feed_config
+                .max_staleness_secs
+                .unwrap_or(config.response.max_staleness_secs);
+            check_freshness(clock_ms, now_ms, max_staleness_secs)
+                .map_err(|e| EnclaveError::GenericError(format!("Sui clock: {}", e)))?;
+            Ok(clock_ms)
+        }
+    }
+}
+
+/// Round `price`, scaled by 10^`from`, to `to` decimals, half away from zero.
+fn reduce_precision(price: u64, from: u32, to: u32) -> Result<u64, EnclaveError> {
+    if to > from {
+        return Err(EnclaveError::GenericError(format!(
+            "Requested {} decimals but the feed is published with {}",
+            to, from
+        )));
+    }
+    let factor = 10_u128.pow(from - to);
+    let price = u128::from(price);
+    let rounded = price / factor + u128::from(price % factor * 2 >= factor);
+    // At most `price`, so it fits
+    Ok(rounded as u64)
+}
+
+/// Extract the price at `response_field` of an upstream response. The value
+/// may be a JSON number or a number in a string. A path ending in an
+/// aggregate such as `[max]` aggregates the values selected with `[*]` or a
+/// filter, or the elements of the array it names.
+pub fn extract_price(json: &Value, response_field: &str) -> Result<Decimal, String> {
+    let failed = |e| format!("Failed to extract price from field '{}': {}", response_field, e);
+    if !response_field.starts_with('/') {
+        if let [path @ .., PathSegment::Aggregate(aggregate)] =
+            parse_field_path(response_field).map_err(failed)?.as_slice()
+        {
+            let mut values = select_values(json, path).map_err(failed)?;
+            if !fans_out(path) {
+                values = values[0]
+                    .as_array()
+                    .ok_or_else(|| failed("an aggregate needs an array or [*]".to_string()))?
+                    .iter()
+                    .collect();
+            }
+            let prices = values
+                .into_iter()
+                .map(|value| decimal_value(value, response_field))
+                .collect::<Result<Vec<_>, _>>()?;
+            return aggregate.apply(&prices).map_err(failed);
+        }
+    }
 
     // Use the new extraction function to handle complex field paths
-    let price_value = extract_field_from_json(&json, &price_feed.response_field)
-        .map_err(|e| {
-            EnclaveError::GenericError(format!(
-                "Failed to extract price from field '{}': {}",
-                price_feed.response_field, e
-            ))
-        })?;
+    let price_value = extract_field_from_json(json, response_field).map_err(failed)?;
+    decimal_value(price_value, response_field)
+}
 
-    let price_decimal = if let Some(price_str) = price_value.as_str() {
+/// The number in a JSON number or string at `response_field`.
+fn decimal_value(price_value: &Value, response_field: &str) -> Result<Decimal, String> {
+    if let Some(price_str) = price_value.as_str() {
         Decimal::from_str(price_str).map_err(|e| {
-            EnclaveError::GenericError(format!(
-                "Price field '{}' is not a valid number string: {}",
-                price_feed.response_field, e
-            ))
-        })?
+            format!("Price field '{}' is not a valid number string: {}", response_field, e)
+        })
     } else if price_value.is_number() {
-        let price_str = price_value.to_string();
-        Decimal::from_str(&price_str).map_err(|e| {
-            EnclaveError::GenericError(format!(
-                "Price field '{}' is not a valid number: {}",
-                price_feed.response_field, e
-            ))
-        })?
+        Decimal::from_str(&price_value.to_string())
+            .map_err(|e| format!("Price field '{}' is not a valid number: {}", response_field, e))
     } else {
-        return Err(EnclaveError::GenericError(format!(
+        Err(format!(
             "Price field '{}' is neither a string nor a number",
-            price_feed.response_field
+            response_field
+        ))
+    }
+}
+
+/// Refuse signing at `now_ms` unless it falls in one of `signing_windows`,
+/// named `what` in errors.
+fn check_signing_window(
+    signing_windows: &MarketHours,
+    what: &str,
+    now_ms: u64,
+) -> Result<(), EnclaveError> {
+    let now = DateTime::<Utc>::from_timestamp_millis(now_ms as i64)
+        .ok_or_else(|| EnclaveError::GenericError("Invalid current timestamp".to_string()))?;
+    let invalid = |e| EnclaveError::GenericError(format!("Invalid {} for feed: {}", what, e));
+    if signing_windows.is_open(now).map_err(invalid)? {
+        return Ok(());
+    }
+    let next = signing_windows.next_open(now).map_err(invalid)?;
+    let message = match next {
+        Some(next) => format!(
+            "Feed only signs within its {}, the next opens at {}",
+            what,
+            next.to_rfc3339()
+        ),
+        None => format!(
+            "Feed only signs within its {} and none opens in the next four weeks",
+            what
+        ),
+    };
+    Err(EnclaveError::OutsideSigningWindow {
+        message,
+        retry_after_secs: next.map(|next| (next - now).num_seconds().max(1) as u64),
+    })
+}
+
+/// Check that `receipt_id` is an unused receipt paying the fee of
+/// `price_feed_id`.
+pub(crate) async fn verify_payment(
+    state: &AppState,
+    config: &Config,
+    price_feed_id: &str,
+    receipt_id: &str,
+) -> Result<(), EnclaveError> {
+    if state.receipts.is_consumed(receipt_id) {
+        return Err(EnclaveError::GenericError(format!(
+            "Receipt {} has already been used",
+            receipt_id
         )));
+    }
+    let receipt = state
+        .sui_client
+        .load_full()
+        .fetch_receipt(receipt_id, &config.payments.receipt_type)
+        .await
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to fetch receipt: {}", e)))?;
+    let fee = fees::fee_mist(config, price_feed_id, Priority::Standard);
+    let max_age_ms = config.payments.max_receipt_age_secs.map(|secs| secs.saturating_mul(1000));
+    check_receipt(&receipt, price_feed_id, fee, max_age_ms, current_timestamp_ms()?)
+        .map_err(EnclaveError::GenericError)
+}
+
+/// Upstream timestamps may run ahead of the enclave clock by this much.
+const MAX_CLOCK_SKEW_MS: u64 = 5_000;
+
+/// Numeric upstream timestamps below this are taken as seconds, anything
+/// else as milliseconds. 1e11 seconds is thousands of years away while 1e11
+/// milliseconds is in 1973.
+const SECONDS_THRESHOLD: u64 = 100_000_000_000;
+
+/// Price read from a feed's upstream API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct UpstreamPrice {
+    /// Scaled to the configured number of decimals, see [`Self::price_u64`]
+    pub price: i128,
+    pub decimals: u32,
+    /// The upstream's own timestamp for the price, for feeds with a
+    /// `timestamp_field`
+    pub source_timestamp_ms: Option<u64>,
+    /// The feed's configured rounding mode, if any
+    pub rounding: Option<RoundingMode>,
+}
+
+impl UpstreamPrice {
+    /// The price as the u64 of `PriceFeedResponse`.
+    pub fn price_u64(&self) -> Result<u64, EnclaveError> {
+        self.check_not_negative()?;
+        u64::try_from(self.price).map_err(|_| {
+            EnclaveError::GenericError(format!(
+                "Scaled price is too large to fit in u64 (decimals: {}), \
+                 request it from /process_data_wide",
+                self.decimals
+            ))
+        })
+    }
+
+    /// The price as the u128 of `WidePriceFeedResponse`.
+    pub fn price_u128(&self) -> Result<u128, EnclaveError> {
+        self.check_not_negative()?;
+        Ok(self.price.unsigned_abs())
+    }
+
+    fn check_not_negative(&self) -> Result<(), EnclaveError> {
+        if self.price < 0 {
+            return Err(EnclaveError::GenericError(
+                "Price is negative, request it from /process_rate".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Extract the timestamp at `timestamp_field` of an upstream response as
+/// milliseconds since the epoch. Accepts Unix seconds or milliseconds, as a
+/// number or a string, and RFC 3339 strings.
+pub fn extract_timestamp_ms(json: &Value, timestamp_field: &str) -> Result<u64, String> {
+    let value = extract_field_from_json(json, timestamp_field).map_err(|e| {
+        format!("Failed to extract timestamp from field '{}': {}", timestamp_field, e)
+    })?;
+
+    let unix = match value {
+        Value::Number(n) => n.as_u64().or_else(|| n.as_f64().filter(|f| *f >= 0.0).map(|f| f as u64)),
+        Value::String(s) => match s.parse::<u64>() {
+            Ok(unix) => Some(unix),
+            Err(_) => {
+                let parsed = DateTime::parse_from_rfc3339(s).map_err(|e| {
+                    format!("Timestamp field '{}' is not a valid timestamp: {}", timestamp_field, e)
+                })?;
+                return u64::try_from(parsed.timestamp_millis()).map_err(|_| {
+                    format!("Timestamp field '{}' is before the epoch", timestamp_field)
+                });
+            }
+        },
+        _ => None,
     };
+    let unix = unix.ok_or_else(|| {
+        format!("Timestamp field '{}' is not a valid timestamp", timestamp_field)
+    })?;
+    Ok(if unix < SECONDS_THRESHOLD {
+        unix * 1000
+    } else {
+        unix
+    })
+}
 
-    // Convert to fixed-point representation using configurable decimals
-    let scale_factor = Decimal::from(10_u64.pow(state.config.response.price_decimals));
-    let price = (price_decimal * scale_factor).to_u64().ok_or_else(|| {
-        EnclaveError::GenericError(format!(
-            "Scaled price is too large to fit in u64 (decimals: {})",
-            state.config.response.price_decimals
-        ))
+/// Check an upstream timestamp is at most `max_staleness_secs` old at `now_ms`
+/// and not ahead of it by more than the allowed clock skew.
+fn check_freshness(source_ms: u64, now_ms: u64, max_staleness_secs: u64) -> Result<(), String> {
+    if source_ms > now_ms.saturating_add(MAX_CLOCK_SKEW_MS) {
+        return Err(format!(
+            "Upstream timestamp {} is {} ms in the future",
+            source_ms,
+            source_ms - now_ms
+        ));
+    }
+    let age_ms = now_ms.saturating_sub(source_ms);
+    if age_ms > max_staleness_secs.saturating_mul(1000) {
+        return Err(format!(
+            "Upstream timestamp {} is {} ms old, more than the {} s allowed",
+            source_ms, age_ms, max_staleness_secs
+        ));
+    }
+    Ok(())
+}
+
+/// Percent-encode `value` for use inside a URL, keeping only unreserved
+/// characters as they are.
+pub fn encode_url_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Longest value of a URL template parameter.
+const MAX_URL_PARAM_LEN: usize = 64;
+
+/// Fill the `{name}` placeholders of a feed's `underlying_url` from the
+/// request's `params`, percent-encoding each value. Every placeholder needs
+/// a value and every value a placeholder, so a request cannot change the URL
+/// beyond its templated parts. Braces around anything but a name of
+/// letters, digits and underscores are left as they are.
+pub fn fill_url_template(url: &str, params: &BTreeMap<String, String>) -> Result<String, String> {
+    for (name, value) in params {
+        if value.is_empty() || value.len() > MAX_URL_PARAM_LEN {
+            return Err(format!(
+                "Parameter '{}' must be 1 to {} bytes long",
+                name, MAX_URL_PARAM_LEN
+            ));
+        }
+    }
+    let mut filled = String::with_capacity(url.len());
+    let mut used = BTreeSet::new();
+    let mut rest = url;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}') {
+            Some(end) if is_param_name(&after[..end]) => {
+                let name = &after[..end];
+                let value = params
+                    .get(name)
+                    .ok_or_else(|| format!("URL placeholder {{{}}} needs a value in params", name))?;
+                filled.push_str(&encode_url_component(value));
+                used.insert(name);
+                rest = &after[end + 1..];
+            }
+            _ => {
+                filled.push('{');
+                rest = after;
+            }
+        }
+    }
+    filled.push_str(rest);
+    match params.keys().find(|name| !used.contains(name.as_str())) {
+        Some(name) => Err(format!("Parameter '{}' is not a placeholder of the feed's URL", name)),
+        None => Ok(filled),
+    }
+}
+
+fn is_param_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Add the authentication header for `api_key`, given as `(key, scheme)`,
+/// to an upstream request of `url`. HMAC signed requests need the feed's
+/// `hmac` settings.
+pub fn with_api_key(
+    request_builder: reqwest::RequestBuilder,
+    url: &str,
+    api_key: Option<(&str, &str)>,
+    hmac: Option<&HmacSigning>,
+) -> Result<reqwest::RequestBuilder, EnclaveError> {
+    match api_key {
+        None => Ok(request_builder),
+        Some((api_key, "Bearer")) => Ok(request_builder.header("Authorization", format!("Bearer {}", api_key))),
+        Some((api_key, "x-api-key")) => Ok(request_builder.header("x-api-key", api_key)),
+        Some((key_id, HMAC_SHA256)) => match hmac {
+            Some(hmac) => hmac.sign(request_builder, key_id, url, current_timestamp_ms()?),
+            None => Err(EnclaveError::GenericError(format!(
+                "api_key_config {} needs an hmac section in the feed's config",
+                HMAC_SHA256
+            ))),
+        },
+        Some((_, OAUTH2)) => Err(EnclaveError::GenericError(format!(
+            "api_key_config {} needs an oauth2 section in the feed's config",
+            OAUTH2
+        ))),
+        Some((_, api_key_config)) => Err(EnclaveError::GenericError(
+            format!("Unsupported api_key_config: {}", api_key_config),
+        )),
+    }
+}
+
+/// Add the feed's configured static headers and User-Agent to an upstream
+/// request. They are checked when the config is loaded.
+pub fn with_feed_headers(
+    mut request_builder: reqwest::RequestBuilder,
+    feed_config: Option<&FeedConfig>,
+) -> reqwest::RequestBuilder {
+    let Some(feed_config) = feed_config else {
+        return request_builder;
+    };
+    for (name, value) in &feed_config.headers {
+        request_builder = request_builder.header(name.as_str(), value.as_str());
+    }
+    if let Some(user_agent) = &feed_config.user_agent {
+        request_builder = request_builder.header(reqwest::header::USER_AGENT, user_agent.as_str());
+    }
+    request_builder
+}
+
+/// GET an upstream API on behalf of `feed_id`, authenticating with
+/// `api_key` as `(key, scheme)` if given, and parse the JSON response within
+/// the configured limits. Shared by every oracle module that reads an
+/// upstream API; the headers configured for `feed_id`, if any, are sent.
+pub async fn fetch_upstream(
+    state: &AppState,
+    config: &Config,
+    feed_id: &str,
+    url: &str,
+    api_key: Option<(&str, &str)>,
+    format: &UpstreamFormat,
+) -> Result<Value, EnclaveError> {
+    // Shared HTTP client, proxied per destination as configured
+    let client = state.outbound.load().client_for(url);
+    let request_builder = client
+        .get(url)
+        .header(reqwest::header::ACCEPT_ENCODING, limits::ACCEPT_ENCODING);
+    let feed_config = config.feeds.get(feed_id);
+    let request_builder = with_feed_headers(request_builder, feed_config);
+    // Keys are kept off chain or encrypted to the enclave
+    let resolved = secrets::resolve_api_key(state, feed_id, url, api_key).await?;
+    let api_key = resolved.as_ref().map(|(key, scheme)| (key.as_str(), *scheme));
+    let oauth2 = feed_config.and_then(|feed_config| feed_config.oauth2.as_ref());
+    let token = oauth::bearer_token(state, api_key, oauth2).await?;
+    let hmac = feed_config.and_then(|feed_config| feed_config.hmac.as_ref());
+    let request_builder = match &token {
+        Some(token) => with_api_key(request_builder, url, Some((token, "Bearer")), None)?,
+        None => with_api_key(request_builder, url, api_key, hmac)?,
+    };
+
+    // Make the request, unless the upstream keeps failing
+    state
+        .upstream_circuits
+        .check(&config.upstream_circuit, url, current_timestamp_ms()?)?;
+    let started = Instant::now();
+    let response = request_builder.send().await;
+    let failed = match &response {
+        Ok(response) => response.status().is_server_error(),
+        Err(_) => true,
+    };
+    state
+        .upstream_circuits
+        .record(&config.upstream_circuit, url, !failed, current_timestamp_ms()?);
+    let status = match &response {
+        Ok(response) => response.status().as_u16().to_string(),
+        Err(_) => "error".to_string(),
+    };
+    telemetry::metrics()
+        .upstream_duration_ms
+        .record(started.elapsed().as_secs_f64() * 1000.0, &[KeyValue::new("status", status)]);
+    let response = response.map_err(|e| {
+        EnclaveError::GenericError(format!("Failed to get upstream response: {}", e))
     })?;
+    let status_code = response.status().as_u16();
+    // A rejected token is fetched anew by the next request
+    if token.is_some() && response.status() == reqwest::StatusCode::UNAUTHORIZED {
+        if let (Some(oauth2), Some((client_id, _))) = (oauth2, api_key) {
+            state.oauth_tokens.invalidate(oauth2, client_id);
+        }
+    }
+
+    // Bound what is read and parsed before trusting the upstream's payload
+    let limits = &config.upstream_limits;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    format
+        .check_content_type(limits, content_type.as_deref())
+        .map_err(EnclaveError::GenericError)?;
+    let content_encoding = response
+        .headers()
+        .get(reqwest::header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let body = limits.read_body(response).await.map_err(EnclaveError::GenericError)?;
+    let body = limits
+        .decode(content_encoding.as_deref(), body)
+        .map_err(EnclaveError::GenericError)?;
+
+    // Keep the raw response for debugging before it can fail to parse
+    if config.capture.responses_per_feed > 0 {
+        let capture = CapturedResponse::new(
+            &config.capture,
+            url,
+            api_key.map(|(key, _)| key),
+            status_code,
+            content_type.as_deref(),
+            &body,
+        );
+        state.captures.record(feed_id, capture, config.capture.responses_per_feed);
+    }
+    let json = format.parse(limits, &body).map_err(EnclaveError::GenericError)?;
+
+    let changes = state.schemas.observe(feed_id, &json);
+    if !changes.is_empty() {
+        warn!(
+            "Upstream response of feed {} changed shape: {}",
+            feed_id,
+            changes.join(", ")
+        );
+        telemetry::metrics()
+            .upstream_schema_changes
+            .add(1, &[KeyValue::new("price_feed_id", feed_id.to_string())]);
+    }
+    Ok(json)
+}
+
+/// Query the feed's upstream API and return the document, the value
+/// extracted or computed by the feed's transform, and that value once scaled
+/// by the feed's expression.
+pub(crate) async fn fetch_value(
+    state: &AppState,
+    config: &Config,
+    price_feed_id: &str,
+    price_feed: &PriceFeed,
+) -> Result<(Value, Decimal, Decimal), EnclaveError> {
+    let feed_config = config.feed(price_feed_id);
+    let json = fetch_upstream(
+        state,
+        config,
+        price_feed_id,
+        &price_feed.underlying_url,
+        price_feed.api_key.as_deref().zip(price_feed.api_key_config.as_deref()),
+        &feed_config.format,
+    )
+    .await?;
+
+    let raw_decimal = match &feed_config.transform {
+        Some(transform) => state
+            .transforms
+            .run(transform, price_feed.transform_hash.as_deref(), &json)
+            .map_err(|e| EnclaveError::GenericError(format!("Transform failed: {}", e)))?,
+        None => {
+            extract_price(&json, &price_feed.response_field).map_err(EnclaveError::GenericError)?
+        }
+    };
+
+    // Apply the feed's scaling expression, e.g. for mantissa and exponent pairs
+    let value = match &feed_config.scale {
+        Some(scale) => scale.apply(&json, raw_decimal).map_err(|e| {
+            EnclaveError::GenericError(format!("Failed to apply scaling expression: {}", e))
+        })?,
+        None => raw_decimal,
+    };
+    Ok((json, raw_decimal, value))
+}
+
+/// Query the feed's upstream API and return the extracted price scaled to
+/// the configured number of decimals, or those of the feed's asset, along
+/// with the upstream timestamp once checked for freshness.
+#[instrument(name = "upstream_fetch", skip_all, fields(url = %price_feed.underlying_url))]
+pub(crate) async fn fetch_price(
+    state: &AppState,
+    config: &Config,
+    price_feed_id: &str,
+    price_feed: &PriceFeed,
+) -> Result<UpstreamPrice, EnclaveError> {
+    fetch_price_traced(state, config, price_feed_id, price_feed)
+        .await
+        .map(|(upstream, _)| upstream)
+}
 
-    let current_timestamp = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .map_err(|e| EnclaveError::GenericError(format!("Failed to get current timestamp: {}", e)))?
-        .as_millis() as u64;
+/// Values a price goes through between the upstream response and its
+/// scaled integer, reported by `/dry_run`.
+#[derive(Debug, Clone)]
+pub(crate) struct PriceTrace {
+    /// The upstream value at `response_field`, if the path names one
+    pub excerpt: Option<Value>,
+    /// Number extracted from the upstream response, or computed by the
+    /// feed's transform
+    pub extracted: Decimal,
+    /// After the feed's scaling expression
+    pub value: Decimal,
+    /// After currency conversion, for feeds with `convert`
+    pub converted: Option<Decimal>,
+    /// After the conventions of the feed's asset
+    pub normalized: Decimal,
+}
+
+/// [`fetch_price`], also returning the values the price went through.
+pub(crate) async fn fetch_price_traced(
+    state: &AppState,
+    config: &Config,
+    price_feed_id: &str,
+    price_feed: &PriceFeed,
+) -> Result<(UpstreamPrice, PriceTrace), EnclaveError> {
+    let feed_config = config.feed(price_feed_id);
+    let (json, raw_decimal, mut value) =
+        fetch_value(state, config, price_feed_id, price_feed).await?;
+    let scaled_value = value;
+
+    // Convert prices quoted in another currency at the FX feed's live rate
+    let mut converted = None;
+    if let Some(convert) = &feed_config.convert {
+        let rate = conversion::fx_rate(state, config, convert).await?;
+        value = convert.apply(value, rate).map_err(|e| {
+            EnclaveError::GenericError(format!("Failed to convert currency: {}", e))
+        })?;
+        converted = Some(value);
+    }
+
+    // Apply the asset's conventions, if the feed names one
+    let asset = config.feed_asset(price_feed_id);
+    let price_decimal = asset.map_or(value, |asset| asset.normalize(value));
+    let decimals = asset
+        .and_then(|asset| asset.decimals)
+        .unwrap_or(config.response.price_decimals);
+
+    // Convert to fixed-point representation using configurable decimals
+    let scale_factor = Decimal::from(10_u64.pow(decimals));
+
+    // Cross-check the extracted value against an independent extractor. Tick
+    // size rounding happens after this and is only done once. Transforms
+    // have a single implementation, the pinned module.
+    if config.response.verify_extraction && feed_config.transform.is_none() {
+        let native = raw_decimal.checked_mul(scale_factor).and_then(|native| native.to_i128());
+        let independent = verify::independent_price(&json, &price_feed.response_field, decimals);
+        if native.is_none() || native != independent.clone().ok() {
+            warn!(
+                "Extraction of feed {} disagrees: native {:?}, independent {:?}",
+                price_feed_id, native, independent
+            );
+            return Err(EnclaveError::GenericError(format!(
+                "Extraction verification failed: native {:?}, independent {:?}",
+                native, independent
+            )));
+        }
+    }
 
-    Ok(Json(to_signed_response(
-        &state.eph_kp,
-        PriceFeedResponse {
-            oracle_id: price_feed.oracle_id,
-            price_feed_id: request.payload.price_feed_id,
+    let rounding = feed_config.rounding;
+    let price = price_decimal
+        .checked_mul(scale_factor)
+        .map(|price| rounding.unwrap_or_default().round(price))
+        .and_then(|price| price.to_i128())
+        .ok_or_else(|| {
+            EnclaveError::GenericError(format!(
+                "Scaled price is too large to represent (decimals: {})",
+                decimals
+            ))
+        })?;
+
+    // The on-chain timestamp_field takes precedence over the configured one
+    let timestamp_field = price_feed
+        .timestamp_field
+        .as_deref()
+        .or(feed_config.timestamp_field.as_deref());
+    let source_timestamp_ms = match timestamp_field {
+        Some(timestamp_field) => {
+            let source_ms =
+                extract_timestamp_ms(&json, timestamp_field).map_err(EnclaveError::GenericError)?;
+            let max_staleness_secs = feed_config
+                .max_staleness_secs
+                .unwrap_or(config.response.max_staleness_secs);
+            check_freshness(source_ms, current_timestamp_ms()?, max_staleness_secs).map_err(|e| {
+                warn!("Feed {} upstream is stale: {}", price_feed_id, e);
+                EnclaveError::GenericError(e)
+            })?;
+            Some(source_ms)
+        }
+        None => None,
+    };
+
+    let trace = PriceTrace {
+        excerpt: extract_field_from_json(&json, &price_feed.response_field).ok().cloned(),
+        extracted: raw_decimal,
+        value: scaled_value,
+        converted,
+        normalized: price_decimal,
+    };
+    Ok((
+        UpstreamPrice {
             price,
-            timestamp_ms: current_timestamp,
+            decimals,
+            source_timestamp_ms,
+            rounding,
         },
-        current_timestamp,
-        IntentScope::PriceFeed,
-    )))
+        trace,
+    ))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::common::IntentMessage;
-    use axum::{extract::State, Json};
+    use axum::extract::State;
     use fastcrypto::{ed25519::Ed25519KeyPair, traits::KeyPair};
 
     #[tokio::test]
     #[ignore] // Ignored since it requires network access and valid price feed data
     async fn test_process_data() {
         use crate::config::{Config, Response, Sui};
-        use crate::sui::SuiClientWrapper;
         
         let config = Config {
             sui: Sui {
                 rpc_url: "https://fullnode.testnet.sui.io:443".to_string(),
                 oracle_builder_package_id: "0x3c15ce11b86d364572f00a40b508d4a80f06d213f37e6b77db3932ffec5c7127".to_string(),
+                ..Default::default()
             },
             response: Response {
                 price_decimals: 8,
+                ..Default::default()
             },
+            ..Default::default()
         };
         
-        let sui_client = SuiClientWrapper::new(
-            &config.sui.rpc_url,
-            config.sui.oracle_builder_package_id.clone(),
-        ).await.unwrap();
-        
-        let state = Arc::new(AppState {
-            eph_kp: Ed25519KeyPair::generate(&mut rand::thread_rng()),
+        let state = AppState::from_config(
+            Ed25519KeyPair::generate(&mut rand::thread_rng()),
             config,
-            sui_client,
-        });
+        ).await.unwrap();
         
         // Replace with a real price feed address when testing
         let result = process_data(
             State(state),
-            Json(ProcessDataRequest {
+            HeaderMap::new(),
+            Bytes::from(serde_json::to_vec(&ProcessDataRequest {
                 payload: PriceFeedRequest {
                     price_feed_id: "0xb2b928c198e2037b5116c4d51ce90a61d534912e49c44d340fab1f8ed3de7e50".to_string(),
+                    receipt_id: None,
+                    decimals: None,
+                    params: BTreeMap::new(),
+                    price_feed: None,
                 },
-            }),
+            }).unwrap()),
         ).await;
         
         // This test will only pass with a valid price feed address
@@ -256,7 +1613,7 @@ mod test {
 
     #[test]
     fn test_serde() {
-        // test result should be consistent with test_serde in `move/enclave/sources/enclave.move`.
+        // test result should be consistent with test_price_feed_layouts in `move/app/sources/oracle_builder.move`.
         use fastcrypto::encoding::{Encoding, Hex};
         let timestamp = 1744038900000;
         let payload = PriceFeedResponse {
@@ -264,20 +1621,208 @@ mod test {
             price_feed_id: "test_price_feed_id".to_string(),
             price: 10050000000, // Price as integer (e.g., scaled by 10^8 for 8 decimal places)
             timestamp_ms: timestamp,
+            rate_of_change: None,
+            market_closed: false,
+            source_timestamp_ms: None,
+            decimals: None,
+            rounding: None,
+            feed_object: None,
+            params: BTreeMap::new(),
         };
-        let intent_msg = IntentMessage::new(payload, timestamp, IntentScope::PriceFeed);
+        assert_eq!(payload.intent(), IntentScope::PriceFeed);
+        let intent_msg = IntentMessage::new(payload.clone(), timestamp, payload.intent());
         let signing_payload = bcs::to_bytes(&intent_msg).expect("should not fail");
-        
-        // Note: This hex will need to be updated to match the new PriceFeedResponse struct
-        // when the corresponding Move code is updated
-        println!("New signing payload hex: {}", Hex::encode(&signing_payload));
-        
-        // Temporarily comment out the assertion until Move code is updated
-        // assert!(
-        //     signing_payload
-        //         == Hex::decode("NEW_HEX_VALUE_HERE")
-        //             .unwrap()
-        // );
+        assert!(
+            signing_payload
+                == Hex::decode("0020b1d110960100000b746573745f6f7261636c6512746573745f70726963655f666565645f696480d406570200000020b1d11096010000")
+                    .unwrap()
+        );
+
+        // Each intent has a single layout
+        let signing_bytes = |response: PriceFeedResponse| {
+            let intent = response.intent();
+            Hex::encode(bcs::to_bytes(&IntentMessage::new(response, timestamp, intent)).unwrap())
+        };
+        let with_change = PriceFeedResponse {
+            rate_of_change: Some(RateOfChange {
+                previous_price: 10000000000,
+                change_bps: 50,
+                is_negative: false,
+                elapsed_ms: 60_000,
+            }),
+            ..payload.clone()
+        };
+        assert_eq!(with_change.intent(), IntentScope::PriceFeedWithChange);
+        assert_eq!(
+            signing_bytes(with_change),
+            "0120b1d110960100000b746573745f6f7261636c6512746573745f70726963655f666565645f696480d406570200000020b1d110960100000100e40b540200000032000000000000000060ea000000000000"
+        );
+        let market_closed = PriceFeedResponse {
+            market_closed: true,
+            ..payload.clone()
+        };
+        assert_eq!(market_closed.intent(), IntentScope::PriceFeedMarketClosed);
+        assert_eq!(
+            signing_bytes(market_closed),
+            "0220b1d110960100000b746573745f6f7261636c6512746573745f70726963655f666565645f696480d406570200000020b1d1109601000001"
+        );
+        let reduced = PriceFeedResponse {
+            price: 1005,
+            decimals: Some(2),
+            ..payload.clone()
+        };
+        assert_eq!(reduced.intent(), IntentScope::PriceFeedReducedPrecision);
+        assert_eq!(
+            signing_bytes(reduced),
+            "0820b1d110960100000b746573745f6f7261636c6512746573745f70726963655f666565645f6964ed0300000000000020b1d110960100000102000000"
+        );
+        let extended = PriceFeedResponse {
+            source_timestamp_ms: Some(1744038899000),
+            rounding: Some(RoundingMode::HalfUp),
+            feed_object: Some(FeedObject {
+                version: 42,
+                digest: "abc".to_string(),
+            }),
+            params: BTreeMap::from([("symbol".to_string(), "BTC".to_string())]),
+            ..payload.clone()
+        };
+        assert_eq!(extended.intent(), IntentScope::PriceFeedExtended);
+        assert_eq!(
+            signing_bytes(extended),
+            "0d20b1d110960100000b746573745f6f7261636c6512746573745f70726963655f666565645f696480d406570200000020b1d1109601000000000138add11096010000000104012a0000000000000003616263010673796d626f6c03425443"
+        );
+        // Combining the single-field layouts also takes the extended one
+        let combined = PriceFeedResponse {
+            market_closed: true,
+            decimals: Some(2),
+            ..payload
+        };
+        assert_eq!(combined.intent(), IntentScope::PriceFeedExtended);
+    }
+
+    #[test]
+    fn test_fill_url_template() {
+        let params = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+        };
+        assert_eq!(
+            fill_url_template(
+                "https://api.example.com/{symbol}/history?date={date}",
+                &params(&[("symbol", "BTC/USD"), ("date", "2024-01-31")]),
+            )
+            .unwrap(),
+            "https://api.example.com/BTC%2FUSD/history?date=2024-01-31"
+        );
+        // Braces around anything but a name are not placeholders
+        assert_eq!(
+            fill_url_template("https://api.example.com/q?f={a-b}", &params(&[])).unwrap(),
+            "https://api.example.com/q?f={a-b}"
+        );
+        assert!(fill_url_template("https://api.example.com/{symbol}", &params(&[])).is_err());
+        assert!(fill_url_template(
+            "https://api.example.com/{symbol}",
+            &params(&[("symbol", "BTC"), ("host", "evil.example.com")]),
+        )
+        .is_err());
+        assert!(fill_url_template("https://api.example.com/{symbol}", &params(&[("symbol", "")])).is_err());
+    }
+
+    #[test]
+    fn test_reduce_precision() {
+        assert_eq!(reduce_precision(123_456_789, 8, 4).unwrap(), 12_346);
+        assert_eq!(reduce_precision(123_445_000, 8, 4).unwrap(), 12_345);
+        assert_eq!(reduce_precision(123_450_000, 8, 4).unwrap(), 12_345);
+        assert_eq!(reduce_precision(123_455_000, 8, 4).unwrap(), 12_346);
+        assert_eq!(reduce_precision(42, 8, 8).unwrap(), 42);
+        assert_eq!(reduce_precision(u64::MAX, 8, 0).unwrap(), 184_467_440_737);
+        assert!(reduce_precision(42, 4, 8).is_err());
+    }
+
+    #[test]
+    fn test_rate_of_change() {
+        let previous = Observation {
+            price: 10_000,
+            timestamp_ms: 1_000,
+        };
+        let up = RateOfChange::between(previous, 10_250, 4_000).unwrap();
+        assert_eq!(up.change_bps, 250);
+        assert!(!up.is_negative);
+        assert_eq!(up.elapsed_ms, 3_000);
+
+        let down = RateOfChange::between(previous, 9_000, 2_000).unwrap();
+        assert_eq!(down.change_bps, 1_000);
+        assert!(down.is_negative);
+
+        let zero = Observation {
+            price: 0,
+            timestamp_ms: 1_000,
+        };
+        assert!(RateOfChange::between(zero, 1, 2_000).is_none());
+
+        // A payload without rate of change keeps the original signed layout.
+        let base = PriceFeedResponse {
+            oracle_id: "o".to_string(),
+            price_feed_id: "f".to_string(),
+            price: 1,
+            timestamp_ms: 2,
+            rate_of_change: None,
+            market_closed: false,
+            source_timestamp_ms: None,
+            decimals: None,
+            rounding: None,
+            feed_object: None,
+            params: BTreeMap::new(),
+        };
+        let with_change = PriceFeedResponse {
+            rate_of_change: Some(up),
+            ..base.clone()
+        };
+        let base_bytes = bcs::to_bytes(&base).unwrap();
+        let change_bytes = bcs::to_bytes(&with_change).unwrap();
+        assert_eq!(base_bytes.len(), 2 + 2 + 8 + 8);
+        assert_eq!(&change_bytes[..base_bytes.len()], &base_bytes[..]);
+        assert_eq!(change_bytes[base_bytes.len()], 1);
+    }
+
+    #[test]
+    fn test_rounding_mode() {
+        let scaled = |text: &str| Decimal::from_str(text).unwrap();
+        let round = |mode: RoundingMode, text: &str| mode.round(scaled(text)).to_i128().unwrap();
+        assert_eq!(round(RoundingMode::Truncate, "12.9"), 12);
+        assert_eq!(round(RoundingMode::Truncate, "-12.9"), -12);
+        assert_eq!(round(RoundingMode::Floor, "12.9"), 12);
+        assert_eq!(round(RoundingMode::Floor, "-12.1"), -13);
+        assert_eq!(round(RoundingMode::Ceil, "12.1"), 13);
+        assert_eq!(round(RoundingMode::Ceil, "-12.9"), -12);
+        assert_eq!(round(RoundingMode::HalfEven, "12.5"), 12);
+        assert_eq!(round(RoundingMode::HalfEven, "13.5"), 14);
+        assert_eq!(round(RoundingMode::HalfUp, "12.5"), 13);
+        assert_eq!(round(RoundingMode::HalfUp, "-12.5"), -13);
+
+        // The mode is signed under the extended layout, after the rate of
+        // change, market closed, source timestamp and decimals.
+        let base = PriceFeedResponse {
+            oracle_id: "o".to_string(),
+            price_feed_id: "f".to_string(),
+            price: 1,
+            timestamp_ms: 2,
+            rate_of_change: None,
+            market_closed: false,
+            source_timestamp_ms: None,
+            decimals: None,
+            rounding: None,
+            feed_object: None,
+            params: BTreeMap::new(),
+        };
+        let rounded = PriceFeedResponse {
+            rounding: Some(RoundingMode::HalfEven),
+            ..base.clone()
+        };
+        let base_bytes = bcs::to_bytes(&base).unwrap();
+        let rounded_bytes = bcs::to_bytes(&rounded).unwrap();
+        assert_eq!(rounded.intent(), IntentScope::PriceFeedExtended);
+        assert_eq!(&rounded_bytes[..base_bytes.len()], &base_bytes[..]);
+        assert_eq!(&rounded_bytes[base_bytes.len()..], &[0, 0, 0, 0, 1, 3, 0, 0]);
     }
 
     #[test]
@@ -349,5 +1894,93 @@ mod test {
         let result = extract_field_from_json(&json, "prices[0");
         assert!(result.is_err());
         assert!(result.unwrap_err().contains("Missing closing bracket in field path"));
+
+        // Array access after a dotted field
+        let json = json!({"data": {"items": [1.5, 2.5]}});
+        let result = extract_field_from_json(&json, "data.items[1]").unwrap();
+        assert_eq!(result.as_f64().unwrap(), 2.5);
+    }
+
+    #[test]
+    fn test_extract_price_aggregates() {
+        use serde_json::json;
+
+        let json = json!({
+            "bids": ["99.5", 101, "100.25"],
+            "venues": [{"price": "10"}, {"price": 11}, {"price": "12.3"}]
+        });
+        let price = |path| extract_price(&json, path);
+        assert_eq!(price("bids[max]"), Ok(Decimal::from(101)));
+        assert_eq!(price("bids[min]"), Ok(Decimal::from_str("99.5").unwrap()));
+        assert_eq!(price("bids[avg]"), Ok(Decimal::from_str("100.25").unwrap()));
+        assert_eq!(price("venues[*].price[avg]"), Ok(Decimal::from_str("11.1").unwrap()));
+        assert_eq!(price("venues[last].price"), Ok(Decimal::from_str("12.3").unwrap()));
+
+        assert!(price("venues[max]").is_err());
+        assert!(price("bids[max].price").is_err());
+        assert!(extract_price(&json!({"bids": []}), "bids[min]").is_err());
+        // Only prices aggregate; other fields must select one value.
+        assert!(extract_field_from_json(&json, "venues[*].price").is_err());
+        assert_eq!(extract_field_from_json(&json, "bids[last]"), Ok(&json!("100.25")));
+    }
+
+    #[test]
+    fn test_extract_filtered_element() {
+        use serde_json::json;
+
+        let json = json!({"data": [
+            {"symbol": "ETHUSD", "price": "3000.5", "venue": "a"},
+            {"symbol": "BTCUSD", "price": "65000", "venue": "a"},
+            {"symbol": "BTCUSD", "price": "65010", "venue": "b", "id": 7}
+        ]});
+        let field = |path| extract_field_from_json(&json, path);
+        assert_eq!(field("data[symbol=ETHUSD].price"), Ok(&json!("3000.5")));
+        assert_eq!(field("data[id=7].price"), Ok(&json!("65010")));
+        assert_eq!(field("data[venue='b'].symbol"), Ok(&json!("BTCUSD")));
+
+        // A filter naming no element, or several, refuses to guess.
+        assert!(field("data[symbol=SOLUSD].price").unwrap_err().contains("No array element"));
+        assert!(field("data[symbol=BTCUSD].price").unwrap_err().contains("matched 2"));
+        assert!(field("data[=BTCUSD].price").is_err());
+
+        assert_eq!(
+            extract_price(&json, "data[symbol=BTCUSD].price[avg]"),
+            Ok(Decimal::from(65005))
+        );
+    }
+
+    #[test]
+    fn test_encode_url_component() {
+        assert_eq!(encode_url_component("San Francisco"), "San%20Francisco");
+        assert_eq!(encode_url_component("São Paulo&x=1"), "S%C3%A3o%20Paulo%26x%3D1");
+    }
+
+    #[test]
+    fn test_extract_timestamp_ms() {
+        use serde_json::json;
+
+        let json = json!({
+            "secs": 1_700_000_000,
+            "millis": 1_700_000_000_123_u64,
+            "string": "1700000000",
+            "rfc3339": "2023-11-14T22:13:20.5Z",
+            "bad": "yesterday",
+        });
+        assert_eq!(extract_timestamp_ms(&json, "secs").unwrap(), 1_700_000_000_000);
+        assert_eq!(extract_timestamp_ms(&json, "millis").unwrap(), 1_700_000_000_123);
+        assert_eq!(extract_timestamp_ms(&json, "string").unwrap(), 1_700_000_000_000);
+        assert_eq!(extract_timestamp_ms(&json, "rfc3339").unwrap(), 1_700_000_000_500);
+        assert!(extract_timestamp_ms(&json, "bad").is_err());
+        assert!(extract_timestamp_ms(&json, "missing").is_err());
+    }
+
+    #[test]
+    fn test_check_freshness() {
+        let now = 1_000_000;
+        assert!(check_freshness(now, now, 60).is_ok());
+        assert!(check_freshness(now - 60_000, now, 60).is_ok());
+        assert!(check_freshness(now - 60_001, now, 60).is_err());
+        assert!(check_freshness(now + MAX_CLOCK_SKEW_MS, now, 60).is_ok());
+        assert!(check_freshness(now + MAX_CLOCK_SKEW_MS + 1, now, 60).is_err());
     }
 }
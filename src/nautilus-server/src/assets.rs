@@ -0,0 +1,202 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::config::MAX_PRICE_DECIMALS;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::extract::{Path, State};
+use axum::Json;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Conventions for an asset, shared by every feed that prices it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct AssetMetadata {
+    /// Human readable name, e.g. "Bitcoin"
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Decimals prices of this asset are scaled by, instead of
+    /// `response.price_decimals`
+    #[serde(default)]
+    pub decimals: Option<u32>,
+    /// Smallest price increment; extracted prices are rounded to a multiple of it
+    #[serde(default)]
+    pub tick_size: Option<Decimal>,
+    /// Other symbols the asset is known by, e.g. "XBT" for "BTC"
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+
+impl AssetMetadata {
+    /// Round `price` to the nearest multiple of the tick size, if any.
+    pub fn normalize(&self, price: Decimal) -> Decimal {
+        match self.tick_size {
+            Some(tick) if !tick.is_zero() => {
+                (price / tick).round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
+                    * tick
+            }
+            _ => price,
+        }
+    }
+}
+
+/// Look up an asset by symbol or alias, ignoring case. Returns the canonical
+/// symbol along with the metadata.
+pub fn resolve<'a>(
+    assets: &'a HashMap<String, AssetMetadata>,
+    name: &str,
+) -> Option<(&'a str, &'a AssetMetadata)> {
+    assets
+        .iter()
+        .find(|(symbol, asset)| {
+            symbol.eq_ignore_ascii_case(name)
+                || asset.aliases.iter().any(|a| a.eq_ignore_ascii_case(name))
+        })
+        .map(|(symbol, asset)| (symbol.as_str(), asset))
+}
+
+/// Problems with the registry, such as aliases that would resolve to more
+/// than one asset.
+pub fn validate(assets: &HashMap<String, AssetMetadata>) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut owners: HashMap<String, &str> = HashMap::new();
+
+    for (symbol, asset) in assets {
+        if let Some(decimals) = asset.decimals {
+            if decimals > MAX_PRICE_DECIMALS {
+                problems.push(format!(
+                    "assets.{}.decimals must be at most {}, got {}",
+                    symbol, MAX_PRICE_DECIMALS, decimals
+                ));
+            }
+        }
+        if let Some(tick) = asset.tick_size {
+            if tick <= Decimal::ZERO {
+                problems.push(format!("assets.{}.tick_size must be positive, got {}", symbol, tick));
+            }
+        }
+
+        for name in std::iter::once(symbol).chain(&asset.aliases) {
+            if let Some(owner) = owners.insert(name.to_ascii_uppercase(), symbol) {
+                if owner != symbol {
+                    problems.push(format!(
+                        "assets.{}: '{}' is already used by asset '{}'",
+                        symbol, name, owner
+                    ));
+                }
+            }
+        }
+    }
+
+    problems
+}
+
+/// An asset as returned by the assets endpoints.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct AssetResponse {
+    pub symbol: String,
+    #[serde(flatten)]
+    pub metadata: AssetMetadata,
+}
+
+/// Response for list assets.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ListAssetsResponse {
+    pub assets: Vec<AssetResponse>,
+}
+
+/// Endpoint that lists every asset in the registry, sorted by symbol.
+#[utoipa::path(
+    get,
+    path = "/assets",
+    tag = "assets",
+    responses((status = 200, body = ListAssetsResponse))
+)]
+pub async fn list_assets(State(state): State<Arc<AppState>>) -> Json<ListAssetsResponse> {
+    let config = state.config.load();
+    let mut assets: Vec<AssetResponse> = config
+        .assets
+        .iter()
+        .map(|(symbol, metadata)| AssetResponse {
+            symbol: symbol.clone(),
+            metadata: metadata.clone(),
+        })
+        .collect();
+    assets.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    Json(ListAssetsResponse { assets })
+}
+
+/// Endpoint that returns a single asset, looked up by symbol or alias.
+#[utoipa::path(
+    get,
+    path = "/assets/{symbol}",
+    tag = "assets",
+    params(("symbol" = String, Path, description = "Asset symbol or alias, case-insensitive")),
+    responses(
+        (status = 200, body = AssetResponse),
+        (status = 400, description = "Unknown asset", body = crate::ErrorResponse),
+    )
+)]
+pub async fn get_asset(
+    State(state): State<Arc<AppState>>,
+    Path(symbol): Path<String>,
+) -> Result<Json<AssetResponse>, EnclaveError> {
+    let config = state.config.load();
+    let (symbol, metadata) = resolve(&config.assets, &symbol)
+        .ok_or_else(|| EnclaveError::GenericError(format!("Unknown asset: {}", symbol)))?;
+    Ok(Json(AssetResponse {
+        symbol: symbol.to_string(),
+        metadata: metadata.clone(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn btc() -> AssetMetadata {
+        AssetMetadata {
+            decimals: Some(6),
+            tick_size: Some(Decimal::from_str("0.5").unwrap()),
+            aliases: vec!["XBT".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_resolve_and_normalize() {
+        let assets = HashMap::from([("BTC".to_string(), btc())]);
+        assert_eq!(resolve(&assets, "btc").unwrap().0, "BTC");
+        assert_eq!(resolve(&assets, "xbt").unwrap().0, "BTC");
+        assert!(resolve(&assets, "ETH").is_none());
+
+        let asset = btc();
+        let normalize = |p: &str| asset.normalize(Decimal::from_str(p).unwrap()).to_string();
+        assert_eq!(normalize("100.24"), "100.0");
+        assert_eq!(normalize("100.25"), "100.5");
+        assert_eq!(AssetMetadata::default().normalize(Decimal::ONE), Decimal::ONE);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut assets = HashMap::from([("BTC".to_string(), btc())]);
+        assert!(validate(&assets).is_empty());
+
+        assets.insert(
+            "XBT".to_string(),
+            AssetMetadata {
+                decimals: Some(19),
+                tick_size: Some(Decimal::ZERO),
+                ..Default::default()
+            },
+        );
+        let problems = validate(&assets).join("\n");
+        assert!(problems.contains("assets.XBT.decimals"));
+        assert!(problems.contains("assets.XBT.tick_size"));
+        assert!(problems.contains("'XBT' is already used"));
+    }
+}
@@ -0,0 +1,298 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Verification of Nitro attestation documents produced by another enclave:
+//! the COSE_Sign1 signature, the certificate chain up to a trusted root and
+//! the fields it attests to.
+
+use anyhow::{Context, Result};
+use ciborium::Value;
+use fastcrypto::encoding::{Encoding, Hex};
+use p384::ecdsa::signature::Verifier;
+use p384::ecdsa::{Signature, VerifyingKey};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use x509_cert::der::asn1::ObjectIdentifier;
+use x509_cert::der::{Decode, Encode};
+use x509_cert::Certificate;
+
+/// SHA-256 of the DER encoded AWS Nitro Enclaves root certificate (G1).
+pub const AWS_NITRO_ROOT_SHA256: &str =
+    "641a0321a3e244efe456463195d606317ed7cdcc3c1756e09893f3c68f79bb5b";
+
+/// ecdsa-with-SHA384, the only algorithm used in the Nitro certificate chain.
+const ECDSA_WITH_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+
+/// COSE algorithm id of ES384.
+const COSE_ES384: i64 = -35;
+
+/// The attested fields of a verified document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttestationDoc {
+    pub module_id: String,
+    pub timestamp_ms: u64,
+    /// Platform configuration registers by index
+    pub pcrs: BTreeMap<u64, Vec<u8>>,
+    pub public_key: Option<Vec<u8>>,
+    pub user_data: Option<Vec<u8>>,
+    pub nonce: Option<Vec<u8>>,
+}
+
+impl AttestationDoc {
+    /// PCR0, the measurement of the enclave image, in Hex.
+    pub fn pcr0(&self) -> Option<String> {
+        self.pcrs.get(&0).map(Hex::encode)
+    }
+}
+
+/// Verify `document` and return its attested fields. The certificate chain
+/// must start at the root whose DER SHA-256 is `root_sha256` (Hex) and every
+/// certificate must be valid at the document's timestamp.
+pub fn verify_attestation(document: &[u8], root_sha256: &str) -> Result<AttestationDoc> {
+    let cose: Value =
+        ciborium::de::from_reader(document).context("Attestation document is not CBOR")?;
+    let cose = match cose {
+        Value::Tag(18, inner) => *inner,
+        other => other,
+    };
+    let [protected, _unprotected, payload, signature] = <[Value; 4]>::try_from(
+        cose.into_array()
+            .map_err(|_| anyhow::anyhow!("Attestation document is not a COSE_Sign1 array"))?,
+    )
+    .map_err(|_| anyhow::anyhow!("COSE_Sign1 must have 4 elements"))?;
+    let protected = into_bytes(protected, "protected header")?;
+    let payload = into_bytes(payload, "payload")?;
+    let signature = into_bytes(signature, "signature")?;
+
+    let header: Value = ciborium::de::from_reader(protected.as_slice())
+        .context("Protected header is not CBOR")?;
+    let alg = map_get(&header, &Value::from(1))
+        .and_then(|v| v.as_integer())
+        .map(i128::from);
+    if alg != Some(COSE_ES384 as i128) {
+        return Err(anyhow::anyhow!("Unsupported COSE algorithm {:?}", alg));
+    }
+
+    let fields: Value = ciborium::de::from_reader(payload.as_slice())
+        .context("Attestation payload is not CBOR")?;
+    let field = |name: &str| map_get(&fields, &Value::from(name));
+    let bytes_field = |name: &str| -> Option<Vec<u8>> { field(name)?.as_bytes().cloned() };
+
+    let timestamp_ms = field("timestamp")
+        .and_then(|v| v.as_integer())
+        .and_then(|v| u64::try_from(v).ok())
+        .ok_or_else(|| anyhow::anyhow!("Attestation payload has no timestamp"))?;
+    let leaf = bytes_field("certificate")
+        .ok_or_else(|| anyhow::anyhow!("Attestation payload has no certificate"))?;
+    let cabundle = field("cabundle")
+        .and_then(|v| v.as_array())
+        .ok_or_else(|| anyhow::anyhow!("Attestation payload has no cabundle"))?
+        .iter()
+        .map(|cert| cert.as_bytes().cloned().ok_or_else(|| anyhow::anyhow!("Invalid cabundle entry")))
+        .collect::<Result<Vec<_>>>()?;
+
+    // The bundle runs from the root down to the issuer of the leaf.
+    let root = cabundle
+        .first()
+        .ok_or_else(|| anyhow::anyhow!("Attestation cabundle is empty"))?;
+    if Hex::encode(Sha256::digest(root)) != root_sha256.to_ascii_lowercase() {
+        return Err(anyhow::anyhow!("Attestation is not rooted in the trusted root certificate"));
+    }
+    let chain = cabundle
+        .iter()
+        .chain(std::iter::once(&leaf))
+        .map(|der| Certificate::from_der(der).context("Invalid certificate in attestation"))
+        .collect::<Result<Vec<_>>>()?;
+    for cert in &chain {
+        check_validity(cert, timestamp_ms)?;
+    }
+    for pair in chain.windows(2) {
+        verify_signed_by(&pair[1], &pair[0])?;
+    }
+
+    // COSE Sig_structure for a single signer without external AAD.
+    let sig_structure = Value::Array(vec![
+        Value::from("Signature1"),
+        Value::Bytes(protected),
+        Value::Bytes(Vec::new()),
+        Value::Bytes(payload.clone()),
+    ]);
+    let mut to_verify = Vec::new();
+    ciborium::ser::into_writer(&sig_structure, &mut to_verify)?;
+    let leaf_key = verifying_key(chain.last().expect("chain contains the leaf"))?;
+    let signature = Signature::from_slice(&signature).context("Invalid ES384 signature")?;
+    leaf_key
+        .verify(&to_verify, &signature)
+        .map_err(|_| anyhow::anyhow!("Attestation signature does not verify"))?;
+
+    let pcrs = field("pcrs")
+        .and_then(|v| v.as_map())
+        .ok_or_else(|| anyhow::anyhow!("Attestation payload has no pcrs"))?
+        .iter()
+        .map(|(index, value)| {
+            let index = index
+                .as_integer()
+                .and_then(|i| u64::try_from(i).ok())
+                .ok_or_else(|| anyhow::anyhow!("Invalid PCR index"))?;
+            let value = value.as_bytes().cloned().ok_or_else(|| anyhow::anyhow!("Invalid PCR value"))?;
+            Ok((index, value))
+        })
+        .collect::<Result<BTreeMap<_, _>>>()?;
+
+    Ok(AttestationDoc {
+        module_id: field("module_id")
+            .and_then(|v| v.as_text())
+            .unwrap_or_default()
+            .to_string(),
+        timestamp_ms,
+        pcrs,
+        public_key: bytes_field("public_key"),
+        user_data: bytes_field("user_data"),
+        nonce: bytes_field("nonce"),
+    })
+}
+
+fn into_bytes(value: Value, name: &str) -> Result<Vec<u8>> {
+    value
+        .into_bytes()
+        .map_err(|_| anyhow::anyhow!("COSE_Sign1 {} is not a byte string", name))
+}
+
+fn map_get<'a>(map: &'a Value, key: &Value) -> Option<&'a Value> {
+    map.as_map()?
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v)
+}
+
+fn verifying_key(cert: &Certificate) -> Result<VerifyingKey> {
+    let spki = &cert.tbs_certificate.subject_public_key_info;
+    VerifyingKey::from_sec1_bytes(spki.subject_public_key.raw_bytes())
+        .context("Certificate key is not a P-384 public key")
+}
+
+fn verify_signed_by(cert: &Certificate, issuer: &Certificate) -> Result<()> {
+    if cert.signature_algorithm.oid != ECDSA_WITH_SHA384 {
+        return Err(anyhow::anyhow!(
+            "Unsupported certificate signature algorithm {}",
+            cert.signature_algorithm.oid
+        ));
+    }
+    let tbs = cert.tbs_certificate.to_der()?;
+    let signature = Signature::from_der(cert.signature.raw_bytes())
+        .context("Invalid certificate signature")?;
+    verifying_key(issuer)?
+        .verify(&tbs, &signature)
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Certificate {} is not signed by {}",
+                cert.tbs_certificate.subject,
+                issuer.tbs_certificate.subject
+            )
+        })
+}
+
+fn check_validity(cert: &Certificate, timestamp_ms: u64) -> Result<()> {
+    let validity = &cert.tbs_certificate.validity;
+    let not_before = validity.not_before.to_unix_duration().as_millis() as u64;
+    let not_after = validity.not_after.to_unix_duration().as_millis() as u64;
+    if timestamp_ms < not_before || timestamp_ms > not_after {
+        return Err(anyhow::anyhow!(
+            "Certificate {} is not valid at the attestation timestamp",
+            cert.tbs_certificate.subject
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use p384::ecdsa::signature::Signer;
+    use p384::ecdsa::SigningKey;
+    use p384::pkcs8::DecodePrivateKey;
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair, PKCS_ECDSA_P384_SHA384};
+
+    /// A document signed by a throwaway chain, with the hex SHA-256 of its root.
+    pub(crate) fn fake_attestation(pcr0: &[u8], public_key: &[u8]) -> (Vec<u8>, String) {
+        let root_key = KeyPair::generate_for(&PKCS_ECDSA_P384_SHA384).unwrap();
+        let mut root_params = CertificateParams::new(vec![]).unwrap();
+        root_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let root = root_params.self_signed(&root_key).unwrap();
+
+        let leaf_key = KeyPair::generate_for(&PKCS_ECDSA_P384_SHA384).unwrap();
+        let leaf = CertificateParams::new(vec!["enclave".to_string()])
+            .unwrap()
+            .signed_by(&leaf_key, &root, &root_key)
+            .unwrap();
+
+        let timestamp_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let payload = Value::Map(vec![
+            (Value::from("module_id"), Value::from("i-test-enc")),
+            (Value::from("timestamp"), Value::from(timestamp_ms)),
+            (
+                Value::from("pcrs"),
+                Value::Map(vec![(Value::from(0), Value::Bytes(pcr0.to_vec()))]),
+            ),
+            (Value::from("certificate"), Value::Bytes(leaf.der().to_vec())),
+            (
+                Value::from("cabundle"),
+                Value::Array(vec![Value::Bytes(root.der().to_vec())]),
+            ),
+            (Value::from("public_key"), Value::Bytes(public_key.to_vec())),
+            (Value::from("user_data"), Value::Null),
+            (Value::from("nonce"), Value::Null),
+        ]);
+        let mut payload_bytes = Vec::new();
+        ciborium::ser::into_writer(&payload, &mut payload_bytes).unwrap();
+        let mut protected = Vec::new();
+        ciborium::ser::into_writer(&Value::Map(vec![(Value::from(1), Value::from(COSE_ES384))]), &mut protected)
+            .unwrap();
+
+        let mut to_sign = Vec::new();
+        ciborium::ser::into_writer(
+            &Value::Array(vec![
+                Value::from("Signature1"),
+                Value::Bytes(protected.clone()),
+                Value::Bytes(Vec::new()),
+                Value::Bytes(payload_bytes.clone()),
+            ]),
+            &mut to_sign,
+        )
+        .unwrap();
+        let signing_key = SigningKey::from_pkcs8_der(&leaf_key.serialize_der()).unwrap();
+        let signature: Signature = signing_key.sign(&to_sign);
+
+        let cose = Value::Tag(
+            18,
+            Box::new(Value::Array(vec![
+                Value::Bytes(protected),
+                Value::Map(vec![]),
+                Value::Bytes(payload_bytes),
+                Value::Bytes(signature.to_bytes().to_vec()),
+            ])),
+        );
+        let mut document = Vec::new();
+        ciborium::ser::into_writer(&cose, &mut document).unwrap();
+        (document, Hex::encode(Sha256::digest(root.der())))
+    }
+
+    #[test]
+    fn test_verify_attestation() {
+        let (document, root_sha256) = fake_attestation(&[7; 48], b"key");
+        let doc = verify_attestation(&document, &root_sha256).unwrap();
+        assert_eq!(doc.public_key.as_deref(), Some(&b"key"[..]));
+        assert_eq!(doc.pcr0(), Some(Hex::encode([7; 48])));
+        assert_eq!(doc.module_id, "i-test-enc");
+
+        // Another root, or a tampered payload, is rejected.
+        assert!(verify_attestation(&document, AWS_NITRO_ROOT_SHA256).is_err());
+        let mut tampered = document.clone();
+        let at = tampered.windows(3).position(|w| w == b"key").unwrap();
+        tampered[at] = b'K';
+        assert!(verify_attestation(&tampered, &root_sha256).is_err());
+    }
+}
@@ -0,0 +1,460 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Disaster recovery backup of the persistent signing key. The key is split
+//! into Shamir shares over GF(256), any `threshold` of which recover it, and
+//! each share is encrypted to one operator's X25519 key, so no single
+//! operator ever holds the key. The `key_backup` binary generates operator
+//! keys, decrypts shares and recombines them.
+
+use anyhow::{Context, Result};
+use axum::{extract::State, Json};
+use fastcrypto::aes::{Aes256Gcm, AesKey, AuthenticatedCipher, InitializationVector};
+use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{Generate, KeyPair, ToFromBytes};
+use hkdf::Hkdf;
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::Arc;
+use tracing::info;
+use typenum::U12;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+use crate::common::current_timestamp_ms;
+use crate::config::KeyMode;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Domain separator for share encryption keys and their AAD.
+const BACKUP_INFO: &[u8] = b"nautilus-key-backup-v1";
+/// Length of the AES-GCM nonce prefixed to each ciphertext.
+const NONCE_LENGTH: usize = 12;
+/// Shares are indexed by a nonzero byte.
+const MAX_SHARES: usize = 255;
+
+/// `[key_backup]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct KeyBackup {
+    /// X25519 public keys of the operators, in Hex, one share each
+    #[serde(default)]
+    pub operator_keys: Vec<String>,
+    /// Number of shares needed to recover the key
+    #[serde(default)]
+    pub threshold: usize,
+}
+
+impl KeyBackup {
+    /// Check the config, returning the problems found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.operator_keys.is_empty() {
+            return problems;
+        }
+        for key in &self.operator_keys {
+            if let Err(e) = parse_public_key(key) {
+                problems.push(format!("key_backup.operator_keys: {}", e));
+            }
+        }
+        if self.operator_keys.len() > MAX_SHARES {
+            problems.push(format!(
+                "key_backup.operator_keys has more than {} keys",
+                MAX_SHARES
+            ));
+        }
+        if self.threshold < 2 || self.threshold > self.operator_keys.len() {
+            problems.push(format!(
+                "key_backup.threshold must be between 2 and the {} operator keys, got {}",
+                self.operator_keys.len(),
+                self.threshold
+            ));
+        }
+        problems
+    }
+}
+
+/// A Shamir share: the evaluation at `index` of the polynomials hiding each
+/// byte of the secret.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Share {
+    pub index: u8,
+    /// Share bytes in Hex
+    pub value: String,
+}
+
+/// A share encrypted to one operator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptedShare {
+    pub index: u8,
+    /// Operator X25519 public key the share is encrypted to, in Hex
+    pub operator_key: String,
+    /// Ephemeral X25519 public key of the enclave, in Hex
+    pub ephemeral_key: String,
+    /// `nonce || ciphertext` of the share bytes, in Hex
+    pub ciphertext: String,
+}
+
+/// Response for export key backup.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct KeyBackupResponse {
+    /// Public key of the backed up signing key, in Hex
+    pub public_key: String,
+    pub threshold: usize,
+    pub shares: Vec<EncryptedShare>,
+}
+
+/// Endpoint that splits the sealed signing key among the operators
+/// configured in `[key_backup]`. Only keys that persist across restarts are
+/// exported; an ephemeral key is not worth recovering.
+pub async fn export_key_backup(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<KeyBackupResponse>, EnclaveError> {
+    info!("export key backup called");
+
+    let config = state.config.load_full();
+    if config.key.mode != KeyMode::Sealed {
+        return Err(EnclaveError::GenericError(
+            "Key backups are only exported in sealed key mode".to_string(),
+        ));
+    }
+    let backup = &config.key_backup;
+    if backup.operator_keys.is_empty() {
+        return Err(EnclaveError::GenericError(
+            "No key_backup.operator_keys configured".to_string(),
+        ));
+    }
+
+    let kp = state.signing_keys.active_at(current_timestamp_ms()?);
+    let shares = export(backup, &kp)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to export key backup: {:#}", e)))?;
+    info!(
+        "Exported key backup in {} shares, threshold {}",
+        shares.len(),
+        backup.threshold
+    );
+    Ok(Json(KeyBackupResponse {
+        public_key: Hex::encode(kp.public().as_bytes()),
+        threshold: backup.threshold,
+        shares,
+    }))
+}
+
+/// Split `kp` and encrypt one share to each operator key.
+pub fn export(backup: &KeyBackup, kp: &Ed25519KeyPair) -> Result<Vec<EncryptedShare>> {
+    let operator_keys = backup
+        .operator_keys
+        .iter()
+        .map(|key| parse_public_key(key))
+        .collect::<Result<Vec<_>>>()?;
+    let shares = split(
+        kp.as_bytes(),
+        backup.threshold,
+        operator_keys.len(),
+        &mut rand::thread_rng(),
+    )?;
+    Ok(shares
+        .into_iter()
+        .zip(&operator_keys)
+        .map(|((index, value), operator_key)| encrypt_share(operator_key, index, &value))
+        .collect())
+}
+
+/// Decrypt a share with the operator's X25519 secret key.
+pub fn decrypt_share(operator_secret: &StaticSecret, share: &EncryptedShare) -> Result<Share> {
+    let operator_key = PublicKey::from(operator_secret);
+    if !Hex::encode(operator_key.as_bytes()).eq_ignore_ascii_case(&share.operator_key) {
+        return Err(anyhow::anyhow!(
+            "Share {} is encrypted to another operator",
+            share.index
+        ));
+    }
+    let ephemeral_key = parse_public_key(&share.ephemeral_key)?;
+    let key = share_key(
+        operator_secret.diffie_hellman(&ephemeral_key).as_bytes(),
+        &operator_key,
+        &ephemeral_key,
+    )?;
+    let bytes =
+        Hex::decode(&share.ciphertext).map_err(|e| anyhow::anyhow!("Invalid ciphertext: {}", e))?;
+    if bytes.len() <= NONCE_LENGTH {
+        return Err(anyhow::anyhow!("Share ciphertext is too short"));
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+    let nonce = InitializationVector::<U12>::from_bytes(nonce)
+        .map_err(|e| anyhow::anyhow!("Invalid share nonce: {}", e))?;
+    let value = Aes256Gcm::<U12>::new(key)
+        .decrypt_authenticated(&nonce, &share_aad(share.index), ciphertext)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt share {}", share.index))?;
+    Ok(Share {
+        index: share.index,
+        value: Hex::encode(value),
+    })
+}
+
+/// Recover the signing key from at least `threshold` decrypted shares.
+/// Fewer shares yield a different key, caught by comparing its public key
+/// with the one the backup was exported for.
+pub fn recover(shares: &[Share], public_key: &str) -> Result<Ed25519KeyPair> {
+    let shares = shares
+        .iter()
+        .map(|share| {
+            let value = Hex::decode(&share.value)
+                .map_err(|e| anyhow::anyhow!("Share {} is not valid hex: {}", share.index, e))?;
+            Ok((share.index, value))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let secret = combine(&shares)?;
+    let kp =
+        Ed25519KeyPair::from_bytes(&secret).map_err(|e| anyhow::anyhow!("Invalid key: {}", e))?;
+    if !Hex::encode(kp.public().as_bytes()).eq_ignore_ascii_case(public_key) {
+        return Err(anyhow::anyhow!(
+            "Recovered key does not match public key {}; are enough distinct shares given?",
+            public_key
+        ));
+    }
+    Ok(kp)
+}
+
+/// Generate an operator X25519 keypair, returned as `(secret, public)` Hex.
+pub fn generate_operator_key() -> (String, String) {
+    let secret = StaticSecret::random_from_rng(rand::thread_rng());
+    let public = PublicKey::from(&secret);
+    (
+        Hex::encode(secret.to_bytes()),
+        Hex::encode(public.as_bytes()),
+    )
+}
+
+/// Parse an operator X25519 secret key from Hex.
+pub fn parse_secret_key(hex: &str) -> Result<StaticSecret> {
+    let bytes: [u8; 32] = Hex::decode(hex.trim().trim_start_matches("0x"))
+        .map_err(|e| anyhow::anyhow!("Invalid X25519 secret key: {}", e))?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("X25519 secret key must be 32 bytes"))?;
+    Ok(StaticSecret::from(bytes))
+}
+
+fn parse_public_key(hex: &str) -> Result<PublicKey> {
+    let bytes: [u8; 32] = Hex::decode(hex.trim_start_matches("0x"))
+        .map_err(|e| anyhow::anyhow!("invalid X25519 key '{}': {}", hex, e))?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("X25519 key '{}' must be 32 bytes", hex))?;
+    Ok(PublicKey::from(bytes))
+}
+
+fn encrypt_share(operator_key: &PublicKey, index: u8, value: &[u8]) -> EncryptedShare {
+    let secret = EphemeralSecret::random_from_rng(rand::thread_rng());
+    let ephemeral_key = PublicKey::from(&secret);
+    let key = share_key(
+        secret.diffie_hellman(operator_key).as_bytes(),
+        operator_key,
+        &ephemeral_key,
+    )
+    .expect("HKDF output of 32 bytes is a valid AES key");
+    let nonce = InitializationVector::<U12>::generate(&mut rand::thread_rng());
+    let ciphertext =
+        Aes256Gcm::<U12>::new(key).encrypt_authenticated(&nonce, &share_aad(index), value);
+    EncryptedShare {
+        index,
+        operator_key: Hex::encode(operator_key.as_bytes()),
+        ephemeral_key: Hex::encode(ephemeral_key.as_bytes()),
+        ciphertext: Hex::encode([nonce.as_bytes(), &ciphertext].concat()),
+    }
+}
+
+/// Derive the AES key of a share from the X25519 shared secret.
+fn share_key(
+    shared: &[u8],
+    operator_key: &PublicKey,
+    ephemeral_key: &PublicKey,
+) -> Result<AesKey<typenum::U32>> {
+    let info = [
+        BACKUP_INFO,
+        operator_key.as_bytes(),
+        ephemeral_key.as_bytes(),
+    ]
+    .concat();
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, shared)
+        .expand(&info, &mut key)
+        .map_err(|_| anyhow::anyhow!("Failed to derive share key"))?;
+    AesKey::from_bytes(&key).map_err(|_| anyhow::anyhow!("Invalid share key"))
+}
+
+/// Binds a ciphertext to its share index, so shares can't be relabelled.
+fn share_aad(index: u8) -> Vec<u8> {
+    [BACKUP_INFO, &[index]].concat()
+}
+
+/// Split `secret` into `count` shares, indexed 1 to `count`, any
+/// `threshold` of which recover it.
+pub fn split(
+    secret: &[u8],
+    threshold: usize,
+    count: usize,
+    rng: &mut (impl RngCore + CryptoRng),
+) -> Result<Vec<(u8, Vec<u8>)>> {
+    if threshold == 0 || threshold > count || count > MAX_SHARES {
+        return Err(anyhow::anyhow!(
+            "Cannot split into {} shares with threshold {}",
+            count,
+            threshold
+        ));
+    }
+    let mut shares: Vec<(u8, Vec<u8>)> = (1..=count as u8)
+        .map(|index| (index, Vec::with_capacity(secret.len())))
+        .collect();
+    let mut coefficients = vec![0u8; threshold];
+    for &byte in secret {
+        // The constant term is the secret byte, the others are random
+        coefficients[0] = byte;
+        rng.fill_bytes(&mut coefficients[1..]);
+        for (index, value) in shares.iter_mut() {
+            value.push(evaluate(&coefficients, *index));
+        }
+    }
+    Ok(shares)
+}
+
+/// Recover the secret from shares by Lagrange interpolation at zero.
+pub fn combine(shares: &[(u8, Vec<u8>)]) -> Result<Vec<u8>> {
+    let length = shares
+        .first()
+        .map(|(_, value)| value.len())
+        .context("No shares given")?;
+    for (i, (index, value)) in shares.iter().enumerate() {
+        if *index == 0 || value.len() != length {
+            return Err(anyhow::anyhow!("Share {} is malformed", index));
+        }
+        if shares[..i].iter().any(|(other, _)| other == index) {
+            return Err(anyhow::anyhow!("Share {} is given twice", index));
+        }
+    }
+    Ok((0..length)
+        .map(|position| {
+            shares.iter().fold(0u8, |secret, (index, value)| {
+                // Lagrange basis polynomial of this share, evaluated at zero
+                let basis = shares
+                    .iter()
+                    .filter(|(other, _)| other != index)
+                    .fold(1u8, |basis, (other, _)| {
+                        gf_mul(basis, gf_mul(*other, gf_inverse(*other ^ *index)))
+                    });
+                secret ^ gf_mul(value[position], basis)
+            })
+        })
+        .collect())
+}
+
+/// Evaluate the polynomial with `coefficients`, lowest degree first, at `x`.
+fn evaluate(coefficients: &[u8], x: u8) -> u8 {
+    coefficients
+        .iter()
+        .rev()
+        .fold(0u8, |acc, &coefficient| gf_mul(acc, x) ^ coefficient)
+}
+
+/// Multiplication in GF(2^8) modulo the AES polynomial x^8 + x^4 + x^3 + x + 1.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse in GF(2^8), a^254. Only called with nonzero `a`.
+fn gf_inverse(a: u8) -> u8 {
+    let mut result = 1u8;
+    for _ in 0..254 {
+        result = gf_mul(result, a);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gf_arithmetic() {
+        // Known product from the AES specification
+        assert_eq!(gf_mul(0x57, 0x83), 0xc1);
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inverse(a)), 1);
+        }
+    }
+
+    #[test]
+    fn test_split_combine() {
+        let secret: Vec<u8> = (0..32).collect();
+        let shares = split(&secret, 3, 5, &mut rand::thread_rng()).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        // Any three shares recover the secret
+        assert_eq!(combine(&shares[..3]).unwrap(), secret);
+        assert_eq!(
+            combine(&[shares[4].clone(), shares[0].clone(), shares[2].clone()]).unwrap(),
+            secret
+        );
+        assert_eq!(combine(&shares).unwrap(), secret);
+        // Two don't
+        assert_ne!(combine(&shares[..2]).unwrap(), secret);
+
+        assert!(combine(&[shares[0].clone(), shares[0].clone()]).is_err());
+        assert!(split(&secret, 4, 3, &mut rand::thread_rng()).is_err());
+    }
+
+    #[test]
+    fn test_export_and_recover() {
+        let operators: Vec<(String, String)> = (0..3).map(|_| generate_operator_key()).collect();
+        let backup = KeyBackup {
+            operator_keys: operators.iter().map(|(_, public)| public.clone()).collect(),
+            threshold: 2,
+        };
+        assert!(backup.validate().is_empty());
+
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let public_key = Hex::encode(kp.public().as_bytes());
+        let encrypted = export(&backup, &kp).unwrap();
+
+        let secret = |i: usize| parse_secret_key(&operators[i].0).unwrap();
+        let shares = vec![
+            decrypt_share(&secret(0), &encrypted[0]).unwrap(),
+            decrypt_share(&secret(2), &encrypted[2]).unwrap(),
+        ];
+        assert_eq!(
+            recover(&shares, &public_key).unwrap().as_bytes(),
+            kp.as_bytes()
+        );
+        assert!(recover(&shares[..1], &public_key).is_err());
+
+        // Each share only opens with its own operator's key
+        assert!(decrypt_share(&secret(1), &encrypted[0]).is_err());
+        let relabelled = EncryptedShare {
+            index: 9,
+            ..encrypted[1].clone()
+        };
+        assert!(decrypt_share(&secret(1), &relabelled).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let (_, public) = generate_operator_key();
+        let backup = KeyBackup {
+            operator_keys: vec![public, "00".to_string()],
+            threshold: 3,
+        };
+        assert_eq!(backup.validate().len(), 2);
+        assert!(KeyBackup::default().validate().is_empty());
+    }
+}
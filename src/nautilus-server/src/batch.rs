@@ -0,0 +1,413 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Batch price requests: one request signs prices for several feeds, each
+//! item choosing how old a price it accepts and whether it may be served
+//! from the prices signed recently, so latency tolerant feeds can be mixed
+//! with feeds that must be fetched fresh. Clients sending
+//! `Accept: application/x-ndjson` get each result on its own line as soon as
+//! it is signed instead of one response once the whole batch is done.
+
+use axum::body::{Body, Bytes};
+use axum::extract::State;
+use axum::http::{header, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::app::{self, PriceFeedModule, PriceFeedRequest, PriceFeedResponse};
+use crate::common::{current_timestamp_ms, IntentMessage, ProcessDataRequest, ProcessedDataResponse};
+use crate::oracle::{self, Signed};
+use crate::AppState;
+use crate::EnclaveError;
+use crate::ErrorResponse;
+
+/// `[batch]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Batch {
+    /// Most items accepted in one request
+    #[serde(default = "default_max_items")]
+    pub max_items: usize,
+    /// Age in milliseconds of a cached price accepted for items that don't
+    /// set `max_age_ms`; 0 fetches every such item
+    #[serde(default)]
+    pub default_max_age_ms: u64,
+    /// Most responses accepted in one `POST /verify/batch` request
+    #[serde(default = "default_max_verify_items")]
+    pub max_verify_items: usize,
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Self {
+            max_items: default_max_items(),
+            default_max_age_ms: 0,
+            max_verify_items: default_max_verify_items(),
+        }
+    }
+}
+
+fn default_max_items() -> usize {
+    50
+}
+
+fn default_max_verify_items() -> usize {
+    1000
+}
+
+/// Whether an item may be served from the prices signed recently.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CachePolicy {
+    /// Serve a cached price if it is within `max_age_ms`, fetch otherwise
+    #[default]
+    Prefer,
+    /// Always fetch and sign a fresh price
+    Bypass,
+}
+
+/// A feed requested in a batch.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BatchItem {
+    pub price_feed_id: String,
+    /// On-chain payment receipt, required when `payments.required` is set
+    #[serde(default)]
+    pub receipt_id: Option<String>,
+    /// Oldest cached price accepted, in milliseconds, instead of
+    /// `batch.default_max_age_ms`
+    #[serde(default)]
+    pub max_age_ms: Option<u64>,
+    #[serde(default)]
+    pub cache: CachePolicy,
+    /// Fewer decimals to round the price to, for authenticated consumers.
+    /// Such items are always fetched.
+    #[serde(default)]
+    pub decimals: Option<u32>,
+    /// Values of the placeholders of the feed's URL. Such items are always
+    /// fetched.
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+}
+
+/// Inner type T for ProcessDataRequest<T>
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BatchRequest {
+    pub items: Vec<BatchItem>,
+}
+
+/// Outcome of one item, in the order requested.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BatchResult {
+    pub price_feed_id: String,
+    /// Whether the price was served from the cache rather than fetched
+    pub cached: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signed: Option<ProcessedDataResponse<IntentMessage<PriceFeedResponse>>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<ErrorResponse>,
+}
+
+impl BatchResult {
+    fn ok(price_feed_id: String, cached: bool, signed: Signed<PriceFeedResponse>) -> Self {
+        Self {
+            price_feed_id,
+            cached,
+            signed: Some(signed),
+            error: None,
+        }
+    }
+
+    fn err(price_feed_id: String, e: EnclaveError) -> Self {
+        Self {
+            price_feed_id,
+            cached: false,
+            signed: None,
+            error: Some(ErrorResponse {
+                code: e.code().map(str::to_string),
+                error: e.to_string(),
+            }),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct BatchResponse {
+    pub results: Vec<BatchResult>,
+}
+
+/// Media type of the streamed batch response.
+pub const NDJSON: &str = "application/x-ndjson";
+
+/// One line of a streamed batch response. Lines come in the order items
+/// finish, so each names the position of its item in the request.
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct StreamedBatchResult {
+    pub index: usize,
+    #[serde(flatten)]
+    pub result: BatchResult,
+}
+
+/// Most recent signed price of each feed.
+#[derive(Default)]
+pub struct SignedPrices {
+    feeds: Mutex<HashMap<String, Signed<PriceFeedResponse>>>,
+}
+
+impl SignedPrices {
+    pub fn store(&self, price_feed_id: &str, signed: &Signed<PriceFeedResponse>) {
+        let mut feeds = self.feeds.lock().expect("signed prices lock poisoned");
+        feeds.insert(price_feed_id.to_string(), signed.clone());
+    }
+
+    /// The last price signed for `price_feed_id` if it was signed at most
+    /// `max_age_ms` before `now_ms`.
+    pub fn get(&self, price_feed_id: &str, max_age_ms: u64, now_ms: u64) -> Option<Signed<PriceFeedResponse>> {
+        let feeds = self.feeds.lock().expect("signed prices lock poisoned");
+        feeds
+            .get(price_feed_id)
+            .filter(|signed| now_ms.saturating_sub(signed.response.timestamp_ms) <= max_age_ms)
+            .cloned()
+    }
+
+    /// Drop the stored price of `price_feed_id`, returning whether there was one.
+    pub fn invalidate(&self, price_feed_id: &str) -> bool {
+        let mut feeds = self.feeds.lock().expect("signed prices lock poisoned");
+        feeds.remove(price_feed_id).is_some()
+    }
+
+    /// Drop every stored price.
+    pub fn clear(&self) {
+        self.feeds.lock().expect("signed prices lock poisoned").clear();
+    }
+
+    /// Every stored price, by price feed id.
+    pub fn export(&self) -> HashMap<String, Signed<PriceFeedResponse>> {
+        self.feeds.lock().expect("signed prices lock poisoned").clone()
+    }
+}
+
+/// Sign prices for several feeds at once. Items are independent: each
+/// reports its own signed price or error. With `Accept: application/x-ndjson`
+/// results are streamed, one line per item, as they are ready.
+#[utoipa::path(
+    post,
+    path = "/process_data/batch",
+    tag = "price feed",
+    request_body = ProcessDataRequest<BatchRequest>,
+    params(
+        ("x-consumer-key" = Option<String>, Header, description = "API key of the consumer, see `[consumers]`"),
+        ("x-sui-signature" = Option<String>, Header, description = "Sui wallet signature of the timestamp and body, instead of an API key"),
+        ("x-sui-timestamp-ms" = Option<u64>, Header, description = "Time the wallet signature was made"),
+        ("accept" = Option<String>, Header, description = "application/x-ndjson to stream results as they are ready"),
+    ),
+    responses(
+        (status = 200, description = "Result of every item", content(
+            (BatchResponse = "application/json"),
+            (StreamedBatchResult = "application/x-ndjson"),
+        )),
+        (status = 400, description = "Empty or oversized batch", body = crate::ErrorResponse),
+        (status = 401, description = "Missing or unknown consumer key or wallet", body = crate::ErrorResponse),
+    )
+)]
+pub async fn process_batch(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, EnclaveError> {
+    let consumer = oracle::authenticate(&state, &headers, &body)?;
+    let request: ProcessDataRequest<BatchRequest> = oracle::parse_body(&body)?;
+    let config = state.config.load_full();
+    let items = request.payload.items;
+    if items.is_empty() || items.len() > config.batch.max_items {
+        return Err(EnclaveError::GenericError(format!(
+            "A batch must have between 1 and {} items, got {}",
+            config.batch.max_items,
+            items.len()
+        )));
+    }
+    let started_ms = current_timestamp_ms()?;
+    let now_ms = current_timestamp_ms()?;
+
+    // Cached prices are served as they are, so they are only used when
+    // nobody has to pay for a signature and at full precision.
+    let cached: Vec<_> = items
+        .iter()
+        .map(|item| {
+            let max_age_ms = item.max_age_ms.unwrap_or(config.batch.default_max_age_ms);
+            match item.cache {
+                CachePolicy::Prefer
+                    if !config.payments.required && item.decimals.is_none() && item.params.is_empty() =>
+                {
+                    state.signed_prices.get(&item.price_feed_id, max_age_ms, now_ms)
+                }
+                _ => None,
+            }
+        })
+        .collect();
+
+    // The PriceFeed objects of the items to sign are fetched together
+    let to_fetch: Vec<&str> = items
+        .iter()
+        .zip(&cached)
+        .filter(|(_, cached)| cached.is_none())
+        .map(|(item, _)| item.price_feed_id.as_str())
+        .collect();
+    let price_feeds = app::prefetch_price_feeds(&state, &to_fetch).await;
+
+    let mut pending = Vec::with_capacity(items.len());
+    for (item, cached) in items.into_iter().zip(cached) {
+        let pending_item = match cached {
+            // A price signed before a pause or the end of a window is held
+            // back too
+            Some(signed) => match app::check_may_sign(
+                &state,
+                &config,
+                &config.feed(&item.price_feed_id),
+                &item.price_feed_id,
+            ) {
+                Ok(()) => {
+                    if let Some(consumer) = &consumer {
+                        oracle::record_served::<PriceFeedModule>(&state, consumer, &signed);
+                    }
+                    Pending::Ready(Box::new(BatchResult::ok(item.price_feed_id, true, signed)))
+                }
+                Err(e) => Pending::Ready(Box::new(BatchResult::err(item.price_feed_id, e))),
+            },
+            None => {
+                let (state, consumer) = (state.clone(), consumer.clone());
+                let request = PriceFeedRequest {
+                    price_feed_id: item.price_feed_id.clone(),
+                    receipt_id: item.receipt_id,
+                    decimals: item.decimals,
+                    params: item.params,
+                    price_feed: price_feeds.get(&item.price_feed_id).cloned(),
+                };
+                Pending::Fetching(
+                    item.price_feed_id,
+                    tokio::spawn(async move {
+                        oracle::run::<PriceFeedModule>(&state, consumer.as_deref(), request).await
+                    }),
+                )
+            }
+        };
+        pending.push(pending_item);
+    }
+
+    let streaming = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains(NDJSON));
+    if streaming {
+        return Ok(stream_results(state, started_ms, pending));
+    }
+
+    let _in_flight = state.capacity.start(started_ms);
+    let mut results = Vec::with_capacity(pending.len());
+    for item in pending {
+        results.push(item.resolve().await);
+    }
+    Ok(Json(BatchResponse { results }).into_response())
+}
+
+/// Stream a line per item as it finishes. The request counts as in flight
+/// until the last line is written.
+fn stream_results(state: Arc<AppState>, started_ms: u64, pending: Vec<Pending>) -> Response {
+    let (tx, rx) = mpsc::channel::<Result<Bytes, Infallible>>(pending.len());
+    tokio::spawn(async move {
+        let _in_flight = state.capacity.start(started_ms);
+        let mut items = JoinSet::new();
+        for (index, item) in pending.into_iter().enumerate() {
+            items.spawn(async move { (index, item.resolve().await) });
+        }
+        while let Some(joined) = items.join_next().await {
+            let Ok((index, result)) = joined else { continue };
+            let mut line = match serde_json::to_vec(&StreamedBatchResult { index, result }) {
+                Ok(line) => line,
+                Err(e) => {
+                    warn!("Failed to serialize batch result: {}", e);
+                    continue;
+                }
+            };
+            line.push(b'\n');
+            // The client went away: the remaining items still finish and
+            // are cached, but nobody reads them
+            if tx.send(Ok(Bytes::from(line))).await.is_err() {
+                break;
+            }
+        }
+    });
+    ([(header::CONTENT_TYPE, NDJSON)], Body::from_stream(ReceiverStream::new(rx))).into_response()
+}
+
+enum Pending {
+    Ready(Box<BatchResult>),
+    Fetching(
+        String,
+        tokio::task::JoinHandle<Result<Signed<PriceFeedResponse>, EnclaveError>>,
+    ),
+}
+
+impl Pending {
+    async fn resolve(self) -> BatchResult {
+        match self {
+            Pending::Ready(result) => *result,
+            Pending::Fetching(price_feed_id, handle) => {
+                let result = handle.await.unwrap_or_else(|e| {
+                    Err(EnclaveError::GenericError(format!("Batch item failed: {}", e)))
+                });
+                match result {
+                    Ok(signed) => BatchResult::ok(price_feed_id, false, signed),
+                    Err(e) => BatchResult::err(price_feed_id, e),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::common::IntentScope;
+
+    pub(crate) fn signed(timestamp_ms: u64) -> Signed<PriceFeedResponse> {
+        let response = PriceFeedResponse {
+            oracle_id: "0x1".to_string(),
+            price_feed_id: "0x2".to_string(),
+            price: 100,
+            timestamp_ms,
+            rate_of_change: None,
+            market_closed: false,
+            source_timestamp_ms: None,
+            decimals: None,
+            rounding: None,
+            feed_object: None,
+            params: BTreeMap::new(),
+        };
+        ProcessedDataResponse {
+            response: IntentMessage::new(response, timestamp_ms, IntentScope::PriceFeed),
+            signature: "00".to_string(),
+            envelope: None,
+            update_id: None,
+        }
+    }
+
+    #[test]
+    fn test_signed_prices_max_age() {
+        let prices = SignedPrices::default();
+        prices.store("0x2", &signed(1_000));
+        assert!(prices.get("0x2", 500, 1_500).is_some());
+        assert!(prices.get("0x2", 499, 1_500).is_none());
+        assert!(prices.get("0x3", u64::MAX, 1_500).is_none());
+
+        prices.store("0x2", &signed(2_000));
+        assert_eq!(prices.get("0x2", 0, 2_000).unwrap().response.timestamp_ms, 2_000);
+    }
+}
@@ -0,0 +1,116 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Operator tooling for key backups exported by POST /admin/export_key_backup.
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{KeyPair, ToFromBytes};
+use nautilus_server::backup::{
+    decrypt_share, generate_operator_key, parse_secret_key, recover, KeyBackupResponse, Share,
+};
+use nautilus_server::keys::{seal_keypair, sealing_key};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(about = "Create operator keys for, decrypt and recombine signing key backups")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Generate an operator X25519 keypair. The public key goes in
+    /// `key_backup.operator_keys`, the secret key stays with the operator.
+    Keygen,
+    /// Decrypt the operator's share of a backup and print it.
+    Decrypt {
+        /// Backup exported by POST /admin/export_key_backup
+        backup: PathBuf,
+        /// Environment variable holding the operator's secret key in Hex
+        #[arg(long, default_value = "NAUTILUS_OPERATOR_KEY")]
+        operator_key_env: String,
+    },
+    /// Recover the signing key from decrypted shares and seal it for a new
+    /// instance.
+    Combine {
+        /// Backup exported by POST /admin/export_key_backup
+        backup: PathBuf,
+        /// Files holding shares printed by `decrypt`
+        shares: Vec<PathBuf>,
+        /// Where to write the sealed key, see `key.sealed_key_path`
+        #[arg(long)]
+        out: PathBuf,
+        /// Environment variable holding the sealing key of the new instance
+        #[arg(long, default_value = "NAUTILUS_SEALING_KEY")]
+        sealing_key_env: String,
+    },
+}
+
+fn main() -> Result<()> {
+    match Args::parse().command {
+        Command::Keygen => {
+            let (secret, public) = generate_operator_key();
+            println!("secret key: {}", secret);
+            println!("public key: {}", public);
+        }
+        Command::Decrypt {
+            backup,
+            operator_key_env,
+        } => {
+            let backup = read_backup(&backup)?;
+            let secret = std::env::var(&operator_key_env)
+                .with_context(|| format!("{} is not set", operator_key_env))?;
+            let secret = parse_secret_key(&secret)?;
+            // Try every share: the operator needn't know which index is theirs
+            let share = backup
+                .shares
+                .iter()
+                .find_map(|share| decrypt_share(&secret, share).ok())
+                .context("No share in the backup is encrypted to this operator key")?;
+            println!("{}", serde_json::to_string(&share)?);
+        }
+        Command::Combine {
+            backup,
+            shares,
+            out,
+            sealing_key_env,
+        } => {
+            let backup = read_backup(&backup)?;
+            if shares.len() < backup.threshold {
+                anyhow::bail!(
+                    "The backup needs {} shares, got {}",
+                    backup.threshold,
+                    shares.len()
+                );
+            }
+            let shares = shares
+                .iter()
+                .map(|path| {
+                    let json = fs::read_to_string(path)
+                        .with_context(|| format!("Failed to read share at: {}", path.display()))?;
+                    serde_json::from_str::<Share>(&json)
+                        .with_context(|| format!("Invalid share at: {}", path.display()))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let kp = recover(&shares, &backup.public_key)?;
+            fs::write(&out, seal_keypair(&sealing_key(&sealing_key_env)?, &kp))
+                .with_context(|| format!("Failed to write sealed key at: {}", out.display()))?;
+            println!(
+                "Recovered {} and sealed it at {}",
+                Hex::encode(kp.public().as_bytes()),
+                out.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn read_backup(path: &Path) -> Result<KeyBackupResponse> {
+    let json = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read backup at: {}", path.display()))?;
+    serde_json::from_str(&json).with_context(|| format!("Invalid backup at: {}", path.display()))
+}
@@ -0,0 +1,233 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feed author tooling: inspect a PriceFeed object, dry-run its extraction
+//! against the upstream, verify a signed response, encrypt an API key to an
+//! enclave and ping one, without going through the enclave's error strings.
+
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
+use fastcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{ToFromBytes, VerifyingKey};
+use nautilus_server::api_key_encryption::{encrypt_api_key, parse_public_key, ENCRYPTED_PREFIX};
+use nautilus_server::app::{extract_price, with_api_key};
+use nautilus_server::sui::SuiClientWrapper;
+use nautilus_server::types::PriceFeed;
+use nautilus_server::verification::signing_bytes;
+use serde_json::Value;
+use std::fs;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(about = "Test price feeds against the chain, their upstreams and a running enclave")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Where PriceFeed objects are read from, as in the `[sui]` config section.
+#[derive(clap::Args)]
+struct SuiArgs {
+    /// Sui fullnode RPC URL
+    #[arg(
+        long,
+        env = "NAUTILUS_SUI_RPC_URL",
+        default_value = "https://fullnode.testnet.sui.io:443"
+    )]
+    rpc_url: String,
+    /// Package id of the oracle_builder Move package
+    #[arg(long, env = "NAUTILUS_SUI_ORACLE_BUILDER_PACKAGE_ID")]
+    package_id: String,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Fetch a PriceFeed object and print it, with its API key redacted.
+    Feed {
+        price_feed_id: String,
+        #[command(flatten)]
+        sui: SuiArgs,
+    },
+    /// Fetch a PriceFeed object, GET its upstream and extract the price at
+    /// its `response_field` the way the enclave does. Only JSON upstreams
+    /// are read, and the `format`, `transform` and `scale` of the enclave's
+    /// `[feeds]` config are not applied.
+    Extract {
+        price_feed_id: String,
+        #[command(flatten)]
+        sui: SuiArgs,
+        /// Try this path instead of the feed's `response_field`
+        #[arg(long)]
+        response_field: Option<String>,
+        /// Extract from this saved upstream response instead of fetching it
+        #[arg(long)]
+        body: Option<PathBuf>,
+    },
+    /// Verify a signed response, as returned by the enclave, against its
+    /// public key.
+    Verify {
+        /// File holding the signed response JSON
+        response: PathBuf,
+        /// Enclave public key in Hex
+        #[arg(long)]
+        public_key: String,
+    },
+    /// Encrypt an API key to an enclave, printing the value to store as the
+    /// feed's `api_key`.
+    EncryptApiKey {
+        /// The API key
+        api_key: String,
+        /// X25519 public key from the enclave's GET /api_key_encryption_key,
+        /// whose attestation should be checked first
+        #[arg(long)]
+        public_key: String,
+        /// Object id of the feed the key is stored in; it is only decrypted
+        /// for this feed
+        #[arg(long)]
+        feed_id: String,
+        /// Host of the feed's upstream URL, e.g. api.vendor.com; it is only
+        /// decrypted for requests to this host
+        #[arg(long)]
+        host: String,
+    },
+    /// Check that an enclave is up and healthy.
+    Ping {
+        /// Base URL of the enclave, e.g. http://localhost:3000
+        url: String,
+    },
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    match Args::parse().command {
+        Command::Feed { price_feed_id, sui } => {
+            let mut price_feed = fetch_price_feed(&sui, &price_feed_id).await?;
+            if price_feed.api_key.is_some() {
+                price_feed.api_key = Some("<redacted>".to_string());
+            }
+            println!("{}", serde_json::to_string_pretty(&price_feed)?);
+        }
+        Command::Extract {
+            price_feed_id,
+            sui,
+            response_field,
+            body,
+        } => {
+            let price_feed = fetch_price_feed(&sui, &price_feed_id).await?;
+            let body = match body {
+                Some(path) => fs::read_to_string(&path)
+                    .with_context(|| format!("Failed to read {}", path.display()))?,
+                None => fetch_upstream(&price_feed).await?,
+            };
+            let json: Value =
+                serde_json::from_str(&body).context("Upstream response is not valid JSON")?;
+            let response_field = response_field.unwrap_or(price_feed.response_field);
+            match extract_price(&json, &response_field) {
+                Ok(price) => println!("{} = {}", response_field, price),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    eprintln!("Upstream response:");
+                    eprintln!("{}", serde_json::to_string_pretty(&json)?);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Verify {
+            response,
+            public_key,
+        } => {
+            let signed: Value = serde_json::from_str(
+                &fs::read_to_string(&response)
+                    .with_context(|| format!("Failed to read {}", response.display()))?,
+            )
+            .context("Signed response is not valid JSON")?;
+            let public_key = Hex::decode(public_key.trim_start_matches("0x"))
+                .ok()
+                .and_then(|bytes| Ed25519PublicKey::from_bytes(&bytes).ok())
+                .context("Invalid public key")?;
+            let signature = signed["signature"]
+                .as_str()
+                .and_then(|signature| Hex::decode(signature).ok())
+                .and_then(|bytes| Ed25519Signature::from_bytes(&bytes).ok())
+                .context("Missing or invalid signature")?;
+            let (bytes, timestamp_ms) =
+                signing_bytes(&signed["response"]).map_err(|e| anyhow::anyhow!(e))?;
+            public_key
+                .verify(&bytes, &signature)
+                .map_err(|_| anyhow::anyhow!("Signature does not match the response"))?;
+            println!(
+                "Valid signature over {} bytes signed at {}",
+                bytes.len(),
+                timestamp_ms
+            );
+        }
+        Command::EncryptApiKey {
+            api_key,
+            public_key,
+            feed_id,
+            host,
+        } => {
+            println!(
+                "{}",
+                encrypt_api_key(&parse_public_key(&public_key)?, &feed_id, &host, &api_key)
+            );
+        }
+        Command::Ping { url } => {
+            let url = url.trim_end_matches('/');
+            let pong = reqwest::get(format!("{}/", url))
+                .await
+                .with_context(|| format!("Failed to reach {}", url))?
+                .text()
+                .await?;
+            println!("/: {}", pong);
+            let health: Value = reqwest::get(format!("{}/health_check", url))
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            println!("/health_check: {}", health);
+        }
+    }
+    Ok(())
+}
+
+async fn fetch_price_feed(sui: &SuiArgs, price_feed_id: &str) -> Result<PriceFeed> {
+    SuiClientWrapper::new(&sui.rpc_url, sui.package_id.clone())
+        .await?
+        .fetch_price_feed(price_feed_id)
+        .await
+}
+
+/// GET the feed's upstream with its API key, as the enclave does. Feeds with
+/// HMAC signed requests need the enclave's config and are not fetched.
+async fn fetch_upstream(price_feed: &PriceFeed) -> Result<String> {
+    if price_feed
+        .api_key
+        .as_deref()
+        .is_some_and(|api_key| api_key.starts_with(ENCRYPTED_PREFIX))
+    {
+        anyhow::bail!("The API key is encrypted to an enclave; pass --body instead");
+    }
+    let api_key = price_feed
+        .api_key
+        .as_deref()
+        .zip(price_feed.api_key_config.as_deref());
+    let request = with_api_key(
+        reqwest::Client::new().get(&price_feed.underlying_url),
+        &price_feed.underlying_url,
+        api_key,
+        None,
+    )
+    .map_err(|e| anyhow::anyhow!("{}", e))?;
+    let response = request
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach {}", price_feed.underlying_url))?;
+    let status = response.status();
+    let body = response.text().await?;
+    if !status.is_success() {
+        anyhow::bail!("Upstream answered {}: {}", status, body);
+    }
+    Ok(body)
+}
@@ -0,0 +1,50 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! BCS test vectors for the Move tests: prints the exact bytes an intent
+//! message is signed over, ready to paste into `enclave.move` or
+//! `oracle_builder.move`, so the Rust and Move layouts can be kept in sync.
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use fastcrypto::encoding::{Encoding, Hex};
+use nautilus_server::verification::signing_bytes;
+use serde_json::Value;
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(about = "Print the BCS signing bytes of example intent messages")]
+struct Args {
+    /// Files holding an intent message as JSON, like the `response` of a
+    /// signed response: `{"intent": 0, "timestamp_ms": ..., "data": {...}}`.
+    /// Read from stdin if none are given.
+    messages: Vec<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    if args.messages.is_empty() {
+        let mut message = String::new();
+        std::io::stdin()
+            .read_to_string(&mut message)
+            .context("Failed to read stdin")?;
+        return print_vector("stdin", &message);
+    }
+    for path in &args.messages {
+        let message = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        print_vector(&path.display().to_string(), &message)?;
+    }
+    Ok(())
+}
+
+fn print_vector(source: &str, message: &str) -> Result<()> {
+    let message: Value =
+        serde_json::from_str(message).with_context(|| format!("{} is not JSON", source))?;
+    let (bytes, _) = signing_bytes(&message).map_err(|e| anyhow::anyhow!("{}: {}", source, e))?;
+    println!("// {}, {} bytes", source, bytes.len());
+    println!("x\"{}\"", Hex::encode(bytes));
+    Ok(())
+}
@@ -0,0 +1,122 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Price deviation circuit breaker: a price moving more than the configured
+//! amount from the last signed one within the window is not signed until an
+//! operator explicitly allows it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use crate::app::RateOfChange;
+use crate::history::Observation;
+
+/// What happens when a price trips the breaker.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BreakerMode {
+    /// Refuse to sign until overridden
+    #[default]
+    Refuse,
+    /// Sign anyway and log a warning
+    Flag,
+}
+
+/// Circuit breaker settings, disabled unless `max_deviation_bps` is set.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CircuitBreaker {
+    /// Largest move from the last signed price, in basis points. Feeds can
+    /// set their own in their `[feeds]` section.
+    #[serde(default)]
+    pub max_deviation_bps: Option<u64>,
+    /// Only prices signed this recently are compared against
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    #[serde(default)]
+    pub mode: BreakerMode,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            max_deviation_bps: None,
+            window_secs: default_window_secs(),
+            mode: BreakerMode::default(),
+        }
+    }
+}
+
+fn default_window_secs() -> u64 {
+    300
+}
+
+impl CircuitBreaker {
+    /// Deviation of `price` from `previous` in basis points, if it is within
+    /// the window and exceeds `max_deviation_bps`.
+    pub fn tripped(
+        &self,
+        max_deviation_bps: u64,
+        previous: Observation,
+        price: u64,
+        timestamp_ms: u64,
+    ) -> Option<u64> {
+        let change = RateOfChange::between(previous, price, timestamp_ms)?;
+        (change.elapsed_ms <= self.window_secs * 1000 && change.change_bps > max_deviation_bps)
+            .then_some(change.change_bps)
+    }
+}
+
+/// Feeds whose next price is signed without a deviation check, as allowed by
+/// an operator.
+#[derive(Default)]
+pub struct BreakerOverrides {
+    feeds: Mutex<HashSet<String>>,
+}
+
+impl BreakerOverrides {
+    pub fn allow_next(&self, price_feed_id: &str) {
+        self.feeds
+            .lock()
+            .expect("overrides lock poisoned")
+            .insert(price_feed_id.to_string());
+    }
+
+    /// Use up the override of `price_feed_id`, returning whether there was one.
+    pub fn take(&self, price_feed_id: &str) -> bool {
+        self.feeds
+            .lock()
+            .expect("overrides lock poisoned")
+            .remove(price_feed_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tripped() {
+        let breaker = CircuitBreaker {
+            max_deviation_bps: Some(500),
+            window_secs: 60,
+            mode: BreakerMode::Refuse,
+        };
+        let previous = Observation {
+            price: 10_000,
+            timestamp_ms: 1_000,
+        };
+
+        assert_eq!(breaker.tripped(500, previous, 10_500, 2_000), None);
+        assert_eq!(breaker.tripped(500, previous, 10_600, 2_000), Some(600));
+        assert_eq!(breaker.tripped(500, previous, 9_000, 2_000), Some(1_000));
+        // The previous price is too old to compare against.
+        assert_eq!(breaker.tripped(500, previous, 20_000, 61_001), None);
+
+        let overrides = BreakerOverrides::default();
+        assert!(!overrides.take("feed"));
+        overrides.allow_next("feed");
+        assert!(overrides.take("feed"));
+        assert!(!overrides.take("feed"));
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::common::current_timestamp_ms;
+use crate::AppState;
+use crate::EnclaveError;
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use utoipa::ToSchema;
+
+/// Length of the window throughput and latency are averaged over.
+pub const CAPACITY_WINDOW_SECS: u64 = 60;
+
+/// Requests completed in one second of the window.
+#[derive(Debug, Clone, Copy, Default)]
+struct Bucket {
+    second: u64,
+    requests: u64,
+    latency_ms: u64,
+}
+
+/// Throughput and concurrency of `process_data`, for autoscaling decisions.
+#[derive(Default)]
+pub struct CapacityTracker {
+    in_flight: AtomicUsize,
+    buckets: Mutex<VecDeque<Bucket>>,
+}
+
+/// Marks a request as in flight until dropped, then records its latency.
+pub struct InFlight<'a> {
+    tracker: &'a CapacityTracker,
+    started_ms: u64,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.tracker.in_flight.fetch_sub(1, Ordering::Relaxed);
+        if let Ok(now_ms) = current_timestamp_ms() {
+            self.tracker.record(now_ms, now_ms.saturating_sub(self.started_ms));
+        }
+    }
+}
+
+/// Snapshot of the tracked window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CapacitySnapshot {
+    pub in_flight: usize,
+    pub requests_per_sec: f64,
+    pub avg_latency_ms: Option<f64>,
+}
+
+impl CapacityTracker {
+    /// Count a request as in flight for the lifetime of the returned guard.
+    pub fn start(&self, now_ms: u64) -> InFlight<'_> {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight {
+            tracker: self,
+            started_ms: now_ms,
+        }
+    }
+
+    /// Record a request completed at `now_ms` after `latency_ms`.
+    pub fn record(&self, now_ms: u64, latency_ms: u64) {
+        let second = now_ms / 1000;
+        let mut buckets = self.buckets.lock().expect("capacity lock poisoned");
+        match buckets.back_mut() {
+            Some(bucket) if bucket.second == second => {
+                bucket.requests += 1;
+                bucket.latency_ms += latency_ms;
+            }
+            _ => buckets.push_back(Bucket {
+                second,
+                requests: 1,
+                latency_ms,
+            }),
+        }
+        while buckets
+            .front()
+            .is_some_and(|b| b.second + CAPACITY_WINDOW_SECS <= second)
+        {
+            buckets.pop_front();
+        }
+    }
+
+    /// Throughput and latency over the window ending at `now_ms`.
+    pub fn snapshot(&self, now_ms: u64) -> CapacitySnapshot {
+        let second = now_ms / 1000;
+        let buckets = self.buckets.lock().expect("capacity lock poisoned");
+        let (requests, latency_ms) = buckets
+            .iter()
+            .filter(|b| b.second + CAPACITY_WINDOW_SECS > second)
+            .fold((0, 0), |(r, l), b| (r + b.requests, l + b.latency_ms));
+
+        CapacitySnapshot {
+            in_flight: self.in_flight.load(Ordering::Relaxed),
+            requests_per_sec: requests as f64 / CAPACITY_WINDOW_SECS as f64,
+            avg_latency_ms: (requests > 0).then(|| latency_ms as f64 / requests as f64),
+        }
+    }
+}
+
+/// Response for capacity.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CapacityResponse {
+    /// Requests completed per second, averaged over the window
+    pub requests_per_sec: f64,
+    /// Average request latency over the window, if any completed
+    pub avg_latency_ms: Option<f64>,
+    /// Requests currently being processed
+    pub in_flight: usize,
+    /// Configured connection limit, if any
+    pub max_connections: Option<usize>,
+    /// Throughput sustainable at the current latency with every connection
+    /// slot busy; needs `runtime.max_connections`
+    pub estimated_max_requests_per_sec: Option<f64>,
+    /// Fraction of that estimate still unused, between 0 and 1
+    pub headroom: Option<f64>,
+}
+
+/// Endpoint reporting throughput and headroom so an external autoscaler can
+/// decide when to add or remove enclave replicas.
+#[utoipa::path(
+    get,
+    path = "/capacity",
+    tag = "health",
+    responses((status = 200, body = CapacityResponse))
+)]
+pub async fn capacity(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<CapacityResponse>, EnclaveError> {
+    let snapshot = state.capacity.snapshot(current_timestamp_ms()?);
+    let max_connections = state.config.load().runtime.max_connections;
+
+    let estimated_max_requests_per_sec = match (max_connections, snapshot.avg_latency_ms) {
+        (Some(max), Some(latency)) if latency > 0.0 => Some(max as f64 * 1000.0 / latency),
+        _ => None,
+    };
+    let headroom = match (max_connections, estimated_max_requests_per_sec) {
+        (_, Some(max_rps)) => Some((1.0 - snapshot.requests_per_sec / max_rps).clamp(0.0, 1.0)),
+        (Some(max), None) if max > 0 => {
+            Some((1.0 - snapshot.in_flight as f64 / max as f64).clamp(0.0, 1.0))
+        }
+        _ => None,
+    };
+
+    Ok(Json(CapacityResponse {
+        requests_per_sec: snapshot.requests_per_sec,
+        avg_latency_ms: snapshot.avg_latency_ms,
+        in_flight: snapshot.in_flight,
+        max_connections,
+        estimated_max_requests_per_sec,
+        headroom,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_flight() {
+        let tracker = CapacityTracker::default();
+        {
+            let _a = tracker.start(0);
+            let _b = tracker.start(0);
+            assert_eq!(tracker.snapshot(0).in_flight, 2);
+        }
+        assert_eq!(tracker.snapshot(0).in_flight, 0);
+    }
+
+    #[test]
+    fn test_window() {
+        let tracker = CapacityTracker::default();
+        assert_eq!(tracker.snapshot(0).avg_latency_ms, None);
+
+        tracker.record(1_000, 10);
+        tracker.record(1_500, 30);
+        tracker.record(30_000, 20);
+        let snapshot = tracker.snapshot(30_000);
+        assert_eq!(snapshot.avg_latency_ms, Some(20.0));
+        assert_eq!(snapshot.requests_per_sec, 3.0 / 60.0);
+
+        // The first second falls out of the window.
+        tracker.record(61_000, 40);
+        let snapshot = tracker.snapshot(61_000);
+        assert_eq!(snapshot.avg_latency_ms, Some(30.0));
+        assert_eq!(tracker.buckets.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,185 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Debug capture of raw upstream responses. When `capture.responses_per_feed`
+//! is set, the last responses of every feed are kept as received, with the
+//! feed's API key redacted, and served on `/admin/upstream_captures`, so an
+//! extraction failing in production can be checked against what the
+//! provider actually returned.
+
+use axum::extract::{Path, State};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use crate::common::current_timestamp_ms;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Replaces API keys in captured URLs and bodies.
+const REDACTED: &str = "[redacted]";
+
+/// `[capture]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Capture {
+    /// Raw upstream responses kept per feed; 0 disables capture
+    #[serde(default)]
+    pub responses_per_feed: usize,
+    /// Bytes of each response body kept, the rest is cut off
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+}
+
+impl Default for Capture {
+    fn default() -> Self {
+        Self {
+            responses_per_feed: 0,
+            max_body_bytes: default_max_body_bytes(),
+        }
+    }
+}
+
+fn default_max_body_bytes() -> usize {
+    64 * 1024
+}
+
+/// An upstream response as received, before parsing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CapturedResponse {
+    pub captured_at_ms: u64,
+    pub url: String,
+    pub status: u16,
+    pub content_type: Option<String>,
+    /// Decompressed body, lossily decoded as UTF-8
+    pub body: String,
+    /// Whether the body was cut off at `capture.max_body_bytes`
+    pub truncated: bool,
+}
+
+impl CapturedResponse {
+    /// Capture of a response of `url`, with every occurrence of `api_key`
+    /// redacted.
+    pub fn new(
+        config: &Capture,
+        url: &str,
+        api_key: Option<&str>,
+        status: u16,
+        content_type: Option<&str>,
+        body: &[u8],
+    ) -> Self {
+        let redact = |text: &str| match api_key {
+            Some(api_key) if !api_key.is_empty() => text.replace(api_key, REDACTED),
+            _ => text.to_string(),
+        };
+        // Redacted before truncating, so no part of a key is left at the cut
+        let mut body = redact(&String::from_utf8_lossy(body));
+        let truncated = body.len() > config.max_body_bytes;
+        if truncated {
+            let mut end = config.max_body_bytes;
+            while !body.is_char_boundary(end) {
+                end -= 1;
+            }
+            body.truncate(end);
+        }
+        Self {
+            captured_at_ms: current_timestamp_ms().unwrap_or_default(),
+            url: redact(url),
+            status,
+            content_type: content_type.map(str::to_string),
+            body,
+            truncated,
+        }
+    }
+}
+
+/// Last captured upstream responses of each feed, oldest first.
+#[derive(Default)]
+pub struct UpstreamCaptures {
+    feeds: Mutex<HashMap<String, VecDeque<CapturedResponse>>>,
+}
+
+impl UpstreamCaptures {
+    /// Keep `capture` for `feed_id`, evicting the oldest beyond `capacity`.
+    pub fn record(&self, feed_id: &str, capture: CapturedResponse, capacity: usize) {
+        let mut feeds = self.feeds.lock().unwrap();
+        let captures = feeds.entry(feed_id.to_string()).or_default();
+        captures.push_back(capture);
+        while captures.len() > capacity {
+            captures.pop_front();
+        }
+    }
+
+    pub fn get(&self, feed_id: &str) -> Vec<CapturedResponse> {
+        self.feeds
+            .lock()
+            .unwrap()
+            .get(feed_id)
+            .map(|captures| captures.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Response for upstream captures.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpstreamCapturesResponse {
+    pub feed_id: String,
+    /// Oldest first
+    pub captures: Vec<CapturedResponse>,
+}
+
+/// Endpoint that returns the captured upstream responses of a feed.
+pub async fn upstream_captures(
+    State(state): State<Arc<AppState>>,
+    Path(feed_id): Path<String>,
+) -> Result<Json<UpstreamCapturesResponse>, EnclaveError> {
+    if state.config.load().capture.responses_per_feed == 0 {
+        return Err(EnclaveError::GenericError(
+            "Upstream capture is disabled, set capture.responses_per_feed".to_string(),
+        ));
+    }
+    Ok(Json(UpstreamCapturesResponse {
+        captures: state.captures.get(&feed_id),
+        feed_id,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_redacts_and_evicts() {
+        let config = Capture {
+            responses_per_feed: 2,
+            max_body_bytes: 24,
+        };
+        let capture = CapturedResponse::new(
+            &config,
+            "https://api.example.com/price?key=secret",
+            Some("secret"),
+            200,
+            Some("application/json"),
+            br#"{"key":"secret","price":"123.456"}"#,
+        );
+        assert_eq!(capture.url, "https://api.example.com/price?key=[redacted]");
+        assert_eq!(capture.body, r#"{"key":"[redacted]","pri"#);
+        assert!(capture.truncated);
+
+        let captures = UpstreamCaptures::default();
+        for status in [500, 502, 200] {
+            let capture = CapturedResponse {
+                status,
+                ..capture.clone()
+            };
+            captures.record("feed", capture, config.responses_per_feed);
+        }
+        let statuses: Vec<u16> = captures
+            .get("feed")
+            .iter()
+            .map(|capture| capture.status)
+            .collect();
+        assert_eq!(statuses, vec![502, 200]);
+        assert!(captures.get("other").is_empty());
+    }
+}
@@ -3,29 +3,34 @@
 
 use crate::AppState;
 use crate::EnclaveError;
-use axum::{extract::State, Json};
+use axum::{
+    extract::{Query, State},
+    Json,
+};
 use fastcrypto::traits::Signer;
 use fastcrypto::{encoding::Encoding, traits::ToFromBytes};
-use fastcrypto::{encoding::Hex, traits::KeyPair as FcKeyPair};
+use fastcrypto::{encoding::Base64, encoding::Hex, traits::KeyPair as FcKeyPair};
 use nsm_api::api::{Request as NsmRequest, Response as NsmResponse};
 use nsm_api::driver;
 
+use rust_decimal::{Decimal, RoundingStrategy};
 use serde::{Deserialize, Serialize};
 use serde_bytes::ByteBuf;
 use serde_repr::Deserialize_repr;
 use serde_repr::Serialize_repr;
 
 use std::fmt::Debug;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use tracing::info;
+use utoipa::{IntoParams, ToSchema};
 
 use fastcrypto::ed25519::Ed25519KeyPair;
 /// ==== COMMON TYPES ====
 
 /// Intent message wrapper struct containing the intent scope and timestamp.
 /// This standardizes the serialized payload for signing.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct IntentMessage<T: Serialize> {
     pub intent: IntentScope,
     pub timestamp_ms: u64,
@@ -34,10 +39,24 @@ pub struct IntentMessage<T: Serialize> {
 
 /// Intent scope enum. Add new scope here if needed, each corresponds to a
 /// scope for signing. Replace in with your own intent per message type being signed by the enclave.
-#[derive(Serialize_repr, Deserialize_repr, Debug)]
+/// Mirrored by `IntentScope` in the `nautilus-verify` crate, add new scopes there too.
+#[derive(Serialize_repr, Deserialize_repr, Debug, Clone, Copy, PartialEq, Eq, Hash, ToSchema)]
 #[repr(u8)]
 pub enum IntentScope {
     PriceFeed = 0,
+    PriceFeedWithChange = 1,
+    PriceFeedMarketClosed = 2,
+    FeeQuote = 3,
+    Weather = 4,
+    SportsResult = 5,
+    ProofOfReserve = 6,
+    NftFloorPrice = 7,
+    PriceFeedReducedPrecision = 8,
+    KeyRetirement = 9,
+    PriceStatistics = 10,
+    PriceFeedWide = 11,
+    RateFeed = 12,
+    PriceFeedExtended = 13,
 }
 
 impl<T: Serialize + Debug> IntentMessage<T> {
@@ -50,19 +69,124 @@ impl<T: Serialize + Debug> IntentMessage<T> {
     }
 }
 
+/// Version of the layout of signed payloads, bumped whenever the BCS
+/// encoding of an intent message or of any response type changes.
+pub const PAYLOAD_SCHEMA_VERSION: u32 = 2;
+
+/// Signature schemes responses can be signed with.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureScheme {
+    /// Ed25519 over the BCS bytes of `response`
+    Ed25519,
+}
+
+/// Where the timestamp of a signed intent message was taken from.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampSource {
+    /// The enclave's clock
+    #[default]
+    Enclave,
+    /// The upstream's own timestamp, extracted at the feed's `timestamp_field`
+    Upstream,
+    /// The on-chain `Clock` object, read through the Sui RPC
+    SuiClock,
+}
+
+/// How an extracted price is rounded to an integer of its scaled decimals.
+/// Signed along with the price of feeds that configure it, so a consumer
+/// comparing against the provider's own feed knows which way it went. The
+/// variant order is part of the signed bytes.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RoundingMode {
+    /// Toward zero, dropping the extra digits
+    #[default]
+    Truncate,
+    /// Toward negative infinity
+    Floor,
+    /// Toward positive infinity
+    Ceil,
+    /// To nearest, ties to the even neighbour (banker's rounding)
+    HalfEven,
+    /// To nearest, ties away from zero
+    HalfUp,
+}
+
+impl RoundingMode {
+    /// Round `value` to an integer.
+    pub fn round(self, value: Decimal) -> Decimal {
+        let strategy = match self {
+            RoundingMode::Truncate => RoundingStrategy::ToZero,
+            RoundingMode::Floor => RoundingStrategy::ToNegativeInfinity,
+            RoundingMode::Ceil => RoundingStrategy::ToPositiveInfinity,
+            RoundingMode::HalfEven => RoundingStrategy::MidpointNearestEven,
+            RoundingMode::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+        };
+        value.round_dp_with_strategy(0, strategy)
+    }
+}
+
+/// Describes how a response was signed, so generic tooling can verify
+/// responses of differently configured enclaves.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct SignatureEnvelope {
+    pub signature_scheme: SignatureScheme,
+    /// Same as `response.intent`
+    pub intent_scope: IntentScope,
+    /// [`PAYLOAD_SCHEMA_VERSION`] the payload was encoded with
+    pub payload_schema_version: u32,
+    /// Length of the signed BCS bytes
+    pub bcs_length: u64,
+    /// Where `response.timestamp_ms` was taken from, for price feeds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp_source: Option<TimestampSource>,
+}
+
 /// Wrapper struct containing the response (the intent message) and signature.
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ProcessedDataResponse<T> {
     pub response: T,
     pub signature: String,
+    /// How `signature` was made. Not part of the signed payload.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub envelope: Option<SignatureEnvelope>,
+    /// Deterministic id of the update the response carries, identical for
+    /// responses that would result in the same on-chain state, so relayers
+    /// can skip duplicates. Not part of the signed payload.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_id: Option<String>,
+}
+
+impl<T> ProcessedDataResponse<T> {
+    pub fn with_update_id(mut self, update_id: String) -> Self {
+        self.update_id = Some(update_id);
+        self
+    }
+
+    pub fn with_timestamp_source(mut self, timestamp_source: TimestampSource) -> Self {
+        if let Some(envelope) = &mut self.envelope {
+            envelope.timestamp_source = Some(timestamp_source);
+        }
+        self
+    }
 }
 
 /// Wrapper struct containing the request payload.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ProcessDataRequest<T> {
     pub payload: T,
 }
 
+/// Current UTC time in milliseconds.
+pub fn current_timestamp_ms() -> Result<u64, EnclaveError> {
+    Ok(std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to get current timestamp: {}", e)))?
+        .as_millis() as u64)
+}
+
 /// Sign the bcs bytes of the the payload with keypair.
 pub fn to_signed_response<T: Serialize + Clone>(
     kp: &Ed25519KeyPair,
@@ -79,15 +203,23 @@ pub fn to_signed_response<T: Serialize + Clone>(
     let signing_payload = bcs::to_bytes(&intent_msg).expect("should not fail");
     let sig = kp.sign(&signing_payload);
     ProcessedDataResponse {
+        envelope: Some(SignatureEnvelope {
+            signature_scheme: SignatureScheme::Ed25519,
+            intent_scope: intent,
+            payload_schema_version: PAYLOAD_SCHEMA_VERSION,
+            bcs_length: signing_payload.len() as u64,
+            timestamp_source: None,
+        }),
         response: intent_msg,
         signature: Hex::encode(sig),
+        update_id: None,
     }
 }
 
 /// ==== HEALTHCHECK, GET ATTESTASTION ENDPOINT IMPL ====
 
 /// Response for get attestation.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct GetAttestationResponse {
     /// Attestation document serialized in Hex.
     pub attestation: String,
@@ -95,28 +227,223 @@ pub struct GetAttestationResponse {
 
 /// Endpoint that returns an attestation committed
 /// to the enclave's public key.
+#[utoipa::path(
+    get,
+    path = "/get_attestation",
+    tag = "attestation",
+    responses(
+        (status = 200, body = GetAttestationResponse),
+        (status = 400, body = crate::ErrorResponse),
+    )
+)]
 pub async fn get_attestation(
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<GetAttestationResponse>, EnclaveError> {
     info!("get attestation called");
 
-    let pk = state.eph_kp.public();
+    let now = current_timestamp_ms()?;
+    let kp = state.signing_keys.active_at(now);
+    let public_key = kp.public().as_bytes();
+    let document = match state.attestation_cache.get(public_key, now) {
+        Some(document) => document,
+        None => attestation_document(public_key, None)?,
+    };
+    Ok(Json(GetAttestationResponse {
+        attestation: Hex::encode(document),
+    }))
+}
+
+/// `[attestation]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Attestation {
+    /// How long a document from POST /attestation/refresh is served by
+    /// GET /get_attestation
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+}
+
+impl Default for Attestation {
+    fn default() -> Self {
+        Self {
+            cache_ttl_secs: default_cache_ttl_secs(),
+        }
+    }
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    3 * 60 * 60
+}
+
+/// An attestation document and when it stops being served.
+#[derive(Debug, Clone)]
+struct CachedAttestation {
+    document: Vec<u8>,
+    public_key: Vec<u8>,
+    expires_at_ms: u64,
+}
+
+/// The last refreshed attestation document.
+#[derive(Default)]
+pub struct AttestationCache(Mutex<Option<CachedAttestation>>);
+
+impl AttestationCache {
+    /// Replace the cached document.
+    pub fn store(&self, document: Vec<u8>, public_key: &[u8], expires_at_ms: u64) {
+        *self.0.lock().expect("attestation cache lock poisoned") = Some(CachedAttestation {
+            document,
+            public_key: public_key.to_vec(),
+            expires_at_ms,
+        });
+    }
+
+    /// The cached document if it is committed to `public_key` and has not
+    /// expired at `now_ms`.
+    pub fn get(&self, public_key: &[u8], now_ms: u64) -> Option<Vec<u8>> {
+        let cached = self.0.lock().expect("attestation cache lock poisoned");
+        cached
+            .as_ref()
+            .filter(|c| c.public_key == public_key && now_ms < c.expires_at_ms)
+            .map(|c| c.document.clone())
+    }
+
+    /// Drop the cached document.
+    pub fn clear(&self) {
+        *self.0.lock().expect("attestation cache lock poisoned") = None;
+    }
+}
+
+/// Response for attestation refresh.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RefreshAttestationResponse {
+    /// Attestation document in Base64.
+    pub attestation: String,
+    /// Public key the document is committed to, in Hex.
+    pub public_key: String,
+    /// Random nonce embedded in the document, in Hex.
+    pub nonce: String,
+    /// Until when GET /get_attestation serves this document.
+    pub expires_at_ms: u64,
+}
+
+/// Endpoint that requests a new attestation document from the NSM, with a
+/// random nonce and the current public key, and caches it for
+/// `attestation.cache_ttl_secs` so verifiers requiring a recent document can
+/// be pointed at GET /get_attestation.
+#[utoipa::path(
+    post,
+    path = "/attestation/refresh",
+    tag = "attestation",
+    responses(
+        (status = 200, body = RefreshAttestationResponse),
+        (status = 400, body = crate::ErrorResponse),
+    )
+)]
+pub async fn refresh_attestation(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<RefreshAttestationResponse>, EnclaveError> {
+    info!("attestation refresh called");
+
+    let now = current_timestamp_ms()?;
+    let kp = state.signing_keys.active_at(now);
+    let public_key = kp.public().as_bytes();
+    let nonce: [u8; 32] = rand::random();
+    let document = attestation_document(public_key, Some(&nonce))?;
+
+    let ttl_ms = state.config.load().attestation.cache_ttl_secs.saturating_mul(1000);
+    let expires_at_ms = now.saturating_add(ttl_ms);
+    state.attestation_cache.store(document.clone(), public_key, expires_at_ms);
+    Ok(Json(RefreshAttestationResponse {
+        attestation: Base64::encode(document),
+        public_key: Hex::encode(public_key),
+        nonce: Hex::encode(nonce),
+        expires_at_ms,
+    }))
+}
+
+/// Largest nonce the NSM accepts in an attestation request.
+pub const MAX_NONCE_BYTES: usize = 512;
+
+/// Query parameters for the fresh attestation endpoint.
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct AttestationQuery {
+    /// Verifier challenge in Hex, embedded in the document.
+    pub nonce: String,
+    /// Attest to the key of this intent, from `key.scoped_intents`, rather
+    /// than the main key.
+    pub intent_scope: Option<u8>,
+}
+
+/// Response for fresh attestation.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AttestationResponse {
+    /// Attestation document in Base64.
+    pub attestation: String,
+    /// Public key the document is committed to, in Hex.
+    pub public_key: String,
+}
+
+/// Endpoint that returns a freshly generated attestation committed to the
+/// current public key and to the verifier's nonce, so the verifier knows the
+/// document was produced after its challenge.
+#[utoipa::path(
+    get,
+    path = "/attestation",
+    tag = "attestation",
+    params(AttestationQuery),
+    responses(
+        (status = 200, body = AttestationResponse),
+        (status = 400, description = "Invalid nonce", body = crate::ErrorResponse),
+    )
+)]
+pub async fn attestation(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AttestationQuery>,
+) -> Result<Json<AttestationResponse>, EnclaveError> {
+    info!("attestation called");
+
+    let nonce = Hex::decode(&query.nonce)
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid nonce: {}", e)))?;
+    if nonce.is_empty() || nonce.len() > MAX_NONCE_BYTES {
+        return Err(EnclaveError::GenericError(format!(
+            "Nonce must be 1 to {} bytes, got {}",
+            MAX_NONCE_BYTES,
+            nonce.len()
+        )));
+    }
+
+    let kp = match query.intent_scope {
+        Some(intent) => state
+            .signing_keys
+            .scoped_key(intent)
+            .map(|(_, kp)| kp)
+            .ok_or_else(|| EnclaveError::GenericError(format!("Intent {} has no key of its own", intent)))?,
+        None => state.signing_keys.active_at(current_timestamp_ms()?),
+    };
+    let public_key = kp.public().as_bytes();
+    let document = attestation_document(public_key, Some(&nonce))?;
+    Ok(Json(AttestationResponse {
+        attestation: Base64::encode(document),
+        public_key: Hex::encode(public_key),
+    }))
+}
+
+/// Request an attestation document from the NSM committed to `public_key`
+/// and, if given, to `nonce`.
+pub fn attestation_document(public_key: &[u8], nonce: Option<&[u8]>) -> Result<Vec<u8>, EnclaveError> {
     let fd = driver::nsm_init();
 
     // Send attestation request to NSM driver with public key set.
     let request = NsmRequest::Attestation {
         user_data: None,
-        nonce: None,
-        public_key: Some(ByteBuf::from(pk.as_bytes().to_vec())),
+        nonce: nonce.map(|nonce| ByteBuf::from(nonce.to_vec())),
+        public_key: Some(ByteBuf::from(public_key.to_vec())),
     };
 
     let response = driver::nsm_process_request(fd, request);
     match response {
         NsmResponse::Attestation { document } => {
             driver::nsm_exit(fd);
-            Ok(Json(GetAttestationResponse {
-                attestation: Hex::encode(document),
-            }))
+            Ok(document)
         }
         _ => {
             driver::nsm_exit(fd);
@@ -128,12 +455,18 @@ pub async fn get_attestation(
 }
 
 /// Health check response.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct HealthCheckResponse {
     pub status: String,
 }
 
 /// Simple health check endpoint that returns 200 status.
+#[utoipa::path(
+    get,
+    path = "/health_check",
+    tag = "health",
+    responses((status = 200, body = HealthCheckResponse))
+)]
 pub async fn health_check() -> Result<Json<HealthCheckResponse>, EnclaveError> {
     Ok(Json(HealthCheckResponse {
         status: "ok".to_string(),
@@ -1,40 +1,983 @@
 use anyhow::{Context, Result};
+use clap::Parser;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use tracing::{error, info};
+use std::sync::OnceLock;
+use tracing::{info, warn};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+use crate::admin::Admin;
+use crate::assets::{self, AssetMetadata};
+use crate::backup::KeyBackup;
+use crate::batch::Batch;
+use crate::breaker::CircuitBreaker;
+use crate::capture::Capture;
+use crate::common::{Attestation, IntentScope, RoundingMode, TimestampSource};
+use crate::consumers::Consumers;
+use crate::conversion::{self, Conversion};
+use crate::definition::ExtractionExample;
+use crate::encoding::Encoding;
+use crate::expression::Scale;
+use crate::fees::Fees;
+use crate::formats::UpstreamFormat;
+use crate::handoff::Handoff;
+use crate::headers::{Cors, SecurityHeaders};
+use crate::health::Health;
+use crate::hmac_auth::HmacSigning;
+use crate::light_client::LightClient;
+use crate::limits::UpstreamLimits;
+use crate::market_hours::{MarketHours, WhenClosed};
+use crate::migrate::migrate;
+use crate::mirrors::{self, Mirror, Mirrors};
+use crate::nft::{self, NftMarketplace};
+use crate::oauth::OAuth2;
+use crate::outbound::{HttpClient, OutboundRoute};
+use crate::pause::Pause;
+use crate::payments::Payments;
+use crate::persistence::{Backend, Persistence};
+use crate::push::Push;
+use crate::refresher::Refresher;
+use crate::reserves::Reserves;
+use crate::secrets::Secrets;
+use crate::sports::{self, SportsProvider};
+use crate::telemetry::Telemetry;
+use crate::transform::Transform;
+use crate::upstream_circuit::UpstreamCircuit;
+use crate::weather::Weather;
+
+/// Server configuration. Values are layered with increasing precedence:
+/// built-in defaults, the TOML file at `CONFIG_PATH`, `NAUTILUS_*`
+/// environment variables and finally command line flags.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct Config {
+    #[serde(default)]
     pub sui: Sui,
+    #[serde(default)]
     pub response: Response,
+    #[serde(default)]
+    pub runtime: Runtime,
+    #[serde(default)]
+    pub server: Server,
+    #[serde(default)]
+    pub key: KeyConfig,
+    #[serde(default)]
+    pub key_backup: KeyBackup,
+    #[serde(default)]
+    pub admin: Admin,
+    #[serde(default)]
+    pub attestation: Attestation,
+    #[serde(default)]
+    pub persistence: Persistence,
+    #[serde(default)]
+    pub handoff: Handoff,
+    #[serde(default)]
+    pub fees: Fees,
+    #[serde(default)]
+    pub payments: Payments,
+    #[serde(default)]
+    pub circuit_breaker: CircuitBreaker,
+    #[serde(default)]
+    pub telemetry: Telemetry,
+    #[serde(default)]
+    pub upstream_limits: UpstreamLimits,
+    #[serde(default)]
+    pub upstream_circuit: UpstreamCircuit,
+    #[serde(default)]
+    pub weather: Weather,
+    #[serde(default)]
+    pub reserves: Reserves,
+    #[serde(default)]
+    pub pause: Pause,
+    #[serde(default)]
+    pub consumers: Consumers,
+    #[serde(default)]
+    pub batch: Batch,
+    #[serde(default)]
+    pub health: Health,
+    #[serde(default)]
+    pub mirrors: Mirrors,
+    #[serde(default)]
+    pub refresher: Refresher,
+    #[serde(default)]
+    pub push: Push,
+    #[serde(default)]
+    pub capture: Capture,
+    #[serde(default)]
+    pub secrets: Secrets,
+    #[serde(default)]
+    pub cors: Cors,
+    #[serde(default)]
+    pub security_headers: SecurityHeaders,
+    #[serde(default)]
+    pub encoding: Encoding,
+    /// Off-chain settings for individual price feeds, keyed by price feed id
+    #[serde(default)]
+    pub feeds: HashMap<String, FeedConfig>,
+    /// Asset metadata registry, keyed by canonical symbol
+    #[serde(default)]
+    pub assets: HashMap<String, AssetMetadata>,
+    /// Sports APIs fixture results are read from, keyed by provider name
+    #[serde(default)]
+    pub sports: HashMap<String, SportsProvider>,
+    /// Marketplace APIs NFT floor prices are read from, keyed by name
+    #[serde(default)]
+    pub nft: HashMap<String, NftMarketplace>,
+    /// Destinations reached through a proxy on the parent instance over vsock
+    #[serde(default)]
+    pub outbound: Vec<OutboundRoute>,
+    #[serde(default)]
+    pub http_client: HttpClient,
+}
+
+/// Per-feed settings that complement the on-chain PriceFeed object.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct FeedConfig {
+    /// Trading calendar; outside market hours the last close is served with
+    /// the market closed intent instead of a fresh tick
+    #[serde(default)]
+    pub market_hours: Option<MarketHours>,
+    /// Whether to serve the last close or refuse while `market_hours` says
+    /// the market is closed
+    #[serde(default)]
+    pub when_closed: WhenClosed,
+    /// Symbol or alias of the asset the feed prices, whose decimals and tick
+    /// size are applied to the extracted price
+    #[serde(default)]
+    pub asset: Option<String>,
+    /// Fee in MIST for a standard priority request, instead of
+    /// `fees.base_fee_mist`
+    #[serde(default)]
+    pub fee_mist: Option<u64>,
+    /// Sample upstream response checked against the on-chain
+    /// `response_field` whenever the feed is loaded; a mismatch refuses signing
+    #[serde(default)]
+    pub example: Option<ExtractionExample>,
+    /// Circuit breaker threshold in basis points, instead of
+    /// `circuit_breaker.max_deviation_bps`
+    #[serde(default)]
+    pub max_deviation_bps: Option<u64>,
+    /// Path of the upstream timestamp, for feeds whose on-chain object has no
+    /// `timestamp_field`
+    #[serde(default)]
+    pub timestamp_field: Option<String>,
+    /// Freshness window for the upstream timestamp, instead of
+    /// `response.max_staleness_secs`
+    #[serde(default)]
+    pub max_staleness_secs: Option<u64>,
+    /// Where the timestamp of signed prices is taken from
+    #[serde(default)]
+    pub timestamp_source: TimestampSource,
+    /// Weekly windows, in the same format as `market_hours`, outside of which
+    /// the feed refuses to sign at all, e.g. for settlement oracles
+    #[serde(default)]
+    pub signing_windows: Option<MarketHours>,
+    /// Expression turning the extracted `response_field` into the price,
+    /// e.g. `value * 10^expo` for mantissa and exponent pairs
+    #[serde(default)]
+    pub scale: Option<Scale>,
+    /// Refuse to sign for this feed until unset and the config reloaded
+    #[serde(default)]
+    pub paused: bool,
+    /// Alternative upstreams compared against the primary by the mirror
+    /// comparison job, see `[mirrors]`
+    #[serde(default)]
+    pub mirrors: Vec<Mirror>,
+    /// `response_format` of the upstream and, for CSV, the `csv` selectors
+    #[serde(flatten)]
+    pub format: UpstreamFormat,
+    /// WASM module computing the price from the upstream document instead of
+    /// `response_field`, pinned by the on-chain `transform_hash`
+    #[serde(default)]
+    pub transform: Option<Transform>,
+    /// Conversion of a price quoted in another currency at an FX feed's rate
+    #[serde(default)]
+    pub convert: Option<Conversion>,
+    /// How the scaled price is rounded to an integer. Unset truncates, as
+    /// before, and signs no rounding mode
+    #[serde(default)]
+    pub rounding: Option<RoundingMode>,
+    /// Static headers sent with every upstream request of the feed, e.g. an
+    /// `Accept` or partner header the provider requires
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// User-Agent of the feed's upstream requests; none is sent if unset
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// Signing of upstream requests, for feeds whose on-chain
+    /// `api_key_config` is `hmac-sha256`
+    #[serde(default)]
+    pub hmac: Option<HmacSigning>,
+    /// Token endpoint and client secret, for feeds whose on-chain
+    /// `api_key_config` is `oauth2`
+    #[serde(default)]
+    pub oauth2: Option<OAuth2>,
+}
+
+impl FeedConfig {
+    /// Check `headers` and `user_agent` are valid HTTP, returning the
+    /// problems found.
+    fn validate_headers(&self, feed_id: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, value) in &self.headers {
+            if reqwest::header::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                problems.push(format!("feeds.{}.headers: '{}' is not a valid header name", feed_id, name));
+            }
+            if reqwest::header::HeaderValue::from_str(value).is_err() {
+                problems.push(format!("feeds.{}.headers.{} is not a valid header value", feed_id, name));
+            }
+        }
+        if let Some(user_agent) = &self.user_agent {
+            if reqwest::header::HeaderValue::from_str(user_agent).is_err() {
+                problems.push(format!("feeds.{}.user_agent is not a valid header value", feed_id));
+            }
+        }
+        problems
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Sui {
     pub rpc_url: String,
     pub oracle_builder_package_id: String,
+    /// Further package ids whose object types are accepted, e.g. the
+    /// original package of an upgraded oracle_builder, whose objects keep
+    /// the types it defined
+    #[serde(default)]
+    pub accepted_package_ids: Vec<String>,
+    /// Most RPC calls sent a second, unlimited if unset
+    #[serde(default)]
+    pub requests_per_sec: Option<u32>,
+    /// Retries of an RPC call the fullnode rate limits, after its
+    /// `Retry-After` or a doubling backoff
+    #[serde(default = "default_rate_limit_retries")]
+    pub rate_limit_retries: u32,
+    /// Fetch the oracle_builder package over RPC at startup to catch a wrong
+    /// RPC URL or package id before serving requests
+    #[serde(default)]
+    pub self_test: bool,
+    /// WebSocket endpoint of the fullnode, subscribed to for transactions of
+    /// the oracle_builder package to drop signed prices of feeds they touch
+    #[serde(default)]
+    pub ws_url: Option<String>,
+    /// Verify PriceFeed objects against certified checkpoints
+    #[serde(default)]
+    pub light_client: LightClient,
+}
+
+impl Default for Sui {
+    fn default() -> Self {
+        Self {
+            rpc_url: "https://fullnode.testnet.sui.io:443".to_string(),
+            oracle_builder_package_id: String::new(),
+            accepted_package_ids: Vec::new(),
+            requests_per_sec: None,
+            rate_limit_retries: default_rate_limit_retries(),
+            self_test: false,
+            ws_url: None,
+            light_client: LightClient::default(),
+        }
+    }
+}
+
+fn default_rate_limit_retries() -> u32 {
+    3
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Response {
     pub price_decimals: u32,
+    /// Sign the change versus the previous observation of the feed along with
+    /// the price, under the `PriceFeedWithChange` intent
+    #[serde(default)]
+    pub include_rate_of_change: bool,
+    /// Extract and scale every price a second time through an independent
+    /// code path and only sign if both agree
+    #[serde(default)]
+    pub verify_extraction: bool,
+    /// Oldest upstream timestamp accepted, in seconds, for feeds with a
+    /// `timestamp_field`
+    #[serde(default = "default_max_staleness_secs")]
+    pub max_staleness_secs: u64,
+    /// Sign the version and digest of the PriceFeed object the price was
+    /// fetched with, as `feed_object`
+    #[serde(default)]
+    pub include_feed_object: bool,
+}
+
+impl Default for Response {
+    fn default() -> Self {
+        Self {
+            price_decimals: 8,
+            include_rate_of_change: false,
+            verify_extraction: false,
+            max_staleness_secs: default_max_staleness_secs(),
+            include_feed_object: false,
+        }
+    }
+}
+
+fn default_max_staleness_secs() -> u64 {
+    60
+}
+
+/// How the enclave signing key is obtained on startup.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyMode {
+    /// Generate a fresh keypair on every boot
+    #[default]
+    Ephemeral,
+    /// Seal the keypair to disk and restore it on restart
+    Sealed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct KeyConfig {
+    #[serde(default)]
+    pub mode: KeyMode,
+    /// Where the sealed keypair is stored in `sealed` mode
+    #[serde(default = "default_sealed_key_path")]
+    pub sealed_key_path: String,
+    /// Environment variable holding the hex encoded 32 byte sealing key
+    #[serde(default = "default_sealing_key_env")]
+    pub sealing_key_env: String,
+    /// How long the old key keeps signing after a rotation, giving time to
+    /// register the new key on chain
+    #[serde(default = "default_rotation_grace_secs")]
+    pub rotation_grace_secs: u64,
+    /// Intents signed with a keypair of their own rather than the main one
+    #[serde(default)]
+    pub scoped_intents: Vec<IntentScope>,
+}
+
+impl Default for KeyConfig {
+    fn default() -> Self {
+        Self {
+            mode: KeyMode::default(),
+            sealed_key_path: default_sealed_key_path(),
+            sealing_key_env: default_sealing_key_env(),
+            rotation_grace_secs: default_rotation_grace_secs(),
+            scoped_intents: Vec::new(),
+        }
+    }
+}
+
+fn default_sealed_key_path() -> String {
+    "/data/sealed_key".to_string()
+}
+
+fn default_sealing_key_env() -> String {
+    "NAUTILUS_SEALING_KEY".to_string()
+}
+
+fn default_rotation_grace_secs() -> u64 {
+    600
+}
+
+/// Largest supported `price_decimals`; 10^19 no longer fits in a u64.
+pub const MAX_PRICE_DECIMALS: u32 = 18;
+
+/// Socket family the HTTP server listens on.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "snake_case")]
+pub enum ListenerKind {
+    /// TCP on `address`
+    #[default]
+    Tcp,
+    /// AF_VSOCK on `vsock_cid` and `vsock_port`
+    Vsock,
+}
+
+/// Where the HTTP server listens. Only read at startup.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Server {
+    #[serde(default)]
+    pub listener: ListenerKind,
+    /// TCP address to bind in `tcp` mode
+    #[serde(default = "default_address")]
+    pub address: String,
+    /// Context id to bind in `vsock` mode, any by default
+    #[serde(default = "default_vsock_cid")]
+    pub vsock_cid: u32,
+    #[serde(default = "default_vsock_port")]
+    pub vsock_port: u32,
+    /// Terminate TLS in the enclave instead of serving plain HTTP
+    #[serde(default)]
+    pub tls: Option<Tls>,
+    /// On SIGTERM or SIGINT, how long open connections get to finish their
+    /// in-flight requests before the server exits
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+}
+
+/// PEM files for the HTTPS listener.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tls {
+    /// Certificate chain, leaf first
+    pub cert_path: String,
+    pub key_path: String,
+    /// CA certificates clients must present a certificate from (mTLS)
+    #[serde(default)]
+    pub client_ca_path: Option<String>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self {
+            listener: ListenerKind::default(),
+            address: default_address(),
+            vsock_cid: default_vsock_cid(),
+            vsock_port: default_vsock_port(),
+            tls: None,
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+        }
+    }
+}
+
+fn default_address() -> String {
+    "0.0.0.0:3000".to_string()
+}
+
+/// VMADDR_CID_ANY
+fn default_vsock_cid() -> u32 {
+    u32::MAX
+}
+
+fn default_vsock_port() -> u32 {
+    3000
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+/// Tokio runtime and server tuning. Unset values fall back to tokio/axum defaults.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Runtime {
+    /// Number of async worker threads, defaults to the number of vCPUs
+    pub worker_threads: Option<usize>,
+    /// Upper bound on threads in the blocking pool (default 512)
+    pub max_blocking_threads: Option<usize>,
+    /// Maximum number of connections open at once; further connections are
+    /// not accepted until one closes
+    pub max_connections: Option<usize>,
+}
+
+impl Runtime {
+    /// Build a multi-threaded tokio runtime using the configured parameters.
+    pub fn build(&self) -> Result<tokio::runtime::Runtime> {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        builder.enable_all();
+        if let Some(worker_threads) = self.worker_threads {
+            builder.worker_threads(worker_threads);
+        }
+        if let Some(max_blocking_threads) = self.max_blocking_threads {
+            builder.max_blocking_threads(max_blocking_threads);
+        }
+        builder.build().context("Failed to build tokio runtime")
+    }
+}
+
+impl Config {
+    /// Settings for `price_feed_id`, or defaults if it has no section.
+    pub fn feed(&self, price_feed_id: &str) -> FeedConfig {
+        self.feeds.get(price_feed_id).cloned().unwrap_or_default()
+    }
+
+    /// Metadata of the asset `price_feed_id` prices, if it names one.
+    pub fn feed_asset(&self, price_feed_id: &str) -> Option<&AssetMetadata> {
+        let symbol = self.feeds.get(price_feed_id)?.asset.as_ref()?;
+        assets::resolve(&self.assets, symbol).map(|(_, asset)| asset)
+    }
+
+    /// Check the config for problems that would otherwise only surface on the
+    /// first request. All problems are reported together.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        if let Err(e) = check_http_url(&self.sui.rpc_url) {
+            problems.push(format!("sui.rpc_url {}", e));
+        }
+        if let Some(ws_url) = &self.sui.ws_url {
+            if let Err(e) = check_ws_url(ws_url) {
+                problems.push(format!("sui.ws_url {}", e));
+            }
+        }
+
+        if !is_valid_object_id(&self.sui.oracle_builder_package_id) {
+            problems.push(format!(
+                "sui.oracle_builder_package_id '{}' must be 0x followed by 1 to 64 hex digits",
+                self.sui.oracle_builder_package_id
+            ));
+        }
+        if self.sui.requests_per_sec == Some(0) {
+            problems.push("sui.requests_per_sec must be at least 1".to_string());
+        }
+        for package_id in &self.sui.accepted_package_ids {
+            if !is_valid_object_id(package_id) {
+                problems.push(format!(
+                    "sui.accepted_package_ids entry '{}' must be 0x followed by 1 to 64 hex digits",
+                    package_id
+                ));
+            }
+        }
+
+        if self.response.price_decimals > MAX_PRICE_DECIMALS {
+            problems.push(format!(
+                "response.price_decimals must be at most {}, got {}",
+                MAX_PRICE_DECIMALS, self.response.price_decimals
+            ));
+        }
+
+        if self.weather.value_decimals > MAX_PRICE_DECIMALS {
+            problems.push(format!(
+                "weather.value_decimals must be at most {}, got {}",
+                MAX_PRICE_DECIMALS, self.weather.value_decimals
+            ));
+        }
+
+        if self.server.listener == ListenerKind::Tcp
+            && self.server.address.parse::<std::net::SocketAddr>().is_err()
+        {
+            problems.push(format!(
+                "server.address '{}' must be an IP address and port",
+                self.server.address
+            ));
+        }
+
+        if let Some(tls) = &self.server.tls {
+            for (name, path) in [
+                ("server.tls.cert_path", Some(&tls.cert_path)),
+                ("server.tls.key_path", Some(&tls.key_path)),
+                ("server.tls.client_ca_path", tls.client_ca_path.as_ref()),
+            ] {
+                if let Some(path) = path {
+                    if !std::path::Path::new(path).is_file() {
+                        problems.push(format!("{} '{}' does not exist", name, path));
+                    }
+                }
+            }
+        }
+
+        for (name, value) in [
+            ("runtime.worker_threads", self.runtime.worker_threads),
+            ("runtime.max_blocking_threads", self.runtime.max_blocking_threads),
+            ("runtime.max_connections", self.runtime.max_connections),
+        ] {
+            if value == Some(0) {
+                problems.push(format!("{} must be greater than 0", name));
+            }
+        }
+
+        if let Some(source_url) = &self.handoff.source_url {
+            if let Err(e) = check_http_url(source_url) {
+                problems.push(format!("handoff.source_url {}", e));
+            }
+            if self.handoff.allowed_pcr0.is_empty() {
+                problems.push("handoff.allowed_pcr0 must list the source's image".to_string());
+            }
+        }
+
+        if self.payments.required && self.payments.receipt_type.is_empty() {
+            problems.push("payments.receipt_type must be set when payments are required".to_string());
+        }
+
+        if let Some(endpoint) = &self.telemetry.otlp_endpoint {
+            if let Err(e) = check_http_url(endpoint) {
+                problems.push(format!("telemetry.otlp_endpoint {}", e));
+            }
+        }
+        if self.telemetry.metrics_interval_secs == 0 {
+            problems.push("telemetry.metrics_interval_secs must be greater than 0".to_string());
+        }
+
+        if self.upstream_limits.max_body_bytes == 0 {
+            problems.push("upstream_limits.max_body_bytes must be greater than 0".to_string());
+        }
+        if self.upstream_limits.max_decompressed_bytes == 0 {
+            problems.push("upstream_limits.max_decompressed_bytes must be greater than 0".to_string());
+        }
+        if self.upstream_limits.max_depth == 0 {
+            problems.push("upstream_limits.max_depth must be greater than 0".to_string());
+        }
+
+        if self.persistence.history_capacity == 0 {
+            problems.push("persistence.history_capacity must be greater than 0".to_string());
+        }
+        if self.persistence.backend != Backend::Memory && self.persistence.path.is_empty() {
+            problems.push("persistence.path must be set for durable backends".to_string());
+        }
+
+        for (i, route) in self.outbound.iter().enumerate() {
+            if route.host.is_empty() {
+                problems.push(format!("outbound[{}].host must not be empty", i));
+            }
+            if route.vsock_port == 0 {
+                problems.push(format!("outbound[{}].vsock_port must be greater than 0", i));
+            }
+        }
+        let http_client = &self.http_client;
+        if http_client.timeout_secs > 0 && http_client.connect_timeout_secs > http_client.timeout_secs {
+            problems.push("http_client.connect_timeout_secs must not exceed timeout_secs".to_string());
+        }
+
+        for (feed_id, feed) in &self.feeds {
+            if let Some(market_hours) = &feed.market_hours {
+                if let Err(e) = market_hours.validate() {
+                    problems.push(format!("feeds.{}.market_hours: {}", feed_id, e));
+                }
+            }
+            if let Some(signing_windows) = &feed.signing_windows {
+                if let Err(e) = signing_windows.validate() {
+                    problems.push(format!("feeds.{}.signing_windows: {}", feed_id, e));
+                }
+            }
+            if let Some(scale) = &feed.scale {
+                if let Err(e) = scale.validate() {
+                    problems.push(format!("feeds.{}.scale: {}", feed_id, e));
+                }
+            }
+            if let Some(example) = &feed.example {
+                if let Err(e) = feed.format.parse(&self.upstream_limits, example.body.as_bytes()) {
+                    problems.push(format!("feeds.{}.example.body: {}", feed_id, e));
+                }
+            }
+            problems.extend(feed.format.validate(&format!("feeds.{}", feed_id)));
+            if let Some(transform) = &feed.transform {
+                problems.extend(transform.validate(&format!("feeds.{}", feed_id)));
+            }
+            if let Some(asset) = &feed.asset {
+                if assets::resolve(&self.assets, asset).is_none() {
+                    problems.push(format!("feeds.{}.asset '{}' is not in the registry", feed_id, asset));
+                }
+            }
+            problems.extend(mirrors::validate(feed_id, &feed.mirrors));
+            problems.extend(feed.validate_headers(feed_id));
+            if let Some(hmac) = &feed.hmac {
+                problems.extend(hmac.validate(feed_id));
+            }
+            if let Some(oauth2) = &feed.oauth2 {
+                problems.extend(oauth2.validate(feed_id));
+            }
+        }
+
+        problems.extend(conversion::validate(&self.feeds));
+        problems.extend(assets::validate(&self.assets));
+        problems.extend(sports::validate(&self.sports));
+        problems.extend(nft::validate(&self.nft));
+        problems.extend(self.consumers.validate());
+        problems.extend(self.key_backup.validate());
+        problems.extend(self.admin.validate());
+        problems.extend(self.refresher.validate());
+        problems.extend(self.push.validate());
+        problems.extend(self.sui.light_client.validate());
+        problems.extend(self.secrets.validate());
+        problems.extend(self.upstream_circuit.validate());
+        problems.extend(self.cors.validate());
+        problems.extend(self.security_headers.validate());
+
+        if self.batch.max_items == 0 {
+            problems.push("batch.max_items must be at least 1".to_string());
+        }
+        if self.health.probe_interval_secs > 0 && self.health.probe_timeout_secs == 0 {
+            problems.push("health.probe_timeout_secs must be at least 1".to_string());
+        }
+        if self.mirrors.window == 0 {
+            problems.push("mirrors.window must be at least 1".to_string());
+        }
+        if self.mirrors.chronic_bps > 10_000 {
+            problems.push("mirrors.chronic_bps must be at most 10000".to_string());
+        }
+        if self.capture.responses_per_feed > 0 && self.capture.max_body_bytes == 0 {
+            problems.push("capture.max_body_bytes must be at least 1".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!(
+                "Invalid config:\n  - {}",
+                problems.join("\n  - ")
+            ))
+        }
+    }
+}
+
+/// Check that `url` parses and uses the http or https scheme.
+pub fn check_http_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("'{}' is not a valid URL: {}", url, e))?;
+    match parsed.scheme() {
+        "http" | "https" => Ok(()),
+        scheme => Err(format!("'{}' must be http or https, got scheme '{}'", url, scheme)),
+    }
+}
+
+/// Check that `url` parses and uses the ws or wss scheme.
+pub fn check_ws_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("'{}' is not a valid URL: {}", url, e))?;
+    match parsed.scheme() {
+        "ws" | "wss" => Ok(()),
+        scheme => Err(format!("'{}' must be ws or wss, got scheme '{}'", url, scheme)),
+    }
+}
+
+/// Whether `id` looks like a Sui object or package id.
+pub fn is_valid_object_id(id: &str) -> bool {
+    match id.strip_prefix("0x") {
+        Some(hex) => !hex.is_empty() && hex.len() <= 64 && hex.chars().all(|c| c.is_ascii_hexdigit()),
+        None => false,
+    }
+}
+
+/// Overrides taken from command line flags, falling back to the matching
+/// environment variable when a flag is not given.
+#[derive(Debug, Clone, Default, Parser)]
+#[command(about = "Nautilus oracle enclave server")]
+pub struct Overrides {
+    /// Path of the TOML config file
+    #[arg(long = "config", env = "CONFIG_PATH")]
+    pub config_path: Option<String>,
+    /// Sui fullnode RPC URL
+    #[arg(long, env = "NAUTILUS_SUI_RPC_URL")]
+    pub sui_rpc_url: Option<String>,
+    /// Package id of the oracle_builder Move package
+    #[arg(long, env = "NAUTILUS_SUI_ORACLE_BUILDER_PACKAGE_ID")]
+    pub sui_oracle_builder_package_id: Option<String>,
+    /// Check the Sui RPC and package at startup
+    #[arg(long, env = "NAUTILUS_SUI_SELF_TEST")]
+    pub sui_self_test: Option<bool>,
+    /// Number of decimals prices are scaled by
+    #[arg(long, env = "NAUTILUS_RESPONSE_PRICE_DECIMALS")]
+    pub response_price_decimals: Option<u32>,
+    /// Include the change versus the previous observation in signed payloads
+    #[arg(long, env = "NAUTILUS_RESPONSE_INCLUDE_RATE_OF_CHANGE")]
+    pub response_include_rate_of_change: Option<bool>,
+    /// Cross-check every extracted price with an independent extractor
+    #[arg(long, env = "NAUTILUS_RESPONSE_VERIFY_EXTRACTION")]
+    pub response_verify_extraction: Option<bool>,
+    /// How the signing key is obtained on startup
+    #[arg(long, env = "NAUTILUS_KEY_MODE", value_enum)]
+    pub key_mode: Option<KeyMode>,
+    /// Socket family the server listens on
+    #[arg(long, env = "NAUTILUS_SERVER_LISTENER", value_enum)]
+    pub server_listener: Option<ListenerKind>,
+    /// TCP address to listen on
+    #[arg(long, env = "NAUTILUS_SERVER_ADDRESS")]
+    pub server_address: Option<String>,
+    /// Vsock port to listen on
+    #[arg(long, env = "NAUTILUS_SERVER_VSOCK_PORT")]
+    pub server_vsock_port: Option<u32>,
+    /// Number of async worker threads
+    #[arg(long, env = "NAUTILUS_RUNTIME_WORKER_THREADS")]
+    pub runtime_worker_threads: Option<usize>,
+    /// Upper bound on threads in the blocking pool
+    #[arg(long, env = "NAUTILUS_RUNTIME_MAX_BLOCKING_THREADS")]
+    pub runtime_max_blocking_threads: Option<usize>,
+    /// Maximum number of connections served concurrently
+    #[arg(long, env = "NAUTILUS_RUNTIME_MAX_CONNECTIONS")]
+    pub runtime_max_connections: Option<usize>,
+}
+
+impl Overrides {
+    /// Apply every override that is set on top of `config`.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(rpc_url) = &self.sui_rpc_url {
+            config.sui.rpc_url = rpc_url.clone();
+        }
+        if let Some(package_id) = &self.sui_oracle_builder_package_id {
+            config.sui.oracle_builder_package_id = package_id.clone();
+        }
+        if let Some(self_test) = self.sui_self_test {
+            config.sui.self_test = self_test;
+        }
+        if let Some(price_decimals) = self.response_price_decimals {
+            config.response.price_decimals = price_decimals;
+        }
+        if let Some(include) = self.response_include_rate_of_change {
+            config.response.include_rate_of_change = include;
+        }
+        if let Some(verify) = self.response_verify_extraction {
+            config.response.verify_extraction = verify;
+        }
+        if let Some(key_mode) = self.key_mode {
+            config.key.mode = key_mode;
+        }
+        if let Some(listener) = self.server_listener {
+            config.server.listener = listener;
+        }
+        if let Some(address) = &self.server_address {
+            config.server.address = address.clone();
+        }
+        if let Some(vsock_port) = self.server_vsock_port {
+            config.server.vsock_port = vsock_port;
+        }
+        if let Some(worker_threads) = self.runtime_worker_threads {
+            config.runtime.worker_threads = Some(worker_threads);
+        }
+        if let Some(max_blocking_threads) = self.runtime_max_blocking_threads {
+            config.runtime.max_blocking_threads = Some(max_blocking_threads);
+        }
+        if let Some(max_connections) = self.runtime_max_connections {
+            config.runtime.max_connections = Some(max_connections);
+        }
+    }
+}
+
+static OVERRIDES: OnceLock<Overrides> = OnceLock::new();
+
+/// Overrides for this process, parsed from the command line once.
+fn overrides() -> &'static Overrides {
+    OVERRIDES.get_or_init(Overrides::parse)
+}
+
+/// Path of the config file, from `--config` or the `CONFIG_PATH` environment variable.
+pub fn config_path() -> Option<String> {
+    overrides().config_path.clone()
 }
 
 pub fn load_config() -> Result<Config> {
-    let config_path = std::env::var("CONFIG_PATH").map_err(|_| {
-        let error_msg = "CONFIG_PATH environment variable is not set";
-        error!("{}", error_msg);
-        anyhow::anyhow!(error_msg)
-    })?;
+    load_config_with(overrides())
+}
+
+/// Load the config file named by `overrides` (if any) and apply the overrides on top.
+pub fn load_config_with(overrides: &Overrides) -> Result<Config> {
+    let mut config = match &overrides.config_path {
+        Some(config_path) => {
+            info!("Loading config from: {}", config_path);
 
-    info!("Loading config from: {}", config_path);
+            let config_content = fs::read_to_string(config_path)
+                .with_context(|| format!("Failed to read config file at: {}", config_path))?;
 
-    let config_content = fs::read_to_string(&config_path)
-        .with_context(|| format!("Failed to read config file at: {}", config_path))?;
+            let mut table: toml::Table = toml::from_str(&config_content)
+                .with_context(|| format!("Failed to parse config file at: {}", config_path))?;
+            for warning in migrate(&mut table) {
+                warn!("{}", warning);
+            }
+            toml::Value::Table(table)
+                .try_into()
+                .with_context(|| format!("Failed to parse config file at: {}", config_path))?
+        }
+        None => {
+            warn!("No config file given, starting from defaults");
+            Config::default()
+        }
+    };
 
-    let config: Config = toml::from_str(&config_content)
-        .with_context(|| format!("Failed to parse config file at: {}", config_path))?;
+    overrides.apply(&mut config);
+    config.validate()?;
 
     info!("Config loaded successfully");
     Ok(config)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_load_config_precedence() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            "[sui]\nrpc_url = \"http://file\"\noracle_builder_package_id = \"0xf11e\"\n\n[response]\nprice_decimals = 6"
+        )
+        .unwrap();
+
+        // File values replace defaults.
+        let overrides = Overrides {
+            config_path: Some(file.path().to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        let config = load_config_with(&overrides).unwrap();
+        assert_eq!(config.sui.rpc_url, "http://file");
+        assert_eq!(config.response.price_decimals, 6);
+        assert!(config.runtime.worker_threads.is_none());
+
+        // Flags replace file values.
+        let overrides = Overrides::try_parse_from([
+            "nautilus-server",
+            "--config",
+            file.path().to_str().unwrap(),
+            "--sui-rpc-url",
+            "http://flag",
+            "--runtime-worker-threads",
+            "2",
+        ])
+        .unwrap();
+        let config = load_config_with(&overrides).unwrap();
+        assert_eq!(config.sui.rpc_url, "http://flag");
+        assert_eq!(config.sui.oracle_builder_package_id, "0xf11e");
+        assert_eq!(config.runtime.worker_threads, Some(2));
+    }
+
+    #[test]
+    fn test_load_config_migrates_deprecated_fields() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(
+            file,
+            "[sui]\nurl = \"http://file\"\npackage_id = \"0xf11e\"\n\n[response]\ndecimals = 6"
+        )
+        .unwrap();
+
+        let overrides = Overrides {
+            config_path: Some(file.path().to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        let config = load_config_with(&overrides).unwrap();
+        assert_eq!(config.sui.rpc_url, "http://file");
+        assert_eq!(config.sui.oracle_builder_package_id, "0xf11e");
+        assert_eq!(config.response.price_decimals, 6);
+    }
+
+    #[test]
+    fn test_load_config_defaults() {
+        let overrides = Overrides {
+            sui_oracle_builder_package_id: Some("0x2".to_string()),
+            ..Default::default()
+        };
+        let config = load_config_with(&overrides).unwrap();
+        assert_eq!(config.sui.rpc_url, Sui::default().rpc_url);
+        assert_eq!(config.response.price_decimals, 8);
+
+        // Defaults alone lack a package id.
+        assert!(load_config_with(&Overrides::default()).is_err());
+    }
+
+    #[test]
+    fn test_validate_reports_all_problems() {
+        let mut config = Config::default();
+        config.sui.rpc_url = "fullnode.testnet.sui.io".to_string();
+        config.sui.oracle_builder_package_id = "0xnothex".to_string();
+        config.sui.accepted_package_ids = vec!["0x1".to_string(), "upgraded".to_string()];
+        config.response.price_decimals = 19;
+        config.runtime.worker_threads = Some(0);
+        config.feeds.insert(
+            "0x3".to_string(),
+            FeedConfig {
+                headers: [("X Partner".to_string(), "acme".to_string())].into(),
+                user_agent: Some("line\nbreak".to_string()),
+                ..Default::default()
+            },
+        );
+
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("sui.rpc_url"));
+        assert!(err.contains("sui.oracle_builder_package_id"));
+        assert!(err.contains("sui.accepted_package_ids entry 'upgraded'"));
+        assert!(!err.contains("entry '0x1'"));
+        assert!(err.contains("response.price_decimals"));
+        assert!(err.contains("runtime.worker_threads"));
+        assert!(!err.contains("runtime.max_connections"));
+        assert!(err.contains("feeds.0x3.headers: 'X Partner'"));
+        assert!(err.contains("feeds.0x3.user_agent"));
+
+        config.sui.rpc_url = "https://fullnode.testnet.sui.io:443".to_string();
+        config.sui.oracle_builder_package_id = "0x2".to_string();
+        config.sui.accepted_package_ids = vec!["0x1".to_string()];
+        config.response.price_decimals = 18;
+        config.runtime.worker_threads = Some(4);
+        config.feeds.clear();
+        assert!(config.validate().is_ok());
+    }
+}
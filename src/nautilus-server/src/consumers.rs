@@ -0,0 +1,460 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Consumers authenticated by API key or Sui wallet signature, and a ledger
+//! of the signed updates served to each, so a dispute about which price a
+//! consumer was given at a given time can be settled from the enclave's own
+//! records.
+
+use axum::extract::{Query, State};
+use axum::Json;
+use fastcrypto::ed25519::{
+    Ed25519PublicKey, Ed25519Signature, ED25519_PUBLIC_KEY_LENGTH, ED25519_SIGNATURE_LENGTH,
+};
+use fastcrypto::encoding::{Base64, Encoding, Hex};
+use fastcrypto::hash::{Blake2b256, HashFunction};
+use fastcrypto::traits::{ToFromBytes, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use tracing::warn;
+
+use crate::common::IntentScope;
+use crate::history::{HistoryStore, MemoryStore, DEFAULT_HISTORY_CAPACITY};
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Header consumers send their API key in.
+pub const CONSUMER_KEY_HEADER: &str = "x-consumer-key";
+/// Header wallet consumers send their serialized Sui signature in, Base64.
+pub const WALLET_SIGNATURE_HEADER: &str = "x-sui-signature";
+/// Header wallet consumers send the signing time in, in milliseconds.
+pub const WALLET_TIMESTAMP_HEADER: &str = "x-sui-timestamp-ms";
+
+/// Flag of Ed25519 in Sui's serialized signatures.
+const ED25519_FLAG: u8 = 0x00;
+/// Intent Sui wallets sign personal messages under: scope PersonalMessage,
+/// version V0, app Sui.
+const PERSONAL_MESSAGE_INTENT: [u8; 3] = [3, 0, 0];
+
+/// `[consumers]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Consumers {
+    /// SHA-256 of each consumer's API key in hex, keyed by consumer name
+    #[serde(default)]
+    pub keys: HashMap<String, String>,
+    /// Sui address of each consumer allowed to authenticate by wallet
+    /// signature, keyed by consumer name
+    #[serde(default)]
+    pub wallets: HashMap<String, String>,
+    /// Refuse requests without a known API key or wallet signature
+    #[serde(default)]
+    pub required: bool,
+    /// Number of served updates kept per consumer
+    #[serde(default = "default_ledger_capacity")]
+    pub ledger_capacity: usize,
+    /// How far the timestamp a wallet signed may be from the enclave's clock
+    #[serde(default = "default_max_signature_age_secs")]
+    pub max_signature_age_secs: u64,
+    /// Number of accepted wallet signatures remembered to refuse replays
+    #[serde(default = "default_seen_signatures_capacity")]
+    pub seen_signatures_capacity: usize,
+}
+
+impl Default for Consumers {
+    fn default() -> Self {
+        Self {
+            keys: HashMap::new(),
+            wallets: HashMap::new(),
+            required: false,
+            ledger_capacity: default_ledger_capacity(),
+            max_signature_age_secs: default_max_signature_age_secs(),
+            seen_signatures_capacity: default_seen_signatures_capacity(),
+        }
+    }
+}
+
+fn default_ledger_capacity() -> usize {
+    10_000
+}
+
+fn default_max_signature_age_secs() -> u64 {
+    60
+}
+
+fn default_seen_signatures_capacity() -> usize {
+    100_000
+}
+
+impl Consumers {
+    /// Check the config, returning the problems found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (name, hash) in &self.keys {
+            if hash.len() != 64 || !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                problems.push(format!(
+                    "consumers.keys.{} must be the SHA-256 of the API key, 64 hex digits",
+                    name
+                ));
+            }
+        }
+        for (name, address) in &self.wallets {
+            if normalize_address(address).is_none() {
+                problems.push(format!(
+                    "consumers.wallets.{} must be a Sui address, 0x and 64 hex digits",
+                    name
+                ));
+            }
+        }
+        if self.required && self.keys.is_empty() && self.wallets.is_empty() {
+            problems.push(
+                "consumers.required is set but no consumers.keys or consumers.wallets are configured"
+                    .to_string(),
+            );
+        }
+        problems
+    }
+
+    /// Whether `name` is a configured consumer.
+    pub fn contains(&self, name: &str) -> bool {
+        self.keys.contains_key(name) || self.wallets.contains_key(name)
+    }
+
+    /// Name of the consumer `api_key` belongs to. Requests without a key are
+    /// anonymous unless `required` is set; an unknown key is always refused.
+    pub fn authenticate(&self, api_key: Option<&str>) -> Result<Option<String>, EnclaveError> {
+        let Some(api_key) = api_key else {
+            return if self.required {
+                Err(EnclaveError::Unauthorized(format!(
+                    "Missing {} or {} header",
+                    CONSUMER_KEY_HEADER, WALLET_SIGNATURE_HEADER
+                )))
+            } else {
+                Ok(None)
+            };
+        };
+        let hash = Hex::encode(Sha256::digest(api_key.as_bytes()));
+        self.keys
+            .iter()
+            .find(|(_, expected)| expected.eq_ignore_ascii_case(&hash))
+            .map(|(name, _)| Some(name.clone()))
+            .ok_or_else(|| EnclaveError::Unauthorized("Unknown consumer key".to_string()))
+    }
+
+    /// Name of the consumer whose wallet signed `body` at `timestamp_ms`.
+    /// The signature is a Sui personal message signature over
+    /// [`wallet_message`]; only Ed25519 wallets are supported. Each signature
+    /// is accepted once, a replay is refused while `seen` remembers it.
+    pub fn authenticate_wallet(
+        &self,
+        signature: &str,
+        timestamp_ms: u64,
+        body: &[u8],
+        now_ms: u64,
+        seen: &SeenSignatures,
+    ) -> Result<String, EnclaveError> {
+        if now_ms.abs_diff(timestamp_ms) > self.max_signature_age_secs.saturating_mul(1000) {
+            return Err(EnclaveError::Unauthorized(format!(
+                "Wallet signature timestamp is more than {}s from the enclave's clock",
+                self.max_signature_age_secs
+            )));
+        }
+        let signature = Base64::decode(signature)
+            .map_err(|_| EnclaveError::Unauthorized("Wallet signature is not valid Base64".to_string()))?;
+        let address = verify_personal_message(&signature, &wallet_message(timestamp_ms, body))?;
+        let name = self
+            .wallets
+            .iter()
+            .find(|(_, allowed)| normalize_address(allowed).as_deref() == Some(address.as_str()))
+            .map(|(name, _)| name.clone())
+            .ok_or_else(|| EnclaveError::Unauthorized(format!("Wallet {} is not allowed", address)))?;
+        // Signatures this old are refused above, so need not be remembered
+        let oldest_ms = now_ms.saturating_sub(self.max_signature_age_secs.saturating_mul(1000));
+        if !seen.insert((address, timestamp_ms, signature), oldest_ms, self.seen_signatures_capacity) {
+            return Err(EnclaveError::Unauthorized("Wallet signature was already used".to_string()));
+        }
+        Ok(name)
+    }
+}
+
+/// Signer address, signed timestamp and serialized signature of an accepted
+/// wallet signature.
+type SeenSignature = (String, u64, Vec<u8>);
+
+/// Wallet signatures already accepted, oldest first, so a captured request
+/// cannot be replayed while its timestamp is still fresh.
+#[derive(Default)]
+pub struct SeenSignatures {
+    seen: Mutex<(HashSet<SeenSignature>, VecDeque<SeenSignature>)>,
+}
+
+impl SeenSignatures {
+    /// Remember `signature`, returning false if it was already seen. Those
+    /// signed before `oldest_ms` are forgotten first, then the oldest ones
+    /// beyond `capacity`.
+    pub fn insert(&self, signature: SeenSignature, oldest_ms: u64, capacity: usize) -> bool {
+        let mut seen = self.seen.lock().expect("seen signatures lock poisoned");
+        let (set, order) = &mut *seen;
+        if set.contains(&signature) {
+            return false;
+        }
+        while let Some(oldest) = order.front() {
+            if oldest.1 >= oldest_ms && order.len() < capacity.max(1) {
+                break;
+            }
+            set.remove(oldest);
+            order.pop_front();
+        }
+        set.insert(signature.clone());
+        order.push_back(signature);
+        true
+    }
+}
+
+/// Message a wallet consumer signs: the timestamp in milliseconds, a colon
+/// and the raw request body.
+pub fn wallet_message(timestamp_ms: u64, body: &[u8]) -> Vec<u8> {
+    [format!("{}:", timestamp_ms).as_bytes(), body].concat()
+}
+
+/// Check a serialized Sui signature (flag, signature, public key) over a
+/// personal message and return the signer's address.
+fn verify_personal_message(serialized: &[u8], message: &[u8]) -> Result<String, EnclaveError> {
+    let invalid = || EnclaveError::Unauthorized("Invalid wallet signature".to_string());
+    if serialized.first() != Some(&ED25519_FLAG) {
+        return Err(EnclaveError::Unauthorized(
+            "Only Ed25519 wallet signatures are supported".to_string(),
+        ));
+    }
+    if serialized.len() != 1 + ED25519_SIGNATURE_LENGTH + ED25519_PUBLIC_KEY_LENGTH {
+        return Err(invalid());
+    }
+    let (signature, public_key) = serialized[1..].split_at(ED25519_SIGNATURE_LENGTH);
+    let signature = Ed25519Signature::from_bytes(signature).map_err(|_| invalid())?;
+    let public_key = Ed25519PublicKey::from_bytes(public_key).map_err(|_| invalid())?;
+
+    let message = bcs::to_bytes(message).map_err(|_| invalid())?;
+    let digest = Blake2b256::digest([&PERSONAL_MESSAGE_INTENT[..], &message].concat());
+    public_key.verify(&digest.digest, &signature).map_err(|_| invalid())?;
+    Ok(sui_address(&public_key))
+}
+
+/// Sui address of an Ed25519 public key.
+fn sui_address(public_key: &Ed25519PublicKey) -> String {
+    let digest = Blake2b256::digest([&[ED25519_FLAG][..], public_key.as_bytes()].concat());
+    format!("0x{}", Hex::encode(digest.digest))
+}
+
+/// Lower case `0x` prefixed form of a full length Sui address.
+fn normalize_address(address: &str) -> Option<String> {
+    let hex = address.strip_prefix("0x")?;
+    (hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| format!("0x{}", hex.to_ascii_lowercase()))
+}
+
+/// A signed update served to a consumer.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ServedUpdate {
+    /// Oracle module that signed it
+    pub module: String,
+    pub intent: IntentScope,
+    pub timestamp_ms: u64,
+    /// Signed payload, as returned to the consumer
+    pub data: Value,
+    pub signature: String,
+}
+
+/// Signed updates served to each authenticated consumer, newest last,
+/// bounded by `consumers.ledger_capacity`. Kept in the `[persistence]` store
+/// next to the price history, so the ledger survives a restart with durable
+/// backends. Storage errors are logged rather than failing the request.
+pub struct ServedLedger {
+    store: Arc<dyn HistoryStore>,
+}
+
+impl ServedLedger {
+    pub fn with_store(store: Arc<dyn HistoryStore>) -> Self {
+        Self { store }
+    }
+
+    /// Record `update` as served to `consumer`, evicting the oldest ones
+    /// beyond `capacity`.
+    pub fn record(&self, consumer: &str, update: ServedUpdate, capacity: usize) {
+        if let Err(e) = self.store.record_served(consumer, update, capacity) {
+            warn!("Failed to record update served to {}: {:#}", consumer, e);
+        }
+    }
+
+    /// Updates served to `consumer` signed within `[from_ms, to_ms]`, oldest
+    /// first, optionally only those of `module`.
+    pub fn query(
+        &self,
+        consumer: &str,
+        from_ms: u64,
+        to_ms: u64,
+        module: Option<&str>,
+    ) -> anyhow::Result<Vec<ServedUpdate>> {
+        let mut updates = self.store.served(consumer, from_ms, to_ms)?;
+        updates.retain(|update| module.map_or(true, |module| update.module == module));
+        Ok(updates)
+    }
+}
+
+impl Default for ServedLedger {
+    fn default() -> Self {
+        Self::with_store(Arc::new(MemoryStore::new(DEFAULT_HISTORY_CAPACITY)))
+    }
+}
+
+/// Query of the served updates endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServedQuery {
+    pub consumer: String,
+    #[serde(default)]
+    pub from_ms: Option<u64>,
+    #[serde(default)]
+    pub to_ms: Option<u64>,
+    /// Only updates of this oracle module, e.g. `price_feed`
+    #[serde(default)]
+    pub module: Option<String>,
+}
+
+/// Response of the served updates endpoint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServedResponse {
+    pub consumer: String,
+    pub updates: Vec<ServedUpdate>,
+}
+
+/// Endpoint listing the signed updates served to a consumer within a time
+/// range, for reconciliation.
+pub async fn served_updates(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ServedQuery>,
+) -> Result<Json<ServedResponse>, EnclaveError> {
+    if !state.config.load().consumers.contains(&query.consumer) {
+        return Err(EnclaveError::GenericError(format!(
+            "Unknown consumer: {}",
+            query.consumer
+        )));
+    }
+    let updates = state
+        .served
+        .query(
+            &query.consumer,
+            query.from_ms.unwrap_or(0),
+            query.to_ms.unwrap_or(u64::MAX),
+            query.module.as_deref(),
+        )
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to read served updates: {:#}", e)))?;
+    Ok(Json(ServedResponse {
+        consumer: query.consumer,
+        updates,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_authenticate() {
+        let consumers = Consumers {
+            keys: HashMap::from([(
+                "acme".to_string(),
+                Hex::encode(Sha256::digest(b"acme-key")),
+            )]),
+            ..Default::default()
+        };
+        assert!(consumers.validate().is_empty());
+        assert_eq!(consumers.authenticate(Some("acme-key")).unwrap(), Some("acme".to_string()));
+        assert_eq!(consumers.authenticate(None).unwrap(), None);
+        assert!(consumers.authenticate(Some("other")).is_err());
+
+        let required = Consumers {
+            required: true,
+            ..consumers
+        };
+        assert!(required.authenticate(None).is_err());
+    }
+
+    #[test]
+    fn test_authenticate_wallet() {
+        use fastcrypto::ed25519::Ed25519KeyPair;
+        use fastcrypto::traits::{KeyPair, Signer};
+
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let sign = |kp: &Ed25519KeyPair, timestamp_ms: u64, body: &[u8]| {
+            let message = bcs::to_bytes(&wallet_message(timestamp_ms, body)).unwrap();
+            let digest = Blake2b256::digest([&PERSONAL_MESSAGE_INTENT[..], &message].concat());
+            let signature: Ed25519Signature = kp.sign(&digest.digest);
+            let serialized = [&[ED25519_FLAG][..], signature.as_ref(), kp.public().as_bytes()].concat();
+            Base64::encode(serialized)
+        };
+        let consumers = Consumers {
+            wallets: HashMap::from([("acme".to_string(), sui_address(kp.public()))]),
+            ..Default::default()
+        };
+        assert!(consumers.validate().is_empty());
+
+        let body = br#"{"payload":{}}"#;
+        let signature = sign(&kp, 1_000, body);
+        let seen = SeenSignatures::default();
+        assert_eq!(consumers.authenticate_wallet(&signature, 1_000, body, 30_000, &seen).unwrap(), "acme");
+        // Another body, another timestamp or a stale signature are refused
+        assert!(consumers.authenticate_wallet(&signature, 1_000, b"{}", 30_000, &seen).is_err());
+        assert!(consumers.authenticate_wallet(&signature, 2_000, body, 30_000, &seen).is_err());
+        assert!(consumers.authenticate_wallet(&signature, 1_000, body, 100_000, &seen).is_err());
+        // So is the same signature a second time
+        let err = consumers
+            .authenticate_wallet(&signature, 1_000, body, 30_000, &seen)
+            .unwrap_err();
+        assert!(err.to_string().contains("already used"));
+        let signature = sign(&kp, 2_000, body);
+        assert!(consumers.authenticate_wallet(&signature, 2_000, body, 30_000, &seen).is_ok());
+
+        let other = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let err = consumers
+            .authenticate_wallet(&sign(&other, 1_000, body), 1_000, body, 1_000, &seen)
+            .unwrap_err();
+        assert!(err.to_string().contains("is not allowed"));
+    }
+
+    #[test]
+    fn test_seen_signatures() {
+        let seen = SeenSignatures::default();
+        let signature = |timestamp_ms| ("0xa".to_string(), timestamp_ms, vec![1, 2, 3]);
+        assert!(seen.insert(signature(10), 0, 2));
+        assert!(!seen.insert(signature(10), 0, 2));
+        assert!(seen.insert(signature(20), 0, 2));
+        // Beyond the capacity the oldest is forgotten
+        assert!(seen.insert(signature(30), 0, 2));
+        assert!(seen.insert(signature(10), 0, 2));
+        // As are those signed before the oldest accepted timestamp
+        assert!(seen.insert(signature(40), 35, 10));
+        assert!(seen.insert(signature(30), 0, 10));
+    }
+
+    #[test]
+    fn test_ledger() {
+        let ledger = ServedLedger::default();
+        let update = |timestamp_ms, module: &str| ServedUpdate {
+            module: module.to_string(),
+            intent: IntentScope::PriceFeed,
+            timestamp_ms,
+            data: json!({ "price": timestamp_ms }),
+            signature: "00".to_string(),
+        };
+        for (timestamp_ms, module) in [(1, "price_feed"), (2, "weather"), (3, "price_feed")] {
+            ledger.record("acme", update(timestamp_ms, module), 2);
+        }
+        // The oldest update was evicted
+        assert_eq!(ledger.query("acme", 0, u64::MAX, None).unwrap().len(), 2);
+        assert_eq!(ledger.query("acme", 3, 3, None).unwrap(), vec![update(3, "price_feed")]);
+        assert_eq!(ledger.query("acme", 0, u64::MAX, Some("weather")).unwrap().len(), 1);
+        assert!(ledger.query("other", 0, u64::MAX, None).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Conversion of feeds quoted in another currency than the one signed, e.g.
+//! a JPY-quoted equity signed in USD. The feed's price is multiplied by the
+//! price of an FX feed, fetched live from its own upstream in the same
+//! request, before the asset's conventions and decimals are applied.
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::app::fetch_value;
+use crate::config::{is_valid_object_id, Config, FeedConfig};
+use crate::{AppState, EnclaveError};
+
+/// Currency conversion step of a feed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Conversion {
+    /// Id of the on-chain PriceFeed quoting the rate, e.g. JPY/USD
+    pub fx_feed: String,
+    /// Divide by the rate instead, for FX feeds quoted the other way round,
+    /// e.g. USD/JPY
+    #[serde(default)]
+    pub invert: bool,
+}
+
+impl Conversion {
+    /// Convert `value` at `rate`.
+    pub fn apply(&self, value: Decimal, rate: Decimal) -> Result<Decimal, String> {
+        if rate <= Decimal::ZERO {
+            return Err(format!("FX rate {} is not positive", rate));
+        }
+        let converted = if self.invert {
+            value.checked_div(rate)
+        } else {
+            value.checked_mul(rate)
+        };
+        converted.ok_or_else(|| format!("Converting {} at {} overflows", value, rate))
+    }
+}
+
+/// Check the conversions of `[feeds]`, returning the problems found. FX
+/// feeds are not converted themselves, so rates never chain.
+pub fn validate(feeds: &HashMap<String, FeedConfig>) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (feed_id, feed) in feeds {
+        let Some(conversion) = &feed.convert else {
+            continue;
+        };
+        if !is_valid_object_id(&conversion.fx_feed) {
+            problems.push(format!(
+                "feeds.{}.convert.fx_feed '{}' is not an object id",
+                feed_id, conversion.fx_feed
+            ));
+        } else if conversion.fx_feed == *feed_id {
+            problems.push(format!(
+                "feeds.{}.convert.fx_feed is the feed itself",
+                feed_id
+            ));
+        } else if feeds
+            .get(&conversion.fx_feed)
+            .is_some_and(|fx_feed| fx_feed.convert.is_some())
+        {
+            problems.push(format!(
+                "feeds.{}.convert.fx_feed {} is converted itself",
+                feed_id, conversion.fx_feed
+            ));
+        }
+    }
+    problems
+}
+
+/// Fetch the current rate of `conversion`'s FX feed from its upstream, with
+/// the FX feed's own extraction, transform and scaling.
+pub async fn fx_rate(
+    state: &AppState,
+    config: &Config,
+    conversion: &Conversion,
+) -> Result<Decimal, EnclaveError> {
+    let fx_feed = state
+        .sui_client
+        .load_full()
+        .fetch_price_feed(&conversion.fx_feed)
+        .await
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to fetch FX feed: {}", e)))?;
+    if !fx_feed.is_valid {
+        return Err(EnclaveError::GenericError(format!(
+            "FX feed {} is not valid",
+            conversion.fx_feed
+        )));
+    }
+    let (_, _, rate) = fetch_value(state, config, &conversion.fx_feed, &fx_feed).await?;
+    Ok(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const FX_FEED: &str = "0x0000000000000000000000000000000000000000000000000000000000000f0f";
+
+    #[test]
+    fn test_apply() {
+        let jpy_usd = Conversion {
+            fx_feed: FX_FEED.to_string(),
+            invert: false,
+        };
+        let rate = Decimal::from_str("0.0065").unwrap();
+        assert_eq!(
+            jpy_usd.apply(Decimal::from(3000), rate),
+            Ok(Decimal::from_str("19.5").unwrap())
+        );
+
+        let usd_jpy = Conversion {
+            invert: true,
+            ..jpy_usd
+        };
+        assert_eq!(
+            usd_jpy.apply(Decimal::from(3000), Decimal::from(150)),
+            Ok(Decimal::from(20))
+        );
+        assert!(usd_jpy.apply(Decimal::from(3000), Decimal::ZERO).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let converted = |fx_feed: &str| FeedConfig {
+            convert: Some(Conversion {
+                fx_feed: fx_feed.to_string(),
+                invert: false,
+            }),
+            ..Default::default()
+        };
+        let feed = "0x0000000000000000000000000000000000000000000000000000000000000001";
+
+        let feeds = HashMap::from([(feed.to_string(), converted(FX_FEED))]);
+        assert!(validate(&feeds).is_empty());
+
+        let feeds = HashMap::from([(feed.to_string(), converted("JPYUSD"))]);
+        assert_eq!(validate(&feeds).len(), 1);
+        let feeds = HashMap::from([(feed.to_string(), converted(feed))]);
+        assert_eq!(validate(&feeds).len(), 1);
+        let feeds = HashMap::from([
+            (feed.to_string(), converted(FX_FEED)),
+            (FX_FEED.to_string(), converted(feed)),
+        ]);
+        assert_eq!(validate(&feeds).len(), 2);
+    }
+}
@@ -0,0 +1,319 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::app::{extract_price, parse_field_path};
+use crate::config::{check_http_url, is_valid_object_id};
+use crate::formats::{ResponseFormat, UpstreamFormat};
+use crate::hmac_auth::HMAC_SHA256;
+use crate::limits::UpstreamLimits;
+use crate::oauth::OAUTH2;
+use crate::xml::Selector;
+
+/// Move module holding the PriceFeed type.
+pub const ORACLE_BUILDER_MODULE: &str = "oracle_builder";
+/// Move entry function that creates a PriceFeed under an oracle.
+pub const CREATE_PRICE_FEED_FUNCTION: &str = "create_price_feed";
+/// Values of `api_key_config` the server knows how to apply to upstream requests.
+pub const SUPPORTED_API_KEY_CONFIGS: &[&str] = &["Bearer", "x-api-key", HMAC_SHA256, OAUTH2];
+
+/// Off-chain description of a PriceFeed, checked against what the server can
+/// actually fetch and parse before it is created on chain.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PriceFeedDefinition {
+    pub underlying_url: String,
+    pub response_field: String,
+    pub live_url: String,
+    pub api_key: Option<String>,
+    pub api_key_config: Option<String>,
+    /// Sample upstream response checked against `response_field`
+    #[serde(default)]
+    pub example: Option<ExtractionExample>,
+}
+
+/// A sample upstream response and the price `response_field` should extract
+/// from it. Running the extraction on it catches a field path that no longer
+/// matches the provider's format.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ExtractionExample {
+    /// Upstream response body, as returned by the provider
+    pub body: String,
+    /// Price expected from `body`
+    pub expected: Decimal,
+}
+
+impl ExtractionExample {
+    /// Extract the price at `response_field` from the example body, read as
+    /// `format`, and check it is the expected one.
+    pub fn check(&self, format: &UpstreamFormat, response_field: &str) -> Result<(), String> {
+        let json = match format.response_format {
+            ResponseFormat::Json => serde_json::from_str(&self.body)
+                .map_err(|e| format!("Example body is not valid JSON: {}", e))?,
+            _ => format.parse(&UpstreamLimits::default(), self.body.as_bytes())?,
+        };
+        let price = extract_price(&json, response_field)?;
+        if price != self.expected {
+            return Err(format!(
+                "'{}' extracts {} from the example body, expected {}",
+                response_field, price, self.expected
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Builder for [`PriceFeedDefinition`], see [`PriceFeedDefinition::builder`].
+#[derive(Debug, Clone, Default)]
+pub struct PriceFeedDefinitionBuilder {
+    underlying_url: Option<String>,
+    response_field: Option<String>,
+    live_url: Option<String>,
+    api_key: Option<String>,
+    api_key_config: Option<String>,
+    example: Option<ExtractionExample>,
+}
+
+/// A Move call that creates a PriceFeed, with arguments in the order the
+/// entry function expects them. `Option` arguments are encoded as empty or
+/// single element vectors.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MoveCall {
+    pub package: String,
+    pub module: String,
+    pub function: String,
+    pub arguments: Vec<Value>,
+}
+
+impl PriceFeedDefinition {
+    pub fn builder() -> PriceFeedDefinitionBuilder {
+        PriceFeedDefinitionBuilder::default()
+    }
+
+    /// Move call creating this feed under `oracle_id` in the given
+    /// oracle_builder package.
+    pub fn move_call(&self, package_id: &str, oracle_id: &str) -> Result<MoveCall> {
+        if !is_valid_object_id(package_id) {
+            return Err(anyhow::anyhow!("Invalid package id: {}", package_id));
+        }
+        if !is_valid_object_id(oracle_id) {
+            return Err(anyhow::anyhow!("Invalid oracle id: {}", oracle_id));
+        }
+
+        Ok(MoveCall {
+            package: package_id.to_string(),
+            module: ORACLE_BUILDER_MODULE.to_string(),
+            function: CREATE_PRICE_FEED_FUNCTION.to_string(),
+            arguments: vec![
+                json!(oracle_id),
+                json!(self.underlying_url),
+                json!(self.response_field),
+                json!(self.live_url),
+                option_arg(&self.api_key),
+                option_arg(&self.api_key_config),
+            ],
+        })
+    }
+}
+
+impl PriceFeedDefinitionBuilder {
+    pub fn underlying_url(mut self, url: impl Into<String>) -> Self {
+        self.underlying_url = Some(url.into());
+        self
+    }
+
+    pub fn response_field(mut self, field: impl Into<String>) -> Self {
+        self.response_field = Some(field.into());
+        self
+    }
+
+    /// Public URL shown to consumers, defaults to the underlying URL.
+    pub fn live_url(mut self, url: impl Into<String>) -> Self {
+        self.live_url = Some(url.into());
+        self
+    }
+
+    /// API key and the way it is attached to upstream requests, one of
+    /// [`SUPPORTED_API_KEY_CONFIGS`].
+    pub fn api_key(mut self, api_key: impl Into<String>, api_key_config: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self.api_key_config = Some(api_key_config.into());
+        self
+    }
+
+    /// Sample upstream body and the price `response_field` must extract from it.
+    pub fn example(mut self, body: impl Into<String>, expected: Decimal) -> Self {
+        self.example = Some(ExtractionExample {
+            body: body.into(),
+            expected,
+        });
+        self
+    }
+
+    /// Validate all fields, reporting every problem at once.
+    pub fn build(self) -> Result<PriceFeedDefinition> {
+        let mut problems = Vec::new();
+
+        let underlying_url = self.underlying_url.unwrap_or_default();
+        if let Err(e) = check_http_url(&underlying_url) {
+            problems.push(format!("underlying_url: {}", e));
+        }
+
+        let live_url = self.live_url.unwrap_or_else(|| underlying_url.clone());
+        if let Err(e) = check_http_url(&live_url) {
+            problems.push(format!("live_url: {}", e));
+        }
+
+        let response_field = self.response_field.unwrap_or_default();
+        if response_field.is_empty() {
+            problems.push("response_field: must not be empty".to_string());
+        } else if let Err(e) = check_field_path(&response_field) {
+            problems.push(format!("response_field: {}", e));
+        } else if let Some(example) = &self.example {
+            // XML selectors imply an XML upstream
+            let format = UpstreamFormat {
+                response_format: if response_field.starts_with('/') {
+                    ResponseFormat::Xml
+                } else {
+                    ResponseFormat::Json
+                },
+                ..Default::default()
+            };
+            if let Err(e) = example.check(&format, &response_field) {
+                problems.push(format!("example: {}", e));
+            }
+        }
+
+        if let Some(api_key_config) = &self.api_key_config {
+            if !SUPPORTED_API_KEY_CONFIGS.contains(&api_key_config.as_str()) {
+                problems.push(format!(
+                    "api_key_config: '{}' is not one of {:?}",
+                    api_key_config, SUPPORTED_API_KEY_CONFIGS
+                ));
+            }
+        }
+        if self.api_key.as_deref() == Some("") {
+            problems.push("api_key: must not be empty".to_string());
+        }
+
+        if !problems.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Invalid price feed definition:\n  - {}",
+                problems.join("\n  - ")
+            ));
+        }
+
+        Ok(PriceFeedDefinition {
+            underlying_url,
+            response_field,
+            live_url,
+            api_key: self.api_key,
+            api_key_config: self.api_key_config,
+            example: self.example,
+        })
+    }
+}
+
+impl MoveCall {
+    /// Arguments formatted for `sui client call --args`.
+    pub fn cli_args(&self) -> Vec<String> {
+        self.arguments
+            .iter()
+            .map(|arg| match arg {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            })
+            .collect()
+    }
+}
+
+fn option_arg(value: &Option<String>) -> Value {
+    match value {
+        Some(v) => json!([v]),
+        None => json!([]),
+    }
+}
+
+
+/// Check a field path parses, as an XML selector if it starts with '/'.
+fn check_field_path(field_path: &str) -> Result<(), String> {
+    if field_path.starts_with('/') {
+        Selector::parse(field_path).map(|_| ())
+    } else {
+        parse_field_path(field_path).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_valid_definition() {
+        let definition = PriceFeedDefinition::builder()
+            .underlying_url("https://api.example.com/price?symbol=SUI")
+            .response_field("data[0].price")
+            .api_key("secret", "Bearer")
+            .build()
+            .unwrap();
+        assert_eq!(definition.live_url, definition.underlying_url);
+
+        let call = definition.move_call("0x2", "0xabc").unwrap();
+        assert_eq!(call.function, CREATE_PRICE_FEED_FUNCTION);
+        assert_eq!(
+            call.cli_args(),
+            vec![
+                "0xabc",
+                "https://api.example.com/price?symbol=SUI",
+                "data[0].price",
+                "https://api.example.com/price?symbol=SUI",
+                "[\"secret\"]",
+                "[\"Bearer\"]",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_build_reports_all_problems() {
+        let err = PriceFeedDefinition::builder()
+            .underlying_url("ftp://example.com")
+            .response_field("prices[abc]")
+            .api_key("secret", "Basic")
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("underlying_url"));
+        assert!(err.contains("live_url"));
+        assert!(err.contains("Invalid array index: 'abc'"));
+        assert!(err.contains("api_key_config"));
+
+        let call = PriceFeedDefinition::builder()
+            .underlying_url("https://example.com")
+            .response_field("price")
+            .build()
+            .unwrap()
+            .move_call("0x2", "oracle");
+        assert!(call.is_err());
+    }
+
+    #[test]
+    fn test_build_checks_example() {
+        let builder = PriceFeedDefinition::builder()
+            .underlying_url("https://example.com")
+            .response_field("data.price");
+        let body = r#"{"data": {"price": "1.25"}}"#;
+
+        assert!(builder.clone().example(body, Decimal::new(125, 2)).build().is_ok());
+
+        // The provider renamed the field.
+        let err = builder
+            .example(r#"{"data": {"last": "1.25"}}"#, Decimal::new(125, 2))
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("example: Failed to extract price"));
+    }
+}
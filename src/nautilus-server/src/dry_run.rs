@@ -0,0 +1,129 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Dry runs of the price pipeline: the PriceFeed is read from Sui and its
+//! upstream fetched, extracted and scaled as for `/process_data`, but the
+//! values along the way are returned instead of a signature. Nothing is
+//! signed, cached or recorded in the feed's history.
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::app;
+use crate::common::ProcessDataRequest;
+use crate::oracle;
+use crate::AppState;
+use crate::EnclaveError;
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DryRunRequest {
+    pub price_feed_id: String,
+    /// Values of the placeholders of the feed's URL, as for `/process_data`
+    #[serde(default)]
+    pub params: BTreeMap<String, String>,
+}
+
+/// What `/process_data` would do with the feed, up to signing. Decimal
+/// values are strings so no precision is lost.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DryRunResponse {
+    pub price_feed_id: String,
+    pub is_valid: bool,
+    pub underlying_url: String,
+    pub response_field: String,
+    /// The upstream value at `response_field`, if the path names one
+    #[schema(value_type = Option<Object>)]
+    pub excerpt: Option<Value>,
+    /// Number extracted from the upstream response, or computed by the
+    /// feed's transform
+    pub extracted_value: String,
+    /// After the feed's scaling expression
+    pub scaled_value: String,
+    /// After currency conversion, for feeds with `convert`
+    pub converted_value: Option<String>,
+    /// After the conventions of the feed's asset
+    pub normalized_value: String,
+    /// The integer that would be signed
+    #[schema(value_type = String)]
+    #[serde(with = "i128_string")]
+    pub price: i128,
+    pub decimals: u32,
+    /// Time of the price at the upstream, for feeds with a timestamp field
+    pub source_timestamp_ms: Option<u64>,
+}
+
+mod i128_string {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &i128, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i128, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Endpoint that runs the price pipeline of a feed without signing, so
+/// operators can check a feed's `response_field` and scaling before use.
+/// Consumers authenticate as for `/process_data`.
+#[utoipa::path(
+    post,
+    path = "/dry_run",
+    tag = "price feed",
+    request_body = ProcessDataRequest<DryRunRequest>,
+    params(
+        ("x-consumer-key" = Option<String>, Header, description = "API key of the consumer, see `[consumers]`"),
+        ("x-sui-signature" = Option<String>, Header, description = "Sui wallet signature of the timestamp and body, instead of an API key"),
+        ("x-sui-timestamp-ms" = Option<u64>, Header, description = "Time the wallet signature was made"),
+    ),
+    responses(
+        (status = 200, description = "Values of the pipeline, unsigned", body = DryRunResponse),
+        (status = 400, description = "Invalid feed or failed fetch", body = crate::ErrorResponse),
+        (status = 401, description = "Missing or unknown consumer key or wallet", body = crate::ErrorResponse),
+    )
+)]
+pub async fn dry_run(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<DryRunResponse>, EnclaveError> {
+    oracle::authenticate(&state, &headers, &body)?;
+    let request: ProcessDataRequest<DryRunRequest> = oracle::parse_body(&body)?;
+    let price_feed_id = request.payload.price_feed_id;
+    let mut price_feed = state
+        .sui_client
+        .load_full()
+        .fetch_price_feed(&price_feed_id)
+        .await
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to fetch price feed: {}", e)))?;
+    price_feed.underlying_url =
+        app::fill_url_template(&price_feed.underlying_url, &request.payload.params)
+            .map_err(EnclaveError::GenericError)?;
+    let config = state.config.load_full();
+    let (upstream, trace) =
+        app::fetch_price_traced(&state, &config, &price_feed_id, &price_feed).await?;
+    Ok(Json(DryRunResponse {
+        price_feed_id,
+        is_valid: price_feed.is_valid,
+        underlying_url: price_feed.underlying_url,
+        response_field: price_feed.response_field,
+        excerpt: trace.excerpt,
+        extracted_value: trace.extracted.to_string(),
+        scaled_value: trace.value.to_string(),
+        converted_value: trace.converted.map(|value| value.to_string()),
+        normalized_value: trace.normalized.to_string(),
+        price: upstream.price,
+        decimals: upstream.decimals,
+        source_timestamp_ms: upstream.source_timestamp_ms,
+    }))
+}
@@ -0,0 +1,146 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Response encodings for high-frequency consumers: JSON responses are
+//! re-encoded as CBOR for clients sending `Accept: application/cbor`, and
+//! gzip-compressed for clients sending `Accept-Encoding: gzip`. The CBOR
+//! document has the same fields as the JSON one, so signed payloads are
+//! rebuilt the same way. Applied when the router is built.
+
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use tower_http::compression::predicate::{NotForContentType, Predicate};
+use tower_http::compression::{CompressionLayer, DefaultPredicate};
+use tracing::warn;
+
+use crate::batch::NDJSON;
+
+/// Media type of CBOR responses.
+pub const CBOR: &str = "application/cbor";
+
+/// `[encoding]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Encoding {
+    /// Compress responses with gzip for clients that accept it
+    #[serde(default = "default_enabled")]
+    pub gzip: bool,
+    /// Serve CBOR instead of JSON to clients that ask for it
+    #[serde(default = "default_enabled")]
+    pub cbor: bool,
+}
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Self {
+            gzip: default_enabled(),
+            cbor: default_enabled(),
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Wrap the router with the enabled encodings.
+pub fn apply(router: Router, encoding: &Encoding) -> Router {
+    let router = if encoding.cbor {
+        router.layer(middleware::from_fn(negotiate_cbor))
+    } else {
+        router
+    };
+    if encoding.gzip {
+        // Streamed batches are left alone: compression would hold back
+        // lines until enough of them fill a block.
+        let predicate = DefaultPredicate::new().and(NotForContentType::const_new(NDJSON));
+        router.layer(CompressionLayer::new().gzip(true).compress_when(predicate))
+    } else {
+        router
+    }
+}
+
+/// Re-encode JSON responses as CBOR when the client accepts it.
+async fn negotiate_cbor(request: Request, next: Next) -> Response {
+    let wants_cbor = accepts_cbor(request.headers());
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !wants_cbor || !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let cbor = match axum::body::to_bytes(body, usize::MAX)
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|json| to_cbor(&json))
+    {
+        Ok(cbor) => cbor,
+        Err(e) => {
+            warn!("Failed to encode response as CBOR: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    parts
+        .headers
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(CBOR));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(cbor))
+}
+
+fn accepts_cbor(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_type| media_type.split(';').next().unwrap_or_default().trim() == CBOR)
+}
+
+/// CBOR encoding of a JSON document.
+fn to_cbor(json: &[u8]) -> Result<Vec<u8>, String> {
+    let value: serde_json::Value = serde_json::from_slice(json).map_err(|e| e.to_string())?;
+    let mut cbor = Vec::new();
+    ciborium::into_writer(&value, &mut cbor).map_err(|e| e.to_string())?;
+    Ok(cbor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accepts_cbor() {
+        let accept = |value: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, HeaderValue::from_static(value));
+            accepts_cbor(&headers)
+        };
+        assert!(accept("application/cbor"));
+        assert!(accept("application/json;q=0.5, application/cbor"));
+        assert!(!accept("application/json"));
+        assert!(!accepts_cbor(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_to_cbor_roundtrip() {
+        let json = br#"{"signature":"ab","response":{"timestamp_ms":18446744073709551615}}"#;
+        let cbor = to_cbor(json).unwrap();
+        let value: serde_json::Value = ciborium::from_reader(cbor.as_slice()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::from_slice::<serde_json::Value>(json).unwrap()
+        );
+    }
+}
@@ -0,0 +1,85 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Randomness for signing key generation. With the `nsm-entropy` feature the
+//! Nitro Security Module's RNG is mixed with the OS RNG, so the key does not
+//! depend on the parent-visible kernel entropy pool alone. Outside an enclave
+//! the NSM is unavailable and the OS RNG is used by itself.
+
+use rand::rngs::{OsRng, StdRng};
+use rand::{RngCore, SeedableRng};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+/// Domain separator of the mixed seed.
+const SEED_DOMAIN: &[u8] = b"nautilus-key-entropy-v1";
+
+/// RNG to generate a signing key with, seeded from the NSM and the OS RNG.
+pub fn key_rng() -> StdRng {
+    let mut os = [0u8; 32];
+    OsRng.fill_bytes(&mut os);
+
+    let nsm = nsm_random();
+    match &nsm {
+        Some(_) => info!("Seeding key generation from the NSM and OS RNGs"),
+        None => info!("Seeding key generation from the OS RNG"),
+    }
+    StdRng::from_seed(mix(nsm.as_deref(), &os))
+}
+
+/// SHA-256 over the domain separator and both sources. Each source is length
+/// prefixed so the NSM bytes cannot be confused with the OS bytes.
+fn mix(nsm: Option<&[u8]>, os: &[u8]) -> [u8; 32] {
+    let nsm = nsm.unwrap_or_default();
+    let mut hasher = Sha256::new();
+    hasher.update(SEED_DOMAIN);
+    hasher.update((nsm.len() as u64).to_le_bytes());
+    hasher.update(nsm);
+    hasher.update((os.len() as u64).to_le_bytes());
+    hasher.update(os);
+    hasher.finalize().into()
+}
+
+/// Random bytes from the NSM, if it is available.
+#[cfg(feature = "nsm-entropy")]
+fn nsm_random() -> Option<Vec<u8>> {
+    use nsm_api::api::{Request, Response};
+    use nsm_api::driver;
+    use tracing::warn;
+
+    let fd = driver::nsm_init();
+    if fd < 0 {
+        warn!("NSM device unavailable, falling back to the OS RNG for key generation");
+        return None;
+    }
+    let response = driver::nsm_process_request(fd, Request::GetRandom);
+    driver::nsm_exit(fd);
+    match response {
+        Response::GetRandom { random } if !random.is_empty() => Some(random),
+        _ => {
+            warn!("Unexpected NSM response to GetRandom, falling back to the OS RNG");
+            None
+        }
+    }
+}
+
+#[cfg(not(feature = "nsm-entropy"))]
+fn nsm_random() -> Option<Vec<u8>> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mix() {
+        let os = [1u8; 32];
+        // Both sources contribute and the OS bytes alone differ from either.
+        assert_ne!(mix(Some(&[2u8; 32]), &os), mix(Some(&[3u8; 32]), &os));
+        assert_ne!(mix(Some(&[2u8; 32]), &os), mix(None, &os));
+        assert_ne!(mix(None, &os), mix(None, &[4u8; 32]));
+        // Bytes cannot move between sources.
+        assert_ne!(mix(Some(&[1u8; 33]), &[1u8; 31]), mix(Some(&[1u8; 32]), &os));
+    }
+}
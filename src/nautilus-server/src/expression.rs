@@ -0,0 +1,331 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-feed scaling expressions such as `value * 10^expo`, for providers that
+//! report a price as a mantissa and exponent rather than a decimal. `value` is
+//! the extracted `response_field`; other variables are extracted from the
+//! fields the feed maps them to.
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+
+use crate::app::extract_price;
+
+/// Name the extracted `response_field` is bound to.
+pub const VALUE_VARIABLE: &str = "value";
+
+/// Largest exponent accepted by `^`, the precision of `Decimal`.
+const MAX_EXPONENT: i64 = 28;
+
+/// `[feeds."0x...".scale]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Scale {
+    /// Arithmetic over `value` and `variables` with + - * / ^ and parentheses
+    pub expression: String,
+    /// Field path each further variable is extracted from
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+}
+
+impl Scale {
+    /// Check the expression parses and only uses known variables.
+    pub fn validate(&self) -> Result<(), String> {
+        let expression = Expression::parse(&self.expression)?;
+        for name in expression.variables() {
+            if name != VALUE_VARIABLE && !self.variables.contains_key(&name) {
+                return Err(format!("unknown variable '{}' in '{}'", name, self.expression));
+            }
+        }
+        Ok(())
+    }
+
+    /// Evaluate the expression for an upstream response whose
+    /// `response_field` holds `value`.
+    pub fn apply(&self, json: &Value, value: Decimal) -> Result<Decimal, String> {
+        let expression = Expression::parse(&self.expression)?;
+        let mut bindings = HashMap::from([(VALUE_VARIABLE.to_string(), value)]);
+        for (name, field) in &self.variables {
+            bindings.insert(name.clone(), extract_price(json, field)?);
+        }
+        expression.eval(&bindings)
+    }
+}
+
+/// Parsed arithmetic expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(Decimal),
+    Variable(String),
+    Negate(Box<Expression>),
+    Binary(Box<Expression>, Operator, Box<Expression>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+impl Expression {
+    /// Parse `source`. `^` binds tightest and is right associative, then
+    /// unary minus, then `*` and `/`, then `+` and `-`.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let expression = parser.sum()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(expression),
+            Some(token) => Err(format!("unexpected {:?} in '{}'", token, source)),
+        }
+    }
+
+    /// Names of the variables used.
+    pub fn variables(&self) -> BTreeSet<String> {
+        let mut names = BTreeSet::new();
+        self.collect_variables(&mut names);
+        names
+    }
+
+    fn collect_variables(&self, names: &mut BTreeSet<String>) {
+        match self {
+            Expression::Number(_) => {}
+            Expression::Variable(name) => {
+                names.insert(name.clone());
+            }
+            Expression::Negate(inner) => inner.collect_variables(names),
+            Expression::Binary(left, _, right) => {
+                left.collect_variables(names);
+                right.collect_variables(names);
+            }
+        }
+    }
+
+    /// Evaluate with checked arithmetic.
+    pub fn eval(&self, bindings: &HashMap<String, Decimal>) -> Result<Decimal, String> {
+        match self {
+            Expression::Number(n) => Ok(*n),
+            Expression::Variable(name) => bindings
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("unbound variable '{}'", name)),
+            Expression::Negate(inner) => Ok(-inner.eval(bindings)?),
+            Expression::Binary(left, operator, right) => {
+                let (left, right) = (left.eval(bindings)?, right.eval(bindings)?);
+                let result = match operator {
+                    Operator::Add => left.checked_add(right),
+                    Operator::Subtract => left.checked_sub(right),
+                    Operator::Multiply => left.checked_mul(right),
+                    Operator::Divide => left.checked_div(right),
+                    Operator::Power => return power(left, right),
+                };
+                result.ok_or_else(|| format!("arithmetic overflow or division by zero in {:?}", operator))
+            }
+        }
+    }
+}
+
+/// `base` to a whole `exponent` of at most [`MAX_EXPONENT`] in magnitude.
+fn power(base: Decimal, exponent: Decimal) -> Result<Decimal, String> {
+    let exponent = i64::try_from(exponent)
+        .ok()
+        .filter(|e| Decimal::from(*e) == exponent && e.abs() <= MAX_EXPONENT)
+        .ok_or_else(|| format!("exponent must be a whole number of at most {}, got {}", MAX_EXPONENT, exponent))?;
+    let mut result = Decimal::ONE;
+    for _ in 0..exponent.abs() {
+        result = result.checked_mul(base).ok_or("arithmetic overflow in Power")?;
+    }
+    if exponent < 0 {
+        result = Decimal::ONE
+            .checked_div(result)
+            .ok_or("arithmetic overflow or division by zero in Power")?;
+    }
+    Ok(result)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(Decimal),
+    Identifier(String),
+    Operator(char),
+    Open,
+    Close,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek().filter(|(_, c)| c.is_ascii_digit() || *c == '.') {
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let number = &source[start..end];
+            tokens.push(Token::Number(
+                number.parse().map_err(|_| format!("invalid number '{}'", number))?,
+            ));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek().filter(|(_, c)| c.is_ascii_alphanumeric() || *c == '_') {
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token::Identifier(source[start..end].to_string()));
+        } else {
+            tokens.push(match c {
+                '+' | '-' | '*' | '/' | '^' => Token::Operator(c),
+                '(' => Token::Open,
+                ')' => Token::Close,
+                _ => return Err(format!("unexpected '{}' in '{}'", c, source)),
+            });
+            chars.next();
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek_operator(&self, operators: &[char]) -> Option<char> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(c)) if operators.contains(c) => Some(*c),
+            _ => None,
+        }
+    }
+
+    fn sum(&mut self) -> Result<Expression, String> {
+        let mut left = self.product()?;
+        while let Some(c) = self.peek_operator(&['+', '-']) {
+            self.position += 1;
+            let operator = if c == '+' { Operator::Add } else { Operator::Subtract };
+            left = Expression::Binary(Box::new(left), operator, Box::new(self.product()?));
+        }
+        Ok(left)
+    }
+
+    fn product(&mut self) -> Result<Expression, String> {
+        let mut left = self.unary()?;
+        while let Some(c) = self.peek_operator(&['*', '/']) {
+            self.position += 1;
+            let operator = if c == '*' { Operator::Multiply } else { Operator::Divide };
+            left = Expression::Binary(Box::new(left), operator, Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        if self.peek_operator(&['-']).is_some() {
+            self.position += 1;
+            return Ok(Expression::Negate(Box::new(self.unary()?)));
+        }
+        self.power()
+    }
+
+    fn power(&mut self) -> Result<Expression, String> {
+        let base = self.atom()?;
+        if self.peek_operator(&['^']).is_some() {
+            self.position += 1;
+            // Right associative, and `10^-8` is allowed
+            let exponent = self.unary()?;
+            return Ok(Expression::Binary(Box::new(base), Operator::Power, Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<Expression, String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        match token {
+            Some(Token::Number(n)) => Ok(Expression::Number(n)),
+            Some(Token::Identifier(name)) => Ok(Expression::Variable(name)),
+            Some(Token::Open) => {
+                let inner = self.sum()?;
+                match self.tokens.get(self.position) {
+                    Some(Token::Close) => {
+                        self.position += 1;
+                        Ok(inner)
+                    }
+                    _ => Err("missing closing parenthesis".to_string()),
+                }
+            }
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::str::FromStr;
+
+    fn eval(source: &str, bindings: &[(&str, &str)]) -> Result<Decimal, String> {
+        let bindings = bindings
+            .iter()
+            .map(|(name, value)| (name.to_string(), Decimal::from_str(value).unwrap()))
+            .collect();
+        Expression::parse(source)?.eval(&bindings)
+    }
+
+    #[test]
+    fn test_eval() {
+        assert_eq!(eval("1 + 2 * 3", &[]).unwrap(), Decimal::from(7));
+        assert_eq!(eval("(1 + 2) * 3", &[]).unwrap(), Decimal::from(9));
+        assert_eq!(eval("2^3^2", &[]).unwrap(), Decimal::from(512));
+        assert_eq!(eval("-2^2", &[]).unwrap(), Decimal::from(-4));
+        assert_eq!(
+            eval("value * 10^expo", &[("value", "6543210000"), ("expo", "-8")]).unwrap(),
+            Decimal::from_str("65.4321").unwrap()
+        );
+        assert!(eval("1 / 0", &[]).is_err());
+        assert!(eval("10^0.5", &[]).is_err());
+        assert!(eval("10^29", &[]).is_err());
+        assert!(eval("missing", &[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Expression::parse("value *").is_err());
+        assert!(Expression::parse("(value").is_err());
+        assert!(Expression::parse("value value").is_err());
+        assert!(Expression::parse("value % 2").is_err());
+        assert_eq!(
+            Expression::parse("value * 10^expo").unwrap().variables(),
+            BTreeSet::from(["expo".to_string(), "value".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_scale() {
+        // Pyth style mantissa and exponent
+        let scale = Scale {
+            expression: "value * 10^expo".to_string(),
+            variables: HashMap::from([("expo".to_string(), "price.expo".to_string())]),
+        };
+        scale.validate().unwrap();
+        let json = json!({"price": {"price": "6543210000", "expo": -8}});
+        assert_eq!(
+            scale.apply(&json, Decimal::from(6543210000_u64)).unwrap(),
+            Decimal::from_str("65.4321").unwrap()
+        );
+
+        let unknown = Scale {
+            expression: "value * 10^exponent".to_string(),
+            ..scale
+        };
+        assert!(unknown.validate().is_err());
+    }
+}
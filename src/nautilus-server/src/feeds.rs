@@ -0,0 +1,85 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Listing of the PriceFeeds of an oracle, read from chain, so operators can
+//! see what the enclave can serve without running an indexer.
+
+use axum::extract::{Query, State};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::config::is_valid_object_id;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Query parameters of `GET /feeds`.
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct FeedsQuery {
+    /// Object id of the oracle whose feeds are listed
+    pub oracle_id: String,
+}
+
+/// A PriceFeed of the oracle. API keys are left out.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct FeedListing {
+    pub price_feed_id: String,
+    pub is_valid: bool,
+    pub underlying_url: String,
+    pub response_field: String,
+    /// Whether the feed has a `[feeds."0x..."]` section in the config
+    pub configured: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FeedsResponse {
+    pub oracle_id: String,
+    /// Sorted by id
+    pub feeds: Vec<FeedListing>,
+}
+
+/// Endpoint that lists every PriceFeed of an oracle, found among the oracle
+/// object's dynamic object fields and the objects it owns.
+#[utoipa::path(
+    get,
+    path = "/feeds",
+    tag = "price feed",
+    params(FeedsQuery),
+    responses(
+        (status = 200, body = FeedsResponse),
+        (status = 400, description = "Invalid oracle id or failed Sui RPC", body = crate::ErrorResponse),
+    )
+)]
+pub async fn list_feeds(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<FeedsQuery>,
+) -> Result<Json<FeedsResponse>, EnclaveError> {
+    if !is_valid_object_id(&query.oracle_id) {
+        return Err(EnclaveError::GenericError(format!(
+            "'{}' is not an object id",
+            query.oracle_id
+        )));
+    }
+    let price_feeds = state
+        .sui_client
+        .load_full()
+        .list_feeds_for_oracle(&query.oracle_id)
+        .await
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to list feeds: {:#}", e)))?;
+    let config = state.config.load();
+    let feeds = price_feeds
+        .into_iter()
+        .map(|(price_feed_id, price_feed)| FeedListing {
+            configured: config.feeds.contains_key(&price_feed_id),
+            price_feed_id,
+            is_valid: price_feed.is_valid,
+            underlying_url: price_feed.underlying_url,
+            response_field: price_feed.response_field,
+        })
+        .collect();
+    Ok(Json(FeedsResponse {
+        oracle_id: query.oracle_id,
+        feeds,
+    }))
+}
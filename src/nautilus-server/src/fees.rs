@@ -0,0 +1,162 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Signed fee quotes for a paid oracle: a client asks what a request will
+//! cost, pays on chain against the signed quote and the contract checks the
+//! payment matches it before accepting the price update.
+
+use axum::{extract::State, Json};
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+use std::sync::Arc;
+use tracing::{info, info_span};
+use utoipa::ToSchema;
+
+use crate::common::{
+    current_timestamp_ms, to_signed_response, IntentMessage, IntentScope, ProcessDataRequest,
+    ProcessedDataResponse,
+};
+use crate::config::Config;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Basis points in a multiplier of one.
+const BPS: u128 = 10_000;
+
+/// Fee schedule, in MIST.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Fees {
+    /// Fee for a standard priority request to a feed without its own fee
+    #[serde(default)]
+    pub base_fee_mist: u64,
+    /// Multiplier applied to the fee of high priority requests, in basis
+    /// points
+    #[serde(default = "default_high_priority_bps")]
+    pub high_priority_bps: u64,
+    /// How long a quote can be paid against
+    #[serde(default = "default_quote_ttl_secs")]
+    pub quote_ttl_secs: u64,
+}
+
+impl Default for Fees {
+    fn default() -> Self {
+        Self {
+            base_fee_mist: 0,
+            high_priority_bps: default_high_priority_bps(),
+            quote_ttl_secs: default_quote_ttl_secs(),
+        }
+    }
+}
+
+fn default_high_priority_bps() -> u64 {
+    20_000
+}
+
+fn default_quote_ttl_secs() -> u64 {
+    60
+}
+
+/// Request class a fee is quoted for.
+#[derive(
+    Serialize_repr, Deserialize_repr, Debug, Clone, Copy, Default, PartialEq, Eq, ToSchema,
+)]
+#[repr(u8)]
+pub enum Priority {
+    #[default]
+    Standard = 0,
+    High = 1,
+}
+
+/// Fee for a request to `price_feed_id` at `priority`, in MIST.
+pub fn fee_mist(config: &Config, price_feed_id: &str, priority: Priority) -> u64 {
+    let base = config.feed(price_feed_id).fee_mist.unwrap_or(config.fees.base_fee_mist);
+    match priority {
+        Priority::Standard => base,
+        Priority::High => {
+            let fee = base as u128 * config.fees.high_priority_bps as u128 / BPS;
+            u64::try_from(fee).unwrap_or(u64::MAX)
+        }
+    }
+}
+
+/// Inner type T for ProcessDataRequest<T>
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FeeQuoteRequest {
+    pub price_feed_id: String,
+    #[serde(default)]
+    pub priority: Priority,
+}
+
+/// Inner type T for IntentMessage<T>, signed under the `FeeQuote` intent.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct FeeQuoteResponse {
+    pub price_feed_id: String,
+    pub priority: Priority,
+    pub fee_mist: u64,
+    /// Payments against the quote are rejected after this time
+    pub expires_at_ms: u64,
+}
+
+/// Endpoint that returns a signed quote of the fee for a request class.
+#[utoipa::path(
+    post,
+    path = "/fee_quote",
+    tag = "price feed",
+    request_body = ProcessDataRequest<FeeQuoteRequest>,
+    responses(
+        (status = 200, description = "Signed quote", body = ProcessedDataResponse<IntentMessage<FeeQuoteResponse>>),
+        (status = 400, body = crate::ErrorResponse),
+    )
+)]
+pub async fn fee_quote(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<ProcessDataRequest<FeeQuoteRequest>>,
+) -> Result<Json<ProcessedDataResponse<IntentMessage<FeeQuoteResponse>>>, EnclaveError> {
+    info!("fee quote called");
+
+    let config = state.config.load();
+    let FeeQuoteRequest {
+        price_feed_id,
+        priority,
+    } = request.payload;
+
+    let current_timestamp = current_timestamp_ms()?;
+    let quote = FeeQuoteResponse {
+        fee_mist: fee_mist(&config, &price_feed_id, priority),
+        price_feed_id,
+        priority,
+        expires_at_ms: current_timestamp + config.fees.quote_ttl_secs * 1000,
+    };
+
+    let kp = state.signing_keys.signer_for(IntentScope::FeeQuote, current_timestamp, current_timestamp)?;
+    let signed = info_span!("sign")
+        .in_scope(|| to_signed_response(&kp, quote, current_timestamp, IntentScope::FeeQuote));
+    Ok(Json(signed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FeedConfig;
+
+    #[test]
+    fn test_fee_mist() {
+        let mut config = Config::default();
+        config.fees.base_fee_mist = 1_000;
+        config.feeds.insert(
+            "premium".to_string(),
+            FeedConfig {
+                fee_mist: Some(5_000),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(fee_mist(&config, "other", Priority::Standard), 1_000);
+        assert_eq!(fee_mist(&config, "other", Priority::High), 2_000);
+        assert_eq!(fee_mist(&config, "premium", Priority::Standard), 5_000);
+        assert_eq!(fee_mist(&config, "premium", Priority::High), 10_000);
+
+        config.fees.base_fee_mist = u64::MAX;
+        assert_eq!(fee_mist(&config, "other", Priority::High), u64::MAX);
+    }
+}
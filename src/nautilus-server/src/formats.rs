@@ -0,0 +1,274 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Upstream bodies other than JSON. A feed's `response_format` turns a CSV
+//! or plain-text body into a JSON document, which `response_field`,
+//! `timestamp_field` and scaling variables are then read from as usual:
+//!
+//! - `csv`: the row at `csv.row` as an object keyed by column name, taken
+//!   from the header line or, for files without one, from `csv.columns`, so
+//!   `response_field` selects the column;
+//! - `text`: `{"value": <the trimmed body>}`, so `response_field` is `value`;
+//! - `xml`: the element tree, read with XPath-like selectors, see [`crate::xml`].
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::limits::UpstreamLimits;
+use crate::xml;
+
+/// Field holding a plain-text body.
+pub const TEXT_FIELD: &str = "value";
+/// Media types accepted for CSV bodies.
+const CSV_MEDIA_TYPES: &[&str] = &["text/csv", "application/csv", "text/plain"];
+/// Media types accepted for plain-text bodies.
+const TEXT_MEDIA_TYPES: &[&str] = &["text/plain"];
+/// Media types accepted for XML bodies, besides any ending in `+xml`.
+const XML_MEDIA_TYPES: &[&str] = &["application/xml", "text/xml"];
+
+/// Format of an upstream response body.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseFormat {
+    #[default]
+    Json,
+    Csv,
+    Text,
+    Xml,
+}
+
+/// Selectors of CSV bodies.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CsvFormat {
+    /// Index of the data row read, from 0; negative indices count from the
+    /// end, so -1 is the last row
+    #[serde(default)]
+    pub row: i64,
+    /// Names of the columns, for files without a header line
+    #[serde(default)]
+    pub columns: Vec<String>,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+}
+
+impl Default for CsvFormat {
+    fn default() -> Self {
+        Self {
+            row: 0,
+            columns: Vec::new(),
+            delimiter: default_delimiter(),
+        }
+    }
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+/// How an upstream body is read, set per feed.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct UpstreamFormat {
+    #[serde(default)]
+    pub response_format: ResponseFormat,
+    #[serde(default)]
+    pub csv: CsvFormat,
+}
+
+impl UpstreamFormat {
+    /// Check the config of the feed at `prefix`, returning the problems found.
+    pub fn validate(&self, prefix: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.csv.delimiter.is_ascii() {
+            problems.push(format!(
+                "{}.csv.delimiter must be an ASCII character",
+                prefix
+            ));
+        }
+        if self.csv.columns.iter().any(String::is_empty) {
+            problems.push(format!("{}.csv.columns must not be empty names", prefix));
+        }
+        problems
+    }
+
+    /// Check the Content-Type header of a response. JSON responses are
+    /// checked against `upstream_limits.content_types`, other formats against
+    /// their own media types unless that list is empty.
+    pub fn check_content_type(
+        &self,
+        limits: &UpstreamLimits,
+        content_type: Option<&str>,
+    ) -> Result<(), String> {
+        let media_types = match self.response_format {
+            ResponseFormat::Json => return limits.check_content_type(content_type),
+            _ if limits.content_types.is_empty() => return Ok(()),
+            ResponseFormat::Csv => CSV_MEDIA_TYPES,
+            ResponseFormat::Text => TEXT_MEDIA_TYPES,
+            ResponseFormat::Xml => XML_MEDIA_TYPES,
+        };
+        let content_type = content_type.ok_or("Upstream response has no Content-Type")?;
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        let is_xml_suffix = self.response_format == ResponseFormat::Xml
+            && media_type.to_ascii_lowercase().ends_with("+xml");
+        if is_xml_suffix
+            || media_types
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(media_type))
+        {
+            Ok(())
+        } else {
+            Err(format!(
+                "Upstream Content-Type '{}' is not one of {}",
+                content_type,
+                media_types.join(", ")
+            ))
+        }
+    }
+
+    /// Parse a decoded body into the JSON document fields are read from.
+    pub fn parse(&self, limits: &UpstreamLimits, body: &[u8]) -> Result<Value, String> {
+        match self.response_format {
+            ResponseFormat::Json => limits.parse(body),
+            ResponseFormat::Csv => self.parse_csv(body),
+            ResponseFormat::Xml => xml::to_json(body, limits.max_depth),
+            ResponseFormat::Text => {
+                let text = std::str::from_utf8(body)
+                    .map_err(|_| "Upstream response is not UTF-8 text".to_string())?
+                    .trim();
+                if text.is_empty() {
+                    return Err("Upstream response is empty".to_string());
+                }
+                let mut document = Map::new();
+                document.insert(TEXT_FIELD.to_string(), Value::String(text.to_string()));
+                Ok(Value::Object(document))
+            }
+        }
+    }
+
+    fn parse_csv(&self, body: &[u8]) -> Result<Value, String> {
+        let selectors = &self.csv;
+        let mut reader = csv::ReaderBuilder::new()
+            .delimiter(selectors.delimiter as u8)
+            .has_headers(selectors.columns.is_empty())
+            .trim(csv::Trim::All)
+            .from_reader(body);
+        let columns: Vec<String> = if selectors.columns.is_empty() {
+            let headers = reader
+                .headers()
+                .map_err(|e| format!("Failed to read CSV header: {}", e))?;
+            headers.iter().map(str::to_string).collect()
+        } else {
+            selectors.columns.clone()
+        };
+        let rows = reader
+            .records()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Failed to parse CSV response: {}", e))?;
+
+        let index = if selectors.row >= 0 {
+            Some(selectors.row as usize)
+        } else {
+            rows.len()
+                .checked_sub(selectors.row.unsigned_abs() as usize)
+        };
+        let row = index.and_then(|index| rows.get(index)).ok_or_else(|| {
+            format!(
+                "CSV response has no row {} among {} rows",
+                selectors.row,
+                rows.len()
+            )
+        })?;
+        if row.len() != columns.len() {
+            return Err(format!(
+                "CSV row {} has {} fields for {} columns",
+                selectors.row,
+                row.len(),
+                columns.len()
+            ));
+        }
+        Ok(Value::Object(
+            columns
+                .into_iter()
+                .zip(row.iter().map(|field| Value::String(field.to_string())))
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn csv_format(row: i64, columns: &[&str]) -> UpstreamFormat {
+        UpstreamFormat {
+            response_format: ResponseFormat::Csv,
+            csv: CsvFormat {
+                row,
+                columns: columns.iter().map(|c| c.to_string()).collect(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let limits = UpstreamLimits::default();
+        let body = b"date,close\n2025-01-02, 101.5\n2025-01-03,\"102.25\"\n";
+        assert_eq!(
+            csv_format(0, &[]).parse(&limits, body).unwrap(),
+            json!({ "date": "2025-01-02", "close": "101.5" })
+        );
+        assert_eq!(
+            csv_format(-1, &[]).parse(&limits, body).unwrap(),
+            json!({ "date": "2025-01-03", "close": "102.25" })
+        );
+        assert!(csv_format(2, &[]).parse(&limits, body).is_err());
+        assert!(csv_format(-3, &[]).parse(&limits, body).is_err());
+
+        let headerless = b"2025-01-02,101.5\n";
+        assert_eq!(
+            csv_format(0, &["date", "close"])
+                .parse(&limits, headerless)
+                .unwrap(),
+            json!({ "date": "2025-01-02", "close": "101.5" })
+        );
+        assert!(csv_format(0, &["close"])
+            .parse(&limits, headerless)
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_text() {
+        let limits = UpstreamLimits::default();
+        let text = UpstreamFormat {
+            response_format: ResponseFormat::Text,
+            ..Default::default()
+        };
+        assert_eq!(
+            text.parse(&limits, b" 4321.5\n").unwrap(),
+            json!({ "value": "4321.5" })
+        );
+        assert!(text.parse(&limits, b"\n").is_err());
+    }
+
+    #[test]
+    fn test_check_content_type() {
+        let limits = UpstreamLimits::default();
+        assert!(csv_format(0, &[])
+            .check_content_type(&limits, Some("text/csv; charset=utf-8"))
+            .is_ok());
+        assert!(csv_format(0, &[])
+            .check_content_type(&limits, Some("application/json"))
+            .is_err());
+        assert!(UpstreamFormat::default()
+            .check_content_type(&limits, Some("text/csv"))
+            .is_err());
+        let xml = UpstreamFormat {
+            response_format: ResponseFormat::Xml,
+            ..Default::default()
+        };
+        assert!(xml
+            .check_content_type(&limits, Some("application/vnd.sdmx.genericdata+xml"))
+            .is_ok());
+    }
+}
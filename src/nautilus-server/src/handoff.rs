@@ -0,0 +1,514 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Warm restart: a replacement instance pulls the history and signing keys of
+//! the instance it replaces. Both sides attest to an ephemeral X25519 key, and
+//! the state is encrypted under the key they agree on, so it is only readable
+//! inside an enclave running an allowed image.
+
+use anyhow::{Context, Result};
+use axum::{extract::State, Json};
+use fastcrypto::aes::{Aes256Gcm, AesKey, AuthenticatedCipher, InitializationVector};
+use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{Generate, ToFromBytes};
+use hkdf::Hkdf;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tracing::{info, warn};
+use typenum::U12;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+use crate::app::PriceFeedResponse;
+use crate::attestation::{verify_attestation, AWS_NITRO_ROOT_SHA256};
+use crate::batch::SignedPrices;
+use crate::common::{attestation_document, current_timestamp_ms, IntentScope};
+use crate::config::{Config, KeyConfig, KeyMode};
+use crate::history::Observation;
+use crate::keys::{
+    load_or_generate_keypair_at, persist_keypair, persist_keypair_at, scoped_key_path, seal_keypair,
+    sealing_key, unseal_keypair, SigningKeys,
+};
+use crate::oracle::Signed;
+use crate::outbound::Outbound;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Domain separator for the derived channel key.
+const HANDOFF_INFO: &[u8] = b"nautilus-handoff-v1";
+/// Length of the AES-GCM nonce prefixed to the ciphertext.
+const NONCE_LENGTH: usize = 12;
+
+/// Handoff settings, read on both the draining and the replacement side.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Handoff {
+    /// Base URL of the instance to take over from. When set, startup waits
+    /// for its state before serving.
+    #[serde(default)]
+    pub source_url: Option<String>,
+    /// PCR0 values (Hex) of enclave images allowed on the other end. Usually
+    /// the current and the next release.
+    #[serde(default)]
+    pub allowed_pcr0: Vec<String>,
+    /// SHA-256 of the DER root certificate attestations must chain to
+    #[serde(default = "default_root_sha256")]
+    pub root_sha256: String,
+    /// Hand over the signing keys too, so the on-chain registration stays
+    /// valid: the main key with any rotation in progress, the scoped keys and
+    /// the retirements committed to
+    #[serde(default = "default_transfer_key")]
+    pub transfer_key: bool,
+}
+
+impl Default for Handoff {
+    fn default() -> Self {
+        Self {
+            source_url: None,
+            allowed_pcr0: Vec::new(),
+            root_sha256: default_root_sha256(),
+            transfer_key: default_transfer_key(),
+        }
+    }
+}
+
+fn default_root_sha256() -> String {
+    AWS_NITRO_ROOT_SHA256.to_string()
+}
+
+fn default_transfer_key() -> bool {
+    true
+}
+
+/// Signing key as handed over. In sealed mode it stays sealed under the
+/// KMS-released sealing key both instances share.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransferredKey {
+    /// Private key bytes in Hex
+    Raw(String),
+    /// Output of `seal_keypair`
+    Sealed(String),
+}
+
+impl TransferredKey {
+    fn new(config: &KeyConfig, kp: &Ed25519KeyPair) -> Result<Self> {
+        match config.mode {
+            KeyMode::Ephemeral => Ok(TransferredKey::Raw(Hex::encode(kp.as_bytes()))),
+            KeyMode::Sealed => Ok(TransferredKey::Sealed(seal_keypair(
+                &sealing_key(&config.sealing_key_env)?,
+                kp,
+            ))),
+        }
+    }
+
+    /// Recover the keypair and, in sealed mode, persist it for later restarts.
+    pub fn keypair(&self, config: &KeyConfig) -> Result<Ed25519KeyPair> {
+        let kp = self.decode(config)?;
+        persist_keypair(config, &kp)?;
+        Ok(kp)
+    }
+
+    /// Recover the keypair without persisting it.
+    pub fn decode(&self, config: &KeyConfig) -> Result<Ed25519KeyPair> {
+        match self {
+            TransferredKey::Raw(hex) => {
+                let bytes = Hex::decode(hex).map_err(|e| anyhow::anyhow!("Invalid key: {}", e))?;
+                Ed25519KeyPair::from_bytes(&bytes)
+                    .map_err(|e| anyhow::anyhow!("Invalid key: {}", e))
+            }
+            TransferredKey::Sealed(sealed) => unseal_keypair(&sealing_key(&config.sealing_key_env)?, sealed),
+        }
+    }
+}
+
+/// Rotated signing key as handed over, with the time it replaces the main key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransferredPendingKey {
+    pub key: TransferredKey,
+    pub activate_at_ms: u64,
+}
+
+/// Pre-signed price as handed over, served by the replacement until it expires.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandedOverPrice {
+    pub signed: Signed<PriceFeedResponse>,
+    /// Time after which the source would not have served it anymore: its
+    /// timestamp plus the longer of `refresher.max_age_ms` and
+    /// `batch.default_max_age_ms`
+    pub expires_at_ms: u64,
+}
+
+/// State handed to the replacement instance.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HandoffBundle {
+    pub history: HashMap<String, Vec<Observation>>,
+    pub key: Option<TransferredKey>,
+    /// Keypairs of the scoped intents, by intent number
+    #[serde(default)]
+    pub scoped_keys: BTreeMap<u8, TransferredKey>,
+    /// Keypair of a rotation still in its overlap window
+    #[serde(default)]
+    pub pending_key: Option<TransferredPendingKey>,
+    /// Retirement deadlines by public key in Hex
+    #[serde(default)]
+    pub retirements: BTreeMap<String, u64>,
+    /// Pre-signed prices by price feed id
+    #[serde(default)]
+    pub signed_prices: BTreeMap<String, HandedOverPrice>,
+}
+
+impl HandoffBundle {
+    /// Keypairs of the intents in `key.scoped_intents`. Handed over ones are
+    /// persisted like the main key in sealed mode, the others are loaded or
+    /// generated as on a cold start.
+    pub fn scoped_keypairs(&self, config: &KeyConfig) -> Result<HashMap<IntentScope, Ed25519KeyPair>> {
+        for intent in self.scoped_keys.keys() {
+            if !config.scoped_intents.iter().any(|&scoped| scoped as u8 == *intent) {
+                warn!("Dropping handed over key of intent {}, which is not in key.scoped_intents", intent);
+            }
+        }
+        config
+            .scoped_intents
+            .iter()
+            .map(|&intent| {
+                let path = scoped_key_path(config, intent);
+                let kp = match self.scoped_keys.get(&(intent as u8)) {
+                    Some(key) => {
+                        let kp = key.decode(config)?;
+                        persist_keypair_at(config, &path, &kp)?;
+                        kp
+                    }
+                    None => load_or_generate_keypair_at(config, &path)?,
+                };
+                Ok((intent, kp))
+            })
+            .collect()
+    }
+
+    /// Resume the rotation in progress and the retirements of the source on
+    /// `keys`, sealing them in sealed mode.
+    pub fn restore_keys(&self, config: &KeyConfig, keys: &SigningKeys) -> Result<()> {
+        if let Some(pending) = &self.pending_key {
+            keys.rotate(pending.key.decode(config)?, pending.activate_at_ms)?;
+            info!("Resumed rotation to a key activating at {}", pending.activate_at_ms);
+        }
+        for (public_key, retire_at_ms) in &self.retirements {
+            let public_key =
+                Hex::decode(public_key).map_err(|e| anyhow::anyhow!("Invalid retired key: {}", e))?;
+            keys.retire(&public_key, *retire_at_ms)?;
+        }
+        Ok(())
+    }
+
+    /// Store the pre-signed prices that have not expired by `now_ms` in
+    /// `prices`, returning how many were kept.
+    pub fn restore_signed_prices(&self, prices: &SignedPrices, now_ms: u64) -> usize {
+        let mut restored = 0;
+        for (price_feed_id, price) in &self.signed_prices {
+            if price.expires_at_ms > now_ms {
+                prices.store(price_feed_id, &price.signed);
+                restored += 1;
+            }
+        }
+        restored
+    }
+}
+
+/// Request for handoff, sent by the replacement.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HandoffRequest {
+    /// Attestation document committed to the replacement's X25519 key, in Hex.
+    pub attestation: String,
+}
+
+/// Response for handoff.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HandoffResponse {
+    /// Attestation document committed to the sender's X25519 key, in Hex.
+    pub attestation: String,
+    /// `nonce || ciphertext` of the JSON bundle, in Hex.
+    pub bundle: String,
+}
+
+/// Endpoint on the draining instance that hands its state to an attested
+/// replacement.
+pub async fn send_handoff(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<HandoffRequest>,
+) -> Result<Json<HandoffResponse>, EnclaveError> {
+    info!("handoff called");
+
+    let config = state.config.load_full();
+    let handoff_err = |e: anyhow::Error| EnclaveError::GenericError(format!("Handoff failed: {:#}", e));
+
+    let document = Hex::decode(&request.attestation)
+        .map_err(|e| EnclaveError::GenericError(format!("Invalid attestation: {}", e)))?;
+    let peer_key = verify_peer(&config.handoff, &document).map_err(handoff_err)?;
+
+    let mut bundle = HandoffBundle {
+        history: state.history.export().map_err(handoff_err)?,
+        key: None,
+        scoped_keys: BTreeMap::new(),
+        pending_key: None,
+        retirements: BTreeMap::new(),
+        signed_prices: BTreeMap::new(),
+    };
+    let max_age_ms = config.refresher.max_age_ms.max(config.batch.default_max_age_ms);
+    let now_ms = current_timestamp_ms()?;
+    for (price_feed_id, signed) in state.signed_prices.export() {
+        let expires_at_ms = signed.response.timestamp_ms.saturating_add(max_age_ms);
+        if expires_at_ms > now_ms {
+            bundle
+                .signed_prices
+                .insert(price_feed_id, HandedOverPrice { signed, expires_at_ms });
+        }
+    }
+    if config.handoff.transfer_key {
+        let transfer = |kp: &Ed25519KeyPair| TransferredKey::new(&config.key, kp).map_err(handoff_err);
+        let kp = state.signing_keys.active_at(current_timestamp_ms()?);
+        bundle.key = Some(transfer(&kp)?);
+        for (intent, kp) in state.signing_keys.scoped_keys() {
+            bundle.scoped_keys.insert(intent as u8, transfer(&kp)?);
+        }
+        if let Some(pending) = state.signing_keys.pending() {
+            bundle.pending_key = Some(TransferredPendingKey {
+                key: transfer(&pending.kp)?,
+                activate_at_ms: pending.activate_at_ms,
+            });
+        }
+        bundle.retirements = state
+            .signing_keys
+            .retirements()
+            .into_iter()
+            .map(|(public_key, retire_at_ms)| (Hex::encode(public_key), retire_at_ms))
+            .collect();
+    }
+
+    let secret = EphemeralSecret::random_from_rng(rand::thread_rng());
+    let public = PublicKey::from(&secret);
+    let attestation = attestation_document(public.as_bytes(), None)?;
+    let channel_key = channel_key(secret, &peer_key, &peer_key, &public).map_err(handoff_err)?;
+    let plaintext = serde_json::to_vec(&bundle)
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to encode bundle: {}", e)))?;
+
+    info!(
+        "Handing off {} feeds, {} pre-signed prices{}",
+        bundle.history.len(),
+        bundle.signed_prices.len(),
+        if bundle.key.is_some() { " and the signing keys" } else { "" }
+    );
+    Ok(Json(HandoffResponse {
+        attestation: Hex::encode(attestation),
+        bundle: Hex::encode(encrypt(&channel_key, &plaintext)),
+    }))
+}
+
+/// Pull the state of the instance at `source_url` for this replacement.
+pub async fn receive_handoff(config: &Config, source_url: &str) -> Result<HandoffBundle> {
+    info!("Requesting handoff from {}", source_url);
+
+    let secret = EphemeralSecret::random_from_rng(rand::thread_rng());
+    let public = PublicKey::from(&secret);
+    let attestation = attestation_document(public.as_bytes(), None)
+        .map_err(|e| anyhow::anyhow!("Failed to attest handoff key: {}", e))?;
+
+    let outbound = Outbound::new(&config.outbound, &config.http_client).await?;
+    let response: HandoffResponse = outbound
+        .client()
+        .post(format!("{}/admin/handoff", source_url.trim_end_matches('/')))
+        .json(&HandoffRequest {
+            attestation: Hex::encode(attestation),
+        })
+        .send()
+        .await
+        .with_context(|| format!("Failed to reach handoff source {}", source_url))?
+        .error_for_status()
+        .context("Handoff source refused the handoff")?
+        .json()
+        .await
+        .context("Invalid handoff response")?;
+
+    let document = Hex::decode(&response.attestation)
+        .map_err(|e| anyhow::anyhow!("Invalid source attestation: {}", e))?;
+    let peer_key = verify_peer(&config.handoff, &document)?;
+    let channel_key = channel_key(secret, &peer_key, &public, &peer_key)?;
+    let ciphertext = Hex::decode(&response.bundle)
+        .map_err(|e| anyhow::anyhow!("Invalid handoff bundle: {}", e))?;
+    let bundle: HandoffBundle = serde_json::from_slice(&decrypt(&channel_key, &ciphertext)?)
+        .context("Invalid handoff bundle")?;
+
+    info!("Received handoff of {} feeds", bundle.history.len());
+    Ok(bundle)
+}
+
+/// Verify the peer's attestation against the handoff policy and return the
+/// X25519 key it commits to.
+fn verify_peer(config: &Handoff, document: &[u8]) -> Result<PublicKey> {
+    let doc = verify_attestation(document, &config.root_sha256)?;
+    let pcr0 = doc
+        .pcr0()
+        .ok_or_else(|| anyhow::anyhow!("Peer attestation has no PCR0"))?;
+    if !config
+        .allowed_pcr0
+        .iter()
+        .any(|allowed| allowed.eq_ignore_ascii_case(&pcr0))
+    {
+        return Err(anyhow::anyhow!("Peer image {} is not in handoff.allowed_pcr0", pcr0));
+    }
+    let key: [u8; 32] = doc
+        .public_key
+        .as_deref()
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(|| anyhow::anyhow!("Peer attestation does not commit to an X25519 key"))?;
+    Ok(PublicKey::from(key))
+}
+
+/// Derive the AES key for the channel between `receiver` and `sender`.
+fn channel_key(
+    secret: EphemeralSecret,
+    peer: &PublicKey,
+    receiver: &PublicKey,
+    sender: &PublicKey,
+) -> Result<AesKey<typenum::U32>> {
+    let shared = secret.diffie_hellman(peer);
+    let info = [HANDOFF_INFO, receiver.as_bytes(), sender.as_bytes()].concat();
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, shared.as_bytes())
+        .expand(&info, &mut key)
+        .map_err(|_| anyhow::anyhow!("Failed to derive handoff key"))?;
+    AesKey::from_bytes(&key).map_err(|_| anyhow::anyhow!("Invalid handoff key"))
+}
+
+fn encrypt(key: &AesKey<typenum::U32>, plaintext: &[u8]) -> Vec<u8> {
+    let nonce = InitializationVector::<U12>::generate(&mut rand::thread_rng());
+    let ciphertext =
+        Aes256Gcm::<U12>::new(key.clone()).encrypt_authenticated(&nonce, HANDOFF_INFO, plaintext);
+    [nonce.as_bytes(), &ciphertext].concat()
+}
+
+fn decrypt(key: &AesKey<typenum::U32>, bytes: &[u8]) -> Result<Vec<u8>> {
+    if bytes.len() <= NONCE_LENGTH {
+        return Err(anyhow::anyhow!("Handoff bundle is too short"));
+    }
+    let (nonce, ciphertext) = bytes.split_at(NONCE_LENGTH);
+    let nonce = InitializationVector::<U12>::from_bytes(nonce)
+        .map_err(|e| anyhow::anyhow!("Invalid handoff nonce: {}", e))?;
+    Aes256Gcm::<U12>::new(key.clone())
+        .decrypt_authenticated(&nonce, HANDOFF_INFO, ciphertext)
+        .map_err(|_| anyhow::anyhow!("Failed to decrypt handoff bundle"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastcrypto::traits::KeyPair;
+    use crate::attestation::tests::fake_attestation;
+    use crate::batch::tests::signed;
+
+    #[test]
+    fn test_channel_roundtrip() {
+        let receiver_secret = EphemeralSecret::random_from_rng(rand::thread_rng());
+        let receiver = PublicKey::from(&receiver_secret);
+        let sender_secret = EphemeralSecret::random_from_rng(rand::thread_rng());
+        let sender = PublicKey::from(&sender_secret);
+
+        let sender_key = channel_key(sender_secret, &receiver, &receiver, &sender).unwrap();
+        let receiver_key = channel_key(receiver_secret, &sender, &receiver, &sender).unwrap();
+
+        let ciphertext = encrypt(&sender_key, b"state");
+        assert_eq!(decrypt(&receiver_key, &ciphertext).unwrap(), b"state");
+
+        let other = AesKey::generate(&mut rand::thread_rng());
+        assert!(decrypt(&other, &ciphertext).is_err());
+    }
+
+    #[test]
+    fn test_verify_peer_policy() {
+        let x25519 = PublicKey::from(&EphemeralSecret::random_from_rng(rand::thread_rng()));
+        let (document, root_sha256) = fake_attestation(&[1; 48], x25519.as_bytes());
+
+        let mut config = Handoff {
+            root_sha256,
+            ..Default::default()
+        };
+        // Nothing is allowed until the image is listed.
+        assert!(verify_peer(&config, &document).is_err());
+
+        config.allowed_pcr0 = vec![Hex::encode([1; 48])];
+        assert_eq!(verify_peer(&config, &document).unwrap().as_bytes(), x25519.as_bytes());
+    }
+
+    #[test]
+    fn test_transferred_key() {
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let transferred = TransferredKey::new(&KeyConfig::default(), &kp).unwrap();
+        let restored = transferred.keypair(&KeyConfig::default()).unwrap();
+        assert_eq!(restored.as_bytes(), kp.as_bytes());
+    }
+
+    #[test]
+    fn test_restore_keys() {
+        let config = KeyConfig {
+            scoped_intents: vec![IntentScope::Weather],
+            ..Default::default()
+        };
+        let scoped = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let pending = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let pending_pk = pending.public().clone();
+        let bundle = HandoffBundle {
+            history: HashMap::new(),
+            key: None,
+            scoped_keys: BTreeMap::from([
+                (IntentScope::Weather as u8, TransferredKey::new(&config, &scoped).unwrap()),
+                (IntentScope::PriceFeed as u8, TransferredKey::new(&config, &scoped).unwrap()),
+            ]),
+            pending_key: Some(TransferredPendingKey {
+                key: TransferredKey::new(&config, &pending).unwrap(),
+                activate_at_ms: 1_000,
+            }),
+            retirements: BTreeMap::from([(Hex::encode([7; 32]), 2_000)]),
+            signed_prices: BTreeMap::new(),
+        };
+        let bundle: HandoffBundle = serde_json::from_slice(&serde_json::to_vec(&bundle).unwrap()).unwrap();
+
+        // Only the configured scoped intents are taken over.
+        let scoped_keys = bundle.scoped_keypairs(&config).unwrap();
+        assert_eq!(scoped_keys.len(), 1);
+        assert_eq!(scoped_keys[&IntentScope::Weather].as_bytes(), scoped.as_bytes());
+
+        let keys = SigningKeys::new(Ed25519KeyPair::generate(&mut rand::thread_rng()));
+        bundle.restore_keys(&config, &keys).unwrap();
+        assert_eq!(keys.retire_at(&[7; 32]), Some(2_000));
+        assert_eq!(keys.active_at(1_000).public(), &pending_pk);
+    }
+
+    #[test]
+    fn test_restore_signed_prices() {
+        let handed_over = |timestamp_ms: u64, expires_at_ms: u64| HandedOverPrice {
+            signed: signed(timestamp_ms),
+            expires_at_ms,
+        };
+        let bundle = HandoffBundle {
+            history: HashMap::new(),
+            key: None,
+            scoped_keys: BTreeMap::new(),
+            pending_key: None,
+            retirements: BTreeMap::new(),
+            signed_prices: BTreeMap::from([
+                ("0x2".to_string(), handed_over(1_000, 3_000)),
+                ("0x3".to_string(), handed_over(500, 2_000)),
+            ]),
+        };
+        let bundle: HandoffBundle = serde_json::from_slice(&serde_json::to_vec(&bundle).unwrap()).unwrap();
+
+        // Prices that expired in transit are dropped.
+        let prices = SignedPrices::default();
+        assert_eq!(bundle.restore_signed_prices(&prices, 2_000), 1);
+        let restored = prices.get("0x2", u64::MAX, 2_000).unwrap();
+        assert_eq!(restored.response.timestamp_ms, 1_000);
+        assert_eq!(restored.signature, "00");
+        assert!(prices.get("0x3", u64::MAX, 2_000).is_none());
+    }
+}
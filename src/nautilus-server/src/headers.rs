@@ -0,0 +1,266 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Browser facing response headers: a CORS policy so dApps can call the
+//! enclave directly instead of through a relay, and standard security
+//! headers on every response. Both are applied when the router is built, so
+//! changes need a restart rather than a reload.
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, CorsLayer};
+use tower_http::set_header::SetResponseHeaderLayer;
+
+use crate::telemetry;
+
+/// `[cors]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Cors {
+    /// Origins allowed to call the enclave from a browser, e.g.
+    /// "https://app.example.com", or "*" for any. Empty allows none.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    /// Request headers browsers may send, or "*" for any
+    #[serde(default = "default_allowed_headers")]
+    pub allowed_headers: Vec<String>,
+    /// Seconds browsers may cache a preflight response
+    #[serde(default)]
+    pub max_age_secs: Option<u64>,
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: default_allowed_methods(),
+            allowed_headers: default_allowed_headers(),
+            max_age_secs: None,
+        }
+    }
+}
+
+fn default_allowed_methods() -> Vec<String> {
+    vec!["GET".to_string(), "POST".to_string()]
+}
+
+fn default_allowed_headers() -> Vec<String> {
+    [
+        "content-type",
+        "x-consumer-key",
+        "x-sui-signature",
+        "x-sui-timestamp-ms",
+    ]
+    .into_iter()
+    .map(str::to_string)
+    .collect()
+}
+
+impl Cors {
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.allowed_origins.len() > 1 && self.allowed_origins.iter().any(|origin| origin == "*")
+        {
+            problems.push("cors.allowed_origins cannot mix \"*\" with other origins".to_string());
+        }
+        for origin in self.allowed_origins.iter().filter(|origin| *origin != "*") {
+            if HeaderValue::from_str(origin).is_err() || !origin.contains("://") {
+                problems.push(format!(
+                    "cors.allowed_origins '{}' is not an origin",
+                    origin
+                ));
+            }
+        }
+        for method in &self.allowed_methods {
+            if Method::from_bytes(method.as_bytes()).is_err() {
+                problems.push(format!(
+                    "cors.allowed_methods '{}' is not an HTTP method",
+                    method
+                ));
+            }
+        }
+        for name in self.allowed_headers.iter().filter(|name| *name != "*") {
+            if HeaderName::from_bytes(name.as_bytes()).is_err() {
+                problems.push(format!(
+                    "cors.allowed_headers '{}' is not a header name",
+                    name
+                ));
+            }
+        }
+        problems
+    }
+
+    /// The CORS layer for this policy. Invalid entries are skipped; they are
+    /// reported by `validate`.
+    pub fn layer(&self) -> CorsLayer {
+        let origins = if self.allowed_origins.iter().any(|origin| origin == "*") {
+            AllowOrigin::any()
+        } else {
+            AllowOrigin::list(
+                self.allowed_origins
+                    .iter()
+                    .filter_map(|origin| HeaderValue::from_str(origin).ok()),
+            )
+        };
+        let headers = if self.allowed_headers.iter().any(|name| name == "*") {
+            AllowHeaders::any()
+        } else {
+            AllowHeaders::list(
+                self.allowed_headers
+                    .iter()
+                    .filter_map(|name| HeaderName::from_bytes(name.as_bytes()).ok()),
+            )
+        };
+        let methods = AllowMethods::list(
+            self.allowed_methods
+                .iter()
+                .filter_map(|method| Method::from_bytes(method.as_bytes()).ok()),
+        );
+        let layer = CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .expose_headers([
+                HeaderName::from_static(telemetry::REQUEST_ID_HEADER),
+                HeaderName::from_static("retry-after"),
+            ]);
+        match self.max_age_secs {
+            Some(secs) => layer.max_age(Duration::from_secs(secs)),
+            None => layer,
+        }
+    }
+}
+
+/// `[security_headers]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SecurityHeaders {
+    /// Send X-Content-Type-Options, X-Frame-Options and Referrer-Policy
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Strict-Transport-Security max-age, only sensible with `server.tls`
+    #[serde(default)]
+    pub hsts_max_age_secs: Option<u64>,
+    /// Content-Security-Policy to send. Unset by default as Swagger UI needs
+    /// its scripts and styles.
+    #[serde(default)]
+    pub content_security_policy: Option<String>,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self {
+            enabled: default_enabled(),
+            hsts_max_age_secs: None,
+            content_security_policy: None,
+        }
+    }
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl SecurityHeaders {
+    pub fn validate(&self) -> Vec<String> {
+        match &self.content_security_policy {
+            Some(policy) if HeaderValue::from_str(policy).is_err() => {
+                vec![
+                    "security_headers.content_security_policy is not a valid header value"
+                        .to_string(),
+                ]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The headers to add to every response, unless a handler set them.
+    pub fn headers(&self) -> Vec<(HeaderName, HeaderValue)> {
+        let mut headers = Vec::new();
+        if self.enabled {
+            headers.push((
+                HeaderName::from_static("x-content-type-options"),
+                HeaderValue::from_static("nosniff"),
+            ));
+            headers.push((
+                HeaderName::from_static("x-frame-options"),
+                HeaderValue::from_static("DENY"),
+            ));
+            headers.push((
+                HeaderName::from_static("referrer-policy"),
+                HeaderValue::from_static("no-referrer"),
+            ));
+        }
+        if let Some(secs) = self.hsts_max_age_secs {
+            headers.push((
+                HeaderName::from_static("strict-transport-security"),
+                HeaderValue::from_str(&format!("max-age={}; includeSubDomains", secs))
+                    .expect("max-age is a valid header value"),
+            ));
+        }
+        if let Some(policy) = self.content_security_policy.as_deref() {
+            if let Ok(value) = HeaderValue::from_str(policy) {
+                headers.push((HeaderName::from_static("content-security-policy"), value));
+            }
+        }
+        headers
+    }
+}
+
+/// Wrap the router with the CORS policy and security headers.
+pub fn apply(router: Router, cors: &Cors, security: &SecurityHeaders) -> Router {
+    let router = security
+        .headers()
+        .into_iter()
+        .fold(router, |router, (name, value)| {
+            router.layer(SetResponseHeaderLayer::if_not_present(name, value))
+        });
+    router.layer(cors.layer())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_cors() {
+        assert!(Cors::default().validate().is_empty());
+        let cors = Cors {
+            allowed_origins: vec!["*".to_string(), "https://app.example.com".to_string()],
+            allowed_methods: vec!["G ET".to_string()],
+            allowed_headers: vec!["x-consumer-key".to_string(), "bad header".to_string()],
+            max_age_secs: None,
+        };
+        assert_eq!(cors.validate().len(), 3);
+
+        let cors = Cors {
+            allowed_origins: vec!["app.example.com".to_string()],
+            ..Cors::default()
+        };
+        assert_eq!(cors.validate().len(), 1);
+    }
+
+    #[test]
+    fn test_security_headers() {
+        let names = |security: &SecurityHeaders| {
+            security
+                .headers()
+                .into_iter()
+                .map(|(name, _)| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&SecurityHeaders::default()).len(), 3);
+
+        let security = SecurityHeaders {
+            enabled: false,
+            hsts_max_age_secs: Some(31_536_000),
+            content_security_policy: Some("default-src 'none'".to_string()),
+        };
+        assert_eq!(
+            names(&security),
+            ["strict-transport-security", "content-security-policy"]
+        );
+    }
+}
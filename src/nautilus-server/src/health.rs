@@ -0,0 +1,249 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Upstream health: a background prober periodically GETs the `live_url` of
+//! every feed configured under `[feeds]` and keeps the outcome, served by
+//! `GET /health/upstreams` and exported as the `nautilus.upstream.probes`
+//! counter.
+
+use axum::{extract::State, Json};
+use opentelemetry::KeyValue;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::app::{with_api_key, with_feed_headers};
+use crate::common::current_timestamp_ms;
+use crate::config::FeedConfig;
+use crate::oauth::bearer_token;
+use crate::secrets::resolve_api_key;
+use crate::telemetry;
+use crate::types::PriceFeed;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// How often the prober checks whether it has been enabled while disabled.
+const DISABLED_RECHECK: Duration = Duration::from_secs(5);
+
+/// `[health]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Health {
+    /// Seconds between probes of every feed's `live_url`; 0 disables probing
+    #[serde(default = "default_probe_interval_secs")]
+    pub probe_interval_secs: u64,
+    /// Seconds a probe may take before the feed counts as unhealthy
+    #[serde(default = "default_probe_timeout_secs")]
+    pub probe_timeout_secs: u64,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Self {
+            probe_interval_secs: default_probe_interval_secs(),
+            probe_timeout_secs: default_probe_timeout_secs(),
+        }
+    }
+}
+
+fn default_probe_interval_secs() -> u64 {
+    60
+}
+
+fn default_probe_timeout_secs() -> u64 {
+    5
+}
+
+/// Outcome of the last probe of a feed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FeedHealth {
+    pub healthy: bool,
+    /// HTTP status of the `live_url`, if it answered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    pub checked_at_ms: u64,
+    /// Failed probes in a row, 0 when healthy
+    pub consecutive_failures: u32,
+}
+
+/// Health of every probed feed.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UpstreamHealthResponse {
+    /// Whether every probed feed is healthy
+    pub healthy: bool,
+    /// Per feed outcome, keyed by price feed id
+    pub feeds: BTreeMap<String, FeedHealth>,
+}
+
+/// Last probe outcome of each feed.
+#[derive(Default)]
+pub struct UpstreamHealth {
+    feeds: Mutex<BTreeMap<String, FeedHealth>>,
+}
+
+impl UpstreamHealth {
+    /// Record a probe of `price_feed_id`. `outcome` is the HTTP status the
+    /// `live_url` answered with, or why it couldn't be reached.
+    pub fn record(&self, price_feed_id: &str, outcome: Result<u16, String>, latency_ms: u64, now_ms: u64) {
+        let mut feeds = self.feeds.lock().expect("upstream health lock poisoned");
+        let failures = feeds.get(price_feed_id).map_or(0, |health| health.consecutive_failures);
+        let (healthy, status, error) = match outcome {
+            Ok(status) => ((200..300).contains(&status), Some(status), None),
+            Err(e) => (false, None, Some(e)),
+        };
+        feeds.insert(
+            price_feed_id.to_string(),
+            FeedHealth {
+                healthy,
+                status,
+                error,
+                latency_ms,
+                checked_at_ms: now_ms,
+                consecutive_failures: if healthy { 0 } else { failures.saturating_add(1) },
+            },
+        );
+    }
+
+    /// Drop feeds no longer configured, after a reload.
+    pub fn retain(&self, keep: impl Fn(&str) -> bool) {
+        let mut feeds = self.feeds.lock().expect("upstream health lock poisoned");
+        feeds.retain(|price_feed_id, _| keep(price_feed_id));
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, FeedHealth> {
+        self.feeds.lock().expect("upstream health lock poisoned").clone()
+    }
+}
+
+/// Probe the `live_url` of every configured feed once.
+pub async fn probe_feeds(state: &AppState) {
+    let config = state.config.load_full();
+    state.upstream_health.retain(|price_feed_id| config.feeds.contains_key(price_feed_id));
+    let timeout = Duration::from_secs(config.health.probe_timeout_secs);
+    let price_feed_ids: Vec<&str> = config.feeds.keys().map(String::as_str).collect();
+    let price_feeds = state.sui_client.load_full().fetch_price_feeds(&price_feed_ids).await;
+
+    for (price_feed_id, price_feed) in price_feed_ids.into_iter().zip(price_feeds) {
+        let started = Instant::now();
+        let outcome = match price_feed {
+            Ok(price_feed) => {
+                let feed_config = config.feeds.get(price_feed_id);
+                probe_feed(state, price_feed_id, &price_feed, feed_config, timeout).await
+            }
+            Err(e) => Err(format!("Failed to fetch price feed: {}", e)),
+        };
+        let latency_ms = started.elapsed().as_millis() as u64;
+        let healthy = matches!(outcome, Ok(status) if (200..300).contains(&status));
+        if let Err(e) = &outcome {
+            warn!("Probe of feed {} failed: {}", price_feed_id, e);
+        }
+
+        let feed = KeyValue::new("price_feed_id", price_feed_id.to_string());
+        let metrics = telemetry::metrics();
+        metrics
+            .upstream_probe_duration_ms
+            .record(latency_ms as f64, &[feed.clone()]);
+        metrics.upstream_probes.add(
+            1,
+            &[feed, KeyValue::new("outcome", if healthy { "healthy" } else { "unhealthy" })],
+        );
+        let now_ms = current_timestamp_ms().unwrap_or_default();
+        state.upstream_health.record(price_feed_id, outcome, latency_ms, now_ms);
+    }
+}
+
+/// GET the feed's `live_url`, with its API key and configured headers,
+/// returning the HTTP status.
+async fn probe_feed(
+    state: &AppState,
+    price_feed_id: &str,
+    price_feed: &PriceFeed,
+    feed_config: Option<&FeedConfig>,
+    timeout: Duration,
+) -> Result<u16, String> {
+    let request = state.outbound.load().client_for(&price_feed.live_url).get(&price_feed.live_url).timeout(timeout);
+    let request = with_feed_headers(request, feed_config);
+    let api_key = price_feed.api_key.as_deref().zip(price_feed.api_key_config.as_deref());
+    let resolved = resolve_api_key(state, price_feed_id, &price_feed.live_url, api_key)
+        .await
+        .map_err(|e| e.to_string())?;
+    let api_key = resolved.as_ref().map(|(key, scheme)| (key.as_str(), *scheme));
+    let oauth2 = feed_config.and_then(|feed_config| feed_config.oauth2.as_ref());
+    let token = bearer_token(state, api_key, oauth2).await.map_err(|e| e.to_string())?;
+    let request = match &token {
+        Some(token) => with_api_key(request, &price_feed.live_url, Some((token, "Bearer")), None),
+        None => with_api_key(
+            request,
+            &price_feed.live_url,
+            api_key,
+            feed_config.and_then(|feed_config| feed_config.hmac.as_ref()),
+        ),
+    }
+    .map_err(|e| e.to_string())?;
+    let response = request.send().await.map_err(|e| format!("Failed to reach live_url: {}", e))?;
+    Ok(response.status().as_u16())
+}
+
+/// Spawn the background prober. The interval is re-read from the config
+/// before every round, so reloads take effect without a restart.
+pub fn spawn_prober(state: &Arc<AppState>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        info!("Upstream health prober started");
+        loop {
+            let interval_secs = state.config.load().health.probe_interval_secs;
+            if interval_secs == 0 {
+                tokio::time::sleep(DISABLED_RECHECK).await;
+                continue;
+            }
+            probe_feeds(&state).await;
+            tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+        }
+    });
+}
+
+/// Health of each feed's upstream as of its last probe.
+#[utoipa::path(
+    get,
+    path = "/health/upstreams",
+    tag = "health",
+    responses((status = 200, description = "Last probe of every configured feed", body = UpstreamHealthResponse))
+)]
+pub async fn upstream_health(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<UpstreamHealthResponse>, EnclaveError> {
+    let feeds = state.upstream_health.snapshot();
+    Ok(Json(UpstreamHealthResponse {
+        healthy: feeds.values().all(|health| health.healthy),
+        feeds,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_counts_failures() {
+        let health = UpstreamHealth::default();
+        health.record("0x1", Ok(503), 10, 1_000);
+        health.record("0x1", Err("timed out".to_string()), 5_000, 2_000);
+        let feed = &health.snapshot()["0x1"];
+        assert!(!feed.healthy);
+        assert_eq!(feed.consecutive_failures, 2);
+        assert_eq!(feed.status, None);
+
+        health.record("0x1", Ok(200), 10, 3_000);
+        health.record("0x2", Ok(204), 10, 3_000);
+        let feeds = health.snapshot();
+        assert!(feeds.values().all(|feed| feed.healthy && feed.consecutive_failures == 0));
+
+        health.retain(|price_feed_id| price_feed_id == "0x2");
+        assert_eq!(health.snapshot().len(), 1);
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+use axum::extract::{Path, Query, State};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use tracing::warn;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::app::PriceFeedResponse;
+use crate::consumers::ServedUpdate;
+use crate::oracle::Signed;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Number of observations kept per feed.
+pub const DEFAULT_HISTORY_CAPACITY: usize = 1024;
+
+/// A price the enclave has signed for a feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct Observation {
+    pub price: u64,
+    pub timestamp_ms: u64,
+}
+
+/// Storage for signed observations, keeping a bounded number per feed, for
+/// the ledger of updates served to each consumer, and for the prices queued
+/// for pushing on chain.
+pub trait HistoryStore: Send + Sync {
+    /// Append an observation for `price_feed_id`, evicting the oldest ones
+    /// beyond the store's capacity.
+    fn record(&self, price_feed_id: &str, observation: Observation) -> Result<()>;
+
+    /// Most recent observation for `price_feed_id`, if any.
+    fn latest(&self, price_feed_id: &str) -> Result<Option<Observation>>;
+
+    /// Observations for `price_feed_id` timestamped within
+    /// `[from_ms, to_ms]`, oldest first.
+    fn range(&self, price_feed_id: &str, from_ms: u64, to_ms: u64) -> Result<Vec<Observation>>;
+
+    /// Every stored observation by feed, oldest first.
+    fn export(&self) -> Result<HashMap<String, Vec<Observation>>>;
+
+    /// Append `update` to the ledger of `consumer`, evicting the oldest ones
+    /// beyond `capacity`.
+    fn record_served(&self, consumer: &str, update: ServedUpdate, capacity: usize) -> Result<()>;
+
+    /// Updates served to `consumer` signed within `[from_ms, to_ms]`, oldest
+    /// first.
+    fn served(&self, consumer: &str, from_ms: u64, to_ms: u64) -> Result<Vec<ServedUpdate>>;
+
+    /// Replace the stored push queue with `prices`.
+    fn save_push_queue(&self, prices: &[Signed<PriceFeedResponse>]) -> Result<()>;
+
+    /// The stored push queue.
+    fn push_queue(&self) -> Result<Vec<Signed<PriceFeedResponse>>>;
+
+    /// Write any buffered observations, served updates and queued prices to
+    /// durable storage.
+    fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// In-memory ring buffer of signed observations per price feed, and of
+/// served updates per consumer, with the push queue.
+pub struct MemoryStore {
+    capacity: usize,
+    feeds: RwLock<HashMap<String, VecDeque<Observation>>>,
+    served: RwLock<HashMap<String, VecDeque<ServedUpdate>>>,
+    push_queue: RwLock<Vec<Signed<PriceFeedResponse>>>,
+}
+
+impl MemoryStore {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            feeds: RwLock::new(HashMap::new()),
+            served: RwLock::new(HashMap::new()),
+            push_queue: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Every served update by consumer, oldest first.
+    pub fn export_served(&self) -> HashMap<String, Vec<ServedUpdate>> {
+        let served = self.served.read().expect("ledger lock poisoned");
+        served
+            .iter()
+            .map(|(consumer, updates)| (consumer.clone(), updates.iter().cloned().collect()))
+            .collect()
+    }
+}
+
+impl HistoryStore for MemoryStore {
+    fn record(&self, price_feed_id: &str, observation: Observation) -> Result<()> {
+        let mut feeds = self.feeds.write().expect("history lock poisoned");
+        let observations = feeds.entry(price_feed_id.to_string()).or_default();
+        if observations.len() == self.capacity {
+            observations.pop_front();
+        }
+        observations.push_back(observation);
+        Ok(())
+    }
+
+    fn latest(&self, price_feed_id: &str) -> Result<Option<Observation>> {
+        let feeds = self.feeds.read().expect("history lock poisoned");
+        Ok(feeds.get(price_feed_id).and_then(|o| o.back().copied()))
+    }
+
+    fn range(&self, price_feed_id: &str, from_ms: u64, to_ms: u64) -> Result<Vec<Observation>> {
+        let feeds = self.feeds.read().expect("history lock poisoned");
+        Ok(feeds
+            .get(price_feed_id)
+            .into_iter()
+            .flatten()
+            .filter(|o| (from_ms..=to_ms).contains(&o.timestamp_ms))
+            .copied()
+            .collect())
+    }
+
+    fn export(&self) -> Result<HashMap<String, Vec<Observation>>> {
+        let feeds = self.feeds.read().expect("history lock poisoned");
+        Ok(feeds
+            .iter()
+            .map(|(feed_id, observations)| (feed_id.clone(), observations.iter().copied().collect()))
+            .collect())
+    }
+
+    fn record_served(&self, consumer: &str, update: ServedUpdate, capacity: usize) -> Result<()> {
+        let mut served = self.served.write().expect("ledger lock poisoned");
+        let updates = served.entry(consumer.to_string()).or_default();
+        while updates.len() >= capacity.max(1) {
+            updates.pop_front();
+        }
+        updates.push_back(update);
+        Ok(())
+    }
+
+    fn served(&self, consumer: &str, from_ms: u64, to_ms: u64) -> Result<Vec<ServedUpdate>> {
+        let served = self.served.read().expect("ledger lock poisoned");
+        Ok(served
+            .get(consumer)
+            .into_iter()
+            .flatten()
+            .filter(|update| (from_ms..=to_ms).contains(&update.timestamp_ms))
+            .cloned()
+            .collect())
+    }
+
+    fn save_push_queue(&self, prices: &[Signed<PriceFeedResponse>]) -> Result<()> {
+        *self.push_queue.write().expect("push queue lock poisoned") = prices.to_vec();
+        Ok(())
+    }
+
+    fn push_queue(&self) -> Result<Vec<Signed<PriceFeedResponse>>> {
+        Ok(self.push_queue.read().expect("push queue lock poisoned").clone())
+    }
+}
+
+/// Signed observations per price feed, kept in the configured store.
+/// Storage errors are logged rather than failing the request that signed.
+pub struct PriceHistory {
+    store: Arc<dyn HistoryStore>,
+}
+
+impl PriceHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self::with_store(Arc::new(MemoryStore::new(capacity)))
+    }
+
+    pub fn with_store(store: Arc<dyn HistoryStore>) -> Self {
+        Self { store }
+    }
+
+    /// Append an observation for `price_feed_id`.
+    pub fn record(&self, price_feed_id: &str, observation: Observation) {
+        if let Err(e) = self.store.record(price_feed_id, observation) {
+            warn!("Failed to record observation for {}: {:#}", price_feed_id, e);
+        }
+    }
+
+    /// Most recent observation for `price_feed_id`, if any.
+    pub fn latest(&self, price_feed_id: &str) -> Option<Observation> {
+        self.store.latest(price_feed_id).unwrap_or_else(|e| {
+            warn!("Failed to read history for {}: {:#}", price_feed_id, e);
+            None
+        })
+    }
+
+    /// Observations for `price_feed_id` within `[from_ms, to_ms]`, oldest
+    /// first.
+    pub fn range(&self, price_feed_id: &str, from_ms: u64, to_ms: u64) -> Result<Vec<Observation>> {
+        self.store.range(price_feed_id, from_ms, to_ms)
+    }
+
+    /// Every stored observation by feed, oldest first.
+    pub fn export(&self) -> Result<HashMap<String, Vec<Observation>>> {
+        self.store.export()
+    }
+
+    /// Write any buffered observations to durable storage, before exiting.
+    pub fn flush(&self) {
+        if let Err(e) = self.store.flush() {
+            warn!("Failed to flush history: {:#}", e);
+        }
+    }
+
+    /// Record observations exported by another instance, oldest first.
+    pub fn import(&self, feeds: HashMap<String, Vec<Observation>>) {
+        for (price_feed_id, observations) in feeds {
+            for observation in observations {
+                self.record(&price_feed_id, observation);
+            }
+        }
+    }
+}
+
+impl Default for PriceHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_CAPACITY)
+    }
+}
+
+/// Query parameters for the history endpoint, in milliseconds.
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct HistoryQuery {
+    /// Oldest timestamp included, the start of history by default
+    #[serde(default)]
+    pub from: Option<u64>,
+    /// Newest timestamp included, the present by default
+    #[serde(default)]
+    pub to: Option<u64>,
+}
+
+/// Response for the history endpoint.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct HistoryResponse {
+    pub price_feed_id: String,
+    /// Oldest first
+    pub observations: Vec<Observation>,
+}
+
+/// Endpoint that lists the prices signed for a feed within a time range, as
+/// far back as `persistence.history_capacity` observations reach.
+#[utoipa::path(
+    get,
+    path = "/history/{price_feed_id}",
+    tag = "price feed",
+    params(
+        ("price_feed_id" = String, Path, description = "Price feed object id"),
+        HistoryQuery,
+    ),
+    responses(
+        (status = 200, body = HistoryResponse),
+        (status = 400, body = crate::ErrorResponse),
+    )
+)]
+pub async fn feed_history(
+    State(state): State<Arc<AppState>>,
+    Path(price_feed_id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HistoryResponse>, EnclaveError> {
+    let observations = state
+        .history
+        .range(
+            &price_feed_id,
+            query.from.unwrap_or(0),
+            query.to.unwrap_or(u64::MAX),
+        )
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to read history: {:#}", e)))?;
+    Ok(Json(HistoryResponse {
+        price_feed_id,
+        observations,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_evicts_oldest() {
+        let store = MemoryStore::new(2);
+        assert_eq!(store.latest("feed").unwrap(), None);
+
+        for (price, timestamp_ms) in [(1, 10), (2, 20), (3, 30)] {
+            store.record("feed", Observation { price, timestamp_ms }).unwrap();
+        }
+        assert_eq!(store.latest("feed").unwrap(), Some(Observation { price: 3, timestamp_ms: 30 }));
+        assert_eq!(store.feeds.read().unwrap()["feed"].len(), 2);
+        assert_eq!(store.latest("other").unwrap(), None);
+
+        let exported = store.export().unwrap();
+        assert_eq!(exported["feed"].iter().map(|o| o.price).collect::<Vec<_>>(), vec![2, 3]);
+        let history = PriceHistory::default();
+        history.import(exported);
+        assert_eq!(history.latest("feed").unwrap().price, 3);
+
+        let range = store.range("feed", 25, u64::MAX).unwrap();
+        assert_eq!(range, vec![Observation { price: 3, timestamp_ms: 30 }]);
+        assert!(store.range("other", 0, u64::MAX).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,645 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use arc_swap::{ArcSwap, ArcSwapOption};
+use fastcrypto::aes::{Aes256Gcm, AesKey, AuthenticatedCipher, InitializationVector};
+use fastcrypto::ed25519::{Ed25519KeyPair, Ed25519PublicKey};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{Generate, KeyPair, ToFromBytes};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::{error, info};
+use typenum::U12;
+
+use crate::common::IntentScope;
+use crate::config::{KeyConfig, KeyMode};
+use crate::entropy;
+use crate::EnclaveError;
+
+/// Additional authenticated data bound into every sealed key blob.
+const SEALED_KEY_AAD: &[u8] = b"nautilus-sealed-ed25519-key-v1";
+/// Same for a rotated keypair sealed with its activation time.
+const SEALED_PENDING_KEY_AAD: &[u8] = b"nautilus-sealed-pending-ed25519-key-v1";
+/// Same for the retirement deadlines of the keys.
+const SEALED_RETIREMENTS_AAD: &[u8] = b"nautilus-sealed-key-retirements-v1";
+/// Length of the AES-GCM nonce prefixed to the sealed blob.
+const NONCE_LENGTH: usize = 12;
+
+type SealingCipher = Aes256Gcm<U12>;
+
+/// The signing keypair in use plus, during a rotation, the keypair that
+/// replaces it once its activation time is reached.
+pub struct SigningKeys {
+    active: ArcSwap<Ed25519KeyPair>,
+    pending: ArcSwapOption<PendingKey>,
+    /// Time after which each retired public key no longer signs
+    retirements: Mutex<HashMap<Vec<u8>, u64>>,
+    /// Every key that has signed since startup, oldest first
+    history: Mutex<Vec<KeyPeriod>>,
+    /// Keypairs signing the intents in `key.scoped_intents` instead of the
+    /// active one. They are not rotated.
+    scoped: HashMap<IntentScope, Arc<Ed25519KeyPair>>,
+    /// Where rotations are sealed in `sealed` mode
+    sealed: Option<SealedKeyFiles>,
+}
+
+/// When a key signed. Times are unknown before startup, and the period of
+/// the active key is open-ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyPeriod {
+    pub public_key: Ed25519PublicKey,
+    pub active_from_ms: Option<u64>,
+    pub active_until_ms: Option<u64>,
+}
+
+/// A rotated keypair waiting for its overlap window to end.
+pub struct PendingKey {
+    pub kp: Arc<Ed25519KeyPair>,
+    pub activate_at_ms: u64,
+}
+
+impl SigningKeys {
+    pub fn new(kp: Ed25519KeyPair) -> Self {
+        let period = KeyPeriod {
+            public_key: kp.public().clone(),
+            active_from_ms: None,
+            active_until_ms: None,
+        };
+        Self {
+            active: ArcSwap::from_pointee(kp),
+            pending: ArcSwapOption::empty(),
+            retirements: Mutex::new(HashMap::new()),
+            history: Mutex::new(vec![period]),
+            scoped: HashMap::new(),
+            sealed: None,
+        }
+    }
+
+    /// Seal rotations and retirements to `files`, restoring the keypair an
+    /// earlier rotation left pending there and the retirements committed to.
+    pub fn with_sealed_files(mut self, files: Option<SealedKeyFiles>) -> Result<Self> {
+        if let Some(files) = &files {
+            if let Some(pending) = files.read_pending()? {
+                info!("Restored rotated signing key activating at {}", pending.activate_at_ms);
+                self.pending.store(Some(Arc::new(pending)));
+            }
+            let retirements = files.read_retirements()?;
+            if !retirements.is_empty() {
+                info!("Restored the retirements of {} keys", retirements.len());
+            }
+            *self.retirements.get_mut().expect("retirements lock poisoned") =
+                retirements.into_iter().collect();
+        }
+        self.sealed = files;
+        Ok(self)
+    }
+
+    /// Sign the intents of `scoped` with their own keypairs.
+    pub fn with_scoped_keys(mut self, scoped: HashMap<IntentScope, Ed25519KeyPair>) -> Self {
+        self.scoped = scoped.into_iter().map(|(intent, kp)| (intent, Arc::new(kp))).collect();
+        self
+    }
+
+    /// Keypair to sign with at `now_ms`, promoting the pending keypair first
+    /// if its activation time has passed.
+    pub fn active_at(&self, now_ms: u64) -> Arc<Ed25519KeyPair> {
+        if let Some(pending) = self.pending.load_full() {
+            if now_ms >= pending.activate_at_ms {
+                // Only the caller that clears `pending` performs the swap.
+                let previous = self.pending.compare_and_swap(&pending, None);
+                if previous.as_ref().is_some_and(|p| Arc::ptr_eq(p, &pending)) {
+                    let mut history = self.history.lock().expect("key history lock poisoned");
+                    if let Some(last) = history.last_mut() {
+                        last.active_until_ms = Some(pending.activate_at_ms);
+                    }
+                    history.push(KeyPeriod {
+                        public_key: pending.kp.public().clone(),
+                        active_from_ms: Some(pending.activate_at_ms),
+                        active_until_ms: None,
+                    });
+                    self.active.store(Arc::clone(&pending.kp));
+                    // Only now does the rotated key replace the sealed one
+                    if let Some(files) = &self.sealed {
+                        if let Err(e) = files.promote(&pending.kp) {
+                            error!("Failed to seal the activated signing key: {:#}", e);
+                        }
+                    }
+                    info!("Rotated signing key is now active");
+                }
+            }
+        }
+        self.active.load_full()
+    }
+
+    /// Keypair to sign an intent message timestamped `timestamp_ms` with at
+    /// `now_ms`: the active one, unless it has been retired by then. The
+    /// message timestamp may be the upstream's, so both are checked.
+    pub fn signer_at(&self, now_ms: u64, timestamp_ms: u64) -> Result<Arc<Ed25519KeyPair>, EnclaveError> {
+        let kp = self.active_at(now_ms);
+        match self.retire_at(kp.public().as_bytes()) {
+            Some(retire_at_ms) if now_ms.max(timestamp_ms) > retire_at_ms => {
+                Err(EnclaveError::GenericError(format!(
+                    "The signing key was retired at {}",
+                    retire_at_ms
+                )))
+            }
+            _ => Ok(kp),
+        }
+    }
+
+    /// Keypair to sign an `intent` message timestamped `timestamp_ms` with at
+    /// `now_ms`: its own if it has one, otherwise the active one. Either is
+    /// refused once retired.
+    pub fn signer_for(
+        &self,
+        intent: IntentScope,
+        now_ms: u64,
+        timestamp_ms: u64,
+    ) -> Result<Arc<Ed25519KeyPair>, EnclaveError> {
+        let Some(kp) = self.scoped.get(&intent) else {
+            return self.signer_at(now_ms, timestamp_ms);
+        };
+        match self.retire_at(kp.public().as_bytes()) {
+            Some(retire_at_ms) if now_ms.max(timestamp_ms) > retire_at_ms => {
+                Err(EnclaveError::GenericError(format!(
+                    "The signing key of intent {:?} was retired at {}",
+                    intent, retire_at_ms
+                )))
+            }
+            _ => Ok(Arc::clone(kp)),
+        }
+    }
+
+    /// The keypair of the intent numbered `intent`, if it has its own.
+    pub fn scoped_key(&self, intent: u8) -> Option<(IntentScope, Arc<Ed25519KeyPair>)> {
+        self.scoped
+            .iter()
+            .find(|(scope, _)| **scope as u8 == intent)
+            .map(|(scope, kp)| (*scope, Arc::clone(kp)))
+    }
+
+    /// Every intent with its own keypair, in intent order.
+    pub fn scoped_keys(&self) -> Vec<(IntentScope, Arc<Ed25519KeyPair>)> {
+        let mut scoped: Vec<_> = self.scoped.iter().map(|(scope, kp)| (*scope, Arc::clone(kp))).collect();
+        scoped.sort_by_key(|(scope, _)| *scope as u8);
+        scoped
+    }
+
+    /// Commit that `public_key` signs nothing after `retire_at_ms`. A
+    /// commitment can only be brought forward, never extended; the deadline
+    /// in effect is returned. In `sealed` mode the commitments are sealed
+    /// next to the key, so they outlive a restart.
+    pub fn retire(&self, public_key: &[u8], retire_at_ms: u64) -> Result<u64> {
+        let mut retirements = self.retirements.lock().expect("retirements lock poisoned");
+        let previous = retirements.get(public_key).copied();
+        let deadline = previous.map_or(retire_at_ms, |previous| previous.min(retire_at_ms));
+        if previous != Some(deadline) {
+            if let Some(files) = &self.sealed {
+                let mut sealed: BTreeMap<Vec<u8>, u64> =
+                    retirements.iter().map(|(key, at)| (key.clone(), *at)).collect();
+                sealed.insert(public_key.to_vec(), deadline);
+                files.write_retirements(&sealed)?;
+            }
+            retirements.insert(public_key.to_vec(), deadline);
+        }
+        Ok(deadline)
+    }
+
+    /// Retirement deadlines by public key.
+    pub fn retirements(&self) -> BTreeMap<Vec<u8>, u64> {
+        let retirements = self.retirements.lock().expect("retirements lock poisoned");
+        retirements.iter().map(|(key, at)| (key.clone(), *at)).collect()
+    }
+
+    /// When `public_key` stops signing, if it has been retired.
+    pub fn retire_at(&self, public_key: &[u8]) -> Option<u64> {
+        let retirements = self.retirements.lock().expect("retirements lock poisoned");
+        retirements.get(public_key).copied()
+    }
+
+    /// Every key that has signed up to `now_ms`, oldest first, each period
+    /// ending no later than the key's retirement.
+    pub fn history_at(&self, now_ms: u64) -> Vec<KeyPeriod> {
+        self.active_at(now_ms);
+        let history = self.history.lock().expect("key history lock poisoned").clone();
+        history
+            .into_iter()
+            .map(|mut period| {
+                if let Some(retire_at_ms) = self.retire_at(period.public_key.as_bytes()) {
+                    period.active_until_ms =
+                        Some(period.active_until_ms.map_or(retire_at_ms, |until| until.min(retire_at_ms)));
+                }
+                period
+            })
+            .collect()
+    }
+
+    /// Keypair waiting to become active, if a rotation is in progress.
+    pub fn pending(&self) -> Option<Arc<PendingKey>> {
+        self.pending.load_full()
+    }
+
+    /// Schedule `kp` to replace the active keypair at `activate_at_ms`. Any
+    /// earlier pending keypair is discarded. In `sealed` mode it is sealed
+    /// apart from the active keypair, which it replaces on disk only once
+    /// activated, so a restart during the overlap keeps signing with the old
+    /// key.
+    pub fn rotate(&self, kp: Ed25519KeyPair, activate_at_ms: u64) -> Result<Arc<PendingKey>> {
+        let pending = Arc::new(PendingKey {
+            kp: Arc::new(kp),
+            activate_at_ms,
+        });
+        if let Some(files) = &self.sealed {
+            files.write_pending(&pending)?;
+        }
+        self.pending.store(Some(Arc::clone(&pending)));
+        Ok(pending)
+    }
+}
+
+/// The files of a sealed signing key: the active keypair at
+/// `sealed_key_path` and, during a rotation, the pending one with its
+/// activation time at `<sealed_key_path>.pending`.
+pub struct SealedKeyFiles {
+    path: String,
+    sealing_key: AesKey<typenum::U32>,
+}
+
+impl SealedKeyFiles {
+    /// The files of `config` in `sealed` mode, None for ephemeral keys.
+    pub fn open(config: &KeyConfig) -> Result<Option<Self>> {
+        match config.mode {
+            KeyMode::Ephemeral => Ok(None),
+            KeyMode::Sealed => Ok(Some(Self::new(
+                &config.sealed_key_path,
+                sealing_key(&config.sealing_key_env)?,
+            ))),
+        }
+    }
+
+    pub fn new(sealed_key_path: &str, sealing_key: AesKey<typenum::U32>) -> Self {
+        Self {
+            path: sealed_key_path.to_string(),
+            sealing_key,
+        }
+    }
+
+    fn pending_path(&self) -> String {
+        format!("{}.pending", self.path)
+    }
+
+    fn retirements_path(&self) -> String {
+        format!("{}.retirements", self.path)
+    }
+
+    /// Seal the retirement deadlines of the keys, by public key.
+    pub fn write_retirements(&self, retirements: &BTreeMap<Vec<u8>, u64>) -> Result<()> {
+        let plaintext = bcs::to_bytes(retirements)?;
+        let path = self.retirements_path();
+        fs::write(&path, Hex::encode(seal(&self.sealing_key, SEALED_RETIREMENTS_AAD, &plaintext)))
+            .with_context(|| format!("Failed to write key retirements at: {}", path))
+    }
+
+    /// The retirements sealed by [`write_retirements`](Self::write_retirements).
+    pub fn read_retirements(&self) -> Result<BTreeMap<Vec<u8>, u64>> {
+        let path = self.retirements_path();
+        if !Path::new(&path).exists() {
+            return Ok(BTreeMap::new());
+        }
+        let sealed = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read key retirements at: {}", path))?;
+        let bytes = Hex::decode(sealed.trim())
+            .map_err(|e| anyhow::anyhow!("Key retirements are not valid hex: {}", e))?;
+        let plaintext = unseal(&self.sealing_key, SEALED_RETIREMENTS_AAD, &bytes)
+            .context("Failed to unseal key retirements")?;
+        Ok(bcs::from_bytes(&plaintext)?)
+    }
+
+    /// Seal `pending` with its activation time.
+    pub fn write_pending(&self, pending: &PendingKey) -> Result<()> {
+        let plaintext = [&pending.activate_at_ms.to_le_bytes()[..], pending.kp.as_bytes()].concat();
+        let path = self.pending_path();
+        fs::write(&path, Hex::encode(seal(&self.sealing_key, SEALED_PENDING_KEY_AAD, &plaintext)))
+            .with_context(|| format!("Failed to write pending key at: {}", path))
+    }
+
+    /// The pending keypair sealed by [`write_pending`](Self::write_pending),
+    /// if any.
+    pub fn read_pending(&self) -> Result<Option<PendingKey>> {
+        let path = self.pending_path();
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+        let sealed = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read pending key at: {}", path))?;
+        let bytes = Hex::decode(sealed.trim())
+            .map_err(|e| anyhow::anyhow!("Pending key is not valid hex: {}", e))?;
+        let plaintext = unseal(&self.sealing_key, SEALED_PENDING_KEY_AAD, &bytes)
+            .context("Failed to unseal pending key")?;
+        if plaintext.len() < 8 {
+            return Err(anyhow::anyhow!("Pending key is too short"));
+        }
+        let (activate_at_ms, private_key) = plaintext.split_at(8);
+        let kp = Ed25519KeyPair::from_bytes(private_key)
+            .map_err(|e| anyhow::anyhow!("Unsealed bytes are not a valid Ed25519 key: {}", e))?;
+        Ok(Some(PendingKey {
+            kp: Arc::new(kp),
+            activate_at_ms: u64::from_le_bytes(activate_at_ms.try_into().expect("split at 8")),
+        }))
+    }
+
+    /// Seal `kp`, the activated pending keypair, as the active one and drop
+    /// the pending file.
+    pub fn promote(&self, kp: &Ed25519KeyPair) -> Result<()> {
+        write_sealed_keypair(Path::new(&self.path), &self.sealing_key, kp)?;
+        let path = self.pending_path();
+        fs::remove_file(&path).with_context(|| format!("Failed to remove pending key at: {}", path))
+    }
+}
+
+/// Load the enclave signing keypair according to the configured key mode.
+///
+/// In `sealed` mode the keypair is restored from `sealed_key_path` if present,
+/// otherwise a fresh one is generated and sealed there, so restarts keep the
+/// public key registered on chain.
+pub fn load_or_generate_keypair(config: &KeyConfig) -> Result<Ed25519KeyPair> {
+    load_or_generate_keypair_at(config, &config.sealed_key_path)
+}
+
+/// Load the keypairs of the intents in `key.scoped_intents` the same way,
+/// each sealed next to the main keypair at `<sealed_key_path>.<intent>`.
+pub fn load_or_generate_scoped_keypairs(config: &KeyConfig) -> Result<HashMap<IntentScope, Ed25519KeyPair>> {
+    config
+        .scoped_intents
+        .iter()
+        .map(|&intent| Ok((intent, load_or_generate_keypair_at(config, &scoped_key_path(config, intent))?)))
+        .collect()
+}
+
+/// Where the keypair of a scoped intent is sealed.
+pub fn scoped_key_path(config: &KeyConfig, intent: IntentScope) -> String {
+    format!("{}.{}", config.sealed_key_path, intent as u8)
+}
+
+/// Same as [`load_or_generate_keypair`], with the keypair sealed at `path`.
+pub fn load_or_generate_keypair_at(config: &KeyConfig, path: &str) -> Result<Ed25519KeyPair> {
+    match config.mode {
+        KeyMode::Ephemeral => Ok(Ed25519KeyPair::generate(&mut entropy::key_rng())),
+        KeyMode::Sealed => {
+            let sealing_key = sealing_key(&config.sealing_key_env)?;
+            let path = Path::new(path);
+
+            if path.exists() {
+                let sealed = fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read sealed key at: {}", path.display()))?;
+                let kp = unseal_keypair(&sealing_key, sealed.trim())?;
+                info!("Restored sealed signing key from {}", path.display());
+                Ok(kp)
+            } else {
+                let kp = Ed25519KeyPair::generate(&mut entropy::key_rng());
+                write_sealed_keypair(path, &sealing_key, &kp)?;
+                info!("Generated and sealed new signing key at {}", path.display());
+                Ok(kp)
+            }
+        }
+    }
+}
+
+/// In `sealed` mode, seal `kp` to `sealed_key_path` so it is restored on the
+/// next restart. Does nothing for ephemeral keys.
+pub fn persist_keypair(config: &KeyConfig, kp: &Ed25519KeyPair) -> Result<()> {
+    persist_keypair_at(config, &config.sealed_key_path, kp)
+}
+
+/// Same as [`persist_keypair`], sealing to `path`.
+pub fn persist_keypair_at(config: &KeyConfig, path: &str, kp: &Ed25519KeyPair) -> Result<()> {
+    match config.mode {
+        KeyMode::Ephemeral => Ok(()),
+        KeyMode::Sealed => write_sealed_keypair(
+            Path::new(path),
+            &sealing_key(&config.sealing_key_env)?,
+            kp,
+        ),
+    }
+}
+
+fn write_sealed_keypair(
+    path: &Path,
+    sealing_key: &AesKey<typenum::U32>,
+    kp: &Ed25519KeyPair,
+) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    fs::write(path, seal_keypair(sealing_key, kp))
+        .with_context(|| format!("Failed to write sealed key at: {}", path.display()))
+}
+
+/// Read the 32 byte hex encoded sealing key from the environment. It is
+/// expected to be released to the enclave by KMS against its attestation and
+/// passed in through the secrets channel in `run.sh`.
+pub fn sealing_key(env_var: &str) -> Result<AesKey<typenum::U32>> {
+    let hex = std::env::var(env_var)
+        .with_context(|| format!("{} must be set to use sealed key mode", env_var))?;
+    let bytes = Hex::decode(hex.trim_start_matches("0x"))
+        .map_err(|e| anyhow::anyhow!("{} is not valid hex: {}", env_var, e))?;
+    AesKey::from_bytes(&bytes).map_err(|_| anyhow::anyhow!("{} must be 32 bytes", env_var))
+}
+
+/// Encrypt the keypair's private key, returning hex of `nonce || ciphertext`.
+pub fn seal_keypair(sealing_key: &AesKey<typenum::U32>, kp: &Ed25519KeyPair) -> String {
+    Hex::encode(seal(sealing_key, SEALED_KEY_AAD, kp.as_bytes()))
+}
+
+/// Reverse of [`seal_keypair`]. Fails if the blob was sealed with another key
+/// or has been tampered with.
+pub fn unseal_keypair(sealing_key: &AesKey<typenum::U32>, sealed: &str) -> Result<Ed25519KeyPair> {
+    let bytes = Hex::decode(sealed).map_err(|e| anyhow::anyhow!("Sealed key is not valid hex: {}", e))?;
+    let private_key = unseal(sealing_key, SEALED_KEY_AAD, &bytes).context("Failed to unseal key")?;
+
+    Ed25519KeyPair::from_bytes(&private_key)
+        .map_err(|e| anyhow::anyhow!("Unsealed bytes are not a valid Ed25519 key: {}", e))
+}
+
+/// Encrypt `plaintext` under the sealing key, returning `nonce || ciphertext`.
+/// `aad` is bound into the ciphertext, keeping blobs of different kinds apart.
+pub fn seal(sealing_key: &AesKey<typenum::U32>, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let nonce = InitializationVector::<U12>::generate(&mut rand::thread_rng());
+    let cipher = SealingCipher::new(sealing_key.clone());
+    let ciphertext = cipher.encrypt_authenticated(&nonce, aad, plaintext);
+
+    let mut sealed = nonce.as_bytes().to_vec();
+    sealed.extend_from_slice(&ciphertext);
+    sealed
+}
+
+/// Reverse of [`seal`].
+pub fn unseal(sealing_key: &AesKey<typenum::U32>, aad: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() <= NONCE_LENGTH {
+        return Err(anyhow::anyhow!("Sealed blob is too short"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LENGTH);
+    let nonce = InitializationVector::<U12>::from_bytes(nonce)
+        .map_err(|e| anyhow::anyhow!("Invalid sealed blob nonce: {}", e))?;
+
+    let cipher = SealingCipher::new(sealing_key.clone());
+    cipher
+        .decrypt_authenticated(&nonce, aad, ciphertext)
+        .map_err(|_| anyhow::anyhow!("Wrong sealing key or corrupted blob"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_unseal_roundtrip() {
+        let sealing_key = AesKey::generate(&mut rand::thread_rng());
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+
+        let sealed = seal_keypair(&sealing_key, &kp);
+        let unsealed = unseal_keypair(&sealing_key, &sealed).unwrap();
+        assert_eq!(unsealed.public(), kp.public());
+
+        let other_key = AesKey::generate(&mut rand::thread_rng());
+        assert!(unseal_keypair(&other_key, &sealed).is_err());
+    }
+
+    #[test]
+    fn test_rotation_overlap() {
+        let old = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let new = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let old_pk = old.public().clone();
+        let new_pk = new.public().clone();
+
+        let keys = SigningKeys::new(old);
+        keys.rotate(new, 1_000).unwrap();
+
+        // The old key keeps signing during the overlap window.
+        assert_eq!(keys.active_at(999).public(), &old_pk);
+        assert!(keys.pending().is_some());
+
+        assert_eq!(keys.active_at(1_000).public(), &new_pk);
+        assert!(keys.pending().is_none());
+        assert_eq!(keys.active_at(0).public(), &new_pk);
+    }
+    #[test]
+    fn test_sealed_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sealed_key");
+        let path = path.to_str().unwrap();
+        let sealing_key = AesKey::generate(&mut rand::thread_rng());
+        let files = || Some(SealedKeyFiles::new(path, sealing_key.clone()));
+        let sealed_keypair = || unseal_keypair(&sealing_key, &fs::read_to_string(path).unwrap()).unwrap();
+
+        let old = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let new = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let (old_pk, new_pk) = (old.public().clone(), new.public().clone());
+        write_sealed_keypair(Path::new(path), &sealing_key, &old).unwrap();
+        let keys = SigningKeys::new(old).with_sealed_files(files()).unwrap();
+        keys.rotate(new, 1_000).unwrap();
+        // The sealed key is only replaced once the rotated one activates.
+        assert_eq!(sealed_keypair().public(), &old_pk);
+
+        // A restart during the overlap restores both.
+        let restarted = SigningKeys::new(sealed_keypair()).with_sealed_files(files()).unwrap();
+        assert_eq!(restarted.active_at(999).public(), &old_pk);
+        assert_eq!(restarted.pending().unwrap().activate_at_ms, 1_000);
+        assert_eq!(restarted.active_at(1_000).public(), &new_pk);
+        assert_eq!(sealed_keypair().public(), &new_pk);
+        assert!(!Path::new(&format!("{}.pending", path)).exists());
+        assert!(SigningKeys::new(Ed25519KeyPair::generate(&mut rand::thread_rng()))
+            .with_sealed_files(files())
+            .unwrap()
+            .pending()
+            .is_none());
+    }
+
+    #[test]
+    fn test_retirement() {
+        let old = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let new = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let old_pk = old.public().as_bytes().to_vec();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sealed_key");
+        let sealing_key = AesKey::generate(&mut rand::thread_rng());
+        let files = || Some(SealedKeyFiles::new(path.to_str().unwrap(), sealing_key.clone()));
+        let keys = SigningKeys::new(old).with_sealed_files(files()).unwrap();
+        assert_eq!(keys.retire(&old_pk, 2_000).unwrap(), 2_000);
+        // A retirement can be brought forward but not extended.
+        assert_eq!(keys.retire(&old_pk, 3_000).unwrap(), 2_000);
+        assert_eq!(keys.retire(&old_pk, 1_500).unwrap(), 1_500);
+
+        assert!(keys.signer_at(1_500, 1_500).is_ok());
+        assert!(keys.signer_at(1_501, 1_501).is_err());
+        // Messages timestamped past the deadline are refused before it, as
+        // with upstream timestamps ahead of the enclave's clock.
+        assert!(keys.signer_at(1_400, 1_501).is_err());
+
+        // Restored after a restart
+        let restarted = SigningKeys::new(Ed25519KeyPair::generate(&mut rand::thread_rng()))
+            .with_sealed_files(files())
+            .unwrap();
+        assert_eq!(restarted.retire_at(&old_pk), Some(1_500));
+
+        // Its successor is not bound by it.
+        keys.rotate(new, 1_600).unwrap();
+        assert!(keys.signer_at(1_600, 1_600).is_ok());
+    }
+
+    #[test]
+    fn test_scoped_signer() {
+        let main = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let weather = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let (main_pk, weather_pk) = (main.public().clone(), weather.public().clone());
+
+        let keys = SigningKeys::new(main)
+            .with_scoped_keys(HashMap::from([(IntentScope::Weather, weather)]));
+        assert_eq!(keys.signer_for(IntentScope::PriceFeed, 0, 0).unwrap().public(), &main_pk);
+        assert_eq!(keys.signer_for(IntentScope::Weather, 0, 0).unwrap().public(), &weather_pk);
+        assert_eq!(keys.scoped_key(IntentScope::Weather as u8).unwrap().1.public(), &weather_pk);
+        assert!(keys.scoped_key(IntentScope::PriceFeed as u8).is_none());
+
+        // Retiring the main key leaves the scoped one signing, and back.
+        keys.retire(main_pk.as_bytes(), 1_000).unwrap();
+        assert!(keys.signer_for(IntentScope::PriceFeed, 1_001, 1_001).is_err());
+        assert!(keys.signer_for(IntentScope::Weather, 1_001, 1_001).is_ok());
+        keys.retire(weather_pk.as_bytes(), 1_000).unwrap();
+        assert!(keys.signer_for(IntentScope::Weather, 1_001, 1_001).is_err());
+        assert!(keys.signer_for(IntentScope::Weather, 900, 1_001).is_err());
+    }
+
+    #[test]
+    fn test_key_history() {
+        let old = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let new = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let (old_pk, new_pk) = (old.public().clone(), new.public().clone());
+
+        let keys = SigningKeys::new(old);
+        keys.rotate(new, 1_000).unwrap();
+        assert_eq!(keys.history_at(999).len(), 1);
+
+        keys.retire(new_pk.as_bytes(), 5_000).unwrap();
+        let history = keys.history_at(1_000);
+        assert_eq!(
+            history,
+            vec![
+                KeyPeriod {
+                    public_key: old_pk,
+                    active_from_ms: None,
+                    active_until_ms: Some(1_000),
+                },
+                KeyPeriod {
+                    public_key: new_pk,
+                    active_from_ms: Some(1_000),
+                    active_until_ms: Some(5_000),
+                },
+            ]
+        );
+    }
+}
@@ -1,37 +1,210 @@
 // Copyright (c), Mysten Labs, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use axum::http::StatusCode;
+use axum::http::{header, HeaderValue, StatusCode};
 use axum::response::IntoResponse;
 use axum::response::Response;
-use axum::Json;
-use serde_json::json;
+use axum::{routing::get, routing::post, Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use utoipa::{OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
 
+pub mod admin;
+pub mod api_key_encryption;
 pub mod app;
+pub mod assets;
+pub mod attestation;
+pub mod backup;
+pub mod batch;
+pub mod breaker;
+pub mod capacity;
+pub mod capture;
 pub mod common;
 pub mod config;
+pub mod consumers;
+pub mod conversion;
+pub mod definition;
+pub mod dry_run;
+pub mod encoding;
+pub mod entropy;
+pub mod expression;
+pub mod feeds;
+pub mod fees;
+pub mod formats;
+pub mod handoff;
+pub mod headers;
+pub mod health;
+pub mod history;
+pub mod hmac_auth;
+pub mod keys;
+pub mod limits;
+pub mod light_client;
+pub mod listener;
+pub mod market_hours;
+pub mod migrate;
+pub mod mirrors;
+pub mod nft;
+pub mod oauth;
+pub mod openapi;
+pub mod oracle;
+pub mod outbound;
+pub mod pause;
+pub mod payments;
+pub mod persistence;
+pub mod push;
+pub mod rates;
+pub mod refresher;
+pub mod reserves;
+pub mod retirement;
+pub mod schema;
+pub mod secrets;
+pub mod sponsor;
+pub mod sports;
 pub mod state;
+pub mod stats;
+pub mod subscription;
 pub mod sui;
+pub mod telemetry;
+pub mod transform;
 pub mod types;
+pub mod upstream_circuit;
+pub mod verification;
+pub mod verify;
+pub mod weather;
+pub mod wide;
+pub mod xml;
 
 pub use state::AppState;
 
+/// Build the enclave's HTTP router with all endpoints and the shared state.
+pub fn router(state: Arc<AppState>) -> Router {
+    let config = state.config.load_full();
+    let router = Router::new()
+        .route("/", get(ping))
+        .route("/get_attestation", get(common::get_attestation))
+        .route("/attestation", get(common::attestation))
+        .route("/attestation/refresh", post(common::refresh_attestation))
+        .route(
+            "/api_key_encryption_key",
+            get(api_key_encryption::api_key_encryption_key),
+        )
+        .route("/key_retirement", get(retirement::key_retirement))
+        .merge(oracle::routes())
+        .route("/process_data/batch", post(batch::process_batch))
+        .route("/dry_run", post(dry_run::dry_run))
+        .route("/verify/batch", post(verification::verify_batch))
+        .route("/fee_quote", post(fees::fee_quote))
+        .route("/health_check", get(common::health_check))
+        .route("/health/upstreams", get(health::upstream_health))
+        .route("/history/:price_feed_id", get(history::feed_history))
+        .route("/capacity", get(capacity::capacity))
+        .route("/assets", get(assets::list_assets))
+        .route("/assets/:symbol", get(assets::get_asset))
+        .route("/feeds", get(feeds::list_feeds))
+        .merge(admin::routes(state.clone()))
+        .route("/admin/handoff", post(handoff::send_handoff))
+        .merge(SwaggerUi::new("/swagger-ui").url("/openapi.json", openapi::ApiDoc::openapi()))
+        .with_state(state);
+    let router = encoding::apply(router, &config.encoding);
+    headers::apply(
+        telemetry::trace_requests(router),
+        &config.cors,
+        &config.security_headers,
+    )
+}
+
+async fn ping() -> &'static str {
+    "Pong!"
+}
+
 /// Implement IntoResponse for EnclaveError.
 impl IntoResponse for EnclaveError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            EnclaveError::GenericError(e) => (StatusCode::BAD_REQUEST, e),
-        };
-        let body = Json(json!({
-            "error": error_message,
-        }));
-        (status, body).into_response()
+        let code = self.code().map(str::to_string);
+        match self {
+            EnclaveError::GenericError(e) => {
+                (StatusCode::BAD_REQUEST, Json(ErrorResponse { error: e, code })).into_response()
+            }
+            EnclaveError::OutsideSigningWindow {
+                message,
+                retry_after_secs,
+            } => {
+                let mut response =
+                    (StatusCode::FORBIDDEN, Json(ErrorResponse { error: message, code })).into_response();
+                if let Some(secs) = retry_after_secs {
+                    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(secs));
+                }
+                response
+            }
+            EnclaveError::Unauthorized(e) => {
+                (StatusCode::UNAUTHORIZED, Json(ErrorResponse { error: e, code })).into_response()
+            }
+            EnclaveError::Paused(e) => {
+                (StatusCode::SERVICE_UNAVAILABLE, Json(ErrorResponse { error: e, code })).into_response()
+            }
+            EnclaveError::UpstreamUnavailable {
+                message,
+                retry_after_secs,
+            } => {
+                let mut response =
+                    (StatusCode::SERVICE_UNAVAILABLE, Json(ErrorResponse { error: message, code })).into_response();
+                if let Some(secs) = retry_after_secs {
+                    response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(secs));
+                }
+                response
+            }
+        }
     }
 }
 
+/// Body of every error response.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: String,
+    /// Stable identifier of the error kind, for errors clients are expected
+    /// to handle, e.g. `outside_signing_window`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+}
+
 /// Enclave errors enum.
 #[derive(Debug, thiserror::Error)]
 pub enum EnclaveError {
     #[error("Generic error: {0}")]
     GenericError(String),
+    /// The feed only signs within its configured signing windows. Served as
+    /// 403 with a Retry-After header when the next window is known.
+    #[error("Outside signing window: {message}")]
+    OutsideSigningWindow {
+        message: String,
+        retry_after_secs: Option<u64>,
+    },
+    /// The consumer API key is missing or unknown. Served as 401.
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    /// Signing is paused by an operator, globally or for the feed. Served
+    /// as 503.
+    #[error("Paused: {0}")]
+    Paused(String),
+    /// The upstream's circuit is open after repeated failures. Served as 503
+    /// with a Retry-After header.
+    #[error("Upstream unavailable: {message}")]
+    UpstreamUnavailable {
+        message: String,
+        retry_after_secs: Option<u64>,
+    },
+}
+
+impl EnclaveError {
+    /// Value of [`ErrorResponse::code`] for this error.
+    pub fn code(&self) -> Option<&'static str> {
+        match self {
+            EnclaveError::GenericError(_) => None,
+            EnclaveError::OutsideSigningWindow { .. } => Some("outside_signing_window"),
+            EnclaveError::Unauthorized(_) => Some("unauthorized"),
+            EnclaveError::Paused(_) => Some("paused"),
+            EnclaveError::UpstreamUnavailable { .. } => Some("upstream_unavailable"),
+        }
+    }
 }
@@ -0,0 +1,668 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Light-client verification of PriceFeed objects. With `sui.light_client`
+//! enabled a PriceFeed is only used once it is found among the outputs of a
+//! transaction in a checkpoint certified by the validator committee, instead
+//! of as the fullnode renders it; anything that does not verify fails the
+//! fetch. Committees are followed from a pinned one through the end-of-epoch
+//! checkpoints.
+//!
+//! The fullnode only locates the data: the transaction that last wrote the
+//! object and the checkpoint it is in. Checkpoints are read from a checkpoint
+//! store serving `<sequence number>.chk` blobs. A certified object is proven
+//! to have been written at its version, not to still be the latest one, so a
+//! fullnode can hold back later updates of a feed but not forge one.
+
+use anyhow::{Context, Result};
+use fastcrypto::bls12381::min_sig::{BLS12381AggregateSignature, BLS12381PublicKey};
+use fastcrypto::encoding::{Base64, Encoding};
+use fastcrypto::traits::{AggregateAuthenticator, ToFromBytes};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use sui_sdk_types::{
+    CheckpointData, Object, ObjectId, ObjectOut, SignedCheckpointSummary, TransactionEffects,
+    ValidatorCommitteeMember,
+};
+use tracing::info;
+
+use crate::config::check_http_url;
+use crate::sui::SuiClientWrapper;
+
+/// Intent checkpoint summaries are signed under: scope `CheckpointSummary`,
+/// version 0, app id Sui
+const CHECKPOINT_SUMMARY_INTENT: [u8; 3] = [2, 0, 0];
+
+/// Leading byte of a checkpoint blob whose data is BCS
+const BLOB_ENCODING_BCS: u8 = 1;
+
+/// Verified objects kept, past which the cache starts over
+const MAX_VERIFIED_OBJECTS: usize = 1_024;
+
+/// `[sui.light_client]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct LightClient {
+    /// Verify PriceFeed objects against certified checkpoints instead of
+    /// trusting the fullnode
+    #[serde(default)]
+    pub enabled: bool,
+    /// Base URL of the checkpoint store, read as `<url>/<sequence number>.chk`
+    #[serde(default)]
+    pub checkpoint_url: String,
+    /// Epoch of `committee`
+    #[serde(default)]
+    pub committee_epoch: u64,
+    /// Validator committee trusted for `committee_epoch`. Objects written
+    /// before that epoch cannot be verified.
+    #[serde(default)]
+    pub committee: Vec<CommitteeMember>,
+}
+
+/// Validator of the pinned committee.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CommitteeMember {
+    /// BLS12-381 public key, in Base64
+    pub public_key: String,
+    pub stake: u64,
+}
+
+impl LightClient {
+    /// Check the config, returning the problems found.
+    pub fn validate(&self) -> Vec<String> {
+        if !self.enabled {
+            return Vec::new();
+        }
+        let mut problems = Vec::new();
+        if let Err(e) = check_http_url(&self.checkpoint_url) {
+            problems.push(format!("sui.light_client.checkpoint_url {}", e));
+        }
+        if let Err(e) = self.pinned_committee() {
+            problems.push(format!("sui.light_client.committee {:#}", e));
+        }
+        problems
+    }
+
+    fn pinned_committee(&self) -> Result<Committee> {
+        let members = self
+            .committee
+            .iter()
+            .map(|member| {
+                let public_key = Base64::decode(&member.public_key)
+                    .map_err(|e| anyhow::anyhow!("has an invalid public key: {}", e))?;
+                Ok((public_key, member.stake))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Committee::new(self.committee_epoch, members)
+    }
+}
+
+/// Validator committee of an epoch.
+struct Committee {
+    epoch: u64,
+    /// Members ordered by public key, which signer bitmaps index
+    members: Vec<(BLS12381PublicKey, u64)>,
+    /// Stake a certificate needs
+    quorum: u64,
+}
+
+impl Committee {
+    fn new(epoch: u64, mut members: Vec<(Vec<u8>, u64)>) -> Result<Self> {
+        if members.is_empty() {
+            return Err(anyhow::anyhow!("is empty"));
+        }
+        members.sort();
+        let members = members
+            .into_iter()
+            .map(|(public_key, stake)| {
+                let public_key = BLS12381PublicKey::from_bytes(&public_key)
+                    .map_err(|e| anyhow::anyhow!("has an invalid public key: {}", e))?;
+                Ok((public_key, stake))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let total_stake = members
+            .iter()
+            .try_fold(0u64, |total, (_, stake)| total.checked_add(*stake))
+            .ok_or_else(|| anyhow::anyhow!("has a total stake above u64"))?;
+        Ok(Self {
+            epoch,
+            members,
+            quorum: (total_stake as u128 * 2 / 3) as u64 + 1,
+        })
+    }
+
+    /// The committee of the next epoch, announced by `members`.
+    fn next(&self, members: &[ValidatorCommitteeMember]) -> Result<Self> {
+        let members = members
+            .iter()
+            .map(|member| (member.public_key.as_bytes().to_vec(), member.stake))
+            .collect();
+        Self::new(self.epoch + 1, members).context("Invalid next epoch committee")
+    }
+
+    /// Check that a quorum of the committee signed `signed`.
+    fn verify(&self, signed: &SignedCheckpointSummary) -> Result<()> {
+        let summary = &signed.checkpoint;
+        let signature = &signed.signature;
+        if summary.epoch != self.epoch || signature.epoch != self.epoch {
+            return Err(anyhow::anyhow!(
+                "Checkpoint {} is of epoch {}, signed in epoch {}, expected {}",
+                summary.sequence_number,
+                summary.epoch,
+                signature.epoch,
+                self.epoch
+            ));
+        }
+
+        let mut signers = Vec::new();
+        let mut stake = 0u64;
+        for index in signature.bitmap.iter() {
+            let (public_key, member_stake) = self
+                .members
+                .get(index as usize)
+                .ok_or_else(|| anyhow::anyhow!("Checkpoint signer {} is not in the committee", index))?;
+            signers.push(public_key.clone());
+            stake = stake.saturating_add(*member_stake);
+        }
+        if stake < self.quorum {
+            return Err(anyhow::anyhow!(
+                "Checkpoint {} is signed by stake {}, short of the quorum {}",
+                summary.sequence_number,
+                stake,
+                self.quorum
+            ));
+        }
+
+        let mut message = CHECKPOINT_SUMMARY_INTENT.to_vec();
+        message.extend(bcs::to_bytes(summary)?);
+        message.extend(bcs::to_bytes(&summary.epoch)?);
+        BLS12381AggregateSignature::from_bytes(signature.signature.as_bytes())
+            .and_then(|aggregate| aggregate.verify(&signers, &message))
+            .map_err(|_| anyhow::anyhow!("Invalid signature of checkpoint {}", summary.sequence_number))
+    }
+}
+
+/// Committees followed so far, from the pinned one on.
+struct Committees {
+    by_epoch: BTreeMap<u64, Arc<Committee>>,
+    /// Checkpoint the search for the end of the latest followed epoch starts
+    /// at, at or before its first checkpoint
+    search_from: u64,
+}
+
+/// Verifies objects against certified checkpoints.
+pub struct CheckpointVerifier {
+    client: Client,
+    checkpoint_url: String,
+    committees: tokio::sync::Mutex<Committees>,
+    /// Objects already verified, by id and version
+    verified: Mutex<HashMap<(ObjectId, u64), Object>>,
+}
+
+impl CheckpointVerifier {
+    /// A verifier for `config` fetching checkpoints with `client`, if enabled.
+    pub fn from_config(config: &LightClient, client: Client) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let committee = config.pinned_committee().context("Invalid sui.light_client.committee")?;
+        Ok(Some(Self {
+            client,
+            checkpoint_url: config.checkpoint_url.trim_end_matches('/').to_string(),
+            committees: tokio::sync::Mutex::new(Committees {
+                by_epoch: BTreeMap::from([(committee.epoch, Arc::new(committee))]),
+                search_from: 0,
+            }),
+            verified: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    /// The object `object_id` at the version of its `data` from
+    /// `sui_getObject`, as written in a certified checkpoint.
+    pub async fn verify_object(&self, sui: &SuiClientWrapper, object_id: &str, data: &Value) -> Result<Object> {
+        let id = ObjectId::from_str(object_id).map_err(|_| anyhow::anyhow!("Invalid object id {}", object_id))?;
+        let version = u64_field(data, "version")?;
+        if let Some(object) = self.verified.lock().expect("verified objects lock poisoned").get(&(id, version)) {
+            return Ok(object.clone());
+        }
+
+        let transaction = data
+            .get("previousTransaction")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing previous transaction of {}", object_id))?;
+        let result = sui
+            .call(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "sui_getTransactionBlock",
+                "params": [transaction, {}]
+            }))
+            .await?;
+        let sequence_number = u64_field(&result, "checkpoint")
+            .with_context(|| format!("Transaction {} is not in a checkpoint yet", transaction))?;
+
+        let checkpoint = self.fetch_checkpoint(sequence_number).await?;
+        let summary = &checkpoint.checkpoint_summary;
+        self.committee(sui, summary.checkpoint.epoch, sequence_number)
+            .await?
+            .verify(summary)?;
+        let object = certified_object(&checkpoint, &id)?;
+        if object.version() != version {
+            return Err(anyhow::anyhow!(
+                "Fullnode reported version {} of {}, checkpoint {} wrote version {}",
+                version,
+                object_id,
+                sequence_number,
+                object.version()
+            ));
+        }
+
+        let mut verified = self.verified.lock().expect("verified objects lock poisoned");
+        if verified.len() >= MAX_VERIFIED_OBJECTS {
+            verified.clear();
+        }
+        verified.insert((id, version), object.clone());
+        Ok(object)
+    }
+
+    /// Committee of `epoch`, followed from the latest known one through the
+    /// end-of-epoch checkpoints before `checkpoint`.
+    async fn committee(&self, sui: &SuiClientWrapper, epoch: u64, checkpoint: u64) -> Result<Arc<Committee>> {
+        let mut committees = self.committees.lock().await;
+        loop {
+            if let Some(committee) = committees.by_epoch.get(&epoch) {
+                return Ok(Arc::clone(committee));
+            }
+            let (&pinned, _) = committees.by_epoch.first_key_value().expect("pinned committee");
+            let (_, latest) = committees.by_epoch.last_key_value().expect("pinned committee");
+            let latest = Arc::clone(latest);
+            if epoch < pinned {
+                return Err(anyhow::anyhow!(
+                    "Checkpoint {} is of epoch {}, before the pinned committee of epoch {}",
+                    checkpoint,
+                    epoch,
+                    pinned
+                ));
+            }
+
+            let last = self
+                .last_checkpoint_of(sui, latest.epoch, committees.search_from, checkpoint)
+                .await?;
+            let end = self.fetch_checkpoint(last).await?.checkpoint_summary;
+            latest.verify(&end)?;
+            let next_members = &end
+                .checkpoint
+                .end_of_epoch_data
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("Checkpoint {} does not end epoch {}", last, latest.epoch))?
+                .next_epoch_committee;
+            let next = latest.next(next_members)?;
+            info!("Followed the validator committee to epoch {}", next.epoch);
+            committees.by_epoch.insert(next.epoch, Arc::new(next));
+            committees.search_from = last + 1;
+        }
+    }
+
+    /// Sequence number of the last checkpoint of `epoch`, searched for
+    /// between `from`, at or before its first checkpoint, and `to`, which is
+    /// of a later epoch. The fullnode is trusted to locate it only: the
+    /// checkpoint found is verified to end the epoch.
+    async fn last_checkpoint_of(&self, sui: &SuiClientWrapper, epoch: u64, from: u64, to: u64) -> Result<u64> {
+        let (mut low, mut high) = (from, to);
+        while high - low > 1 {
+            let middle = low + (high - low) / 2;
+            let result = sui
+                .call(&json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "method": "sui_getCheckpoint",
+                    "params": [middle.to_string()]
+                }))
+                .await?;
+            if u64_field(&result, "epoch")? <= epoch {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+        Ok(low)
+    }
+
+    async fn fetch_checkpoint(&self, sequence_number: u64) -> Result<CheckpointData> {
+        let url = format!("{}/{}.chk", self.checkpoint_url, sequence_number);
+        let bytes = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .with_context(|| format!("Failed to fetch checkpoint {}", sequence_number))?
+            .error_for_status()
+            .with_context(|| format!("Checkpoint store refused checkpoint {}", sequence_number))?
+            .bytes()
+            .await
+            .with_context(|| format!("Failed to read checkpoint {}", sequence_number))?;
+        let checkpoint = decode_checkpoint(&bytes)
+            .with_context(|| format!("Invalid checkpoint {}", sequence_number))?;
+        if checkpoint.checkpoint_summary.checkpoint.sequence_number != sequence_number {
+            return Err(anyhow::anyhow!("Checkpoint store served another checkpoint for {}", sequence_number));
+        }
+        Ok(checkpoint)
+    }
+}
+
+/// Decode a checkpoint blob: an encoding byte followed by the data.
+fn decode_checkpoint(bytes: &[u8]) -> Result<CheckpointData> {
+    match bytes.split_first() {
+        Some((&BLOB_ENCODING_BCS, data)) => Ok(bcs::from_bytes(data)?),
+        Some((encoding, _)) => Err(anyhow::anyhow!("Unknown blob encoding {}", encoding)),
+        None => Err(anyhow::anyhow!("Empty blob")),
+    }
+}
+
+/// The object `id` as written by a transaction of `checkpoint`, whose
+/// summary must already be verified: the contents must be those the summary
+/// commits to, the effects of the transaction among them, and the object the
+/// output the effects commit to.
+fn certified_object(checkpoint: &CheckpointData, id: &ObjectId) -> Result<Object> {
+    let summary = &checkpoint.checkpoint_summary.checkpoint;
+    let contents = &checkpoint.checkpoint_contents;
+    if contents.digest() != summary.content_digest {
+        return Err(anyhow::anyhow!("Contents of checkpoint {} do not match its summary", summary.sequence_number));
+    }
+    let committed: HashMap<_, _> = contents
+        .transactions()
+        .iter()
+        .map(|info| (info.transaction, info.effects))
+        .collect();
+
+    for transaction in &checkpoint.transactions {
+        let TransactionEffects::V2(effects) = &transaction.effects else {
+            continue;
+        };
+        let Some(change) = effects.changed_objects.iter().find(|change| change.object_id == *id) else {
+            continue;
+        };
+        if committed.get(&effects.transaction_digest) != Some(&transaction.effects.digest()) {
+            return Err(anyhow::anyhow!(
+                "Effects of transaction {} are not those of checkpoint {}",
+                effects.transaction_digest,
+                summary.sequence_number
+            ));
+        }
+        let ObjectOut::ObjectWrite { digest, .. } = &change.output_state else {
+            return Err(anyhow::anyhow!("Transaction {} did not leave {} as an object", effects.transaction_digest, id));
+        };
+        return transaction
+            .output_objects
+            .iter()
+            .find(|object| object.digest() == *digest)
+            .filter(|object| {
+                object
+                    .as_struct()
+                    .and_then(|move_struct| move_struct.contents().get(..ObjectId::LENGTH))
+                    == Some(id.as_bytes())
+            })
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Checkpoint {} does not hold the output {} of {}", summary.sequence_number, digest, id));
+    }
+    Err(anyhow::anyhow!("Checkpoint {} did not write {}", summary.sequence_number, id))
+}
+
+/// A u64 the RPC renders as a string
+fn u64_field(value: &Value, name: &str) -> Result<u64> {
+    value
+        .get(name)
+        .and_then(|v| match v {
+            Value::String(s) => s.parse().ok(),
+            v => v.as_u64(),
+        })
+        .ok_or_else(|| anyhow::anyhow!("Missing or invalid {}", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastcrypto::bls12381::min_sig::BLS12381KeyPair;
+    use fastcrypto::traits::{KeyPair, Signer};
+    use sui_sdk_types::{
+        Address, Bls12381Signature, ChangedObject, CheckpointContents, CheckpointSummary,
+        CheckpointTransaction, CheckpointTransactionInfo, ExecutionStatus, GasCostSummary, GasPayment,
+        IdOperation, Identifier, MoveStruct, ObjectData, ObjectIn, Owner, ProgrammableTransaction,
+        SignedTransaction, StructTag, Transaction, TransactionDigest, TransactionEffectsV2,
+        TransactionExpiration, TransactionKind, ValidatorAggregatedSignature,
+    };
+
+    /// Four validators of equal stake, ordered as the committee orders them.
+    fn validators() -> Vec<BLS12381KeyPair> {
+        let mut keys: Vec<_> = (0..4)
+            .map(|_| BLS12381KeyPair::generate(&mut rand::thread_rng()))
+            .collect();
+        keys.sort_by(|a, b| a.public().as_bytes().cmp(b.public().as_bytes()));
+        keys
+    }
+
+    fn committee(epoch: u64, keys: &[BLS12381KeyPair]) -> Committee {
+        let members = keys
+            .iter()
+            .map(|kp| (kp.public().as_bytes().to_vec(), 2_500))
+            .collect();
+        Committee::new(epoch, members).unwrap()
+    }
+
+    fn summary(epoch: u64, content_digest: sui_sdk_types::CheckpointContentsDigest) -> CheckpointSummary {
+        CheckpointSummary {
+            epoch,
+            sequence_number: 10,
+            network_total_transactions: 100,
+            content_digest,
+            previous_digest: None,
+            epoch_rolling_gas_cost_summary: GasCostSummary::default(),
+            timestamp_ms: 1_000,
+            checkpoint_commitments: Vec::new(),
+            end_of_epoch_data: None,
+            version_specific_data: Vec::new(),
+        }
+    }
+
+    /// `summary` signed by the validators at `signers`.
+    fn sign(summary: CheckpointSummary, keys: &[BLS12381KeyPair], signers: &[u32]) -> SignedCheckpointSummary {
+        let mut message = CHECKPOINT_SUMMARY_INTENT.to_vec();
+        message.extend(bcs::to_bytes(&summary).unwrap());
+        message.extend(bcs::to_bytes(&summary.epoch).unwrap());
+        let signatures: Vec<_> = signers
+            .iter()
+            .map(|&index| keys[index as usize].sign(&message))
+            .collect();
+        let aggregate = BLS12381AggregateSignature::aggregate(&signatures).unwrap();
+        SignedCheckpointSummary {
+            signature: ValidatorAggregatedSignature {
+                epoch: summary.epoch,
+                signature: Bls12381Signature::from_bytes(aggregate.as_bytes()).unwrap(),
+                bitmap: signers.iter().copied().collect(),
+            },
+            checkpoint: summary,
+        }
+    }
+
+    #[test]
+    fn test_committee_verify() {
+        let keys = validators();
+        let committee = committee(5, &keys);
+        let digest = CheckpointContents::new(Vec::new()).digest();
+
+        assert!(committee.verify(&sign(summary(5, digest), &keys, &[0, 1, 2])).is_ok());
+        // Half the stake is short of the quorum.
+        let err = committee.verify(&sign(summary(5, digest), &keys, &[0, 1])).unwrap_err();
+        assert!(err.to_string().contains("short of the quorum"));
+        // Signed by another epoch's committee.
+        assert!(committee.verify(&sign(summary(6, digest), &keys, &[0, 1, 2])).is_err());
+
+        let mut tampered = sign(summary(5, digest), &keys, &[0, 1, 2]);
+        tampered.checkpoint.timestamp_ms += 1;
+        let err = committee.verify(&tampered).unwrap_err();
+        assert!(err.to_string().contains("Invalid signature"));
+
+        // A bitmap claiming a signer that did not sign.
+        let mut claimed = sign(summary(5, digest), &keys, &[0, 1]);
+        claimed.signature.bitmap.insert(2);
+        assert!(committee.verify(&claimed).is_err());
+    }
+
+    #[test]
+    fn test_committee_config() {
+        let keys = validators();
+        let mut config = LightClient {
+            enabled: true,
+            checkpoint_url: "https://checkpoints.testnet.sui.io".to_string(),
+            committee_epoch: 5,
+            committee: Vec::new(),
+        };
+        assert_eq!(config.validate().len(), 1);
+
+        config.committee = keys
+            .iter()
+            .rev()
+            .map(|kp| CommitteeMember {
+                public_key: Base64::encode(kp.public().as_bytes()),
+                stake: 2_500,
+            })
+            .collect();
+        assert!(config.validate().is_empty());
+        // Members are indexed in key order whatever the order they are listed in.
+        let digest = CheckpointContents::new(Vec::new()).digest();
+        let pinned = config.pinned_committee().unwrap();
+        assert!(pinned.verify(&sign(summary(5, digest), &keys, &[1, 2, 3])).is_ok());
+
+        config.committee[0].public_key = Base64::encode([1; 96]);
+        assert_eq!(config.validate().len(), 1);
+    }
+
+    /// A checkpoint whose only transaction wrote a PriceFeed `id` at version 7.
+    fn checkpoint(id: ObjectId) -> CheckpointData {
+        let type_ = StructTag {
+            address: Address::from_hex("0x2").unwrap(),
+            module: Identifier::new("oracle_builder").unwrap(),
+            name: Identifier::new("PriceFeed").unwrap(),
+            type_params: Vec::new(),
+        };
+        let transaction_digest = TransactionDigest::new([3; 32]);
+        let contents = [id.as_bytes(), &[4; 40]].concat();
+        let object = Object::new(
+            ObjectData::Struct(MoveStruct::new(type_, false, 7, contents).unwrap()),
+            Owner::Shared(1),
+            transaction_digest,
+            0,
+        );
+        let effects = TransactionEffects::V2(Box::new(TransactionEffectsV2 {
+            status: ExecutionStatus::Success,
+            epoch: 5,
+            gas_used: GasCostSummary::default(),
+            transaction_digest,
+            gas_object_index: None,
+            events_digest: None,
+            dependencies: Vec::new(),
+            lamport_version: 7,
+            changed_objects: vec![ChangedObject {
+                object_id: id,
+                input_state: ObjectIn::NotExist,
+                output_state: ObjectOut::ObjectWrite {
+                    digest: object.digest(),
+                    owner: Owner::Shared(1),
+                },
+                id_operation: IdOperation::None,
+            }],
+            unchanged_shared_objects: Vec::new(),
+            auxiliary_data_digest: None,
+        }));
+        let contents = CheckpointContents::new(vec![CheckpointTransactionInfo {
+            transaction: transaction_digest,
+            effects: effects.digest(),
+            signatures: Vec::new(),
+        }]);
+        let transaction = Transaction {
+            kind: TransactionKind::ProgrammableTransaction(ProgrammableTransaction {
+                inputs: Vec::new(),
+                commands: Vec::new(),
+            }),
+            sender: Address::ZERO,
+            gas_payment: GasPayment {
+                objects: Vec::new(),
+                owner: Address::ZERO,
+                price: 1,
+                budget: 1,
+            },
+            expiration: TransactionExpiration::None,
+        };
+        let keys = validators();
+        CheckpointData {
+            checkpoint_summary: sign(summary(5, contents.digest()), &keys, &[0, 1, 2]),
+            checkpoint_contents: contents,
+            transactions: vec![CheckpointTransaction {
+                transaction: SignedTransaction {
+                    transaction,
+                    signatures: Vec::new(),
+                },
+                effects,
+                events: None,
+                input_objects: Vec::new(),
+                output_objects: vec![object],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_certified_object() {
+        let id = ObjectId::new([9; 32]);
+        let data = checkpoint(id);
+        let object = certified_object(&data, &id).unwrap();
+        assert_eq!(object.version(), 7);
+        assert_eq!(&object.as_struct().unwrap().contents()[32..], &[4; 40]);
+
+        assert!(certified_object(&data, &ObjectId::new([8; 32])).is_err());
+
+        // Contents other than those the summary commits to
+        let mut other_contents = data.clone();
+        other_contents.checkpoint_contents = CheckpointContents::new(Vec::new());
+        assert!(certified_object(&other_contents, &id).is_err());
+
+        // An output other than the one the effects commit to
+        let mut forged = data.clone();
+        let forged_object = Object::new(
+            ObjectData::Struct(
+                MoveStruct::new(
+                    data.transactions[0].output_objects[0].as_struct().unwrap().object_type().clone(),
+                    false,
+                    7,
+                    [id.as_bytes(), &[5; 40]].concat(),
+                )
+                .unwrap(),
+            ),
+            Owner::Shared(1),
+            TransactionDigest::new([3; 32]),
+            0,
+        );
+        forged.transactions[0].output_objects = vec![forged_object];
+        assert!(certified_object(&forged, &id).is_err());
+
+        // Effects that are not those of the checkpoint
+        let mut other_effects = data;
+        if let TransactionEffects::V2(effects) = &mut other_effects.transactions[0].effects {
+            effects.gas_used.computation_cost = 1;
+        }
+        assert!(certified_object(&other_effects, &id).is_err());
+    }
+
+    #[test]
+    fn test_decode_checkpoint() {
+        let data = checkpoint(ObjectId::new([9; 32]));
+        let bcs = bcs::to_bytes(&data).unwrap();
+        let blob = [&[BLOB_ENCODING_BCS][..], &bcs].concat();
+        assert_eq!(decode_checkpoint(&blob).unwrap(), data);
+
+        assert!(decode_checkpoint(&[&[2][..], &bcs].concat()).is_err());
+        assert!(decode_checkpoint(&[]).is_err());
+    }
+}
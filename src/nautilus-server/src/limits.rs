@@ -0,0 +1,363 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Limits on upstream responses, checked before they are parsed so a feed
+//! pointing at a huge, highly compressed or deeply nested payload cannot
+//! exhaust enclave memory.
+
+use brotli::{BrotliDecompressStream, BrotliResult, BrotliState, HeapAlloc, HuffmanCode};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::Read;
+
+/// Accept-Encoding sent upstream; every encoding listed can be decoded.
+pub const ACCEPT_ENCODING: &str = "gzip, deflate, br";
+
+/// First bytes of a gzip stream.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Limits applied to every upstream price response.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UpstreamLimits {
+    /// Largest response body read, in bytes
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// Largest body after decompression, in bytes
+    #[serde(default = "default_max_decompressed_bytes")]
+    pub max_decompressed_bytes: usize,
+    /// Deepest nesting of JSON arrays and objects accepted
+    #[serde(default = "default_max_depth")]
+    pub max_depth: usize,
+    /// Accepted media types of the Content-Type header, compared without
+    /// parameters. A missing header is rejected unless this is empty.
+    #[serde(default = "default_content_types")]
+    pub content_types: Vec<String>,
+}
+
+impl Default for UpstreamLimits {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: default_max_body_bytes(),
+            max_decompressed_bytes: default_max_decompressed_bytes(),
+            max_depth: default_max_depth(),
+            content_types: default_content_types(),
+        }
+    }
+}
+
+fn default_max_body_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_max_decompressed_bytes() -> usize {
+    8 * 1024 * 1024
+}
+
+fn default_max_depth() -> usize {
+    32
+}
+
+fn default_content_types() -> Vec<String> {
+    vec!["application/json".to_string()]
+}
+
+impl UpstreamLimits {
+    /// Check the Content-Type header of a response.
+    pub fn check_content_type(&self, content_type: Option<&str>) -> Result<(), String> {
+        if self.content_types.is_empty() {
+            return Ok(());
+        }
+        let content_type = content_type.ok_or("Upstream response has no Content-Type")?;
+        let media_type = content_type.split(';').next().unwrap_or_default().trim();
+        if self
+            .content_types
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(media_type))
+        {
+            Ok(())
+        } else {
+            Err(format!(
+                "Upstream Content-Type '{}' is not one of {}",
+                content_type,
+                self.content_types.join(", ")
+            ))
+        }
+    }
+
+    /// Read the body of `response` up to `max_body_bytes`, giving up as soon
+    /// as either the declared length or the bytes received exceed it.
+    pub async fn read_body(&self, mut response: reqwest::Response) -> Result<Vec<u8>, String> {
+        if let Some(length) = response.content_length() {
+            if length > self.max_body_bytes as u64 {
+                return Err(format!(
+                    "Upstream response of {} bytes exceeds the {} byte limit",
+                    length, self.max_body_bytes
+                ));
+            }
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| format!("Failed to read upstream response: {}", e))?
+        {
+            if body.len() + chunk.len() > self.max_body_bytes {
+                return Err(format!(
+                    "Upstream response exceeds the {} byte limit",
+                    self.max_body_bytes
+                ));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        Ok(body)
+    }
+
+    /// Undo the Content-Encoding of `body`, codings applied last first.
+    /// Labels are checked against the bytes: a gzip or zlib stream is
+    /// decoded whatever it is labelled, and a body labelled compressed that
+    /// is plainly JSON is taken as is.
+    pub fn decode(&self, content_encoding: Option<&str>, body: Vec<u8>) -> Result<Vec<u8>, String> {
+        let labels: Vec<&str> = content_encoding
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|label| !label.is_empty())
+            .collect();
+        if labels.is_empty() {
+            return self.decode_one(None, body);
+        }
+        labels
+            .into_iter()
+            .rev()
+            .try_fold(body, |body, label| self.decode_one(Some(label), body))
+    }
+
+    fn decode_one(&self, label: Option<&str>, body: Vec<u8>) -> Result<Vec<u8>, String> {
+        let label = label.map(str::to_ascii_lowercase);
+        if body.starts_with(&GZIP_MAGIC) {
+            return self.inflate("gzip", flate2::read::GzDecoder::new(body.as_slice()));
+        }
+        if is_zlib(&body) {
+            return self.inflate("deflate", flate2::read::ZlibDecoder::new(body.as_slice()));
+        }
+        if looks_like_json(&body) {
+            return Ok(body);
+        }
+        match label.as_deref() {
+            None | Some("identity") => Ok(body),
+            // Strictly zlib, but raw deflate streams are common
+            Some("deflate") => self.inflate("deflate", flate2::read::DeflateDecoder::new(body.as_slice())),
+            Some("br") => self.inflate_brotli(&body),
+            Some("gzip" | "x-gzip") => Err("Upstream response labelled gzip is not gzip".to_string()),
+            Some(label) => Err(format!("Unsupported upstream Content-Encoding '{}'", label)),
+        }
+    }
+
+    /// Read `decoder` to the end, refusing output beyond
+    /// `max_decompressed_bytes`.
+    fn inflate(&self, encoding: &str, decoder: impl Read) -> Result<Vec<u8>, String> {
+        let mut body = Vec::new();
+        decoder
+            .take(self.max_decompressed_bytes as u64 + 1)
+            .read_to_end(&mut body)
+            .map_err(|e| format!("Failed to decode {} upstream response: {}", encoding, e))?;
+        if body.len() > self.max_decompressed_bytes {
+            return Err(format!(
+                "Decompressed upstream response exceeds the {} byte limit",
+                self.max_decompressed_bytes
+            ));
+        }
+        Ok(body)
+    }
+
+    /// Decode a brotli `body`, refusing a stream that ends before its last
+    /// meta-block as well as output beyond `max_decompressed_bytes`.
+    fn inflate_brotli(&self, body: &[u8]) -> Result<Vec<u8>, String> {
+        let mut state = BrotliState::new(
+            HeapAlloc::<u8>::new(0),
+            HeapAlloc::<u32>::new(0),
+            HeapAlloc::<HuffmanCode>::new(HuffmanCode::default()),
+        );
+        let (mut available_in, mut input_offset, mut total_out) = (body.len(), 0, 0);
+        let mut chunk = [0u8; 4096];
+        let mut decoded = Vec::new();
+        loop {
+            let (mut available_out, mut output_offset) = (chunk.len(), 0);
+            let result = BrotliDecompressStream(
+                &mut available_in,
+                &mut input_offset,
+                body,
+                &mut available_out,
+                &mut output_offset,
+                &mut chunk,
+                &mut total_out,
+                &mut state,
+            );
+            decoded.extend_from_slice(&chunk[..output_offset]);
+            if decoded.len() > self.max_decompressed_bytes {
+                return Err(format!(
+                    "Decompressed upstream response exceeds the {} byte limit",
+                    self.max_decompressed_bytes
+                ));
+            }
+            match result {
+                BrotliResult::ResultSuccess => return Ok(decoded),
+                BrotliResult::NeedsMoreOutput => continue,
+                BrotliResult::NeedsMoreInput => {
+                    return Err("Brotli upstream response is truncated".to_string())
+                }
+                BrotliResult::ResultFailure => {
+                    return Err("Failed to decode br upstream response".to_string())
+                }
+            }
+        }
+    }
+
+    /// Parse `body` as JSON after checking its nesting depth.
+    pub fn parse(&self, body: &[u8]) -> Result<Value, String> {
+        let depth = json_depth(body);
+        if depth > self.max_depth {
+            return Err(format!(
+                "Upstream response nests {} levels deep, more than the {} allowed",
+                depth, self.max_depth
+            ));
+        }
+        serde_json::from_slice(body).map_err(|e| format!("Failed to parse price feed response: {}", e))
+    }
+}
+
+/// Whether `body` starts with a zlib header for deflate with a 32K window,
+/// as every common encoder writes, and a valid header checksum. JSON text
+/// never starts with 0x78.
+fn is_zlib(body: &[u8]) -> bool {
+    match body {
+        [0x78, flg, ..] => (0x7800 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+/// Whether `body` starts like a JSON object or array.
+fn looks_like_json(body: &[u8]) -> bool {
+    matches!(
+        body.iter().find(|b| !b.is_ascii_whitespace()),
+        Some(b'{' | b'[')
+    )
+}
+
+/// Deepest nesting of arrays and objects in a JSON text, found by scanning
+/// brackets outside of strings. Malformed input is left for the parser.
+fn json_depth(body: &[u8]) -> usize {
+    let (mut depth, mut max_depth) = (0usize, 0usize);
+    let (mut in_string, mut escaped) = (false, false);
+    for &byte in body {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                max_depth = max_depth.max(depth);
+            }
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    max_depth
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_depth() {
+        assert_eq!(json_depth(b"42"), 0);
+        assert_eq!(json_depth(br#"{"a": [1, {"b": 2}], "c": {}}"#), 3);
+        // Brackets inside strings, including after escaped quotes, don't count.
+        assert_eq!(json_depth(br#"{"a": "[[[\"{{{"}"#), 1);
+
+        let limits = UpstreamLimits {
+            max_depth: 2,
+            ..Default::default()
+        };
+        assert!(limits.parse(br#"{"a": [1]}"#).is_ok());
+        assert!(limits.parse(br#"{"a": [[1]]}"#).is_err());
+    }
+
+    #[test]
+    fn test_decode() {
+        use flate2::write::{GzEncoder, ZlibEncoder};
+        use flate2::Compression;
+        use std::io::Write;
+
+        let json = br#"{"price": "1.5"}"#.to_vec();
+        let gzip = {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&json).unwrap();
+            encoder.finish().unwrap()
+        };
+        let zlib = {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(&json).unwrap();
+            encoder.finish().unwrap()
+        };
+        let br = {
+            let mut out = Vec::new();
+            brotli::CompressorWriter::new(&mut out, 4096, 5, 22).write_all(&json).unwrap();
+            out
+        };
+
+        let limits = UpstreamLimits::default();
+        assert_eq!(limits.decode(Some("gzip"), gzip.clone()).unwrap(), json);
+        assert_eq!(limits.decode(Some("deflate"), zlib.clone()).unwrap(), json);
+        assert_eq!(limits.decode(Some("br"), br.clone()).unwrap(), json);
+        assert_eq!(limits.decode(None, json.clone()).unwrap(), json);
+        // Mislabelled: gzip sent as identity or brotli, plain JSON sent as gzip.
+        assert_eq!(limits.decode(None, gzip.clone()).unwrap(), json);
+        assert_eq!(limits.decode(Some("br"), gzip.clone()).unwrap(), json);
+        assert_eq!(limits.decode(Some("gzip"), json.clone()).unwrap(), json);
+        assert!(limits.decode(Some("compress"), vec![0, 1, 2]).is_err());
+        assert!(limits.decode(Some("br"), br[..br.len() / 2].to_vec()).is_err());
+    }
+
+    #[test]
+    fn test_decompression_bomb() {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![b' '; 1024 * 1024]).unwrap();
+        let bomb = encoder.finish().unwrap();
+
+        let limits = UpstreamLimits {
+            max_decompressed_bytes: 64 * 1024,
+            ..Default::default()
+        };
+        assert!(bomb.len() < limits.max_body_bytes);
+        let err = limits.decode(Some("gzip"), bomb).unwrap_err();
+        assert!(err.contains("byte limit"));
+    }
+
+    #[test]
+    fn test_content_type() {
+        let limits = UpstreamLimits::default();
+        assert!(limits.check_content_type(Some("application/json")).is_ok());
+        assert!(limits.check_content_type(Some("Application/JSON; charset=utf-8")).is_ok());
+        assert!(limits.check_content_type(Some("text/html")).is_err());
+        assert!(limits.check_content_type(None).is_err());
+
+        let any = UpstreamLimits {
+            content_types: vec![],
+            ..Default::default()
+        };
+        assert!(any.check_content_type(None).is_ok());
+    }
+}
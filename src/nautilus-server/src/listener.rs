@@ -0,0 +1,345 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::server::graceful::{GracefulShutdown, Watcher};
+use hyper_util::service::TowerToHyperService;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::fmt::Debug;
+use std::fs::File;
+use std::future::Future;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_rustls::TlsAcceptor;
+use tokio_vsock::{VsockAddr, VsockListener};
+use tracing::{info, warn};
+
+use crate::config::{ListenerKind, Server, Tls};
+
+/// Serve `app` on the listener selected in the config until it fails or
+/// `shutdown` completes. With `max_connections` set, no further connection
+/// is accepted while that many are open; they wait in the listen backlog. On
+/// shutdown no new connections are accepted and open ones finish their
+/// in-flight requests, for at most `shutdown_timeout_secs`.
+pub async fn serve(
+    config: &Server,
+    max_connections: Option<usize>,
+    app: Router,
+    shutdown: impl Future<Output = ()>,
+) -> Result<()> {
+    let acceptor = config.tls.as_ref().map(tls_acceptor).transpose()?;
+    let graceful = GracefulShutdown::new();
+    let limit = ConnectionLimit(max_connections.map(|max| Arc::new(Semaphore::new(max))));
+
+    let accept = async {
+        match config.listener {
+            ListenerKind::Tcp => accept_tcp(&config.address, acceptor, app, &limit, &graceful).await,
+            ListenerKind::Vsock => {
+                accept_vsock(config.vsock_cid, config.vsock_port, acceptor, app, &limit, &graceful).await
+            }
+        }
+    };
+
+    tokio::select! {
+        result = accept => return result,
+        _ = shutdown => {}
+    }
+
+    info!("Shutting down, draining open connections");
+    let timeout = Duration::from_secs(config.shutdown_timeout_secs);
+    match tokio::time::timeout(timeout, graceful.shutdown()).await {
+        Ok(()) => info!("All connections drained"),
+        Err(_) => warn!("Connections still open after {:?}, dropping them", timeout),
+    }
+    Ok(())
+}
+
+/// Cap on open connections, if any.
+struct ConnectionLimit(Option<Arc<Semaphore>>);
+
+impl ConnectionLimit {
+    /// Wait for a connection slot, held until the permit is dropped.
+    async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        let semaphore = self.0.as_ref()?;
+        Some(
+            Arc::clone(semaphore)
+                .acquire_owned()
+                .await
+                .expect("connection semaphore is never closed"),
+        )
+    }
+}
+
+async fn accept_tcp(
+    address: &str,
+    acceptor: Option<TlsAcceptor>,
+    app: Router,
+    limit: &ConnectionLimit,
+    graceful: &GracefulShutdown,
+) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(address)
+        .await
+        .with_context(|| format!("Failed to bind {}", address))?;
+    info!("listening on {}", listener.local_addr()?);
+
+    loop {
+        let permit = limit.acquire().await;
+        let (stream, peer) = listener
+            .accept()
+            .await
+            .context("Failed to accept connection")?;
+        spawn_connection(stream, peer, acceptor.clone(), app.clone(), graceful.watcher(), permit);
+    }
+}
+
+/// Accept connections on an AF_VSOCK socket, so the parent instance can reach
+/// the enclave without a TCP forwarding sidecar inside it.
+async fn accept_vsock(
+    cid: u32,
+    port: u32,
+    acceptor: Option<TlsAcceptor>,
+    app: Router,
+    limit: &ConnectionLimit,
+    graceful: &GracefulShutdown,
+) -> Result<()> {
+    let mut listener = VsockListener::bind(VsockAddr::new(cid, port))
+        .with_context(|| format!("Failed to bind vsock {}:{}", cid, port))?;
+    info!("listening on vsock {}:{}", cid, port);
+
+    loop {
+        let permit = limit.acquire().await;
+        let (stream, peer) = listener
+            .accept()
+            .await
+            .context("Failed to accept vsock connection")?;
+        spawn_connection(stream, peer, acceptor.clone(), app.clone(), graceful.watcher(), permit);
+    }
+}
+
+/// Completes on SIGTERM or SIGINT.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    let terminate = async {
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+    }
+}
+
+/// Serve HTTP on a single accepted connection, after a TLS handshake if an
+/// acceptor is given. `watcher` lets shutdown wait for it to finish, and
+/// `permit` frees its connection slot once it is closed.
+fn spawn_connection<S, P>(
+    stream: S,
+    peer: P,
+    acceptor: Option<TlsAcceptor>,
+    app: Router,
+    watcher: Watcher,
+    permit: Option<OwnedSemaphorePermit>,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    P: Debug + Send + 'static,
+{
+    tokio::spawn(async move {
+        let _permit = permit;
+        let service = TowerToHyperService::new(app);
+        let builder = Builder::new(TokioExecutor::new());
+        let result = match acceptor {
+            Some(acceptor) => match acceptor.accept(stream).await {
+                Ok(stream) => {
+                    watcher
+                        .watch(builder.serve_connection(TokioIo::new(stream), service))
+                        .await
+                }
+                Err(e) => {
+                    warn!("TLS handshake with {:?} failed: {}", peer, e);
+                    return;
+                }
+            },
+            None => {
+                watcher
+                    .watch(builder.serve_connection(TokioIo::new(stream), service))
+                    .await
+            }
+        };
+        if let Err(e) = result {
+            warn!("connection from {:?} failed: {}", peer, e);
+        }
+    });
+}
+
+/// Build a rustls acceptor from the configured PEM files. With a client CA
+/// set, clients must present a certificate signed by it.
+pub fn tls_acceptor(tls: &Tls) -> Result<TlsAcceptor> {
+    let certs = load_certs(&tls.cert_path)?;
+    let key: PrivateKeyDer<'static> =
+        rustls_pemfile::private_key(&mut BufReader::new(open(&tls.key_path)?))
+            .with_context(|| format!("Failed to read private key from {}", tls.key_path))?
+            .ok_or_else(|| anyhow::anyhow!("No private key found in {}", tls.key_path))?;
+
+    let builder = ServerConfig::builder();
+    let builder = match &tls.client_ca_path {
+        Some(client_ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(client_ca_path)? {
+                roots.add(cert).context("Invalid client CA certificate")?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots))
+                .build()
+                .context("Failed to build client certificate verifier")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate or key")?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(open(path)?))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("Failed to read certificates from {}", path))?;
+    if certs.is_empty() {
+        return Err(anyhow::anyhow!("No certificates found in {}", path));
+    }
+    Ok(certs)
+}
+
+fn open(path: &str) -> Result<File> {
+    File::open(path).with_context(|| format!("Failed to open {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn pem_file(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_tls_acceptor() {
+        let server = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let cert = pem_file(&server.cert.pem());
+        let key = pem_file(&server.key_pair.serialize_pem());
+        let path = |file: &tempfile::NamedTempFile| file.path().to_str().unwrap().to_string();
+
+        let mut tls = Tls {
+            cert_path: path(&cert),
+            key_path: path(&key),
+            client_ca_path: None,
+        };
+        assert!(tls_acceptor(&tls).is_ok());
+
+        tls.client_ca_path = Some(path(&cert));
+        assert!(tls_acceptor(&tls).is_ok());
+
+        // A key file is not a certificate.
+        tls.client_ca_path = Some(path(&key));
+        assert!(tls_acceptor(&tls).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_in_flight() {
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let config = Server {
+            address: address.to_string(),
+            ..Default::default()
+        };
+        // Signalled once the request reaches the handler, so shutdown starts
+        // while it is in flight however long the client takes to connect.
+        let started = Arc::new(tokio::sync::Notify::new());
+        let handler_started = Arc::clone(&started);
+        let app = Router::new().route(
+            "/slow",
+            axum::routing::get(move || async move {
+                handler_started.notify_one();
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                "done"
+            }),
+        );
+
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(async move {
+            serve(&config, None, app, async {
+                shutdown_rx.await.ok();
+            })
+            .await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let request = tokio::spawn(reqwest::get(format!("http://{}/slow", address)));
+        started.notified().await;
+        shutdown_tx.send(()).unwrap();
+
+        // The request started before shutdown still completes.
+        let response = request.await.unwrap().unwrap();
+        assert_eq!(response.text().await.unwrap(), "done");
+        server.await.unwrap().unwrap();
+        assert!(reqwest::get(format!("http://{}/slow", address)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_max_connections() {
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let config = Server {
+            address: address.to_string(),
+            ..Default::default()
+        };
+        let app = Router::new().route("/", axum::routing::get(|| async { "ok" }));
+        let server = tokio::spawn(async move { serve(&config, Some(1), app, std::future::pending()).await });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // An idle connection holds the only slot, so a request on a second
+        // one is not served until it closes.
+        let idle = tokio::net::TcpStream::connect(address).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_millis(300))
+            .build()
+            .unwrap();
+        assert!(client.get(format!("http://{}/", address)).send().await.is_err());
+
+        drop(idle);
+        let response = client.get(format!("http://{}/", address)).send().await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "ok");
+        server.abort();
+    }
+}
@@ -2,35 +2,46 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use anyhow::Result;
-use axum::{routing::get, routing::post, Router};
-use nautilus_server::app::process_data;
-use nautilus_server::common::{get_attestation, health_check};
-use nautilus_server::AppState;
-use tower_http::cors::{Any, CorsLayer};
+use nautilus_server::config::{load_config, Config};
+use nautilus_server::{
+    health, listener, mirrors, persistence, push, refresher, router, subscription, telemetry, AppState,
+};
 use tracing::info;
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let state = AppState::new().await?;
+fn main() -> Result<()> {
+    telemetry::init();
+    // The config is loaded before the runtime exists so that it can size it.
+    let config = load_config()?;
+    config.runtime.build()?.block_on(run(config))
+}
 
-    // Define your own restricted CORS policy here if needed.
-    let cors = CorsLayer::new().allow_methods(Any).allow_headers(Any);
+async fn run(config: Config) -> Result<()> {
+    let max_connections = config.runtime.max_connections;
+    let server = config.server.clone();
+    let self_test = config.sui.self_test;
+    telemetry::export(&config.telemetry)?;
+    let state = AppState::new(config).await?;
 
-    let app = Router::new()
-        .route("/", get(ping))
-        .route("/get_attestation", get(get_attestation))
-        .route("/process_data", post(process_data))
-        .route("/health_check", get(health_check))
-        .with_state(state)
-        .layer(cors);
+    if self_test {
+        state.sui_client.load().check_package().await?;
+        info!("Sui self-test passed");
+    }
+    state.spawn_config_watcher();
+    health::spawn_prober(&state);
+    mirrors::spawn_comparisons(&state);
+    refresher::spawn_refresher(&state);
+    push::spawn_pusher(&state);
+    persistence::spawn_flusher(&state);
+    subscription::spawn_subscriber(&state);
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
-    info!("listening on {}", listener.local_addr().unwrap());
-    axum::serve(listener, app.into_make_service())
-        .await
-        .map_err(|e| anyhow::anyhow!("Server error: {}", e))
-}
+    let app = router(state.clone());
+    listener::serve(&server, max_connections, app, listener::shutdown_signal()).await?;
 
-async fn ping() -> &'static str {
-    "Pong!"
+    // Queued pushes go out before the store is flushed, so that whatever
+    // is left is what gets persisted
+    push::drain(&state).await;
+    state.history.flush();
+    telemetry::shutdown();
+    info!("Shutdown complete");
+    Ok(())
 }
@@ -0,0 +1,253 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use chrono::{DateTime, Datelike, NaiveDate, Timelike, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+/// How far ahead `next_open` looks, enough to skip a run of holidays.
+const MAX_SEARCH_MINUTES: u32 = 28 * MINUTES_PER_DAY;
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Built-in trading calendars.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Calendar {
+    /// NYSE/Nasdaq regular session, 09:30-16:00 New York time on weekdays
+    UsEquities,
+    /// Spot FX, Sunday 17:00 to Friday 17:00 New York time
+    Fx,
+}
+
+/// What a feed with market hours serves while its market is closed.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WhenClosed {
+    /// The last close, signed under the market closed intent
+    #[default]
+    LastClose,
+    /// Nothing: requests are refused until the market reopens
+    Refuse,
+}
+
+/// A weekly trading session, e.g. `open = "Mon 09:30"`, `close = "Mon 16:00"`.
+/// A session may wrap around the end of the week.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Session {
+    pub open: String,
+    pub close: String,
+}
+
+/// When a feed's market is open. Either a built-in `calendar` or explicit
+/// `timezone` and `sessions`; explicit values take precedence over the
+/// calendar's. Holidays are local dates on which the market stays closed.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MarketHours {
+    #[serde(default)]
+    pub calendar: Option<Calendar>,
+    /// IANA timezone name, e.g. "America/New_York"
+    #[serde(default)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    pub sessions: Vec<Session>,
+    #[serde(default)]
+    pub holidays: Vec<NaiveDate>,
+}
+
+impl MarketHours {
+    /// Whether the market is open at `at`.
+    pub fn is_open(&self, at: DateTime<Utc>) -> Result<bool, String> {
+        Ok(self.open_in(&self.timezone()?, &self.session_ranges()?, at))
+    }
+
+    /// Start of the first minute after `at` at which the market is open, if
+    /// it opens within the next four weeks.
+    pub fn next_open(&self, at: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, String> {
+        let (timezone, ranges) = (self.timezone()?, self.session_ranges()?);
+        let start = at.timestamp() / 60 + 1;
+        Ok((start..start + i64::from(MAX_SEARCH_MINUTES))
+            .filter_map(|minute| DateTime::from_timestamp(minute * 60, 0))
+            .find(|candidate| self.open_in(&timezone, &ranges, *candidate)))
+    }
+
+    fn open_in(&self, timezone: &Tz, ranges: &[(u32, u32)], at: DateTime<Utc>) -> bool {
+        let local = at.with_timezone(timezone);
+        if self.holidays.contains(&local.date_naive()) {
+            return false;
+        }
+
+        let minute_of_week = local.weekday().num_days_from_monday() * MINUTES_PER_DAY
+            + local.hour() * 60
+            + local.minute();
+
+        ranges.iter().any(|&(open, close)| {
+            if open <= close {
+                minute_of_week >= open && minute_of_week < close
+            } else {
+                minute_of_week >= open || minute_of_week < close
+            }
+        })
+    }
+
+    /// Check the timezone and sessions parse.
+    pub fn validate(&self) -> Result<(), String> {
+        self.timezone()?;
+        if self.session_ranges()?.is_empty() {
+            return Err("no sessions or calendar configured".to_string());
+        }
+        Ok(())
+    }
+
+    fn timezone(&self) -> Result<Tz, String> {
+        let name = match (&self.timezone, self.calendar) {
+            (Some(name), _) => name.as_str(),
+            (None, Some(_)) => "America/New_York",
+            (None, None) => "UTC",
+        };
+        name.parse()
+            .map_err(|_| format!("unknown timezone '{}'", name))
+    }
+
+    /// Sessions as minute-of-week ranges, Monday 00:00 being minute 0.
+    fn session_ranges(&self) -> Result<Vec<(u32, u32)>, String> {
+        if !self.sessions.is_empty() {
+            return self
+                .sessions
+                .iter()
+                .map(|s| Ok((parse_minute_of_week(&s.open)?, parse_minute_of_week(&s.close)?)))
+                .collect();
+        }
+
+        let ranges = match self.calendar {
+            Some(Calendar::UsEquities) => (0..5)
+                .map(|day| (day * MINUTES_PER_DAY + 9 * 60 + 30, day * MINUTES_PER_DAY + 16 * 60))
+                .collect(),
+            Some(Calendar::Fx) => vec![(6 * MINUTES_PER_DAY + 17 * 60, 4 * MINUTES_PER_DAY + 17 * 60)],
+            None => Vec::new(),
+        };
+        Ok(ranges)
+    }
+}
+
+/// Parse "Mon 09:30" into minutes since Monday 00:00.
+fn parse_minute_of_week(value: &str) -> Result<u32, String> {
+    let invalid = || format!("invalid session time '{}', expected e.g. 'Mon 09:30'", value);
+
+    let (day, time) = value.trim().split_once(' ').ok_or_else(invalid)?;
+    let day = WEEKDAYS
+        .iter()
+        .position(|d| d.eq_ignore_ascii_case(day))
+        .ok_or_else(invalid)? as u32;
+    let (hour, minute) = time.split_once(':').ok_or_else(invalid)?;
+    let hour: u32 = hour.parse().map_err(|_| invalid())?;
+    let minute: u32 = minute.parse().map_err(|_| invalid())?;
+    if hour > 24 || minute > 59 || (hour == 24 && minute != 0) {
+        return Err(invalid());
+    }
+
+    Ok(day * MINUTES_PER_DAY + hour * 60 + minute)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn test_us_equities() {
+        let hours = MarketHours {
+            calendar: Some(Calendar::UsEquities),
+            holidays: vec![NaiveDate::from_ymd_opt(2025, 7, 4).unwrap()],
+            ..Default::default()
+        };
+        hours.validate().unwrap();
+
+        // Tuesday 2025-07-01, EDT is UTC-4: 13:30 UTC is 09:30 local.
+        assert!(!hours.is_open(utc(2025, 7, 1, 13, 29)).unwrap());
+        assert!(hours.is_open(utc(2025, 7, 1, 13, 30)).unwrap());
+        assert!(!hours.is_open(utc(2025, 7, 1, 20, 0)).unwrap());
+        // Holiday and weekend.
+        assert!(!hours.is_open(utc(2025, 7, 4, 15, 0)).unwrap());
+        assert!(!hours.is_open(utc(2025, 7, 5, 15, 0)).unwrap());
+        // Winter, EST is UTC-5.
+        assert!(!hours.is_open(utc(2025, 1, 7, 14, 0)).unwrap());
+        assert!(hours.is_open(utc(2025, 1, 7, 14, 30)).unwrap());
+    }
+
+    #[test]
+    fn test_fx_wraps_weekend() {
+        let hours = MarketHours {
+            calendar: Some(Calendar::Fx),
+            ..Default::default()
+        };
+        // Saturday closed, Sunday evening open, Wednesday open.
+        assert!(!hours.is_open(utc(2025, 7, 5, 12, 0)).unwrap());
+        assert!(hours.is_open(utc(2025, 7, 6, 22, 0)).unwrap());
+        assert!(hours.is_open(utc(2025, 7, 2, 3, 0)).unwrap());
+        assert!(!hours.is_open(utc(2025, 7, 4, 21, 30)).unwrap());
+    }
+
+    #[test]
+    fn test_custom_sessions() {
+        let hours = MarketHours {
+            timezone: Some("Asia/Tokyo".to_string()),
+            sessions: vec![Session {
+                open: "Mon 09:00".to_string(),
+                close: "Mon 15:00".to_string(),
+            }],
+            ..Default::default()
+        };
+        // Monday 2025-07-07 10:00 JST is 01:00 UTC.
+        assert!(hours.is_open(utc(2025, 7, 7, 1, 0)).unwrap());
+        assert!(!hours.is_open(utc(2025, 7, 8, 1, 0)).unwrap());
+
+        let bad = MarketHours {
+            sessions: vec![Session {
+                open: "Funday 09:00".to_string(),
+                close: "Mon 15:00".to_string(),
+            }],
+            ..Default::default()
+        };
+        assert!(bad.validate().is_err());
+        assert!(MarketHours::default().validate().is_err());
+    }
+
+    #[test]
+    fn test_next_open() {
+        // A five minute settlement window on Fridays at 16:00 UTC.
+        let window = MarketHours {
+            sessions: vec![Session {
+                open: "Fri 16:00".to_string(),
+                close: "Fri 16:05".to_string(),
+            }],
+            ..Default::default()
+        };
+        // Wednesday 2025-07-02 to Friday 2025-07-04.
+        assert_eq!(window.next_open(utc(2025, 7, 2, 12, 0)).unwrap(), Some(utc(2025, 7, 4, 16, 0)));
+        // Inside the window the next opening is the following minute.
+        assert_eq!(
+            window.next_open(utc(2025, 7, 4, 16, 1)).unwrap(),
+            Some(utc(2025, 7, 4, 16, 2))
+        );
+        // A holiday pushes it back a week.
+        let window = MarketHours {
+            holidays: vec![NaiveDate::from_ymd_opt(2025, 7, 4).unwrap()],
+            ..window
+        };
+        assert_eq!(window.next_open(utc(2025, 7, 2, 12, 0)).unwrap(), Some(utc(2025, 7, 11, 16, 0)));
+
+        let never = MarketHours {
+            sessions: vec![Session {
+                open: "Mon 00:00".to_string(),
+                close: "Mon 00:00".to_string(),
+            }],
+            ..Default::default()
+        };
+        assert_eq!(never.next_open(utc(2025, 7, 2, 12, 0)).unwrap(), None);
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Maps deprecated config fields onto the current [`Config`](crate::config::Config)
+//! layout, so config files keep loading across upgrades.
+
+use toml::{Table, Value};
+
+/// Deprecated dotted paths and the path their value moved to.
+pub const RENAMED_FIELDS: &[(&str, &str)] = &[
+    ("sui.package_id", "sui.oracle_builder_package_id"),
+    ("sui.url", "sui.rpc_url"),
+    ("response.decimals", "response.price_decimals"),
+    ("runtime.workers", "runtime.worker_threads"),
+    ("runtime.max_concurrent_requests", "runtime.max_connections"),
+    ("key.rotation_grace_period_secs", "key.rotation_grace_secs"),
+];
+
+/// Rewrite deprecated fields of a parsed config file in place. Returns one
+/// warning per field that was migrated or ignored.
+pub fn migrate(config: &mut Table) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for (from, to) in RENAMED_FIELDS {
+        if let Some(value) = take(config, from) {
+            warnings.push(move_value(config, value, from, to));
+        }
+    }
+
+    // `key.sealed = true` predates the `key.mode` enum.
+    if let Some(sealed) = take(config, "key.sealed") {
+        let mode = match sealed.as_bool() {
+            Some(true) => "sealed",
+            _ => "ephemeral",
+        };
+        warnings.push(move_value(config, Value::from(mode), "key.sealed", "key.mode"));
+    }
+
+    warnings
+}
+
+/// Store `value` at `to` unless it is already set there, and describe what happened.
+fn move_value(config: &mut Table, value: Value, from: &str, to: &str) -> String {
+    if get(config, to).is_some() {
+        return format!("Config field '{}' is deprecated and ignored since '{}' is set", from, to);
+    }
+    set(config, to, value);
+    format!("Config field '{}' is deprecated, use '{}' instead", from, to)
+}
+
+fn get<'a>(table: &'a Table, path: &str) -> Option<&'a Value> {
+    let (parent, key) = match path.rsplit_once('.') {
+        Some((parent, key)) => (parent_table(table, parent)?, key),
+        None => (table, path),
+    };
+    parent.get(key)
+}
+
+fn take(table: &mut Table, path: &str) -> Option<Value> {
+    match path.rsplit_once('.') {
+        Some((parent, key)) => parent_table_mut(table, parent, false)?.remove(key),
+        None => table.remove(path),
+    }
+}
+
+fn set(table: &mut Table, path: &str, value: Value) {
+    match path.rsplit_once('.') {
+        Some((parent, key)) => {
+            if let Some(parent) = parent_table_mut(table, parent, true) {
+                parent.insert(key.to_string(), value);
+            }
+        }
+        None => {
+            table.insert(path.to_string(), value);
+        }
+    }
+}
+
+fn parent_table<'a>(table: &'a Table, path: &str) -> Option<&'a Table> {
+    path.split('.')
+        .try_fold(table, |table, key| table.get(key)?.as_table())
+}
+
+/// Walk to the table at `path`, creating missing tables if `create` is set.
+fn parent_table_mut<'a>(table: &'a mut Table, path: &str, create: bool) -> Option<&'a mut Table> {
+    path.split('.').try_fold(table, |table, key| {
+        if create && !table.contains_key(key) {
+            table.insert(key.to_string(), Value::Table(Table::new()));
+        }
+        table.get_mut(key)?.as_table_mut()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate() {
+        let mut config: Table = toml::from_str(
+            r#"
+            [sui]
+            url = "http://old"
+            package_id = "0x1"
+            oracle_builder_package_id = "0x2"
+
+            [response]
+            decimals = 6
+
+            [key]
+            sealed = true
+            "#,
+        )
+        .unwrap();
+
+        let warnings = migrate(&mut config);
+        assert_eq!(warnings.len(), 4);
+        assert!(warnings.iter().any(|w| w.contains("'sui.package_id' is deprecated and ignored")));
+
+        assert_eq!(config["sui"]["rpc_url"].as_str(), Some("http://old"));
+        assert_eq!(config["sui"]["oracle_builder_package_id"].as_str(), Some("0x2"));
+        assert!(config["sui"].get("package_id").is_none());
+        assert_eq!(config["response"]["price_decimals"].as_integer(), Some(6));
+        assert_eq!(config["key"]["mode"].as_str(), Some("sealed"));
+        assert!(config.get("runtime").is_none());
+
+        // A current config is left alone.
+        let mut current = config.clone();
+        assert!(migrate(&mut current).is_empty());
+        assert_eq!(current, config);
+    }
+}
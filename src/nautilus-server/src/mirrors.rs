@@ -0,0 +1,421 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Mirror comparison: a scheduled job fetches every feed that lists
+//! `mirrors` from its primary upstream and from each mirror, and records how
+//! far each mirror deviates from the primary in the history store. Mirrors
+//! that deviate in most recent comparisons are reported by
+//! `GET /admin/mirrors`.
+
+use axum::{extract::State, Json};
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::app::{extract_price, fetch_upstream};
+use crate::common::current_timestamp_ms;
+use crate::config::check_http_url;
+use crate::formats::UpstreamFormat;
+use crate::history::Observation;
+use crate::types::PriceFeed;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// How often the job checks whether it has been enabled while disabled.
+const DISABLED_RECHECK: Duration = Duration::from_secs(5);
+
+/// `[mirrors]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Mirrors {
+    /// Seconds between comparisons; 0 disables the job
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+    /// Deviation from the primary, in basis points, above which a
+    /// comparison counts as divergent
+    #[serde(default = "default_max_deviation_bps")]
+    pub max_deviation_bps: u64,
+    /// Number of most recent comparisons a mirror is judged on
+    #[serde(default = "default_window")]
+    pub window: usize,
+    /// Share of the window, in basis points, that must be divergent for a
+    /// mirror to be reported as chronically divergent
+    #[serde(default = "default_chronic_bps")]
+    pub chronic_bps: u64,
+}
+
+impl Default for Mirrors {
+    fn default() -> Self {
+        Self {
+            interval_secs: default_interval_secs(),
+            max_deviation_bps: default_max_deviation_bps(),
+            window: default_window(),
+            chronic_bps: default_chronic_bps(),
+        }
+    }
+}
+
+fn default_interval_secs() -> u64 {
+    300
+}
+
+fn default_max_deviation_bps() -> u64 {
+    50
+}
+
+fn default_window() -> usize {
+    12
+}
+
+fn default_chronic_bps() -> u64 {
+    5000
+}
+
+/// An alternative upstream for a feed, configured under
+/// `[[feeds."0x...".mirrors]]`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Mirror {
+    pub name: String,
+    pub url: String,
+    /// Path of the price in the mirror's response, instead of the on-chain
+    /// `response_field`
+    #[serde(default)]
+    pub response_field: Option<String>,
+    /// Environment variable holding the API key, if the mirror needs one
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// How the API key is sent: "Bearer" or "x-api-key"
+    #[serde(default)]
+    pub api_key_config: Option<String>,
+}
+
+/// Check the mirrors of a feed, returning the problems found.
+pub fn validate(feed_id: &str, mirrors: &[Mirror]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (i, mirror) in mirrors.iter().enumerate() {
+        let prefix = format!("feeds.{}.mirrors[{}]", feed_id, i);
+        if mirror.name.is_empty() || mirror.name.contains('/') {
+            problems.push(format!("{}.name must be non-empty and must not contain '/'", prefix));
+        }
+        if mirrors[..i].iter().any(|other| other.name == mirror.name) {
+            problems.push(format!("{}.name '{}' is used twice", prefix, mirror.name));
+        }
+        if let Err(e) = check_http_url(&mirror.url) {
+            problems.push(format!("{}.url {}", prefix, e));
+        }
+        if mirror.api_key_config.is_some() != mirror.api_key_env.is_some() {
+            problems.push(format!("{}.api_key_env and api_key_config must be set together", prefix));
+        }
+    }
+    problems
+}
+
+/// History store key under which the deviations of `mirror` from the
+/// primary of `price_feed_id` are kept, as observations whose `price` is the
+/// deviation in basis points.
+pub fn history_key(price_feed_id: &str, mirror: &str) -> String {
+    format!("{}/mirror/{}", price_feed_id, mirror)
+}
+
+/// Compare every feed that has mirrors once.
+pub async fn compare_feeds(state: &AppState) {
+    let config = state.config.load_full();
+    let mirrored: Vec<_> = config
+        .feeds
+        .iter()
+        .filter(|(_, feed)| !feed.mirrors.is_empty())
+        .collect();
+    let price_feed_ids: Vec<&str> = mirrored.iter().map(|(price_feed_id, _)| price_feed_id.as_str()).collect();
+    let price_feeds = state.sui_client.load_full().fetch_price_feeds(&price_feed_ids).await;
+    for ((price_feed_id, feed), price_feed) in mirrored.into_iter().zip(price_feeds) {
+        let primary = match price_feed {
+            Ok(price_feed) => fetch_primary(state, price_feed_id, price_feed).await,
+            Err(e) => {
+                warn!("Skipping mirror comparison of feed {}: failed to fetch price feed: {}", price_feed_id, e);
+                continue;
+            }
+        };
+        let primary = match primary {
+            Ok(primary) => primary,
+            Err(e) => {
+                warn!("Skipping mirror comparison of feed {}: {}", price_feed_id, e);
+                continue;
+            }
+        };
+        for mirror in &feed.mirrors {
+            let deviation_bps = match fetch_mirror(state, price_feed_id, mirror, &primary).await {
+                Ok(price) => deviation_bps(primary.price, price),
+                Err(e) => {
+                    warn!("Failed to fetch mirror {} of feed {}: {}", mirror.name, price_feed_id, e);
+                    continue;
+                }
+            };
+            if deviation_bps > config.mirrors.max_deviation_bps {
+                warn!(
+                    "Mirror {} of feed {} deviates {} bps from the primary",
+                    mirror.name, price_feed_id, deviation_bps
+                );
+            }
+            let timestamp_ms = current_timestamp_ms().unwrap_or_default();
+            state.history.record(
+                &history_key(price_feed_id, &mirror.name),
+                Observation {
+                    price: deviation_bps,
+                    timestamp_ms,
+                },
+            );
+        }
+    }
+}
+
+/// Price extracted from the feed's primary upstream, with the on-chain
+/// response field mirrors default to.
+struct Primary {
+    price: Decimal,
+    response_field: String,
+}
+
+async fn fetch_primary(
+    state: &AppState,
+    price_feed_id: &str,
+    price_feed: PriceFeed,
+) -> Result<Primary, EnclaveError> {
+    let config = state.config.load_full();
+    let json = fetch_upstream(
+        state,
+        &config,
+        price_feed_id,
+        &price_feed.underlying_url,
+        price_feed.api_key.as_deref().zip(price_feed.api_key_config.as_deref()),
+        &config.feed(price_feed_id).format,
+    )
+    .await?;
+    let price = match &config.feed(price_feed_id).transform {
+        Some(transform) => state
+            .transforms
+            .run(transform, price_feed.transform_hash.as_deref(), &json)
+            .map_err(|e| EnclaveError::GenericError(format!("Transform failed: {}", e)))?,
+        None => extract_price(&json, &price_feed.response_field).map_err(EnclaveError::GenericError)?,
+    };
+    Ok(Primary {
+        price,
+        response_field: price_feed.response_field,
+    })
+}
+
+async fn fetch_mirror(
+    state: &AppState,
+    price_feed_id: &str,
+    mirror: &Mirror,
+    primary: &Primary,
+) -> Result<Decimal, EnclaveError> {
+    let config = state.config.load_full();
+    let api_key = match &mirror.api_key_env {
+        Some(env) => Some(std::env::var(env).map_err(|_| {
+            EnclaveError::GenericError(format!("API key variable {} is not set", env))
+        })?),
+        None => None,
+    };
+    // Mirrors have their own response shape
+    let json = fetch_upstream(
+        state,
+        &config,
+        &history_key(price_feed_id, &mirror.name),
+        &mirror.url,
+        api_key.as_deref().zip(mirror.api_key_config.as_deref()),
+        &UpstreamFormat::default(),
+    )
+    .await?;
+    let response_field = mirror.response_field.as_deref().unwrap_or(&primary.response_field);
+    extract_price(&json, response_field).map_err(EnclaveError::GenericError)
+}
+
+/// Deviation of `mirror` from `primary` in basis points, as extracted and
+/// before any scaling. Saturates when the primary is zero.
+fn deviation_bps(primary: Decimal, mirror: Decimal) -> u64 {
+    if primary == mirror {
+        return 0;
+    }
+    (mirror - primary)
+        .abs()
+        .checked_mul(Decimal::from(10_000))
+        .and_then(|scaled| scaled.checked_div(primary.abs()))
+        .and_then(|bps| {
+            bps.round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
+                .to_u64()
+        })
+        .unwrap_or(u64::MAX)
+}
+
+/// Spawn the comparison job. The interval is re-read from the config before
+/// every round, so reloads take effect without a restart.
+pub fn spawn_comparisons(state: &Arc<AppState>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        info!("Mirror comparison job started");
+        loop {
+            let interval_secs = state.config.load().mirrors.interval_secs;
+            if interval_secs == 0 {
+                tokio::time::sleep(DISABLED_RECHECK).await;
+                continue;
+            }
+            compare_feeds(&state).await;
+            tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+        }
+    });
+}
+
+/// Deviation statistics of one mirror over the window.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MirrorStats {
+    pub price_feed_id: String,
+    pub mirror: String,
+    /// Comparisons in the window
+    pub samples: usize,
+    pub mean_deviation_bps: u64,
+    pub max_deviation_bps: u64,
+    /// Comparisons above `mirrors.max_deviation_bps`
+    pub divergent_samples: usize,
+    /// Whether at least `mirrors.chronic_bps` of the window is divergent
+    pub chronic: bool,
+    pub last_compared_ms: u64,
+}
+
+/// Response for mirrors.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MirrorsResponse {
+    pub mirrors: Vec<MirrorStats>,
+}
+
+/// Endpoint that reports the deviation statistics of every configured
+/// mirror, chronically divergent ones first.
+pub async fn mirror_stats(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<MirrorsResponse>, EnclaveError> {
+    let config = state.config.load_full();
+    let history = state
+        .history
+        .export()
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to read history: {:#}", e)))?;
+
+    let mut mirrors = Vec::new();
+    for (price_feed_id, feed) in &config.feeds {
+        for mirror in &feed.mirrors {
+            let observations = history
+                .get(&history_key(price_feed_id, &mirror.name))
+                .map_or(&[][..], Vec::as_slice);
+            let recent = &observations[observations.len().saturating_sub(config.mirrors.window)..];
+            if let Some(stats) = stats(price_feed_id, &mirror.name, recent, &config.mirrors) {
+                mirrors.push(stats);
+            }
+        }
+    }
+    mirrors.sort_by(|a, b| {
+        b.chronic
+            .cmp(&a.chronic)
+            .then_with(|| a.price_feed_id.cmp(&b.price_feed_id))
+            .then_with(|| a.mirror.cmp(&b.mirror))
+    });
+    Ok(Json(MirrorsResponse { mirrors }))
+}
+
+/// Statistics of a mirror's recent deviations, oldest first.
+fn stats(price_feed_id: &str, mirror: &str, recent: &[Observation], config: &Mirrors) -> Option<MirrorStats> {
+    let last = recent.last()?;
+    let samples = recent.len();
+    let sum: u128 = recent.iter().map(|o| u128::from(o.price)).sum();
+    let divergent_samples = recent
+        .iter()
+        .filter(|o| o.price > config.max_deviation_bps)
+        .count();
+    Some(MirrorStats {
+        price_feed_id: price_feed_id.to_string(),
+        mirror: mirror.to_string(),
+        samples,
+        mean_deviation_bps: (sum / samples as u128) as u64,
+        max_deviation_bps: recent.iter().map(|o| o.price).max().unwrap_or_default(),
+        divergent_samples,
+        chronic: divergent_samples as u128 * 10_000 >= u128::from(config.chronic_bps) * samples as u128,
+        last_compared_ms: last.timestamp_ms,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_deviation_bps() {
+        let price = |s| Decimal::from_str(s).unwrap();
+        assert_eq!(deviation_bps(price("100"), price("100")), 0);
+        assert_eq!(deviation_bps(price("100"), price("101")), 100);
+        assert_eq!(deviation_bps(price("100"), price("99.995")), 1);
+        assert_eq!(deviation_bps(price("0"), price("1")), u64::MAX);
+    }
+
+    #[test]
+    fn test_deviation_bps_rounds_midpoints_away_from_zero() {
+        let price = |s| Decimal::from_str(s).unwrap();
+        // 2.5 bps: banker's rounding would report 2
+        assert_eq!(deviation_bps(price("100"), price("100.025")), 3);
+        assert_eq!(deviation_bps(price("100"), price("99.975")), 3);
+        assert_eq!(deviation_bps(price("100"), price("100.0249")), 2);
+    }
+
+    #[test]
+    fn test_stats() {
+        let config = Mirrors {
+            max_deviation_bps: 50,
+            chronic_bps: 5000,
+            ..Default::default()
+        };
+        let observations = |deviations: &[u64]| {
+            deviations
+                .iter()
+                .enumerate()
+                .map(|(i, &price)| Observation {
+                    price,
+                    timestamp_ms: i as u64,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let stats_of = |deviations: &[u64]| stats("0x1", "m", &observations(deviations), &config);
+        let healthy = stats_of(&[0, 10, 100, 10]).unwrap();
+        assert!(!healthy.chronic);
+        assert_eq!(healthy.mean_deviation_bps, 30);
+        assert_eq!(healthy.max_deviation_bps, 100);
+        assert_eq!(healthy.divergent_samples, 1);
+        assert_eq!(healthy.last_compared_ms, 3);
+
+        assert!(stats_of(&[0, 60, 100, 10]).unwrap().chronic);
+        assert!(stats_of(&[]).is_none());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mirror = Mirror {
+            name: "backup".to_string(),
+            url: "https://mirror.example.com/price".to_string(),
+            response_field: None,
+            api_key_env: None,
+            api_key_config: None,
+        };
+        assert!(validate("0x1", &[mirror.clone()]).is_empty());
+        let problems = validate(
+            "0x1",
+            &[
+                mirror.clone(),
+                Mirror {
+                    url: "ftp://mirror.example.com".to_string(),
+                    api_key_env: Some("KEY".to_string()),
+                    ..mirror
+                },
+            ],
+        );
+        assert_eq!(problems.len(), 3);
+    }
+}
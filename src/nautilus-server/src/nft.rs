@@ -0,0 +1,288 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! NFT floor price oracle module: signs the floor price of a collection as
+//! reported by one of the configured marketplace APIs, together with the
+//! collection id and the currency it is quoted in.
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::app::{encode_url_component, extract_field_from_json, extract_price, fetch_upstream};
+use crate::common::{
+    current_timestamp_ms, to_signed_response, IntentMessage, IntentScope, ProcessDataRequest,
+    ProcessedDataResponse,
+};
+use crate::config::check_http_url;
+use crate::formats::UpstreamFormat;
+use crate::oracle::{self, OracleModule, Signed};
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Placeholder in a marketplace's `url` replaced by the collection id.
+const COLLECTION_PLACEHOLDER: &str = "{collection_id}";
+
+/// A marketplace API floor prices are read from, configured under
+/// `[nft.<name>]`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NftMarketplace {
+    /// Collection stats endpoint, with `{collection_id}` where the id goes
+    pub url: String,
+    /// Path of the floor price in the response
+    pub floor_price_field: String,
+    /// Currency every floor price is quoted in, e.g. `ETH`, for APIs that
+    /// don't report it
+    #[serde(default)]
+    pub currency: Option<String>,
+    /// Path of the currency in the response, instead of `currency`
+    #[serde(default)]
+    pub currency_field: Option<String>,
+    /// Number of decimals floor prices are scaled by, instead of
+    /// `response.price_decimals`
+    #[serde(default)]
+    pub decimals: Option<u32>,
+    /// Environment variable holding the API key, if the marketplace needs one
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// How the API key is sent: "Bearer" or "x-api-key"
+    #[serde(default)]
+    pub api_key_config: Option<String>,
+}
+
+/// Check every marketplace, returning the problems found.
+pub fn validate(marketplaces: &HashMap<String, NftMarketplace>) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (name, marketplace) in marketplaces {
+        if !marketplace.url.contains(COLLECTION_PLACEHOLDER) {
+            problems.push(format!("nft.{}.url must contain {}", name, COLLECTION_PLACEHOLDER));
+        }
+        if let Err(e) = check_http_url(&marketplace.url.replace(COLLECTION_PLACEHOLDER, "0")) {
+            problems.push(format!("nft.{}.url {}", name, e));
+        }
+        if marketplace.currency.is_some() == marketplace.currency_field.is_some() {
+            problems.push(format!(
+                "nft.{}: exactly one of currency and currency_field must be set",
+                name
+            ));
+        }
+        if let Some(api_key_config) = &marketplace.api_key_config {
+            if !matches!(api_key_config.as_str(), "Bearer" | "x-api-key") {
+                problems.push(format!(
+                    "nft.{}.api_key_config must be \"Bearer\" or \"x-api-key\", got '{}'",
+                    name, api_key_config
+                ));
+            }
+        }
+        if marketplace.api_key_config.is_some() != marketplace.api_key_env.is_some() {
+            problems.push(format!(
+                "nft.{}.api_key_env and api_key_config must be set together",
+                name
+            ));
+        }
+    }
+    problems
+}
+
+/// Inner type T for IntentMessage<T>
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct NftFloorPriceResponse {
+    pub marketplace: String,
+    pub collection_id: String,
+    /// Currency the floor price is quoted in
+    pub currency: String,
+    /// Floor price scaled by 10^decimals
+    pub floor_price: u64,
+    pub decimals: u32,
+    pub timestamp_ms: u64,
+}
+
+/// Inner type T for ProcessDataRequest<T>
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NftFloorPriceRequest {
+    /// Name of a configured marketplace
+    pub marketplace: String,
+    pub collection_id: String,
+}
+
+/// Fetch the floor price of a collection from a configured marketplace API
+/// and sign it. The route is served by [`NftFloorModule`].
+#[utoipa::path(
+    post,
+    path = "/nft_floor_price",
+    tag = "nft",
+    request_body = ProcessDataRequest<NftFloorPriceRequest>,
+    params(
+        ("x-consumer-key" = Option<String>, Header, description = "API key of the consumer, see `[consumers]`"),
+        ("x-sui-signature" = Option<String>, Header, description = "Sui wallet signature of the timestamp and body, instead of an API key"),
+        ("x-sui-timestamp-ms" = Option<u64>, Header, description = "Time the wallet signature was made"),
+    ),
+    responses(
+        (status = 200, description = "Signed floor price", body = ProcessedDataResponse<IntentMessage<NftFloorPriceResponse>>),
+        (status = 400, description = "Unknown marketplace or failed fetch", body = crate::ErrorResponse),
+        (status = 401, description = "Missing or unknown consumer key or wallet", body = crate::ErrorResponse),
+        (status = 503, description = "Signing is paused", body = crate::ErrorResponse),
+    )
+)]
+pub async fn process_nft_floor_price(
+    state: State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ProcessedDataResponse<IntentMessage<NftFloorPriceResponse>>>, EnclaveError> {
+    oracle::handle::<NftFloorModule>(state, headers, body).await
+}
+
+/// Signed collection floor prices from configured marketplace APIs.
+pub struct NftFloorModule;
+
+impl OracleModule for NftFloorModule {
+    const NAME: &'static str = "nft_floor";
+    const PATH: &'static str = "/nft_floor_price";
+    const INTENT: IntentScope = IntentScope::NftFloorPrice;
+
+    type Request = NftFloorPriceRequest;
+    type Response = NftFloorPriceResponse;
+
+    #[instrument(name = "nft_floor_price", skip_all, fields(marketplace = %request.marketplace, collection_id = %request.collection_id))]
+    async fn process(
+        state: &AppState,
+        request: NftFloorPriceRequest,
+    ) -> Result<Signed<NftFloorPriceResponse>, EnclaveError> {
+        let config = state.config.load_full();
+        let marketplace = config.nft.get(&request.marketplace).ok_or_else(|| {
+            EnclaveError::GenericError(format!("Unknown NFT marketplace: {}", request.marketplace))
+        })?;
+
+        let api_key = match &marketplace.api_key_env {
+            Some(env) => Some(std::env::var(env).map_err(|_| {
+                EnclaveError::GenericError(format!("API key variable {} is not set", env))
+            })?),
+            None => None,
+        };
+        let url = marketplace
+            .url
+            .replace(COLLECTION_PLACEHOLDER, &encode_url_component(&request.collection_id));
+        // Collections of one marketplace share a response shape
+        let json = fetch_upstream(
+            state,
+            &config,
+            &format!("nft:{}", request.marketplace),
+            &url,
+            api_key.as_deref().zip(marketplace.api_key_config.as_deref()),
+            &UpstreamFormat::default(),
+        )
+        .await?;
+
+        let floor = extract_price(&json, &marketplace.floor_price_field).map_err(EnclaveError::GenericError)?;
+        let decimals = marketplace.decimals.unwrap_or(config.response.price_decimals);
+        let floor_price = scale_floor_price(floor, decimals)?;
+        let currency = match (&marketplace.currency, &marketplace.currency_field) {
+            (Some(currency), _) => currency.clone(),
+            (None, Some(field)) => extract_currency(&json, field)?,
+            (None, None) => {
+                return Err(EnclaveError::GenericError(format!(
+                    "NFT marketplace {} has no currency configured",
+                    request.marketplace
+                )))
+            }
+        };
+
+        let current_timestamp = current_timestamp_ms()?;
+        let kp = state.signing_keys.signer_for(Self::INTENT, current_timestamp, current_timestamp)?;
+        let response = NftFloorPriceResponse {
+            marketplace: request.marketplace,
+            collection_id: request.collection_id,
+            currency,
+            floor_price,
+            decimals,
+            timestamp_ms: current_timestamp,
+        };
+        Ok(to_signed_response(&kp, response, current_timestamp, Self::INTENT))
+    }
+}
+
+/// Scale a floor price by 10^decimals, refusing negative prices.
+fn scale_floor_price(floor: Decimal, decimals: u32) -> Result<u64, EnclaveError> {
+    let scaled = if floor.is_sign_negative() && !floor.is_zero() {
+        None
+    } else {
+        10_u64
+            .checked_pow(decimals)
+            .and_then(|factor| Decimal::from(factor).checked_mul(floor))
+            .and_then(|scaled| scaled.round().to_u64())
+    };
+    scaled.ok_or_else(|| {
+        EnclaveError::GenericError(format!(
+            "Floor price {} can't be scaled to a u64 with {} decimals",
+            floor, decimals
+        ))
+    })
+}
+
+/// Extract the currency symbol reported alongside the floor price.
+fn extract_currency(json: &Value, field: &str) -> Result<String, EnclaveError> {
+    match extract_field_from_json(json, field).map_err(EnclaveError::GenericError)? {
+        Value::String(currency) if !currency.is_empty() => Ok(currency.clone()),
+        _ => Err(EnclaveError::GenericError(format!(
+            "Currency field '{}' is not a non-empty string",
+            field
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_scale_floor_price() {
+        let floor = Decimal::from_str("12.3456789").unwrap();
+        assert_eq!(scale_floor_price(floor, 4).unwrap(), 123457);
+        assert!(scale_floor_price(Decimal::from(-1), 4).is_err());
+        assert!(scale_floor_price(floor, 30).is_err());
+    }
+
+    #[test]
+    fn test_extract_currency() {
+        let json = json!({"stats": {"currency": "ETH", "empty": "", "number": 1}});
+        assert_eq!(extract_currency(&json, "stats.currency").unwrap(), "ETH");
+        assert!(extract_currency(&json, "stats.empty").is_err());
+        assert!(extract_currency(&json, "stats.number").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let marketplace = NftMarketplace {
+            url: "https://api.example.com/collections/{collection_id}/stats".to_string(),
+            floor_price_field: "floor_price".to_string(),
+            currency: Some("ETH".to_string()),
+            currency_field: None,
+            decimals: Some(9),
+            api_key_env: None,
+            api_key_config: None,
+        };
+        let mut marketplaces = HashMap::from([("ok".to_string(), marketplace.clone())]);
+        assert!(validate(&marketplaces).is_empty());
+
+        marketplaces.insert(
+            "bad".to_string(),
+            NftMarketplace {
+                url: "https://api.example.com/collections".to_string(),
+                currency_field: Some("currency".to_string()),
+                ..marketplace
+            },
+        );
+        assert_eq!(validate(&marketplaces).len(), 2);
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! OAuth2 client credentials for upstream APIs. A feed opts in with
+//! `api_key_config = "oauth2"` on chain; its `api_key` is then the client
+//! id, exchanged together with the client secret, kept off chain in the
+//! environment variable named by the feed's `[feeds."0x...".oauth2]`
+//! section, for a bearer token. Tokens are cached until shortly before they
+//! expire, or until an upstream rejects them.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::common::current_timestamp_ms;
+use crate::config::check_http_url;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// `api_key_config` of feeds authenticating with OAuth2.
+pub const OAUTH2: &str = "oauth2";
+
+/// Tokens are refreshed this long before they expire, so none expires in
+/// flight.
+const EXPIRY_MARGIN_MS: u64 = 30_000;
+
+/// Lifetime of tokens whose response has no `expires_in`.
+const DEFAULT_EXPIRES_IN_SECS: u64 = 300;
+
+/// How a feed obtains its bearer token.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct OAuth2 {
+    /// Token endpoint of the vendor's authorization server
+    pub token_url: String,
+    /// Environment variable holding the client secret
+    pub client_secret_env: String,
+    /// Space separated scopes to request, if the vendor needs any
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+impl OAuth2 {
+    /// Check the config of feed `feed_id`, returning the problems found.
+    pub fn validate(&self, feed_id: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Err(e) = check_http_url(&self.token_url) {
+            problems.push(format!("feeds.{}.oauth2.token_url: {}", feed_id, e));
+        }
+        if self.client_secret_env.is_empty() {
+            problems.push(format!(
+                "feeds.{}.oauth2.client_secret_env must name a variable",
+                feed_id
+            ));
+        }
+        problems
+    }
+}
+
+/// Body of a successful token response, RFC 6749 section 5.1.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct CachedToken {
+    access_token: String,
+    expires_at_ms: u64,
+}
+
+/// Token URL, client id and scope of a token.
+type TokenKey = (String, String, Option<String>);
+
+/// Bearer tokens by token URL, client id and scope.
+#[derive(Default)]
+pub struct OAuthTokens {
+    tokens: Mutex<HashMap<TokenKey, CachedToken>>,
+}
+
+impl OAuthTokens {
+    fn get(&self, key: &TokenKey, now_ms: u64) -> Option<String> {
+        let tokens = self.tokens.lock().unwrap();
+        tokens
+            .get(key)
+            .filter(|token| token.expires_at_ms > now_ms + EXPIRY_MARGIN_MS)
+            .map(|token| token.access_token.clone())
+    }
+
+    /// Forget the token of `client_id`, e.g. after an upstream rejected it.
+    pub fn invalidate(&self, oauth2: &OAuth2, client_id: &str) {
+        self.tokens.lock().unwrap().remove(&(
+            oauth2.token_url.clone(),
+            client_id.to_string(),
+            oauth2.scope.clone(),
+        ));
+    }
+}
+
+/// The bearer token to send instead of `api_key` for feeds whose
+/// `api_key_config` is `oauth2`, from the cache or the token endpoint. None
+/// for other feeds.
+pub async fn bearer_token(
+    state: &AppState,
+    api_key: Option<(&str, &str)>,
+    oauth2: Option<&OAuth2>,
+) -> Result<Option<String>, EnclaveError> {
+    let (Some((client_id, OAUTH2)), Some(oauth2)) = (api_key, oauth2) else {
+        return Ok(None);
+    };
+    let key = (
+        oauth2.token_url.clone(),
+        client_id.to_string(),
+        oauth2.scope.clone(),
+    );
+    let now_ms = current_timestamp_ms()?;
+    if let Some(access_token) = state.oauth_tokens.get(&key, now_ms) {
+        return Ok(Some(access_token));
+    }
+
+    let client_secret = std::env::var(&oauth2.client_secret_env).map_err(|_| {
+        EnclaveError::GenericError(format!(
+            "OAuth2 client secret variable {} is not set",
+            oauth2.client_secret_env
+        ))
+    })?;
+    let mut form = vec![
+        ("grant_type", "client_credentials"),
+        ("client_id", client_id),
+        ("client_secret", client_secret.as_str()),
+    ];
+    if let Some(scope) = &oauth2.scope {
+        form.push(("scope", scope.as_str()));
+    }
+    let failed =
+        |e: String| EnclaveError::GenericError(format!("Failed to get OAuth2 token: {}", e));
+    let response = state
+        .outbound
+        .load()
+        .client_for(&oauth2.token_url)
+        .post(&oauth2.token_url)
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| failed(e.to_string()))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(failed(format!("token endpoint answered {}", status)));
+    }
+    let token: TokenResponse = response.json().await.map_err(|e| failed(e.to_string()))?;
+    let expires_in_secs = token.expires_in.unwrap_or(DEFAULT_EXPIRES_IN_SECS);
+    state.oauth_tokens.tokens.lock().unwrap().insert(
+        key,
+        CachedToken {
+            access_token: token.access_token.clone(),
+            expires_at_ms: now_ms.saturating_add(expires_in_secs.saturating_mul(1000)),
+        },
+    );
+    Ok(Some(token.access_token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cached_token_expiry() {
+        let tokens = OAuthTokens::default();
+        let oauth2 = OAuth2 {
+            token_url: "https://auth.example.com/token".to_string(),
+            client_secret_env: "SECRET".to_string(),
+            scope: None,
+        };
+        let key = (oauth2.token_url.clone(), "client".to_string(), None);
+        tokens.tokens.lock().unwrap().insert(
+            key.clone(),
+            CachedToken {
+                access_token: "token".to_string(),
+                expires_at_ms: 100_000,
+            },
+        );
+        assert_eq!(tokens.get(&key, 60_000).as_deref(), Some("token"));
+        // Within the margin the token is refreshed rather than used
+        assert_eq!(tokens.get(&key, 80_000), None);
+
+        tokens.invalidate(&oauth2, "client");
+        assert_eq!(tokens.get(&key, 0), None);
+        assert!(oauth2.validate("0x1").is_empty());
+    }
+}
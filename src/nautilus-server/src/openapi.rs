@@ -0,0 +1,71 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use utoipa::OpenApi;
+
+use crate::{
+    api_key_encryption, app, assets, batch, capacity, common, dry_run, feeds, fees, health,
+    history, nft, rates, reserves, retirement, sports, stats, verification, weather, wide,
+};
+
+/// OpenAPI description of the public endpoints, served at `/openapi.json`
+/// and browsable with Swagger UI at `/swagger-ui`. Admin endpoints are left
+/// out as they are only meant for the operator.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Nautilus server",
+        description = "Price feeds and other oracle data signed inside an AWS Nitro enclave."
+    ),
+    paths(
+        app::process_data,
+        batch::process_batch,
+        dry_run::dry_run,
+        fees::fee_quote,
+        weather::process_weather,
+        sports::process_sports_result,
+        reserves::process_reserves,
+        nft::process_nft_floor_price,
+        common::get_attestation,
+        common::attestation,
+        common::refresh_attestation,
+        api_key_encryption::api_key_encryption_key,
+        retirement::key_retirement,
+        verification::verify_batch,
+        common::health_check,
+        health::upstream_health,
+        history::feed_history,
+        feeds::list_feeds,
+        stats::process_stats,
+        wide::process_data_wide,
+        rates::process_rate,
+        capacity::capacity,
+        assets::list_assets,
+        assets::get_asset,
+    ),
+    tags(
+        (name = "price feed", description = "Signed prices and fee quotes"),
+        (name = "weather", description = "Signed weather readings"),
+        (name = "sports", description = "Signed fixture results"),
+        (name = "reserves", description = "Signed proof of reserve"),
+        (name = "nft", description = "Signed NFT collection floor prices"),
+        (name = "attestation", description = "Attestation documents for the signing key"),
+        (name = "health", description = "Liveness and capacity"),
+        (name = "assets", description = "Asset registry"),
+    )
+)]
+pub struct ApiDoc;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_documents_process_data() {
+        let spec = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let post = &spec["paths"]["/process_data"]["post"];
+        assert!(post["requestBody"].is_object());
+        assert!(post["responses"]["200"].is_object());
+        assert!(spec["components"]["schemas"]["PriceFeedResponse"].is_object());
+    }
+}
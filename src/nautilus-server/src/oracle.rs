@@ -0,0 +1,174 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Oracle modules: each kind of data the enclave attests to, price feeds
+//! being the first, is a module with its own route, request and response
+//! types and intent. Adding a module means implementing [`OracleModule`] and
+//! listing it in [`routes`]; request bookkeeping and metrics are shared.
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::routing::post;
+use axum::{Json, Router};
+use opentelemetry::KeyValue;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::app::PriceFeedModule;
+use crate::common::{current_timestamp_ms, IntentMessage, IntentScope, ProcessDataRequest, ProcessedDataResponse};
+use crate::consumers::{
+    ServedUpdate, CONSUMER_KEY_HEADER, WALLET_SIGNATURE_HEADER, WALLET_TIMESTAMP_HEADER,
+};
+use crate::nft::NftFloorModule;
+use crate::rates::RateModule;
+use crate::reserves::ReservesModule;
+use crate::sports::SportsModule;
+use crate::stats::StatsModule;
+use crate::telemetry;
+use crate::weather::WeatherModule;
+use crate::wide::WidePriceModule;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Signed envelope a module returns for its response type.
+pub type Signed<T> = ProcessedDataResponse<IntentMessage<T>>;
+
+/// A kind of data fetched, checked and signed by the enclave.
+pub trait OracleModule: 'static {
+    /// Name used in logs and metrics
+    const NAME: &'static str;
+    /// Route requests are posted to, as `ProcessDataRequest<Self::Request>`
+    const PATH: &'static str;
+    /// Intent responses are signed under. A module may sign variants of its
+    /// response under further intents of its own.
+    const INTENT: IntentScope;
+
+    type Request: DeserializeOwned + Send + 'static;
+    type Response: Serialize + Clone + Send + 'static;
+
+    /// Refuse options of `request` that `consumer` may not use. Requests are
+    /// allowed by default.
+    fn authorize(_request: &Self::Request, _consumer: Option<&str>) -> Result<(), EnclaveError> {
+        Ok(())
+    }
+
+    /// Fetch, check and sign the data for `request`.
+    fn process(
+        state: &AppState,
+        request: Self::Request,
+    ) -> impl Future<Output = Result<Signed<Self::Response>, EnclaveError>> + Send;
+}
+
+/// Routes of every module.
+pub fn routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .merge(route::<PriceFeedModule>())
+        .merge(route::<WeatherModule>())
+        .merge(route::<SportsModule>())
+        .merge(route::<ReservesModule>())
+        .merge(route::<NftFloorModule>())
+        .merge(route::<StatsModule>())
+        .merge(route::<WidePriceModule>())
+        .merge(route::<RateModule>())
+}
+
+/// Route of a single module.
+pub fn route<M: OracleModule>() -> Router<Arc<AppState>> {
+    Router::new().route(M::PATH, post(handle::<M>))
+}
+
+/// Handler shared by all modules: authenticates the consumer, counts the
+/// request towards capacity and [`run`]s the module.
+pub async fn handle<M: OracleModule>(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<Signed<M::Response>>, EnclaveError> {
+    let consumer = authenticate(&state, &headers, &body)?;
+    let request: ProcessDataRequest<M::Request> = parse_body(&body)?;
+    let _in_flight = state.capacity.start(current_timestamp_ms()?);
+    run::<M>(&state, consumer.as_deref(), request.payload)
+        .await
+        .map(Json)
+}
+
+/// Consumer the request comes from, if it carries an API key or a wallet
+/// signature over `body`. The body is taken raw so the signature can be
+/// checked over exactly the bytes sent.
+pub fn authenticate(state: &AppState, headers: &HeaderMap, body: &[u8]) -> Result<Option<String>, EnclaveError> {
+    let header = |name| headers.get(name).map(|value| value.to_str().unwrap_or_default());
+    let consumers = &state.config.load().consumers;
+    if let Some(signature) = header(WALLET_SIGNATURE_HEADER) {
+        let timestamp_ms = header(WALLET_TIMESTAMP_HEADER)
+            .and_then(|timestamp| timestamp.parse().ok())
+            .ok_or_else(|| {
+                EnclaveError::Unauthorized(format!("Missing or invalid {} header", WALLET_TIMESTAMP_HEADER))
+            })?;
+        return consumers
+            .authenticate_wallet(
+                signature,
+                timestamp_ms,
+                body,
+                current_timestamp_ms()?,
+                &state.seen_signatures,
+            )
+            .map(Some);
+    }
+    consumers.authenticate(header(CONSUMER_KEY_HEADER))
+}
+
+/// Parse a JSON request body, once the consumer is authenticated.
+pub fn parse_body<T: DeserializeOwned>(body: &[u8]) -> Result<T, EnclaveError> {
+    serde_json::from_slice(body).map_err(|e| EnclaveError::GenericError(format!("Invalid request body: {}", e)))
+}
+
+/// Process a single request of module `M`: refuses while signing is paused
+/// globally or if `consumer` may not make the request, records request
+/// metrics and logs what was served to `consumer`.
+pub async fn run<M: OracleModule>(
+    state: &AppState,
+    consumer: Option<&str>,
+    request: M::Request,
+) -> Result<Signed<M::Response>, EnclaveError> {
+    let started = Instant::now();
+    let result = match state
+        .pause
+        .check(&state.config.load(), None)
+        .and_then(|()| M::authorize(&request, consumer))
+    {
+        Ok(()) => M::process(state, request).await,
+        Err(e) => Err(e),
+    };
+    let metrics = telemetry::metrics();
+    let attributes = [
+        KeyValue::new("module", M::NAME),
+        KeyValue::new("outcome", if result.is_ok() { "ok" } else { "error" }),
+    ];
+    metrics
+        .process_data_duration_ms
+        .record(started.elapsed().as_secs_f64() * 1000.0, &attributes);
+    metrics.process_data_requests.add(1, &attributes);
+
+    let signed = result?;
+    if let Some(consumer) = consumer {
+        record_served::<M>(state, consumer, &signed);
+    }
+    Ok(signed)
+}
+
+/// Log `signed` as served to `consumer`.
+pub fn record_served<M: OracleModule>(state: &AppState, consumer: &str, signed: &Signed<M::Response>) {
+    let update = ServedUpdate {
+        module: M::NAME.to_string(),
+        intent: signed.response.intent,
+        timestamp_ms: signed.response.timestamp_ms,
+        data: serde_json::to_value(&signed.response.data).unwrap_or_default(),
+        signature: signed.signature.clone(),
+    };
+    let capacity = state.config.load().consumers.ledger_capacity;
+    state.served.record(consumer, update, capacity);
+}
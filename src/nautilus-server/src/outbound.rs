@@ -0,0 +1,332 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Outbound HTTP transport. Requests to destinations matching a configured
+//! route are sent through a proxy on the parent instance reached over vsock,
+//! everything else connects directly. A single pooled client is shared by
+//! every request, so connections and TLS sessions to an upstream are reused;
+//! hosts known to speak HTTP/2 get a second one that skips negotiating it.
+
+use anyhow::{Context, Result};
+use reqwest::{Client, Proxy, Url};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_vsock::{VsockAddr, VsockStream};
+use tracing::{info, warn};
+
+/// Context id of the parent instance as seen from inside the enclave.
+pub const PARENT_CID: u32 = 3;
+
+/// Routes requests for matching hosts through a host-side HTTP proxy
+/// (CONNECT for https) listening on a vsock port of the parent.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct OutboundRoute {
+    /// Host name, `*.example.com` for a domain and its subdomains, or `*`
+    pub host: String,
+    #[serde(default = "default_vsock_cid")]
+    pub vsock_cid: u32,
+    pub vsock_port: u32,
+}
+
+fn default_vsock_cid() -> u32 {
+    PARENT_CID
+}
+
+/// `[http_client]` config section: connection pooling and timeouts of the
+/// shared client. A duration of 0 disables the setting.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HttpClient {
+    /// Idle connections kept open per host
+    #[serde(default = "default_pool_max_idle_per_host")]
+    pub pool_max_idle_per_host: usize,
+    /// How long an idle pooled connection is kept; 0 keeps it indefinitely
+    #[serde(default = "default_pool_idle_timeout_secs")]
+    pub pool_idle_timeout_secs: u64,
+    /// Interval of TCP keep-alive probes
+    #[serde(default = "default_tcp_keepalive_secs")]
+    pub tcp_keepalive_secs: u64,
+    /// Interval of HTTP/2 PING frames keeping HTTP/2 connections alive
+    #[serde(default)]
+    pub http2_keep_alive_interval_secs: u64,
+    /// Hosts, as in [`OutboundRoute::host`], spoken to in HTTP/2 without
+    /// negotiating it, for upstreams known to support it
+    #[serde(default)]
+    pub http2_prior_knowledge_hosts: Vec<String>,
+    #[serde(default = "default_connect_timeout_secs")]
+    pub connect_timeout_secs: u64,
+    /// Limit on a whole request, from connecting to reading the body
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self {
+            pool_max_idle_per_host: default_pool_max_idle_per_host(),
+            pool_idle_timeout_secs: default_pool_idle_timeout_secs(),
+            tcp_keepalive_secs: default_tcp_keepalive_secs(),
+            http2_keep_alive_interval_secs: 0,
+            http2_prior_knowledge_hosts: Vec::new(),
+            connect_timeout_secs: default_connect_timeout_secs(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+fn default_pool_max_idle_per_host() -> usize {
+    32
+}
+
+fn default_pool_idle_timeout_secs() -> u64 {
+    90
+}
+
+fn default_tcp_keepalive_secs() -> u64 {
+    60
+}
+
+fn default_connect_timeout_secs() -> u64 {
+    10
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+/// `secs` as a duration, None for 0.
+fn nonzero_secs(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+impl HttpClient {
+    /// Apply the settings to a client being built.
+    fn configure(&self, mut builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        builder = builder
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(nonzero_secs(self.pool_idle_timeout_secs))
+            .tcp_keepalive(nonzero_secs(self.tcp_keepalive_secs))
+            .http2_keep_alive_interval(nonzero_secs(self.http2_keep_alive_interval_secs));
+        if let Some(connect_timeout) = nonzero_secs(self.connect_timeout_secs) {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(timeout) = nonzero_secs(self.timeout_secs) {
+            builder = builder.timeout(timeout);
+        }
+        builder
+    }
+}
+
+/// Whether `host` is matched by `pattern`, ignoring case.
+pub fn host_matches(pattern: &str, host: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    let host = host.to_ascii_lowercase();
+    let pattern = pattern.to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host == domain || host.ends_with(&format!(".{}", domain)),
+        None => host == pattern,
+    }
+}
+
+/// HTTP client shared by upstream API and Sui RPC calls, along with the
+/// local bridges its proxied routes go through. Bridges stop when dropped.
+pub struct Outbound {
+    client: Client,
+    /// Client speaking HTTP/2 with prior knowledge, and the hosts it serves
+    http2_client: Option<(Client, Vec<String>)>,
+    /// Host pattern and local bridge of each route
+    proxies: Vec<(String, Url)>,
+    bridges: Vec<JoinHandle<()>>,
+}
+
+impl Outbound {
+    /// Start a local TCP to vsock bridge per route and build a client that
+    /// proxies matching requests through them. The first matching route wins.
+    pub async fn new(routes: &[OutboundRoute], http_client: &HttpClient) -> Result<Self> {
+        let mut proxies = Vec::new();
+        let mut bridges = Vec::new();
+        for route in routes {
+            let (proxy_url, bridge) = spawn_bridge(route.vsock_cid, route.vsock_port).await?;
+            info!(
+                "Routing outbound requests for {} through vsock {}:{}",
+                route.host, route.vsock_cid, route.vsock_port
+            );
+            proxies.push((route.host.clone(), proxy_url));
+            bridges.push(bridge);
+        }
+
+        let build = |http2_prior_knowledge: bool| {
+            let mut builder = http_client.configure(Client::builder());
+            if http2_prior_knowledge {
+                builder = builder.http2_prior_knowledge();
+            }
+            if !proxies.is_empty() {
+                let proxies = proxies.clone();
+                builder = builder.proxy(Proxy::custom(move |url| {
+                    let host = url.host_str()?;
+                    proxies
+                        .iter()
+                        .find(|(pattern, _)| host_matches(pattern, host))
+                        .map(|(_, proxy_url)| proxy_url.clone())
+                }));
+            }
+            builder.build().context("Failed to build HTTP client")
+        };
+        let client = build(false)?;
+        let http2_client = if http_client.http2_prior_knowledge_hosts.is_empty() {
+            None
+        } else {
+            Some((
+                build(true)?,
+                http_client.http2_prior_knowledge_hosts.clone(),
+            ))
+        };
+
+        Ok(Self {
+            client,
+            http2_client,
+            proxies,
+            bridges,
+        })
+    }
+
+    /// Client for outbound requests to hosts not known to speak HTTP/2.
+    pub fn client(&self) -> Client {
+        self.client.clone()
+    }
+
+    /// Client for requests to `url`: the HTTP/2 one if its host is in
+    /// `http2_prior_knowledge_hosts`, the shared one otherwise.
+    pub fn client_for(&self, url: &str) -> Client {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+        match (&self.http2_client, host) {
+            (Some((client, hosts)), Some(host))
+                if hosts.iter().any(|pattern| host_matches(pattern, &host)) =>
+            {
+                client.clone()
+            }
+            _ => self.client.clone(),
+        }
+    }
+
+    /// Local HTTP proxy that connections to `host` go through, if a route
+    /// matches it. For connections not made with [`Self::client`].
+    pub fn proxy_for(&self, host: &str) -> Option<Url> {
+        self.proxies
+            .iter()
+            .find(|(pattern, _)| host_matches(pattern, host))
+            .map(|(_, proxy_url)| proxy_url.clone())
+    }
+}
+
+impl Drop for Outbound {
+    fn drop(&mut self) {
+        for bridge in &self.bridges {
+            bridge.abort();
+        }
+    }
+}
+
+/// Listen on an ephemeral loopback port and pipe every connection to the
+/// vsock address. Returns the proxy URL of the loopback side.
+async fn spawn_bridge(cid: u32, port: u32) -> Result<(Url, JoinHandle<()>)> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .await
+        .context("Failed to bind outbound bridge")?;
+    let proxy_url = Url::parse(&format!("http://{}", listener.local_addr()?))?;
+
+    let bridge = tokio::spawn(async move {
+        loop {
+            let mut inbound = match listener.accept().await {
+                Ok((stream, _)) => stream,
+                Err(e) => {
+                    warn!("Outbound bridge accept failed: {}", e);
+                    continue;
+                }
+            };
+            tokio::spawn(async move {
+                let result = async {
+                    let mut outbound = VsockStream::connect(VsockAddr::new(cid, port)).await?;
+                    tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await
+                }
+                .await;
+                if let Err(e) = result {
+                    warn!("Outbound bridge to vsock {}:{} failed: {}", cid, port, e);
+                }
+            });
+        }
+    });
+
+    Ok((proxy_url, bridge))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_matches() {
+        assert!(host_matches("*", "api.example.com"));
+        assert!(host_matches("api.example.com", "API.example.com"));
+        assert!(!host_matches("api.example.com", "example.com"));
+        assert!(host_matches("*.sui.io", "fullnode.testnet.sui.io"));
+        assert!(host_matches("*.sui.io", "sui.io"));
+        assert!(!host_matches("*.sui.io", "notsui.io"));
+    }
+
+    #[tokio::test]
+    async fn test_http_client_config() {
+        let http_client: HttpClient = toml::from_str(
+            r#"
+            timeout_secs = 0
+            http2_prior_knowledge_hosts = ["*.example.com"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(http_client.pool_max_idle_per_host, 32);
+        assert_eq!(nonzero_secs(http_client.timeout_secs), None);
+        assert_eq!(
+            nonzero_secs(http_client.connect_timeout_secs),
+            Some(Duration::from_secs(10))
+        );
+        assert!(Outbound::new(&[], &http_client).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_http2_prior_knowledge_per_host() {
+        let server = wiremock::MockServer::start().await;
+        wiremock::Mock::given(wiremock::matchers::any())
+            .respond_with(wiremock::ResponseTemplate::new(200))
+            .mount(&server)
+            .await;
+        let http_client = HttpClient {
+            http2_prior_knowledge_hosts: vec!["h2.example.com".to_string()],
+            ..Default::default()
+        };
+        let outbound = Outbound::new(&[], &http_client).await.unwrap();
+
+        // The HTTP/1 upstream is not spoken to in HTTP/2.
+        let response = outbound
+            .client_for(&server.uri())
+            .get(server.uri())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.version(), reqwest::Version::HTTP_11);
+        let response = outbound
+            .http2_client
+            .as_ref()
+            .unwrap()
+            .0
+            .get(server.uri())
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.version(), reqwest::Version::HTTP_2);
+    }
+}
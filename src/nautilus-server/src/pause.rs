@@ -0,0 +1,120 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Operator pause switch for incident response: while the enclave or a feed
+//! is paused nothing is signed for it. A pause can come from the config or
+//! from POST /admin/pause; a pause set in the config can only be lifted there.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use crate::config::Config;
+use crate::EnclaveError;
+
+/// `[pause]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Pause {
+    /// Refuse to sign anything. Feeds are paused individually with `paused`
+    /// in their `[feeds."0x..."]` section.
+    #[serde(default)]
+    pub global: bool,
+}
+
+/// Pauses set through the admin API, with the operator's reason.
+#[derive(Default)]
+pub struct PauseSwitch {
+    state: Mutex<PauseState>,
+}
+
+#[derive(Default)]
+struct PauseState {
+    global: Option<String>,
+    feeds: BTreeMap<String, String>,
+}
+
+impl PauseSwitch {
+    /// Pause or resume everything, or only `feed_id` if given.
+    pub fn set(&self, feed_id: Option<&str>, paused: bool, reason: &str) {
+        let mut state = self.state.lock().expect("pause lock poisoned");
+        match (feed_id, paused) {
+            (None, true) => state.global = Some(reason.to_string()),
+            (None, false) => state.global = None,
+            (Some(feed_id), true) => {
+                state.feeds.insert(feed_id.to_string(), reason.to_string());
+            }
+            (Some(feed_id), false) => {
+                state.feeds.remove(feed_id);
+            }
+        }
+    }
+
+    /// Whether everything is paused through the admin API, and the feeds
+    /// paused individually.
+    pub fn status(&self) -> (bool, Vec<String>) {
+        let state = self.state.lock().expect("pause lock poisoned");
+        (state.global.is_some(), state.feeds.keys().cloned().collect())
+    }
+
+    /// Refuse with [`EnclaveError::Paused`] if signing is paused globally or,
+    /// given `feed_id`, for that feed, by `config` or through the admin API.
+    pub fn check(&self, config: &Config, feed_id: Option<&str>) -> Result<(), EnclaveError> {
+        let state = self.state.lock().expect("pause lock poisoned");
+        let reason = if config.pause.global {
+            Some("paused in config")
+        } else if let Some(reason) = &state.global {
+            Some(reason.as_str())
+        } else if let Some(feed_id) = feed_id {
+            if config.feeds.get(feed_id).is_some_and(|feed| feed.paused) {
+                Some("feed paused in config")
+            } else {
+                state.feeds.get(feed_id).map(String::as_str)
+            }
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => Err(EnclaveError::Paused(format!("Signing is paused: {}", reason))),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FeedConfig;
+
+    #[test]
+    fn test_pause() {
+        let mut config = Config::default();
+        let switch = PauseSwitch::default();
+        assert!(switch.check(&config, Some("feed")).is_ok());
+
+        switch.set(Some("feed"), true, "bad upstream");
+        assert!(switch.check(&config, Some("other")).is_ok());
+        assert!(switch.check(&config, None).is_ok());
+        let err = switch.check(&config, Some("feed")).unwrap_err();
+        assert!(err.to_string().contains("bad upstream"));
+
+        switch.set(None, true, "incident");
+        assert!(switch.check(&config, Some("other")).is_err());
+        assert_eq!(switch.status(), (true, vec!["feed".to_string()]));
+
+        switch.set(None, false, "");
+        switch.set(Some("feed"), false, "");
+        assert!(switch.check(&config, Some("feed")).is_ok());
+
+        // Config pauses can't be lifted through the switch.
+        config.feeds.insert(
+            "feed".to_string(),
+            FeedConfig {
+                paused: true,
+                ..Default::default()
+            },
+        );
+        assert!(switch.check(&config, Some("feed")).is_err());
+        config.pause.global = true;
+        assert!(switch.check(&config, None).is_err());
+    }
+}
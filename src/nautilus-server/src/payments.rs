@@ -0,0 +1,118 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pay-per-query signing: with payments required, `process_data` only signs
+//! for a request that references an unused on-chain receipt paying at least
+//! the feed's fee.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use crate::types::Receipt;
+
+/// Payment settings.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Payments {
+    /// Refuse to sign prices without a receipt
+    #[serde(default)]
+    pub required: bool,
+    /// Full Move type of receipt objects, e.g. `0x...::payment::Receipt`.
+    /// Receipts must have `price_feed_id: ID` and `amount: u64` fields.
+    #[serde(default)]
+    pub receipt_type: String,
+    /// Only accept receipts paid at most this long ago. Receipts must then
+    /// also have a `timestamp_ms: u64` field, e.g. from the `Clock`, which
+    /// bounds how long an unused receipt can be held on to and keeps old
+    /// receipts from being replayed after a restart.
+    #[serde(default)]
+    pub max_receipt_age_secs: Option<u64>,
+}
+
+/// Check that `receipt` pays for a request to `price_feed_id` costing
+/// `fee_mist` and, with `max_age_ms`, was paid recently enough at `now_ms`.
+pub fn check_receipt(
+    receipt: &Receipt,
+    price_feed_id: &str,
+    fee_mist: u64,
+    max_age_ms: Option<u64>,
+    now_ms: u64,
+) -> Result<(), String> {
+    if let Some(max_age_ms) = max_age_ms {
+        let paid_ms = receipt
+            .timestamp_ms
+            .ok_or_else(|| format!("Receipt {} has no timestamp_ms", receipt.receipt_id))?;
+        if now_ms.saturating_sub(paid_ms) > max_age_ms {
+            return Err(format!(
+                "Receipt {} was paid at {}, more than {} ms ago",
+                receipt.receipt_id, paid_ms, max_age_ms
+            ));
+        }
+    }
+    if receipt.price_feed_id != price_feed_id {
+        return Err(format!(
+            "Receipt {} is for feed {}, not {}",
+            receipt.receipt_id, receipt.price_feed_id, price_feed_id
+        ));
+    }
+    if receipt.amount_mist < fee_mist {
+        return Err(format!(
+            "Receipt {} pays {} MIST, the fee is {} MIST",
+            receipt.receipt_id, receipt.amount_mist, fee_mist
+        ));
+    }
+    Ok(())
+}
+
+/// Receipts already paid out with a signature. Kept in memory, so a receipt
+/// is single use for the lifetime of the instance; contracts that need
+/// stronger guarantees should also consume it on chain.
+#[derive(Default)]
+pub struct ConsumedReceipts {
+    ids: Mutex<HashSet<String>>,
+}
+
+impl ConsumedReceipts {
+    /// Mark `receipt_id` consumed, returning false if it already was.
+    pub fn consume(&self, receipt_id: &str) -> bool {
+        self.ids
+            .lock()
+            .expect("receipts lock poisoned")
+            .insert(receipt_id.to_string())
+    }
+
+    pub fn is_consumed(&self, receipt_id: &str) -> bool {
+        self.ids.lock().expect("receipts lock poisoned").contains(receipt_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_and_consume() {
+        let receipt = Receipt {
+            receipt_id: "0x1".to_string(),
+            price_feed_id: "feed".to_string(),
+            amount_mist: 100,
+            timestamp_ms: Some(1_000),
+        };
+        assert!(check_receipt(&receipt, "feed", 100, None, 0).is_ok());
+        assert!(check_receipt(&receipt, "feed", 101, None, 0).is_err());
+        assert!(check_receipt(&receipt, "other", 0, None, 0).is_err());
+
+        assert!(check_receipt(&receipt, "feed", 100, Some(500), 1_500).is_ok());
+        assert!(check_receipt(&receipt, "feed", 100, Some(500), 1_501).is_err());
+        let undated = Receipt {
+            timestamp_ms: None,
+            ..receipt
+        };
+        assert!(check_receipt(&undated, "feed", 100, Some(500), 1_000).is_err());
+
+        let consumed = ConsumedReceipts::default();
+        assert!(consumed.consume("0x1"));
+        assert!(consumed.is_consumed("0x1"));
+        assert!(!consumed.consume("0x1"));
+    }
+}
@@ -0,0 +1,788 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Durable [`HistoryStore`] backends. The backend is picked by the
+//! `[persistence]` config section at startup; SQLite and sled are behind the
+//! `sqlite` and `sled` cargo features. The sealed backend keeps the history
+//! encrypted under the sealing key, for hosts that should not see it. Each
+//! backend also keeps the ledger of updates served to consumers, and the
+//! prices queued for pushing on chain so that a restart does not drop them.
+//!
+//! Durable stores are flushed every `flush_interval_secs` and on shutdown.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::app::PriceFeedResponse;
+use crate::consumers::ServedUpdate;
+use crate::history::{HistoryStore, MemoryStore, DEFAULT_HISTORY_CAPACITY};
+use crate::keys::sealing_key;
+use crate::oracle::Signed;
+use crate::AppState;
+
+/// How often the flusher checks whether it has been enabled while disabled.
+const DISABLED_RECHECK: Duration = Duration::from_secs(5);
+
+/// Where signed observations are stored.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    /// Lost on restart; for deployments without a writable disk
+    #[default]
+    Memory,
+    /// SQLite database file at `path`
+    Sqlite,
+    /// sled database directory at `path`
+    Sled,
+    /// File at `path` encrypted with AES-256-GCM under the sealing key
+    Sealed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Persistence {
+    #[serde(default)]
+    pub backend: Backend,
+    /// Database location for the durable backends
+    #[serde(default = "default_path")]
+    pub path: String,
+    /// Observations kept per feed
+    #[serde(default = "default_history_capacity")]
+    pub history_capacity: usize,
+    /// Environment variable holding the hex encoded 32 byte sealing key of
+    /// the sealed backend
+    #[serde(default = "default_sealing_key_env")]
+    pub sealing_key_env: String,
+    /// Seconds between writes of buffered observations to durable storage;
+    /// 0 writes them only on shutdown
+    #[serde(default = "default_flush_interval_secs")]
+    pub flush_interval_secs: u64,
+}
+
+impl Default for Persistence {
+    fn default() -> Self {
+        Self {
+            backend: Backend::default(),
+            path: default_path(),
+            history_capacity: default_history_capacity(),
+            sealing_key_env: default_sealing_key_env(),
+            flush_interval_secs: default_flush_interval_secs(),
+        }
+    }
+}
+
+fn default_path() -> String {
+    "/data/history".to_string()
+}
+
+fn default_history_capacity() -> usize {
+    DEFAULT_HISTORY_CAPACITY
+}
+
+fn default_sealing_key_env() -> String {
+    "NAUTILUS_SEALING_KEY".to_string()
+}
+
+fn default_flush_interval_secs() -> u64 {
+    30
+}
+
+/// Open the configured backend.
+pub fn open_store(config: &Persistence) -> Result<Box<dyn HistoryStore>> {
+    match config.backend {
+        Backend::Memory => Ok(Box::new(MemoryStore::new(config.history_capacity))),
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => Ok(Box::new(SqliteStore::open(&config.path, config.history_capacity)?)),
+        #[cfg(feature = "sled")]
+        Backend::Sled => Ok(Box::new(SledStore::open(&config.path, config.history_capacity)?)),
+        Backend::Sealed => {
+            let sealing_key = sealing_key(&config.sealing_key_env)
+                .context("the sealed persistence backend needs a sealing key")?;
+            Ok(Box::new(SealedStore::open(&config.path, config.history_capacity, sealing_key)?))
+        }
+        #[allow(unreachable_patterns)]
+        backend => Err(anyhow::anyhow!(
+            "persistence backend {:?} is not compiled in, enable its cargo feature",
+            backend
+        )),
+    }
+}
+
+/// Spawn the background flusher. The interval is re-read from the config
+/// before every round, so reloads take effect without a restart.
+pub fn spawn_flusher(state: &Arc<AppState>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        loop {
+            let interval_secs = state.config.load().persistence.flush_interval_secs;
+            if interval_secs == 0 {
+                tokio::time::sleep(DISABLED_RECHECK).await;
+                continue;
+            }
+            tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+            state.history.flush();
+        }
+    });
+}
+
+pub use self::sealed::SealedStore;
+
+mod sealed {
+    use super::*;
+    use crate::history::Observation;
+    use crate::keys::{seal, unseal};
+    use fastcrypto::aes::AesKey;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    /// Additional authenticated data bound into the sealed history file.
+    const SEALED_HISTORY_AAD: &[u8] = b"nautilus-sealed-history-v1";
+    /// Additional authenticated data bound into the sealed served ledger.
+    const SEALED_SERVED_AAD: &[u8] = b"nautilus-sealed-served-v1";
+    /// Additional authenticated data bound into the sealed push queue.
+    const SEALED_PUSH_QUEUE_AAD: &[u8] = b"nautilus-sealed-push-queue-v1";
+
+    /// Observations kept in memory and written to a single sealed file on
+    /// flush, as the BCS encoded export. Each flush rewrites the whole file
+    /// through a temporary one, so a crash leaves the previous version. The
+    /// served ledger goes to a second sealed file next to it, as JSON since
+    /// the signed payloads are, and the push queue to a third.
+    pub struct SealedStore {
+        path: PathBuf,
+        sealing_key: AesKey<typenum::U32>,
+        memory: MemoryStore,
+        dirty: AtomicBool,
+    }
+
+    impl SealedStore {
+        pub fn open(path: &str, capacity: usize, sealing_key: AesKey<typenum::U32>) -> Result<Self> {
+            let store = Self {
+                path: PathBuf::from(path),
+                sealing_key,
+                memory: MemoryStore::new(capacity),
+                dirty: AtomicBool::new(false),
+            };
+            if store.path.exists() {
+                let sealed = fs::read(&store.path)
+                    .with_context(|| format!("Failed to read sealed history at {}", path))?;
+                let bytes = unseal(&store.sealing_key, SEALED_HISTORY_AAD, &sealed)
+                    .with_context(|| format!("Failed to unseal history at {}", path))?;
+                let feeds: HashMap<String, Vec<Observation>> = bcs::from_bytes(&bytes)?;
+                for (price_feed_id, observations) in feeds {
+                    for observation in observations {
+                        store.memory.record(&price_feed_id, observation)?;
+                    }
+                }
+            }
+            let served_path = store.served_path();
+            if served_path.exists() {
+                let sealed = fs::read(&served_path)
+                    .with_context(|| format!("Failed to read sealed ledger at {}", served_path.display()))?;
+                let bytes = unseal(&store.sealing_key, SEALED_SERVED_AAD, &sealed)
+                    .with_context(|| format!("Failed to unseal ledger at {}", served_path.display()))?;
+                let consumers: HashMap<String, Vec<ServedUpdate>> = serde_json::from_slice(&bytes)?;
+                for (consumer, updates) in consumers {
+                    for update in updates {
+                        // Bounded when recorded, by the capacity of the time
+                        store.memory.record_served(&consumer, update, usize::MAX)?;
+                    }
+                }
+            }
+            let push_queue_path = store.push_queue_path();
+            if push_queue_path.exists() {
+                let sealed = fs::read(&push_queue_path).with_context(|| {
+                    format!("Failed to read sealed push queue at {}", push_queue_path.display())
+                })?;
+                let bytes = unseal(&store.sealing_key, SEALED_PUSH_QUEUE_AAD, &sealed)
+                    .with_context(|| format!("Failed to unseal push queue at {}", push_queue_path.display()))?;
+                let prices: Vec<Signed<PriceFeedResponse>> = serde_json::from_slice(&bytes)?;
+                store.memory.save_push_queue(&prices)?;
+            }
+            Ok(store)
+        }
+
+        /// Sealed served ledger, next to the history file.
+        fn served_path(&self) -> PathBuf {
+            self.path.with_extension("served")
+        }
+
+        /// Sealed push queue, next to the history file.
+        fn push_queue_path(&self) -> PathBuf {
+            self.path.with_extension("push")
+        }
+
+        fn write(&self) -> Result<()> {
+            let bytes = bcs::to_bytes(&self.memory.export()?)?;
+            write_sealed(&self.path, &seal(&self.sealing_key, SEALED_HISTORY_AAD, &bytes))?;
+            let bytes = serde_json::to_vec(&self.memory.export_served())?;
+            write_sealed(&self.served_path(), &seal(&self.sealing_key, SEALED_SERVED_AAD, &bytes))?;
+            let bytes = serde_json::to_vec(&self.memory.push_queue()?)?;
+            write_sealed(
+                &self.push_queue_path(),
+                &seal(&self.sealing_key, SEALED_PUSH_QUEUE_AAD, &bytes),
+            )
+        }
+    }
+
+    /// Replace `path` with `sealed` through a temporary file.
+    fn write_sealed(path: &Path, sealed: &[u8]) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, sealed)
+            .with_context(|| format!("Failed to write {}", temporary.display()))?;
+        fs::rename(&temporary, path).with_context(|| format!("Failed to replace {}", path.display()))
+    }
+
+    impl HistoryStore for SealedStore {
+        fn record(&self, price_feed_id: &str, observation: Observation) -> Result<()> {
+            self.memory.record(price_feed_id, observation)?;
+            self.dirty.store(true, Ordering::Release);
+            Ok(())
+        }
+
+        fn latest(&self, price_feed_id: &str) -> Result<Option<Observation>> {
+            self.memory.latest(price_feed_id)
+        }
+
+        fn range(&self, price_feed_id: &str, from_ms: u64, to_ms: u64) -> Result<Vec<Observation>> {
+            self.memory.range(price_feed_id, from_ms, to_ms)
+        }
+
+        fn export(&self) -> Result<HashMap<String, Vec<Observation>>> {
+            self.memory.export()
+        }
+
+        fn record_served(&self, consumer: &str, update: ServedUpdate, capacity: usize) -> Result<()> {
+            self.memory.record_served(consumer, update, capacity)?;
+            self.dirty.store(true, Ordering::Release);
+            Ok(())
+        }
+
+        fn served(&self, consumer: &str, from_ms: u64, to_ms: u64) -> Result<Vec<ServedUpdate>> {
+            self.memory.served(consumer, from_ms, to_ms)
+        }
+
+        fn save_push_queue(&self, prices: &[Signed<PriceFeedResponse>]) -> Result<()> {
+            self.memory.save_push_queue(prices)?;
+            self.dirty.store(true, Ordering::Release);
+            Ok(())
+        }
+
+        fn push_queue(&self) -> Result<Vec<Signed<PriceFeedResponse>>> {
+            self.memory.push_queue()
+        }
+
+        fn flush(&self) -> Result<()> {
+            if !self.dirty.swap(false, Ordering::AcqRel) {
+                return Ok(());
+            }
+            let written = self.write();
+            if written.is_err() {
+                self.dirty.store(true, Ordering::Release);
+            }
+            written
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::SqliteStore;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use super::*;
+    use crate::history::Observation;
+    use anyhow::Context;
+    use rusqlite::{params, Connection, OptionalExtension};
+    use std::sync::Mutex;
+
+    /// Observations in a single SQLite table. Prices and timestamps are
+    /// stored as the bit patterns of i64 since SQLite has no unsigned type.
+    /// Served updates are a second table, as JSON, and the push queue a
+    /// third.
+    pub struct SqliteStore {
+        capacity: usize,
+        pub(super) conn: Mutex<Connection>,
+    }
+
+    impl SqliteStore {
+        pub fn open(path: &str, capacity: usize) -> Result<Self> {
+            let conn = Connection::open(path)
+                .with_context(|| format!("Failed to open SQLite database at {}", path))?;
+            conn.execute_batch(
+                "CREATE TABLE IF NOT EXISTS observations (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    feed_id TEXT NOT NULL,
+                    price INTEGER NOT NULL,
+                    timestamp_ms INTEGER NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS observations_feed ON observations (feed_id, id);
+                CREATE TABLE IF NOT EXISTS served (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    consumer TEXT NOT NULL,
+                    timestamp_ms INTEGER NOT NULL,
+                    update_json TEXT NOT NULL
+                );
+                CREATE INDEX IF NOT EXISTS served_consumer ON served (consumer, id);
+                CREATE TABLE IF NOT EXISTS push_queue (
+                    feed_id TEXT PRIMARY KEY,
+                    price_json TEXT NOT NULL
+                );",
+            )?;
+            Ok(Self {
+                capacity,
+                conn: Mutex::new(conn),
+            })
+        }
+    }
+
+    impl HistoryStore for SqliteStore {
+        fn record(&self, price_feed_id: &str, observation: Observation) -> Result<()> {
+            let conn = self.conn.lock().expect("sqlite lock poisoned");
+            conn.execute(
+                "INSERT INTO observations (feed_id, price, timestamp_ms) VALUES (?1, ?2, ?3)",
+                params![
+                    price_feed_id,
+                    observation.price as i64,
+                    observation.timestamp_ms as i64
+                ],
+            )?;
+            conn.execute(
+                "DELETE FROM observations WHERE feed_id = ?1 AND id NOT IN (
+                    SELECT id FROM observations WHERE feed_id = ?1 ORDER BY id DESC LIMIT ?2
+                )",
+                params![price_feed_id, self.capacity as i64],
+            )?;
+            Ok(())
+        }
+
+        fn latest(&self, price_feed_id: &str) -> Result<Option<Observation>> {
+            let conn = self.conn.lock().expect("sqlite lock poisoned");
+            let observation = conn
+                .query_row(
+                    "SELECT price, timestamp_ms FROM observations
+                     WHERE feed_id = ?1 ORDER BY id DESC LIMIT 1",
+                    params![price_feed_id],
+                    |row| {
+                        Ok(Observation {
+                            price: row.get::<_, i64>(0)? as u64,
+                            timestamp_ms: row.get::<_, i64>(1)? as u64,
+                        })
+                    },
+                )
+                .optional()?;
+            Ok(observation)
+        }
+
+        fn range(&self, price_feed_id: &str, from_ms: u64, to_ms: u64) -> Result<Vec<Observation>> {
+            // Real timestamps fit in i64, so clamping the bounds keeps the
+            // comparison correct on the stored bit patterns
+            let clamp = |ms: u64| ms.min(i64::MAX as u64) as i64;
+            let conn = self.conn.lock().expect("sqlite lock poisoned");
+            let mut statement = conn.prepare(
+                "SELECT price, timestamp_ms FROM observations
+                 WHERE feed_id = ?1 AND timestamp_ms BETWEEN ?2 AND ?3 ORDER BY id",
+            )?;
+            let rows = statement.query_map(params![price_feed_id, clamp(from_ms), clamp(to_ms)], |row| {
+                Ok(Observation {
+                    price: row.get::<_, i64>(0)? as u64,
+                    timestamp_ms: row.get::<_, i64>(1)? as u64,
+                })
+            })?;
+            Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+        }
+
+        fn export(&self) -> Result<HashMap<String, Vec<Observation>>> {
+            let conn = self.conn.lock().expect("sqlite lock poisoned");
+            let mut statement = conn.prepare(
+                "SELECT feed_id, price, timestamp_ms FROM observations ORDER BY id",
+            )?;
+            let rows = statement.query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    Observation {
+                        price: row.get::<_, i64>(1)? as u64,
+                        timestamp_ms: row.get::<_, i64>(2)? as u64,
+                    },
+                ))
+            })?;
+            let mut feeds: HashMap<String, Vec<Observation>> = HashMap::new();
+            for row in rows {
+                let (feed_id, observation) = row?;
+                feeds.entry(feed_id).or_default().push(observation);
+            }
+            Ok(feeds)
+        }
+
+        fn record_served(&self, consumer: &str, update: ServedUpdate, capacity: usize) -> Result<()> {
+            let conn = self.conn.lock().expect("sqlite lock poisoned");
+            conn.execute(
+                "INSERT INTO served (consumer, timestamp_ms, update_json) VALUES (?1, ?2, ?3)",
+                params![
+                    consumer,
+                    update.timestamp_ms as i64,
+                    serde_json::to_string(&update)?
+                ],
+            )?;
+            conn.execute(
+                "DELETE FROM served WHERE consumer = ?1 AND id NOT IN (
+                    SELECT id FROM served WHERE consumer = ?1 ORDER BY id DESC LIMIT ?2
+                )",
+                params![consumer, capacity.clamp(1, i64::MAX as usize) as i64],
+            )?;
+            Ok(())
+        }
+
+        fn served(&self, consumer: &str, from_ms: u64, to_ms: u64) -> Result<Vec<ServedUpdate>> {
+            let clamp = |ms: u64| ms.min(i64::MAX as u64) as i64;
+            let conn = self.conn.lock().expect("sqlite lock poisoned");
+            let mut statement = conn.prepare(
+                "SELECT update_json FROM served
+                 WHERE consumer = ?1 AND timestamp_ms BETWEEN ?2 AND ?3 ORDER BY id",
+            )?;
+            let rows = statement.query_map(params![consumer, clamp(from_ms), clamp(to_ms)], |row| {
+                row.get::<_, String>(0)
+            })?;
+            rows.map(|json| Ok(serde_json::from_str(&json?)?)).collect()
+        }
+
+        fn save_push_queue(&self, prices: &[Signed<PriceFeedResponse>]) -> Result<()> {
+            let mut conn = self.conn.lock().expect("sqlite lock poisoned");
+            let transaction = conn.transaction()?;
+            transaction.execute("DELETE FROM push_queue", [])?;
+            for signed in prices {
+                transaction.execute(
+                    "INSERT OR REPLACE INTO push_queue (feed_id, price_json) VALUES (?1, ?2)",
+                    params![signed.response.data.price_feed_id, serde_json::to_string(signed)?],
+                )?;
+            }
+            transaction.commit()?;
+            Ok(())
+        }
+
+        fn push_queue(&self) -> Result<Vec<Signed<PriceFeedResponse>>> {
+            let conn = self.conn.lock().expect("sqlite lock poisoned");
+            let mut statement = conn.prepare("SELECT price_json FROM push_queue ORDER BY feed_id")?;
+            let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
+            rows.map(|json| Ok(serde_json::from_str(&json?)?)).collect()
+        }
+    }
+}
+
+#[cfg(feature = "sled")]
+pub use self::sled_store::SledStore;
+
+#[cfg(feature = "sled")]
+mod sled_store {
+    use super::*;
+    use crate::history::Observation;
+    use anyhow::Context;
+
+    /// Prefix of the tree names holding each consumer's served updates.
+    const SERVED_TREE_PREFIX: &str = "served:";
+    /// Tree holding the push queue, keyed by feed id.
+    const PUSH_QUEUE_TREE: &str = "push_queue";
+
+    /// One sled tree per feed, keyed by a monotonically increasing id so the
+    /// last entry is the latest observation. Served updates are kept the same
+    /// way in one tree per consumer, as JSON, and queued prices in one more.
+    pub struct SledStore {
+        capacity: usize,
+        pub(super) db: sled::Db,
+    }
+
+    impl SledStore {
+        pub fn open(path: &str, capacity: usize) -> Result<Self> {
+            let db = sled::open(path)
+                .with_context(|| format!("Failed to open sled database at {}", path))?;
+            Ok(Self { capacity, db })
+        }
+    }
+
+    impl HistoryStore for SledStore {
+        fn record(&self, price_feed_id: &str, observation: Observation) -> Result<()> {
+            let tree = self.db.open_tree(price_feed_id)?;
+            let id = self.db.generate_id()?;
+            tree.insert(id.to_be_bytes(), bcs::to_bytes(&observation)?)?;
+            while tree.len() > self.capacity {
+                tree.pop_min()?;
+            }
+            Ok(())
+        }
+
+        fn latest(&self, price_feed_id: &str) -> Result<Option<Observation>> {
+            let tree = self.db.open_tree(price_feed_id)?;
+            match tree.last()? {
+                Some((_, value)) => Ok(Some(bcs::from_bytes(&value)?)),
+                None => Ok(None),
+            }
+        }
+
+        fn range(&self, price_feed_id: &str, from_ms: u64, to_ms: u64) -> Result<Vec<Observation>> {
+            let tree = self.db.open_tree(price_feed_id)?;
+            let mut observations = Vec::new();
+            for value in tree.iter().values() {
+                let observation: Observation = bcs::from_bytes(&value?)?;
+                if (from_ms..=to_ms).contains(&observation.timestamp_ms) {
+                    observations.push(observation);
+                }
+            }
+            Ok(observations)
+        }
+
+        fn export(&self) -> Result<HashMap<String, Vec<Observation>>> {
+            let mut feeds = HashMap::new();
+            for name in self.db.tree_names() {
+                // The default tree, the served ledger and the push queue hold
+                // no observations.
+                if &*name == b"__sled__default"
+                    || name.starts_with(SERVED_TREE_PREFIX.as_bytes())
+                    || &*name == PUSH_QUEUE_TREE.as_bytes()
+                {
+                    continue;
+                }
+                let tree = self.db.open_tree(&name)?;
+                let observations = tree
+                    .iter()
+                    .values()
+                    .map(|value| Ok(bcs::from_bytes(&value?)?))
+                    .collect::<Result<Vec<Observation>>>()?;
+                feeds.insert(String::from_utf8(name.to_vec())?, observations);
+            }
+            Ok(feeds)
+        }
+
+        fn record_served(&self, consumer: &str, update: ServedUpdate, capacity: usize) -> Result<()> {
+            let tree = self.db.open_tree(format!("{}{}", SERVED_TREE_PREFIX, consumer))?;
+            let id = self.db.generate_id()?;
+            tree.insert(id.to_be_bytes(), serde_json::to_vec(&update)?)?;
+            while tree.len() > capacity.max(1) {
+                tree.pop_min()?;
+            }
+            Ok(())
+        }
+
+        fn served(&self, consumer: &str, from_ms: u64, to_ms: u64) -> Result<Vec<ServedUpdate>> {
+            let tree = self.db.open_tree(format!("{}{}", SERVED_TREE_PREFIX, consumer))?;
+            let mut updates = Vec::new();
+            for value in tree.iter().values() {
+                let update: ServedUpdate = serde_json::from_slice(&value?)?;
+                if (from_ms..=to_ms).contains(&update.timestamp_ms) {
+                    updates.push(update);
+                }
+            }
+            Ok(updates)
+        }
+
+        fn save_push_queue(&self, prices: &[Signed<PriceFeedResponse>]) -> Result<()> {
+            let tree = self.db.open_tree(PUSH_QUEUE_TREE)?;
+            let mut batch = sled::Batch::default();
+            for key in tree.iter().keys() {
+                batch.remove(key?);
+            }
+            for signed in prices {
+                batch.insert(signed.response.data.price_feed_id.as_bytes(), serde_json::to_vec(signed)?);
+            }
+            tree.apply_batch(batch)?;
+            Ok(())
+        }
+
+        fn push_queue(&self) -> Result<Vec<Signed<PriceFeedResponse>>> {
+            let tree = self.db.open_tree(PUSH_QUEUE_TREE)?;
+            tree.iter()
+                .values()
+                .map(|value| Ok(serde_json::from_slice(&value?)?))
+                .collect()
+        }
+
+        fn flush(&self) -> Result<()> {
+            self.db.flush()?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::tests::signed;
+    use crate::common::IntentScope;
+    use crate::history::Observation;
+    use fastcrypto::aes::AesKey;
+    use fastcrypto::traits::Generate;
+
+    /// Exercise a store through the trait, returning it reopened; `reopen`
+    /// must return a store over the same data.
+    fn check_store<S: HistoryStore>(reopen: impl Fn() -> S) -> S {
+        let store = reopen();
+        assert_eq!(store.latest("feed").unwrap(), None);
+        for (price, timestamp_ms) in [(1, 10), (u64::MAX, 20), (3, 30)] {
+            store.record("feed", Observation { price, timestamp_ms }).unwrap();
+        }
+        store.record("other", Observation { price: 9, timestamp_ms: 90 }).unwrap();
+        drop(store);
+
+        let store = reopen();
+        assert_eq!(store.latest("feed").unwrap(), Some(Observation { price: 3, timestamp_ms: 30 }));
+        assert_eq!(store.latest("other").unwrap(), Some(Observation { price: 9, timestamp_ms: 90 }));
+
+        let exported = store.export().unwrap();
+        assert_eq!(exported.len(), 2);
+        assert_eq!(
+            exported["feed"].iter().map(|o| o.price).collect::<Vec<_>>(),
+            vec![u64::MAX, 3]
+        );
+        assert_eq!(
+            store.range("feed", 0, 25).unwrap(),
+            vec![Observation { price: u64::MAX, timestamp_ms: 20 }]
+        );
+        assert_eq!(store.range("feed", 0, u64::MAX).unwrap().len(), 2);
+
+        // The served ledger is kept too, bounded by the given capacity
+        for timestamp_ms in [10, 20, 30] {
+            store.record_served("acme", served_update(timestamp_ms), 2).unwrap();
+        }
+        drop(store);
+        let store = reopen();
+        assert_eq!(store.served("acme", 0, u64::MAX).unwrap(), vec![served_update(20), served_update(30)]);
+        assert_eq!(store.served("acme", 25, u64::MAX).unwrap(), vec![served_update(30)]);
+        assert!(store.served("other", 0, u64::MAX).unwrap().is_empty());
+        assert_eq!(store.export().unwrap().len(), 2);
+
+        // So is the push queue, replaced as a whole
+        assert!(store.push_queue().unwrap().is_empty());
+        let mut other = signed(40);
+        other.response.data.price_feed_id = "0x1".to_string();
+        store.save_push_queue(&[signed(30), other]).unwrap();
+        store.save_push_queue(&[signed(50)]).unwrap();
+        drop(store);
+        let store = reopen();
+        let queued = store.push_queue().unwrap();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].response.data.timestamp_ms, 50);
+        assert_eq!(store.export().unwrap().len(), 2);
+        store
+    }
+
+    fn served_update(timestamp_ms: u64) -> ServedUpdate {
+        ServedUpdate {
+            module: "price_feed".to_string(),
+            intent: IntentScope::PriceFeed,
+            timestamp_ms,
+            data: serde_json::json!({ "price": timestamp_ms }),
+            signature: "00".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_memory_backend_is_default() {
+        let store = open_store(&Persistence::default()).unwrap();
+        store.record("feed", Observation { price: 1, timestamp_ms: 1 }).unwrap();
+        assert_eq!(store.latest("feed").unwrap().unwrap().price, 1);
+    }
+
+    #[test]
+    fn test_sealed_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.sealed");
+        let path = path.to_str().unwrap();
+        let sealing_key = AesKey::generate(&mut rand::thread_rng());
+        check_store(|| {
+            let store = SealedStore::open(path, 2, sealing_key.clone()).unwrap();
+            // Observations are only written out on flush.
+            FlushOnDrop(store)
+        });
+
+        let sealed = std::fs::read(path).unwrap();
+        assert!(!sealed.windows(4).any(|w| w == b"feed"));
+        let sealed = std::fs::read(dir.path().join("history.served")).unwrap();
+        assert!(!sealed.windows(4).any(|w| w == b"acme"));
+        let sealed = std::fs::read(dir.path().join("history.push")).unwrap();
+        assert!(!sealed.windows(3).any(|w| w == b"0x2"));
+        let other_key = AesKey::generate(&mut rand::thread_rng());
+        assert!(SealedStore::open(path, 2, other_key).is_err());
+    }
+
+    /// Flushes the wrapped store when dropped, like the server on shutdown.
+    struct FlushOnDrop<S: HistoryStore>(S);
+
+    impl<S: HistoryStore> Drop for FlushOnDrop<S> {
+        fn drop(&mut self) {
+            self.0.flush().unwrap();
+        }
+    }
+
+    impl<S: HistoryStore> HistoryStore for FlushOnDrop<S> {
+        fn record(&self, price_feed_id: &str, observation: Observation) -> Result<()> {
+            self.0.record(price_feed_id, observation)
+        }
+
+        fn latest(&self, price_feed_id: &str) -> Result<Option<Observation>> {
+            self.0.latest(price_feed_id)
+        }
+
+        fn range(&self, price_feed_id: &str, from_ms: u64, to_ms: u64) -> Result<Vec<Observation>> {
+            self.0.range(price_feed_id, from_ms, to_ms)
+        }
+
+        fn export(&self) -> Result<HashMap<String, Vec<Observation>>> {
+            self.0.export()
+        }
+
+        fn record_served(&self, consumer: &str, update: ServedUpdate, capacity: usize) -> Result<()> {
+            self.0.record_served(consumer, update, capacity)
+        }
+
+        fn served(&self, consumer: &str, from_ms: u64, to_ms: u64) -> Result<Vec<ServedUpdate>> {
+            self.0.served(consumer, from_ms, to_ms)
+        }
+
+        fn save_push_queue(&self, prices: &[Signed<PriceFeedResponse>]) -> Result<()> {
+            self.0.save_push_queue(prices)
+        }
+
+        fn push_queue(&self) -> Result<Vec<Signed<PriceFeedResponse>>> {
+            self.0.push_queue()
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sqlite_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.db");
+        let path = path.to_str().unwrap();
+        let store = check_store(|| SqliteStore::open(path, 2).unwrap());
+        let conn = store.conn.lock().unwrap();
+        let count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM observations WHERE feed_id = 'feed'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[cfg(feature = "sled")]
+    #[test]
+    fn test_sled_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().to_str().unwrap();
+        // sled's background threads release the lock of a dropped database
+        // a moment later, so reopening it is retried briefly.
+        let reopen = || {
+            for _ in 0..50 {
+                if let Ok(store) = SledStore::open(path, 2) {
+                    return store;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            SledStore::open(path, 2).unwrap()
+        };
+        let store = check_store(reopen);
+        assert_eq!(store.db.open_tree("feed").unwrap().len(), 2);
+    }
+}
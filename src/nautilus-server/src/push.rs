@@ -0,0 +1,1267 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Push mode: the enclave submits the prices it signs for `push.feeds` to
+//! the oracle_builder contract itself, as shared PriceUpdate objects,
+//! instead of leaving that to relayers. Transactions are sent from the Sui
+//! address of the active signing key and call `push_price_update` once per
+//! price, so only prices signed under the plain `PriceFeed` intent are
+//! pushed.
+//!
+//! Every transaction is dry-run first and budgeted its cost plus
+//! `gas_budget_margin_bps`, at the reference gas price refreshed every
+//! `gas_price_refresh_secs`. It is paid with the sender's largest coins,
+//! topped up with its smallest ones, which Sui merges into the first so
+//! that the gas does not end up spread over dust. With `[push.sponsor]`
+//! the gas is paid by a gas station instead, see [`crate::sponsor`].
+//!
+//! Signed prices wait in a queue holding only the latest price of each
+//! feed, and one submission at a time drains it, so that transactions never
+//! race for the same gas coins. Submissions rejected for a locked or
+//! equivocated object are retried with fresh coins after a backoff; prices
+//! whose submission failed stay queued for the next round unless a newer
+//! price of their feed replaces them. On shutdown the queue is flushed one
+//! last time, for at most `shutdown_timeout_secs`. `GET /admin/push` lists
+//! the queue and the outcome of the last submission.
+//!
+//! When several instances run push mode with the same key, a shared
+//! `PushLease` object elects the one that pushes: every push transaction
+//! first claims the lease for the instance's `instance_id`, which aborts
+//! while another instance holds it, and instances that see the lease held
+//! by another do not sign prices for pushing at all. The holder renews it
+//! with every push, so it passes on once the holder stops pushing for
+//! `lease_duration_ms`.
+
+use anyhow::{Context, Result};
+use axum::extract::State;
+use axum::Json;
+use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{KeyPair, Signer, ToFromBytes};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use sui_sdk_types::{
+    Address, Argument, Command, Ed25519PublicKey, Ed25519Signature, GasPayment, Identifier, Input, MoveCall,
+    ObjectId, ProgrammableTransaction, SimpleSignature, Transaction, TransactionExpiration, TransactionKind,
+    TypeTag, UserSignature,
+};
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+use crate::app::{self, PriceFeedRequest, PriceFeedResponse};
+use crate::common::{current_timestamp_ms, IntentScope};
+use crate::config::is_valid_object_id;
+use crate::history::HistoryStore;
+use crate::oracle::Signed;
+use crate::sponsor::Sponsor;
+use crate::sui::{GasCoin, GasUsed, PushLease, SuiClientWrapper};
+use crate::telemetry;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// How often the pusher checks whether it has been enabled while disabled.
+const DISABLED_RECHECK: Duration = Duration::from_secs(5);
+
+/// Fewest gas units a transaction is budgeted, below which Sui refuses it
+const MIN_GAS_UNITS: u64 = 1_000;
+
+/// Most coins Sui accepts as the gas payment of a transaction
+const MAX_GAS_PAYMENT_OBJECTS: usize = 256;
+
+/// `[push]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Push {
+    /// Price feed ids whose prices are pushed on chain
+    #[serde(default)]
+    pub feeds: Vec<String>,
+    /// Milliseconds between pushes; 0 disables push mode
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+    /// Shared `Enclave` object registered for the signing key
+    #[serde(default)]
+    pub enclave_object_id: String,
+    /// Type argument of the `Enclave` object, by default
+    /// `<oracle_builder_package_id>::oracle_builder::ORACLE_BUILDER`
+    #[serde(default)]
+    pub enclave_type: Option<String>,
+    /// Share of the dry-run cost added to a transaction's budget, in basis
+    /// points
+    #[serde(default = "default_gas_budget_margin_bps")]
+    pub gas_budget_margin_bps: u64,
+    /// Largest budget a transaction is given, in MIST; pushes that would
+    /// cost more are not sent
+    #[serde(default = "default_max_gas_budget")]
+    pub max_gas_budget: u64,
+    /// Seconds the reference gas price is cached for
+    #[serde(default = "default_gas_price_refresh_secs")]
+    pub gas_price_refresh_secs: u64,
+    /// Most coins paying for a transaction, merged into the first
+    #[serde(default = "default_max_gas_coins")]
+    pub max_gas_coins: usize,
+    /// Gas station paying for pushes instead of the signing key
+    #[serde(default)]
+    pub sponsor: Option<Sponsor>,
+    /// Attempts at submitting a queue rejected for locked or equivocated
+    /// objects
+    #[serde(default = "default_max_submit_attempts")]
+    pub max_submit_attempts: u32,
+    /// Milliseconds before the first retry, doubled for each further one
+    #[serde(default = "default_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+    /// Seconds the queue gets to be flushed on shutdown; 0 leaves it queued
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// Shared `PushLease` object electing the instance that pushes
+    #[serde(default)]
+    pub lease_object_id: Option<String>,
+    /// Milliseconds each push extends the instance's claim on the lease
+    #[serde(default = "default_lease_duration_ms")]
+    pub lease_duration_ms: u64,
+    /// Id the instance claims the lease under, random by default
+    #[serde(default)]
+    pub instance_id: Option<String>,
+}
+
+impl Default for Push {
+    fn default() -> Self {
+        Self {
+            feeds: Vec::new(),
+            interval_ms: default_interval_ms(),
+            enclave_object_id: String::new(),
+            enclave_type: None,
+            gas_budget_margin_bps: default_gas_budget_margin_bps(),
+            max_gas_budget: default_max_gas_budget(),
+            gas_price_refresh_secs: default_gas_price_refresh_secs(),
+            max_gas_coins: default_max_gas_coins(),
+            sponsor: None,
+            max_submit_attempts: default_max_submit_attempts(),
+            retry_backoff_ms: default_retry_backoff_ms(),
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            lease_object_id: None,
+            lease_duration_ms: default_lease_duration_ms(),
+            instance_id: None,
+        }
+    }
+}
+
+fn default_interval_ms() -> u64 {
+    10_000
+}
+
+fn default_gas_budget_margin_bps() -> u64 {
+    2_000
+}
+
+fn default_max_gas_budget() -> u64 {
+    500_000_000
+}
+
+fn default_gas_price_refresh_secs() -> u64 {
+    60
+}
+
+fn default_max_gas_coins() -> usize {
+    16
+}
+
+fn default_max_submit_attempts() -> u32 {
+    3
+}
+
+fn default_retry_backoff_ms() -> u64 {
+    500
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+fn default_lease_duration_ms() -> u64 {
+    30_000
+}
+
+impl Push {
+    pub fn is_enabled(&self) -> bool {
+        !self.feeds.is_empty() && self.interval_ms > 0
+    }
+
+    /// Check the config, returning the problems found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.is_enabled() {
+            return problems;
+        }
+        if !is_valid_object_id(&self.enclave_object_id) {
+            problems.push(format!(
+                "push.enclave_object_id '{}' must be 0x followed by 1 to 64 hex digits",
+                self.enclave_object_id
+            ));
+        }
+        if let Some(enclave_type) = &self.enclave_type {
+            if TypeTag::from_str(enclave_type).is_err() {
+                problems.push(format!("push.enclave_type '{}' is not a Move type", enclave_type));
+            }
+        }
+        if self.max_gas_budget == 0 {
+            problems.push("push.max_gas_budget must be greater than 0".to_string());
+        }
+        if !(1..=MAX_GAS_PAYMENT_OBJECTS).contains(&self.max_gas_coins) {
+            problems.push(format!(
+                "push.max_gas_coins must be between 1 and {}",
+                MAX_GAS_PAYMENT_OBJECTS
+            ));
+        }
+        if let Some(lease_object_id) = &self.lease_object_id {
+            if !is_valid_object_id(lease_object_id) {
+                problems.push(format!(
+                    "push.lease_object_id '{}' must be 0x followed by 1 to 64 hex digits",
+                    lease_object_id
+                ));
+            }
+            // The holder renews the lease with every push
+            if self.lease_duration_ms <= self.interval_ms {
+                problems.push("push.lease_duration_ms must be longer than push.interval_ms".to_string());
+            }
+        }
+        if self.instance_id.as_deref() == Some("") {
+            problems.push("push.instance_id must not be empty".to_string());
+        }
+        if self.max_submit_attempts == 0 {
+            problems.push("push.max_submit_attempts must be greater than 0".to_string());
+        }
+        if let Some(sponsor) = &self.sponsor {
+            problems.extend(sponsor.validate());
+        }
+        problems
+    }
+
+    /// Type argument of the `Enclave` object
+    fn enclave_type(&self, package_id: &str) -> Result<TypeTag> {
+        let enclave_type = match &self.enclave_type {
+            Some(enclave_type) => enclave_type.clone(),
+            None => format!("{}::oracle_builder::ORACLE_BUILDER", package_id),
+        };
+        TypeTag::from_str(&enclave_type).with_context(|| format!("Invalid enclave type {}", enclave_type))
+    }
+}
+
+/// Budget of a transaction whose dry run used `gas_used`, with `margin_bps`
+/// on top, and at least [`MIN_GAS_UNITS`] at `gas_price`. The storage rebate
+/// is only paid out once the transaction ran, so it does not lower it.
+pub fn gas_budget(gas_used: GasUsed, gas_price: u64, margin_bps: u64) -> u64 {
+    let cost = gas_used.computation_cost as u128 + gas_used.storage_cost as u128;
+    let budget = cost * (10_000 + margin_bps as u128) / 10_000;
+    let budget = budget.max(MIN_GAS_UNITS as u128 * gas_price as u128);
+    budget.min(u64::MAX as u128) as u64
+}
+
+/// Coins paying a budget of `budget`: the largest coins until they cover it,
+/// then the smallest of the others up to `max_coins` in all, so that dust is
+/// merged into the gas coin. `None` if the `max_coins` largest coins do not
+/// cover it.
+pub fn select_gas_coins(mut coins: Vec<GasCoin>, budget: u64, max_coins: usize) -> Option<Vec<GasCoin>> {
+    coins.sort_by(|a, b| b.balance.cmp(&a.balance));
+    let mut covered = 0u128;
+    let needed = coins
+        .iter()
+        .take(max_coins)
+        .position(|coin| {
+            covered += coin.balance as u128;
+            covered >= budget as u128
+        })?
+        + 1;
+    let mut selected: Vec<GasCoin> = coins.drain(..needed).collect();
+    let dust = max_coins.saturating_sub(needed).min(coins.len());
+    selected.extend(coins.drain(coins.len() - dust..));
+    Some(selected)
+}
+
+/// Sui address of the Ed25519 key `kp`.
+pub fn sender_address(kp: &Ed25519KeyPair) -> Address {
+    let public_key: [u8; 32] = kp.public().as_bytes().try_into().expect("Ed25519 keys are 32 bytes");
+    Ed25519PublicKey::new(public_key).derive_address()
+}
+
+/// Signature of `transaction` by its sender `kp`.
+pub fn sign_transaction(kp: &Ed25519KeyPair, transaction: &Transaction) -> UserSignature {
+    let signature = kp.sign(&transaction.signing_digest());
+    let signature: [u8; 64] = signature.as_bytes().try_into().expect("Ed25519 signatures are 64 bytes");
+    let public_key: [u8; 32] = kp.public().as_bytes().try_into().expect("Ed25519 keys are 32 bytes");
+    UserSignature::Simple(SimpleSignature::Ed25519 {
+        signature: Ed25519Signature::new(signature),
+        public_key: Ed25519PublicKey::new(public_key),
+    })
+}
+
+/// Shared object as transactions name it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SharedObject {
+    pub object_id: ObjectId,
+    pub initial_shared_version: u64,
+}
+
+/// The shared `Clock` object.
+const CLOCK: SharedObject = SharedObject {
+    object_id: ObjectId::new({
+        let mut bytes = [0; 32];
+        bytes[31] = 6;
+        bytes
+    }),
+    initial_shared_version: 1,
+};
+
+/// Claim of a push transaction on the `PushLease` object `lease`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaseClaim {
+    pub lease: SharedObject,
+    pub instance_id: String,
+    pub duration_ms: u64,
+}
+
+/// Programmable transaction calling `push_price_update` of `package` for
+/// each of `prices`, against `enclave` of type `enclave_type`, after
+/// claiming the lease of `claim` if there is one.
+pub fn push_transaction(
+    package: ObjectId,
+    enclave_type: TypeTag,
+    enclave: SharedObject,
+    claim: Option<&LeaseClaim>,
+    prices: &[Signed<PriceFeedResponse>],
+) -> Result<ProgrammableTransaction> {
+    let mut inputs = vec![Input::Shared {
+        object_id: enclave.object_id,
+        initial_shared_version: enclave.initial_shared_version,
+        mutable: false,
+    }];
+    let mut commands = Vec::with_capacity(prices.len() + 1);
+    if let Some(claim) = claim {
+        inputs.extend([
+            Input::Shared {
+                object_id: claim.lease.object_id,
+                initial_shared_version: claim.lease.initial_shared_version,
+                mutable: true,
+            },
+            Input::Pure {
+                value: bcs::to_bytes(&claim.instance_id)?,
+            },
+            Input::Pure {
+                value: bcs::to_bytes(&claim.duration_ms)?,
+            },
+            Input::Shared {
+                object_id: CLOCK.object_id,
+                initial_shared_version: CLOCK.initial_shared_version,
+                mutable: false,
+            },
+        ]);
+        commands.push(Command::MoveCall(MoveCall {
+            package,
+            module: Identifier::new("oracle_builder")?,
+            function: Identifier::new("claim_push_lease")?,
+            type_arguments: vec![enclave_type.clone()],
+            arguments: [1, 0, 2, 3, 4].map(Argument::Input).to_vec(),
+        }));
+    }
+    for signed in prices {
+        let data = &signed.response.data;
+        if signed.response.intent != IntentScope::PriceFeed || signed.response.timestamp_ms != data.timestamp_ms {
+            return Err(anyhow::anyhow!(
+                "Price of {} is not signed under the PriceFeed intent",
+                data.price_feed_id
+            ));
+        }
+        let signature = Hex::decode(&signed.signature)
+            .map_err(|e| anyhow::anyhow!("Invalid signature of {}: {}", data.price_feed_id, e))?;
+        let first = u16::try_from(inputs.len()).context("Too many prices for one transaction")?;
+        inputs.extend([
+            bcs::to_bytes(&data.oracle_id)?,
+            bcs::to_bytes(&data.price_feed_id)?,
+            bcs::to_bytes(&data.price)?,
+            bcs::to_bytes(&data.timestamp_ms)?,
+            bcs::to_bytes(&signature)?,
+        ]
+        .into_iter()
+        .map(|value| Input::Pure { value }));
+        let mut arguments: Vec<Argument> = (first..first + 5).map(Argument::Input).collect();
+        arguments.push(Argument::Input(0));
+        commands.push(Command::MoveCall(MoveCall {
+            package,
+            module: Identifier::new("oracle_builder")?,
+            function: Identifier::new("push_price_update")?,
+            type_arguments: vec![enclave_type.clone()],
+            arguments,
+        }));
+    }
+    Ok(ProgrammableTransaction { inputs, commands })
+}
+
+/// Whether a submission failed because another transaction held or
+/// equivocated one of its objects, so that it may pass with fresh gas coins.
+pub fn is_retryable(error: &anyhow::Error) -> bool {
+    let message = format!("{:#}", error).to_lowercase();
+    [
+        "objectlock",
+        "locked",
+        "equivocat",
+        "not available for consumption",
+        "unavailable for consumption",
+    ]
+    .iter()
+    .any(|pattern| message.contains(pattern))
+}
+
+/// Queued prices, and those of the submission in flight.
+#[derive(Default)]
+struct Queued {
+    pending: BTreeMap<String, Signed<PriceFeedResponse>>,
+    in_flight: Vec<Signed<PriceFeedResponse>>,
+}
+
+impl Queued {
+    /// Queue `signed` unless a newer price of its feed is pending.
+    fn enqueue(&mut self, signed: Signed<PriceFeedResponse>) -> bool {
+        let data = &signed.response.data;
+        let newer_queued = self
+            .pending
+            .get(&data.price_feed_id)
+            .is_some_and(|queued| queued.response.data.timestamp_ms > data.timestamp_ms);
+        if !newer_queued {
+            self.pending.insert(data.price_feed_id.clone(), signed);
+        }
+        !newer_queued
+    }
+
+    /// Prices not yet on chain: the pending ones, and those in flight that
+    /// no pending price supersedes.
+    fn unsettled(&self) -> Vec<Signed<PriceFeedResponse>> {
+        let mut prices: Vec<_> = self.pending.values().cloned().collect();
+        prices.extend(
+            self.in_flight
+                .iter()
+                .filter(|signed| !self.pending.contains_key(&signed.response.data.price_feed_id))
+                .cloned(),
+        );
+        prices
+    }
+}
+
+/// Prices waiting to be pushed, at most one per feed, written through to
+/// the persistence store so that they survive restarts. Prices taken for a
+/// submission stay stored until it is confirmed.
+pub struct PushQueue {
+    queued: Mutex<Queued>,
+    store: Arc<dyn HistoryStore>,
+}
+
+impl PushQueue {
+    /// Queue over `store`, restoring the prices it holds.
+    pub fn with_store(store: Arc<dyn HistoryStore>) -> Self {
+        let mut queued = Queued::default();
+        match store.push_queue() {
+            Ok(prices) => {
+                if !prices.is_empty() {
+                    info!("Restored {} queued push prices", prices.len());
+                }
+                for signed in prices {
+                    queued.enqueue(signed);
+                }
+            }
+            Err(e) => warn!("Failed to restore the push queue: {:#}", e),
+        }
+        record_depth(queued.pending.len());
+        Self {
+            queued: Mutex::new(queued),
+            store,
+        }
+    }
+
+    /// Write the prices not yet on chain to the store and record the depth.
+    fn save(&self, queued: &Queued) {
+        let unsettled = queued.unsettled();
+        record_depth(unsettled.len());
+        if let Err(e) = self.store.save_push_queue(&unsettled) {
+            warn!("Failed to store the push queue: {:#}", e);
+        }
+    }
+
+    /// Queue `signed`, replacing an older price of its feed. Returns false,
+    /// dropping it, if a newer price of the feed is already queued.
+    pub fn enqueue(&self, signed: Signed<PriceFeedResponse>) -> bool {
+        let mut queued = self.queued.lock().expect("push queue lock poisoned");
+        let enqueued = queued.enqueue(signed);
+        if enqueued {
+            self.save(&queued);
+        }
+        enqueued
+    }
+
+    /// Take every queued price, by feed id, for a submission to be
+    /// [`confirm`](Self::confirm)ed or [`requeue`](Self::requeue)d. Prices
+    /// of a submission that was abandoned, e.g. on a shutdown deadline, are
+    /// taken again.
+    pub fn take(&self) -> Vec<Signed<PriceFeedResponse>> {
+        let mut queued = self.queued.lock().expect("push queue lock poisoned");
+        for signed in std::mem::take(&mut queued.in_flight) {
+            queued.enqueue(signed);
+        }
+        let prices: Vec<_> = std::mem::take(&mut queued.pending).into_values().collect();
+        queued.in_flight = prices.clone();
+        prices
+    }
+
+    /// Forget the prices taken, now on chain.
+    pub fn confirm(&self) {
+        let mut queued = self.queued.lock().expect("push queue lock poisoned");
+        queued.in_flight.clear();
+        self.save(&queued);
+    }
+
+    /// Queue the prices taken back after their submission failed, except
+    /// where a newer price of their feed was queued meanwhile.
+    pub fn requeue(&self) {
+        let mut queued = self.queued.lock().expect("push queue lock poisoned");
+        for signed in std::mem::take(&mut queued.in_flight) {
+            queued.enqueue(signed);
+        }
+        self.save(&queued);
+    }
+
+    /// Number of prices not yet on chain
+    pub fn depth(&self) -> usize {
+        self.queued.lock().expect("push queue lock poisoned").unsettled().len()
+    }
+
+    /// The prices not yet on chain, by feed id, with whether a submission
+    /// of them is in flight.
+    pub fn entries(&self) -> Vec<QueuedPrice> {
+        let queued = self.queued.lock().expect("push queue lock poisoned");
+        let mut entries: Vec<QueuedPrice> = queued
+            .unsettled()
+            .iter()
+            .map(|signed| {
+                let data = &signed.response.data;
+                QueuedPrice {
+                    price_feed_id: data.price_feed_id.clone(),
+                    price: data.price,
+                    timestamp_ms: data.timestamp_ms,
+                    in_flight: !queued.pending.contains_key(&data.price_feed_id),
+                }
+            })
+            .collect();
+        entries.sort_by(|a, b| a.price_feed_id.cmp(&b.price_feed_id));
+        entries
+    }
+}
+
+/// A price waiting to be pushed, as `GET /admin/push` lists it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedPrice {
+    pub price_feed_id: String,
+    pub price: u64,
+    pub timestamp_ms: u64,
+    /// Whether the submission carrying it has yet to complete
+    pub in_flight: bool,
+}
+
+/// How the last submission ended.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Submission {
+    pub at_ms: u64,
+    /// Digest of the transaction, if it was executed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<String>,
+    /// Why it failed, otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn record_depth(depth: usize) {
+    telemetry::metrics().push_queue_depth.record(depth as u64, &[]);
+}
+
+/// Values the pusher looks up on chain and keeps between pushes, and the
+/// prices it has yet to push.
+pub struct PushState {
+    /// Reference gas price and when it was fetched
+    gas_price: Mutex<Option<(u64, Instant)>>,
+    /// Initial shared versions of the `Enclave` objects pushed against
+    shared_versions: Mutex<HashMap<ObjectId, u64>>,
+    /// Id of this instance, unless `push.instance_id` sets one
+    instance_id: String,
+    /// Prices signed for pushing and not yet on chain
+    pub queue: PushQueue,
+    /// Held while a submission is in flight
+    submitting: tokio::sync::Mutex<()>,
+    /// Outcome of the last submission
+    last_submission: Mutex<Option<Submission>>,
+}
+
+impl PushState {
+    /// State with the push queue kept in `store`.
+    pub fn with_store(store: Arc<dyn HistoryStore>) -> Self {
+        Self {
+            gas_price: Mutex::new(None),
+            shared_versions: Mutex::new(HashMap::new()),
+            instance_id: Hex::encode(rand::random::<[u8; 8]>()),
+            queue: PushQueue::with_store(store),
+            submitting: tokio::sync::Mutex::new(()),
+            last_submission: Mutex::new(None),
+        }
+    }
+
+    fn record_submission(&self, outcome: &Result<String>) {
+        let submission = Submission {
+            at_ms: current_timestamp_ms().unwrap_or_default(),
+            digest: outcome.as_ref().ok().cloned(),
+            error: outcome.as_ref().err().map(|e| format!("{:#}", e)),
+        };
+        *self.last_submission.lock().expect("submission lock poisoned") = Some(submission);
+    }
+
+    /// Reference gas price, refetched once older than `refresh`
+    async fn gas_price(&self, sui: &SuiClientWrapper, refresh: Duration) -> Result<u64> {
+        let cached = *self.gas_price.lock().expect("gas price lock poisoned");
+        if let Some((gas_price, fetched_at)) = cached {
+            if fetched_at.elapsed() < refresh {
+                return Ok(gas_price);
+            }
+        }
+        let gas_price = sui.reference_gas_price().await?;
+        *self.gas_price.lock().expect("gas price lock poisoned") = Some((gas_price, Instant::now()));
+        Ok(gas_price)
+    }
+
+    /// The shared object `object_id`, whose initial shared version never
+    /// changes
+    async fn shared_object(&self, sui: &SuiClientWrapper, object_id: ObjectId) -> Result<SharedObject> {
+        let cached = self
+            .shared_versions
+            .lock()
+            .expect("shared versions lock poisoned")
+            .get(&object_id)
+            .copied();
+        let initial_shared_version = match cached {
+            Some(initial_shared_version) => initial_shared_version,
+            None => {
+                let initial_shared_version = sui.initial_shared_version(&object_id).await?;
+                self.shared_versions
+                    .lock()
+                    .expect("shared versions lock poisoned")
+                    .insert(object_id, initial_shared_version);
+                initial_shared_version
+            }
+        };
+        Ok(SharedObject {
+            object_id,
+            initial_shared_version,
+        })
+    }
+
+    /// Id this instance claims the push lease under.
+    pub fn instance_id(&self, push: &Push) -> String {
+        push.instance_id.clone().unwrap_or_else(|| self.instance_id.clone())
+    }
+}
+
+/// Whether this instance may push.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Leadership {
+    /// No lease is configured
+    Unelected,
+    /// The lease is free, expired or held by this instance, which claims it
+    Leader(LeaseClaim),
+    /// Another instance holds the lease
+    Follower { holder: String, expires_at_ms: u64 },
+}
+
+/// Whether an instance `instance_id` may push under `lease` at `now_ms`,
+/// claiming it for `duration_ms`.
+pub fn leadership(
+    lease_object_id: ObjectId,
+    lease: &PushLease,
+    instance_id: &str,
+    duration_ms: u64,
+    now_ms: u64,
+) -> Leadership {
+    if lease.holder != instance_id && now_ms < lease.expires_at_ms {
+        return Leadership::Follower {
+            holder: lease.holder.clone(),
+            expires_at_ms: lease.expires_at_ms,
+        };
+    }
+    Leadership::Leader(LeaseClaim {
+        lease: SharedObject {
+            object_id: lease_object_id,
+            initial_shared_version: lease.initial_shared_version,
+        },
+        instance_id: instance_id.to_string(),
+        duration_ms,
+    })
+}
+
+/// Read the configured lease to tell whether this instance may push.
+async fn check_lease(state: &AppState, push: &Push) -> Result<Leadership> {
+    let Some(lease_object_id) = &push.lease_object_id else {
+        return Ok(Leadership::Unelected);
+    };
+    let lease_object_id = ObjectId::from_str(lease_object_id).context("Invalid push.lease_object_id")?;
+    let lease = state
+        .sui_client
+        .load()
+        .fetch_push_lease(&lease_object_id)
+        .await
+        .context("Failed to fetch the PushLease object")?;
+    Ok(leadership(
+        lease_object_id,
+        &lease,
+        &state.push.instance_id(push),
+        push.lease_duration_ms,
+        current_timestamp_ms()?,
+    ))
+}
+
+/// Sign a fresh price of every push feed, with their PriceFeed objects
+/// fetched together, queue those signed under the `PriceFeed` intent and
+/// [`flush`] the queue, returning the digest of the transaction. Nothing is
+/// signed when payments are required, as each price must then be paid for,
+/// nor while another instance holds the push lease.
+pub async fn push_feeds(state: &Arc<AppState>) -> Result<Option<String>> {
+    let config = state.config.load_full();
+    if config.payments.required {
+        return Ok(None);
+    }
+    if let Leadership::Follower { holder, expires_at_ms } = check_lease(state, &config.push).await? {
+        debug!("Instance {} holds the push lease until {}, not pushing", holder, expires_at_ms);
+        return Ok(None);
+    }
+    let feeds: Vec<&str> = config.push.feeds.iter().map(String::as_str).collect();
+    let mut price_feeds = app::prefetch_price_feeds(state, &feeds).await;
+    let mut signing = JoinSet::new();
+    for price_feed_id in &config.push.feeds {
+        let state = Arc::clone(state);
+        let request = PriceFeedRequest {
+            price_feed_id: price_feed_id.clone(),
+            receipt_id: None,
+            decimals: None,
+            params: BTreeMap::new(),
+            price_feed: price_feeds.remove(price_feed_id),
+        };
+        signing.spawn(async move {
+            let price_feed_id = request.price_feed_id.clone();
+            (price_feed_id, app::sign_and_cache(&state, request).await)
+        });
+    }
+    while let Some(signed) = signing.join_next().await {
+        match signed? {
+            (_, Ok(signed)) if signed.response.intent == IntentScope::PriceFeed => {
+                state.push.queue.enqueue(signed);
+            }
+            (price_feed_id, Ok(signed)) => warn!(
+                "Price of {} is signed under {:?}, only PriceFeed prices are pushed",
+                price_feed_id, signed.response.intent
+            ),
+            (price_feed_id, Err(e)) => warn!("Signing {} for a push failed: {}", price_feed_id, e),
+        }
+    }
+    flush(state).await
+}
+
+/// Push every queued price in one transaction, returning its digest, or
+/// None if nothing was queued. Submissions rejected for a locked or
+/// equivocated object are retried, with the backoff doubling, up to
+/// `push.max_submit_attempts` times; after that, or after any other
+/// failure, the prices go back to the queue.
+pub async fn flush(state: &AppState) -> Result<Option<String>> {
+    let _submitting = state.push.submitting.lock().await;
+    let prices = state.push.queue.take();
+    if prices.is_empty() {
+        return Ok(None);
+    }
+    let push = state.config.load().push.clone();
+    let mut attempt = 1;
+    loop {
+        let outcome = submit(state, &prices).await;
+        state.push.record_submission(&outcome);
+        match outcome {
+            Ok(digest) => {
+                state.push.queue.confirm();
+                return Ok(Some(digest));
+            }
+            Err(e) if attempt < push.max_submit_attempts && is_retryable(&e) => {
+                let backoff = push.retry_backoff_ms.saturating_mul(1 << (attempt - 1).min(16));
+                warn!("Push attempt {} failed, retrying in {} ms: {:#}", attempt, backoff, e);
+                tokio::time::sleep(Duration::from_millis(backoff)).await;
+                attempt += 1;
+            }
+            Err(e) => {
+                state.push.queue.requeue();
+                return Err(e);
+            }
+        }
+    }
+}
+
+/// Submit one transaction pushing `prices`, paid by the sender or by its
+/// sponsor, returning its digest.
+async fn submit(state: &AppState, prices: &[Signed<PriceFeedResponse>]) -> Result<String> {
+    let config = state.config.load_full();
+    let push = &config.push;
+    let sui = state.sui_client.load_full();
+    let kp = state.signing_keys.active_at(current_timestamp_ms()?);
+    let sender = sender_address(&kp);
+
+    let gas_price = state
+        .push
+        .gas_price(&sui, Duration::from_secs(push.gas_price_refresh_secs))
+        .await
+        .context("Failed to fetch the reference gas price")?;
+    let enclave_id = ObjectId::from_str(&push.enclave_object_id).context("Invalid push.enclave_object_id")?;
+    let enclave = state
+        .push
+        .shared_object(&sui, enclave_id)
+        .await
+        .context("Failed to fetch the Enclave object")?;
+    // The claim in the transaction is what elects; checking the lease first
+    // saves paying for one that would abort
+    let claim = match check_lease(state, push).await? {
+        Leadership::Unelected => None,
+        Leadership::Leader(claim) => Some(claim),
+        Leadership::Follower { holder, expires_at_ms } => {
+            return Err(anyhow::anyhow!(
+                "Instance {} holds the push lease until {}",
+                holder,
+                expires_at_ms
+            ))
+        }
+    };
+    let package = ObjectId::from_str(&config.sui.oracle_builder_package_id)
+        .context("Invalid sui.oracle_builder_package_id")?;
+    let ptb = push_transaction(
+        package,
+        push.enclave_type(&config.sui.oracle_builder_package_id)?,
+        enclave,
+        claim.as_ref(),
+        prices,
+    )?;
+
+    let mut transaction = Transaction {
+        kind: TransactionKind::ProgrammableTransaction(ptb),
+        sender,
+        gas_payment: GasPayment {
+            objects: Vec::new(),
+            owner: sender,
+            price: gas_price,
+            budget: 0,
+        },
+        expiration: TransactionExpiration::None,
+    };
+    match &push.sponsor {
+        Some(sponsor) => {
+            // The reserved coins cover max_gas_budget, the dry run tells
+            // how much of it to allow
+            let client = state.outbound.load().client_for(&sponsor.url);
+            let reservation = sponsor.reserve_gas(&client, push.max_gas_budget).await?;
+            transaction.gas_payment.objects = reservation.gas_coins;
+            transaction.gas_payment.owner = reservation.sponsor_address;
+            transaction.gas_payment.budget = push.max_gas_budget;
+            let gas_used = sui.dry_run(&transaction).await?;
+            transaction.gas_payment.budget = checked_gas_budget(push, gas_used, gas_price)?;
+            let signature = sign_transaction(&kp, &transaction);
+            sponsor
+                .execute(&client, reservation.reservation_id, &transaction, &signature)
+                .await
+        }
+        None => {
+            // Dry-run with as much of the budget as the coins allow, then
+            // pay only what it cost plus the margin
+            let coins = sui.gas_coins(&sender).await?;
+            let mut largest = coins.clone();
+            largest.sort_by(|a, b| b.balance.cmp(&a.balance));
+            largest.truncate(push.max_gas_coins);
+            let balance: u64 = largest.iter().map(|coin| coin.balance).fold(0, u64::saturating_add);
+            let dry_run_budget = balance.min(push.max_gas_budget);
+            if dry_run_budget < MIN_GAS_UNITS.saturating_mul(gas_price) {
+                return Err(anyhow::anyhow!("{} has not enough SUI to pay for a push", sender));
+            }
+            transaction.gas_payment.objects = largest.into_iter().map(|coin| coin.object_ref).collect();
+            transaction.gas_payment.budget = dry_run_budget;
+            let gas_used = sui.dry_run(&transaction).await?;
+
+            let budget = checked_gas_budget(push, gas_used, gas_price)?;
+            let coins = select_gas_coins(coins, budget, push.max_gas_coins)
+                .ok_or_else(|| anyhow::anyhow!("{} has not enough SUI for a budget of {} MIST", sender, budget))?;
+            transaction.gas_payment.objects = coins.into_iter().map(|coin| coin.object_ref).collect();
+            transaction.gas_payment.budget = budget;
+
+            let signature = sign_transaction(&kp, &transaction);
+            sui.execute(&transaction, &[signature]).await
+        }
+    }
+}
+
+/// Budget of a push whose dry run used `gas_used`, refused if it exceeds
+/// `push.max_gas_budget`.
+fn checked_gas_budget(push: &Push, gas_used: GasUsed, gas_price: u64) -> Result<u64> {
+    let budget = gas_budget(gas_used, gas_price, push.gas_budget_margin_bps);
+    if budget > push.max_gas_budget {
+        return Err(anyhow::anyhow!(
+            "Push would need a gas budget of {} MIST, over push.max_gas_budget",
+            budget
+        ));
+    }
+    Ok(budget)
+}
+
+/// Response for push.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PushResponse {
+    pub enabled: bool,
+    /// Sui address sending push transactions
+    pub sender: String,
+    /// Id this instance claims the push lease under
+    pub instance_id: String,
+    /// Gas station paying for them, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sponsor_url: Option<String>,
+    pub depth: usize,
+    pub queue: Vec<QueuedPrice>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_submission: Option<Submission>,
+}
+
+/// Endpoint that reports the push queue and how the last submission ended.
+pub async fn push_status(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<PushResponse>, EnclaveError> {
+    let config = state.config.load_full();
+    let kp = state.signing_keys.active_at(current_timestamp_ms()?);
+    let queue = state.push.queue.entries();
+    Ok(Json(PushResponse {
+        enabled: config.push.is_enabled(),
+        sender: sender_address(&kp).to_string(),
+        instance_id: state.push.instance_id(&config.push),
+        sponsor_url: config.push.sponsor.as_ref().map(|sponsor| sponsor.url.clone()),
+        depth: queue.len(),
+        queue,
+        last_submission: state.push.last_submission.lock().expect("submission lock poisoned").clone(),
+    }))
+}
+
+/// Flush the queue before the server exits, waiting for a submission in
+/// flight first, for at most `push.shutdown_timeout_secs`. Prices that do
+/// not make it stay in the persistence store for the next instance.
+pub async fn drain(state: &AppState) {
+    let push = state.config.load().push.clone();
+    if !push.is_enabled() || push.shutdown_timeout_secs == 0 || state.push.queue.depth() == 0 {
+        return;
+    }
+    info!("Pushing {} queued prices before shutdown", state.push.queue.depth());
+    let timeout = Duration::from_secs(push.shutdown_timeout_secs);
+    match tokio::time::timeout(timeout, flush(state)).await {
+        Ok(Ok(Some(digest))) => info!("Pushed queued prices in transaction {}", digest),
+        Ok(Ok(None)) => {}
+        Ok(Err(e)) => warn!("Pushing queued prices on shutdown failed: {:#}", e),
+        Err(_) => warn!(
+            "Pushing queued prices did not finish within {} seconds, {} left queued",
+            push.shutdown_timeout_secs,
+            state.push.queue.depth()
+        ),
+    }
+}
+
+/// Spawn the background pusher. The config is re-read before every round,
+/// so reloads take effect without a restart.
+pub fn spawn_pusher(state: &Arc<AppState>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        info!("Pusher started");
+        loop {
+            let push = state.config.load().push.clone();
+            if !push.is_enabled() {
+                tokio::time::sleep(DISABLED_RECHECK).await;
+                continue;
+            }
+            match push_feeds(&state).await {
+                Ok(Some(digest)) => info!("Pushed prices in transaction {}", digest),
+                Ok(None) => {}
+                Err(e) => warn!("Push failed: {:#}", e),
+            }
+            tokio::time::sleep(Duration::from_millis(push.interval_ms)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::tests::signed;
+    use crate::history::MemoryStore;
+    use fastcrypto::ed25519::Ed25519PublicKey as FcEd25519PublicKey;
+    use fastcrypto::ed25519::Ed25519Signature as FcEd25519Signature;
+    use fastcrypto::traits::VerifyingKey;
+    use rand::{rngs::StdRng, SeedableRng};
+    use sui_sdk_types::{ObjectDigest, ObjectReference};
+
+    fn coin(id: u8, balance: u64) -> GasCoin {
+        GasCoin {
+            object_ref: ObjectReference::new(ObjectId::new([id; 32]), 1, ObjectDigest::new([id; 32])),
+            balance,
+        }
+    }
+
+    fn balances(coins: &[GasCoin]) -> Vec<u64> {
+        coins.iter().map(|coin| coin.balance).collect()
+    }
+
+    #[test]
+    fn test_gas_budget() {
+        let gas_used = GasUsed {
+            computation_cost: 1_000_000,
+            storage_cost: 3_000_000,
+            storage_rebate: 2_000_000,
+        };
+        assert_eq!(gas_budget(gas_used, 750, 2_000), 4_800_000);
+        assert_eq!(gas_budget(gas_used, 750, 0), 4_000_000);
+        // Never below the minimum Sui accepts
+        assert_eq!(gas_budget(GasUsed::default(), 750, 2_000), 750_000);
+    }
+
+    #[test]
+    fn test_select_gas_coins() {
+        let coins = vec![coin(1, 10), coin(2, 500), coin(3, 3), coin(4, 300), coin(5, 1)];
+        // The largest coin covers it, the smallest are merged into it
+        let selected = select_gas_coins(coins.clone(), 400, 3).unwrap();
+        assert_eq!(balances(&selected), vec![500, 3, 1]);
+        let selected = select_gas_coins(coins.clone(), 700, 2).unwrap();
+        assert_eq!(balances(&selected), vec![500, 300]);
+        let selected = select_gas_coins(coins.clone(), 700, 16).unwrap();
+        assert_eq!(balances(&selected), vec![500, 300, 10, 3, 1]);
+        assert!(select_gas_coins(coins.clone(), 900, 16).is_none());
+        assert!(select_gas_coins(coins, 810, 2).is_none());
+        assert!(select_gas_coins(Vec::new(), 1, 16).is_none());
+    }
+
+    #[test]
+    fn test_push_transaction() {
+        let package = ObjectId::from_str("0x3c15ce11b86d364572f00a40b508d4a80f06d213f37e6b77db3932ffec5c7127").unwrap();
+        let enclave_type = Push::default()
+            .enclave_type("0x3c15ce11b86d364572f00a40b508d4a80f06d213f37e6b77db3932ffec5c7127")
+            .unwrap();
+        let enclave = SharedObject {
+            object_id: ObjectId::new([7; 32]),
+            initial_shared_version: 12,
+        };
+        let prices = [signed(1_000), signed(2_000)];
+        let ptb = push_transaction(package, enclave_type.clone(), enclave, None, &prices).unwrap();
+
+        assert_eq!(ptb.inputs.len(), 11);
+        assert_eq!(
+            ptb.inputs[0],
+            Input::Shared {
+                object_id: enclave.object_id,
+                initial_shared_version: 12,
+                mutable: false,
+            }
+        );
+        let data = &prices[1].response.data;
+        assert_eq!(ptb.inputs[6], Input::Pure { value: bcs::to_bytes(&data.oracle_id).unwrap() });
+        assert_eq!(ptb.inputs[8], Input::Pure { value: bcs::to_bytes(&data.price).unwrap() });
+        assert_eq!(ptb.inputs[9], Input::Pure { value: bcs::to_bytes(&2_000u64).unwrap() });
+        let signature = Hex::decode(&prices[1].signature).unwrap();
+        assert_eq!(ptb.inputs[10], Input::Pure { value: bcs::to_bytes(&signature).unwrap() });
+
+        let Command::MoveCall(call) = &ptb.commands[1] else {
+            panic!("expected a Move call");
+        };
+        assert_eq!(call.package, package);
+        assert_eq!(call.function.as_str(), "push_price_update");
+        assert_eq!(call.type_arguments, vec![enclave_type.clone()]);
+        assert_eq!(
+            call.arguments,
+            [6, 7, 8, 9, 10, 0].map(Argument::Input).to_vec()
+        );
+
+        // Only plain PriceFeed prices can be pushed
+        let mut closed = signed(3_000);
+        closed.response.intent = IntentScope::PriceFeedMarketClosed;
+        assert!(push_transaction(package, enclave_type.clone(), enclave, None, &[closed]).is_err());
+
+        // A lease claim comes first, its inputs right after the enclave
+        let claim = LeaseClaim {
+            lease: SharedObject {
+                object_id: ObjectId::new([8; 32]),
+                initial_shared_version: 5,
+            },
+            instance_id: "replica-1".to_string(),
+            duration_ms: 30_000,
+        };
+        let ptb = push_transaction(package, enclave_type.clone(), enclave, Some(&claim), &prices).unwrap();
+        assert_eq!(ptb.inputs.len(), 15);
+        assert_eq!(ptb.commands.len(), 3);
+        assert_eq!(
+            ptb.inputs[1],
+            Input::Shared {
+                object_id: claim.lease.object_id,
+                initial_shared_version: 5,
+                mutable: true,
+            }
+        );
+        assert_eq!(ptb.inputs[2], Input::Pure { value: bcs::to_bytes("replica-1").unwrap() });
+        assert_eq!(ptb.inputs[3], Input::Pure { value: bcs::to_bytes(&30_000u64).unwrap() });
+        assert_eq!(
+            ptb.inputs[4],
+            Input::Shared {
+                object_id: ObjectId::from_str("0x6").unwrap(),
+                initial_shared_version: 1,
+                mutable: false,
+            }
+        );
+        let Command::MoveCall(call) = &ptb.commands[0] else {
+            panic!("expected a Move call");
+        };
+        assert_eq!(call.function.as_str(), "claim_push_lease");
+        assert_eq!(call.type_arguments, vec![enclave_type]);
+        assert_eq!(call.arguments, [1, 0, 2, 3, 4].map(Argument::Input).to_vec());
+        let Command::MoveCall(call) = &ptb.commands[1] else {
+            panic!("expected a Move call");
+        };
+        assert_eq!(call.arguments, [5, 6, 7, 8, 9, 0].map(Argument::Input).to_vec());
+    }
+
+    #[test]
+    fn test_leadership() {
+        let lease_id = ObjectId::new([8; 32]);
+        let lease = PushLease {
+            holder: "replica-1".to_string(),
+            expires_at_ms: 10_000,
+            initial_shared_version: 5,
+        };
+        let claim = |instance_id: &str| {
+            Leadership::Leader(LeaseClaim {
+                lease: SharedObject {
+                    object_id: lease_id,
+                    initial_shared_version: 5,
+                },
+                instance_id: instance_id.to_string(),
+                duration_ms: 30_000,
+            })
+        };
+        // The holder renews its lease
+        assert_eq!(leadership(lease_id, &lease, "replica-1", 30_000, 5_000), claim("replica-1"));
+        // Others wait for it to expire
+        assert_eq!(
+            leadership(lease_id, &lease, "replica-2", 30_000, 5_000),
+            Leadership::Follower {
+                holder: "replica-1".to_string(),
+                expires_at_ms: 10_000,
+            }
+        );
+        assert_eq!(leadership(lease_id, &lease, "replica-2", 30_000, 10_000), claim("replica-2"));
+    }
+
+    #[test]
+    fn test_push_queue() {
+        let store: Arc<dyn HistoryStore> = Arc::new(MemoryStore::new(16));
+        let queue = PushQueue::with_store(Arc::clone(&store));
+        assert!(queue.enqueue(signed(2_000)));
+        // Only the latest price of a feed is kept
+        assert!(!queue.enqueue(signed(1_000)));
+        assert!(queue.enqueue(signed(3_000)));
+        let mut other = signed(1_000);
+        other.response.data.price_feed_id = "0x1".to_string();
+        assert!(queue.enqueue(other));
+        assert_eq!(queue.depth(), 2);
+
+        let prices = queue.take();
+        let feeds: Vec<&str> = prices.iter().map(|p| p.response.data.price_feed_id.as_str()).collect();
+        assert_eq!(feeds, ["0x1", "0x2"]);
+        assert_eq!(prices[1].response.data.timestamp_ms, 3_000);
+        // Taken prices stay stored until confirmed
+        assert_eq!(queue.depth(), 2);
+        assert_eq!(PushQueue::with_store(Arc::clone(&store)).depth(), 2);
+
+        // Failed prices go back unless superseded meanwhile
+        assert!(queue.enqueue(signed(4_000)));
+        queue.requeue();
+        let prices = queue.take();
+        assert_eq!(prices.len(), 2);
+        assert_eq!(prices[1].response.data.timestamp_ms, 4_000);
+        queue.confirm();
+        assert_eq!(queue.depth(), 0);
+        assert!(store.push_queue().unwrap().is_empty());
+
+        // An abandoned submission's prices are taken again
+        assert!(queue.enqueue(signed(5_000)));
+        queue.take();
+        assert_eq!(queue.take().len(), 1);
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let locked = anyhow::anyhow!(
+            "Failed to sign transaction by a quorum of validators because of locked objects: ObjectLockConflict"
+        );
+        assert!(is_retryable(&locked.context("Sui RPC error")));
+        assert!(is_retryable(&anyhow::anyhow!(
+            "Transaction needs 0x5 which is equivocated until the next epoch"
+        )));
+        assert!(!is_retryable(&anyhow::anyhow!("Transaction failed: MoveAbort in transaction block")));
+    }
+
+    #[test]
+    fn test_sign_transaction() {
+        let kp = Ed25519KeyPair::generate(&mut StdRng::seed_from_u64(7));
+        let sender = sender_address(&kp);
+        let transaction = Transaction {
+            kind: TransactionKind::ProgrammableTransaction(ProgrammableTransaction {
+                inputs: Vec::new(),
+                commands: Vec::new(),
+            }),
+            sender,
+            gas_payment: GasPayment {
+                objects: vec![coin(1, 1).object_ref],
+                owner: sender,
+                price: 750,
+                budget: 1_000_000,
+            },
+            expiration: TransactionExpiration::None,
+        };
+        let UserSignature::Simple(SimpleSignature::Ed25519 { signature, public_key }) =
+            sign_transaction(&kp, &transaction)
+        else {
+            panic!("expected an Ed25519 signature");
+        };
+        assert_eq!(public_key.derive_address(), sender);
+        let public_key = FcEd25519PublicKey::from_bytes(public_key.as_bytes()).unwrap();
+        let signature = FcEd25519Signature::from_bytes(signature.as_bytes()).unwrap();
+        assert!(public_key.verify(&transaction.signing_digest(), &signature).is_ok());
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Push::default().validate().is_empty());
+        let mut push = Push {
+            feeds: vec!["0x1".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(push.validate().len(), 1);
+        push.enclave_object_id = "0x7".to_string();
+        assert!(push.validate().is_empty());
+        push.enclave_type = Some("not a type".to_string());
+        push.max_gas_coins = 0;
+        push.max_submit_attempts = 0;
+        assert_eq!(push.validate().len(), 3);
+        push.sponsor = Some(Sponsor {
+            url: "not a url".to_string(),
+            token_env: None,
+            reserve_duration_secs: 60,
+        });
+        assert_eq!(push.validate().len(), 4);
+        push.lease_object_id = Some("lease".to_string());
+        push.lease_duration_ms = push.interval_ms;
+        push.instance_id = Some(String::new());
+        assert_eq!(push.validate().len(), 7);
+    }
+}
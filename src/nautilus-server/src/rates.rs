@@ -0,0 +1,131 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Feeds whose value can legitimately go negative, like interest or funding
+//! rates, signed under their own intent. Move has no signed integers, so the
+//! value is carried as a magnitude and a sign, as in `RateOfChange`. Like
+//! wide prices, rates are not recorded in the price history.
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::common::{
+    IntentMessage, IntentScope, ProcessDataRequest, ProcessedDataResponse, RoundingMode,
+};
+use crate::oracle::{self, OracleModule, Signed};
+use crate::wide::fetch_unrecorded;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Inner type T for ProcessDataRequest<T>
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RateRequest {
+    pub price_feed_id: String,
+    /// On-chain payment receipt, required when `payments.required` is set
+    #[serde(default)]
+    pub receipt_id: Option<String>,
+}
+
+/// Inner type T for IntentMessage<T>
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct RateFeedResponse {
+    pub oracle_id: String,
+    pub price_feed_id: String,
+    /// Absolute value, scaled by 10^exponent
+    pub magnitude: u64,
+    pub is_negative: bool,
+    pub exponent: u32,
+    pub timestamp_ms: u64,
+    /// Time the upstream says the rate is from, for feeds with a
+    /// `timestamp_field`
+    pub source_timestamp_ms: Option<u64>,
+    /// Rounding mode of the feed, for feeds that configure one. Always
+    /// serialized, so the signed bytes keep a single layout.
+    #[serde(default)]
+    pub rounding: Option<RoundingMode>,
+}
+
+impl RateFeedResponse {
+    /// The scaled value with its sign.
+    pub fn value(&self) -> i128 {
+        let magnitude = i128::from(self.magnitude);
+        if self.is_negative {
+            -magnitude
+        } else {
+            magnitude
+        }
+    }
+}
+
+/// Fetch and sign a feed's value, which may be negative. The route is
+/// served by [`RateModule`].
+#[utoipa::path(
+    post,
+    path = "/process_rate",
+    tag = "price feed",
+    request_body = ProcessDataRequest<RateRequest>,
+    params(
+        ("x-consumer-key" = Option<String>, Header, description = "API key of the consumer, see `[consumers]`"),
+        ("x-sui-signature" = Option<String>, Header, description = "Sui wallet signature of the timestamp and body, instead of an API key"),
+        ("x-sui-timestamp-ms" = Option<u64>, Header, description = "Time the wallet signature was made"),
+    ),
+    responses(
+        (status = 200, description = "Signed rate", body = ProcessedDataResponse<IntentMessage<RateFeedResponse>>),
+        (status = 400, description = "Invalid feed, failed fetch or market closed", body = crate::ErrorResponse),
+        (status = 401, description = "Missing or unknown consumer key or wallet", body = crate::ErrorResponse),
+        (status = 403, description = "Outside the feed's signing windows", body = crate::ErrorResponse),
+        (status = 503, description = "Signing is paused, globally or for the feed", body = crate::ErrorResponse),
+    )
+)]
+pub async fn process_rate(
+    state: State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ProcessedDataResponse<IntentMessage<RateFeedResponse>>>, EnclaveError> {
+    oracle::handle::<RateModule>(state, headers, body).await
+}
+
+/// Signed values of on-chain PriceFeed objects that can go negative.
+pub struct RateModule;
+
+impl OracleModule for RateModule {
+    const NAME: &'static str = "rate_feed";
+    const PATH: &'static str = "/process_rate";
+    const INTENT: IntentScope = IntentScope::RateFeed;
+
+    type Request = RateRequest;
+    type Response = RateFeedResponse;
+
+    #[instrument(name = "process_rate", skip_all, fields(price_feed_id = %request.price_feed_id))]
+    async fn process(
+        state: &AppState,
+        request: RateRequest,
+    ) -> Result<Signed<RateFeedResponse>, EnclaveError> {
+        let fetched =
+            fetch_unrecorded(state, &request.price_feed_id, request.receipt_id, "Rates").await?;
+        let upstream = &fetched.upstream;
+        let magnitude = u64::try_from(upstream.price.unsigned_abs()).map_err(|_| {
+            EnclaveError::GenericError(format!(
+                "Scaled rate is too large to fit in u64 (decimals: {})",
+                upstream.decimals
+            ))
+        })?;
+        let response = RateFeedResponse {
+            oracle_id: fetched.oracle_id.clone(),
+            price_feed_id: request.price_feed_id,
+            magnitude,
+            is_negative: upstream.price < 0,
+            exponent: upstream.decimals,
+            timestamp_ms: fetched.timestamp_ms,
+            source_timestamp_ms: upstream.source_timestamp_ms,
+            rounding: upstream.rounding,
+        };
+        fetched.sign(state, response, Self::INTENT)
+    }
+}
@@ -0,0 +1,117 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Hot feeds: a background task keeps a recently signed price of every feed
+//! listed in `refresher.hot_feeds`, and `/process_data` serves it as is, with
+//! its original timestamp, while it is at most `max_age_ms` old. Only once it
+//! is older is a price fetched and signed for the request, so upstream
+//! latency stays off the request path.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::app::{self, PriceFeedRequest};
+use crate::AppState;
+
+/// How often the refresher checks whether it has been enabled while disabled.
+const DISABLED_RECHECK: Duration = Duration::from_secs(5);
+
+/// `[refresher]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Refresher {
+    /// Price feed ids signed continuously in the background
+    #[serde(default)]
+    pub hot_feeds: Vec<String>,
+    /// Milliseconds between refreshes of the hot feeds; 0 disables refreshing
+    #[serde(default = "default_interval_ms")]
+    pub interval_ms: u64,
+    /// Age of a pre-signed price past which requests sign a fresh one
+    #[serde(default = "default_max_age_ms")]
+    pub max_age_ms: u64,
+}
+
+impl Default for Refresher {
+    fn default() -> Self {
+        Self {
+            hot_feeds: Vec::new(),
+            interval_ms: default_interval_ms(),
+            max_age_ms: default_max_age_ms(),
+        }
+    }
+}
+
+fn default_interval_ms() -> u64 {
+    1_000
+}
+
+fn default_max_age_ms() -> u64 {
+    5_000
+}
+
+impl Refresher {
+    pub fn is_hot(&self, price_feed_id: &str) -> bool {
+        self.hot_feeds.iter().any(|hot| hot == price_feed_id)
+    }
+
+    /// Check the config, returning the problems found.
+    pub fn validate(&self) -> Vec<String> {
+        if !self.hot_feeds.is_empty() && self.interval_ms > self.max_age_ms {
+            vec!["refresher.max_age_ms must be at least refresher.interval_ms, or every pre-signed price goes stale".to_string()]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// Sign a fresh price for every hot feed, concurrently, with their PriceFeed
+/// objects fetched together. Nothing is signed when payments are required,
+/// as each price must then be paid for.
+pub async fn refresh_feeds(state: &Arc<AppState>) {
+    let config = state.config.load_full();
+    if config.payments.required {
+        return;
+    }
+    let hot_feeds: Vec<&str> = config.refresher.hot_feeds.iter().map(String::as_str).collect();
+    let mut price_feeds = app::prefetch_price_feeds(state, &hot_feeds).await;
+    let mut refreshes = JoinSet::new();
+    for price_feed_id in &config.refresher.hot_feeds {
+        let state = Arc::clone(state);
+        let price_feed = price_feeds.remove(price_feed_id);
+        let price_feed_id = price_feed_id.clone();
+        refreshes.spawn(async move {
+            let request = PriceFeedRequest {
+                price_feed_id: price_feed_id.clone(),
+                receipt_id: None,
+                decimals: None,
+                params: BTreeMap::new(),
+                price_feed,
+            };
+            if let Err(e) = app::sign_and_cache(&state, request).await {
+                warn!("Refresh of hot feed {} failed: {}", price_feed_id, e);
+            }
+        });
+    }
+    while refreshes.join_next().await.is_some() {}
+}
+
+/// Spawn the background refresher. The interval is re-read from the config
+/// before every round, so reloads take effect without a restart.
+pub fn spawn_refresher(state: &Arc<AppState>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        info!("Hot feed refresher started");
+        loop {
+            let interval_ms = state.config.load().refresher.interval_ms;
+            if interval_ms == 0 {
+                tokio::time::sleep(DISABLED_RECHECK).await;
+                continue;
+            }
+            refresh_feeds(&state).await;
+            tokio::time::sleep(Duration::from_millis(interval_ms)).await;
+        }
+    });
+}
@@ -0,0 +1,240 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Proof of reserve oracle module: reads the balance of every account listed
+//! in an on-chain ReserveConfig object from the exchange or custodian holding
+//! it, and signs the total along with each account's share.
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::app::{extract_price, fetch_upstream};
+use crate::common::{
+    current_timestamp_ms, to_signed_response, IntentMessage, IntentScope, ProcessDataRequest,
+    ProcessedDataResponse,
+};
+use crate::formats::UpstreamFormat;
+use crate::oracle::{self, OracleModule, Signed};
+use crate::AppState;
+use crate::EnclaveError;
+
+/// `[reserves]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Reserves {
+    /// Number of decimals balances are scaled by
+    #[serde(default = "default_balance_decimals")]
+    pub balance_decimals: u32,
+}
+
+impl Default for Reserves {
+    fn default() -> Self {
+        Self {
+            balance_decimals: default_balance_decimals(),
+        }
+    }
+}
+
+fn default_balance_decimals() -> u32 {
+    6
+}
+
+/// Balance of one account, scaled like the total.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct AccountBalance {
+    pub label: String,
+    pub balance: u64,
+}
+
+/// Inner type T for IntentMessage<T>
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct ReserveResponse {
+    pub oracle_id: String,
+    pub reserve_config_id: String,
+    pub asset: String,
+    /// Sum of the account balances, scaled by 10^decimals
+    pub total_reserves: u64,
+    pub decimals: u32,
+    pub accounts: Vec<AccountBalance>,
+    pub timestamp_ms: u64,
+}
+
+/// Inner type T for ProcessDataRequest<T>
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReserveRequest {
+    pub reserve_config_id: String,
+}
+
+/// Fetch the balances of the accounts of a reserve config registered on
+/// chain and sign their total. The route is served by [`ReservesModule`].
+#[utoipa::path(
+    post,
+    path = "/proof_of_reserve",
+    tag = "reserves",
+    request_body = ProcessDataRequest<ReserveRequest>,
+    params(
+        ("x-consumer-key" = Option<String>, Header, description = "API key of the consumer, see `[consumers]`"),
+        ("x-sui-signature" = Option<String>, Header, description = "Sui wallet signature of the timestamp and body, instead of an API key"),
+        ("x-sui-timestamp-ms" = Option<u64>, Header, description = "Time the wallet signature was made"),
+    ),
+    responses(
+        (status = 200, description = "Signed reserves", body = ProcessedDataResponse<IntentMessage<ReserveResponse>>),
+        (status = 400, description = "Invalid config or failed fetch", body = crate::ErrorResponse),
+        (status = 401, description = "Missing or unknown consumer key or wallet", body = crate::ErrorResponse),
+        (status = 503, description = "Signing is paused, globally or for the config", body = crate::ErrorResponse),
+    )
+)]
+pub async fn process_reserves(
+    state: State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ProcessedDataResponse<IntentMessage<ReserveResponse>>>, EnclaveError> {
+    oracle::handle::<ReservesModule>(state, headers, body).await
+}
+
+/// Signed total reserves of on-chain ReserveConfig objects.
+pub struct ReservesModule;
+
+impl OracleModule for ReservesModule {
+    const NAME: &'static str = "reserves";
+    const PATH: &'static str = "/proof_of_reserve";
+    const INTENT: IntentScope = IntentScope::ProofOfReserve;
+
+    type Request = ReserveRequest;
+    type Response = ReserveResponse;
+
+    #[instrument(name = "proof_of_reserve", skip_all, fields(reserve_config_id = %request.reserve_config_id))]
+    async fn process(
+        state: &AppState,
+        request: ReserveRequest,
+    ) -> Result<Signed<ReserveResponse>, EnclaveError> {
+        let config = state.config.load_full();
+        let reserve_config_id = request.reserve_config_id;
+        state.pause.check(&config, Some(&reserve_config_id))?;
+
+        let reserve_config = state
+            .sui_client
+            .load_full()
+            .fetch_reserve_config(&reserve_config_id)
+            .await
+            .map_err(|e| EnclaveError::GenericError(format!("Failed to fetch reserve config: {}", e)))?;
+        if !reserve_config.is_valid {
+            return Err(EnclaveError::GenericError(
+                "Reserve config is not valid".to_string(),
+            ));
+        }
+        if reserve_config.accounts.is_empty() {
+            return Err(EnclaveError::GenericError(
+                "Reserve config lists no accounts".to_string(),
+            ));
+        }
+
+        let decimals = config.reserves.balance_decimals;
+        let mut accounts = Vec::with_capacity(reserve_config.accounts.len());
+        for account in &reserve_config.accounts {
+            // Each account's API has its own response shape
+            let json = fetch_upstream(
+                state,
+                &config,
+                &format!("{}:{}", reserve_config_id, account.label),
+                &account.url,
+                account.api_key.as_deref().zip(account.api_key_config.as_deref()),
+                &UpstreamFormat::default(),
+            )
+            .await?;
+            let balance = extract_price(&json, &account.balance_field).map_err(EnclaveError::GenericError)?;
+            accounts.push(AccountBalance {
+                label: account.label.clone(),
+                balance: scale_balance(&account.label, balance, decimals)?,
+            });
+        }
+        let total_reserves = total(&accounts)?;
+
+        let current_timestamp = current_timestamp_ms()?;
+        let kp = state.signing_keys.signer_for(Self::INTENT, current_timestamp, current_timestamp)?;
+        let response = ReserveResponse {
+            oracle_id: reserve_config.oracle_id,
+            reserve_config_id,
+            asset: reserve_config.asset,
+            total_reserves,
+            decimals,
+            accounts,
+            timestamp_ms: current_timestamp,
+        };
+        Ok(to_signed_response(&kp, response, current_timestamp, Self::INTENT))
+    }
+}
+
+/// Scale a balance by 10^decimals, refusing negative balances.
+fn scale_balance(label: &str, balance: Decimal, decimals: u32) -> Result<u64, EnclaveError> {
+    if balance.is_sign_negative() && !balance.is_zero() {
+        return Err(EnclaveError::GenericError(format!(
+            "Account {} reports a negative balance: {}",
+            label, balance
+        )));
+    }
+    Decimal::from(10_u64.pow(decimals))
+        .checked_mul(balance)
+        .and_then(|scaled| {
+            scaled
+                .round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero)
+                .to_u64()
+        })
+        .ok_or_else(|| {
+            EnclaveError::GenericError(format!(
+                "Scaled balance of account {} is too large to fit in u64 (decimals: {})",
+                label, decimals
+            ))
+        })
+}
+
+/// Sum of the scaled account balances.
+fn total(accounts: &[AccountBalance]) -> Result<u64, EnclaveError> {
+    accounts
+        .iter()
+        .try_fold(0_u64, |sum, account| sum.checked_add(account.balance))
+        .ok_or_else(|| EnclaveError::GenericError("Total reserves overflow u64".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_scale_balance() {
+        let balance = Decimal::from_str("1234.5678905").unwrap();
+        assert_eq!(scale_balance("a", balance, 6).unwrap(), 1234567891);
+        assert_eq!(scale_balance("a", Decimal::ZERO, 6).unwrap(), 0);
+        assert!(scale_balance("a", Decimal::from(-1), 6).is_err());
+        assert!(scale_balance("a", Decimal::from(u64::MAX), 6).is_err());
+    }
+
+    #[test]
+    fn test_scale_balance_rounds_midpoints_away_from_zero() {
+        // Banker's rounding would give 2 for both midpoints.
+        let balance = Decimal::from_str("0.0000025").unwrap();
+        assert_eq!(scale_balance("a", balance, 6).unwrap(), 3);
+        let balance = Decimal::from_str("0.0000015").unwrap();
+        assert_eq!(scale_balance("a", balance, 6).unwrap(), 2);
+        let balance = Decimal::from_str("0.0000024999").unwrap();
+        assert_eq!(scale_balance("a", balance, 6).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_total() {
+        let account = |balance| AccountBalance {
+            label: "a".to_string(),
+            balance,
+        };
+        assert_eq!(total(&[account(1), account(2)]).unwrap(), 3);
+        assert!(total(&[account(u64::MAX), account(1)]).is_err());
+    }
+}
@@ -0,0 +1,141 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Key retirement: the signing key commits, in a statement it signs itself
+//! under the KeyRetirement intent, that it signs nothing after a given time.
+//! Verifiers holding the statement can stop trusting the key at that time
+//! without waiting for it to be deregistered, and the enclave refuses to
+//! sign messages with it afterwards, or timestamped afterwards. The keys of
+//! `key.scoped_intents` are retired the same way, by their intent.
+//!
+//! In `sealed` mode the commitments are sealed next to the key and restored
+//! on restart. Ephemeral keys do not outlive a restart anyway.
+
+use axum::extract::{Query, State};
+use axum::Json;
+use fastcrypto::ed25519::Ed25519KeyPair;
+use fastcrypto::traits::{KeyPair, ToFromBytes};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::info;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::common::{
+    current_timestamp_ms, to_signed_response, IntentMessage, IntentScope, ProcessedDataResponse,
+};
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Signed statement that `public_key` signs nothing after `retire_at_ms`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct KeyRetirement {
+    pub public_key: Vec<u8>,
+    pub retire_at_ms: u64,
+}
+
+/// Request for retire key.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RetireKeyRequest {
+    /// Time after which the current signing key signs nothing, in the future
+    pub retire_at_ms: u64,
+    /// Retire the key of this intent, from `key.scoped_intents`, rather than
+    /// the main key
+    #[serde(default)]
+    pub intent_scope: Option<u8>,
+}
+
+/// Query parameters for key retirement.
+#[derive(Debug, Serialize, Deserialize, IntoParams)]
+pub struct KeyRetirementQuery {
+    /// The statement of the key of this intent, from `key.scoped_intents`,
+    /// rather than of the main key
+    pub intent_scope: Option<u8>,
+}
+
+/// The key of `intent_scope` if given, otherwise the active main key.
+fn retiring_key(
+    state: &AppState,
+    intent_scope: Option<u8>,
+    now: u64,
+) -> Result<Arc<Ed25519KeyPair>, EnclaveError> {
+    match intent_scope {
+        Some(intent) => state
+            .signing_keys
+            .scoped_key(intent)
+            .map(|(_, kp)| kp)
+            .ok_or_else(|| {
+                EnclaveError::GenericError(format!("Intent {} has no key of its own", intent))
+            }),
+        None => Ok(state.signing_keys.active_at(now)),
+    }
+}
+
+/// Endpoint that commits the current signing key, or the key of an intent,
+/// to a retirement time and returns the statement signed by that key. A key
+/// already retired keeps the earlier of the two times.
+pub async fn retire_key(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<RetireKeyRequest>,
+) -> Result<Json<ProcessedDataResponse<IntentMessage<KeyRetirement>>>, EnclaveError> {
+    let now = current_timestamp_ms()?;
+    if request.retire_at_ms <= now {
+        return Err(EnclaveError::GenericError(format!(
+            "retire_at_ms must be in the future, got {} at {}",
+            request.retire_at_ms, now
+        )));
+    }
+    let kp = retiring_key(&state, request.intent_scope, now)?;
+    let retire_at_ms = state
+        .signing_keys
+        .retire(kp.public().as_bytes(), request.retire_at_ms)
+        .map_err(|e| {
+            EnclaveError::GenericError(format!("Failed to persist the retirement: {:#}", e))
+        })?;
+    info!("Signing key retires at {}", retire_at_ms);
+
+    let statement = KeyRetirement {
+        public_key: kp.public().as_bytes().to_vec(),
+        retire_at_ms,
+    };
+    Ok(Json(to_signed_response(
+        &kp,
+        statement,
+        now,
+        IntentScope::KeyRetirement,
+    )))
+}
+
+/// Retirement statement of the current signing key, or of the key of an
+/// intent, for verifiers to time-bound their trust in it. Signing it is
+/// allowed past the deadline.
+#[utoipa::path(
+    get,
+    path = "/key_retirement",
+    tag = "attestation",
+    params(KeyRetirementQuery),
+    responses(
+        (status = 200, description = "Statement signed by the retired key", body = ProcessedDataResponse<IntentMessage<KeyRetirement>>),
+        (status = 400, description = "The current key is not retired", body = crate::ErrorResponse),
+    )
+)]
+pub async fn key_retirement(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<KeyRetirementQuery>,
+) -> Result<Json<ProcessedDataResponse<IntentMessage<KeyRetirement>>>, EnclaveError> {
+    let now = current_timestamp_ms()?;
+    let kp = retiring_key(&state, query.intent_scope, now)?;
+    let retire_at_ms = state
+        .signing_keys
+        .retire_at(kp.public().as_bytes())
+        .ok_or_else(|| EnclaveError::GenericError("The signing key is not retired".to_string()))?;
+    let statement = KeyRetirement {
+        public_key: kp.public().as_bytes().to_vec(),
+        retire_at_ms,
+    };
+    Ok(Json(to_signed_response(
+        &kp,
+        statement,
+        now,
+        IntentScope::KeyRetirement,
+    )))
+}
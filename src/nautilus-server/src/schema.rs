@@ -0,0 +1,123 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Structural fingerprints of upstream responses. Providers often change the
+//! shape of a response before the values in it go wrong, so a feed whose
+//! response loses fields or changes their types is alerted on even while its
+//! price still extracts.
+
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+/// JSON type of every path in a response. Array elements share the `[]`
+/// segment, so the length of an array does not change the fingerprint.
+pub type Fingerprint = BTreeMap<String, &'static str>;
+
+/// Fingerprint of `json`.
+pub fn fingerprint(json: &Value) -> Fingerprint {
+    let mut paths = Fingerprint::new();
+    collect(json, String::new(), &mut paths);
+    paths
+}
+
+fn collect(value: &Value, path: String, paths: &mut Fingerprint) {
+    let kind = match value {
+        Value::Null => "null",
+        Value::Bool(_) => "bool",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(items) => {
+            for item in items {
+                collect(item, format!("{}[]", path), paths);
+            }
+            "array"
+        }
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                let child = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                collect(field, child, paths);
+            }
+            "object"
+        }
+    };
+    paths.insert(path, kind);
+}
+
+/// Material differences from `previous` to `current`: paths that disappeared
+/// or changed type. New paths are not material, providers add fields freely.
+pub fn material_changes(previous: &Fingerprint, current: &Fingerprint) -> Vec<String> {
+    previous
+        .iter()
+        .filter_map(|(path, kind)| {
+            let path_name = if path.is_empty() { "<root>" } else { path.as_str() };
+            match current.get(path) {
+                None => Some(format!("{} removed", path_name)),
+                // A null field may legitimately take a value and vice versa.
+                Some(new_kind) if new_kind != kind && *kind != "null" && *new_kind != "null" => {
+                    Some(format!("{} changed from {} to {}", path_name, kind, new_kind))
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Last seen fingerprint of each feed's upstream response.
+#[derive(Default)]
+pub struct SchemaTracker {
+    feeds: RwLock<HashMap<String, Fingerprint>>,
+}
+
+impl SchemaTracker {
+    /// Record the shape of the latest response of `price_feed_id`, returning
+    /// the material changes since the previous one.
+    pub fn observe(&self, price_feed_id: &str, json: &Value) -> Vec<String> {
+        let current = fingerprint(json);
+        let mut feeds = self.feeds.write().expect("schema lock poisoned");
+        let changes = match feeds.get(price_feed_id) {
+            Some(previous) if *previous == current => return Vec::new(),
+            Some(previous) => material_changes(previous, &current),
+            None => Vec::new(),
+        };
+        feeds.insert(price_feed_id.to_string(), current);
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_observe_reports_material_changes() {
+        let tracker = SchemaTracker::default();
+        let first = json!({"data": {"price": "1.5", "ts": 1}, "items": [{"a": 1}, {"a": 2}]});
+        assert!(tracker.observe("feed", &first).is_empty());
+
+        // Different values, array length and an added field are not material.
+        let same_shape = json!({"data": {"price": "1.7", "ts": 2, "extra": true}, "items": [{"a": 3}]});
+        assert!(tracker.observe("feed", &same_shape).is_empty());
+
+        let changed = json!({"data": {"price": 1.7, "extra": true}, "items": []});
+        let changes = tracker.observe("feed", &changed);
+        assert_eq!(
+            changes,
+            vec![
+                "data.price changed from string to number",
+                "data.ts removed",
+                "items[] removed",
+                "items[].a removed",
+            ]
+        );
+
+        // The new shape is the baseline from now on.
+        assert!(tracker.observe("feed", &changed).is_empty());
+        assert!(tracker.observe("other", &json!(1)).is_empty());
+    }
+}
@@ -0,0 +1,394 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Secrets kept off chain. A feed whose on-chain `api_key` is a reference of
+//! the form `secret:<name>` has the key resolved through the configured
+//! [`SecretProvider`] before each upstream request, so paid data providers'
+//! keys are never stored in a public Sui object. Each secret is only resolved
+//! for the feeds and upstream hosts its `secrets.allowlist` entry names, so a
+//! feed cannot send another provider's key to a host of its choosing.
+//! Resolved values are cached for `secrets.cache_ttl_secs`.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio_vsock::{VsockAddr, VsockStream};
+
+use crate::common::current_timestamp_ms;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Prefix of on-chain `api_key` values that name a secret.
+pub const SECRET_REFERENCE_PREFIX: &str = "secret:";
+
+/// `[secrets]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Secrets {
+    #[serde(default)]
+    pub provider: Provider,
+    /// Prefix of the environment variables of the `env` provider
+    #[serde(default = "default_env_prefix")]
+    pub env_prefix: String,
+    /// Address of the secret service of the `vsock` provider on the parent
+    /// instance
+    #[serde(default = "default_vsock_cid")]
+    pub vsock_cid: u32,
+    #[serde(default)]
+    pub vsock_port: Option<u32>,
+    /// Seconds a resolved secret is reused; 0 resolves it for every request
+    #[serde(default = "default_cache_ttl_secs")]
+    pub cache_ttl_secs: u64,
+    /// Where each secret may be used, by name. Secrets without an entry are
+    /// refused.
+    #[serde(default)]
+    pub allowlist: BTreeMap<String, SecretUse>,
+}
+
+/// Feeds and upstream hosts a secret may be sent to. A request must match
+/// every list that is set.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SecretUse {
+    /// Object ids of the feeds whose requests may carry the secret
+    #[serde(default)]
+    pub feeds: Vec<String>,
+    /// Hosts of the upstream URLs the secret may be sent to
+    #[serde(default)]
+    pub hosts: Vec<String>,
+}
+
+impl SecretUse {
+    /// Whether the secret may be sent for `feed_id` to `url`.
+    fn allows(&self, feed_id: &str, url: &str) -> bool {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_ascii_lowercase));
+        (self.feeds.is_empty() || self.feeds.iter().any(|feed| feed == feed_id))
+            && (self.hosts.is_empty()
+                || host.is_some_and(|host| {
+                    self.hosts
+                        .iter()
+                        .any(|allowed| allowed.eq_ignore_ascii_case(&host))
+                }))
+    }
+}
+
+impl Default for Secrets {
+    fn default() -> Self {
+        Self {
+            provider: Provider::default(),
+            env_prefix: default_env_prefix(),
+            vsock_cid: default_vsock_cid(),
+            vsock_port: None,
+            cache_ttl_secs: default_cache_ttl_secs(),
+            allowlist: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_env_prefix() -> String {
+    "NAUTILUS_SECRET_".to_string()
+}
+
+fn default_vsock_cid() -> u32 {
+    3
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    300
+}
+
+impl Secrets {
+    /// Check the config, returning the problems found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.provider == Provider::Vsock && self.vsock_port.is_none() {
+            problems.push("secrets.vsock_port is required by the vsock provider".to_string());
+        }
+        for (name, allowed) in &self.allowlist {
+            if allowed.feeds.is_empty() && allowed.hosts.is_empty() {
+                problems.push(format!(
+                    "secrets.allowlist.{} must list feeds or hosts",
+                    name
+                ));
+            }
+        }
+        problems
+    }
+}
+
+/// Where secrets are resolved from.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Provider {
+    /// Environment variables of the enclave, `env_prefix` followed by the name
+    #[default]
+    Env,
+    /// A service on the parent instance reached over vsock, e.g. one reading
+    /// AWS Secrets Manager or decrypting with KMS under the instance's role
+    Vsock,
+}
+
+/// Source of secret values by name.
+pub trait SecretProvider: Send + Sync {
+    /// Value of the secret `name`.
+    fn resolve<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>>;
+}
+
+/// Secrets from environment variables.
+pub struct EnvProvider {
+    prefix: String,
+}
+
+impl SecretProvider for EnvProvider {
+    fn resolve<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        Box::pin(async move {
+            let variable = format!("{}{}", self.prefix, name);
+            std::env::var(&variable).with_context(|| format!("variable {} is not set", variable))
+        })
+    }
+}
+
+/// Secrets from a service on the parent instance. Each lookup is a
+/// connection carrying one JSON line each way: `{"name": ...}` and the
+/// answer `{"value": ...}` or `{"error": ...}`.
+pub struct VsockProvider {
+    cid: u32,
+    port: u32,
+}
+
+impl SecretProvider for VsockProvider {
+    fn resolve<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<String>> + Send + 'a>> {
+        Box::pin(async move {
+            let stream = VsockStream::connect(VsockAddr::new(self.cid, self.port))
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to reach the secret service at vsock {}:{}",
+                        self.cid, self.port
+                    )
+                })?;
+            request_secret(stream, name).await
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SecretRequest {
+    name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SecretAnswer {
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Ask the secret service on `stream` for `name`.
+async fn request_secret<S: AsyncRead + AsyncWrite + Unpin>(
+    stream: S,
+    name: &str,
+) -> Result<String> {
+    let mut stream = BufReader::new(stream);
+    let mut request = serde_json::to_vec(&SecretRequest {
+        name: name.to_string(),
+    })?;
+    request.push(b'\n');
+    stream.write_all(&request).await?;
+    stream.flush().await?;
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+    let answer: SecretAnswer =
+        serde_json::from_str(&line).context("the secret service answered with invalid JSON")?;
+    match (answer.value, answer.error) {
+        (Some(value), _) => Ok(value),
+        (None, Some(error)) => Err(anyhow::anyhow!("the secret service refused: {}", error)),
+        (None, None) => Err(anyhow::anyhow!(
+            "the secret service answered without a value"
+        )),
+    }
+}
+
+/// The provider selected by `config`.
+pub fn open_provider(config: &Secrets) -> Box<dyn SecretProvider> {
+    match (config.provider, config.vsock_port) {
+        (Provider::Vsock, Some(port)) => Box::new(VsockProvider {
+            cid: config.vsock_cid,
+            port,
+        }),
+        // Without a port the config is refused on load
+        _ => Box::new(EnvProvider {
+            prefix: config.env_prefix.clone(),
+        }),
+    }
+}
+
+/// Resolved secrets by name, with the time they were resolved.
+#[derive(Default)]
+pub struct SecretCache {
+    values: Mutex<HashMap<String, (String, u64)>>,
+}
+
+impl SecretCache {
+    /// `api_key` as given, or the secret it references, resolved through the
+    /// provider of `config` unless cached. A secret is refused unless its
+    /// allowlist entry admits a request for `feed_id` to `url`.
+    pub async fn resolve_api_key(
+        &self,
+        config: &Secrets,
+        feed_id: &str,
+        url: &str,
+        api_key: &str,
+    ) -> Result<String, EnclaveError> {
+        let Some(name) = api_key.strip_prefix(SECRET_REFERENCE_PREFIX) else {
+            return Ok(api_key.to_string());
+        };
+        if !config
+            .allowlist
+            .get(name)
+            .is_some_and(|allowed| allowed.allows(feed_id, url))
+        {
+            return Err(EnclaveError::GenericError(format!(
+                "Secret '{}' is not allowed for feed {} and its upstream",
+                name, feed_id
+            )));
+        }
+        let now_ms = current_timestamp_ms()?;
+        let ttl_ms = config.cache_ttl_secs.saturating_mul(1000);
+        if let Some((value, resolved_at_ms)) = self.values.lock().unwrap().get(name) {
+            if now_ms.saturating_sub(*resolved_at_ms) < ttl_ms {
+                return Ok(value.clone());
+            }
+        }
+        let value = open_provider(config).resolve(name).await.map_err(|e| {
+            EnclaveError::GenericError(format!("Failed to resolve secret '{}': {:#}", name, e))
+        })?;
+        self.values
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), (value.clone(), now_ms));
+        Ok(value)
+    }
+}
+
+/// `api_key` of a request for `feed_id` to `url`, given as `(key, scheme)`,
+/// decrypted if it is encrypted to the enclave and with a secret reference
+/// resolved.
+pub async fn resolve_api_key<'a>(
+    state: &AppState,
+    feed_id: &str,
+    url: &str,
+    api_key: Option<(&str, &'a str)>,
+) -> Result<Option<(String, &'a str)>, EnclaveError> {
+    let Some((key, scheme)) = api_key else {
+        return Ok(None);
+    };
+    let key = state.api_key_decryption.decrypt_api_key(key, feed_id, url)?;
+    let config = state.config.load_full();
+    let key = state
+        .secrets
+        .resolve_api_key(&config.secrets, feed_id, url, &key)
+        .await?;
+    Ok(Some((key, scheme)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_env_provider_and_cache() {
+        std::env::set_var("NAUTILUS_SECRET_TEST_VENDOR", "k3y");
+        let config: Secrets = toml::from_str(
+            r#"
+            [allowlist.TEST_VENDOR]
+            feeds = ["0xfeed"]
+            hosts = ["api.vendor.com"]
+            [allowlist.TEST_MISSING]
+            hosts = ["api.vendor.com"]
+            "#,
+        )
+        .unwrap();
+        assert!(config.validate().is_empty());
+        let cache = SecretCache::default();
+        let url = "https://API.vendor.com/price?symbol=BTC";
+        assert_eq!(
+            cache
+                .resolve_api_key(&config, "0xother", "https://evil.com", "plain")
+                .await
+                .unwrap(),
+            "plain"
+        );
+        assert_eq!(
+            cache
+                .resolve_api_key(&config, "0xfeed", url, "secret:TEST_VENDOR")
+                .await
+                .unwrap(),
+            "k3y"
+        );
+        // Served from the cache while the variable changes
+        std::env::set_var("NAUTILUS_SECRET_TEST_VENDOR", "rotated");
+        assert_eq!(
+            cache
+                .resolve_api_key(&config, "0xfeed", url, "secret:TEST_VENDOR")
+                .await
+                .unwrap(),
+            "k3y"
+        );
+        assert!(cache
+            .resolve_api_key(&config, "0xfeed", url, "secret:TEST_MISSING")
+            .await
+            .is_err());
+
+        // Refused for other feeds, other hosts and secrets not listed, even
+        // when cached
+        for (feed_id, url, api_key) in [
+            ("0xother", url, "secret:TEST_VENDOR"),
+            ("0xfeed", "https://evil.com/price", "secret:TEST_VENDOR"),
+            ("0xfeed", url, "secret:UNLISTED"),
+        ] {
+            let err = cache
+                .resolve_api_key(&config, feed_id, url, api_key)
+                .await
+                .unwrap_err();
+            assert!(err.to_string().contains("is not allowed"));
+        }
+        let config: Secrets = toml::from_str("[allowlist.TEST_VENDOR]").unwrap();
+        assert_eq!(config.validate().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_request_secret() {
+        let (client, server) = tokio::io::duplex(1024);
+        let service = tokio::spawn(async move {
+            let mut server = BufReader::new(server);
+            let mut line = String::new();
+            server.read_line(&mut line).await.unwrap();
+            let request: SecretRequest = serde_json::from_str(&line).unwrap();
+            let answer = format!("{{\"value\": \"value of {}\"}}\n", request.name);
+            server.write_all(answer.as_bytes()).await.unwrap();
+        });
+        assert_eq!(
+            request_secret(client, "vendor").await.unwrap(),
+            "value of vendor"
+        );
+        service.await.unwrap();
+
+        let config: Secrets = toml::from_str(r#"provider = "vsock""#).unwrap();
+        assert_eq!(config.validate().len(), 1);
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sponsored pushes: with a `[push.sponsor]` section, push transactions are
+//! paid by a gas station instead of the enclave key, which then needs no
+//! SUI at all. The station speaks the sui-gas-pool API: gas coins of its
+//! sponsor address are reserved for a budget, the transaction is built
+//! with them and signed by the enclave key, and the station adds the
+//! sponsor's signature and executes it. Reserved coins the push does not
+//! end up using are released by the station once the reservation expires.
+
+use anyhow::{Context, Result};
+use fastcrypto::encoding::{Base64, Encoding};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::str::FromStr;
+use sui_sdk_types::{Address, ObjectDigest, ObjectReference, Transaction, UserSignature};
+
+use crate::config::check_http_url;
+use crate::sui::{check_effects, u64_value};
+
+/// Longest reservation the gas station grants
+const MAX_RESERVE_DURATION_SECS: u64 = 600;
+
+/// `[push.sponsor]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Sponsor {
+    /// Base URL of the gas station
+    pub url: String,
+    /// Environment variable holding the station's bearer token, if it
+    /// needs one
+    #[serde(default)]
+    pub token_env: Option<String>,
+    /// Seconds gas coins are reserved for a push
+    #[serde(default = "default_reserve_duration_secs")]
+    pub reserve_duration_secs: u64,
+}
+
+fn default_reserve_duration_secs() -> u64 {
+    60
+}
+
+impl Sponsor {
+    /// Check the config, returning the problems found.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Err(e) = check_http_url(&self.url) {
+            problems.push(format!("push.sponsor.url: {}", e));
+        }
+        if self.token_env.as_deref() == Some("") {
+            problems.push("push.sponsor.token_env must name a variable".to_string());
+        }
+        if !(1..=MAX_RESERVE_DURATION_SECS).contains(&self.reserve_duration_secs) {
+            problems.push(format!(
+                "push.sponsor.reserve_duration_secs must be between 1 and {}",
+                MAX_RESERVE_DURATION_SECS
+            ));
+        }
+        problems
+    }
+
+    /// Call `route` of the gas station with `body`, returning its answer.
+    async fn call(&self, client: &Client, route: &str, body: &Value) -> Result<Value> {
+        let url = format!("{}/v1/{}", self.url.trim_end_matches('/'), route);
+        let mut request = client.post(&url).json(body);
+        if let Some(token_env) = &self.token_env {
+            let token = std::env::var(token_env)
+                .map_err(|_| anyhow::anyhow!("Gas station token variable {} is not set", token_env))?;
+            request = request.bearer_auth(token);
+        }
+        let response = request
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach the gas station at {}", url))?;
+        let status = response.status();
+        let answer: Value = response
+            .json()
+            .await
+            .with_context(|| format!("Gas station answered {} without JSON", status))?;
+        if let Some(error) = answer.get("error").and_then(|v| v.as_str()) {
+            return Err(anyhow::anyhow!("Gas station refused {}: {}", route, error));
+        }
+        if !status.is_success() {
+            return Err(anyhow::anyhow!("Gas station answered {} to {}", status, route));
+        }
+        Ok(answer)
+    }
+
+    /// Reserve gas coins of the sponsor covering `budget` MIST.
+    pub async fn reserve_gas(&self, client: &Client, budget: u64) -> Result<Reservation> {
+        let body = json!({
+            "gas_budget": budget,
+            "reserve_duration_secs": self.reserve_duration_secs,
+        });
+        let answer = self.call(client, "reserve_gas", &body).await?;
+        let result = answer
+            .get("result")
+            .ok_or_else(|| anyhow::anyhow!("No result in gas reservation"))?;
+        Reservation::from_json(result)
+    }
+
+    /// Have the station co-sign and execute `transaction`, built with the
+    /// coins of `reservation_id` and signed by its sender, returning its
+    /// digest. Fails if it aborted.
+    pub async fn execute(
+        &self,
+        client: &Client,
+        reservation_id: u64,
+        transaction: &Transaction,
+        signature: &UserSignature,
+    ) -> Result<String> {
+        let body = json!({
+            "reservation_id": reservation_id,
+            "tx_bytes": Base64::encode(bcs::to_bytes(transaction)?),
+            "user_sig": signature.to_base64(),
+        });
+        let answer = self.call(client, "execute_tx", &body).await?;
+        let effects = answer
+            .get("effects")
+            .ok_or_else(|| anyhow::anyhow!("No effects in sponsored execution"))?;
+        let digest = effects
+            .get("transactionDigest")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("No digest in sponsored execution"))?;
+        check_effects(effects).with_context(|| format!("Transaction {} failed", digest))?;
+        Ok(digest.to_string())
+    }
+}
+
+/// Gas coins the station reserved for one transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reservation {
+    /// Owner of the coins, who pays for the transaction
+    pub sponsor_address: Address,
+    pub reservation_id: u64,
+    pub gas_coins: Vec<ObjectReference>,
+}
+
+impl Reservation {
+    fn from_json(result: &Value) -> Result<Self> {
+        let sponsor_address = result
+            .get("sponsor_address")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing or invalid sponsor_address"))?;
+        let reservation_id = result
+            .get("reservation_id")
+            .and_then(u64_value)
+            .ok_or_else(|| anyhow::anyhow!("Missing or invalid reservation_id"))?;
+        let gas_coins = result
+            .get("gas_coins")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| anyhow::anyhow!("Missing or invalid gas_coins"))?
+            .iter()
+            .map(|coin| {
+                let object_id = coin
+                    .get("objectId")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing or invalid gas coin objectId"))?;
+                let version = coin
+                    .get("version")
+                    .and_then(u64_value)
+                    .ok_or_else(|| anyhow::anyhow!("Missing or invalid gas coin version"))?;
+                let digest = coin
+                    .get("digest")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow::anyhow!("Missing or invalid gas coin digest"))?;
+                Ok(ObjectReference::new(
+                    object_id.parse()?,
+                    version,
+                    ObjectDigest::from_base58(digest)?,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if gas_coins.is_empty() {
+            return Err(anyhow::anyhow!("Gas station reserved no coins"));
+        }
+        Ok(Self {
+            sponsor_address: Address::from_str(sponsor_address)?,
+            reservation_id,
+            gas_coins,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sui_sdk_types::ObjectId;
+
+    #[test]
+    fn test_reservation_from_json() {
+        let digest = ObjectDigest::new([3; 32]);
+        let reservation = Reservation::from_json(&json!({
+            "sponsor_address": "0x5",
+            "reservation_id": 42,
+            "gas_coins": [
+                { "objectId": "0x7", "version": 9, "digest": digest.to_base58() },
+                { "objectId": "0x8", "version": "10", "digest": digest.to_base58() },
+            ],
+        }))
+        .unwrap();
+        assert_eq!(reservation.sponsor_address, Address::from_str("0x5").unwrap());
+        assert_eq!(reservation.reservation_id, 42);
+        assert_eq!(
+            reservation.gas_coins[1],
+            ObjectReference::new(ObjectId::from_str("0x8").unwrap(), 10, digest)
+        );
+
+        assert!(Reservation::from_json(&json!({
+            "sponsor_address": "0x5",
+            "reservation_id": 42,
+            "gas_coins": [],
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut sponsor = Sponsor {
+            url: "https://gas.example.com".to_string(),
+            token_env: Some("GAS_STATION_TOKEN".to_string()),
+            reserve_duration_secs: default_reserve_duration_secs(),
+        };
+        assert!(sponsor.validate().is_empty());
+        sponsor.url = "ftp://gas.example.com".to_string();
+        sponsor.token_env = Some(String::new());
+        sponsor.reserve_duration_secs = 0;
+        assert_eq!(sponsor.validate().len(), 3);
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sports results oracle module: signs the score and status of a fixture as
+//! reported by one of the configured sports APIs, for prediction markets to
+//! settle against.
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use rust_decimal::prelude::ToPrimitive;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::app::{encode_url_component, extract_field_from_json, extract_price, fetch_upstream};
+use crate::common::{
+    current_timestamp_ms, to_signed_response, IntentMessage, IntentScope, ProcessDataRequest,
+    ProcessedDataResponse,
+};
+use crate::config::check_http_url;
+use crate::formats::UpstreamFormat;
+use crate::oracle::{self, OracleModule, Signed};
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Placeholder in a provider's `url` replaced by the fixture id.
+const FIXTURE_PLACEHOLDER: &str = "{fixture_id}";
+
+/// A sports API results are read from, configured under `[sports.<name>]`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SportsProvider {
+    /// Fixture endpoint, with `{fixture_id}` where the id goes
+    pub url: String,
+    /// Paths of the scores and status in the response
+    pub home_score_field: String,
+    pub away_score_field: String,
+    pub status_field: String,
+    /// Statuses, as reported by the provider, of fixtures whose result is final
+    #[serde(default = "default_settled_statuses")]
+    pub settled_statuses: Vec<String>,
+    /// Environment variable holding the API key, if the provider needs one
+    #[serde(default)]
+    pub api_key_env: Option<String>,
+    /// How the API key is sent: "Bearer" or "x-api-key"
+    #[serde(default)]
+    pub api_key_config: Option<String>,
+}
+
+fn default_settled_statuses() -> Vec<String> {
+    vec!["FT".to_string(), "AET".to_string(), "PEN".to_string()]
+}
+
+/// Check every provider, returning the problems found.
+pub fn validate(providers: &HashMap<String, SportsProvider>) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (name, provider) in providers {
+        if !provider.url.contains(FIXTURE_PLACEHOLDER) {
+            problems.push(format!("sports.{}.url must contain {}", name, FIXTURE_PLACEHOLDER));
+        }
+        if let Err(e) = check_http_url(&provider.url.replace(FIXTURE_PLACEHOLDER, "0")) {
+            problems.push(format!("sports.{}.url {}", name, e));
+        }
+        if let Some(api_key_config) = &provider.api_key_config {
+            if !matches!(api_key_config.as_str(), "Bearer" | "x-api-key") {
+                problems.push(format!(
+                    "sports.{}.api_key_config must be \"Bearer\" or \"x-api-key\", got '{}'",
+                    name, api_key_config
+                ));
+            }
+        }
+        if provider.api_key_config.is_some() != provider.api_key_env.is_some() {
+            problems.push(format!(
+                "sports.{}.api_key_env and api_key_config must be set together",
+                name
+            ));
+        }
+    }
+    problems
+}
+
+/// Inner type T for IntentMessage<T>
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct SportsResultResponse {
+    pub provider: String,
+    pub fixture_id: String,
+    pub home_score: u32,
+    pub away_score: u32,
+    /// Status as reported by the provider
+    pub status: String,
+    /// Whether `status` is one of the provider's settled statuses, i.e. the
+    /// score is final
+    pub settled: bool,
+    pub timestamp_ms: u64,
+}
+
+/// Inner type T for ProcessDataRequest<T>
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SportsResultRequest {
+    /// Name of a configured provider
+    pub provider: String,
+    pub fixture_id: String,
+}
+
+/// Fetch the result of a fixture from a configured sports API and sign it.
+/// The route is served by [`SportsModule`].
+#[utoipa::path(
+    post,
+    path = "/sports_result",
+    tag = "sports",
+    request_body = ProcessDataRequest<SportsResultRequest>,
+    params(
+        ("x-consumer-key" = Option<String>, Header, description = "API key of the consumer, see `[consumers]`"),
+        ("x-sui-signature" = Option<String>, Header, description = "Sui wallet signature of the timestamp and body, instead of an API key"),
+        ("x-sui-timestamp-ms" = Option<u64>, Header, description = "Time the wallet signature was made"),
+    ),
+    responses(
+        (status = 200, description = "Signed result", body = ProcessedDataResponse<IntentMessage<SportsResultResponse>>),
+        (status = 400, description = "Unknown provider or failed fetch", body = crate::ErrorResponse),
+        (status = 401, description = "Missing or unknown consumer key or wallet", body = crate::ErrorResponse),
+        (status = 503, description = "Signing is paused", body = crate::ErrorResponse),
+    )
+)]
+pub async fn process_sports_result(
+    state: State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ProcessedDataResponse<IntentMessage<SportsResultResponse>>>, EnclaveError> {
+    oracle::handle::<SportsModule>(state, headers, body).await
+}
+
+/// Signed fixture results from configured sports APIs.
+pub struct SportsModule;
+
+impl OracleModule for SportsModule {
+    const NAME: &'static str = "sports";
+    const PATH: &'static str = "/sports_result";
+    const INTENT: IntentScope = IntentScope::SportsResult;
+
+    type Request = SportsResultRequest;
+    type Response = SportsResultResponse;
+
+    #[instrument(name = "sports_result", skip_all, fields(provider = %request.provider, fixture_id = %request.fixture_id))]
+    async fn process(
+        state: &AppState,
+        request: SportsResultRequest,
+    ) -> Result<Signed<SportsResultResponse>, EnclaveError> {
+        let config = state.config.load_full();
+        let provider = config.sports.get(&request.provider).ok_or_else(|| {
+            EnclaveError::GenericError(format!("Unknown sports provider: {}", request.provider))
+        })?;
+
+        let api_key = match &provider.api_key_env {
+            Some(env) => Some(std::env::var(env).map_err(|_| {
+                EnclaveError::GenericError(format!("API key variable {} is not set", env))
+            })?),
+            None => None,
+        };
+        let url = provider
+            .url
+            .replace(FIXTURE_PLACEHOLDER, &encode_url_component(&request.fixture_id));
+        // Results of one provider share a response shape
+        let json = fetch_upstream(
+            state,
+            &config,
+            &format!("sports:{}", request.provider),
+            &url,
+            api_key.as_deref().zip(provider.api_key_config.as_deref()),
+            &UpstreamFormat::default(),
+        )
+        .await?;
+
+        let home_score = extract_score(&json, &provider.home_score_field)?;
+        let away_score = extract_score(&json, &provider.away_score_field)?;
+        let status = match extract_field_from_json(&json, &provider.status_field)
+            .map_err(EnclaveError::GenericError)?
+        {
+            Value::String(status) => status.clone(),
+            Value::Number(status) => status.to_string(),
+            _ => {
+                return Err(EnclaveError::GenericError(format!(
+                    "Status field '{}' is neither a string nor a number",
+                    provider.status_field
+                )))
+            }
+        };
+        let settled = provider.settled_statuses.contains(&status);
+
+        let current_timestamp = current_timestamp_ms()?;
+        let kp = state.signing_keys.signer_for(Self::INTENT, current_timestamp, current_timestamp)?;
+        let response = SportsResultResponse {
+            provider: request.provider,
+            fixture_id: request.fixture_id,
+            home_score,
+            away_score,
+            status,
+            settled,
+            timestamp_ms: current_timestamp,
+        };
+        Ok(to_signed_response(&kp, response, current_timestamp, Self::INTENT))
+    }
+}
+
+/// Extract a whole, non-negative score.
+fn extract_score(json: &Value, field: &str) -> Result<u32, EnclaveError> {
+    let score = extract_price(json, field).map_err(EnclaveError::GenericError)?;
+    let whole = if score.fract().is_zero() { score.to_u32() } else { None };
+    whole.ok_or_else(|| {
+        EnclaveError::GenericError(format!("Score field '{}' is not a whole number: {}", field, score))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_extract_score() {
+        let json = json!({"home": 2, "away": "1", "bad": 1.5, "negative": -1});
+        assert_eq!(extract_score(&json, "home").unwrap(), 2);
+        assert_eq!(extract_score(&json, "away").unwrap(), 1);
+        assert!(extract_score(&json, "bad").is_err());
+        assert!(extract_score(&json, "negative").is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let provider = SportsProvider {
+            url: "https://api.example.com/fixtures/{fixture_id}".to_string(),
+            home_score_field: "score.home".to_string(),
+            away_score_field: "score.away".to_string(),
+            status_field: "status".to_string(),
+            settled_statuses: default_settled_statuses(),
+            api_key_env: None,
+            api_key_config: None,
+        };
+        let mut providers = HashMap::from([("ok".to_string(), provider.clone())]);
+        assert!(validate(&providers).is_empty());
+
+        providers.insert(
+            "bad".to_string(),
+            SportsProvider {
+                url: "https://api.example.com/fixtures".to_string(),
+                api_key_config: Some("Basic".to_string()),
+                ..provider
+            },
+        );
+        assert_eq!(validate(&providers).len(), 3);
+    }
+}
@@ -1,36 +1,225 @@
 use anyhow::Result;
-use fastcrypto::{ed25519::Ed25519KeyPair, traits::KeyPair};
+use arc_swap::ArcSwap;
+use fastcrypto::ed25519::Ed25519KeyPair;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{error, info};
 
-use crate::config::{load_config, Config};
+use crate::api_key_encryption::ApiKeyDecryption;
+use crate::batch::SignedPrices;
+use crate::breaker::BreakerOverrides;
+use crate::capacity::CapacityTracker;
+use crate::capture::UpstreamCaptures;
+use crate::common::{current_timestamp_ms, AttestationCache, IntentScope};
+use crate::config::{config_path, load_config, Config};
+use crate::consumers::{SeenSignatures, ServedLedger};
+use crate::handoff::receive_handoff;
+use crate::health::UpstreamHealth;
+use crate::history::{HistoryStore, PriceHistory};
+use crate::keys::{
+    load_or_generate_keypair, load_or_generate_scoped_keypairs, SealedKeyFiles, SigningKeys,
+};
+use crate::light_client::CheckpointVerifier;
+use crate::oauth::OAuthTokens;
+use crate::outbound::Outbound;
+use crate::pause::PauseSwitch;
+use crate::payments::ConsumedReceipts;
+use crate::persistence::open_store;
+use crate::push::PushState;
+use crate::schema::SchemaTracker;
+use crate::secrets::SecretCache;
 use crate::sui::SuiClientWrapper;
+use crate::transform::Transforms;
+use crate::upstream_circuit::UpstreamCircuits;
 
-/// App state, at minimum needs to maintain the ephemeral keypair.  
+/// How often the config file is checked for modifications.
+const CONFIG_WATCH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// App state, at minimum needs to maintain the ephemeral keypair.
 pub struct AppState {
-    /// Ephemeral keypair on boot
-    pub eph_kp: Ed25519KeyPair,
-    /// Configuration loaded from file
-    pub config: Config,
-    /// Sui client wrapper for oracle builder operations
-    pub sui_client: SuiClientWrapper,
+    /// Signing keypairs, ephemeral or restored from sealed storage, with any
+    /// rotation in progress
+    pub signing_keys: SigningKeys,
+    /// Configuration loaded from file, swappable at runtime on reload
+    pub config: ArcSwap<Config>,
+    /// Sui client wrapper for oracle builder operations, rebuilt on reload
+    pub sui_client: ArcSwap<SuiClientWrapper>,
+    /// HTTP client for upstream APIs and Sui RPC, rebuilt on reload
+    pub outbound: ArcSwap<Outbound>,
+    /// Recently signed observations per price feed, in the store selected at
+    /// startup
+    pub history: PriceHistory,
+    /// Throughput and in-flight requests of process_data
+    pub capacity: CapacityTracker,
+    /// Payment receipts already used for a signature
+    pub receipts: ConsumedReceipts,
+    /// Shape of each feed's last upstream response
+    pub schemas: SchemaTracker,
+    /// Feeds allowed to move past the circuit breaker once
+    pub breaker_overrides: BreakerOverrides,
+    /// Attestation document from the last refresh
+    pub attestation_cache: AttestationCache,
+    /// Pauses set through the admin API
+    pub pause: PauseSwitch,
+    /// Signed updates served to each authenticated consumer
+    pub served: ServedLedger,
+    /// Wallet signatures already accepted, to refuse replays
+    pub seen_signatures: SeenSignatures,
+    /// Last signed price of each feed, served to batch items that accept it
+    pub signed_prices: SignedPrices,
+    /// Gas price and Enclave object of push mode
+    pub push: PushState,
+    /// Last probe of each feed's `live_url`
+    pub upstream_health: UpstreamHealth,
+    /// Compiled WASM transform modules
+    pub transforms: Transforms,
+    /// Raw upstream responses kept for debugging, see `[capture]`
+    pub captures: UpstreamCaptures,
+    /// OAuth2 bearer tokens of upstream APIs
+    pub oauth_tokens: OAuthTokens,
+    /// API keys resolved from their off-chain secret references
+    pub secrets: SecretCache,
+    /// Key on-chain API keys are encrypted to
+    pub api_key_decryption: ApiKeyDecryption,
+    /// Circuits of upstream hosts failing repeatedly
+    pub upstream_circuits: UpstreamCircuits,
 }
 
 impl AppState {
-    /// Initialize AppState with generated keypair, loaded configuration and Sui client
-    pub async fn new() -> Result<Arc<AppState>> {
-        let eph_kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
-        let config = load_config()?;
-        
+    /// Initialize AppState with a generated or unsealed keypair, loaded
+    /// configuration and Sui client. With a handoff source configured, the
+    /// keypairs, rotation, retirements, history and unexpired pre-signed
+    /// prices are taken over from that instance instead.
+    pub async fn new(config: Config) -> Result<Arc<AppState>> {
+        let bundle = match &config.handoff.source_url {
+            Some(source_url) => Some(receive_handoff(&config, source_url).await?),
+            None => None,
+        };
+        let eph_kp = match bundle.as_ref().and_then(|bundle| bundle.key.as_ref()) {
+            Some(key) => key.keypair(&config.key)?,
+            None => load_or_generate_keypair(&config.key)?,
+        };
+        let scoped = match &bundle {
+            Some(bundle) => bundle.scoped_keypairs(&config.key)?,
+            None => load_or_generate_scoped_keypairs(&config.key)?,
+        };
+
+        let state = Self::from_keys(eph_kp, scoped, config).await?;
+        if let Some(bundle) = bundle {
+            bundle.restore_keys(&state.config.load().key, &state.signing_keys)?;
+            let restored = bundle.restore_signed_prices(&state.signed_prices, current_timestamp_ms()?);
+            info!("Restored {} pre-signed prices", restored);
+            state.history.import(bundle.history);
+        }
+        Ok(state)
+    }
+
+    /// Initialize AppState from an already loaded configuration
+    pub async fn from_config(eph_kp: Ed25519KeyPair, config: Config) -> Result<Arc<AppState>> {
+        let scoped = load_or_generate_scoped_keypairs(&config.key)?;
+        Self::from_keys(eph_kp, scoped, config).await
+    }
+
+    async fn from_keys(
+        eph_kp: Ed25519KeyPair,
+        scoped: HashMap<IntentScope, Ed25519KeyPair>,
+        config: Config,
+    ) -> Result<Arc<AppState>> {
         // Initialize Sui client with config values
-        let sui_client = SuiClientWrapper::new(
+        let outbound = Outbound::new(&config.outbound, &config.http_client).await?;
+        let sui_client = SuiClientWrapper::with_client(
+            outbound.client_for(&config.sui.rpc_url),
             &config.sui.rpc_url,
             config.sui.oracle_builder_package_id.clone(),
-        ).await?;
-        
-        Ok(Arc::new(AppState { 
-            eph_kp, 
-            config,
-            sui_client,
+        ).await?
+        .with_accepted_package_ids(config.sui.accepted_package_ids.clone())
+        .with_rate_limit(config.sui.requests_per_sec, config.sui.rate_limit_retries)
+        .with_light_client(CheckpointVerifier::from_config(
+            &config.sui.light_client,
+            outbound.client_for(&config.sui.light_client.checkpoint_url),
+        )?);
+        let store: Arc<dyn HistoryStore> = open_store(&config.persistence)?.into();
+        let history = PriceHistory::with_store(Arc::clone(&store));
+
+        let api_key_decryption = ApiKeyDecryption::derive(&eph_kp);
+
+        Ok(Arc::new(AppState {
+            signing_keys: SigningKeys::new(eph_kp)
+                .with_scoped_keys(scoped)
+                .with_sealed_files(SealedKeyFiles::open(&config.key)?)?,
+            config: ArcSwap::from_pointee(config),
+            sui_client: ArcSwap::from_pointee(sui_client),
+            outbound: ArcSwap::from_pointee(outbound),
+            history,
+            capacity: CapacityTracker::default(),
+            receipts: ConsumedReceipts::default(),
+            schemas: SchemaTracker::default(),
+            breaker_overrides: BreakerOverrides::default(),
+            attestation_cache: AttestationCache::default(),
+            pause: PauseSwitch::default(),
+            served: ServedLedger::with_store(Arc::clone(&store)),
+            seen_signatures: SeenSignatures::default(),
+            signed_prices: SignedPrices::default(),
+            push: PushState::with_store(store),
+            upstream_health: UpstreamHealth::default(),
+            transforms: Transforms::default(),
+            captures: UpstreamCaptures::default(),
+            oauth_tokens: OAuthTokens::default(),
+            secrets: SecretCache::default(),
+            api_key_decryption,
+            upstream_circuits: UpstreamCircuits::default(),
         }))
     }
-} 
\ No newline at end of file
+
+    /// Re-read the config file and atomically swap it in, together with a Sui
+    /// client built from the new values. The ephemeral keypair is kept.
+    pub async fn reload_config(&self) -> Result<()> {
+        let config = load_config()?;
+        let outbound = Outbound::new(&config.outbound, &config.http_client).await?;
+        let sui_client = SuiClientWrapper::with_client(
+            outbound.client_for(&config.sui.rpc_url),
+            &config.sui.rpc_url,
+            config.sui.oracle_builder_package_id.clone(),
+        ).await?
+        .with_accepted_package_ids(config.sui.accepted_package_ids.clone())
+        .with_rate_limit(config.sui.requests_per_sec, config.sui.rate_limit_retries)
+        .with_light_client(CheckpointVerifier::from_config(
+            &config.sui.light_client,
+            outbound.client_for(&config.sui.light_client.checkpoint_url),
+        )?);
+
+        self.outbound.store(Arc::new(outbound));
+        self.sui_client.store(Arc::new(sui_client));
+        self.config.store(Arc::new(config));
+        info!("Config reloaded");
+        Ok(())
+    }
+
+    /// Spawn a background task that polls the config file's modification time
+    /// and reloads it when it changes. Failed reloads keep the current config.
+    pub fn spawn_config_watcher(self: &Arc<Self>) {
+        let state = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut last_modified = config_modified_time();
+            let mut interval = tokio::time::interval(CONFIG_WATCH_INTERVAL);
+            loop {
+                interval.tick().await;
+                let modified = config_modified_time();
+                if modified.is_none() || modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+                if let Err(e) = state.reload_config().await {
+                    error!("Failed to reload config, keeping previous one: {:#}", e);
+                }
+            }
+        });
+    }
+}
+
+/// Modification time of the config file, if it can be determined.
+fn config_modified_time() -> Option<SystemTime> {
+    let path = config_path()?;
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,225 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Signed statistics over the prices a feed has been signed at: for each
+//! requested window, an exponential moving average and the realized
+//! volatility, computed from the observations in the history store.
+//!
+//! The EMA weighs the window's samples with alpha = 2 / (samples + 1),
+//! seeded with the oldest one. Realized volatility is the square root of the
+//! sum of squared log returns between consecutive samples, in basis points
+//! and not annualized, so it depends on how often the feed was signed.
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::common::{
+    current_timestamp_ms, to_signed_response, IntentMessage, IntentScope, ProcessDataRequest,
+    ProcessedDataResponse,
+};
+use crate::history::Observation;
+use crate::oracle::{self, OracleModule, Signed};
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Most windows computed in one request.
+pub const MAX_WINDOWS: usize = 8;
+
+/// Inner type T for ProcessDataRequest<T>
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PriceStatsRequest {
+    pub price_feed_id: String,
+    /// Lengths of the windows ending now, in milliseconds
+    pub windows_ms: Vec<u64>,
+}
+
+/// Statistics of one window.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct WindowStats {
+    pub window_ms: u64,
+    /// Signed prices in the window
+    pub samples: u64,
+    /// Exponential moving average, scaled like the feed's prices
+    pub ema: u64,
+    /// Realized volatility in basis points
+    pub volatility_bps: u64,
+}
+
+/// Inner type T for IntentMessage<T>
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct PriceStatsResponse {
+    pub price_feed_id: String,
+    pub windows: Vec<WindowStats>,
+    pub timestamp_ms: u64,
+}
+
+/// Compute and sign statistics over a feed's signed prices. The route is
+/// served by [`StatsModule`].
+#[utoipa::path(
+    post,
+    path = "/stats",
+    tag = "price feed",
+    request_body = ProcessDataRequest<PriceStatsRequest>,
+    params(
+        ("x-consumer-key" = Option<String>, Header, description = "API key of the consumer, see `[consumers]`"),
+        ("x-sui-signature" = Option<String>, Header, description = "Sui wallet signature of the timestamp and body, instead of an API key"),
+        ("x-sui-timestamp-ms" = Option<u64>, Header, description = "Time the wallet signature was made"),
+    ),
+    responses(
+        (status = 200, description = "Signed statistics", body = ProcessedDataResponse<IntentMessage<PriceStatsResponse>>),
+        (status = 400, description = "Invalid windows or too little history", body = crate::ErrorResponse),
+        (status = 401, description = "Missing or unknown consumer key or wallet", body = crate::ErrorResponse),
+        (status = 503, description = "Signing is paused, globally or for the feed", body = crate::ErrorResponse),
+    )
+)]
+pub async fn process_stats(
+    state: State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ProcessedDataResponse<IntentMessage<PriceStatsResponse>>>, EnclaveError> {
+    oracle::handle::<StatsModule>(state, headers, body).await
+}
+
+/// Signed EMA and realized volatility of price feeds.
+pub struct StatsModule;
+
+impl OracleModule for StatsModule {
+    const NAME: &'static str = "stats";
+    const PATH: &'static str = "/stats";
+    const INTENT: IntentScope = IntentScope::PriceStatistics;
+
+    type Request = PriceStatsRequest;
+    type Response = PriceStatsResponse;
+
+    #[instrument(name = "stats", skip_all, fields(price_feed_id = %request.price_feed_id))]
+    async fn process(
+        state: &AppState,
+        request: PriceStatsRequest,
+    ) -> Result<Signed<PriceStatsResponse>, EnclaveError> {
+        let config = state.config.load_full();
+        let price_feed_id = request.price_feed_id;
+        // Other series in the history store, like mirror deviations, are
+        // keyed by paths under the feed id and are not prices
+        if price_feed_id.contains('/') {
+            return Err(EnclaveError::GenericError(format!(
+                "Invalid price feed id: {}",
+                price_feed_id
+            )));
+        }
+        state.pause.check(&config, Some(&price_feed_id))?;
+        if request.windows_ms.is_empty() || request.windows_ms.len() > MAX_WINDOWS {
+            return Err(EnclaveError::GenericError(format!(
+                "Between 1 and {} windows can be requested, got {}",
+                MAX_WINDOWS,
+                request.windows_ms.len()
+            )));
+        }
+
+        let current_timestamp = current_timestamp_ms()?;
+        let longest = request.windows_ms.iter().copied().max().unwrap_or_default();
+        let observations = state
+            .history
+            .range(
+                &price_feed_id,
+                current_timestamp.saturating_sub(longest),
+                current_timestamp,
+            )
+            .map_err(|e| EnclaveError::GenericError(format!("Failed to read history: {:#}", e)))?;
+
+        let windows = request
+            .windows_ms
+            .iter()
+            .map(|&window_ms| {
+                let from_ms = current_timestamp.saturating_sub(window_ms);
+                let samples: Vec<u64> = observations
+                    .iter()
+                    .filter(|observation| observation.timestamp_ms >= from_ms)
+                    .map(|observation: &Observation| observation.price)
+                    .collect();
+                window_stats(window_ms, &samples).map_err(EnclaveError::GenericError)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let kp = state.signing_keys.signer_for(Self::INTENT, current_timestamp, current_timestamp)?;
+        let response = PriceStatsResponse {
+            price_feed_id,
+            windows,
+            timestamp_ms: current_timestamp,
+        };
+        Ok(to_signed_response(
+            &kp,
+            response,
+            current_timestamp,
+            Self::INTENT,
+        ))
+    }
+}
+
+/// Statistics of the prices signed in one window, oldest first.
+pub fn window_stats(window_ms: u64, prices: &[u64]) -> Result<WindowStats, String> {
+    if prices.len() < 2 {
+        return Err(format!(
+            "Window of {} ms has {} signed prices, at least 2 are needed",
+            window_ms,
+            prices.len()
+        ));
+    }
+    Ok(WindowStats {
+        window_ms,
+        samples: prices.len() as u64,
+        ema: ema(prices),
+        volatility_bps: realized_volatility_bps(prices)?,
+    })
+}
+
+/// EMA of `prices` with alpha = 2 / (len + 1), seeded with the first.
+fn ema(prices: &[u64]) -> u64 {
+    let alpha = Decimal::TWO / Decimal::from(prices.len() as u64 + 1);
+    let ema = prices[1..]
+        .iter()
+        .fold(Decimal::from(prices[0]), |ema, &price| {
+            ema + alpha * (Decimal::from(price) - ema)
+        });
+    // Stays between the smallest and largest price, so it fits
+    ema.round().to_u64().unwrap_or_default()
+}
+
+/// Square root of the sum of squared log returns, in basis points.
+fn realized_volatility_bps(prices: &[u64]) -> Result<u64, String> {
+    if prices.contains(&0) {
+        return Err("Volatility is undefined for a price of zero".to_string());
+    }
+    let sum_of_squares: f64 = prices
+        .windows(2)
+        .map(|pair| (pair[1] as f64 / pair[0] as f64).ln().powi(2))
+        .sum();
+    Ok((sum_of_squares.sqrt() * 10_000.0).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_stats() {
+        assert!(window_stats(1_000, &[100]).is_err());
+        assert!(window_stats(1_000, &[100, 0]).is_err());
+
+        let flat = window_stats(1_000, &[100, 100, 100]).unwrap();
+        assert_eq!((flat.ema, flat.volatility_bps, flat.samples), (100, 0, 3));
+
+        // alpha = 1/2: 100 -> 150 -> 175
+        let stats = window_stats(1_000, &[100, 200, 200]).unwrap();
+        assert_eq!(stats.ema, 175);
+        // ln(2) = 0.6931...
+        assert_eq!(stats.volatility_bps, 6_931);
+    }
+}
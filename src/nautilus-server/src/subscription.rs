@@ -0,0 +1,210 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! On-chain change subscription: with `sui.ws_url` set, a background task
+//! subscribes to transactions calling the oracle_builder package and drops
+//! the pre-signed price of every feed whose object they mutate, delete or
+//! wrap, so a feed updated or invalidated on chain stops being served from
+//! cache right away rather than when its price expires. PriceFeed objects
+//! themselves are read afresh for every signature.
+//!
+//! Prices signed while no subscription was active are all dropped once it
+//! is, as changes in between were missed. The URL and package are read on
+//! every (re)connection.
+
+use anyhow::{Context, Result};
+use futures_util::{SinkExt, StreamExt};
+use reqwest::Url;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{client_async_tls, MaybeTlsStream, WebSocketStream};
+use tracing::{info, warn};
+
+use crate::outbound::Outbound;
+use crate::AppState;
+
+/// How often the subscriber checks whether it has been enabled while disabled.
+const DISABLED_RECHECK: Duration = Duration::from_secs(5);
+
+/// Wait before reconnecting a failed or closed subscription.
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// Largest HTTP CONNECT response header accepted from an outbound proxy.
+const MAX_CONNECT_RESPONSE_BYTES: usize = 8 * 1024;
+
+/// Spawn the subscriber. It stays idle while `sui.ws_url` is unset.
+pub fn spawn_subscriber(state: &Arc<AppState>) {
+    let state = Arc::clone(state);
+    tokio::spawn(async move {
+        info!("On-chain change subscriber started");
+        loop {
+            let Some(ws_url) = state.config.load().sui.ws_url.clone() else {
+                tokio::time::sleep(DISABLED_RECHECK).await;
+                continue;
+            };
+            match subscribe(&state, &ws_url).await {
+                Ok(()) => warn!("Subscription at {} closed, reconnecting", ws_url),
+                Err(e) => warn!("Subscription at {} failed: {:#}", ws_url, e),
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+/// Subscribe to the oracle_builder package's transactions and invalidate
+/// the signed prices of the objects they touch until the connection ends.
+async fn subscribe(state: &AppState, ws_url: &str) -> Result<()> {
+    let mut ws = connect(&state.outbound.load_full(), ws_url).await?;
+    let package_id = state.config.load().sui.oracle_builder_package_id.clone();
+    let request = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "suix_subscribeTransaction",
+        "params": [{ "MoveFunction": { "package": package_id, "module": "oracle_builder", "function": null } }],
+    });
+    ws.send(Message::Text(request.to_string()))
+        .await
+        .context("Failed to send subscription request")?;
+
+    while let Some(message) = ws.next().await {
+        let text = match message.context("Failed to read from subscription")? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let message: Value =
+            serde_json::from_str(&text).context("Failed to parse subscription message")?;
+        if let Some(error) = message.get("error") {
+            return Err(anyhow::anyhow!("Sui RPC error: {}", error));
+        }
+        match message.pointer("/params/result") {
+            Some(effects) => {
+                for object_id in touched_objects(effects) {
+                    if state.signed_prices.invalidate(&object_id) {
+                        info!(
+                            "Feed {} changed on chain, dropped its signed price",
+                            object_id
+                        );
+                    }
+                }
+            }
+            // The subscription is confirmed
+            None if message.get("result").is_some() => {
+                state.signed_prices.clear();
+                info!("Subscribed to oracle_builder transactions at {}", ws_url);
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+/// Open the WebSocket, through the outbound route of its host if any.
+async fn connect(
+    outbound: &Outbound,
+    ws_url: &str,
+) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
+    let url = Url::parse(ws_url).with_context(|| format!("Invalid WebSocket URL {}", ws_url))?;
+    let host = url.host_str().context("WebSocket URL has no host")?;
+    let port = url
+        .port_or_known_default()
+        .context("WebSocket URL has no port")?;
+    let stream = match outbound.proxy_for(host) {
+        Some(proxy_url) => {
+            let proxy = format!(
+                "{}:{}",
+                proxy_url.host_str().unwrap_or_default(),
+                proxy_url.port_or_known_default().unwrap_or_default()
+            );
+            let mut stream = TcpStream::connect(&proxy)
+                .await
+                .with_context(|| format!("Failed to reach outbound proxy {}", proxy))?;
+            http_connect(&mut stream, host, port).await?;
+            stream
+        }
+        None => TcpStream::connect((host, port))
+            .await
+            .with_context(|| format!("Failed to reach {}:{}", host, port))?,
+    };
+    let (ws, _) = client_async_tls(ws_url, stream)
+        .await
+        .context("WebSocket handshake failed")?;
+    Ok(ws)
+}
+
+/// Open a tunnel to `host:port` through the HTTP proxy `stream` is
+/// connected to.
+async fn http_connect(stream: &mut TcpStream, host: &str, port: u16) -> Result<()> {
+    let request = format!(
+        "CONNECT {0}:{1} HTTP/1.1\r\nHost: {0}:{1}\r\n\r\n",
+        host, port
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read the response header byte by byte so nothing past it is consumed
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_CONNECT_RESPONSE_BYTES {
+            return Err(anyhow::anyhow!(
+                "Outbound proxy CONNECT response is too large"
+            ));
+        }
+        response.push(
+            stream
+                .read_u8()
+                .await
+                .context("Outbound proxy closed the tunnel")?,
+        );
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    if status_line.split_whitespace().nth(1) != Some("200") {
+        return Err(anyhow::anyhow!(
+            "Outbound proxy refused CONNECT: {}",
+            status_line
+        ));
+    }
+    Ok(())
+}
+
+/// Ids of the objects a transaction's effects mutated, deleted or wrapped.
+pub fn touched_objects(effects: &Value) -> Vec<String> {
+    let ids = |field: &str, pointer: &str| -> Vec<String> {
+        effects
+            .get(field)
+            .and_then(|objects| objects.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|object| object.pointer(pointer)?.as_str().map(str::to_string))
+            .collect()
+    };
+    let mut objects = ids("mutated", "/reference/objectId");
+    objects.extend(ids("deleted", "/objectId"));
+    objects.extend(ids("wrapped", "/objectId"));
+    objects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_touched_objects() {
+        let effects = json!({
+            "status": { "status": "success" },
+            "created": [{ "owner": "Immutable", "reference": { "objectId": "0xc" } }],
+            "mutated": [
+                { "owner": { "Shared": { "initial_shared_version": 3 } }, "reference": { "objectId": "0xa" } },
+                { "owner": { "AddressOwner": "0x1" }, "reference": { "objectId": "0xb" } },
+            ],
+            "deleted": [{ "objectId": "0xd", "version": 4 }],
+            "wrapped": [{ "objectId": "0xe", "version": 4 }],
+        });
+        assert_eq!(touched_objects(&effects), vec!["0xa", "0xb", "0xd", "0xe"]);
+        assert!(touched_objects(&json!({})).is_empty());
+    }
+}
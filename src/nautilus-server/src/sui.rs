@@ -1,149 +1,850 @@
 use anyhow::{Context, Result};
-use reqwest::Client;
+use fastcrypto::encoding::{Base64, Encoding};
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, StatusCode};
 use serde_json::{json, Value};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use sui_sdk_types::{Address, ObjectDigest, ObjectId, ObjectReference, StructTag, Transaction, UserSignature};
+use tracing::{instrument, warn};
 
-use crate::types::PriceFeed;
+use crate::light_client::CheckpointVerifier;
+use crate::telemetry;
+
+use crate::types::{PriceFeed, PriceFeedObject, Receipt, ReserveAccount, ReserveConfig, WeatherFeed};
+
+/// Object id of the shared `Clock`
+const SUI_CLOCK_ID: &str = "0x6";
+
+/// Most objects a single `sui_multiGetObjects` call may ask for
+const MULTI_GET_MAX_OBJECTS: usize = 50;
+
+/// Page size of `suix_getDynamicFields` and `suix_getOwnedObjects`
+const LIST_PAGE_LIMIT: usize = 50;
+
+/// JSON-RPC error code of fullnodes that rate limit a call
+const RATE_LIMITED_CODE: i64 = -32050;
+
+/// Wait before retrying a rate-limited call that came without `Retry-After`,
+/// doubled for every further retry
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Options of `sui_getObject` and `sui_multiGetObjects`. PriceFeed objects
+/// are decoded from their BCS, other objects from their rendered content.
+/// The previous transaction locates the checkpoint a PriceFeed is verified
+/// against.
+fn object_options() -> Value {
+    json!({
+        "showType": true,
+        "showOwner": true,
+        "showPreviousTransaction": true,
+        "showDisplay": false,
+        "showContent": true,
+        "showBcs": true,
+        "showStorageRebate": false
+    })
+}
+
+/// The `data` of a `sui_getObject` result, or its error
+fn object_data(result: &Value) -> Result<Value> {
+    if let Some(error) = result.get("error") {
+        return Err(anyhow::anyhow!("Object error: {}", error));
+    }
+    result
+        .get("data")
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("No data in result"))
+}
+
+/// A u64 the RPC may render as a string
+pub(crate) fn u64_value(value: &Value) -> Option<u64> {
+    match value {
+        Value::String(s) => s.parse().ok(),
+        v => v.as_u64(),
+    }
+}
+
+/// A SUI coin, as `suix_getCoins` lists it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GasCoin {
+    pub object_ref: ObjectReference,
+    pub balance: u64,
+}
+
+/// A `PushLease` object: the instance holding it and until when
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushLease {
+    pub holder: String,
+    pub expires_at_ms: u64,
+    pub initial_shared_version: u64,
+}
+
+/// Gas a transaction used, in MIST
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GasUsed {
+    pub computation_cost: u64,
+    pub storage_cost: u64,
+    pub storage_rebate: u64,
+}
+
+/// Check the `effects` of a dry-run or executed transaction report success,
+/// returning the gas it used
+pub(crate) fn check_effects(effects: &Value) -> Result<GasUsed> {
+    let status = effects
+        .pointer("/status/status")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow::anyhow!("Missing transaction status"))?;
+    if status != "success" {
+        let error = effects.pointer("/status/error").and_then(|v| v.as_str()).unwrap_or(status);
+        return Err(anyhow::anyhow!("Transaction failed: {}", error));
+    }
+    let cost = |name: &str| {
+        effects
+            .pointer(&format!("/gasUsed/{}", name))
+            .and_then(u64_value)
+            .ok_or_else(|| anyhow::anyhow!("Missing or invalid gasUsed.{}", name))
+    };
+    Ok(GasUsed {
+        computation_cost: cost("computationCost")?,
+        storage_cost: cost("storageCost")?,
+        storage_rebate: cost("storageRebate")?,
+    })
+}
+
+/// Client-side token bucket RPC calls take from, holding at most a second's
+/// worth of requests. A rate limit response pauses it for every call.
+struct Throttle {
+    /// Requests per second, unlimited if unset
+    rate: Option<f64>,
+    state: Mutex<ThrottleState>,
+}
+
+struct ThrottleState {
+    tokens: f64,
+    refilled_at: Instant,
+    paused_until: Option<Instant>,
+}
+
+impl Throttle {
+    fn new(requests_per_sec: Option<u32>) -> Self {
+        let rate = requests_per_sec.map(f64::from);
+        Self {
+            rate,
+            state: Mutex::new(ThrottleState {
+                tokens: rate.unwrap_or_default(),
+                refilled_at: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Wait until a pause is over and a token is available, and take it
+    async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().expect("throttle lock poisoned");
+                let now = Instant::now();
+                match state.paused_until {
+                    Some(until) if until > now => until - now,
+                    _ => {
+                        let Some(rate) = self.rate else { return };
+                        let refill = now.duration_since(state.refilled_at).as_secs_f64() * rate;
+                        state.tokens = (state.tokens + refill).min(rate);
+                        state.refilled_at = now;
+                        if state.tokens >= 1.0 {
+                            state.tokens -= 1.0;
+                            return;
+                        }
+                        Duration::from_secs_f64((1.0 - state.tokens) / rate)
+                    }
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Hold every call back for `delay` and empty the bucket
+    fn pause(&self, delay: Duration) {
+        let mut state = self.state.lock().expect("throttle lock poisoned");
+        let until = Instant::now() + delay;
+        state.paused_until = Some(state.paused_until.map_or(until, |paused| paused.max(until)));
+        state.tokens = 0.0;
+    }
+}
 
 /// Wrapper around HTTP client for Sui RPC operations
 pub struct SuiClientWrapper {
     client: Client,
     rpc_url: String,
     oracle_builder_package_id: String,
+    /// Further package ids whose oracle_builder object types are accepted
+    accepted_package_ids: Vec<String>,
+    throttle: Throttle,
+    /// Retries of a rate-limited call before it fails
+    rate_limit_retries: u32,
+    /// Verifier of PriceFeed objects, with `sui.light_client` enabled
+    light_client: Option<CheckpointVerifier>,
 }
 
 impl SuiClientWrapper {
     /// Initialize a new SuiClientWrapper with the given RPC URL and package ID
     pub async fn new(rpc_url: &str, oracle_builder_package_id: String) -> Result<Self> {
-        let client = Client::new();
+        Self::with_client(Client::new(), rpc_url, oracle_builder_package_id).await
+    }
 
+    /// Initialize a SuiClientWrapper that sends RPC calls through `client`
+    pub async fn with_client(
+        client: Client,
+        rpc_url: &str,
+        oracle_builder_package_id: String,
+    ) -> Result<Self> {
         Ok(Self {
             client,
             rpc_url: rpc_url.to_string(),
             oracle_builder_package_id,
+            accepted_package_ids: Vec::new(),
+            throttle: Throttle::new(None),
+            rate_limit_retries: 0,
+            light_client: None,
         })
     }
 
-    /// Fetch a PriceFeed object from the Sui network by its address
-    pub async fn fetch_price_feed(&self, price_feed_address: &str) -> Result<PriceFeed> {
+    /// Send at most `requests_per_sec` RPC calls a second, and retry calls
+    /// the fullnode rate limits up to `retries` times
+    pub fn with_rate_limit(mut self, requests_per_sec: Option<u32>, retries: u32) -> Self {
+        self.throttle = Throttle::new(requests_per_sec);
+        self.rate_limit_retries = retries;
+        self
+    }
+
+    /// Also accept objects whose types are defined by `package_ids`, such as
+    /// the original package of an upgraded oracle_builder
+    pub fn with_accepted_package_ids(mut self, package_ids: Vec<String>) -> Self {
+        self.accepted_package_ids = package_ids;
+        self
+    }
+
+    /// Only use PriceFeed objects `verifier` finds in certified checkpoints
+    pub fn with_light_client(mut self, verifier: Option<CheckpointVerifier>) -> Self {
+        self.light_client = verifier;
+        self
+    }
+
+    /// Every accepted type of the oracle_builder struct `name`, that of the
+    /// configured package first
+    fn object_types(&self, name: &str) -> Vec<String> {
+        std::iter::once(&self.oracle_builder_package_id)
+            .chain(&self.accepted_package_ids)
+            .map(|package_id| format!("{}::oracle_builder::{}", package_id, name))
+            .collect()
+    }
+
+    /// Check that the `data` of an object is of an accepted type of the
+    /// oracle_builder struct `name`
+    fn check_object_type(&self, data: &Value, name: &str) -> Result<()> {
+        let object_type = data
+            .get("type")
+            .and_then(|t| t.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Missing object type"))?;
+        let expected_types = self.object_types(name);
+        if !expected_types.iter().any(|expected| expected == object_type) {
+            return Err(anyhow::anyhow!(
+                "Expected {} type {}, got {}",
+                name,
+                expected_types.join(" or "),
+                object_type
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that a certified object is of an accepted type of the
+    /// oracle_builder struct `name`
+    fn check_struct_type(&self, struct_tag: &StructTag, name: &str) -> Result<()> {
+        let accepted = std::iter::once(&self.oracle_builder_package_id)
+            .chain(&self.accepted_package_ids)
+            .any(|package_id| Address::from_hex(package_id).is_ok_and(|address| address == struct_tag.address));
+        if !accepted
+            || struct_tag.module.as_str() != "oracle_builder"
+            || struct_tag.name.as_str() != name
+            || !struct_tag.type_params.is_empty()
+        {
+            return Err(anyhow::anyhow!(
+                "Expected {} type {}, got {}",
+                name,
+                self.object_types(name).join(" or "),
+                struct_tag
+            ));
+        }
+        Ok(())
+    }
+
+    /// Dry-run check that the RPC endpoint is reachable and that the configured
+    /// oracle_builder package exists on it
+    pub async fn check_package(&self) -> Result<()> {
         let request_body = json!({
             "jsonrpc": "2.0",
             "id": 1,
             "method": "sui_getObject",
             "params": [
-                price_feed_address,
-                {
-                    "showType": true,
-                    "showOwner": true,
-                    "showPreviousTransaction": false,
-                    "showDisplay": false,
-                    "showContent": true,
-                    "showBcs": false,
-                    "showStorageRebate": false
-                }
+                self.oracle_builder_package_id,
+                { "showType": true }
             ]
         });
 
-        // Send HTTP request to Sui RPC
-        let response = self
+        let response_body: Value = self
             .client
             .post(&self.rpc_url)
             .header("Content-Type", "application/json")
             .json(&request_body)
             .send()
             .await
-            .context("Failed to send request to Sui RPC")?;
-
-        let response_body: Value = response
+            .with_context(|| format!("Failed to reach Sui RPC at {}", self.rpc_url))?
             .json()
             .await
             .context("Failed to parse response from Sui RPC")?;
 
+        if let Some(error) = response_body.get("error") {
+            return Err(anyhow::anyhow!("Sui RPC error: {}", error));
+        }
+
+        let object_type = response_body
+            .pointer("/result/data/type")
+            .and_then(|t| t.as_str())
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "oracle_builder package {} not found",
+                    self.oracle_builder_package_id
+                )
+            })?;
+
+        if object_type != "package" {
+            return Err(anyhow::anyhow!(
+                "{} is not a package, got object of type {}",
+                self.oracle_builder_package_id,
+                object_type
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Fetch an object with its type and content, returning the `data` of
+    /// the `sui_getObject` result
+    async fn get_object(&self, object_id: &str) -> Result<Value> {
+        let request_body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "sui_getObject",
+            "params": [object_id, object_options()]
+        });
+        let result = self.call(&request_body).await?;
+        object_data(&result)
+    }
+
+    /// Fetch several objects in `sui_multiGetObjects` calls of at most
+    /// [`MULTI_GET_MAX_OBJECTS`], returning the `data` of each in order of
+    /// `object_ids`. A missing object fails on its own, a failed call fails
+    /// all the objects it asked for.
+    async fn multi_get_objects(&self, object_ids: &[&str]) -> Vec<Result<Value>> {
+        let mut objects = Vec::with_capacity(object_ids.len());
+        for chunk in object_ids.chunks(MULTI_GET_MAX_OBJECTS) {
+            let request_body = json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "sui_multiGetObjects",
+                "params": [chunk, object_options()]
+            });
+            let results = self.call(&request_body).await.and_then(|result| match result {
+                Value::Array(results) if results.len() == chunk.len() => Ok(results),
+                _ => Err(anyhow::anyhow!("Unexpected sui_multiGetObjects result")),
+            });
+            match results {
+                Ok(results) => objects.extend(results.iter().map(object_data)),
+                Err(e) => {
+                    let e = format!("{:#}", e);
+                    objects.extend(chunk.iter().map(|_| Err(anyhow::anyhow!("{}", e))));
+                }
+            }
+        }
+        objects
+    }
+
+    /// Send a JSON-RPC request and return its `result`. Rate-limited calls
+    /// are retried after the fullnode's `Retry-After`, or a backoff.
+    pub(crate) async fn call(&self, request_body: &Value) -> Result<Value> {
+        let mut retries = 0;
+        let mut response_body = loop {
+            self.throttle.acquire().await;
+            let response = self
+                .client
+                .post(&self.rpc_url)
+                .header("Content-Type", "application/json")
+                .json(request_body)
+                .send()
+                .await
+                .context("Failed to send request to Sui RPC")?;
+
+            let status = response.status();
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs);
+            let response_body: Result<Value> = response
+                .json()
+                .await
+                .context("Failed to parse response from Sui RPC");
+            let error_code = response_body
+                .as_ref()
+                .ok()
+                .and_then(|body| body.pointer("/error/code"))
+                .and_then(|code| code.as_i64());
+            if status != StatusCode::TOO_MANY_REQUESTS && error_code != Some(RATE_LIMITED_CODE) {
+                break response_body?;
+            }
+
+            telemetry::metrics().sui_rate_limited.add(1, &[]);
+            if retries >= self.rate_limit_retries {
+                return Err(anyhow::anyhow!(
+                    "Sui RPC rate limited the call, gave up after {} retries",
+                    retries
+                ));
+            }
+            let delay = retry_after.unwrap_or(RATE_LIMIT_BACKOFF * 2u32.pow(retries));
+            warn!("Sui RPC rate limited the call, retrying in {:?}", delay);
+            self.throttle.pause(delay);
+            retries += 1;
+        };
+
         // Check for RPC errors
         if let Some(error) = response_body.get("error") {
             return Err(anyhow::anyhow!("Sui RPC error: {}", error));
         }
 
-        // Extract the result
-        let result = response_body
-            .get("result")
-            .ok_or_else(|| anyhow::anyhow!("No result in RPC response"))?;
+        response_body
+            .get_mut("result")
+            .map(Value::take)
+            .ok_or_else(|| anyhow::anyhow!("No result in RPC response"))
+    }
 
-        let data = result
-            .get("data")
-            .ok_or_else(|| anyhow::anyhow!("No data in result"))?;
+    /// Fetch a payment receipt and check it is of `receipt_type`
+    #[instrument(name = "receipt_fetch", skip(self))]
+    pub async fn fetch_receipt(&self, receipt_id: &str, receipt_type: &str) -> Result<Receipt> {
+        let data = self.get_object(receipt_id).await?;
 
-        // Verify object type
         let object_type = data
             .get("type")
             .and_then(|t| t.as_str())
             .ok_or_else(|| anyhow::anyhow!("Missing object type"))?;
-
-        let expected_type = format!("{}::oracle_builder::PriceFeed", self.oracle_builder_package_id);
-        if object_type != expected_type {
+        if object_type != receipt_type {
             return Err(anyhow::anyhow!(
-                "Expected PriceFeed type {}, got {}",
-                expected_type,
+                "Expected receipt type {}, got {}",
+                receipt_type,
                 object_type
             ));
         }
 
-        // Extract content
-        let content = data
-            .get("content")
-            .ok_or_else(|| anyhow::anyhow!("Missing content"))?;
-
-        let fields = content
-            .get("fields")
+        let fields = data
+            .pointer("/content/fields")
             .ok_or_else(|| anyhow::anyhow!("Missing fields in content"))?;
 
-        // Parse fields
-        let oracle_id = fields
-            .get("oracle_id")
+        let price_feed_id = fields
+            .get("price_feed_id")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing or invalid oracle_id field"))?
+            .ok_or_else(|| anyhow::anyhow!("Missing or invalid price_feed_id field"))?
             .to_string();
 
-        let is_valid = fields
-            .get("is_valid")
-            .and_then(|v| v.as_bool())
-            .ok_or_else(|| anyhow::anyhow!("Missing or invalid is_valid field"))?;
+        // u64 fields are rendered as strings by the RPC
+        let amount_mist = fields
+            .get("amount")
+            .and_then(|v| match v {
+                Value::String(s) => s.parse().ok(),
+                v => v.as_u64(),
+            })
+            .ok_or_else(|| anyhow::anyhow!("Missing or invalid amount field"))?;
+        let timestamp_ms = fields.get("timestamp_ms").and_then(|v| match v {
+            Value::String(s) => s.parse().ok(),
+            v => v.as_u64(),
+        });
 
-        let api_key = fields
-            .get("api_key")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string());
+        Ok(Receipt {
+            receipt_id: receipt_id.to_string(),
+            price_feed_id,
+            amount_mist,
+            timestamp_ms,
+        })
+    }
 
-        let api_key_config = fields
-            .get("api_key_config")
-            .and_then(|v| v.as_str())
-            .map(|s| s.to_string());
+    /// Current time of the on-chain `Clock` object, in milliseconds
+    #[instrument(name = "clock_fetch", skip(self))]
+    pub async fn fetch_clock_ms(&self) -> Result<u64> {
+        let data = self.get_object(SUI_CLOCK_ID).await?;
+        data.pointer("/content/fields/timestamp_ms")
+            .and_then(|v| match v {
+                Value::String(s) => s.parse().ok(),
+                v => v.as_u64(),
+            })
+            .ok_or_else(|| anyhow::anyhow!("Missing or invalid timestamp_ms in Clock"))
+    }
+
+    /// Fetch a PriceFeed object from the Sui network by its address
+    #[instrument(name = "sui_fetch", skip(self))]
+    pub async fn fetch_price_feed(&self, price_feed_address: &str) -> Result<PriceFeed> {
+        let data = self.get_object(price_feed_address).await?;
+        self.price_feed(price_feed_address, &data).await
+    }
 
-        let underlying_url = fields
-            .get("underlying_url")
+    /// Fetch several PriceFeed objects in as few RPC calls as possible,
+    /// returning them in order of `price_feed_addresses`
+    #[instrument(name = "sui_fetch", skip_all, fields(count = price_feed_addresses.len()))]
+    pub async fn fetch_price_feeds(&self, price_feed_addresses: &[&str]) -> Vec<Result<PriceFeed>> {
+        let objects = self.multi_get_objects(price_feed_addresses).await;
+        let mut price_feeds = Vec::with_capacity(objects.len());
+        for (address, data) in price_feed_addresses.iter().zip(objects) {
+            price_feeds.push(match data {
+                Ok(data) => self.price_feed(address, &data).await,
+                Err(e) => Err(e),
+            });
+        }
+        price_feeds
+    }
+
+    /// Every PriceFeed of an oracle, found among the oracle object's dynamic
+    /// object fields and the objects it owns, along with their ids and
+    /// sorted by id. Only feeds whose `oracle_id` is the oracle are listed.
+    #[instrument(name = "sui_list", skip(self))]
+    pub async fn list_feeds_for_oracle(&self, oracle_id: &str) -> Result<Vec<(String, PriceFeed)>> {
+        let feed_types = self.object_types("PriceFeed");
+        let mut feed_ids = Vec::new();
+
+        let dynamic_fields = self
+            .list_pages("suix_getDynamicFields", |cursor| {
+                json!([oracle_id, cursor, LIST_PAGE_LIMIT])
+            })
+            .await
+            .context("Failed to list dynamic fields")?;
+        // Plain dynamic fields hold their value inline, so only dynamic
+        // object fields are PriceFeed objects of their own
+        for field in dynamic_fields {
+            let string = |name: &str| field.get(name).and_then(|v| v.as_str());
+            let is_feed = string("objectType").is_some_and(|t| feed_types.iter().any(|feed_type| feed_type == t));
+            if string("type") != Some("DynamicObject") || !is_feed {
+                continue;
+            }
+            if let Some(object_id) = string("objectId") {
+                feed_ids.push(object_id.to_string());
+            }
+        }
+
+        for feed_type in &feed_types {
+            let owned = self
+                .list_pages("suix_getOwnedObjects", |cursor| {
+                    json!([
+                        oracle_id,
+                        { "filter": { "StructType": feed_type }, "options": { "showType": true } },
+                        cursor,
+                        LIST_PAGE_LIMIT
+                    ])
+                })
+                .await
+                .context("Failed to list owned objects")?;
+            for object in owned {
+                if let Some(object_id) = object.pointer("/data/objectId").and_then(|v| v.as_str()) {
+                    feed_ids.push(object_id.to_string());
+                }
+            }
+        }
+
+        feed_ids.sort();
+        feed_ids.dedup();
+        let ids: Vec<&str> = feed_ids.iter().map(String::as_str).collect();
+        let price_feeds = self.fetch_price_feeds(&ids).await;
+        let mut feeds = Vec::with_capacity(feed_ids.len());
+        for (feed_id, price_feed) in feed_ids.iter().zip(price_feeds) {
+            let price_feed =
+                price_feed.with_context(|| format!("Failed to fetch price feed {}", feed_id))?;
+            if price_feed.oracle_id == oracle_id {
+                feeds.push((feed_id.clone(), price_feed));
+            }
+        }
+        Ok(feeds)
+    }
+
+    /// Follow the cursor of a paginated listing `method` to its end,
+    /// returning the `data` of every page
+    async fn list_pages(&self, method: &str, params: impl Fn(Value) -> Value) -> Result<Vec<Value>> {
+        let mut items = Vec::new();
+        let mut cursor = Value::Null;
+        loop {
+            let request_body = json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params(cursor),
+            });
+            let mut page = self.call(&request_body).await?;
+            match page.get_mut("data").map(Value::take) {
+                Some(Value::Array(data)) => items.extend(data),
+                _ => return Err(anyhow::anyhow!("No data in {} result", method)),
+            }
+            let has_next_page = page.get("hasNextPage").and_then(|v| v.as_bool()) == Some(true);
+            cursor = page.get_mut("nextCursor").map(Value::take).unwrap_or(Value::Null);
+            if !has_next_page || cursor.is_null() {
+                return Ok(items);
+            }
+        }
+    }
+
+    /// The PriceFeed `object_id` whose `data` was fetched: as certified in a
+    /// checkpoint with the light client enabled, as the fullnode returned it
+    /// otherwise
+    async fn price_feed(&self, object_id: &str, data: &Value) -> Result<PriceFeed> {
+        let Some(light_client) = &self.light_client else {
+            return self.parse_price_feed(data);
+        };
+        let object = light_client
+            .verify_object(self, object_id, data)
+            .await
+            .with_context(|| format!("Failed to verify PriceFeed {}", object_id))?;
+        let move_struct = object
+            .as_struct()
+            .ok_or_else(|| anyhow::anyhow!("{} is not a Move object", object_id))?;
+        self.check_struct_type(move_struct.object_type(), "PriceFeed")?;
+        let price_feed = PriceFeedObject::from_bcs(move_struct.contents())
+            .context("PriceFeed object does not match the expected Move struct layout")?;
+        Ok(price_feed.into_price_feed(object.version(), object.digest().to_base58()))
+    }
+
+    /// Parse the `data` of a PriceFeed object
+    fn parse_price_feed(&self, data: &Value) -> Result<PriceFeed> {
+        self.check_object_type(data, "PriceFeed")?;
+
+        // The RPC renders the version as a string
+        let version = data
+            .get("version")
+            .and_then(|v| match v {
+                Value::String(s) => s.parse().ok(),
+                v => v.as_u64(),
+            })
+            .ok_or_else(|| anyhow::anyhow!("Missing or invalid object version"))?;
+        let digest = data
+            .get("digest")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing or invalid underlying_url field"))?
+            .ok_or_else(|| anyhow::anyhow!("Missing or invalid object digest"))?
             .to_string();
 
-        let response_field = fields
-            .get("response_field")
+        // Decode the Move struct from its BCS rather than the rendered fields
+        let bcs_bytes = data
+            .pointer("/bcs/bcsBytes")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing or invalid response_field field"))?
-            .to_string();
+            .ok_or_else(|| anyhow::anyhow!("Missing BCS of PriceFeed object"))?;
+        let bytes = Base64::decode(bcs_bytes)
+            .map_err(|e| anyhow::anyhow!("Invalid BCS encoding of PriceFeed object: {}", e))?;
+        let object = PriceFeedObject::from_bcs(&bytes)
+            .context("PriceFeed object does not match the expected Move struct layout")?;
+
+        Ok(object.into_price_feed(version, digest))
+    }
+
+    /// Reference gas price of the current epoch, in MIST
+    pub async fn reference_gas_price(&self) -> Result<u64> {
+        let request_body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "suix_getReferenceGasPrice",
+            "params": []
+        });
+        let result = self.call(&request_body).await?;
+        u64_value(&result).ok_or_else(|| anyhow::anyhow!("Invalid reference gas price {}", result))
+    }
+
+    /// Every SUI coin `owner` holds
+    pub async fn gas_coins(&self, owner: &Address) -> Result<Vec<GasCoin>> {
+        let owner = owner.to_string();
+        let coins = self
+            .list_pages("suix_getCoins", |cursor| {
+                json!([owner, "0x2::sui::SUI", cursor, LIST_PAGE_LIMIT])
+            })
+            .await
+            .context("Failed to list gas coins")?;
+        coins
+            .iter()
+            .map(|coin| {
+                let string = |name: &str| {
+                    coin.get(name)
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| anyhow::anyhow!("Missing or invalid coin {}", name))
+                };
+                let number = |name: &str| {
+                    coin.get(name)
+                        .and_then(u64_value)
+                        .ok_or_else(|| anyhow::anyhow!("Missing or invalid coin {}", name))
+                };
+                Ok(GasCoin {
+                    object_ref: ObjectReference::new(
+                        string("coinObjectId")?.parse()?,
+                        number("version")?,
+                        ObjectDigest::from_base58(string("digest")?)?,
+                    ),
+                    balance: number("balance")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Version at which the shared object `object_id` was shared, which
+    /// transactions name it by
+    pub async fn initial_shared_version(&self, object_id: &ObjectId) -> Result<u64> {
+        let data = self.get_object(&object_id.to_string()).await?;
+        data.pointer("/owner/Shared/initial_shared_version")
+            .and_then(u64_value)
+            .ok_or_else(|| anyhow::anyhow!("{} is not a shared object", object_id))
+    }
 
-        let live_url = fields
-            .get("live_url")
+    /// Fetch the `PushLease` object `object_id`
+    pub async fn fetch_push_lease(&self, object_id: &ObjectId) -> Result<PushLease> {
+        let data = self.get_object(&object_id.to_string()).await?;
+        self.check_object_type(&data, "PushLease")?;
+        let fields = data
+            .pointer("/content/fields")
+            .ok_or_else(|| anyhow::anyhow!("Missing fields in content"))?;
+        Ok(PushLease {
+            holder: fields
+                .get("holder")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing or invalid holder field"))?
+                .to_string(),
+            expires_at_ms: fields
+                .get("expires_at_ms")
+                .and_then(u64_value)
+                .ok_or_else(|| anyhow::anyhow!("Missing or invalid expires_at_ms field"))?,
+            initial_shared_version: data
+                .pointer("/owner/Shared/initial_shared_version")
+                .and_then(u64_value)
+                .ok_or_else(|| anyhow::anyhow!("{} is not a shared object", object_id))?,
+        })
+    }
+
+    /// Execute `transaction` without committing it, returning the gas it
+    /// would use. Fails if it would abort.
+    pub async fn dry_run(&self, transaction: &Transaction) -> Result<GasUsed> {
+        let request_body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "sui_dryRunTransactionBlock",
+            "params": [Base64::encode(bcs::to_bytes(transaction)?)]
+        });
+        let result = self.call(&request_body).await?;
+        let effects = result
+            .get("effects")
+            .ok_or_else(|| anyhow::anyhow!("No effects in dry run result"))?;
+        check_effects(effects).context("Dry run failed")
+    }
+
+    /// Execute `transaction` with its `signatures` and wait for the fullnode
+    /// to apply it, returning its digest. Fails if it aborted.
+    pub async fn execute(&self, transaction: &Transaction, signatures: &[UserSignature]) -> Result<String> {
+        let signatures: Vec<String> = signatures.iter().map(UserSignature::to_base64).collect();
+        let request_body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "sui_executeTransactionBlock",
+            "params": [
+                Base64::encode(bcs::to_bytes(transaction)?),
+                signatures,
+                { "showEffects": true },
+                "WaitForLocalExecution"
+            ]
+        });
+        let result = self.call(&request_body).await?;
+        let digest = result
+            .get("digest")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow::anyhow!("Missing or invalid live_url field"))?
-            .to_string();
+            .ok_or_else(|| anyhow::anyhow!("No digest in execution result"))?;
+        let effects = result
+            .get("effects")
+            .ok_or_else(|| anyhow::anyhow!("No effects in execution result"))?;
+        check_effects(effects).with_context(|| format!("Transaction {} failed", digest))?;
+        Ok(digest.to_string())
+    }
+
+    /// Fetch a WeatherFeed object from the Sui network by its address
+    #[instrument(name = "sui_fetch", skip(self))]
+    pub async fn fetch_weather_feed(&self, weather_feed_address: &str) -> Result<WeatherFeed> {
+        let data = self.get_object(weather_feed_address).await?;
+        self.check_object_type(&data, "WeatherFeed")?;
+
+        let fields = data
+            .pointer("/content/fields")
+            .ok_or_else(|| anyhow::anyhow!("Missing fields in content"))?;
+        let string_field = |name: &str| {
+            fields
+                .get(name)
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+                .ok_or_else(|| anyhow::anyhow!("Missing or invalid {} field", name))
+        };
+
+        Ok(WeatherFeed {
+            oracle_id: string_field("oracle_id")?,
+            is_valid: fields
+                .get("is_valid")
+                .and_then(|v| v.as_bool())
+                .ok_or_else(|| anyhow::anyhow!("Missing or invalid is_valid field"))?,
+            location: string_field("location")?,
+            provider: string_field("provider")?,
+            field: string_field("field")?,
+            metric: string_field("metric")?,
+            api_key: string_field("api_key").ok(),
+            api_key_config: string_field("api_key_config").ok(),
+        })
+    }
+
+    /// Fetch a ReserveConfig object and the accounts it lists
+    #[instrument(name = "sui_fetch", skip(self))]
+    pub async fn fetch_reserve_config(&self, reserve_config_address: &str) -> Result<ReserveConfig> {
+        let data = self.get_object(reserve_config_address).await?;
+        self.check_object_type(&data, "ReserveConfig")?;
+
+        let fields = data
+            .pointer("/content/fields")
+            .ok_or_else(|| anyhow::anyhow!("Missing fields in content"))?;
+        let string_field = |fields: &Value, name: &str| {
+            fields
+                .get(name)
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+                .ok_or_else(|| anyhow::anyhow!("Missing or invalid {} field", name))
+        };
+
+        let accounts = fields
+            .get("accounts")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| anyhow::anyhow!("Missing or invalid accounts field"))?
+            .iter()
+            .map(|account| {
+                // Nested structs are rendered with their own type and fields
+                let account = account.get("fields").unwrap_or(account);
+                Ok(ReserveAccount {
+                    label: string_field(account, "label")?,
+                    url: string_field(account, "url")?,
+                    balance_field: string_field(account, "balance_field")?,
+                    api_key: string_field(account, "api_key").ok(),
+                    api_key_config: string_field(account, "api_key_config").ok(),
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
 
-        Ok(PriceFeed {
-            oracle_id,
-            is_valid,
-            api_key,
-            api_key_config,
-            underlying_url,
-            response_field,
-            live_url,
+        Ok(ReserveConfig {
+            oracle_id: string_field(fields, "oracle_id")?,
+            is_valid: fields
+                .get("is_valid")
+                .and_then(|v| v.as_bool())
+                .ok_or_else(|| anyhow::anyhow!("Missing or invalid is_valid field"))?,
+            asset: string_field(fields, "asset")?,
+            accounts,
         })
     }
 }
@@ -162,6 +863,27 @@ mod tests {
         assert!(client.is_ok());
     }
 
+    #[tokio::test]
+    async fn test_throttle() {
+        let throttle = Throttle::new(Some(20));
+        let start = Instant::now();
+        for _ in 0..20 {
+            throttle.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(40));
+        // The bucket is empty, the next token takes 1/20s to refill
+        throttle.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(40));
+
+        let throttle = Throttle::new(None);
+        throttle.pause(Duration::from_millis(50));
+        let start = Instant::now();
+        throttle.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(40));
+        throttle.acquire().await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
     // Note: This test requires a valid price feed address on the network
     // Replace with an actual price feed address to test the functionality
     #[tokio::test]
@@ -180,7 +902,7 @@ mod tests {
         match result {
             Ok(price_feed) => {
                 println!("Fetched price feed: {:?}", price_feed);
-                assert!(price_feed.oracle_id.len() > 0);
+                assert!(!price_feed.oracle_id.is_empty());
             }
             Err(e) => {
                 println!("Expected error for dummy address: {}", e);
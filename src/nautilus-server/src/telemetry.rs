@@ -0,0 +1,194 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use axum::body::Body;
+use axum::http::{HeaderName, Request};
+use axum::Router;
+use opentelemetry::metrics::{Counter, Gauge, Histogram};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry::{global, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::{self as sdktrace, Tracer};
+use opentelemetry_sdk::{runtime, Resource};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use std::time::Duration;
+use tower::ServiceBuilder;
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_http::trace::TraceLayer;
+use tracing::{info, Span};
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, EnvFilter, Registry};
+
+/// Header carrying the id of a request, taken from the client if it sent one.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// OpenTelemetry export settings.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Telemetry {
+    /// OTLP gRPC collector, e.g. `http://localhost:4317`. Traces and metrics
+    /// are only exported when set.
+    #[serde(default)]
+    pub otlp_endpoint: Option<String>,
+    /// `service.name` resource attribute
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// Seconds between metric exports
+    #[serde(default = "default_metrics_interval_secs")]
+    pub metrics_interval_secs: u64,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: default_service_name(),
+            metrics_interval_secs: default_metrics_interval_secs(),
+        }
+    }
+}
+
+fn default_service_name() -> String {
+    "nautilus-server".to_string()
+}
+
+fn default_metrics_interval_secs() -> u64 {
+    60
+}
+
+type OtelLayer = Option<OpenTelemetryLayer<Registry, Tracer>>;
+
+/// Handle to install the span exporter once the config is loaded, since the
+/// config loading itself logs.
+static OTEL_LAYER: OnceLock<reload::Handle<OtelLayer, Registry>> = OnceLock::new();
+
+/// Log to stdout, filtered by `RUST_LOG` and at `info` by default.
+pub fn init() {
+    let (otel, handle) = reload::Layer::new(None);
+    tracing_subscriber::registry()
+        .with(otel)
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    let _ = OTEL_LAYER.set(handle);
+}
+
+/// Start exporting spans and metrics over OTLP if an endpoint is configured.
+/// Must be called from within the tokio runtime, after [`init`].
+pub fn export(config: &Telemetry) -> Result<()> {
+    let Some(endpoint) = &config.otlp_endpoint else {
+        return Ok(());
+    };
+    let resource = Resource::new([KeyValue::new("service.name", config.service_name.clone())]);
+
+    let tracer_provider = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+        .with_trace_config(sdktrace::Config::default().with_resource(resource.clone()))
+        .install_batch(runtime::Tokio)
+        .context("Failed to start OTLP trace export")?;
+    let tracer = tracer_provider.tracer("nautilus-server");
+    global::set_tracer_provider(tracer_provider);
+
+    let meter_provider = opentelemetry_otlp::new_pipeline()
+        .metrics(runtime::Tokio)
+        .with_exporter(opentelemetry_otlp::new_exporter().tonic().with_endpoint(endpoint))
+        .with_resource(resource)
+        .with_period(Duration::from_secs(config.metrics_interval_secs))
+        .build()
+        .context("Failed to start OTLP metric export")?;
+    global::set_meter_provider(meter_provider);
+
+    let handle = OTEL_LAYER
+        .get()
+        .ok_or_else(|| anyhow::anyhow!("Tracing must be initialized before export"))?;
+    handle
+        .modify(|layer| *layer = Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+        .context("Failed to install OTLP span layer")?;
+
+    info!("Exporting traces and metrics to {}", endpoint);
+    Ok(())
+}
+
+/// Export spans still buffered, before exiting.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Instruments recorded while serving requests.
+pub struct Metrics {
+    /// `process_data` calls by outcome
+    pub process_data_requests: Counter<u64>,
+    /// `process_data` latency in milliseconds
+    pub process_data_duration_ms: Histogram<f64>,
+    /// Sui RPC latency in milliseconds
+    pub sui_fetch_duration_ms: Histogram<f64>,
+    /// Sui RPC calls the fullnode rate limited, retried or not
+    pub sui_rate_limited: Counter<u64>,
+    /// Upstream price API latency in milliseconds, by response status
+    pub upstream_duration_ms: Histogram<f64>,
+    /// Material changes in the shape of upstream responses, by feed
+    pub upstream_schema_changes: Counter<u64>,
+    /// Probes of each feed's `live_url`, by feed and outcome
+    pub upstream_probes: Counter<u64>,
+    /// `live_url` probe latency in milliseconds, by feed
+    pub upstream_probe_duration_ms: Histogram<f64>,
+    /// Prices queued for pushing on chain
+    pub push_queue_depth: Gauge<u64>,
+}
+
+/// Instruments of the global meter, created on first use so they are bound
+/// to the provider installed by [`export`].
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(|| {
+        let meter = global::meter("nautilus-server");
+        Metrics {
+            process_data_requests: meter.u64_counter("nautilus.process_data.requests").init(),
+            process_data_duration_ms: meter
+                .f64_histogram("nautilus.process_data.duration")
+                .with_unit("ms")
+                .init(),
+            sui_fetch_duration_ms: meter.f64_histogram("nautilus.sui_fetch.duration").with_unit("ms").init(),
+            sui_rate_limited: meter.u64_counter("nautilus.sui.rate_limited").init(),
+            upstream_duration_ms: meter.f64_histogram("nautilus.upstream.duration").with_unit("ms").init(),
+            upstream_schema_changes: meter.u64_counter("nautilus.upstream.schema_changes").init(),
+            upstream_probes: meter.u64_counter("nautilus.upstream.probes").init(),
+            upstream_probe_duration_ms: meter
+                .f64_histogram("nautilus.upstream.probe_duration")
+                .with_unit("ms")
+                .init(),
+            push_queue_depth: meter.u64_gauge("nautilus.push.queue_depth").init(),
+        }
+    })
+}
+
+/// Give every request an `x-request-id`, run its handler in a span carrying
+/// that id and echo the id in the response, so everything logged while
+/// serving a request can be tied back to it.
+pub fn trace_requests(router: Router) -> Router {
+    let header = HeaderName::from_static(REQUEST_ID_HEADER);
+    router.layer(
+        ServiceBuilder::new()
+            .layer(SetRequestIdLayer::new(header.clone(), MakeRequestUuid))
+            .layer(TraceLayer::new_for_http().make_span_with(request_span))
+            .layer(PropagateRequestIdLayer::new(header)),
+    )
+}
+
+fn request_span(request: &Request<Body>) -> Span {
+    let request_id = request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        path = %request.uri().path(),
+        request_id,
+    )
+}
@@ -0,0 +1,263 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! WASM transformation hooks, for upstreams whose price takes more than a
+//! field path and a scaling expression to compute. A feed's `transform`
+//! section names a WASM module, whose SHA-256 must equal the
+//! `transform_hash` of the feed's on-chain object: the code computing a
+//! signed price is then pinned on chain next to the enclave image's PCRs.
+//!
+//! Modules run in the wasmi interpreter without any imports, so they cannot
+//! reach the network, clock or filesystem, and with bounded fuel and memory.
+//! They export:
+//!
+//! - `memory`;
+//! - `alloc(len: i32) -> i32`, returning where `len` bytes of input can be
+//!   written;
+//! - `transform(ptr: i32, len: i32) -> i64`, given the upstream document as
+//!   JSON and returning its output packed as `ptr << 32 | len`: the price as a
+//!   UTF-8 decimal string such as `"1234.5"`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use fastcrypto::encoding::{Encoding, Hex};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use wasmi::{Engine, Linker, Module, Store, StoreLimits, StoreLimitsBuilder};
+
+/// Longest output accepted from a module, a decimal number has no use for more.
+const MAX_OUTPUT_BYTES: usize = 256;
+
+/// WASM module transforming a feed's upstream document into its price.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Transform {
+    /// Path of the `.wasm` module
+    pub module: PathBuf,
+    /// Instructions the module may execute per price, roughly
+    #[serde(default = "default_fuel")]
+    pub fuel: u64,
+    /// Most linear memory the module may grow to
+    #[serde(default = "default_max_memory_bytes")]
+    pub max_memory_bytes: usize,
+}
+
+fn default_fuel() -> u64 {
+    10_000_000
+}
+
+fn default_max_memory_bytes() -> usize {
+    16 * 1024 * 1024
+}
+
+impl Transform {
+    /// Check the config of the feed at `prefix`, returning the problems found.
+    pub fn validate(&self, prefix: &str) -> Vec<String> {
+        let mut problems = Vec::new();
+        if !self.module.is_file() {
+            problems.push(format!(
+                "{}.transform.module {} is not a file",
+                prefix,
+                self.module.display()
+            ));
+        }
+        if self.fuel == 0 {
+            problems.push(format!("{}.transform.fuel must be positive", prefix));
+        }
+        problems
+    }
+}
+
+/// Compiled transform modules, keyed by the hex SHA-256 of their bytes.
+pub struct Transforms {
+    engine: Engine,
+    modules: Mutex<HashMap<String, Arc<Module>>>,
+}
+
+impl Default for Transforms {
+    fn default() -> Self {
+        let mut config = wasmi::Config::default();
+        config.consume_fuel(true);
+        Self {
+            engine: Engine::new(&config),
+            modules: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl Transforms {
+    /// Run `transform` on an upstream document. The module is read again on
+    /// every call and refused unless its hash is `pinned_hash`, the hex
+    /// `transform_hash` of the feed's on-chain object.
+    pub fn run(
+        &self,
+        transform: &Transform,
+        pinned_hash: Option<&str>,
+        document: &Value,
+    ) -> Result<Decimal, String> {
+        let pinned_hash = pinned_hash
+            .ok_or("the feed's on-chain object pins no transform_hash")?
+            .trim_start_matches("0x")
+            .to_ascii_lowercase();
+        let bytes = std::fs::read(&transform.module)
+            .map_err(|e| format!("failed to read {}: {}", transform.module.display(), e))?;
+        let hash = Hex::encode(Sha256::digest(&bytes));
+        if hash != pinned_hash {
+            return Err(format!(
+                "module {} has hash {}, the feed pins {}",
+                transform.module.display(),
+                hash,
+                pinned_hash
+            ));
+        }
+        let module = self.module(&hash, &bytes)?;
+        let input = serde_json::to_vec(document).map_err(|e| e.to_string())?;
+        let output = self.call(transform, &module, &input)?;
+        let text =
+            std::str::from_utf8(&output).map_err(|_| "module output is not UTF-8".to_string())?;
+        Decimal::from_str(text.trim())
+            .map_err(|e| format!("module output '{}' is not a number: {}", text, e))
+    }
+
+    fn module(&self, hash: &str, bytes: &[u8]) -> Result<Arc<Module>, String> {
+        let mut modules = self.modules.lock().unwrap();
+        if let Some(module) = modules.get(hash) {
+            return Ok(module.clone());
+        }
+        let module = Arc::new(
+            Module::new(&self.engine, bytes).map_err(|e| format!("invalid WASM module: {}", e))?,
+        );
+        modules.insert(hash.to_string(), module.clone());
+        Ok(module)
+    }
+
+    fn call(
+        &self,
+        transform: &Transform,
+        module: &Module,
+        input: &[u8],
+    ) -> Result<Vec<u8>, String> {
+        let trap = |e: wasmi::core::Trap| format!("module trapped: {}", e);
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(transform.max_memory_bytes)
+            .instances(1)
+            .build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.add_fuel(transform.fuel).map_err(|e| e.to_string())?;
+
+        let instance = Linker::<StoreLimits>::new(&self.engine)
+            .instantiate(&mut store, module)
+            .and_then(|instance| instance.start(&mut store))
+            .map_err(|e| format!("failed to instantiate module: {}", e))?;
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or("module exports no memory")?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&store, "alloc")
+            .map_err(|e| format!("module export alloc: {}", e))?;
+        let run = instance
+            .get_typed_func::<(i32, i32), i64>(&store, "transform")
+            .map_err(|e| format!("module export transform: {}", e))?;
+
+        let len = i32::try_from(input.len()).map_err(|_| "upstream document is too large")?;
+        let ptr = alloc.call(&mut store, len).map_err(trap)?;
+        memory
+            .write(&mut store, ptr as u32 as usize, input)
+            .map_err(|e| format!("failed to write input: {}", e))?;
+        let packed = run.call(&mut store, (ptr, len)).map_err(trap)? as u64;
+
+        let (out_ptr, out_len) = ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize);
+        if out_len > MAX_OUTPUT_BYTES {
+            return Err(format!(
+                "module output of {} bytes exceeds {}",
+                out_len, MAX_OUTPUT_BYTES
+            ));
+        }
+        let mut output = vec![0; out_len];
+        memory
+            .read(&store, out_ptr, &mut output)
+            .map_err(|e| format!("failed to read output: {}", e))?;
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::io::Write;
+
+    /// Returns its input unchanged.
+    const ECHO: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "alloc") (param i32) (result i32) i32.const 1024)
+        (func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+            (i64.or
+                (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                (i64.extend_i32_u (local.get $len)))))"#;
+
+    /// Never returns.
+    const SPIN: &str = r#"(module
+        (memory (export "memory") 1)
+        (func (export "alloc") (param i32) (result i32) i32.const 1024)
+        (func (export "transform") (param i32 i32) (result i64)
+            (loop $spin (br $spin))
+            i64.const 0))"#;
+
+    fn module_file(wat: &str) -> (tempfile::NamedTempFile, Transform, String) {
+        let bytes = wat::parse_str(wat).unwrap();
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&bytes).unwrap();
+        let transform = Transform {
+            module: file.path().to_path_buf(),
+            fuel: default_fuel(),
+            max_memory_bytes: default_max_memory_bytes(),
+        };
+        (file, transform, Hex::encode(Sha256::digest(&bytes)))
+    }
+
+    #[test]
+    fn test_run_transform() {
+        let transforms = Transforms::default();
+        let (_file, transform, hash) = module_file(ECHO);
+        assert_eq!(
+            transforms.run(&transform, Some(&hash), &json!(1234.5)),
+            Ok(Decimal::from_str("1234.5").unwrap())
+        );
+        assert_eq!(
+            transforms.run(
+                &transform,
+                Some(&format!("0x{}", hash.to_uppercase())),
+                &json!(7)
+            ),
+            Ok(Decimal::from(7))
+        );
+        // The echoed document is not a number.
+        assert!(transforms
+            .run(&transform, Some(&hash), &json!({"price": 1}))
+            .is_err());
+    }
+
+    #[test]
+    fn test_transform_pinning_and_limits() {
+        let transforms = Transforms::default();
+        let (_file, transform, hash) = module_file(ECHO);
+        assert!(transforms.run(&transform, None, &json!(1)).is_err());
+        assert!(transforms
+            .run(&transform, Some(&"0".repeat(64)), &json!(1))
+            .unwrap_err()
+            .contains("pins"));
+
+        let (_file, transform, hash_spin) = module_file(SPIN);
+        assert!(transforms
+            .run(&transform, Some(&hash_spin), &json!(1))
+            .unwrap_err()
+            .contains("trapped"));
+        assert_ne!(hash, hash_spin);
+    }
+}
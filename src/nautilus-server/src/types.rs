@@ -1,3 +1,4 @@
+use fastcrypto::encoding::{Encoding, Hex};
 use serde::{Deserialize, Serialize};
 
 /// PriceFeed type that matches the on-chain Move struct exactly
@@ -10,4 +11,287 @@ pub struct PriceFeed {
     pub underlying_url: String,
     pub response_field: String,
     pub live_url: String,
-} 
\ No newline at end of file
+    /// Path of the upstream's own timestamp for the price, if it reports one
+    pub timestamp_field: Option<String>,
+    /// Hex SHA-256 of the WASM module the feed's price is computed with, for
+    /// feeds with a `transform`
+    pub transform_hash: Option<String>,
+    /// Version of the object the fields were read at. Reported by the RPC
+    /// next to the Move struct rather than part of it.
+    pub version: u64,
+    /// Base58 digest of the object at `version`
+    pub digest: String,
+}
+
+/// Fields of the on-chain PriceFeed Move struct, field for field and in
+/// order. PriceFeed objects are decoded into it, so a change of the Move
+/// struct fails loudly until it is mirrored here. Objects of the first
+/// version of the struct end after `live_url`, and later versions appended
+/// `timestamp_field` and then `transform_hash`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriceFeedObject {
+    /// `UID` of the object
+    pub id: [u8; 32],
+    pub oracle_id: [u8; 32],
+    pub is_valid: bool,
+    pub api_key: Option<String>,
+    pub api_key_config: Option<String>,
+    pub underlying_url: String,
+    pub response_field: String,
+    pub live_url: String,
+    /// Empty or absent when the upstream reports no timestamp of its own
+    pub timestamp_field: Option<String>,
+    /// Empty or absent for feeds without a `transform`
+    pub transform_hash: Option<String>,
+}
+
+/// BCS layout of the fields every version of the PriceFeed struct starts
+/// with, up to and including `live_url`
+type PriceFeedBaseLayout = (
+    [u8; 32],
+    [u8; 32],
+    bool,
+    Option<String>,
+    Option<String>,
+    String,
+    String,
+    String,
+);
+
+impl PriceFeedObject {
+    /// Decode the BCS of a PriceFeed object of any version of the struct,
+    /// newest first. Each layout must consume every byte, so at most one
+    /// matches.
+    pub fn from_bcs(bytes: &[u8]) -> Result<Self, bcs::Error> {
+        if let Ok((base, timestamp_field, transform_hash)) =
+            bcs::from_bytes::<(PriceFeedBaseLayout, String, String)>(bytes)
+        {
+            return Ok(Self::from_layout(
+                base,
+                Some(timestamp_field),
+                Some(transform_hash),
+            ));
+        }
+        if let Ok((base, timestamp_field)) = bcs::from_bytes::<(PriceFeedBaseLayout, String)>(bytes)
+        {
+            return Ok(Self::from_layout(base, Some(timestamp_field), None));
+        }
+        let base = bcs::from_bytes::<PriceFeedBaseLayout>(bytes)?;
+        Ok(Self::from_layout(base, None, None))
+    }
+
+    /// BCS of the object in the oldest version of the struct holding its
+    /// fields
+    pub fn to_bcs(&self) -> Result<Vec<u8>, bcs::Error> {
+        let base: PriceFeedBaseLayout = (
+            self.id,
+            self.oracle_id,
+            self.is_valid,
+            self.api_key.clone(),
+            self.api_key_config.clone(),
+            self.underlying_url.clone(),
+            self.response_field.clone(),
+            self.live_url.clone(),
+        );
+        match (&self.timestamp_field, &self.transform_hash) {
+            (timestamp_field, Some(transform_hash)) => bcs::to_bytes(&(
+                base,
+                timestamp_field.clone().unwrap_or_default(),
+                transform_hash,
+            )),
+            (Some(timestamp_field), None) => bcs::to_bytes(&(base, timestamp_field)),
+            (None, None) => bcs::to_bytes(&base),
+        }
+    }
+
+    fn from_layout(
+        base: PriceFeedBaseLayout,
+        timestamp_field: Option<String>,
+        transform_hash: Option<String>,
+    ) -> Self {
+        let (
+            id,
+            oracle_id,
+            is_valid,
+            api_key,
+            api_key_config,
+            underlying_url,
+            response_field,
+            live_url,
+        ) = base;
+        Self {
+            id,
+            oracle_id,
+            is_valid,
+            api_key,
+            api_key_config,
+            underlying_url,
+            response_field,
+            live_url,
+            timestamp_field,
+            transform_hash,
+        }
+    }
+
+    /// The PriceFeed these fields make up at object `version` and `digest`
+    pub fn into_price_feed(self, version: u64, digest: String) -> PriceFeed {
+        let non_empty = |s: Option<String>| s.filter(|s| !s.is_empty());
+        PriceFeed {
+            oracle_id: format!("0x{}", Hex::encode(self.oracle_id)),
+            is_valid: self.is_valid,
+            api_key: self.api_key,
+            api_key_config: self.api_key_config,
+            underlying_url: self.underlying_url,
+            response_field: self.response_field,
+            live_url: self.live_url,
+            timestamp_field: non_empty(self.timestamp_field),
+            transform_hash: non_empty(self.transform_hash),
+            version,
+            digest,
+        }
+    }
+}
+
+/// WeatherFeed type that matches the on-chain Move struct
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeatherFeed {
+    pub oracle_id: String,
+    pub is_valid: bool,
+    /// Location the feed reports on, substituted for `{location}` in
+    /// `provider`
+    pub location: String,
+    /// Upstream URL of the weather provider
+    pub provider: String,
+    /// Path of the value in the provider's response
+    pub field: String,
+    /// What the value measures, `temperature` or `precipitation`
+    pub metric: String,
+    pub api_key: Option<String>,
+    pub api_key_config: Option<String>,
+}
+
+/// ReserveConfig type that matches the on-chain Move struct: the accounts
+/// whose balances make up an issuer's reserves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReserveConfig {
+    pub oracle_id: String,
+    pub is_valid: bool,
+    /// Symbol of the asset held, e.g. `USD`
+    pub asset: String,
+    pub accounts: Vec<ReserveAccount>,
+}
+
+/// ReserveAccount type that matches the on-chain Move struct: an address or
+/// account at an exchange or custodian and the API reporting its balance
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReserveAccount {
+    pub label: String,
+    /// Balance endpoint of the exchange or custodian for this account
+    pub url: String,
+    /// Path of the balance in the response
+    pub balance_field: String,
+    pub api_key: Option<String>,
+    pub api_key_config: Option<String>,
+}
+
+/// Payment receipt object, as defined by the payment package configured in
+/// `payments.receipt_type`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Receipt {
+    pub receipt_id: String,
+    /// Feed the payment is for
+    pub price_feed_id: String,
+    pub amount_mist: u64,
+    /// When the payment was made, for receipt types that record it
+    pub timestamp_ms: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_feed_object_versions() {
+        let object = PriceFeedObject {
+            id: [1; 32],
+            oracle_id: [2; 32],
+            is_valid: true,
+            api_key: None,
+            api_key_config: Some("header:X-Api-Key".to_string()),
+            underlying_url: "https://api.example.com/price".to_string(),
+            response_field: "price".to_string(),
+            live_url: "https://api.example.com/price".to_string(),
+            timestamp_field: None,
+            transform_hash: None,
+        };
+        // Objects of the first version of the struct end after `live_url`
+        let bytes = object.to_bcs().unwrap();
+        assert_eq!(PriceFeedObject::from_bcs(&bytes).unwrap(), object);
+        let feed = PriceFeedObject::from_bcs(&bytes)
+            .unwrap()
+            .into_price_feed(3, "digest".to_string());
+        assert_eq!(feed.timestamp_field, None);
+        assert_eq!(feed.transform_hash, None);
+
+        let with_timestamp = PriceFeedObject {
+            timestamp_field: Some("data.ts".to_string()),
+            ..object.clone()
+        };
+        let bytes = with_timestamp.to_bcs().unwrap();
+        assert_eq!(PriceFeedObject::from_bcs(&bytes).unwrap(), with_timestamp);
+
+        let with_transform = PriceFeedObject {
+            timestamp_field: Some(String::new()),
+            transform_hash: Some("ab".repeat(32)),
+            ..object.clone()
+        };
+        let bytes = with_transform.to_bcs().unwrap();
+        assert_eq!(PriceFeedObject::from_bcs(&bytes).unwrap(), with_transform);
+        let feed = PriceFeedObject::from_bcs(&bytes)
+            .unwrap()
+            .into_price_feed(3, "digest".to_string());
+        assert_eq!(feed.timestamp_field, None);
+        assert_eq!(feed.transform_hash, Some("ab".repeat(32)));
+
+        // Nor does a field cut short
+        let mut bytes = object.to_bcs().unwrap();
+        bytes.push(1);
+        assert!(PriceFeedObject::from_bcs(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_price_feed_object_layout_bytes() {
+        // BCS of objects created by each version of the Move struct, written
+        // out so a change to the decoding or the layouts can't go unnoticed
+        let base = format!("{}{}01000001750170016c", "01".repeat(32), "02".repeat(32));
+        let object = PriceFeedObject {
+            id: [1; 32],
+            oracle_id: [2; 32],
+            is_valid: true,
+            api_key: None,
+            api_key_config: None,
+            underlying_url: "u".to_string(),
+            response_field: "p".to_string(),
+            live_url: "l".to_string(),
+            timestamp_field: None,
+            transform_hash: None,
+        };
+        let with_timestamp = PriceFeedObject {
+            timestamp_field: Some("ts".to_string()),
+            ..object.clone()
+        };
+        let with_transform = PriceFeedObject {
+            transform_hash: Some("h".to_string()),
+            ..with_timestamp.clone()
+        };
+        for (object, hex) in [
+            (object, base.clone()),
+            (with_timestamp, format!("{}027473", base)),
+            (with_transform, format!("{}0274730168", base)),
+        ] {
+            let bytes = Hex::decode(&hex).unwrap();
+            assert_eq!(PriceFeedObject::from_bcs(&bytes).unwrap(), object);
+            assert_eq!(object.to_bcs().unwrap(), bytes);
+        }
+    }
+}
@@ -0,0 +1,196 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-upstream circuit breaker: after `failure_threshold` consecutive
+//! failures of an upstream host, requests to it fail fast for `open_secs`
+//! instead of each waiting out the timeout of a dead provider. Then a single
+//! request is let through as a probe; its success closes the circuit and its
+//! failure opens it again. Failures are transport errors and 5xx answers.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+use crate::EnclaveError;
+
+/// `[upstream_circuit]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UpstreamCircuit {
+    /// Consecutive failures opening the circuit of a host; 0 disables it
+    #[serde(default = "default_failure_threshold")]
+    pub failure_threshold: u32,
+    /// How long an open circuit fails requests before letting a probe through
+    #[serde(default = "default_open_secs")]
+    pub open_secs: u64,
+}
+
+impl Default for UpstreamCircuit {
+    fn default() -> Self {
+        Self {
+            failure_threshold: default_failure_threshold(),
+            open_secs: default_open_secs(),
+        }
+    }
+}
+
+fn default_failure_threshold() -> u32 {
+    5
+}
+
+fn default_open_secs() -> u64 {
+    30
+}
+
+impl UpstreamCircuit {
+    /// Check the config, returning the problems found.
+    pub fn validate(&self) -> Vec<String> {
+        if self.failure_threshold > 0 && self.open_secs == 0 {
+            vec!["upstream_circuit.open_secs must be greater than 0".to_string()]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Circuit {
+    consecutive_failures: u32,
+    /// Set while the circuit is open: requests before this time fail, the
+    /// first one after it is the probe
+    open_until_ms: Option<u64>,
+}
+
+/// Circuits by upstream host and port.
+#[derive(Default)]
+pub struct UpstreamCircuits {
+    circuits: Mutex<HashMap<String, Circuit>>,
+}
+
+impl UpstreamCircuits {
+    /// Refuse a request to `url` at `now_ms` while its host's circuit is
+    /// open. Once it has been open for `open_secs` the caller is let through
+    /// as the probe, and others are refused for another `open_secs` unless
+    /// the probe succeeds first.
+    pub fn check(
+        &self,
+        config: &UpstreamCircuit,
+        url: &str,
+        now_ms: u64,
+    ) -> Result<(), EnclaveError> {
+        if config.failure_threshold == 0 {
+            return Ok(());
+        }
+        let Some(host) = host_key(url) else {
+            return Ok(());
+        };
+        let mut circuits = self.circuits.lock().unwrap();
+        let Some(circuit) = circuits.get_mut(&host) else {
+            return Ok(());
+        };
+        match circuit.open_until_ms {
+            Some(open_until_ms) if now_ms < open_until_ms => {
+                Err(EnclaveError::UpstreamUnavailable {
+                    message: format!(
+                        "The circuit of upstream {} is open after {} consecutive failures",
+                        host, circuit.consecutive_failures
+                    ),
+                    retry_after_secs: Some((open_until_ms - now_ms).div_ceil(1000)),
+                })
+            }
+            Some(_) => {
+                info!("Probing upstream {} with its circuit half-open", host);
+                circuit.open_until_ms =
+                    Some(now_ms.saturating_add(config.open_secs.saturating_mul(1000)));
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Record the outcome of a request to `url` made at `now_ms`.
+    pub fn record(&self, config: &UpstreamCircuit, url: &str, success: bool, now_ms: u64) {
+        if config.failure_threshold == 0 {
+            return;
+        }
+        let Some(host) = host_key(url) else {
+            return;
+        };
+        let mut circuits = self.circuits.lock().unwrap();
+        if success {
+            if let Some(circuit) = circuits.remove(&host) {
+                if circuit.open_until_ms.is_some() {
+                    info!("Closed the circuit of upstream {}", host);
+                }
+            }
+            return;
+        }
+        let circuit = circuits.entry(host.clone()).or_default();
+        circuit.consecutive_failures = circuit.consecutive_failures.saturating_add(1);
+        // A failed probe opens the circuit again right away
+        if circuit.open_until_ms.is_some()
+            || circuit.consecutive_failures >= config.failure_threshold
+        {
+            if circuit.open_until_ms.is_none() {
+                warn!(
+                    "Opened the circuit of upstream {} after {} consecutive failures",
+                    host, circuit.consecutive_failures
+                );
+            }
+            circuit.open_until_ms =
+                Some(now_ms.saturating_add(config.open_secs.saturating_mul(1000)));
+        }
+    }
+}
+
+/// Host and port of `url`, the unit a circuit covers.
+fn host_key(url: &str) -> Option<String> {
+    let url = reqwest::Url::parse(url).ok()?;
+    Some(format!(
+        "{}:{}",
+        url.host_str()?,
+        url.port_or_known_default()?
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_circuit_opens_probes_and_closes() {
+        let config = UpstreamCircuit {
+            failure_threshold: 2,
+            open_secs: 10,
+        };
+        let circuits = UpstreamCircuits::default();
+        let url = "https://api.example.com/price?symbol=BTC";
+        circuits.record(&config, url, false, 0);
+        assert!(circuits.check(&config, url, 0).is_ok());
+        circuits.record(&config, url, false, 1_000);
+        let Err(EnclaveError::UpstreamUnavailable {
+            retry_after_secs, ..
+        }) = circuits.check(&config, url, 2_500)
+        else {
+            panic!("the circuit should be open");
+        };
+        assert_eq!(retry_after_secs, Some(9));
+        // Other hosts are unaffected
+        assert!(circuits
+            .check(&config, "https://other.example.com/price", 2_500)
+            .is_ok());
+
+        // One probe after open_secs, which fails and reopens the circuit
+        assert!(circuits.check(&config, url, 11_000).is_ok());
+        assert!(circuits.check(&config, url, 11_001).is_err());
+        circuits.record(&config, url, false, 11_500);
+        assert!(circuits.check(&config, url, 21_000).is_err());
+
+        // A successful probe closes it
+        assert!(circuits.check(&config, url, 21_500).is_ok());
+        circuits.record(&config, url, true, 21_600);
+        assert!(circuits.check(&config, url, 21_700).is_ok());
+        circuits.record(&config, url, false, 21_800);
+        assert!(circuits.check(&config, url, 21_900).is_ok());
+    }
+}
@@ -0,0 +1,274 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Bulk verification of relayed responses at `POST /verify/batch`, for
+//! indexers backfilling oracle data. Each response is re-encoded to the BCS
+//! bytes its intent was signed over and checked against every key this
+//! instance has signed with, whose activity windows are returned alongside.
+//!
+//! Key history starts when the instance does: keys it never held, or held
+//! only through an earlier instance, are unknown to it.
+
+use axum::extract::State;
+use axum::Json;
+use fastcrypto::ed25519::Ed25519Signature;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{ToFromBytes, VerifyingKey};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+use crate::app::PriceFeedResponse;
+use crate::common::{current_timestamp_ms, IntentMessage, IntentScope, ProcessDataRequest};
+use crate::fees::FeeQuoteResponse;
+use crate::keys::KeyPeriod;
+use crate::nft::NftFloorPriceResponse;
+use crate::rates::RateFeedResponse;
+use crate::reserves::ReserveResponse;
+use crate::retirement::KeyRetirement;
+use crate::sports::SportsResultResponse;
+use crate::stats::PriceStatsResponse;
+use crate::weather::WeatherResponse;
+use crate::wide::WidePriceFeedResponse;
+use crate::AppState;
+use crate::EnclaveError;
+
+/// A signed response as returned by the enclave. Only `response` and
+/// `signature` are needed; other fields are ignored.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RelayedResponse {
+    /// The signed intent message: `intent`, `timestamp_ms` and `data`
+    #[schema(value_type = Object)]
+    pub response: Value,
+    /// Signature in Hex
+    pub signature: String,
+}
+
+/// Inner type T for ProcessDataRequest<T>
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct VerifyBatchRequest {
+    pub items: Vec<RelayedResponse>,
+}
+
+/// Outcome of one item, in the order given.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct VerifyResult {
+    /// Whether the signature is valid for one of the known keys
+    pub valid: bool,
+    /// Key that made the signature, in Hex
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    /// Whether the response's timestamp lies in that key's window. Feeds
+    /// timestamped by their upstream or the Sui clock can be slightly off.
+    pub within_key_window: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// When a key signed, by the enclave's clock.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct KeyWindow {
+    /// Public key in Hex
+    pub public_key: String,
+    /// Unset for the key the instance started with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_from_ms: Option<u64>,
+    /// Unset while the key is still signing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_until_ms: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct VerifyBatchResponse {
+    pub results: Vec<VerifyResult>,
+    /// Every key this instance has signed with, oldest first
+    pub keys: Vec<KeyWindow>,
+}
+
+/// Verify many relayed responses against the enclave's key history.
+#[utoipa::path(
+    post,
+    path = "/verify/batch",
+    tag = "attestation",
+    request_body = ProcessDataRequest<VerifyBatchRequest>,
+    responses(
+        (status = 200, description = "Result of every item and the key history", body = VerifyBatchResponse),
+        (status = 400, description = "Empty or oversized batch", body = crate::ErrorResponse),
+    )
+)]
+pub async fn verify_batch(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<ProcessDataRequest<VerifyBatchRequest>>,
+) -> Result<Json<VerifyBatchResponse>, EnclaveError> {
+    let max_items = state.config.load().batch.max_verify_items;
+    let items = request.payload.items;
+    if items.is_empty() || items.len() > max_items {
+        return Err(EnclaveError::GenericError(format!(
+            "A verification batch must have between 1 and {} items, got {}",
+            max_items,
+            items.len()
+        )));
+    }
+
+    let history = state.signing_keys.history_at(current_timestamp_ms()?);
+    let results = items.iter().map(|item| verify(item, &history)).collect();
+    let keys = history
+        .into_iter()
+        .map(|period| KeyWindow {
+            public_key: Hex::encode(period.public_key.as_bytes()),
+            active_from_ms: period.active_from_ms,
+            active_until_ms: period.active_until_ms,
+        })
+        .collect();
+    Ok(Json(VerifyBatchResponse { results, keys }))
+}
+
+/// Verify one relayed response against the keys in `history`.
+pub fn verify(item: &RelayedResponse, history: &[KeyPeriod]) -> VerifyResult {
+    let invalid = |error: String| VerifyResult {
+        valid: false,
+        public_key: None,
+        within_key_window: false,
+        error: Some(error),
+    };
+    let (bytes, timestamp_ms) = match signing_bytes(&item.response) {
+        Ok(signed) => signed,
+        Err(e) => return invalid(e),
+    };
+    let signature = match Hex::decode(&item.signature)
+        .ok()
+        .and_then(|bytes| Ed25519Signature::from_bytes(&bytes).ok())
+    {
+        Some(signature) => signature,
+        None => return invalid("Signature is not a Hex Ed25519 signature".to_string()),
+    };
+    let Some(period) = history
+        .iter()
+        .find(|period| period.public_key.verify(&bytes, &signature).is_ok())
+    else {
+        return invalid("Signature does not match any known key".to_string());
+    };
+
+    VerifyResult {
+        valid: true,
+        public_key: Some(Hex::encode(period.public_key.as_bytes())),
+        within_key_window: period
+            .active_from_ms
+            .map_or(true, |from| timestamp_ms >= from)
+            && period
+                .active_until_ms
+                .map_or(true, |until| timestamp_ms <= until),
+        error: None,
+    }
+}
+
+/// The BCS bytes `response` was signed over, and its timestamp. The data is
+/// decoded as the type its intent is signed with, so optional fields keep
+/// the layout they were signed with. Also used by the `test_vectors` binary
+/// to produce fixtures for the Move tests.
+pub fn signing_bytes(response: &Value) -> Result<(Vec<u8>, u64), String> {
+    let message: IntentMessage<Value> = serde_json::from_value(response.clone())
+        .map_err(|e| format!("Invalid intent message: {}", e))?;
+    let bytes = match message.intent {
+        IntentScope::PriceFeed
+        | IntentScope::PriceFeedWithChange
+        | IntentScope::PriceFeedMarketClosed
+        | IntentScope::PriceFeedReducedPrecision
+        | IntentScope::PriceFeedExtended => encode_price_feed(&message),
+        IntentScope::FeeQuote => encode::<FeeQuoteResponse>(&message),
+        IntentScope::Weather => encode::<WeatherResponse>(&message),
+        IntentScope::SportsResult => encode::<SportsResultResponse>(&message),
+        IntentScope::ProofOfReserve => encode::<ReserveResponse>(&message),
+        IntentScope::NftFloorPrice => encode::<NftFloorPriceResponse>(&message),
+        IntentScope::KeyRetirement => encode::<KeyRetirement>(&message),
+        IntentScope::PriceStatistics => encode::<PriceStatsResponse>(&message),
+        IntentScope::PriceFeedWide => encode::<WidePriceFeedResponse>(&message),
+        IntentScope::RateFeed => encode::<RateFeedResponse>(&message),
+    }?;
+    Ok((bytes, message.timestamp_ms))
+}
+
+/// Price feed responses pick their layout from the fields set, which must
+/// be the layout of the intent they were signed under.
+fn encode_price_feed(message: &IntentMessage<Value>) -> Result<Vec<u8>, String> {
+    let data: PriceFeedResponse = serde_json::from_value(message.data.clone())
+        .map_err(|e| format!("Invalid data for intent {:?}: {}", message.intent, e))?;
+    if data.intent() != message.intent {
+        return Err(format!(
+            "Data of intent {:?} has the layout of {:?}",
+            message.intent,
+            data.intent()
+        ));
+    }
+    encode::<PriceFeedResponse>(message)
+}
+
+fn encode<T: Serialize + DeserializeOwned>(
+    message: &IntentMessage<Value>,
+) -> Result<Vec<u8>, String> {
+    let data: T = serde_json::from_value(message.data.clone())
+        .map_err(|e| format!("Invalid data for intent {:?}: {}", message.intent, e))?;
+    let typed = IntentMessage {
+        intent: message.intent,
+        timestamp_ms: message.timestamp_ms,
+        data,
+    };
+    bcs::to_bytes(&typed).map_err(|e| format!("Failed to encode message: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::to_signed_response;
+    use fastcrypto::ed25519::Ed25519KeyPair;
+    use fastcrypto::traits::KeyPair;
+
+    fn relayed(kp: &Ed25519KeyPair, timestamp_ms: u64) -> RelayedResponse {
+        let statement = KeyRetirement {
+            public_key: vec![1, 2, 3],
+            retire_at_ms: 9_000,
+        };
+        let signed = to_signed_response(kp, statement, timestamp_ms, IntentScope::KeyRetirement);
+        RelayedResponse {
+            response: serde_json::to_value(&signed.response).unwrap(),
+            signature: signed.signature,
+        }
+    }
+
+    #[test]
+    fn test_signing_bytes() {
+        let statement = KeyRetirement {
+            public_key: vec![1, 2, 3],
+            retire_at_ms: 9_000,
+        };
+        let message = IntentMessage::new(statement, 1_000, IntentScope::KeyRetirement);
+        let (bytes, timestamp_ms) = signing_bytes(&serde_json::to_value(&message).unwrap()).unwrap();
+        assert_eq!(bytes, bcs::to_bytes(&message).unwrap());
+        assert_eq!(timestamp_ms, 1_000);
+
+        let mismatched = serde_json::json!({ "intent": 9, "timestamp_ms": 1_000, "data": { "price": 1 } });
+        assert!(signing_bytes(&mismatched).is_err());
+    }
+
+        #[test]
+    fn test_verify() {
+        let kp = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let other = Ed25519KeyPair::generate(&mut rand::thread_rng());
+        let history = vec![KeyPeriod {
+            public_key: kp.public().clone(),
+            active_from_ms: Some(1_000),
+            active_until_ms: None,
+        }];
+
+        let result = verify(&relayed(&kp, 2_000), &history);
+        assert!(result.valid && result.within_key_window);
+        assert!(!verify(&relayed(&kp, 500), &history).within_key_window);
+        assert!(!verify(&relayed(&other, 2_000), &history).valid);
+
+        let mut tampered = relayed(&kp, 2_000);
+        tampered.response["data"]["retire_at_ms"] = 10_000.into();
+        assert!(!verify(&tampered, &history).valid);
+    }
+}
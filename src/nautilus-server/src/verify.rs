@@ -0,0 +1,235 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Second, independent extraction of a price, used to cross-check the native
+//! extractor before signing. It shares no code with it: the field is looked
+//! up by a JSONPath engine and scaled with integer string arithmetic instead
+//! of `Decimal`, and aggregates are computed exactly on the scaled integers.
+//! XML selectors have a single implementation, so for XML responses only the
+//! scaling is checked independently.
+
+use serde_json::Value;
+use serde_json_path::JsonPath;
+
+/// Price at `response_field` of `json` scaled to `decimals` and truncated,
+/// computed independently of [`crate::app::extract_price`].
+pub fn independent_price(json: &Value, response_field: &str, decimals: u32) -> Result<i128, String> {
+    if response_field.starts_with('/') {
+        let text = number_text(crate::xml::select(json, response_field)?)?;
+        return scale_signed(&text, decimals);
+    }
+
+    let (path, aggregate) = match response_field.rsplit_once('[') {
+        Some((path, aggregate @ ("min]" | "max]" | "avg]"))) => (path, Some(&aggregate[..3])),
+        _ => (response_field, None),
+    };
+    // `[last]` is JSONPath's `[-1]`, filters are JSONPath filters, and an
+    // aggregate over an array without `[*]` or a filter is over its elements.
+    let fans_out = path.contains("[*]") || path.contains('=');
+    let mut path = json_path_filters(&path.replace("[last]", "[-1]"));
+    if aggregate.is_some() && !fans_out {
+        path.push_str("[*]");
+    }
+    let path = JsonPath::parse(&format!("$.{}", path))
+        .map_err(|e| format!("'{}' is not a valid JSONPath: {}", response_field, e))?;
+    let nodes = path.query(json);
+
+    let Some(aggregate) = aggregate else {
+        let value = nodes
+            .exactly_one()
+            .map_err(|e| format!("JSONPath '{}' did not match one value: {}", response_field, e))?;
+        return scale_signed(&number_text(value)?, decimals);
+    };
+    let texts = nodes
+        .all()
+        .into_iter()
+        .map(number_text)
+        .collect::<Result<Vec<_>, _>>()?;
+    if texts.is_empty() {
+        return Err(format!("JSONPath '{}' matched no values", response_field));
+    }
+    let scaled = || texts.iter().map(|text| scale_signed(text, decimals));
+    match aggregate {
+        "min" => scaled().try_fold(i128::MAX, |min, value| value.map(|value| min.min(value))),
+        "max" => scaled().try_fold(i128::MIN, |max, value| value.map(|value| max.max(value))),
+        _ => average(&texts, decimals),
+    }
+}
+
+/// `path` with each `[field=value]` rewritten as the JSONPath filter
+/// `[?@['field'] == 'value']`, also matching the number `value` if it is one.
+fn json_path_filters(path: &str) -> String {
+    let quote = |s: &str| format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"));
+    let mut rewritten = String::new();
+    let mut rest = path;
+    while let Some(open) = rest.find('[') {
+        let Some(close) = rest[open..].find(']').map(|close| open + close) else {
+            break;
+        };
+        rewritten.push_str(&rest[..open]);
+        let inner = &rest[open + 1..close];
+        match inner.split_once('=') {
+            Some((field, value)) => {
+                let (field, value) = (field.trim(), value.trim());
+                let value = value
+                    .strip_prefix('\'')
+                    .and_then(|v| v.strip_suffix('\''))
+                    .or_else(|| value.strip_prefix('"').and_then(|v| v.strip_suffix('"')))
+                    .unwrap_or(value);
+                let field = format!("@[{}]", quote(field));
+                rewritten.push_str(&format!("[?{} == {}", field, quote(value)));
+                if serde_json::from_str::<serde_json::Number>(value).is_ok() {
+                    rewritten.push_str(&format!(" || {} == {}", field, value));
+                }
+                rewritten.push(']');
+            }
+            None => rewritten.push_str(&rest[open..=close]),
+        }
+        rest = &rest[close + 1..];
+    }
+    rewritten.push_str(rest);
+    rewritten
+}
+
+fn number_text(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.trim().to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        other => Err(format!("Value {} is neither a string nor a number", other)),
+    }
+}
+
+/// Fixed-point value of a plain decimal string such as `"123.4567"` with
+/// `decimals` fractional digits, dropping any further digits.
+pub fn scale_decimal_string(text: &str, decimals: u32) -> Result<u64, String> {
+    scaled_digits(text, decimals)?
+        .parse()
+        .map_err(|_| format!("'{}' scaled by {} decimals does not fit in u64", text, decimals))
+}
+
+/// [`scale_decimal_string`] for prices wider than u64 or negative, which
+/// are truncated towards zero.
+fn scale_signed(text: &str, decimals: u32) -> Result<i128, String> {
+    let (negative, magnitude) = match text.strip_prefix('-') {
+        Some(magnitude) => (true, magnitude),
+        None => (false, text),
+    };
+    let value: i128 = scaled_digits(magnitude, decimals)?
+        .parse()
+        .map_err(|_| format!("'{}' scaled by {} decimals does not fit in i128", text, decimals))?;
+    Ok(if negative { -value } else { value })
+}
+
+/// Digits of `text` scaled by `decimals`, without leading zeros.
+fn scaled_digits(text: &str, decimals: u32) -> Result<String, String> {
+    let (integer, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if integer.is_empty() && fraction.is_empty()
+        || !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit())
+    {
+        return Err(format!("'{}' is not an unsigned decimal number", text));
+    }
+
+    let mut digits = integer.to_string();
+    digits.extend(fraction.chars().chain(std::iter::repeat('0')).take(decimals as usize));
+    let digits = digits.trim_start_matches('0');
+    Ok(if digits.is_empty() { "0" } else { digits }.to_string())
+}
+
+/// Mean of `texts` scaled to `decimals` and truncated, computed exactly by
+/// scaling every value to the longest fraction among them first.
+fn average(texts: &[String], decimals: u32) -> Result<i128, String> {
+    let overflow = || "Average of the matched values overflows".to_string();
+    let fraction_digits = texts
+        .iter()
+        .map(|text| text.split_once('.').map_or(0, |(_, fraction)| fraction.len()))
+        .max()
+        .unwrap_or(0) as u32;
+    let sum = texts.iter().try_fold(0i128, |sum, text| {
+        let value = scale_signed(text, fraction_digits).map_err(|_| overflow())?;
+        sum.checked_add(value).ok_or_else(overflow)
+    })?;
+    let numerator = 10i128
+        .checked_pow(decimals)
+        .and_then(|scale| sum.checked_mul(scale))
+        .ok_or_else(overflow)?;
+    let denominator = 10i128
+        .checked_pow(fraction_digits)
+        .and_then(|scale| scale.checked_mul(texts.len() as i128))
+        .ok_or_else(overflow)?;
+    // Truncates towards zero, like the native extractor
+    Ok(numerator / denominator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_scale_decimal_string() {
+        assert_eq!(scale_decimal_string("123.4567", 2), Ok(12345));
+        assert_eq!(scale_decimal_string("1.5", 8), Ok(150_000_000));
+        assert_eq!(scale_decimal_string("42", 0), Ok(42));
+        assert_eq!(scale_decimal_string(".5", 1), Ok(5));
+        assert_eq!(scale_decimal_string("0.000", 2), Ok(0));
+        assert!(scale_decimal_string("-1.5", 2).is_err());
+        assert!(scale_decimal_string("1e5", 2).is_err());
+        assert!(scale_decimal_string(".", 2).is_err());
+        assert!(scale_decimal_string("18446744073709551616", 0).is_err());
+    }
+
+    #[test]
+    fn test_independent_price() {
+        let json = json!({"data": [{"price": "100.25"}, {"price": 7.5}]});
+        assert_eq!(independent_price(&json, "data[0].price", 2), Ok(10025));
+        assert_eq!(independent_price(&json, "data[1].price", 3), Ok(7500));
+        assert!(independent_price(&json, "data[2].price", 2).is_err());
+        assert!(independent_price(&json, "data", 2).is_err());
+
+        // Funding rates go negative and truncate towards zero.
+        let json = json!({"rate": "-0.00012345", "rates": [-1.5, "0.5", "-2"]});
+        assert_eq!(independent_price(&json, "rate", 6), Ok(-123));
+        assert_eq!(independent_price(&json, "rates[min]", 1), Ok(-20));
+        assert_eq!(independent_price(&json, "rates[avg]", 2), Ok(-100));
+        assert_eq!(independent_price(&json, "rates[last]", 0), Ok(-2));
+
+        // 18 decimals of a high-supply token overflow u64 but not u128.
+        let json = json!({"supply": "123456789012.5"});
+        assert_eq!(
+            independent_price(&json, "supply", 18),
+            Ok(123_456_789_012_500_000_000_000_000_000)
+        );
+    }
+
+    #[test]
+    fn test_independent_aggregates() {
+        let json = json!({
+            "bids": ["99.5", 101, "100.25"],
+            "venues": [{"price": "10"}, {"price": 11}, {"price": "12.3"}]
+        });
+        assert_eq!(independent_price(&json, "bids[max]", 2), Ok(10100));
+        assert_eq!(independent_price(&json, "bids[min]", 2), Ok(9950));
+        assert_eq!(independent_price(&json, "bids[avg]", 3), Ok(100250));
+        assert_eq!(independent_price(&json, "venues[*].price[avg]", 4), Ok(111000));
+        assert_eq!(independent_price(&json, "venues[last].price", 1), Ok(123));
+        // 10 / 3 truncates rather than rounds.
+        let thirds = json!({"values": ["1", "2", "7"]});
+        assert_eq!(independent_price(&thirds, "values[avg]", 2), Ok(333));
+        assert!(independent_price(&json!({"bids": []}), "bids[min]", 2).is_err());
+        assert!(independent_price(&json, "venues[max]", 2).is_err());
+    }
+
+    #[test]
+    fn test_independent_filters() {
+        let json = json!({"data": [
+            {"symbol": "ETHUSD", "price": "3000.5"},
+            {"symbol": "BTCUSD", "price": "65000", "id": 7},
+            {"symbol": "BTCUSD", "price": "65010"}
+        ]});
+        assert_eq!(independent_price(&json, "data[symbol=ETHUSD].price", 1), Ok(30005));
+        assert_eq!(independent_price(&json, "data[id=7].price", 0), Ok(65000));
+        assert_eq!(independent_price(&json, "data[symbol='BTCUSD'].price[max]", 0), Ok(65010));
+        assert!(independent_price(&json, "data[symbol=BTCUSD].price", 0).is_err());
+        assert!(independent_price(&json, "data[symbol=SOLUSD].price", 0).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Weather oracle module: signs a temperature or precipitation reading for
+//! the location of an on-chain WeatherFeed object, through the same upstream
+//! fetch and extraction as price feeds.
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+use tracing::instrument;
+use utoipa::ToSchema;
+
+use crate::app::{encode_url_component, extract_price, fetch_upstream};
+use crate::common::{
+    current_timestamp_ms, to_signed_response, IntentMessage, IntentScope, ProcessDataRequest,
+    ProcessedDataResponse,
+};
+use crate::formats::UpstreamFormat;
+use crate::oracle::{self, OracleModule, Signed};
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Placeholder in a WeatherFeed's `provider` URL replaced by its location.
+const LOCATION_PLACEHOLDER: &str = "{location}";
+
+/// `[weather]` config section.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Weather {
+    /// Number of decimals readings are scaled by
+    #[serde(default = "default_value_decimals")]
+    pub value_decimals: u32,
+}
+
+impl Default for Weather {
+    fn default() -> Self {
+        Self {
+            value_decimals: default_value_decimals(),
+        }
+    }
+}
+
+fn default_value_decimals() -> u32 {
+    2
+}
+
+/// What a weather reading measures, in the provider's units.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WeatherMetric {
+    Temperature,
+    Precipitation,
+}
+
+impl FromStr for WeatherMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "temperature" => Ok(Self::Temperature),
+            "precipitation" => Ok(Self::Precipitation),
+            _ => Err(format!("Unsupported weather metric: {}", s)),
+        }
+    }
+}
+
+/// Inner type T for IntentMessage<T>
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
+pub struct WeatherResponse {
+    pub oracle_id: String,
+    pub weather_feed_id: String,
+    pub location: String,
+    pub metric: WeatherMetric,
+    /// Reading scaled by 10^decimals; temperatures can be negative
+    pub value: i64,
+    pub decimals: u32,
+    pub timestamp_ms: u64,
+}
+
+/// Inner type T for ProcessDataRequest<T>
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WeatherRequest {
+    pub weather_feed_id: String,
+}
+
+/// Fetch the reading of a weather feed registered on chain and sign it. The
+/// route is served by [`WeatherModule`].
+#[utoipa::path(
+    post,
+    path = "/weather",
+    tag = "weather",
+    request_body = ProcessDataRequest<WeatherRequest>,
+    params(
+        ("x-consumer-key" = Option<String>, Header, description = "API key of the consumer, see `[consumers]`"),
+        ("x-sui-signature" = Option<String>, Header, description = "Sui wallet signature of the timestamp and body, instead of an API key"),
+        ("x-sui-timestamp-ms" = Option<u64>, Header, description = "Time the wallet signature was made"),
+    ),
+    responses(
+        (status = 200, description = "Signed reading", body = ProcessedDataResponse<IntentMessage<WeatherResponse>>),
+        (status = 400, description = "Invalid feed or failed fetch", body = crate::ErrorResponse),
+        (status = 401, description = "Missing or unknown consumer key or wallet", body = crate::ErrorResponse),
+        (status = 503, description = "Signing is paused, globally or for the feed", body = crate::ErrorResponse),
+    )
+)]
+pub async fn process_weather(
+    state: State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ProcessedDataResponse<IntentMessage<WeatherResponse>>>, EnclaveError> {
+    oracle::handle::<WeatherModule>(state, headers, body).await
+}
+
+/// Signed weather readings of on-chain WeatherFeed objects.
+pub struct WeatherModule;
+
+impl OracleModule for WeatherModule {
+    const NAME: &'static str = "weather";
+    const PATH: &'static str = "/weather";
+    const INTENT: IntentScope = IntentScope::Weather;
+
+    type Request = WeatherRequest;
+    type Response = WeatherResponse;
+
+    #[instrument(name = "weather", skip_all, fields(weather_feed_id = %request.weather_feed_id))]
+    async fn process(
+        state: &AppState,
+        request: WeatherRequest,
+    ) -> Result<Signed<WeatherResponse>, EnclaveError> {
+        let config = state.config.load_full();
+        let weather_feed_id = request.weather_feed_id;
+        state.pause.check(&config, Some(&weather_feed_id))?;
+
+        let feed = state
+            .sui_client
+            .load_full()
+            .fetch_weather_feed(&weather_feed_id)
+            .await
+            .map_err(|e| EnclaveError::GenericError(format!("Failed to fetch weather feed: {}", e)))?;
+        if !feed.is_valid {
+            return Err(EnclaveError::GenericError(
+                "Weather feed is not valid".to_string(),
+            ));
+        }
+        let metric = WeatherMetric::from_str(&feed.metric).map_err(EnclaveError::GenericError)?;
+
+        let url = feed
+            .provider
+            .replace(LOCATION_PLACEHOLDER, &encode_url_component(&feed.location));
+        let json = fetch_upstream(
+            state,
+            &config,
+            &weather_feed_id,
+            &url,
+            feed.api_key.as_deref().zip(feed.api_key_config.as_deref()),
+            &UpstreamFormat::default(),
+        )
+        .await?;
+        let reading = extract_price(&json, &feed.field).map_err(EnclaveError::GenericError)?;
+
+        let decimals = config.weather.value_decimals;
+        let value = (reading * Decimal::from(10_u64.pow(decimals)))
+            .round()
+            .to_i64()
+            .ok_or_else(|| {
+                EnclaveError::GenericError(format!(
+                    "Scaled reading is too large to fit in i64 (decimals: {})",
+                    decimals
+                ))
+            })?;
+
+        let current_timestamp = current_timestamp_ms()?;
+        let kp = state.signing_keys.signer_for(Self::INTENT, current_timestamp, current_timestamp)?;
+        let response = WeatherResponse {
+            oracle_id: feed.oracle_id,
+            weather_feed_id,
+            location: feed.location,
+            metric,
+            value,
+            decimals,
+            timestamp_ms: current_timestamp,
+        };
+        Ok(to_signed_response(&kp, response, current_timestamp, Self::INTENT))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metric() {
+        assert_eq!("temperature".parse(), Ok(WeatherMetric::Temperature));
+        assert!("humidity".parse::<WeatherMetric>().is_err());
+    }
+}
@@ -0,0 +1,280 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Prices too large for the u64 of `PriceFeedResponse`, like high-supply
+//! tokens quoted with 18 decimals, signed as a u128 with an explicit
+//! exponent under their own intent. Scaled prices are computed with the 28
+//! digits of `Decimal`, so they reach about 7.9e28.
+//!
+//! The price is fetched and checked like a `/process_data` one, but the
+//! price history, circuit breaker and rate of change are kept in u64 and do
+//! not apply, and no last close is served while the feed's market is closed.
+
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{info_span, instrument};
+use utoipa::ToSchema;
+
+use crate::app::{check_may_sign, fetch_price, intent_timestamp_ms, verify_payment, UpstreamPrice};
+use crate::common::{
+    current_timestamp_ms, to_signed_response, IntentMessage, IntentScope, ProcessDataRequest,
+    ProcessedDataResponse, RoundingMode, TimestampSource,
+};
+use crate::oracle::{self, OracleModule, Signed};
+use crate::AppState;
+use crate::EnclaveError;
+
+/// Inner type T for ProcessDataRequest<T>
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WidePriceRequest {
+    pub price_feed_id: String,
+    /// On-chain payment receipt, required when `payments.required` is set
+    #[serde(default)]
+    pub receipt_id: Option<String>,
+}
+
+/// Inner type T for IntentMessage<T>
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, ToSchema)]
+pub struct WidePriceFeedResponse {
+    pub oracle_id: String,
+    pub price_feed_id: String,
+    /// The price is `price * 10^-exponent`. A u128 in the signed bytes and a
+    /// decimal string in JSON, which cannot carry it as a number.
+    #[serde(with = "wide_integer")]
+    #[schema(value_type = String)]
+    pub price: u128,
+    pub exponent: u32,
+    pub timestamp_ms: u64,
+    /// Time the upstream says the price is from, for feeds with a
+    /// `timestamp_field`
+    pub source_timestamp_ms: Option<u64>,
+    /// Rounding mode of the feed, for feeds that configure one. Always
+    /// serialized, so the signed bytes keep a single layout.
+    #[serde(default)]
+    pub rounding: Option<RoundingMode>,
+}
+
+/// Fetch and sign a feed's price as a u128. The route is served by
+/// [`WidePriceModule`].
+#[utoipa::path(
+    post,
+    path = "/process_data_wide",
+    tag = "price feed",
+    request_body = ProcessDataRequest<WidePriceRequest>,
+    params(
+        ("x-consumer-key" = Option<String>, Header, description = "API key of the consumer, see `[consumers]`"),
+        ("x-sui-signature" = Option<String>, Header, description = "Sui wallet signature of the timestamp and body, instead of an API key"),
+        ("x-sui-timestamp-ms" = Option<u64>, Header, description = "Time the wallet signature was made"),
+    ),
+    responses(
+        (status = 200, description = "Signed price", body = ProcessedDataResponse<IntentMessage<WidePriceFeedResponse>>),
+        (status = 400, description = "Invalid feed, failed fetch or market closed", body = crate::ErrorResponse),
+        (status = 401, description = "Missing or unknown consumer key or wallet", body = crate::ErrorResponse),
+        (status = 403, description = "Outside the feed's signing windows", body = crate::ErrorResponse),
+        (status = 503, description = "Signing is paused, globally or for the feed", body = crate::ErrorResponse),
+    )
+)]
+pub async fn process_data_wide(
+    state: State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<ProcessedDataResponse<IntentMessage<WidePriceFeedResponse>>>, EnclaveError> {
+    oracle::handle::<WidePriceModule>(state, headers, body).await
+}
+
+/// Signed u128 prices of on-chain PriceFeed objects.
+pub struct WidePriceModule;
+
+impl OracleModule for WidePriceModule {
+    const NAME: &'static str = "price_feed_wide";
+    const PATH: &'static str = "/process_data_wide";
+    const INTENT: IntentScope = IntentScope::PriceFeedWide;
+
+    type Request = WidePriceRequest;
+    type Response = WidePriceFeedResponse;
+
+    #[instrument(name = "process_data_wide", skip_all, fields(price_feed_id = %request.price_feed_id))]
+    async fn process(
+        state: &AppState,
+        request: WidePriceRequest,
+    ) -> Result<Signed<WidePriceFeedResponse>, EnclaveError> {
+        let fetched = fetch_unrecorded(
+            state,
+            &request.price_feed_id,
+            request.receipt_id,
+            "Wide prices",
+        )
+        .await?;
+        let response = WidePriceFeedResponse {
+            oracle_id: fetched.oracle_id.clone(),
+            price_feed_id: request.price_feed_id,
+            price: fetched.upstream.price_u128()?,
+            exponent: fetched.upstream.decimals,
+            timestamp_ms: fetched.timestamp_ms,
+            source_timestamp_ms: fetched.upstream.source_timestamp_ms,
+            rounding: fetched.upstream.rounding,
+        };
+        fetched.sign(state, response, Self::INTENT)
+    }
+}
+
+/// A price fetched for an intent other than `PriceFeed`, which is neither
+/// recorded in the history nor checked by the circuit breaker.
+pub(crate) struct Unrecorded {
+    pub oracle_id: String,
+    pub upstream: UpstreamPrice,
+    /// Timestamp to sign with, from the feed's `timestamp_source`
+    pub timestamp_ms: u64,
+    timestamp_source: TimestampSource,
+    /// Time the price was fetched, at which the signing key is picked
+    fetched_at_ms: u64,
+    receipt_id: Option<String>,
+}
+
+impl Unrecorded {
+    /// Consume the request's payment receipt and sign `response`.
+    pub fn sign<T: Serialize + Clone>(
+        self,
+        state: &AppState,
+        response: T,
+        intent: IntentScope,
+    ) -> Result<Signed<T>, EnclaveError> {
+        let kp = state.signing_keys.signer_for(intent, self.fetched_at_ms, self.timestamp_ms)?;
+        if let Some(receipt_id) = &self.receipt_id {
+            if !state.receipts.consume(receipt_id) {
+                return Err(EnclaveError::GenericError(format!(
+                    "Receipt {} has already been used",
+                    receipt_id
+                )));
+            }
+        }
+        let signed = info_span!("sign")
+            .in_scope(|| to_signed_response(&kp, response, self.timestamp_ms, intent));
+        Ok(signed.with_timestamp_source(self.timestamp_source))
+    }
+}
+
+/// Check that a feed may be signed, verify the payment if required and
+/// fetch its price. `kind` names what is refused while the market is closed.
+pub(crate) async fn fetch_unrecorded(
+    state: &AppState,
+    price_feed_id: &str,
+    receipt_id: Option<String>,
+    kind: &str,
+) -> Result<Unrecorded, EnclaveError> {
+    let config = state.config.load_full();
+    let feed_config = config.feed(price_feed_id);
+    check_may_sign(state, &config, &feed_config, price_feed_id)?;
+
+    let current_timestamp = current_timestamp_ms()?;
+    if let Some(market_hours) = &feed_config.market_hours {
+        let now = DateTime::<Utc>::from_timestamp_millis(current_timestamp as i64)
+            .ok_or_else(|| EnclaveError::GenericError("Invalid current timestamp".to_string()))?;
+        let is_open = market_hours.is_open(now).map_err(|e| {
+            EnclaveError::GenericError(format!("Invalid market hours for feed: {}", e))
+        })?;
+        if !is_open {
+            return Err(EnclaveError::GenericError(format!(
+                "{} are not served while the market is closed",
+                kind
+            )));
+        }
+    }
+
+    let receipt_id = if config.payments.required {
+        let receipt_id = receipt_id.ok_or_else(|| {
+            EnclaveError::GenericError("A payment receipt_id is required".to_string())
+        })?;
+        verify_payment(state, &config, price_feed_id, &receipt_id).await?;
+        Some(receipt_id)
+    } else {
+        None
+    };
+
+    let price_feed = state
+        .sui_client
+        .load_full()
+        .fetch_price_feed(price_feed_id)
+        .await
+        .map_err(|e| EnclaveError::GenericError(format!("Failed to fetch price feed: {}", e)))?;
+    if !price_feed.is_valid {
+        return Err(EnclaveError::GenericError(
+            "Price feed is not valid".to_string(),
+        ));
+    }
+
+    let upstream = fetch_price(state, &config, price_feed_id, &price_feed).await?;
+    let timestamp_ms =
+        intent_timestamp_ms(state, &config, &feed_config, &upstream, current_timestamp).await?;
+    Ok(Unrecorded {
+        oracle_id: price_feed.oracle_id,
+        upstream,
+        timestamp_ms,
+        timestamp_source: feed_config.timestamp_source,
+        fetched_at_ms: current_timestamp,
+        receipt_id,
+    })
+}
+
+/// A u128 as a number in binary formats like BCS and as a decimal string in
+/// human readable ones like JSON.
+mod wide_integer {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &u128, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(value)
+        } else {
+            serializer.serialize_u128(*value)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u128, D::Error> {
+        if deserializer.is_human_readable() {
+            String::deserialize(deserializer)?
+                .parse()
+                .map_err(D::Error::custom)
+        } else {
+            u128::deserialize(deserializer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wide_price_encoding() {
+        let response = WidePriceFeedResponse {
+            oracle_id: "0x1".to_string(),
+            price_feed_id: "0x2".to_string(),
+            price: u128::from(u64::MAX) * 1000,
+            exponent: 18,
+            timestamp_ms: 1_700_000_000_000,
+            source_timestamp_ms: None,
+            rounding: None,
+        };
+        let json = serde_json::to_value(&response).unwrap();
+        assert_eq!(json["price"], "18446744073709551615000");
+        assert_eq!(
+            serde_json::from_value::<WidePriceFeedResponse>(json).unwrap(),
+            response
+        );
+
+        // The signed bytes carry the price as a 16 byte little-endian u128.
+        let bytes = bcs::to_bytes(&response).unwrap();
+        let price_at = 2 * 4;
+        assert_eq!(bytes[price_at..price_at + 16], response.price.to_le_bytes());
+        assert_eq!(
+            bcs::from_bytes::<WidePriceFeedResponse>(&bytes).unwrap(),
+            response
+        );
+    }
+}
@@ -0,0 +1,371 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! XML upstream responses, for central bank and other legacy providers. The
+//! body is converted to a JSON document holding the element tree under
+//! `xml`, each element as `{"name", "attributes", "text", "children"}` with
+//! namespace prefixes dropped. Field paths starting with `/` are XPath-like
+//! selectors evaluated on that tree, e.g. `//Cube[@currency='USD']/@rate`:
+//!
+//! - steps separated by `/`, or `//` for any depth, naming an element or `*`;
+//! - predicates `[n]` (the n-th match, from 1), `[@attr]` and `[@attr='v']`;
+//! - a final `/@attr` selects an attribute, otherwise the element's text.
+//!
+//! A selector must match exactly one node.
+
+use serde_json::{json, Map, Value};
+
+/// Field of the converted document holding the root element.
+pub const ROOT_FIELD: &str = "xml";
+
+/// Convert an XML body to a JSON document, refusing elements nested deeper
+/// than `max_depth`. DTDs are rejected, so entities cannot expand.
+pub fn to_json(body: &[u8], max_depth: usize) -> Result<Value, String> {
+    let text =
+        std::str::from_utf8(body).map_err(|_| "Upstream XML response is not UTF-8".to_string())?;
+    let document = roxmltree::Document::parse(text)
+        .map_err(|e| format!("Failed to parse XML response: {}", e))?;
+    let root = element(document.root_element(), 1, max_depth)?;
+    Ok(json!({ ROOT_FIELD: root }))
+}
+
+fn element(node: roxmltree::Node, depth: usize, max_depth: usize) -> Result<Value, String> {
+    if depth > max_depth {
+        return Err(format!(
+            "Upstream response nests more than the {} levels allowed",
+            max_depth
+        ));
+    }
+    let attributes: Map<String, Value> = node
+        .attributes()
+        .map(|attribute| {
+            (
+                attribute.name().to_string(),
+                Value::String(attribute.value().to_string()),
+            )
+        })
+        .collect();
+    let text: String = node
+        .children()
+        .filter(|child| child.is_text())
+        .filter_map(|child| child.text())
+        .collect();
+    let children = node
+        .children()
+        .filter(|child| child.is_element())
+        .map(|child| element(child, depth + 1, max_depth))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(json!({
+        "name": node.tag_name().name(),
+        "attributes": attributes,
+        "text": text.trim(),
+        "children": children,
+    }))
+}
+
+/// A parsed XPath-like selector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selector {
+    steps: Vec<Step>,
+    target: Target,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    /// Whether the step matches at any depth below the previous one
+    descendants: bool,
+    /// Local name of the elements matched, any for `*`
+    name: Option<String>,
+    predicates: Vec<Predicate>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Predicate {
+    /// The n-th match, from 1
+    Position(usize),
+    /// Elements with the attribute, set to `value` if given
+    Attribute { name: String, value: Option<String> },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Target {
+    Text,
+    Attribute(String),
+}
+
+impl Selector {
+    pub fn parse(selector: &str) -> Result<Self, String> {
+        let invalid = |reason: &str| format!("Invalid XML selector '{}': {}", selector, reason);
+        let mut steps = Vec::new();
+        let mut target = Target::Text;
+        let mut rest = selector;
+        while !rest.is_empty() {
+            let descendants = if let Some(after) = rest.strip_prefix("//") {
+                rest = after;
+                true
+            } else if let Some(after) = rest.strip_prefix('/') {
+                rest = after;
+                false
+            } else {
+                return Err(invalid("steps must be separated by / or //"));
+            };
+
+            // Attributes and text end the selector
+            if let Some(name) = rest.strip_prefix('@') {
+                if descendants || steps.is_empty() || name.is_empty() || name.contains(['/', '[']) {
+                    return Err(invalid(
+                        "an attribute must be the last step, after an element",
+                    ));
+                }
+                target = Target::Attribute(local_name(name).to_string());
+                break;
+            }
+            if rest == "text()" {
+                if descendants || steps.is_empty() {
+                    return Err(invalid("text() must be the last step, after an element"));
+                }
+                break;
+            }
+
+            let end = rest.find(['/', '[']).unwrap_or(rest.len());
+            let name = &rest[..end];
+            if name.is_empty() {
+                return Err(invalid("empty element name"));
+            }
+            rest = &rest[end..];
+            let mut predicates = Vec::new();
+            while let Some(after) = rest.strip_prefix('[') {
+                let close = after.find(']').ok_or_else(|| invalid("missing ]"))?;
+                predicates.push(Predicate::parse(&after[..close]).map_err(|e| invalid(&e))?);
+                rest = &after[close + 1..];
+            }
+            steps.push(Step {
+                descendants,
+                name: (name != "*").then(|| local_name(name).to_string()),
+                predicates,
+            });
+        }
+        if steps.is_empty() {
+            return Err(invalid("no element steps"));
+        }
+        Ok(Self { steps, target })
+    }
+
+    /// The selected attribute or text in a document from [`to_json`].
+    pub fn select<'a>(&self, document: &'a Value) -> Result<&'a Value, String> {
+        let root = document
+            .get(ROOT_FIELD)
+            .ok_or("XML selectors only apply to XML responses")?;
+        // None is the document node, whose only child is the root element
+        let mut context: Vec<Option<&'a Value>> = vec![None];
+        for step in &self.steps {
+            if step.descendants {
+                context = descendants_or_self(&context, root);
+            }
+            let mut matched: Vec<Option<&'a Value>> = Vec::new();
+            for node in context {
+                let candidates = children(node, root)
+                    .into_iter()
+                    .filter(|child| step.matches_name(child));
+                for child in step.filter(candidates.collect()) {
+                    if !matched
+                        .iter()
+                        .any(|m| m.is_some_and(|m| std::ptr::eq(m, child)))
+                    {
+                        matched.push(Some(child));
+                    }
+                }
+            }
+            context = matched;
+        }
+
+        let node = match context[..] {
+            [Some(node)] => node,
+            _ => return Err(format!("matched {} elements, expected one", context.len())),
+        };
+        match &self.target {
+            Target::Text => node
+                .get("text")
+                .ok_or_else(|| "element has no text".to_string()),
+            Target::Attribute(name) => node
+                .get("attributes")
+                .and_then(|attributes| attributes.get(name))
+                .ok_or_else(|| format!("element has no attribute '{}'", name)),
+        }
+    }
+}
+
+impl Step {
+    fn matches_name(&self, element: &Value) -> bool {
+        self.name.as_deref().map_or(true, |name| {
+            element.get("name").and_then(Value::as_str) == Some(name)
+        })
+    }
+
+    fn filter<'a>(&self, mut elements: Vec<&'a Value>) -> Vec<&'a Value> {
+        for predicate in &self.predicates {
+            elements = match predicate {
+                Predicate::Position(position) => {
+                    elements.get(position - 1).copied().into_iter().collect()
+                }
+                Predicate::Attribute { name, value } => elements
+                    .into_iter()
+                    .filter(|element| {
+                        let attribute = element
+                            .get("attributes")
+                            .and_then(|attributes| attributes.get(name))
+                            .and_then(Value::as_str);
+                        match value {
+                            Some(value) => attribute == Some(value.as_str()),
+                            None => attribute.is_some(),
+                        }
+                    })
+                    .collect(),
+            };
+        }
+        elements
+    }
+}
+
+impl Predicate {
+    fn parse(predicate: &str) -> Result<Self, String> {
+        let predicate = predicate.trim();
+        if let Ok(position) = predicate.parse::<usize>() {
+            return match position {
+                0 => Err("positions start at 1".to_string()),
+                position => Ok(Predicate::Position(position)),
+            };
+        }
+        let attribute = predicate
+            .strip_prefix('@')
+            .ok_or_else(|| format!("unsupported predicate [{}]", predicate))?;
+        let Some((name, value)) = attribute.split_once('=') else {
+            return Ok(Predicate::Attribute {
+                name: local_name(attribute.trim()).to_string(),
+                value: None,
+            });
+        };
+        let value = value.trim();
+        let unquoted = value
+            .strip_prefix('\'')
+            .and_then(|v| v.strip_suffix('\''))
+            .or_else(|| value.strip_prefix('"').and_then(|v| v.strip_suffix('"')))
+            .ok_or_else(|| format!("value of [{}] must be quoted", predicate))?;
+        Ok(Predicate::Attribute {
+            name: local_name(name.trim()).to_string(),
+            value: Some(unquoted.to_string()),
+        })
+    }
+}
+
+/// Name without its namespace prefix.
+fn local_name(name: &str) -> &str {
+    name.rsplit(':').next().unwrap_or(name)
+}
+
+fn children<'a>(node: Option<&'a Value>, root: &'a Value) -> Vec<&'a Value> {
+    match node {
+        None => vec![root],
+        Some(node) => node
+            .get("children")
+            .and_then(Value::as_array)
+            .map(|children| children.iter().collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Every node of `context` and all elements below them, in document order.
+fn descendants_or_self<'a>(
+    context: &[Option<&'a Value>],
+    root: &'a Value,
+) -> Vec<Option<&'a Value>> {
+    let mut nodes: Vec<Option<&'a Value>> = Vec::new();
+    for &node in context {
+        let mut stack = vec![node];
+        while let Some(node) = stack.pop() {
+            let seen = nodes.iter().any(|seen| match (seen, node) {
+                (None, None) => true,
+                (Some(seen), Some(node)) => std::ptr::eq(*seen, node),
+                _ => false,
+            });
+            if seen {
+                continue;
+            }
+            nodes.push(node);
+            stack.extend(children(node, root).into_iter().rev().map(Some));
+        }
+    }
+    nodes
+}
+
+/// The value at an XPath-like `selector` of a document from [`to_json`].
+pub fn select<'a>(document: &'a Value, selector: &str) -> Result<&'a Value, String> {
+    Selector::parse(selector)?.select(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ECB: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01" xmlns="http://www.ecb.int/vocabulary/2002-08-01/eurofxref">
+  <gesmes:subject>Reference rates</gesmes:subject>
+  <Cube>
+    <Cube time="2025-01-03">
+      <Cube currency="USD" rate="1.0298"/>
+      <Cube currency="JPY" rate="163.02"/>
+    </Cube>
+  </Cube>
+</gesmes:Envelope>"#;
+
+    #[test]
+    fn test_select() {
+        let document = to_json(ECB.as_bytes(), 32).unwrap();
+        let select = |selector| select(&document, selector);
+        assert_eq!(
+            select("//Cube[@currency='USD']/@rate"),
+            Ok(&json!("1.0298"))
+        );
+        assert_eq!(
+            select("/Envelope/Cube/Cube/Cube[2]/@rate"),
+            Ok(&json!("163.02"))
+        );
+        assert_eq!(
+            select("/gesmes:Envelope/subject"),
+            Ok(&json!("Reference rates"))
+        );
+        assert_eq!(select("//*[@time]/@time"), Ok(&json!("2025-01-03")));
+        assert_eq!(
+            select("/Envelope/subject/text()"),
+            Ok(&json!("Reference rates"))
+        );
+
+        // Ambiguous or missing matches are refused.
+        assert!(select("//Cube[@currency]/@rate").is_err());
+        assert!(select("//Cube[@currency='GBP']/@rate").is_err());
+        assert!(select("//Cube[@currency='USD']/@missing").is_err());
+        assert!(select("//Cube").is_err());
+    }
+
+    #[test]
+    fn test_parse_selector() {
+        for invalid in [
+            "Cube",
+            "/",
+            "//@rate",
+            "/Cube[0]",
+            "/Cube[@a=b]",
+            "/Cube[",
+            "/Cube//@a",
+        ] {
+            assert!(Selector::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_to_json_limits() {
+        assert!(to_json(b"<a><b><c/></b></a>", 2).is_err());
+        assert!(to_json(b"<!DOCTYPE a [<!ENTITY x \"y\">]><a>&x;</a>", 32).is_err());
+        assert!(to_json(b"not xml", 32).is_err());
+    }
+}
@@ -0,0 +1,394 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Mock Sui RPC and upstream APIs, and helpers running the real axum app
+//! against them, shared by the integration tests.
+#![allow(dead_code)]
+
+use fastcrypto::ed25519::{Ed25519KeyPair, Ed25519PublicKey, Ed25519Signature};
+use fastcrypto::encoding::{Base64, Encoding, Hex};
+use fastcrypto::traits::{KeyPair, Signer, ToFromBytes, VerifyingKey};
+use nautilus_server::app::PriceFeedResponse;
+use nautilus_server::common::{
+    IntentMessage, IntentScope, ProcessedDataResponse, SignatureEnvelope,
+    SignatureScheme, TimestampSource, PAYLOAD_SCHEMA_VERSION,
+};
+use nautilus_server::config::{Config, Response, Sui};
+use nautilus_server::types::PriceFeedObject;
+use nautilus_server::{router, AppState};
+use rand::{rngs::StdRng, SeedableRng};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use sui_sdk_types::{ObjectDigest, ObjectId, TransactionDigest};
+use wiremock::matchers::{body_partial_json, method, path};
+use wiremock::{Mock, MockBuilder, MockServer, Request, Respond, ResponseTemplate};
+
+pub const PACKAGE_ID: &str = "0x3c15ce11b86d364572f00a40b508d4a80f06d213f37e6b77db3932ffec5c7127";
+pub const FEED_ID: &str = "0xb2b928c198e2037b5116c4d51ce90a61d534912e49c44d340fab1f8ed3de7e50";
+pub const ORACLE_ID: &str = "0x0e5d8e5d6a1a5f7b3b6b1d7f1b9e7c4b6f6a4b8e6c2d1a0f9e8d7c6b5a4f3e2d";
+/// Version and digest of the mocked PriceFeed object, as the RPC renders them
+pub const FEED_VERSION: &str = "42";
+pub const FEED_DIGEST: &str = "8Bcd2mPaJ9tzjmeNFb1eVXumVnjzPNm4ZAvdtmM1AbUe";
+
+/// Time the fixed vectors below are signed at.
+pub const VECTOR_TIMESTAMP_MS: u64 = 1744038900000;
+/// Signed bytes of a `PriceFeed` update of 100.5 for `FEED_ID`, also checked
+/// by `test_integration_vectors` in `move/app/sources/oracle_builder.move`.
+pub const PRICE_FEED_VECTOR: &str = "0020b1d11096010000423078306535643865356436613161356637623362366231643766316239653763346236663661346238653663326431613066396538643763366235613466336532644230786232623932386331393865323033376235313136633464353163653930613631643533343931326534396334346433343066616231663865643364653765353080d406570200000020b1d11096010000";
+/// Same for a `PriceFeedExtended` update of 100.5 signed with the upstream's
+/// timestamp, 1 s before `VECTOR_TIMESTAMP_MS`, and rounded half up.
+pub const PRICE_FEED_EXTENDED_VECTOR: &str = "0d38add11096010000423078306535643865356436613161356637623362366231643766316239653763346236663661346238653663326431613066396538643763366235613466336532644230786232623932386331393865323033376235313136633464353163653930613631643533343931326534396334346433343066616231663865643364653765353080d406570200000038add1109601000000000138add110960100000001040000";
+
+/// Deterministic keypair so signatures can be recomputed by the test.
+pub fn test_keypair() -> Ed25519KeyPair {
+    Ed25519KeyPair::generate(&mut StdRng::seed_from_u64(42))
+}
+
+pub fn test_config(rpc_url: &str) -> Config {
+    Config {
+        sui: Sui {
+            rpc_url: rpc_url.to_string(),
+            oracle_builder_package_id: PACKAGE_ID.to_string(),
+            ..Default::default()
+        },
+        response: Response {
+            price_decimals: 8,
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
+/// On-chain PriceFeed fields as returned by `sui_getObject`.
+pub fn price_feed_fields(underlying_url: &str, response_field: &str) -> Value {
+    json!({
+        "oracle_id": ORACLE_ID,
+        "is_valid": true,
+        "api_key": null,
+        "api_key_config": null,
+        "underlying_url": underlying_url,
+        "response_field": response_field,
+        "live_url": underlying_url,
+    })
+}
+
+/// 32 bytes of a `0x` hex object id, which may omit leading zeros.
+pub fn object_id_bytes(object_id: &str) -> [u8; 32] {
+    let hex = format!("{:0>64}", object_id.trim_start_matches("0x"));
+    Hex::decode(&hex).unwrap().try_into().unwrap()
+}
+
+/// PriceFeed object with `fields` as the `data` of a `sui_getObject` result,
+/// both rendered and as the BCS the server decodes.
+pub fn price_feed_object(object_id: &str, fields: Value) -> Value {
+    let string = |name: &str| fields[name].as_str().map(str::to_string);
+    let object = PriceFeedObject {
+        id: object_id_bytes(object_id),
+        oracle_id: object_id_bytes(fields["oracle_id"].as_str().unwrap()),
+        is_valid: fields["is_valid"].as_bool().unwrap(),
+        api_key: string("api_key"),
+        api_key_config: string("api_key_config"),
+        underlying_url: string("underlying_url").unwrap(),
+        response_field: string("response_field").unwrap(),
+        live_url: string("live_url").unwrap(),
+        timestamp_field: string("timestamp_field"),
+        transform_hash: string("transform_hash"),
+    };
+    let object_type = format!("{}::oracle_builder::PriceFeed", PACKAGE_ID);
+    json!({
+        "objectId": object_id,
+        "version": FEED_VERSION,
+        "digest": FEED_DIGEST,
+        "type": object_type,
+        "content": {
+            "dataType": "moveObject",
+            "fields": fields,
+        },
+        "bcs": {
+            "dataType": "moveObject",
+            "type": object_type,
+            "hasPublicTransfer": false,
+            "version": FEED_VERSION.parse::<u64>().unwrap(),
+            "bcsBytes": Base64::encode(object.to_bcs().unwrap()),
+        },
+    })
+}
+
+/// Answers `sui_multiGetObjects` with the PriceFeed objects it knows, by id,
+/// and a not found error for any other.
+pub struct MultiGetPriceFeeds(pub HashMap<String, Value>);
+
+impl Respond for MultiGetPriceFeeds {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body: Value = serde_json::from_slice(&request.body).unwrap();
+        let results: Vec<Value> = body["params"][0]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|object_id| {
+                let object_id = object_id.as_str().unwrap();
+                match self.0.get(object_id) {
+                    Some(fields) => json!({ "data": price_feed_object(object_id, fields.clone()) }),
+                    None => json!({ "error": { "code": "notExists", "object_id": object_id } }),
+                }
+            })
+            .collect();
+        rpc_result(json!(results))
+    }
+}
+
+/// Mock of `rpc_method` calls on the Sui RPC.
+pub fn sui_call(rpc_method: &str) -> MockBuilder {
+    Mock::given(method("POST")).and(body_partial_json(json!({ "method": rpc_method })))
+}
+
+/// Mock of `sui_getObject` calls for `object_id` on the Sui RPC.
+pub fn sui_object(object_id: &str) -> MockBuilder {
+    Mock::given(method("POST")).and(body_partial_json(json!({
+        "method": "sui_getObject",
+        "params": [object_id],
+    })))
+}
+
+/// Sui RPC answer carrying `result`.
+pub fn rpc_result(result: Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "result": result,
+    }))
+}
+
+/// Sui RPC answer carrying an error.
+pub fn rpc_error(code: i64, message: &str) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "error": { "code": code, "message": message },
+    }))
+}
+
+/// Mount `sui_getObject` and `sui_multiGetObjects` responses for `FEED_ID`
+/// on the mock Sui RPC.
+pub async fn mount_price_feed(sui: &MockServer, fields: Value) {
+    sui_object(FEED_ID)
+        .respond_with(rpc_result(json!({ "data": price_feed_object(FEED_ID, fields.clone()) })))
+        .mount(sui)
+        .await;
+    sui_call("sui_multiGetObjects")
+        .respond_with(MultiGetPriceFeeds(HashMap::from([(FEED_ID.to_string(), fields)])))
+        .mount(sui)
+        .await;
+}
+
+/// Mount the on-chain `Clock` reading `clock_ms` on the mock Sui RPC.
+pub async fn mount_sui_clock(sui: &MockServer, clock_ms: u64) {
+    sui_object("0x6")
+        .respond_with(rpc_result(json!({
+            "data": {
+                "objectId": "0x6",
+                "type": "0x2::clock::Clock",
+                "content": {
+                    "dataType": "moveObject",
+                    "fields": { "id": { "id": "0x6" }, "timestamp_ms": clock_ms.to_string() },
+                },
+            },
+        })))
+        .mount(sui)
+        .await;
+}
+
+/// Shared `Enclave` object pushes are verified against.
+pub const ENCLAVE_ID: &str = "0x5e1f5c3c2a6e0b7d4e9f1a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f";
+
+/// Shared `PushLease` object electing the pushing instance.
+pub const LEASE_ID: &str = "0x1ea5e1ea5e1ea5e1ea5e1ea5e1ea5e1ea5e1ea5e1ea5e1ea5e1ea5e1ea5e1ea5";
+
+/// Mount the `LEASE_ID` object on `sui`, held by `holder` until
+/// `expires_at_ms`.
+pub async fn mount_push_lease(sui: &MockServer, holder: &str, expires_at_ms: u64) {
+    sui_object(LEASE_ID)
+        .respond_with(rpc_result(json!({
+            "data": {
+                "objectId": LEASE_ID,
+                "type": format!("{}::oracle_builder::PushLease", PACKAGE_ID),
+                "owner": { "Shared": { "initial_shared_version": 20 } },
+                "content": {
+                    "dataType": "moveObject",
+                    "fields": { "holder": holder, "expires_at_ms": expires_at_ms.to_string() },
+                },
+            },
+        })))
+        .mount(sui)
+        .await;
+}
+
+/// Effects of every mocked dry run and executed push.
+pub fn push_effects() -> Value {
+    json!({
+        "status": { "status": "success" },
+        "gasUsed": {
+            "computationCost": "1000000",
+            "storageCost": "3000000",
+            "storageRebate": "2000000",
+            "nonRefundableStorageFee": "0",
+        },
+    })
+}
+
+/// A SUI coin as `suix_getCoins` lists it.
+pub fn gas_coin(id: u8, balance: u64) -> Value {
+    json!({
+        "coinType": "0x2::sui::SUI",
+        "coinObjectId": ObjectId::new([id; 32]).to_string(),
+        "version": "3",
+        "digest": ObjectDigest::new([id; 32]).to_base58(),
+        "balance": balance.to_string(),
+    })
+}
+
+/// Digest of every mocked executed push.
+pub fn push_digest() -> String {
+    TransactionDigest::new([9; 32]).to_base58()
+}
+
+/// Mount what push mode reads on the mock Sui RPC: a gas price of 750, the
+/// `ENCLAVE_ID` object, a large coin and a small one, and dry runs.
+pub async fn mount_push_chain(sui: &MockServer) {
+    sui_call("suix_getReferenceGasPrice")
+        .respond_with(rpc_result(json!("750")))
+        .mount(sui)
+        .await;
+    sui_object(ENCLAVE_ID)
+        .respond_with(rpc_result(json!({
+            "data": {
+                "objectId": ENCLAVE_ID,
+                "owner": { "Shared": { "initial_shared_version": 12 } },
+            },
+        })))
+        .mount(sui)
+        .await;
+    sui_call("suix_getCoins")
+        .respond_with(rpc_result(json!({
+            "data": [gas_coin(1, 1_000), gas_coin(2, 5_000_000_000)],
+            "hasNextPage": false,
+            "nextCursor": null,
+        })))
+        .mount(sui)
+        .await;
+    sui_call("sui_dryRunTransactionBlock")
+        .respond_with(rpc_result(json!({ "effects": push_effects() })))
+        .mount(sui)
+        .await;
+}
+
+/// Bodies of the `rpc_method` calls `sui` received.
+pub async fn sui_calls(sui: &MockServer, rpc_method: &str) -> Vec<Value> {
+    sui.received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_slice::<Value>(&request.body).unwrap())
+        .filter(|body| body["method"] == rpc_method)
+        .collect()
+}
+
+/// Start a mock Sui RPC and a mock upstream API.
+pub async fn start_mocks() -> (MockServer, MockServer) {
+    (MockServer::start().await, MockServer::start().await)
+}
+
+/// Mock of `GET route` on an upstream API answering `body`.
+pub fn upstream_json(route: &str, body: Value) -> Mock {
+    Mock::given(method("GET"))
+        .and(path(route))
+        .respond_with(ResponseTemplate::new(200).set_body_json(body))
+}
+
+/// On-chain fields of a PriceFeed reading `price` from `GET /price` on
+/// `upstream`.
+pub fn upstream_price_fields(upstream: &MockServer) -> Value {
+    price_feed_fields(&format!("{}/price", upstream.uri()), "price")
+}
+
+/// Mount `GET /price` answering `body` on `upstream`, and `FEED_ID` reading
+/// its `price` on `sui`.
+pub async fn mount_upstream_feed(sui: &MockServer, upstream: &MockServer, body: Value) {
+    upstream_json("/price", body).mount(upstream).await;
+    mount_price_feed(sui, upstream_price_fields(upstream)).await;
+}
+
+/// Start the real app on an ephemeral port and return its base URL.
+pub async fn spawn_app(config: Config) -> String {
+    serve(AppState::from_config(test_keypair(), config).await.unwrap()).await
+}
+
+/// Serve `state` on an ephemeral port and return its base URL.
+pub async fn serve(state: Arc<AppState>) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, router(state).into_make_service())
+            .await
+            .unwrap();
+    });
+    format!("http://{}", addr)
+}
+
+pub async fn post_process_data(app: &str, price_feed_id: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/process_data", app))
+        .json(&json!({ "payload": { "price_feed_id": price_feed_id } }))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Recompute the signing payload for the expected data and check that both the
+/// returned message and signature match it byte-for-byte.
+pub fn assert_signed(body: &Value, expected_price: u64) {
+    let signed: ProcessedDataResponse<IntentMessage<PriceFeedResponse>> =
+        serde_json::from_value(body.clone()).unwrap();
+    let timestamp_ms = signed.response.timestamp_ms;
+    assert_eq!(signed.response.data.timestamp_ms, timestamp_ms);
+
+    let expected = IntentMessage::new(
+        PriceFeedResponse {
+            oracle_id: ORACLE_ID.to_string(),
+            price_feed_id: FEED_ID.to_string(),
+            price: expected_price,
+            timestamp_ms,
+            rate_of_change: None,
+            market_closed: false,
+            source_timestamp_ms: None,
+            decimals: None,
+            rounding: None,
+            feed_object: None,
+            params: BTreeMap::new(),
+        },
+        timestamp_ms,
+        IntentScope::PriceFeed,
+    );
+    let expected_bytes = bcs::to_bytes(&expected).unwrap();
+    assert_eq!(bcs::to_bytes(&signed.response).unwrap(), expected_bytes);
+
+    let kp = test_keypair();
+    let expected_sig = kp.sign(&expected_bytes);
+    assert_eq!(signed.signature, Hex::encode(&expected_sig));
+
+    let sig = Ed25519Signature::from_bytes(&Hex::decode(&signed.signature).unwrap()).unwrap();
+    let pk: &Ed25519PublicKey = kp.public();
+    assert!(pk.verify(&expected_bytes, &sig).is_ok());
+
+    assert_eq!(
+        signed.envelope,
+        Some(SignatureEnvelope {
+            signature_scheme: SignatureScheme::Ed25519,
+            intent_scope: IntentScope::PriceFeed,
+            payload_schema_version: PAYLOAD_SCHEMA_VERSION,
+            bcs_length: expected_bytes.len() as u64,
+            timestamp_source: Some(TimestampSource::Enclave),
+        })
+    );
+}
@@ -0,0 +1,341 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! End-to-end tests of the oracle modules beyond price feeds: weather,
+//! sports, NFT floors, proof of reserve and scaling expressions.
+
+mod common;
+
+use common::*;
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::Signer;
+use nautilus_server::common::{IntentMessage, IntentScope, ProcessedDataResponse};
+use nautilus_server::config::FeedConfig;
+use nautilus_server::expression::Scale;
+use nautilus_server::nft::{NftFloorPriceResponse, NftMarketplace};
+use nautilus_server::reserves::{AccountBalance, ReserveResponse};
+use nautilus_server::sports::{SportsProvider, SportsResultResponse};
+use nautilus_server::weather::{WeatherMetric, WeatherResponse};
+use serde_json::{json, Value};
+use wiremock::matchers::{body_partial_json, header, method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn test_weather_signs_reading() {
+    const WEATHER_ID: &str = "0x5eba";
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/current"))
+        .and(query_param("q", "San Francisco"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "current": { "temp_c": -3.254 } })))
+        .mount(&upstream)
+        .await;
+    Mock::given(method("POST"))
+        .and(body_partial_json(json!({ "params": [WEATHER_ID] })))
+        .respond_with(rpc_result(json!({
+            "data": {
+                "objectId": WEATHER_ID,
+                "type": format!("{}::oracle_builder::WeatherFeed", PACKAGE_ID),
+                "content": {
+                    "fields": {
+                        "oracle_id": ORACLE_ID,
+                        "is_valid": true,
+                        "location": "San Francisco",
+                        "provider": format!("{}/current?q={{location}}", upstream.uri()),
+                        "field": "current.temp_c",
+                        "metric": "temperature",
+                    },
+                },
+            },
+        })))
+        .mount(&sui)
+        .await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/weather", app))
+        .json(&json!({ "payload": { "weather_feed_id": WEATHER_ID } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<WeatherResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.intent, IntentScope::Weather);
+    assert_eq!(signed.response.data.metric, WeatherMetric::Temperature);
+    assert_eq!(signed.response.data.value, -325);
+    assert_eq!(signed.response.data.decimals, 2);
+
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+}
+
+#[tokio::test]
+async fn test_sports_result_signed() {
+    let upstream = MockServer::start().await;
+    upstream_json(
+        "/fixtures/42",
+        json!({
+            "fixture": { "status": "FT" },
+            "goals": { "home": 3, "away": 1 },
+        }),
+    )
+    .mount(&upstream)
+    .await;
+
+    let mut config = test_config("http://localhost:9000");
+    config.sports.insert(
+        "football".to_string(),
+        SportsProvider {
+            url: format!("{}/fixtures/{{fixture_id}}", upstream.uri()),
+            home_score_field: "goals.home".to_string(),
+            away_score_field: "goals.away".to_string(),
+            status_field: "fixture.status".to_string(),
+            settled_statuses: vec!["FT".to_string()],
+            api_key_env: None,
+            api_key_config: None,
+        },
+    );
+    let app = spawn_app(config).await;
+
+    let post = |provider: &'static str| {
+        reqwest::Client::new()
+            .post(format!("{}/sports_result", app))
+            .json(&json!({ "payload": { "provider": provider, "fixture_id": "42" } }))
+            .send()
+    };
+
+    let response = post("football").await.unwrap();
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<SportsResultResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.intent, IntentScope::SportsResult);
+    let result = &signed.response.data;
+    assert_eq!((result.home_score, result.away_score), (3, 1));
+    assert_eq!(result.status, "FT");
+    assert!(result.settled);
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+
+    assert_eq!(post("cricket").await.unwrap().status(), 400);
+}
+
+#[tokio::test]
+async fn test_nft_floor_price_signed() {
+    let upstream = MockServer::start().await;
+    upstream_json(
+        "/collections/sui-punks/stats",
+        json!({
+            "total": { "floor_price": 12.5, "floor_price_symbol": "SUI" },
+        }),
+    )
+    .mount(&upstream)
+    .await;
+
+    let mut config = test_config("http://localhost:9000");
+    config.nft.insert(
+        "market".to_string(),
+        NftMarketplace {
+            url: format!("{}/collections/{{collection_id}}/stats", upstream.uri()),
+            floor_price_field: "total.floor_price".to_string(),
+            currency: None,
+            currency_field: Some("total.floor_price_symbol".to_string()),
+            decimals: Some(9),
+            api_key_env: None,
+            api_key_config: None,
+        },
+    );
+    let app = spawn_app(config).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/nft_floor_price", app))
+        .json(&json!({ "payload": { "marketplace": "market", "collection_id": "sui-punks" } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<NftFloorPriceResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.intent, IntentScope::NftFloorPrice);
+    let floor = &signed.response.data;
+    assert_eq!(floor.collection_id, "sui-punks");
+    assert_eq!(floor.currency, "SUI");
+    assert_eq!((floor.floor_price, floor.decimals), (12500000000, 9));
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+}
+
+#[tokio::test]
+async fn test_proof_of_reserve_sums_accounts() {
+    const RESERVE_ID: &str = "0x2e5e";
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/exchange/balance"))
+        .and(header("x-api-key", "secret"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "USD": { "total": "1500.25" } })))
+        .mount(&upstream)
+        .await;
+    upstream_json("/custodian/accounts/7", json!({ "balance": 2500 })).mount(&upstream).await;
+    Mock::given(method("POST"))
+        .and(body_partial_json(json!({ "params": [RESERVE_ID] })))
+        .respond_with(rpc_result(json!({
+            "data": {
+                "objectId": RESERVE_ID,
+                "type": format!("{}::oracle_builder::ReserveConfig", PACKAGE_ID),
+                "content": {
+                    "fields": {
+                        "oracle_id": ORACLE_ID,
+                        "is_valid": true,
+                        "asset": "USD",
+                        "accounts": [
+                            {
+                                "type": format!("{}::oracle_builder::ReserveAccount", PACKAGE_ID),
+                                "fields": {
+                                    "label": "exchange",
+                                    "url": format!("{}/exchange/balance", upstream.uri()),
+                                    "balance_field": "USD.total",
+                                    "api_key": "secret",
+                                    "api_key_config": "x-api-key",
+                                },
+                            },
+                            {
+                                "type": format!("{}::oracle_builder::ReserveAccount", PACKAGE_ID),
+                                "fields": {
+                                    "label": "custodian",
+                                    "url": format!("{}/custodian/accounts/7", upstream.uri()),
+                                    "balance_field": "balance",
+                                    "api_key": null,
+                                    "api_key_config": null,
+                                },
+                            },
+                        ],
+                    },
+                },
+            },
+        })))
+        .mount(&sui)
+        .await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/proof_of_reserve", app))
+        .json(&json!({ "payload": { "reserve_config_id": RESERVE_ID } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<ReserveResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.intent, IntentScope::ProofOfReserve);
+    assert_eq!(signed.response.data.asset, "USD");
+    assert_eq!(signed.response.data.total_reserves, 4000250000);
+    assert_eq!(
+        signed.response.data.accounts,
+        vec![
+            AccountBalance {
+                label: "exchange".to_string(),
+                balance: 1500250000,
+            },
+            AccountBalance {
+                label: "custodian".to_string(),
+                balance: 2500000000,
+            },
+        ]
+    );
+
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+}
+
+#[tokio::test]
+async fn test_process_data_scaling_expression() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json(
+        "/price",
+        json!({
+            "parsed": [{ "price": { "price": "6543210000", "expo": -8 } }],
+        }),
+    )
+    .mount(&upstream)
+    .await;
+    mount_price_feed(
+        &sui,
+        price_feed_fields(&format!("{}/price", upstream.uri()), "parsed[0].price.price"),
+    )
+    .await;
+
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            scale: Some(Scale {
+                expression: "value * 10^expo".to_string(),
+                variables: [("expo".to_string(), "parsed[0].price.expo".to_string())].into(),
+            }),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+
+    // 65.4321 scaled by the configured 8 decimals.
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 6543210000);
+}
+
+#[tokio::test]
+async fn test_dry_run_returns_pipeline_values() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json(
+        "/price",
+        json!({
+            "parsed": [{ "price": { "price": "6543210000", "expo": -8 } }],
+        }),
+    )
+    .mount(&upstream)
+    .await;
+    mount_price_feed(
+        &sui,
+        price_feed_fields(&format!("{}/price", upstream.uri()), "parsed[0].price.price"),
+    )
+    .await;
+
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            scale: Some(Scale {
+                expression: "value * 10^expo".to_string(),
+                variables: [("expo".to_string(), "parsed[0].price.expo".to_string())].into(),
+            }),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/dry_run", app))
+        .json(&json!({ "payload": { "price_feed_id": FEED_ID } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["excerpt"], "6543210000");
+    assert_eq!(body["extracted_value"], "6543210000");
+    let scaled: f64 = body["scaled_value"].as_str().unwrap().parse().unwrap();
+    assert_eq!(scaled, 65.4321);
+    assert_eq!(body["converted_value"], Value::Null);
+    assert_eq!(body["price"], "6543210000");
+    assert_eq!(body["decimals"], 8);
+    assert!(body.get("signature").is_none());
+
+    // Nothing was recorded for the feed.
+    let history: Value = reqwest::get(format!("{}/history/{}", app, FEED_ID))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(history["observations"], json!([]));
+}
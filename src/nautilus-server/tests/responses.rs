@@ -0,0 +1,838 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! End-to-end tests of signed responses: their layouts and timestamps, fixed
+//! vectors shared with the Move tests, history and the admin views.
+
+mod common;
+
+use common::*;
+use fastcrypto::ed25519::{Ed25519KeyPair, Ed25519PublicKey, Ed25519Signature};
+use fastcrypto::encoding::{Base64, Encoding, Hex};
+use fastcrypto::hash::{Blake2b256, HashFunction};
+use fastcrypto::traits::{KeyPair, Signer, ToFromBytes, VerifyingKey};
+use futures_util::{SinkExt, StreamExt};
+use nautilus_server::app::PriceFeedResponse;
+use nautilus_server::common::{
+    IntentMessage, IntentScope, ProcessedDataResponse, RoundingMode, TimestampSource,
+};
+use nautilus_server::config::FeedConfig;
+use nautilus_server::market_hours::{MarketHours, Session};
+use nautilus_server::mirrors::Mirror;
+use nautilus_server::persistence::Backend;
+use nautilus_server::rates::RateFeedResponse;
+use nautilus_server::stats::{PriceStatsResponse, WindowStats};
+use nautilus_server::wide::WidePriceFeedResponse;
+use nautilus_server::{mirrors, refresher, subscription, AppState};
+use rand::{rngs::StdRng, SeedableRng};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tokio_tungstenite::tungstenite::Message as WsMessage;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn test_process_data_gzip_cbor() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 42 })).await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/process_data", app))
+        .header("accept", "application/cbor")
+        .header("accept-encoding", "gzip")
+        .json(&json!({ "payload": { "price_feed_id": FEED_ID } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/cbor");
+    assert_eq!(response.headers()["content-encoding"], "gzip");
+
+    let compressed = response.bytes().await.unwrap();
+    let mut cbor = Vec::new();
+    std::io::Read::read_to_end(&mut flate2::read::GzDecoder::new(&compressed[..]), &mut cbor)
+        .unwrap();
+    let body: Value = ciborium::from_reader(cbor.as_slice()).unwrap();
+    assert_signed(&body, 4200000000);
+}
+
+#[tokio::test]
+async fn test_process_data_sui_clock_timestamp() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 42 })).await;
+    let clock_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+        - 2_000;
+    mount_sui_clock(&sui, clock_ms).await;
+
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            timestamp_source: TimestampSource::SuiClock,
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<PriceFeedResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.timestamp_ms, clock_ms);
+    assert_eq!(signed.response.data.timestamp_ms, clock_ms);
+    assert_eq!(
+        signed.envelope.unwrap().timestamp_source,
+        Some(TimestampSource::SuiClock)
+    );
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+}
+
+/// Assert `body` is a signed price feed update over exactly `vector`.
+fn assert_signed_vector(body: &Value, vector: &str) {
+    let signed: ProcessedDataResponse<IntentMessage<PriceFeedResponse>> =
+        serde_json::from_value(body.clone()).unwrap();
+    assert_eq!(Hex::encode(bcs::to_bytes(&signed.response).unwrap()), vector);
+    let sig = Ed25519Signature::from_bytes(&Hex::decode(&signed.signature).unwrap()).unwrap();
+    let pk: Ed25519PublicKey = test_keypair().public().clone();
+    assert!(pk.verify(&Hex::decode(vector).unwrap(), &sig).is_ok());
+}
+
+#[tokio::test]
+async fn test_process_data_signs_fixed_vectors() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": "100.5" })).await;
+    mount_sui_clock(&sui, VECTOR_TIMESTAMP_MS).await;
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            timestamp_source: TimestampSource::SuiClock,
+            max_staleness_secs: Some(u64::MAX),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed_vector(&response.json().await.unwrap(), PRICE_FEED_VECTOR);
+
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "price": "100.5", "ts": VECTOR_TIMESTAMP_MS - 1_000 }))
+        .mount(&upstream)
+        .await;
+    let mut fields = upstream_price_fields(&upstream);
+    fields["timestamp_field"] = json!("ts");
+    mount_price_feed(&sui, fields).await;
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            timestamp_source: TimestampSource::Upstream,
+            max_staleness_secs: Some(u64::MAX),
+            rounding: Some(RoundingMode::HalfUp),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed_vector(&response.json().await.unwrap(), PRICE_FEED_EXTENDED_VECTOR);
+}
+
+#[tokio::test]
+async fn test_process_data_serves_hot_feed() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "price": 100 }))
+        .up_to_n_times(1)
+        .mount(&upstream)
+        .await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 200 })).await;
+
+    let mut config = test_config(&sui.uri());
+    config.refresher.hot_feeds = vec![FEED_ID.to_string()];
+    config.refresher.max_age_ms = 60_000;
+    let state = AppState::from_config(test_keypair(), config).await.unwrap();
+    refresher::refresh_feeds(&state).await;
+    let app = serve(state).await;
+
+    // Both requests get the price signed in the background, untouched
+    let first: Value = post_process_data(&app, FEED_ID).await.json().await.unwrap();
+    let second: Value = post_process_data(&app, FEED_ID).await.json().await.unwrap();
+    assert_signed(&first, 10000000000);
+    assert_eq!(first["signature"], second["signature"]);
+    assert_eq!(upstream.received_requests().await.unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_subscription_drops_signed_price_of_changed_feed() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+
+    // Fullnode WebSocket that confirms the subscription, then reports a
+    // transaction mutating the feed once told to
+    let ws_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let ws_url = format!("ws://{}", ws_listener.local_addr().unwrap());
+    let (acked_tx, acked_rx) = tokio::sync::oneshot::channel();
+    let (notify_tx, notify_rx) = tokio::sync::oneshot::channel::<()>();
+    tokio::spawn(async move {
+        let (stream, _) = ws_listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let Some(Ok(WsMessage::Text(request))) = ws.next().await else {
+            panic!("expected a subscription request");
+        };
+        let request: Value = serde_json::from_str(&request).unwrap();
+        assert_eq!(request["method"], "suix_subscribeTransaction");
+        assert_eq!(request["params"][0]["MoveFunction"]["package"], PACKAGE_ID);
+        let ack = json!({ "jsonrpc": "2.0", "id": 1, "result": 7 });
+        ws.send(WsMessage::Text(ack.to_string())).await.unwrap();
+        acked_tx.send(()).unwrap();
+
+        notify_rx.await.unwrap();
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "suix_subscribeTransaction",
+            "params": {
+                "subscription": 7,
+                "result": {
+                    "status": { "status": "success" },
+                    "mutated": [{ "owner": { "Shared": {} }, "reference": { "objectId": FEED_ID } }],
+                },
+            },
+        });
+        ws.send(WsMessage::Text(notification.to_string())).await.unwrap();
+        std::future::pending::<()>().await;
+    });
+
+    let mut config = test_config(&sui.uri());
+    config.sui.ws_url = Some(ws_url);
+    config.refresher.hot_feeds = vec![FEED_ID.to_string()];
+    let state = AppState::from_config(test_keypair(), config).await.unwrap();
+    subscription::spawn_subscriber(&state);
+    acked_rx.await.unwrap();
+    // Let the subscriber handle the confirmation, which drops every price
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    refresher::refresh_feeds(&state).await;
+    assert!(state.signed_prices.get(FEED_ID, u64::MAX, 0).is_some());
+    notify_tx.send(()).unwrap();
+    for _ in 0..100 {
+        if state.signed_prices.get(FEED_ID, u64::MAX, 0).is_none() {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("the signed price of the changed feed was not dropped");
+}
+
+#[tokio::test]
+async fn test_verify_batch() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 42 })).await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+
+    let signed: Value = post_process_data(&app, FEED_ID).await.json().await.unwrap();
+    let mut tampered = signed.clone();
+    tampered["response"]["data"]["price"] = json!(1);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/verify/batch", app))
+        .json(&json!({ "payload": { "items": [signed, tampered] } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let public_key = Hex::encode(test_keypair().public().as_bytes());
+
+    assert_eq!(body["results"][0]["valid"], true);
+    assert_eq!(body["results"][0]["within_key_window"], true);
+    assert_eq!(body["results"][0]["public_key"], public_key);
+    assert_eq!(body["results"][1]["valid"], false);
+    assert!(body["results"][1]["error"].is_string());
+    assert_eq!(body["keys"], json!([{ "public_key": public_key }]));
+}
+
+#[tokio::test]
+async fn test_feed_history() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "price": 100 }))
+        .up_to_n_times(1)
+        .mount(&upstream)
+        .await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 101 })).await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+
+    let first: Value = post_process_data(&app, FEED_ID).await.json().await.unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    let second: Value = post_process_data(&app, FEED_ID).await.json().await.unwrap();
+    let first_ms = first["response"]["timestamp_ms"].as_u64().unwrap();
+    let second_ms = second["response"]["timestamp_ms"].as_u64().unwrap();
+
+    let history = |query: String| {
+        let url = format!("{}/history/{}{}", app, FEED_ID, query);
+        async move { reqwest::get(url).await.unwrap().json::<Value>().await.unwrap() }
+    };
+    let all = history(String::new()).await;
+    assert_eq!(
+        all["observations"],
+        json!([
+            { "price": 10000000000u64, "timestamp_ms": first_ms },
+            { "price": 10100000000u64, "timestamp_ms": second_ms },
+        ])
+    );
+    let later = history(format!("?from={}", first_ms + 1)).await;
+    assert_eq!(later["observations"].as_array().unwrap().len(), 1);
+    assert_eq!(later["observations"][0]["timestamp_ms"], second_ms);
+    let none = history(format!("?to={}", first_ms - 1)).await;
+    assert!(none["observations"].as_array().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_process_data_wide() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/supply", json!({ "supply": "12345.678" })).mount(&upstream).await;
+    mount_price_feed(&sui, price_feed_fields(&format!("{}/supply", upstream.uri()), "supply")).await;
+    let mut config = test_config(&sui.uri());
+    config.response.price_decimals = 18;
+    config.response.verify_extraction = true;
+    let app = spawn_app(config).await;
+
+    // Past u64 at 18 decimals.
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("/process_data_wide"));
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/process_data_wide", app))
+        .json(&json!({ "payload": { "price_feed_id": FEED_ID } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<WidePriceFeedResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.intent, IntentScope::PriceFeedWide);
+    assert_eq!(signed.response.data.price, 12_345_678_000_000_000_000_000);
+    assert_eq!(signed.response.data.exponent, 18);
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+}
+
+#[tokio::test]
+async fn test_process_rate_negative() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/funding", json!({ "fundingRate": "-0.0125" })).mount(&upstream).await;
+    mount_price_feed(&sui, price_feed_fields(&format!("{}/funding", upstream.uri()), "fundingRate")).await;
+    let mut config = test_config(&sui.uri());
+    config.response.verify_extraction = true;
+    let app = spawn_app(config).await;
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("/process_rate"));
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/process_rate", app))
+        .json(&json!({ "payload": { "price_feed_id": FEED_ID } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<RateFeedResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.intent, IntentScope::RateFeed);
+    assert_eq!(signed.response.data.value(), -1_250_000);
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+}
+
+#[tokio::test]
+async fn test_price_stats_signed() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "price": 100 }))
+        .up_to_n_times(1)
+        .mount(&upstream)
+        .await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 200 })).await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let client = reqwest::Client::new();
+    let stats = |windows_ms: Value| {
+        client
+            .post(format!("{}/stats", app))
+            .json(&json!({ "payload": { "price_feed_id": FEED_ID, "windows_ms": windows_ms } }))
+            .send()
+    };
+
+    // Nothing signed yet.
+    assert_eq!(stats(json!([60_000])).await.unwrap().status(), 400);
+
+    assert_eq!(post_process_data(&app, FEED_ID).await.status(), 200);
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    assert_eq!(post_process_data(&app, FEED_ID).await.status(), 200);
+
+    let response = stats(json!([60_000])).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<PriceStatsResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.intent, IntentScope::PriceStatistics);
+    assert_eq!(
+        signed.response.data.windows,
+        vec![WindowStats {
+            window_ms: 60_000,
+            samples: 2,
+            // alpha = 2/3 moves two thirds of the way from 100 to 200.
+            ema: 16666666667,
+            // ln(2)
+            volatility_bps: 6931,
+        }]
+    );
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+
+    // Mirror deviations share the history store but are not prices.
+    let mirror_id = format!("{}/mirror/a", FEED_ID);
+    let response = client
+        .post(format!("{}/stats", app))
+        .json(&json!({ "payload": { "price_feed_id": mirror_id, "windows_ms": [60_000] } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_admin_routes_require_token() {
+    let sui = MockServer::start().await;
+    let mut config = test_config(&sui.uri());
+    config.admin.token_sha256 = Some(Hex::encode(Sha256::digest(b"admin-token")));
+    let app = spawn_app(config).await;
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{}/admin/key", app)).send().await.unwrap();
+    assert_eq!(response.status(), 401);
+    let response = client
+        .post(format!("{}/admin/pause", app))
+        .bearer_auth("wrong-token")
+        .json(&json!({ "paused": true }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+
+    let key: Value = client
+        .get(format!("{}/admin/key", app))
+        .bearer_auth("admin-token")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(key["mode"], "ephemeral");
+    assert_eq!(key["public_key"], Hex::encode(test_keypair().public().as_bytes()));
+
+    let response = client
+        .post(format!("{}/admin/flush_cache", app))
+        .bearer_auth("admin-token")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    // Public routes stay open
+    assert_eq!(reqwest::get(format!("{}/health_check", app)).await.unwrap().status(), 200);
+}
+
+#[tokio::test]
+async fn test_mirror_comparison_reports_divergent_mirror() {
+    let (sui, upstream) = start_mocks().await;
+    for (route, body) in [
+        ("/price", json!({ "price": 100 })),
+        ("/close", json!({ "price": 100.1 })),
+        ("/far", json!({ "data": { "last": 110 } })),
+    ] {
+        Mock::given(method("GET"))
+            .and(path(route))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .mount(&upstream)
+            .await;
+    }
+    mount_price_feed(&sui, upstream_price_fields(&upstream)).await;
+
+    let mut config = test_config(&sui.uri());
+    let mirror = |name: &str, route: &str, response_field: Option<&str>| Mirror {
+        name: name.to_string(),
+        url: format!("{}{}", upstream.uri(), route),
+        response_field: response_field.map(str::to_string),
+        api_key_env: None,
+        api_key_config: None,
+    };
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            mirrors: vec![
+                mirror("close", "/close", None),
+                mirror("far", "/far", Some("data.last")),
+            ],
+            ..Default::default()
+        },
+    );
+    let state = AppState::from_config(test_keypair(), config).await.unwrap();
+    mirrors::compare_feeds(&state).await;
+    mirrors::compare_feeds(&state).await;
+
+    let app = serve(state).await;
+    let body: Value = reqwest::get(format!("{}/admin/mirrors", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let reported = body["mirrors"].as_array().unwrap();
+    assert_eq!(reported.len(), 2);
+    assert_eq!(reported[0]["mirror"], "far");
+    assert_eq!(reported[0]["chronic"], true);
+    assert_eq!(reported[0]["samples"], 2);
+    assert_eq!(reported[0]["max_deviation_bps"], 1000);
+    assert_eq!(reported[1]["mirror"], "close");
+    assert_eq!(reported[1]["chronic"], false);
+    assert_eq!(reported[1]["mean_deviation_bps"], 10);
+}
+
+#[tokio::test]
+async fn test_served_updates_per_consumer() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+
+    let mut config = test_config(&sui.uri());
+    config
+        .consumers
+        .keys
+        .insert("acme".to_string(), Hex::encode(Sha256::digest(b"acme-key")));
+    let app = spawn_app(config).await;
+
+    let post = |key: &'static str| {
+        reqwest::Client::new()
+            .post(format!("{}/process_data", app))
+            .header("x-consumer-key", key)
+            .json(&json!({ "payload": { "price_feed_id": FEED_ID } }))
+            .send()
+    };
+    let response = post("acme-key").await.unwrap();
+    assert_eq!(response.status(), 200);
+    let signed: Value = response.json().await.unwrap();
+    // Anonymous requests are served but not recorded
+    assert_eq!(post_process_data(&app, FEED_ID).await.status(), 200);
+
+    let response = post("wrong-key").await.unwrap();
+    assert_eq!(response.status(), 401);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "unauthorized");
+
+    let served: Value = reqwest::get(format!("{}/admin/served?consumer=acme&from_ms=0", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let updates = served["updates"].as_array().unwrap();
+    assert_eq!(updates.len(), 1);
+    assert_eq!(updates[0]["module"], "price_feed");
+    assert_eq!(updates[0]["signature"], signed["signature"]);
+    assert_eq!(updates[0]["data"], signed["response"]["data"]);
+}
+
+#[tokio::test]
+async fn test_served_updates_survive_restart() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = test_config(&sui.uri());
+    config
+        .consumers
+        .keys
+        .insert("acme".to_string(), Hex::encode(Sha256::digest(b"acme-key")));
+    config.persistence.backend = Backend::Sqlite;
+    config.persistence.path = dir.path().join("history.db").to_str().unwrap().to_string();
+    let app = spawn_app(config.clone()).await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/process_data", app))
+        .header("x-consumer-key", "acme-key")
+        .json(&json!({ "payload": { "price_feed_id": FEED_ID } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    // Another instance over the same store lists it
+    let app = spawn_app(config).await;
+    let served: Value = reqwest::get(format!("{}/admin/served?consumer=acme", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(served["updates"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn test_process_data_reduced_precision() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": "100.123456789" })).await;
+
+    let mut config = test_config(&sui.uri());
+    config
+        .consumers
+        .keys
+        .insert("acme".to_string(), Hex::encode(Sha256::digest(b"acme-key")));
+    let app = spawn_app(config).await;
+
+    let post = |key: Option<&'static str>| {
+        let mut request = reqwest::Client::new()
+            .post(format!("{}/process_data", app))
+            .json(&json!({ "payload": { "price_feed_id": FEED_ID, "decimals": 4 } }));
+        if let Some(key) = key {
+            request = request.header("x-consumer-key", key);
+        }
+        request.send()
+    };
+    // Anonymous callers only get full precision
+    assert_eq!(post(None).await.unwrap().status(), 401);
+
+    let response = post(Some("acme-key")).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<PriceFeedResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.intent, IntentScope::PriceFeedReducedPrecision);
+    // 100.123456789 at 8 decimals is 10012345678, rounded to 4 decimals
+    assert_eq!(signed.response.data.price, 1001235);
+    assert_eq!(signed.response.data.decimals, Some(4));
+
+    let kp = test_keypair();
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    let signature = Ed25519Signature::from_bytes(&Hex::decode(&signed.signature).unwrap()).unwrap();
+    assert!(kp.public().verify(&bytes, &signature).is_ok());
+}
+
+#[tokio::test]
+async fn test_process_data_wallet_signature() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+
+    // Sui address of an Ed25519 wallet: Blake2b-256 of the flag and public key
+    let wallet = Ed25519KeyPair::generate(&mut StdRng::seed_from_u64(7));
+    let address = Blake2b256::digest([&[0u8][..], wallet.public().as_bytes()].concat());
+    let mut config = test_config(&sui.uri());
+    config.consumers.required = true;
+    config
+        .consumers
+        .wallets
+        .insert("globex".to_string(), format!("0x{}", Hex::encode(address.digest)));
+    let app = spawn_app(config).await;
+
+    let body = json!({ "payload": { "price_feed_id": FEED_ID } }).to_string();
+    let timestamp_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    // Personal message intent, then the BCS encoded message
+    let message = bcs::to_bytes(format!("{}:{}", timestamp_ms, body).as_bytes()).unwrap();
+    let digest = Blake2b256::digest([&[3u8, 0, 0][..], &message].concat());
+    let signature: Ed25519Signature = wallet.sign(&digest.digest);
+    let serialized = [&[0u8][..], signature.as_ref(), wallet.public().as_bytes()].concat();
+
+    let post = |body: String| {
+        reqwest::Client::new()
+            .post(format!("{}/process_data", app))
+            .header("content-type", "application/json")
+            .header("x-sui-signature", Base64::encode(&serialized))
+            .header("x-sui-timestamp-ms", timestamp_ms.to_string())
+            .body(body)
+    };
+    let response = post(body.clone()).send().await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 10000000000);
+
+    // The signature covers the body, and is accepted once
+    let tampered = json!({ "payload": { "price_feed_id": ORACLE_ID } }).to_string();
+    assert_eq!(post(tampered).send().await.unwrap().status(), 401);
+    assert_eq!(post(body).send().await.unwrap().status(), 401);
+    assert_eq!(post_process_data(&app, FEED_ID).await.status(), 401);
+}
+
+#[tokio::test]
+async fn test_process_data_verify_extraction() {
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/price"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "data": [{ "price": "123.456789" }] })),
+        )
+        .mount(&upstream)
+        .await;
+    mount_price_feed(
+        &sui,
+        price_feed_fields(&format!("{}/price", upstream.uri()), "data[0].price"),
+    )
+    .await;
+
+    let mut config = test_config(&sui.uri());
+    config.response.verify_extraction = true;
+    let app = spawn_app(config).await;
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 12345678900);
+}
+
+#[tokio::test]
+async fn test_process_data_source_timestamp() {
+    let (sui, upstream) = start_mocks().await;
+    let now_secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    Mock::given(method("GET"))
+        .and(path("/fresh"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "price": 42, "ts": now_secs })),
+        )
+        .mount(&upstream)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/stale"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "price": 42, "ts": now_secs - 600 })),
+        )
+        .mount(&upstream)
+        .await;
+
+    // Fresh: the upstream timestamp is signed along with the enclave's.
+    let mut fields = price_feed_fields(&format!("{}/fresh", upstream.uri()), "price");
+    fields["timestamp_field"] = json!("ts");
+    mount_price_feed(&sui, fields).await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<PriceFeedResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.data.source_timestamp_ms, Some(now_secs * 1000));
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    let sig = Ed25519Signature::from_bytes(&Hex::decode(&signed.signature).unwrap()).unwrap();
+    assert!(test_keypair().public().verify(&bytes, &sig).is_ok());
+
+    // Stale: ten minutes old against the default 60 second window. The
+    // timestamp path comes from the feed's config this time.
+    sui.reset().await;
+    mount_price_feed(&sui, price_feed_fields(&format!("{}/stale", upstream.uri()), "price")).await;
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            timestamp_field: Some("ts".to_string()),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("old"));
+}
+
+#[tokio::test]
+async fn test_process_data_upstream_limits() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": "1", "pad": "x".repeat(256) })).await;
+
+    let mut config = test_config(&sui.uri());
+    config.upstream_limits.max_body_bytes = 64;
+    let app = spawn_app(config).await;
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("byte limit"));
+
+    let mut config = test_config(&sui.uri());
+    config.upstream_limits.content_types = vec!["text/plain".to_string()];
+    let app = spawn_app(config).await;
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("Content-Type"));
+}
+
+#[tokio::test]
+async fn test_process_data_signing_windows() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 42 })).await;
+
+    let with_window = |open: &str, close: &str| {
+        let mut config = test_config(&sui.uri());
+        config.feeds.insert(
+            FEED_ID.to_string(),
+            FeedConfig {
+                signing_windows: Some(MarketHours {
+                    sessions: vec![Session {
+                        open: open.to_string(),
+                        close: close.to_string(),
+                    }],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        config
+    };
+
+    // An empty window never opens: refused with a typed error and no retry hint.
+    let app = spawn_app(with_window("Mon 00:00", "Mon 00:00")).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 403);
+    assert!(response.headers().get("retry-after").is_none());
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "outside_signing_window");
+
+    // A window spanning the whole week always signs.
+    let app = spawn_app(with_window("Mon 00:00", "Sun 24:00")).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 4200000000);
+}
+
+#[tokio::test]
+async fn test_get_attestation_serves_refreshed_document() {
+    let state = AppState::from_config(test_keypair(), test_config("http://localhost:9000"))
+        .await
+        .unwrap();
+    let public_key = test_keypair().public().as_bytes().to_vec();
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    state
+        .attestation_cache
+        .store(b"cached document".to_vec(), &public_key, now + 60_000);
+
+    let app = serve(state).await;
+
+    // Served from the cache without going to the NSM.
+    let body: Value = reqwest::get(format!("{}/get_attestation", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["attestation"], Hex::encode(b"cached document"));
+}
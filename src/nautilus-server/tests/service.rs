@@ -0,0 +1,1058 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! End-to-end tests of the routes around signing: attestation, payments,
+//! batches, pausing, health and key retirement, and of pushing prices on
+//! chain.
+
+mod common;
+
+use common::*;
+use fastcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+use fastcrypto::encoding::{Base64, Encoding, Hex};
+use fastcrypto::traits::{KeyPair, Signer, ToFromBytes, VerifyingKey};
+use nautilus_server::app::PriceFeedResponse;
+use nautilus_server::common::{IntentMessage, IntentScope, ProcessedDataResponse};
+use nautilus_server::config::FeedConfig;
+use nautilus_server::definition::ExtractionExample;
+use nautilus_server::fees::{FeeQuoteResponse, Priority};
+use nautilus_server::market_hours::{MarketHours, Session};
+use nautilus_server::persistence::Backend;
+use nautilus_server::retirement::KeyRetirement;
+use nautilus_server::sponsor::Sponsor;
+use nautilus_server::{health, push, AppState};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use sui_sdk_types::{Address, Command, Input, ObjectDigest, ObjectId, ObjectReference, Transaction, TransactionKind, UserSignature};
+use wiremock::matchers::{body_partial_json, header, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn test_attestation_rejects_bad_nonce() {
+    let sui = MockServer::start().await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+
+    // Rejected before the NSM is asked for a document.
+    let long_nonce = "ab".repeat(513);
+    for nonce in ["", "not-hex", long_nonce.as_str()] {
+        let response = reqwest::get(format!("{}/attestation?nonce={}", app, nonce))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+    }
+}
+
+#[tokio::test]
+async fn test_scoped_intent_key() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+    let mut config = test_config(&sui.uri());
+    config.key.scoped_intents = vec![IntentScope::PriceFeed];
+    let app = spawn_app(config).await;
+
+    let key: Value = reqwest::get(format!("{}/admin/key", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(key["public_key"], Hex::encode(test_keypair().public().as_bytes()));
+    assert_eq!(key["scoped_keys"][0]["intent_scope"], IntentScope::PriceFeed as u8);
+    let scoped_pk = key["scoped_keys"][0]["public_key"].as_str().unwrap();
+    assert_ne!(scoped_pk, key["public_key"]);
+
+    let body: Value = post_process_data(&app, FEED_ID).await.json().await.unwrap();
+    let signed: ProcessedDataResponse<IntentMessage<PriceFeedResponse>> =
+        serde_json::from_value(body).unwrap();
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    let sig = Ed25519Signature::from_bytes(&Hex::decode(&signed.signature).unwrap()).unwrap();
+    let pk = Ed25519PublicKey::from_bytes(&Hex::decode(scoped_pk).unwrap()).unwrap();
+    assert!(pk.verify(&bytes, &sig).is_ok());
+    assert!(test_keypair().public().verify(&bytes, &sig).is_err());
+
+    // Only intents with a key of their own can be attested to separately
+    let response = reqwest::get(format!("{}/attestation?nonce=ab&intent_scope=4", app))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+
+    // The scoped key is retired by its intent, leaving the main key alone
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/retire_key", app))
+        .json(&json!({ "retire_at_ms": u64::MAX - 1, "intent_scope": IntentScope::PriceFeed as u8 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<KeyRetirement>> =
+        response.json().await.unwrap();
+    assert_eq!(Hex::encode(&signed.response.data.public_key), scoped_pk);
+    let response = reqwest::get(format!("{}/key_retirement?intent_scope=0", app))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let response = reqwest::get(format!("{}/key_retirement", app)).await.unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_openapi_served() {
+    let sui = MockServer::start().await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+
+    let spec: Value = reqwest::get(format!("{}/openapi.json", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(spec["paths"]["/process_data"]["post"].is_object());
+
+    let ui = reqwest::get(format!("{}/swagger-ui/", app)).await.unwrap();
+    assert_eq!(ui.status(), 200);
+}
+
+#[tokio::test]
+async fn test_request_id_propagated() {
+    let sui = MockServer::start().await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/health_check", app))
+        .header("x-request-id", "relayer-42")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-request-id"], "relayer-42");
+
+    // One is generated when the client does not send it.
+    let response = client.get(format!("{}/health_check", app)).send().await.unwrap();
+    assert!(!response.headers()["x-request-id"].is_empty());
+}
+
+#[tokio::test]
+async fn test_fee_quote_signed() {
+    let sui = MockServer::start().await;
+    let mut config = test_config(&sui.uri());
+    config.fees.base_fee_mist = 1_000;
+    let app = spawn_app(config).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/fee_quote", app))
+        .json(&json!({ "payload": { "price_feed_id": FEED_ID, "priority": 1 } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let signed: ProcessedDataResponse<IntentMessage<FeeQuoteResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.intent, IntentScope::FeeQuote);
+    assert_eq!(signed.response.data.priority, Priority::High);
+    assert_eq!(signed.response.data.fee_mist, 2_000);
+    assert!(signed.response.data.expires_at_ms > signed.response.timestamp_ms);
+
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+}
+
+#[tokio::test]
+async fn test_process_data_requires_payment() {
+    const RECEIPT_ID: &str = "0x7ec3";
+    let receipt_type = format!("{}::payment::Receipt", PACKAGE_ID);
+
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 42 })).await;
+    sui_object(RECEIPT_ID)
+        .respond_with(rpc_result(json!({
+            "data": {
+                "objectId": RECEIPT_ID,
+                "type": receipt_type,
+                "content": {
+                    "dataType": "moveObject",
+                    "fields": { "price_feed_id": FEED_ID, "amount": "1000" },
+                },
+            },
+        })))
+        .mount(&sui)
+        .await;
+
+    let mut config = test_config(&sui.uri());
+    config.fees.base_fee_mist = 1_000;
+    config.payments.required = true;
+    config.payments.receipt_type = receipt_type;
+    let app = spawn_app(config).await;
+
+    // Without a receipt nothing is signed.
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+
+    let post_with_receipt = || async {
+        reqwest::Client::new()
+            .post(format!("{}/process_data", app))
+            .json(&json!({ "payload": { "price_feed_id": FEED_ID, "receipt_id": RECEIPT_ID } }))
+            .send()
+            .await
+            .unwrap()
+    };
+    let response = post_with_receipt().await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 4200000000);
+
+    // A receipt pays for a single signature.
+    let response = post_with_receipt().await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("already been used"));
+}
+
+#[tokio::test]
+async fn test_process_data_rejects_stale_receipt() {
+    let receipt_type = format!("{}::payment::Receipt", PACKAGE_ID);
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 42 })).await;
+    for (receipt_id, paid_ms) in [("0x7ec5", now_ms - 10_000), ("0x7ec6", now_ms - 600_000)] {
+        sui_object(receipt_id)
+            .respond_with(rpc_result(json!({
+                "data": {
+                    "objectId": receipt_id,
+                    "type": receipt_type,
+                    "content": {
+                        "dataType": "moveObject",
+                        "fields": {
+                            "price_feed_id": FEED_ID,
+                            "amount": "1000",
+                            "timestamp_ms": paid_ms.to_string(),
+                        },
+                    },
+                },
+            })))
+            .mount(&sui)
+            .await;
+    }
+
+    let mut config = test_config(&sui.uri());
+    config.payments.required = true;
+    config.payments.receipt_type = receipt_type;
+    config.payments.max_receipt_age_secs = Some(300);
+    let app = spawn_app(config).await;
+
+    let post_with_receipt = |receipt_id: &'static str| {
+        reqwest::Client::new()
+            .post(format!("{}/process_data", app))
+            .json(&json!({ "payload": { "price_feed_id": FEED_ID, "receipt_id": receipt_id } }))
+            .send()
+    };
+    let response = post_with_receipt("0x7ec5").await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 4200000000);
+
+    let response = post_with_receipt("0x7ec6").await.unwrap();
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("ms ago"));
+}
+
+#[tokio::test]
+async fn test_process_data_example_self_check() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "price": 42 }))
+        .expect(0)
+        .mount(&upstream)
+        .await;
+    mount_price_feed(&sui, upstream_price_fields(&upstream)).await;
+
+    // The example reflects a provider that moved the price under "data".
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            example: Some(ExtractionExample {
+                body: r#"{"data": {"price": 42}}"#.to_string(),
+                expected: 42.into(),
+            }),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("self-check"));
+}
+
+#[tokio::test]
+async fn test_process_data_circuit_breaker() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "price": 100 }))
+        .up_to_n_times(1)
+        .mount(&upstream)
+        .await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 1000 })).await;
+
+    let mut config = test_config(&sui.uri());
+    config.circuit_breaker.max_deviation_bps = Some(1_000);
+    let app = spawn_app(config).await;
+
+    let first = post_process_data(&app, FEED_ID).await;
+    assert_signed(&first.json().await.unwrap(), 10000000000);
+
+    // A tenfold jump is refused until an operator allows it.
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("Circuit breaker tripped"));
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/override_breaker", app))
+        .json(&json!({ "price_feed_id": FEED_ID }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 100000000000);
+}
+
+#[tokio::test]
+async fn test_process_data_pause() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+
+    let pause = |body: Value| {
+        let app = app.clone();
+        async move {
+            let response = reqwest::Client::new()
+                .post(format!("{}/admin/pause", app))
+                .json(&body)
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), 200);
+            response.json::<Value>().await.unwrap()
+        }
+    };
+
+    let status = pause(json!({ "price_feed_id": FEED_ID, "paused": true, "reason": "bad upstream" })).await;
+    assert_eq!(status["paused_feeds"], json!([FEED_ID]));
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 503);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "paused");
+    assert!(body["error"].as_str().unwrap().contains("bad upstream"));
+
+    pause(json!({ "price_feed_id": FEED_ID, "paused": false })).await;
+    let status = pause(json!({ "paused": true })).await;
+    assert_eq!(status["global_paused"], true);
+    assert_eq!(post_process_data(&app, FEED_ID).await.status(), 503);
+
+    pause(json!({ "paused": false })).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 10000000000);
+}
+
+#[tokio::test]
+async fn test_list_feeds_for_oracle() {
+    const OWNED_FEED_ID: &str = "0x00000000000000000000000000000000000000000000000000000000000000b3";
+    const OTHER_ORACLE_FEED_ID: &str =
+        "0x00000000000000000000000000000000000000000000000000000000000000b4";
+    let sui = MockServer::start().await;
+    let feed_type = format!("{}::oracle_builder::PriceFeed", PACKAGE_ID);
+    // Two pages of dynamic fields, one of them not a PriceFeed
+    sui_call("suix_getDynamicFields")
+        .and(body_partial_json(json!({ "params": [ORACLE_ID, null] })))
+        .respond_with(rpc_result(json!({
+            "data": [
+                { "type": "DynamicObject", "objectType": feed_type, "objectId": FEED_ID },
+                { "type": "DynamicField", "objectType": "u64", "objectId": "0x99" },
+            ],
+            "nextCursor": "0x99",
+            "hasNextPage": true,
+        })))
+        .mount(&sui)
+        .await;
+    sui_call("suix_getDynamicFields")
+        .and(body_partial_json(json!({ "params": [ORACLE_ID, "0x99"] })))
+        .respond_with(rpc_result(json!({
+            "data": [
+                { "type": "DynamicObject", "objectType": feed_type, "objectId": OTHER_ORACLE_FEED_ID },
+            ],
+            "nextCursor": null,
+            "hasNextPage": false,
+        })))
+        .mount(&sui)
+        .await;
+    sui_call("suix_getOwnedObjects")
+        .respond_with(rpc_result(json!({
+            "data": [{ "data": { "objectId": OWNED_FEED_ID } }],
+            "nextCursor": null,
+            "hasNextPage": false,
+        })))
+        .mount(&sui)
+        .await;
+    let fields = price_feed_fields("https://example.com/price", "price");
+    let mut other_oracle = fields.clone();
+    other_oracle["oracle_id"] = json!("0x1");
+    sui_call("sui_multiGetObjects")
+        .respond_with(MultiGetPriceFeeds(HashMap::from([
+            (FEED_ID.to_string(), fields.clone()),
+            (OWNED_FEED_ID.to_string(), fields),
+            (OTHER_ORACLE_FEED_ID.to_string(), other_oracle),
+        ])))
+        .mount(&sui)
+        .await;
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(FEED_ID.to_string(), FeedConfig::default());
+    let app = spawn_app(config).await;
+
+    let body: Value = reqwest::get(format!("{}/feeds?oracle_id={}", app, ORACLE_ID))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["oracle_id"], ORACLE_ID);
+    let feeds = body["feeds"].as_array().unwrap();
+    assert_eq!(feeds.len(), 2);
+    assert_eq!(feeds[0]["price_feed_id"], OWNED_FEED_ID);
+    assert_eq!(feeds[0]["configured"], false);
+    assert_eq!(feeds[1]["price_feed_id"], FEED_ID);
+    assert_eq!(feeds[1]["configured"], true);
+    assert!(feeds[1].get("api_key").is_none());
+
+    let response = reqwest::get(format!("{}/feeds?oracle_id=oracle", app)).await.unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_upstream_health_probe() {
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/live"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&upstream)
+        .await;
+    let mut fields = upstream_price_fields(&upstream);
+    fields["live_url"] = json!(format!("{}/live", upstream.uri()));
+    mount_price_feed(&sui, fields).await;
+
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(FEED_ID.to_string(), FeedConfig::default());
+    // The second feed's object can't be fetched, so it is unhealthy too
+    config.feeds.insert(ORACLE_ID.to_string(), FeedConfig::default());
+    let state = AppState::from_config(test_keypair(), config).await.unwrap();
+    health::probe_feeds(&state).await;
+    health::probe_feeds(&state).await;
+
+    let app = serve(state).await;
+    let body: Value = reqwest::get(format!("{}/health/upstreams", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["healthy"], false);
+    let feed = &body["feeds"][FEED_ID];
+    assert_eq!(feed["healthy"], false);
+    assert_eq!(feed["status"], 503);
+    assert_eq!(feed["consecutive_failures"], 2);
+    assert!(body["feeds"][ORACLE_ID]["error"].is_string());
+}
+
+#[tokio::test]
+async fn test_process_batch_cache_per_item() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "price": 100 }))
+        .up_to_n_times(1)
+        .mount(&upstream)
+        .await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 200 })).await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+
+    let first = post_process_data(&app, FEED_ID).await;
+    assert_signed(&first.json().await.unwrap(), 10000000000);
+
+    let post_batch = |items: Value| {
+        reqwest::Client::new()
+            .post(format!("{}/process_data/batch", app))
+            .json(&json!({ "payload": { "items": items } }))
+            .send()
+    };
+    let response = post_batch(json!([
+        { "price_feed_id": FEED_ID, "max_age_ms": 60_000 },
+        { "price_feed_id": FEED_ID, "cache": "bypass" },
+        { "price_feed_id": "0xmissing" },
+    ]))
+    .await
+    .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    let results = body["results"].as_array().unwrap();
+    assert_eq!(results.len(), 3);
+
+    // Latency tolerant item: the price signed above
+    assert_eq!(results[0]["cached"], true);
+    assert_signed(&results[0]["signed"], 10000000000);
+    // Must-be-fresh item: fetched and signed again
+    assert_eq!(results[1]["cached"], false);
+    assert_signed(&results[1]["signed"], 20000000000);
+    // Failures are reported per item
+    assert!(results[2]["signed"].is_null());
+    assert!(results[2]["error"]["error"].is_string());
+
+    assert_eq!(post_batch(json!([])).await.unwrap().status(), 400);
+}
+
+#[tokio::test]
+async fn test_process_batch_cached_item_outside_signing_window() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+    let state = AppState::from_config(test_keypair(), test_config(&sui.uri()))
+        .await
+        .unwrap();
+    let app = serve(state.clone()).await;
+    assert_eq!(post_process_data(&app, FEED_ID).await.status(), 200);
+
+    // The window closes after the price was signed
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            signing_windows: Some(MarketHours {
+                sessions: vec![Session {
+                    open: "Mon 00:00".to_string(),
+                    close: "Mon 00:00".to_string(),
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    );
+    state.config.store(Arc::new(config));
+    let body: Value = reqwest::Client::new()
+        .post(format!("{}/process_data/batch", app))
+        .json(&json!({ "payload": { "items": [
+            { "price_feed_id": FEED_ID, "max_age_ms": 60_000 },
+        ] } }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let result = &body["results"][0];
+    assert!(result["signed"].is_null());
+    assert_eq!(result["error"]["code"], "outside_signing_window");
+}
+
+#[tokio::test]
+async fn test_process_batch_fetches_feeds_together() {
+    const OTHER_FEED_ID: &str = "0x00000000000000000000000000000000000000000000000000000000000000b2";
+    let (sui, upstream) = start_mocks().await;
+    for (route, price) in [("/a", 100), ("/b", 200)] {
+        Mock::given(method("GET"))
+            .and(path(route))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "price": price })))
+            .mount(&upstream)
+            .await;
+    }
+    // No sui_getObject is mounted: both objects come from a single call
+    sui_call("sui_multiGetObjects")
+        .respond_with(MultiGetPriceFeeds(HashMap::from([
+            (FEED_ID.to_string(), price_feed_fields(&format!("{}/a", upstream.uri()), "price")),
+            (OTHER_FEED_ID.to_string(), price_feed_fields(&format!("{}/b", upstream.uri()), "price")),
+        ])))
+        .expect(1)
+        .mount(&sui)
+        .await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/process_data/batch", app))
+        .json(&json!({ "payload": { "items": [
+            { "price_feed_id": FEED_ID },
+            { "price_feed_id": OTHER_FEED_ID },
+        ] } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_signed(&body["results"][0]["signed"], 10000000000);
+    assert_eq!(body["results"][1]["signed"]["response"]["data"]["price"], 20000000000u64);
+}
+
+#[tokio::test]
+async fn test_process_batch_streams_ndjson() {
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/price"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "price": 100 }))
+                .set_delay(std::time::Duration::from_millis(500)),
+        )
+        .mount(&upstream)
+        .await;
+    mount_price_feed(&sui, upstream_price_fields(&upstream)).await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/process_data/batch", app))
+        .header("accept", "application/x-ndjson")
+        .json(&json!({ "payload": { "items": [
+            { "price_feed_id": FEED_ID },
+            { "price_feed_id": "0xmissing" },
+        ] } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/x-ndjson");
+    let body = response.text().await.unwrap();
+    let lines: Vec<Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 2);
+
+    // The failing item doesn't wait for the slow upstream
+    assert_eq!(lines[0]["index"], 1);
+    assert!(lines[0]["error"]["error"].is_string());
+    assert_eq!(lines[1]["index"], 0);
+    assert_eq!(lines[1]["price_feed_id"], FEED_ID);
+    assert_signed(&lines[1]["signed"], 10000000000);
+}
+
+#[tokio::test]
+async fn test_cors_and_security_headers() {
+    let sui = MockServer::start().await;
+    let mut config = test_config(&sui.uri());
+    config.cors.allowed_origins = vec!["https://app.example.com".to_string()];
+    config.security_headers.hsts_max_age_secs = Some(600);
+    let app = spawn_app(config).await;
+
+    let preflight = |origin: &'static str| {
+        reqwest::Client::new()
+            .request(reqwest::Method::OPTIONS, format!("{}/process_data", app))
+            .header("origin", origin)
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "content-type,x-consumer-key")
+            .send()
+    };
+    let response = preflight("https://app.example.com").await.unwrap();
+    assert_eq!(response.status(), 200);
+    let headers = response.headers();
+    assert_eq!(headers["access-control-allow-origin"], "https://app.example.com");
+    assert!(headers["access-control-allow-methods"].to_str().unwrap().contains("POST"));
+    assert!(headers["access-control-allow-headers"].to_str().unwrap().contains("x-consumer-key"));
+
+    let response = preflight("https://evil.example.com").await.unwrap();
+    assert!(response.headers().get("access-control-allow-origin").is_none());
+
+    let response = reqwest::get(format!("{}/health_check", app)).await.unwrap();
+    let headers = response.headers();
+    assert_eq!(headers["x-content-type-options"], "nosniff");
+    assert_eq!(headers["x-frame-options"], "DENY");
+    assert_eq!(headers["referrer-policy"], "no-referrer");
+    assert_eq!(headers["strict-transport-security"], "max-age=600; includeSubDomains");
+}
+
+#[tokio::test]
+async fn test_key_retirement() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 42 })).await;
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let client = reqwest::Client::new();
+
+    let response = client.get(format!("{}/key_retirement", app)).send().await.unwrap();
+    assert_eq!(response.status(), 400);
+
+    let now_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    let retire_at_ms = now_ms + 1_000;
+    let response = client
+        .post(format!("{}/admin/retire_key", app))
+        .json(&json!({ "retire_at_ms": retire_at_ms }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let response = client.get(format!("{}/key_retirement", app)).send().await.unwrap();
+    let signed: ProcessedDataResponse<IntentMessage<KeyRetirement>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.intent, IntentScope::KeyRetirement);
+    assert_eq!(signed.response.data.retire_at_ms, retire_at_ms);
+    assert_eq!(signed.response.data.public_key, test_keypair().public().as_bytes());
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+
+    assert_eq!(post_process_data(&app, FEED_ID).await.status(), 200);
+    tokio::time::sleep(std::time::Duration::from_millis(1_100)).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("retired"));
+
+    // The commitment cannot be pushed back
+    let response = client
+        .post(format!("{}/admin/retire_key", app))
+        .json(&json!({ "retire_at_ms": retire_at_ms + 60_000 }))
+        .send()
+        .await
+        .unwrap();
+    let signed: ProcessedDataResponse<IntentMessage<KeyRetirement>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.data.retire_at_ms, retire_at_ms);
+}
+
+#[tokio::test]
+async fn test_push_feeds_submits_transaction() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+    mount_push_chain(&sui).await;
+    sui_call("sui_executeTransactionBlock")
+        .respond_with(rpc_result(json!({ "digest": push_digest(), "effects": push_effects() })))
+        .expect(1)
+        .mount(&sui)
+        .await;
+
+    let mut config = test_config(&sui.uri());
+    config.push.feeds = vec![FEED_ID.to_string()];
+    config.push.enclave_object_id = ENCLAVE_ID.to_string();
+    let state = AppState::from_config(test_keypair(), config).await.unwrap();
+    let digest = push::push_feeds(&state).await.unwrap();
+    assert_eq!(digest, Some(push_digest()));
+
+    // Budgeted the dry-run cost plus 20%, paid with the large coin and the
+    // small one merged into it, and signed by the enclave key's address
+    let execute = &sui_calls(&sui, "sui_executeTransactionBlock").await[0];
+    let bytes = Base64::decode(execute["params"][0].as_str().unwrap()).unwrap();
+    let transaction: Transaction = bcs::from_bytes(&bytes).unwrap();
+    assert_eq!(transaction.sender, push::sender_address(&test_keypair()));
+    assert_eq!(transaction.gas_payment.owner, transaction.sender);
+    assert_eq!(transaction.gas_payment.price, 750);
+    assert_eq!(transaction.gas_payment.budget, 4_800_000);
+    assert_eq!(transaction.gas_payment.objects.len(), 2);
+    let TransactionKind::ProgrammableTransaction(ptb) = &transaction.kind else {
+        panic!("expected a programmable transaction");
+    };
+    assert_eq!(ptb.inputs[3], Input::Pure { value: bcs::to_bytes(&10000000000u64).unwrap() });
+
+    let signature = UserSignature::from_base64(execute["params"][1][0].as_str().unwrap()).unwrap();
+    assert_eq!(signature, push::sign_transaction(&test_keypair(), &transaction));
+}
+
+#[tokio::test]
+async fn test_push_feeds_claims_lease() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+    mount_push_chain(&sui).await;
+    // Expired, so free to claim
+    mount_push_lease(&sui, "replica-2", 1).await;
+    sui_call("sui_executeTransactionBlock")
+        .respond_with(rpc_result(json!({ "digest": push_digest(), "effects": push_effects() })))
+        .expect(1)
+        .mount(&sui)
+        .await;
+
+    let mut config = test_config(&sui.uri());
+    config.push.feeds = vec![FEED_ID.to_string()];
+    config.push.enclave_object_id = ENCLAVE_ID.to_string();
+    config.push.lease_object_id = Some(LEASE_ID.to_string());
+    config.push.instance_id = Some("replica-1".to_string());
+    let state = AppState::from_config(test_keypair(), config).await.unwrap();
+    assert_eq!(push::push_feeds(&state).await.unwrap(), Some(push_digest()));
+
+    // The lease is claimed for 30 s before the price is pushed
+    let execute = &sui_calls(&sui, "sui_executeTransactionBlock").await[0];
+    let bytes = Base64::decode(execute["params"][0].as_str().unwrap()).unwrap();
+    let transaction: Transaction = bcs::from_bytes(&bytes).unwrap();
+    let TransactionKind::ProgrammableTransaction(ptb) = &transaction.kind else {
+        panic!("expected a programmable transaction");
+    };
+    assert_eq!(
+        ptb.inputs[1],
+        Input::Shared {
+            object_id: ObjectId::from_str(LEASE_ID).unwrap(),
+            initial_shared_version: 20,
+            mutable: true,
+        }
+    );
+    assert_eq!(ptb.inputs[2], Input::Pure { value: bcs::to_bytes("replica-1").unwrap() });
+    assert_eq!(ptb.inputs[3], Input::Pure { value: bcs::to_bytes(&30_000u64).unwrap() });
+    let Command::MoveCall(claim) = &ptb.commands[0] else {
+        panic!("expected a Move call");
+    };
+    assert_eq!(claim.function.as_str(), "claim_push_lease");
+    assert_eq!(ptb.commands.len(), 2);
+}
+
+#[tokio::test]
+async fn test_push_feeds_skipped_while_lease_held() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+    mount_push_chain(&sui).await;
+    mount_push_lease(&sui, "replica-2", u64::MAX).await;
+    sui_call("sui_executeTransactionBlock")
+        .respond_with(rpc_result(json!({ "digest": push_digest(), "effects": push_effects() })))
+        .expect(0)
+        .mount(&sui)
+        .await;
+
+    let mut config = test_config(&sui.uri());
+    config.push.feeds = vec![FEED_ID.to_string()];
+    config.push.enclave_object_id = ENCLAVE_ID.to_string();
+    config.push.lease_object_id = Some(LEASE_ID.to_string());
+    config.push.instance_id = Some("replica-1".to_string());
+    let state = AppState::from_config(test_keypair(), config).await.unwrap();
+    assert_eq!(push::push_feeds(&state).await.unwrap(), None);
+    // Nothing was signed for pushing
+    assert_eq!(state.push.queue.depth(), 0);
+    assert!(sui_calls(&sui, "sui_dryRunTransactionBlock").await.is_empty());
+}
+
+#[tokio::test]
+async fn test_push_feeds_sponsored_by_gas_station() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+    mount_push_chain(&sui).await;
+    let station = MockServer::start().await;
+    let sponsor = Address::new([5; 32]);
+    let coin = ObjectReference::new(ObjectId::new([6; 32]), 4, ObjectDigest::new([6; 32]));
+    Mock::given(method("POST"))
+        .and(path("/v1/reserve_gas"))
+        .and(header("authorization", "Bearer station-token"))
+        .and(body_partial_json(json!({ "gas_budget": 500_000_000, "reserve_duration_secs": 30 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "result": {
+                "sponsor_address": sponsor.to_string(),
+                "reservation_id": 17,
+                "gas_coins": [{
+                    "objectId": coin.object_id().to_string(),
+                    "version": 4,
+                    "digest": coin.digest().to_base58(),
+                }],
+            },
+            "error": null,
+        })))
+        .expect(1)
+        .mount(&station)
+        .await;
+    let mut effects = push_effects();
+    effects["transactionDigest"] = json!(push_digest());
+    Mock::given(method("POST"))
+        .and(path("/v1/execute_tx"))
+        .and(body_partial_json(json!({ "reservation_id": 17 })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "effects": effects, "error": null })))
+        .expect(1)
+        .mount(&station)
+        .await;
+
+    std::env::set_var("PUSH_TEST_STATION_TOKEN", "station-token");
+    let mut config = test_config(&sui.uri());
+    config.push.feeds = vec![FEED_ID.to_string()];
+    config.push.enclave_object_id = ENCLAVE_ID.to_string();
+    config.push.sponsor = Some(Sponsor {
+        url: station.uri(),
+        token_env: Some("PUSH_TEST_STATION_TOKEN".to_string()),
+        reserve_duration_secs: 30,
+    });
+    let state = AppState::from_config(test_keypair(), config).await.unwrap();
+    let digest = push::push_feeds(&state).await.unwrap();
+    assert_eq!(digest, Some(push_digest()));
+
+    // Sent by the enclave key, paid with the reserved coin of the sponsor,
+    // and never touching the enclave key's own coins
+    let requests = station.received_requests().await.unwrap();
+    let execute: Value = serde_json::from_slice(&requests[1].body).unwrap();
+    let bytes = Base64::decode(execute["tx_bytes"].as_str().unwrap()).unwrap();
+    let transaction: Transaction = bcs::from_bytes(&bytes).unwrap();
+    assert_eq!(transaction.sender, push::sender_address(&test_keypair()));
+    assert_eq!(transaction.gas_payment.owner, sponsor);
+    assert_eq!(transaction.gas_payment.objects, vec![coin]);
+    assert_eq!(transaction.gas_payment.budget, 4_800_000);
+    let signature = UserSignature::from_base64(execute["user_sig"].as_str().unwrap()).unwrap();
+    assert_eq!(signature, push::sign_transaction(&test_keypair(), &transaction));
+    assert!(sui_calls(&sui, "suix_getCoins").await.is_empty());
+    assert!(sui_calls(&sui, "sui_executeTransactionBlock").await.is_empty());
+}
+
+#[tokio::test]
+async fn test_push_retries_locked_objects_and_requeues_failures() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+    mount_push_chain(&sui).await;
+    sui_call("sui_executeTransactionBlock")
+        .respond_with(rpc_error(
+            -32002,
+            "Transaction is rejected as invalid by more than 1/3 of validators: ObjectLockConflict",
+        ))
+        .up_to_n_times(1)
+        .mount(&sui)
+        .await;
+    sui_call("sui_executeTransactionBlock")
+        .respond_with(rpc_result(json!({ "digest": push_digest(), "effects": push_effects() })))
+        .up_to_n_times(1)
+        .mount(&sui)
+        .await;
+    sui_call("sui_executeTransactionBlock")
+        .respond_with(rpc_error(-32002, "MoveAbort in command 0"))
+        .mount(&sui)
+        .await;
+
+    let mut config = test_config(&sui.uri());
+    config.push.feeds = vec![FEED_ID.to_string()];
+    config.push.enclave_object_id = ENCLAVE_ID.to_string();
+    config.push.retry_backoff_ms = 1;
+    let state = AppState::from_config(test_keypair(), config).await.unwrap();
+
+    // The lock conflict is retried with the coins listed again
+    assert_eq!(push::push_feeds(&state).await.unwrap(), Some(push_digest()));
+    assert_eq!(sui_calls(&sui, "sui_executeTransactionBlock").await.len(), 2);
+    assert_eq!(sui_calls(&sui, "suix_getCoins").await.len(), 2);
+    assert_eq!(state.push.queue.depth(), 0);
+
+    // Other failures are not retried, and leave the price queued
+    assert!(push::push_feeds(&state).await.is_err());
+    assert_eq!(sui_calls(&sui, "sui_executeTransactionBlock").await.len(), 3);
+    assert_eq!(state.push.queue.depth(), 1);
+}
+
+#[tokio::test]
+async fn test_push_queue_survives_restart() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+    mount_push_chain(&sui).await;
+    sui_call("sui_executeTransactionBlock")
+        .respond_with(rpc_error(-32002, "MoveAbort in command 0"))
+        .up_to_n_times(1)
+        .mount(&sui)
+        .await;
+    sui_call("sui_executeTransactionBlock")
+        .respond_with(rpc_result(json!({ "digest": push_digest(), "effects": push_effects() })))
+        .mount(&sui)
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = test_config(&sui.uri());
+    config.push.feeds = vec![FEED_ID.to_string()];
+    config.push.enclave_object_id = ENCLAVE_ID.to_string();
+    config.persistence.backend = Backend::Sqlite;
+    config.persistence.path = dir.path().join("history.db").to_str().unwrap().to_string();
+    let state = AppState::from_config(test_keypair(), config.clone()).await.unwrap();
+    assert!(push::push_feeds(&state).await.is_err());
+    drop(state);
+
+    // The price that failed to land is pushed by the next instance
+    let state = AppState::from_config(test_keypair(), config).await.unwrap();
+    assert_eq!(state.push.queue.depth(), 1);
+    assert_eq!(push::flush(&state).await.unwrap(), Some(push_digest()));
+    assert_eq!(state.push.queue.depth(), 0);
+    let executed = sui_calls(&sui, "sui_executeTransactionBlock").await;
+    assert_eq!(executed[0]["params"][0], executed[1]["params"][0]);
+}
+
+#[tokio::test]
+async fn test_push_drain_on_shutdown() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+    mount_push_chain(&sui).await;
+    sui_call("sui_executeTransactionBlock")
+        .respond_with(rpc_error(-32002, "MoveAbort in command 0"))
+        .up_to_n_times(1)
+        .mount(&sui)
+        .await;
+    sui_call("sui_executeTransactionBlock")
+        .respond_with(
+            rpc_result(json!({ "digest": push_digest(), "effects": push_effects() }))
+                .set_delay(std::time::Duration::from_secs(3)),
+        )
+        .up_to_n_times(1)
+        .mount(&sui)
+        .await;
+    sui_call("sui_executeTransactionBlock")
+        .respond_with(rpc_result(json!({ "digest": push_digest(), "effects": push_effects() })))
+        .expect(1)
+        .mount(&sui)
+        .await;
+
+    let mut config = test_config(&sui.uri());
+    config.push.feeds = vec![FEED_ID.to_string()];
+    config.push.enclave_object_id = ENCLAVE_ID.to_string();
+    config.push.shutdown_timeout_secs = 1;
+    let state = AppState::from_config(test_keypair(), config).await.unwrap();
+    assert!(push::push_feeds(&state).await.is_err());
+    assert_eq!(state.push.queue.depth(), 1);
+
+    // A submission outlasting the deadline leaves the price queued
+    push::drain(&state).await;
+    assert_eq!(state.push.queue.depth(), 1);
+
+    push::drain(&state).await;
+    assert_eq!(state.push.queue.depth(), 0);
+}
+
+#[tokio::test]
+async fn test_admin_push_lists_queue() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+    mount_push_chain(&sui).await;
+    sui_call("sui_executeTransactionBlock")
+        .respond_with(rpc_error(-32002, "MoveAbort in command 0"))
+        .up_to_n_times(1)
+        .mount(&sui)
+        .await;
+    sui_call("sui_executeTransactionBlock")
+        .respond_with(rpc_result(json!({ "digest": push_digest(), "effects": push_effects() })))
+        .mount(&sui)
+        .await;
+
+    let mut config = test_config(&sui.uri());
+    config.push.feeds = vec![FEED_ID.to_string()];
+    config.push.enclave_object_id = ENCLAVE_ID.to_string();
+    let state = AppState::from_config(test_keypair(), config).await.unwrap();
+    assert!(push::push_feeds(&state).await.is_err());
+    let app = serve(Arc::clone(&state)).await;
+    let push_status = || async {
+        reqwest::get(format!("{}/admin/push", app))
+            .await
+            .unwrap()
+            .json::<Value>()
+            .await
+            .unwrap()
+    };
+
+    let body = push_status().await;
+    assert_eq!(body["enabled"], true);
+    assert_eq!(body["sender"], push::sender_address(&test_keypair()).to_string());
+    // A random instance id unless one is configured
+    assert_eq!(body["instance_id"].as_str().unwrap().len(), 16);
+    assert_eq!(body["depth"], 1);
+    assert_eq!(body["queue"][0]["price_feed_id"], FEED_ID);
+    assert_eq!(body["queue"][0]["price"], 10000000000u64);
+    assert_eq!(body["queue"][0]["in_flight"], false);
+    assert!(body["last_submission"]["error"].as_str().unwrap().contains("MoveAbort"));
+
+    push::flush(&state).await.unwrap();
+    let body = push_status().await;
+    assert_eq!(body["depth"], 0);
+    assert_eq!(body["last_submission"]["digest"], push_digest());
+    assert!(body["last_submission"].get("error").is_none());
+}
@@ -0,0 +1,898 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! End-to-end tests of fetching prices from mock upstream APIs: their
+//! authentication and formats, transforms, and the Sui RPC feeds are read from.
+
+mod common;
+
+use common::*;
+use fastcrypto::encoding::{Base64, Encoding, Hex};
+use fastcrypto::traits::Signer;
+use nautilus_server::api_key_encryption::{encrypt_api_key, ApiKeyDecryption};
+use nautilus_server::app::{FeedObject, PriceFeedResponse};
+use nautilus_server::assets::AssetMetadata;
+use nautilus_server::common::{IntentMessage, IntentScope, ProcessedDataResponse, RoundingMode};
+use nautilus_server::config::FeedConfig;
+use nautilus_server::conversion::Conversion;
+use nautilus_server::formats::{CsvFormat, ResponseFormat, UpstreamFormat};
+use nautilus_server::hmac_auth::HmacSigning;
+use nautilus_server::market_hours::{MarketHours, Session, WhenClosed};
+use nautilus_server::oauth::OAuth2;
+use nautilus_server::secrets::SecretUse;
+use nautilus_server::transform::Transform;
+use nautilus_server::upstream_circuit::UpstreamCircuit;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use wiremock::matchers::{body_string_contains, header, method, path};
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+#[tokio::test]
+async fn test_process_data_signs_price() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "data": [{ "price": "123.456" }] })).mount(&upstream).await;
+    mount_price_feed(
+        &sui,
+        price_feed_fields(&format!("{}/price", upstream.uri()), "data[0].price"),
+    )
+    .await;
+
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 12345600000);
+}
+
+#[tokio::test]
+async fn test_process_data_url_template() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price/ETH%20USD", json!({ "price": "3100.5" })).mount(&upstream).await;
+    mount_price_feed(
+        &sui,
+        price_feed_fields(&format!("{}/price/{{symbol}}", upstream.uri()), "price"),
+    )
+    .await;
+
+    let mut config = test_config(&sui.uri());
+    config.response.include_rate_of_change = true;
+    let app = spawn_app(config).await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/process_data", app))
+        .json(&json!({
+            "payload": { "price_feed_id": FEED_ID, "params": { "symbol": "ETH USD" } }
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<PriceFeedResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.data.price, 310050000000);
+    assert_eq!(signed.response.data.params["symbol"], "ETH USD");
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+
+    // The feed's history is left to its own URL.
+    let history: Value = reqwest::get(format!("{}/history/{}", app, FEED_ID))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(history["observations"], json!([]));
+
+    // Without the parameter the URL cannot be filled.
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_process_data_rate_of_change() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "price": 100 }))
+        .up_to_n_times(1)
+        .mount(&upstream)
+        .await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 95 })).await;
+
+    let mut config = test_config(&sui.uri());
+    config.response.include_rate_of_change = true;
+    let app = spawn_app(config).await;
+
+    // The first observation has nothing to compare against.
+    let first = post_process_data(&app, FEED_ID).await;
+    assert_signed(&first.json().await.unwrap(), 10000000000);
+
+    let second: Value = post_process_data(&app, FEED_ID).await.json().await.unwrap();
+    let signed: ProcessedDataResponse<IntentMessage<PriceFeedResponse>> =
+        serde_json::from_value(second).unwrap();
+    assert_eq!(signed.response.intent, IntentScope::PriceFeedWithChange);
+    let change = signed.response.data.rate_of_change.clone().unwrap();
+    assert_eq!(change.previous_price, 10000000000);
+    assert_eq!(change.change_bps, 500);
+    assert!(change.is_negative);
+
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+}
+
+#[tokio::test]
+async fn test_process_data_market_closed() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "price": 42 }))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    mount_price_feed(&sui, upstream_price_fields(&upstream)).await;
+
+    // An empty session means the market is never open.
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            market_hours: Some(MarketHours {
+                sessions: vec![Session {
+                    open: "Mon 00:00".to_string(),
+                    close: "Mon 00:00".to_string(),
+                }],
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+
+    // The first request falls back to upstream, the second serves the same
+    // last close from history without fetching again.
+    let mut update_ids = Vec::new();
+    for _ in 0..2 {
+        let body: Value = post_process_data(&app, FEED_ID).await.json().await.unwrap();
+        let signed: ProcessedDataResponse<IntentMessage<PriceFeedResponse>> =
+            serde_json::from_value(body).unwrap();
+        assert_eq!(signed.response.intent, IntentScope::PriceFeedMarketClosed);
+        assert!(signed.response.data.market_closed);
+        assert_eq!(signed.response.data.price, 4200000000);
+
+        let bytes = bcs::to_bytes(&signed.response).unwrap();
+        assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+        update_ids.push(signed.update_id.unwrap());
+    }
+    // Both carry the same close, so relayers only submit it once.
+    assert_eq!(update_ids[0], update_ids[1]);
+}
+
+#[tokio::test]
+async fn test_process_data_market_closed_refused() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "price": 42 }))
+        .expect(0)
+        .mount(&upstream)
+        .await;
+    mount_price_feed(&sui, upstream_price_fields(&upstream)).await;
+
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            market_hours: Some(MarketHours {
+                sessions: vec![Session {
+                    open: "Mon 00:00".to_string(),
+                    close: "Mon 00:00".to_string(),
+                }],
+                ..Default::default()
+            }),
+            when_closed: WhenClosed::Refuse,
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 403);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "outside_signing_window");
+    assert!(body["error"].as_str().unwrap().contains("market hours"));
+}
+
+#[tokio::test]
+async fn test_process_data_asset_conventions() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": "64123.37" })).await;
+
+    let mut config = test_config(&sui.uri());
+    config.assets.insert(
+        "BTC".to_string(),
+        AssetMetadata {
+            decimals: Some(2),
+            tick_size: Some("0.5".parse().unwrap()),
+            aliases: vec!["XBT".to_string()],
+            ..Default::default()
+        },
+    );
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            asset: Some("xbt".to_string()),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+
+    // Rounded to the 0.5 tick and scaled by the asset's 2 decimals.
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_signed(&response.json().await.unwrap(), 6412350);
+
+    let asset: Value = reqwest::get(format!("{}/assets/XBT", app))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(asset["symbol"], "BTC");
+    assert_eq!(asset["decimals"], 2);
+
+    let response = reqwest::get(format!("{}/assets/ETH", app)).await.unwrap();
+    assert_eq!(response.status(), 400);
+}
+
+#[tokio::test]
+async fn test_process_data_bearer_auth() {
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/price"))
+        .and(header("Authorization", "Bearer secret"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "price": 2.5 })))
+        .mount(&upstream)
+        .await;
+    let mut fields = upstream_price_fields(&upstream);
+    fields["api_key"] = json!("secret");
+    fields["api_key_config"] = json!("Bearer");
+    mount_price_feed(&sui, fields).await;
+
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 250000000);
+}
+
+#[tokio::test]
+async fn test_process_data_feed_headers() {
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/price"))
+        .and(header("x-partner-id", "acme"))
+        .and(header("user-agent", "nautilus-test"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "price": 2.5 })))
+        .mount(&upstream)
+        .await;
+    mount_price_feed(&sui, upstream_price_fields(&upstream)).await;
+
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            headers: [("X-Partner-Id".to_string(), "acme".to_string())].into(),
+            user_agent: Some("nautilus-test".to_string()),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 250000000);
+}
+
+/// Upstream answering only requests signed as `HmacSigning` describes.
+struct HmacVerifier(HmacSigning);
+
+impl Respond for HmacVerifier {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let header = |name: &str| request.headers.get(name).and_then(|value| value.to_str().ok());
+        let timestamp_ms = header(&self.0.timestamp_header).and_then(|value| value.parse().ok());
+        let expected = timestamp_ms
+            .map(|timestamp_ms| self.0.signature(b"hunter2", timestamp_ms, request.url.as_str(), b"").unwrap());
+        if header(&self.0.key_header) == Some("key-id") && header(&self.0.signature_header) == expected.as_deref() {
+            ResponseTemplate::new(200).set_body_json(json!({ "price": "42.5" }))
+        } else {
+            ResponseTemplate::new(401)
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_process_data_hmac_signed_upstream() {
+    let (sui, upstream) = start_mocks().await;
+    let signing: HmacSigning = toml::from_str(
+        r#"
+        secret_env = "NAUTILUS_TEST_HMAC_SECRET"
+        signature_header = "OK-ACCESS-SIGN"
+        encoding = "base64"
+        "#,
+    )
+    .unwrap();
+    std::env::set_var("NAUTILUS_TEST_HMAC_SECRET", "hunter2");
+    Mock::given(method("GET"))
+        .and(path("/api/v5/account/balance"))
+        .respond_with(HmacVerifier(signing.clone()))
+        .mount(&upstream)
+        .await;
+    let mut fields = price_feed_fields(
+        &format!("{}/api/v5/account/balance?ccy=BTC", upstream.uri()),
+        "price",
+    );
+    fields["api_key"] = json!("key-id");
+    fields["api_key_config"] = json!("hmac-sha256");
+    mount_price_feed(&sui, fields).await;
+
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            hmac: Some(signing),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 4250000000);
+}
+
+#[tokio::test]
+async fn test_process_data_oauth2_upstream() {
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("POST"))
+        .and(path("/token"))
+        .and(body_string_contains("grant_type=client_credentials"))
+        .and(body_string_contains("client_id=vendor-client"))
+        .and(body_string_contains("client_secret=s3cret"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "access_token": "vendor-token", "token_type": "Bearer", "expires_in": 3600
+        })))
+        .expect(1)
+        .mount(&upstream)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/price"))
+        .and(header("Authorization", "Bearer vendor-token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "price": 7 })))
+        .mount(&upstream)
+        .await;
+    std::env::set_var("NAUTILUS_TEST_OAUTH2_SECRET", "s3cret");
+    let mut fields = upstream_price_fields(&upstream);
+    fields["api_key"] = json!("vendor-client");
+    fields["api_key_config"] = json!("oauth2");
+    mount_price_feed(&sui, fields).await;
+
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            oauth2: Some(OAuth2 {
+                token_url: format!("{}/token", upstream.uri()),
+                client_secret_env: "NAUTILUS_TEST_OAUTH2_SECRET".to_string(),
+                scope: None,
+            }),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+    // The token is fetched once and reused.
+    for _ in 0..2 {
+        let response = post_process_data(&app, FEED_ID).await;
+        assert_eq!(response.status(), 200);
+        assert_signed(&response.json().await.unwrap(), 700000000);
+    }
+}
+
+#[tokio::test]
+async fn test_upstream_capture_of_failed_extraction() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "error": "rate limited", "key": "secret" }))
+        .mount(&upstream)
+        .await;
+    let mut fields = upstream_price_fields(&upstream);
+    fields["api_key"] = json!("secret");
+    fields["api_key_config"] = json!("Bearer");
+    mount_price_feed(&sui, fields).await;
+
+    let mut config = test_config(&sui.uri());
+    config.capture.responses_per_feed = 2;
+    let app = spawn_app(config).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+
+    let body: Value = reqwest::get(format!("{}/admin/upstream_captures/{}", app, FEED_ID))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let captures = body["captures"].as_array().unwrap();
+    assert_eq!(captures.len(), 1);
+    assert_eq!(captures[0]["status"], 200);
+    let captured: Value = serde_json::from_str(captures[0]["body"].as_str().unwrap()).unwrap();
+    assert_eq!(captured, json!({ "error": "rate limited", "key": "[redacted]" }));
+}
+
+#[tokio::test]
+async fn test_process_data_secret_api_key() {
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/price"))
+        .and(header("Authorization", "Bearer off-chain-key"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "price": 2.5 })))
+        .mount(&upstream)
+        .await;
+    std::env::set_var("NAUTILUS_SECRET_INTEGRATION_VENDOR", "off-chain-key");
+    let mut fields = upstream_price_fields(&upstream);
+    fields["api_key"] = json!("secret:INTEGRATION_VENDOR");
+    fields["api_key_config"] = json!("Bearer");
+    mount_price_feed(&sui, fields).await;
+
+    let mut config = test_config(&sui.uri());
+    config.secrets.allowlist.insert(
+        "INTEGRATION_VENDOR".to_string(),
+        SecretUse {
+            feeds: vec![FEED_ID.to_string()],
+            hosts: vec!["127.0.0.1".to_string()],
+        },
+    );
+    let app = spawn_app(config).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 250000000);
+}
+
+#[tokio::test]
+async fn test_process_data_encrypted_api_key() {
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/price"))
+        .and(header("Authorization", "Bearer decrypted-key"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "price": 2.5 })))
+        .mount(&upstream)
+        .await;
+    let public_key = ApiKeyDecryption::derive(&test_keypair()).public_key();
+    let mut fields = upstream_price_fields(&upstream);
+    fields["api_key"] = json!(encrypt_api_key(&public_key, FEED_ID, "127.0.0.1", "decrypted-key"));
+    fields["api_key_config"] = json!("Bearer");
+    mount_price_feed(&sui, fields).await;
+
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 250000000);
+}
+
+#[tokio::test]
+async fn test_process_data_upstream_circuit_opens() {
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/price"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(2)
+        .mount(&upstream)
+        .await;
+    mount_price_feed(&sui, upstream_price_fields(&upstream)).await;
+
+    let mut config = test_config(&sui.uri());
+    config.upstream_circuit = UpstreamCircuit {
+        failure_threshold: 2,
+        open_secs: 60,
+    };
+    let app = spawn_app(config).await;
+    for _ in 0..2 {
+        assert_eq!(post_process_data(&app, FEED_ID).await.status(), 400);
+    }
+    // The third request fails fast without reaching the upstream
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 503);
+    assert!(response.headers().contains_key("retry-after"));
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["code"], "upstream_unavailable");
+}
+
+#[tokio::test]
+async fn test_process_data_x_api_key_auth() {
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/price"))
+        .and(header("x-api-key", "secret"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "price": 7 })))
+        .mount(&upstream)
+        .await;
+    let mut fields = upstream_price_fields(&upstream);
+    fields["api_key"] = json!("secret");
+    fields["api_key_config"] = json!("x-api-key");
+    mount_price_feed(&sui, fields).await;
+
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 700000000);
+}
+
+#[tokio::test]
+async fn test_process_data_unsupported_auth() {
+    let sui = MockServer::start().await;
+    let mut fields = price_feed_fields("http://127.0.0.1:1/price", "price");
+    fields["api_key"] = json!("secret");
+    fields["api_key_config"] = json!("Basic");
+    mount_price_feed(&sui, fields).await;
+
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("Unsupported api_key_config: Basic"));
+}
+
+#[tokio::test]
+async fn test_process_data_csv_upstream() {
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/quotes.csv"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            "date,close\n2025-01-02,101.5\n2025-01-03,102.25\n",
+            "text/csv",
+        ))
+        .mount(&upstream)
+        .await;
+    mount_price_feed(
+        &sui,
+        price_feed_fields(&format!("{}/quotes.csv", upstream.uri()), "close"),
+    )
+    .await;
+
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            format: UpstreamFormat {
+                response_format: ResponseFormat::Csv,
+                csv: CsvFormat {
+                    row: -1,
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+
+    // The last row's close, scaled by the configured 8 decimals.
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 10225000000);
+}
+
+#[tokio::test]
+async fn test_process_data_xml_upstream() {
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/eurofxref-daily.xml"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw(
+            r#"<gesmes:Envelope xmlns:gesmes="http://www.gesmes.org/xml/2002-08-01">
+                <Cube><Cube time="2025-01-03">
+                    <Cube currency="USD" rate="1.0298"/>
+                    <Cube currency="JPY" rate="163.02"/>
+                </Cube></Cube>
+            </gesmes:Envelope>"#,
+            "text/xml",
+        ))
+        .mount(&upstream)
+        .await;
+    mount_price_feed(
+        &sui,
+        price_feed_fields(
+            &format!("{}/eurofxref-daily.xml", upstream.uri()),
+            "//Cube[@currency='USD']/@rate",
+        ),
+    )
+    .await;
+
+    let mut config = test_config(&sui.uri());
+    config.response.verify_extraction = true;
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            format: UpstreamFormat {
+                response_format: ResponseFormat::Xml,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 102980000);
+}
+
+#[tokio::test]
+async fn test_process_data_wasm_transform() {
+    // Hands back its input, the upstream document, as the price.
+    let wasm = wat::parse_str(
+        r#"(module
+            (memory (export "memory") 1)
+            (func (export "alloc") (param i32) (result i32) i32.const 1024)
+            (func (export "transform") (param $ptr i32) (param $len i32) (result i64)
+                (i64.or
+                    (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+                    (i64.extend_i32_u (local.get $len)))))"#,
+    )
+    .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let module = dir.path().join("echo.wasm");
+    std::fs::write(&module, &wasm).unwrap();
+
+    let (sui, upstream) = start_mocks().await;
+    Mock::given(method("GET"))
+        .and(path("/price"))
+        .respond_with(ResponseTemplate::new(200).set_body_raw("42.5", "application/json"))
+        .mount(&upstream)
+        .await;
+    let mut fields = price_feed_fields(&format!("{}/price", upstream.uri()), "unused");
+    fields["transform_hash"] = json!(Hex::encode(Sha256::digest(&wasm)));
+    mount_price_feed(&sui, fields).await;
+
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            transform: Some(Transform {
+                module: module.clone(),
+                fuel: 100_000,
+                max_memory_bytes: 1 << 20,
+            }),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config.clone()).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 4250000000);
+
+    // A module whose hash the feed does not pin is refused.
+    sui.reset().await;
+    let mut fields = price_feed_fields(&format!("{}/price", upstream.uri()), "unused");
+    fields["transform_hash"] = json!("00".repeat(32));
+    mount_price_feed(&sui, fields).await;
+    let app = spawn_app(config).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("Transform failed"));
+}
+
+#[tokio::test]
+async fn test_process_data_currency_conversion() {
+    const FX_FEED_ID: &str = "0x00000000000000000000000000000000000000000000000000000000000f0f0f";
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/7203.T", json!({ "price": "3000" })).mount(&upstream).await;
+    upstream_json("/USDJPY", json!({ "rate": "150" })).mount(&upstream).await;
+    mount_price_feed(&sui, price_feed_fields(&format!("{}/7203.T", upstream.uri()), "price")).await;
+    sui_object(FX_FEED_ID)
+        .respond_with(rpc_result(json!({
+            "data": price_feed_object(
+                FX_FEED_ID,
+                price_feed_fields(&format!("{}/USDJPY", upstream.uri()), "rate"),
+            ),
+        })))
+        .mount(&sui)
+        .await;
+
+    // 3000 JPY at 150 JPY per USD.
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            convert: Some(Conversion {
+                fx_feed: FX_FEED_ID.to_string(),
+                invert: true,
+            }),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    assert_signed(&response.json().await.unwrap(), 2_000_000_000);
+}
+
+#[tokio::test]
+async fn test_process_data_rounding_mode() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": "1.234567891" })).await;
+    let mut config = test_config(&sui.uri());
+    config.feeds.insert(
+        FEED_ID.to_string(),
+        FeedConfig {
+            rounding: Some(RoundingMode::Ceil),
+            ..Default::default()
+        },
+    );
+    let app = spawn_app(config).await;
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<PriceFeedResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(signed.response.data.price, 123_456_790);
+    assert_eq!(signed.response.data.rounding, Some(RoundingMode::Ceil));
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+}
+
+#[tokio::test]
+async fn test_process_data_feed_object() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "price": 100 })).await;
+    let mut config = test_config(&sui.uri());
+    config.response.include_feed_object = true;
+    let app = spawn_app(config).await;
+
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    let signed: ProcessedDataResponse<IntentMessage<PriceFeedResponse>> =
+        response.json().await.unwrap();
+    assert_eq!(
+        signed.response.data.feed_object,
+        Some(FeedObject {
+            version: 42,
+            digest: FEED_DIGEST.to_string(),
+        })
+    );
+    let bytes = bcs::to_bytes(&signed.response).unwrap();
+    assert_eq!(signed.signature, Hex::encode(test_keypair().sign(&bytes)));
+}
+
+#[tokio::test]
+async fn test_process_data_invalid_feed() {
+    let sui = MockServer::start().await;
+    let mut fields = price_feed_fields("http://127.0.0.1:1/price", "price");
+    fields["is_valid"] = json!(false);
+    mount_price_feed(&sui, fields).await;
+
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("Price feed is not valid"));
+}
+
+#[tokio::test]
+async fn test_process_data_accepted_package_id() {
+    const ORIGINAL_PACKAGE_ID: &str = "0x00000000000000000000000000000000000000000000000000000000000000a1";
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "price": 100 })).mount(&upstream).await;
+    // A feed created before the package was upgraded keeps the original type
+    let mut object = price_feed_object(
+        FEED_ID,
+        upstream_price_fields(&upstream),
+    );
+    object["type"] = json!(format!("{}::oracle_builder::PriceFeed", ORIGINAL_PACKAGE_ID));
+    sui_object(FEED_ID)
+        .respond_with(rpc_result(json!({ "data": object })))
+        .mount(&sui)
+        .await;
+
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert!(!response.status().is_success());
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("Expected PriceFeed type"));
+
+    let mut config = test_config(&sui.uri());
+    config.sui.accepted_package_ids = vec![ORIGINAL_PACKAGE_ID.to_string()];
+    let app = spawn_app(config).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_signed(&body, 10_000_000_000);
+}
+
+#[tokio::test]
+async fn test_process_data_retries_rate_limited_rpc() {
+    let (sui, upstream) = start_mocks().await;
+    upstream_json("/price", json!({ "price": 100 })).mount(&upstream).await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(429).insert_header("retry-after", "0"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&sui)
+        .await;
+    mount_price_feed(&sui, upstream_price_fields(&upstream)).await;
+
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_signed(&body, 10_000_000_000);
+}
+
+#[tokio::test]
+async fn test_process_data_rate_limited_rpc_gives_up() {
+    let sui = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(rpc_error(-32050, "Too many requests"))
+        .expect(2)
+        .mount(&sui)
+        .await;
+
+    let mut config = test_config(&sui.uri());
+    config.sui.rate_limit_retries = 1;
+    let app = spawn_app(config).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert!(!response.status().is_success());
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("rate limited"));
+}
+
+#[tokio::test]
+async fn test_process_data_feed_layout_mismatch() {
+    let sui = MockServer::start().await;
+    let mut fields = price_feed_fields("http://127.0.0.1:1/price", "price");
+    fields["timestamp_field"] = json!("");
+    fields["transform_hash"] = json!("");
+    let mut object = price_feed_object(FEED_ID, fields);
+    // A field appended to the latest Move struct that the server does not know of
+    let mut bytes = Base64::decode(object["bcs"]["bcsBytes"].as_str().unwrap()).unwrap();
+    bytes.extend(bcs::to_bytes(&7u64).unwrap());
+    object["bcs"]["bcsBytes"] = json!(Base64::encode(bytes));
+    sui_object(FEED_ID)
+        .respond_with(rpc_result(json!({ "data": object })))
+        .mount(&sui)
+        .await;
+
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert!(!response.status().is_success());
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("does not match the expected Move struct layout"));
+}
+
+#[tokio::test]
+async fn test_process_data_missing_field() {
+    let (sui, upstream) = start_mocks().await;
+    mount_upstream_feed(&sui, &upstream, json!({ "other": 1 })).await;
+
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let response = post_process_data(&app, FEED_ID).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("Field 'price' not found"));
+}
+
+#[tokio::test]
+async fn test_process_data_sui_rpc_error() {
+    let sui = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(rpc_error(-32602, "invalid object id"))
+        .mount(&sui)
+        .await;
+
+    let app = spawn_app(test_config(&sui.uri())).await;
+    let response = post_process_data(&app, "0xbad").await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("Failed to fetch price feed"));
+}
@@ -0,0 +1,149 @@
+// Copyright (c), Mysten Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Verification of responses signed by a Nautilus oracle enclave, for
+//! relayers and indexers that should not link the server.
+//!
+//! The enclave signs the BCS bytes of an [`IntentMessage`] with Ed25519 and
+//! returns them as `{"response": {...}, "signature": "<hex>"}`. Deserialize
+//! that into a [`SignedResponse`] with the payload type of its intent, whose
+//! fields must match the server's response type in name, order and type, and
+//! check it with [`verify_signed_response`].
+
+use fastcrypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+use fastcrypto::encoding::{Encoding, Hex};
+use fastcrypto::traits::{ToFromBytes, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use serde_repr::{Deserialize_repr, Serialize_repr};
+
+/// Intent message the enclave signs: the payload along with its intent
+/// scope and timestamp. Same layout as the server's `IntentMessage`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntentMessage<T> {
+    pub intent: IntentScope,
+    pub timestamp_ms: u64,
+    pub data: T,
+}
+
+/// Intent scopes the enclave signs under. Must be kept in step with the
+/// server's `IntentScope`, which numbers them the same way.
+#[derive(Serialize_repr, Deserialize_repr, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum IntentScope {
+    PriceFeed = 0,
+    PriceFeedWithChange = 1,
+    PriceFeedMarketClosed = 2,
+    FeeQuote = 3,
+    Weather = 4,
+    SportsResult = 5,
+    ProofOfReserve = 6,
+    NftFloorPrice = 7,
+    PriceFeedReducedPrecision = 8,
+    KeyRetirement = 9,
+    PriceStatistics = 10,
+    PriceFeedWide = 11,
+    RateFeed = 12,
+    PriceFeedExtended = 13,
+}
+
+/// A signed response as returned by the enclave. Other fields of the
+/// response, like its envelope, are not signed and are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedResponse<T> {
+    pub response: IntentMessage<T>,
+    /// Ed25519 signature of the BCS bytes of `response`, in Hex
+    pub signature: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VerifyError {
+    #[error("Invalid public key: {0}")]
+    InvalidPublicKey(String),
+    #[error("Invalid signature encoding: {0}")]
+    InvalidSignature(String),
+    #[error("Failed to encode the intent message: {0}")]
+    Encoding(#[from] bcs::Error),
+    #[error("Signature does not match the response and public key")]
+    Mismatch,
+}
+
+/// The BCS bytes the enclave signs for `message`.
+pub fn signing_bytes<T: Serialize>(message: &IntentMessage<T>) -> Result<Vec<u8>, VerifyError> {
+    Ok(bcs::to_bytes(message)?)
+}
+
+/// Check that `response` was signed by the enclave key `public_key`, given
+/// as its 32 raw bytes.
+pub fn verify_signed_response<T: Serialize>(
+    public_key: &[u8],
+    response: &SignedResponse<T>,
+) -> Result<(), VerifyError> {
+    let public_key = Ed25519PublicKey::from_bytes(public_key)
+        .map_err(|e| VerifyError::InvalidPublicKey(e.to_string()))?;
+    let signature = Hex::decode(&response.signature)
+        .map_err(|e| VerifyError::InvalidSignature(e.to_string()))?;
+    let signature = Ed25519Signature::from_bytes(&signature)
+        .map_err(|e| VerifyError::InvalidSignature(e.to_string()))?;
+    let bytes = signing_bytes(&response.response)?;
+    public_key
+        .verify(&bytes, &signature)
+        .map_err(|_| VerifyError::Mismatch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fastcrypto::ed25519::Ed25519KeyPair;
+    use fastcrypto::traits::{KeyPair, Signer};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct SigningPayload {
+        location: String,
+        temperature: u64,
+    }
+
+    fn payload() -> IntentMessage<SigningPayload> {
+        IntentMessage {
+            intent: IntentScope::PriceFeed,
+            timestamp_ms: 1744038900000,
+            data: SigningPayload {
+                location: "San Francisco".to_string(),
+                temperature: 13,
+            },
+        }
+    }
+
+    #[test]
+    fn test_signing_bytes_match_move() {
+        // Same vector as `test_serde` in `move/enclave/sources/enclave.move`
+        assert_eq!(
+            Hex::encode(signing_bytes(&payload()).unwrap()),
+            "0020b1d110960100000d53616e204672616e636973636f0d00000000000000"
+        );
+    }
+
+    #[test]
+    fn test_verify_signed_response() {
+        let kp = Ed25519KeyPair::generate(&mut StdRng::seed_from_u64(42));
+        let public_key = kp.public().as_bytes().to_vec();
+        let signature = kp.sign(&signing_bytes(&payload()).unwrap());
+        let json = serde_json::json!({
+            "envelope": { "signature_scheme": "ed25519" },
+            "response": payload(),
+            "signature": Hex::encode(signature),
+        });
+        let signed: SignedResponse<SigningPayload> = serde_json::from_value(json).unwrap();
+        assert!(verify_signed_response(&public_key, &signed).is_ok());
+
+        let mut tampered = signed.clone();
+        tampered.response.data.temperature = 14;
+        assert!(matches!(
+            verify_signed_response(&public_key, &tampered),
+            Err(VerifyError::Mismatch)
+        ));
+        let other = Ed25519KeyPair::generate(&mut StdRng::seed_from_u64(7));
+        assert!(verify_signed_response(other.public().as_bytes(), &signed).is_err());
+        assert!(verify_signed_response(&public_key[..31], &signed).is_err());
+    }
+}